@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkClient};
+use serde::Deserialize;
+
+const BIOME_PATH: &str = "server_assets/biomes/";
+
+/// How the world looks while in a biome, read from the biome files of the resource pack.
+#[derive(Deserialize, Clone)]
+pub struct BiomeVisuals {
+    /// Color of the fog, the alpha is how thick it gets.
+    pub fog_color: Color,
+    /// How far it is possible to see through the fog.
+    pub fog_distance: f32,
+    /// Multiplied with the color of the sky.
+    pub sky_tint: Color,
+    /// Multiplied with the color of the materials tinted as "water".
+    pub water_color: Color,
+    /// Multiplied with the color of the materials tinted as "foliage".
+    pub foliage_color: Color,
+}
+
+/// The biomes of the server, by the ids it sends with the chunks.
+#[derive(Resource)]
+pub struct Biomes {
+    names: Vec<String>,
+    visuals: Vec<BiomeVisuals>,
+}
+
+impl Biomes {
+    pub fn get_name(&self, biome_id: u8) -> Option<&str> {
+        return self.names.get(biome_id as usize).map(String::as_str);
+    }
+
+    pub fn get_visuals(&self, biome_id: u8) -> Option<&BiomeVisuals> {
+        return self.visuals.get(biome_id as usize);
+    }
+}
+
+pub fn load_biomes(
+    mut commands: Commands,
+    net: Res<NetworkClient>,
+    server_config: Res<messages::ServerConfig>,
+) {
+    let directory = match std::fs::read_dir(BIOME_PATH) {
+        Ok(d) => d,
+        Err(e) => {
+            net.disconnect(format!(
+                "Misconfigured resource pack: Could not read the biome directory at '{}'\nError: {}",
+                BIOME_PATH, e
+            ));
+            return;
+        }
+    };
+
+    let mut visuals = HashMap::new();
+    for dir_entry in directory {
+        let file_path = match dir_entry {
+            Ok(entry) => entry.path(),
+            Err(e) => {
+                net.disconnect(format!(
+                    "Encountered error reading file entries in directory: {}\n Error: {}",
+                    BIOME_PATH, e
+                ));
+                return;
+            }
+        };
+
+        let file = match std::fs::File::open(&file_path) {
+            Ok(f) => f,
+            Err(e) => {
+                net.disconnect(format!(
+                    "Failed to open biome config.\nPath: {}\nError: {}",
+                    file_path.to_string_lossy(),
+                    e
+                ));
+                return;
+            }
+        };
+
+        let biome_visuals: BiomeVisuals = match serde_json::from_reader(file) {
+            Ok(c) => c,
+            Err(e) => {
+                net.disconnect(format!(
+                    "Failed to read biome configuration, path: {}\nError: {}",
+                    file_path.to_string_lossy(),
+                    e
+                ));
+                return;
+            }
+        };
+
+        let name = file_path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        visuals.insert(name, biome_visuals);
+    }
+
+    let mut biome_ids: Vec<(&String, &u8)> = server_config.biome_ids.iter().collect();
+    biome_ids.sort_by_key(|(_, id)| **id);
+
+    let mut biomes = Biomes {
+        names: Vec::with_capacity(biome_ids.len()),
+        visuals: Vec::with_capacity(biome_ids.len()),
+    };
+    for (index, (name, id)) in biome_ids.into_iter().enumerate() {
+        if *id as usize != index {
+            net.disconnect(
+                "Server sent invalid biome ids, they must be numbered from zero without gaps",
+            );
+            return;
+        }
+
+        let Some(biome_visuals) = visuals.remove(name) else {
+            net.disconnect(format!(
+                "Misconfigured resource pack: The biome '{}' is missing its config at '{}'",
+                name, BIOME_PATH
+            ));
+            return;
+        };
+
+        biomes.names.push(name.clone());
+        biomes.visuals.push(biome_visuals);
+    }
+
+    commands.insert_resource(biomes);
+}
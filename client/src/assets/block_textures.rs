@@ -24,49 +24,88 @@ impl BlockTextures {
     }
 }
 
-// TODO: All error should lead to disconnect
-//
-/// Stiches all the textures used by blocks into a texture array.
-pub fn load_block_textures(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+/// The block textures stitched into a texture array, before it is added as an asset.
+#[derive(Resource)]
+pub struct StitchedBlockTextures {
+    image: Image,
+    texture_array_indices: HashMap<String, u32>,
+}
+
+/// Stiches all the textures used by blocks into a texture array. Errors are formatted to be shown
+/// to the player.
+pub(super) fn stitch_block_textures() -> Result<StitchedBlockTextures, String> {
     // size of 16*16 png 8 bit indexed png
     let mut image_buffer = Vec::with_capacity(256);
     let path = "server_assets/textures/blocks";
 
     let mut texture_array_indices: HashMap<String, u32> = HashMap::new();
 
+    let directory = match std::fs::read_dir(path) {
+        Ok(d) => d,
+        Err(e) => {
+            return Err(format!(
+                "Misconfigured resource pack: Failed to read the block texture directory at '{}'\n\
+                Error: {}",
+                path, e
+            ));
+        }
+    };
+
     let mut final_image_data = Vec::new();
     let mut id = 0;
-    for dir_entry in std::fs::read_dir(path).unwrap() {
+    for dir_entry in directory {
         let path = match dir_entry {
             Ok(d) => d.path(),
-            Err(e) => panic!(
-                "Error reading file path while loading textures.\nError: {}",
-                e
-            ),
+            Err(e) => {
+                return Err(format!(
+                    "Error reading file path while loading textures.\nError: {}",
+                    e
+                ));
+            }
         };
 
         let mut file = match std::fs::File::open(&path) {
             Ok(f) => f,
-            Err(e) => panic!(
-                "Failed to open texture at {}\nError: {}",
-                path.to_string_lossy(),
-                e
-            ),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to open texture at {}\nError: {}",
+                    path.to_string_lossy(),
+                    e
+                ));
+            }
         };
-        // TODO: handle error
         image_buffer.clear();
-        file.read_to_end(&mut image_buffer).ok();
+        if let Err(e) = file.read_to_end(&mut image_buffer) {
+            return Err(format!(
+                "Failed to read texture at {}\nError: {}",
+                path.to_string_lossy(),
+                e
+            ));
+        }
 
-        let image = Image::from_buffer(
+        let image = match Image::from_buffer(
             &image_buffer,
             ImageType::MimeType("image/png"),
             CompressedImageFormats::NONE,
             true,
             ImageSampler::Default,
-        )
-        .unwrap();
+        ) {
+            Ok(i) => i,
+            Err(e) => {
+                return Err(format!(
+                    "Misconfigured resource pack: Failed to read texture at {}\nError: {}",
+                    path.to_string_lossy(),
+                    e
+                ));
+            }
+        };
 
-        assert!(image.size()[0] == 16);
+        if image.size()[0] != 16 {
+            return Err(format!(
+                "Misconfigured resource pack: The block texture at {} must be 16 pixels wide",
+                path.to_string_lossy()
+            ));
+        }
 
         let id_increment = image.height() / 16;
         final_image_data.extend(image.data);
@@ -96,10 +135,23 @@ pub fn load_block_textures(mut commands: Commands, mut images: ResMut<Assets<Ima
     //    image::ColorType::Rgba8,
     //).unwrap();
 
-    let block_textures = BlockTextures {
-        handle: images.add(final_image),
+    return Ok(StitchedBlockTextures {
+        image: final_image,
         texture_array_indices,
+    });
+}
+
+/// Adds the stitched texture array as an asset.
+pub(super) fn load_block_textures(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut stitched: ResMut<StitchedBlockTextures>,
+) {
+    let block_textures = BlockTextures {
+        handle: images.add(std::mem::take(&mut stitched.image)),
+        texture_array_indices: std::mem::take(&mut stitched.texture_array_indices),
     };
 
+    commands.remove_resource::<StitchedBlockTextures>();
     commands.insert_resource(block_textures);
 }
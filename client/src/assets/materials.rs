@@ -26,6 +26,19 @@ impl Materials {
     }
 }
 
+/// Which of the biome's colors a material is tinted by.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BiomeTint {
+    Foliage,
+    Water,
+}
+
+/// The block materials that change color with the biome, along with the color they were
+/// configured with.
+#[derive(Resource, Default, Deref)]
+pub struct TintedMaterials(Vec<(Handle<BlockMaterial>, BiomeTint, Color)>);
+
 #[derive(Deserialize)]
 #[serde(default)]
 struct MaterialConfig {
@@ -46,6 +59,8 @@ struct MaterialConfig {
     pub fog_enabled: bool,
     pub transparency: String,
     pub animation_frames: u32,
+    // Only for "block" materials, one of "foliage" or "water"
+    pub biome_tint: Option<BiomeTint>,
 }
 
 impl Default for MaterialConfig {
@@ -69,6 +84,7 @@ impl Default for MaterialConfig {
             fog_enabled: true,
             transparency: "opaque".to_owned(),
             animation_frames: 1,
+            biome_tint: None,
         }
     }
 }
@@ -84,6 +100,7 @@ pub fn load_materials(
     mut standard_materials: ResMut<Assets<StandardMaterial>>,
 ) {
     let mut materials = Materials::default();
+    let mut tinted_materials = TintedMaterials::default();
 
     let dir = std::path::PathBuf::from("server_assets/materials");
     for dir_entry in std::fs::read_dir(&dir).unwrap() {
@@ -193,8 +210,24 @@ pub fn load_materials(
                 texture_array: Some(block_textures.handle.clone()),
                 animation_frames: config.animation_frames,
             };
-            block_materials.add(material).untyped()
+            let handle = block_materials.add(material);
+            if let Some(biome_tint) = config.biome_tint {
+                tinted_materials
+                    .0
+                    .push((handle.clone(), biome_tint, config.base_color));
+            }
+            handle.untyped()
         } else if config.r#type == "standard" {
+            if config.biome_tint.is_some() {
+                net.disconnect(format!(
+                    "Misconfigured material, path: {}
+ Only materials of the 'block' type can \
+                    have a 'biome_tint'",
+                    &file_path.to_string_lossy().into_owned()
+                ));
+                return;
+            }
+
             // TODO: Maybe this can be removed, nothing uses it. I can't quite remember what the
             // plan was. Think I thought mobs were to use it.
             let material = StandardMaterial {
@@ -233,4 +266,5 @@ pub fn load_materials(
     }
 
     commands.insert_resource(materials);
+    commands.insert_resource(tinted_materials);
 }
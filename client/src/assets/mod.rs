@@ -1,15 +1,28 @@
 use sha1::Digest;
-use std::io::prelude::*;
+use std::{io::prelude::*, time::Instant};
 
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, IoTaskPool, Task},
+};
 use fmc_networking::{messages, NetworkData};
+use futures_lite::future;
 
+use crate::{
+    ui::server::{items::ItemConfigFiles, InterfaceConfigs},
+    world::blocks::BlockConfigs,
+};
+
+mod biomes;
 mod block_textures;
 mod materials;
 pub mod models;
+mod translations;
 
+pub use biomes::{BiomeVisuals, Biomes};
 pub use block_textures::BlockTextures;
-pub use materials::Materials;
+pub use materials::{BiomeTint, Materials, TintedMaterials};
+pub use translations::Translations;
 
 /// Assets are downloaded on connection to the server. It first waits for the server config. Then
 /// checks if server_config.asset_hash is the same as the hash of any stored assets. If not it asks
@@ -46,28 +59,46 @@ impl Plugin for AssetPlugin {
             (
                 start_asset_loading.run_if(in_state(AssetState::Inactive)),
                 handle_assets_response.run_if(in_state(AssetState::Downloading)),
-                test_finished_load_state_one.run_if(in_state(LoadingState::One)),
+                (
+                    poll_loading_task::<block_textures::StitchedBlockTextures>,
+                    poll_loading_task::<models::Models>,
+                    poll_loading_task::<BlockConfigs>,
+                    poll_loading_task::<ItemConfigFiles>,
+                    poll_loading_task::<InterfaceConfigs>,
+                    apply_deferred,
+                    test_finished_load_state_one,
+                )
+                    .chain()
+                    .run_if(in_state(LoadingState::One)),
             ),
         )
         .add_systems(OnEnter(AssetState::Loading), start_loading)
         .add_systems(
             OnEnter(LoadingState::One),
             (
-                block_textures::load_block_textures,
-                models::load_models,
+                start_loading_tasks,
                 crate::ui::server::key_bindings::load_key_bindings,
+                translations::load_translations,
+                biomes::load_biomes,
             ),
         )
         .add_systems(
             OnEnter(LoadingState::Two),
             (
+                block_textures::load_block_textures,
+                apply_deferred,
                 materials::load_materials,
                 apply_deferred,
+                lap("materials"),
                 crate::world::blocks::load_blocks,
                 apply_deferred,
-                crate::ui::server::items::load_items,
-                crate::ui::server::load_interfaces,
+                lap("blocks"),
+                (
+                    crate::ui::server::items::load_items,
+                    crate::ui::server::load_interfaces,
+                ),
                 apply_deferred,
+                lap("items and interfaces"),
                 finish,
             )
                 .chain(),
@@ -75,12 +106,145 @@ impl Plugin for AssetPlugin {
     }
 }
 
+/// Time taken by each loading stage, logged as they finish.
+#[derive(Resource)]
+struct LoadingTimer {
+    start: Instant,
+    lap: Instant,
+}
+
+impl LoadingTimer {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            start: now,
+            lap: now,
+        }
+    }
+
+    fn lap(&mut self, stage: &str) {
+        info!("Loaded {} in {:.2?}", stage, self.lap.elapsed());
+        self.lap = Instant::now();
+    }
+}
+
+fn lap(stage: &'static str) -> impl Fn(ResMut<LoadingTimer>) {
+    move |mut timer: ResMut<LoadingTimer>| timer.lap(stage)
+}
+
+/// An asset that is loaded on the task pool, it is inserted as a resource when the task finishes.
+/// Errors are formatted to be shown to the player.
+#[derive(Resource)]
+struct LoadingTask<T: Resource>(Task<Result<T, String>>);
+
+// The textures, models and the configs of blocks, items and interfaces are all read in the
+// background at the same time. Only the parts that need the results of each other, or the world,
+// are left for LoadingState::Two. If the server uses the same assets as the last one we connected
+// to, the block configurations from then are reused.
+fn start_loading_tasks(
+    mut commands: Commands,
+    server_config: Res<messages::ServerConfig>,
+    asset_server: Res<AssetServer>,
+    block_configs: Option<Res<BlockConfigs>>,
+) {
+    let io_pool = IoTaskPool::get();
+    let compute_pool = AsyncComputeTaskPool::get();
+
+    commands.insert_resource(LoadingTask(
+        compute_pool.spawn(async move { block_textures::stitch_block_textures() }),
+    ));
+
+    let asset_server = asset_server.clone();
+    let model_ids = server_config.model_ids.clone();
+    let model_hitboxes = server_config.model_hitboxes.clone();
+    commands.insert_resource(LoadingTask(io_pool.spawn(async move {
+        models::read_models(asset_server, model_ids, model_hitboxes)
+    })));
+
+    let item_ids = server_config.item_ids.clone();
+    commands.insert_resource(LoadingTask(
+        io_pool.spawn(async move { crate::ui::server::items::read_item_configs(item_ids) }),
+    ));
+
+    // Interface images are decoded to find their dimensions.
+    commands.insert_resource(LoadingTask(
+        compute_pool.spawn(async move { crate::ui::server::read_interface_configs() }),
+    ));
+
+    if let Some(block_configs) = block_configs {
+        if block_configs.assets_hash == server_config.assets_hash {
+            // A task from a connection that failed to load would replace them.
+            commands.remove_resource::<LoadingTask<BlockConfigs>>();
+            return;
+        }
+    }
+
+    let assets_hash = server_config.assets_hash.clone();
+    commands.insert_resource(LoadingTask(
+        io_pool.spawn(async move { BlockConfigs::read(assets_hash) }),
+    ));
+}
+
+fn poll_loading_task<T: Resource>(
+    mut commands: Commands,
+    net: Res<fmc_networking::NetworkClient>,
+    task: Option<ResMut<LoadingTask<T>>>,
+    mut loading_state: ResMut<NextState<LoadingState>>,
+) {
+    let Some(mut task) = task else {
+        return;
+    };
+
+    let Some(result) = future::block_on(future::poll_once(&mut task.0)) else {
+        return;
+    };
+
+    commands.remove_resource::<LoadingTask<T>>();
+
+    match result {
+        Ok(asset) => commands.insert_resource(asset),
+        Err(message) => {
+            net.disconnect(message);
+            loading_state.set(LoadingState::Inactive);
+        }
+    }
+}
+
 fn test_finished_load_state_one(
     net: Res<fmc_networking::NetworkClient>,
-    models: Res<models::Models>,
+    models: Option<Res<models::Models>>,
     asset_server: Res<AssetServer>,
+    running_tasks: (
+        Option<Res<LoadingTask<block_textures::StitchedBlockTextures>>>,
+        Option<Res<LoadingTask<models::Models>>>,
+        Option<Res<LoadingTask<BlockConfigs>>>,
+        Option<Res<LoadingTask<ItemConfigFiles>>>,
+        Option<Res<LoadingTask<InterfaceConfigs>>>,
+    ),
     mut loading_state: ResMut<NextState<LoadingState>>,
+    mut timer: ResMut<LoadingTimer>,
 ) {
+    let (textures, models_task, block_configs, item_configs, interface_configs) = running_tasks;
+    if textures.is_some()
+        || models_task.is_some()
+        || block_configs.is_some()
+        || item_configs.is_some()
+        || interface_configs.is_some()
+    {
+        return;
+    }
+
+    // One of the tasks failed and disconnected.
+    if loading_state.0.is_some() {
+        return;
+    }
+
+    let Some(models) = models else {
+        return;
+    };
+
+    // Gltf files are loaded by the asset server, it has to finish before the items can check
+    // their animations.
     for model in models.iter() {
         match asset_server.get_load_state(&model.handle).unwrap() {
             bevy::asset::LoadState::Failed => {
@@ -97,6 +261,7 @@ fn test_finished_load_state_one(
         }
     }
 
+    timer.lap("textures, models and configs");
     loading_state.set(LoadingState::Two);
 }
 
@@ -105,9 +270,14 @@ fn start_loading(mut loading_state: ResMut<NextState<LoadingState>>) {
 }
 
 fn finish(
+    mut commands: Commands,
+    timer: Res<LoadingTimer>,
     mut asset_state: ResMut<NextState<AssetState>>,
     mut loading_state: ResMut<NextState<LoadingState>>,
 ) {
+    info!("Finished loading assets in {:.2?}", timer.start.elapsed());
+    commands.remove_resource::<LoadingTimer>();
+
     asset_state.set(AssetState::Inactive);
     loading_state.set(LoadingState::Inactive);
 }
@@ -116,11 +286,14 @@ fn finish(
 // proper cleanup of state between connections, and then just listen for when serverconfig is added
 // as a resource, but I can't be assed.
 fn start_asset_loading(
+    mut commands: Commands,
     net: Res<fmc_networking::NetworkClient>,
     mut server_config_event: EventReader<NetworkData<messages::ServerConfig>>,
     mut asset_state: ResMut<NextState<AssetState>>,
 ) {
     for config in server_config_event.read() {
+        commands.insert_resource(LoadingTimer::new());
+
         if !has_assets(&config.assets_hash) {
             info!("Downloading assets from the server...");
             net.send_message(messages::AssetRequest);
@@ -132,16 +305,21 @@ fn start_asset_loading(
 }
 
 fn handle_assets_response(
+    mut commands: Commands,
     mut asset_state: ResMut<NextState<AssetState>>,
     mut asset_events: EventReader<NetworkData<messages::AssetResponse>>,
+    mut timer: ResMut<LoadingTimer>,
 ) {
     // TODO: Does this need an explicit timeout? Don't want to let the server be able to leave the
     // client in limbo without the player being able to quit.
     // TODO: Unpacking stores tarball in extraction directory, delete it.
     for tarball in asset_events.read() {
         info!("Received assets from server...");
+        timer.lap("assets from server");
+
         // Remove old assets if they exist.
         std::fs::remove_dir_all("server_assets").ok();
+        commands.remove_resource::<BlockConfigs>();
 
         let mut archive = tar::Archive::new(std::io::Cursor::new(&tarball.file));
         archive.unpack("./server_assets").unwrap();
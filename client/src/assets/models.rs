@@ -5,7 +5,7 @@ use bevy::{
     utils::HashMap,
 };
 
-use fmc_networking::{messages::ServerConfig, NetworkClient};
+use fmc_networking::messages::Hitbox;
 use serde::Deserialize;
 
 const MODEL_PATH: &str = "server_assets/textures/models/";
@@ -39,16 +39,19 @@ impl Models {
 // TODO: Idk why I made this struct, remove if it isn't expanded upon
 pub struct Model {
     pub handle: Handle<Gltf>,
+    /// Parts of the model that can be interacted with.
+    pub hitboxes: Vec<Hitbox>,
 }
 
 // TODO: If AssetServer implements some kind of synchronous load or verification in the future,
 // models should be confirmed so we can disconnect when a model fails to load.
-pub(super) fn load_models(
-    mut commands: Commands,
-    net: Res<NetworkClient>,
-    server_config: Res<ServerConfig>,
-    asset_server: Res<AssetServer>,
-) {
+/// Reads the models of the resource pack, gltf models are loaded in the background by the asset
+/// server. Errors are formatted to be shown to the player.
+pub(super) fn read_models(
+    asset_server: AssetServer,
+    model_ids: std::collections::HashMap<String, ModelId>,
+    mut model_hitboxes: std::collections::HashMap<ModelId, Vec<Hitbox>>,
+) -> Result<Models, String> {
     let mut models = Models {
         inner: std::collections::HashMap::new(),
         reverse: std::collections::HashMap::new(),
@@ -57,11 +60,10 @@ pub(super) fn load_models(
     let directory = match std::fs::read_dir(MODEL_PATH) {
         Ok(dir) => dir,
         Err(e) => {
-            net.disconnect(&format!(
+            return Err(format!(
                 "Misconfigured resource pack: Failed to read model directory at '{}'\n Error: {}",
                 MODEL_PATH, e
             ));
-            return;
         }
     };
 
@@ -74,48 +76,44 @@ pub(super) fn load_models(
         let path = match dir_entry {
             Ok(d) => d.path(),
             Err(e) => {
-                net.disconnect(&format!(
+                return Err(format!(
                     "Misconfigured resource pack: Failed to read the file path of a model\n\
                     Error: {}",
                     e
                 ));
-                return;
             }
         };
 
         let model_name = path.file_stem().unwrap().to_string_lossy().into_owned();
 
         let Some(extension) = path.extension() else {
-            net.disconnect(&format!(
+            return Err(format!(
                 "Invalid model file at '{}', the file is missing its extension. \
                     Should be one of 'json', 'gltf' or 'glb'.",
                 path.display()
             ));
-            return;
         };
 
         let model_handle = if extension == "json" {
             let file = match std::fs::File::open(&path) {
                 Ok(f) => f,
                 Err(e) => {
-                    net.disconnect(&format!(
+                    return Err(format!(
                         "Failed to open file at '{}'\nError: {e}",
                         path.display()
                     ));
-                    return;
                 }
             };
             let json_model: JsonModel = match serde_json::from_reader(file) {
                 Ok(m) => m,
                 Err(e) => {
-                    net.disconnect(&format!(
+                    return Err(format!(
                         "Misconfigured resource pack: Could not parse model at '{}'\nError: {e}",
                         path.display()
                     ));
-                    return;
                 }
             };
-            let mut gltf = json_model.build_gltf(asset_server.as_ref());
+            let mut gltf = json_model.build_gltf(&asset_server);
             gltf.animations.push(click_animation.clone());
             gltf.named_animations
                 .insert("left_click".to_owned(), click_animation.clone());
@@ -123,27 +121,30 @@ pub(super) fn load_models(
         } else if extension == "glb" || extension == "gltf" {
             asset_server.load(path)
         } else {
-            //net.disconnect(message);
-            panic!();
-            return;
+            return Err(format!(
+                "Invalid model file at '{}', the extension should be one of 'json', 'gltf' or \
+                    'glb'.",
+                path.display()
+            ));
         };
 
         handles.insert(model_name, model_handle);
     }
 
-    for (name, id) in server_config.model_ids.iter() {
-        if let Some(handle) = handles.remove(name) {
-            models.reverse.insert(name.to_owned(), *id);
-            models.inner.insert(*id, Model { handle });
+    for (name, id) in model_ids {
+        if let Some(handle) = handles.remove(&name) {
+            let hitboxes = model_hitboxes.remove(&id).unwrap_or_default();
+            models.reverse.insert(name, id);
+            models.inner.insert(id, Model { handle, hitboxes });
         } else {
-            net.disconnect(&format!(
+            return Err(format!(
                 "Misconfigured resource pack: Missing model, no model with the name '{}'",
                 name
             ));
         }
     }
 
-    commands.insert_resource(models);
+    return Ok(models);
 }
 
 const BLOCK_MODEL_VERTICES: [[[f32; 3]; 6]; 6] = [
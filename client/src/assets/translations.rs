@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::settings::Settings;
+
+const LANGUAGE_PATH: &str = "server_assets/languages/";
+
+/// Translations of the text keys sent by the server, read from the language file in the resource
+/// pack that matches the language setting.
+///
+/// The language files contain one translation per line, as 'key:translation'. The translation can
+/// contain '{}' which are replaced by the arguments sent with the key, in order.
+#[derive(Resource, Default)]
+pub struct Translations {
+    inner: HashMap<String, String>,
+}
+
+impl Translations {
+    /// Translate the key, if there is no translation for it, the key itself is used.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        return self.inner.get(key).map(String::as_str).unwrap_or(key);
+    }
+
+    /// Translate the key and fill in the arguments. Placeholders without a matching argument are
+    /// left as is, and extra arguments are ignored.
+    pub fn format(&self, key: &str, args: &[String]) -> String {
+        let translation = self.get(key);
+
+        let mut formatted = String::with_capacity(translation.len());
+        let mut args = args.iter();
+        let mut pieces = translation.split("{}").peekable();
+        while let Some(piece) = pieces.next() {
+            formatted.push_str(piece);
+            if pieces.peek().is_some() {
+                match args.next() {
+                    Some(arg) => formatted.push_str(arg),
+                    None => formatted.push_str("{}"),
+                }
+            }
+        }
+
+        return formatted;
+    }
+}
+
+pub fn load_translations(mut commands: Commands, settings: Res<Settings>) {
+    let path = LANGUAGE_PATH.to_owned() + &settings.language;
+
+    // A missing language is not fatal, the server's keys are shown as is instead.
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) => {
+            warn!(
+                "Failed to read language file at '{}', text from the server will not be \
+                translated.\nError: {}",
+                path, e
+            );
+            commands.insert_resource(Translations::default());
+            return;
+        }
+    };
+
+    let mut translations = Translations::default();
+    for (line_num, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let Some((key, translation)) = line.split_once(":") else {
+            warn!(
+                "Ignoring line {} in language file '{}', translations must be of the format \
+                'key:translation', it cannot be '{}'",
+                line_num + 1,
+                path,
+                line
+            );
+            continue;
+        };
+
+        translations
+            .inner
+            .insert(key.to_owned(), translation.to_owned());
+    }
+
+    commands.insert_resource(translations);
+}
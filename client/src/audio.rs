@@ -1,20 +1,59 @@
-use bevy::{math::DVec3, prelude::*, render::primitives::Aabb};
-use fmc_networking::{messages, NetworkData};
+use std::time::Duration;
+
+use bevy::{
+    asset::LoadState,
+    audio::{AddAudioSource, Decodable, Source, Volume},
+    math::DVec3,
+    prelude::*,
+    reflect::TypePath,
+    render::primitives::Aabb,
+};
+use fmc_networking::{messages, BlockId, NetworkData};
 
 use crate::{
+    constants::CHUNK_SIZE,
     game_state::GameState,
     player::Player,
+    rendering::lighting::LightMap,
     settings::Settings,
-    world::{blocks::Blocks, world_map::WorldMap, Origin},
+    utils,
+    world::{
+        blocks::{Blocks, Friction},
+        world_map::WorldMap,
+        Origin,
+    },
 };
 
+// Each solid block between a sound and the listener multiplies its volume by this.
+const OCCLUSION_PER_BLOCK: f32 = 0.6;
+// Sounds can always be heard a little through walls.
+const MIN_OCCLUDED_VOLUME: f32 = 0.15;
+// Sounds further away than this are too quiet for occlusion to matter, and are not tested.
+const MAX_OCCLUSION_DISTANCE: f64 = 64.0;
+// Where the sunlight is dimmer than this the listener is considered to be underground.
+const ENCLOSED_SUNLIGHT: u8 = 6;
+// Fraction of the listener's chunk that must be open for an enclosed space to be a large cave.
+const LARGE_CAVE_OPENNESS: f32 = 0.3;
+// How far the pitch of the walking sound can stray from the original, the server does the same
+// for the sounds it sends.
+const PITCH_VARIATION: f32 = 0.1;
+
 pub struct AudioPlugin;
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(ClientSideAudio { enabled: true })
+            .insert_resource(ReverbZone::default())
+            .add_audio_source::<ReverbSound>()
             .add_systems(
                 Update,
-                (play_sounds, toggle_client_side_sound, play_walking_sound)
+                (
+                    update_reverb_zone,
+                    play_sounds,
+                    toggle_client_side_sound,
+                    play_walking_sound,
+                    play_reverb_sounds,
+                )
+                    .chain()
                     .run_if(GameState::in_game),
             );
     }
@@ -25,22 +64,239 @@ struct ClientSideAudio {
     enabled: bool,
 }
 
+/// How sounds echo, decided by the space around the listener.
+#[derive(Resource, Default, Clone, Copy, PartialEq)]
+enum ReverbZone {
+    #[default]
+    Open,
+    SmallCave,
+    LargeCave,
+}
+
+impl ReverbZone {
+    // The delay of the echo, and its volume relative to the sound.
+    fn reverb(&self) -> Option<(Duration, f32)> {
+        return match self {
+            Self::Open => None,
+            Self::SmallCave => Some((Duration::from_millis(60), 0.25)),
+            Self::LargeCave => Some((Duration::from_millis(180), 0.4)),
+        };
+    }
+}
+
+// A sound mixed with a delayed copy of itself.
+#[derive(Asset, TypePath)]
+struct ReverbSound {
+    source: AudioSource,
+    delay: Duration,
+    amplitude: f32,
+}
+
+impl Decodable for ReverbSound {
+    type DecoderItem = i16;
+    type Decoder = Box<dyn Source<Item = i16> + Send>;
+
+    fn decoder(&self) -> Self::Decoder {
+        return Box::new(
+            self.source
+                .decoder()
+                .buffered()
+                .reverb(self.delay, self.amplitude),
+        );
+    }
+}
+
+// The echo is made from the samples of the sound, so sounds wait with this until they are loaded.
+#[derive(Component)]
+struct PendingReverb {
+    source: Handle<AudioSource>,
+    settings: PlaybackSettings,
+    delay: Duration,
+    amplitude: f32,
+}
+
+fn spawn_sound(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    reverb_zone: ReverbZone,
+    path: &str,
+    transform: Transform,
+    settings: PlaybackSettings,
+) {
+    let source = asset_server.load(path.to_owned());
+    let mut entity_commands = commands.spawn(TransformBundle::from_transform(transform));
+    match reverb_zone.reverb() {
+        Some((delay, amplitude)) => entity_commands.insert(PendingReverb {
+            source,
+            settings,
+            delay,
+            amplitude,
+        }),
+        None => entity_commands.insert(AudioBundle { source, settings }),
+    };
+}
+
+fn play_reverb_sounds(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    audio_sources: Res<Assets<AudioSource>>,
+    mut reverb_sounds: ResMut<Assets<ReverbSound>>,
+    pending_query: Query<(Entity, &PendingReverb)>,
+) {
+    for (entity, pending) in pending_query.iter() {
+        let Some(source) = audio_sources.get(&pending.source) else {
+            if asset_server.get_load_state(&pending.source) == Some(LoadState::Failed) {
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        let reverb_sound = reverb_sounds.add(ReverbSound {
+            source: source.clone(),
+            delay: pending.delay,
+            amplitude: pending.amplitude,
+        });
+
+        commands
+            .entity(entity)
+            .remove::<PendingReverb>()
+            .insert(AudioSourceBundle {
+                source: reverb_sound,
+                settings: pending.settings,
+            });
+    }
+}
+
+fn is_solid(blocks: &Blocks, block_id: BlockId) -> bool {
+    return matches!(
+        blocks.get_config(block_id).friction(),
+        Friction::Static { .. }
+    );
+}
+
+// Volume multiplier for the solid blocks on the line between the listener and the sound. The line
+// is sampled every half block, which is cheap, but can miss blocks it only clips the corner of.
+fn occlusion(world_map: &WorldMap, listener: DVec3, sound: DVec3) -> f32 {
+    let distance = listener.distance(sound);
+    if distance > MAX_OCCLUSION_DISTANCE || distance < 1.0 {
+        return 1.0;
+    }
+
+    let blocks = Blocks::get();
+    let direction = (sound - listener) / distance;
+    // Sounds often come from the block that was e.g. placed, it shouldn't muffle itself.
+    let sound_block = sound.floor().as_ivec3();
+
+    let mut last_block = listener.floor().as_ivec3();
+    let mut solid_blocks = 0;
+    for step in 1..(distance * 2.0) as usize {
+        let block_position = (listener + direction * step as f64 * 0.5)
+            .floor()
+            .as_ivec3();
+        if block_position == last_block || block_position == sound_block {
+            continue;
+        }
+        last_block = block_position;
+
+        if world_map
+            .get_block(&block_position)
+            .is_some_and(|block_id| is_solid(blocks, block_id))
+        {
+            solid_blocks += 1;
+        }
+    }
+
+    return OCCLUSION_PER_BLOCK
+        .powi(solid_blocks)
+        .max(MIN_OCCLUDED_VOLUME);
+}
+
+// The listener is in a cave when there's little sunlight where they are, and how big it is, is
+// estimated from how much of the chunk they're in is open space. It is only updated when the
+// listener moves into another block.
+fn update_reverb_zone(
+    origin: Res<Origin>,
+    world_map: Res<WorldMap>,
+    light_map: Res<LightMap>,
+    listener_query: Query<&GlobalTransform, (With<SpatialListener>, Changed<GlobalTransform>)>,
+    mut reverb_zone: ResMut<ReverbZone>,
+    mut last_position: Local<IVec3>,
+) {
+    let Ok(listener_transform) = listener_query.get_single() else {
+        return;
+    };
+
+    let position = (origin.0.as_dvec3() + listener_transform.translation().as_dvec3())
+        .floor()
+        .as_ivec3();
+    if position == *last_position {
+        return;
+    }
+    *last_position = position;
+
+    // Not known until the chunk has been lit.
+    let Some(light) = light_map.get_light(position) else {
+        return;
+    };
+
+    let new_zone = if light.sunlight() >= ENCLOSED_SUNLIGHT {
+        ReverbZone::Open
+    } else {
+        let Some(chunk) = world_map.get_chunk(&utils::world_position_to_chunk_pos(position)) else {
+            return;
+        };
+        let blocks = Blocks::get();
+        let open_blocks = (0..CHUNK_SIZE.pow(3))
+            .filter(|index| !is_solid(blocks, chunk[*index]))
+            .count();
+        let openness = open_blocks as f32 / CHUNK_SIZE.pow(3) as f32;
+
+        if openness >= LARGE_CAVE_OPENNESS {
+            ReverbZone::LargeCave
+        } else {
+            ReverbZone::SmallCave
+        }
+    };
+
+    if *reverb_zone != new_zone {
+        *reverb_zone = new_zone;
+    }
+}
+
 fn play_sounds(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     origin: Res<Origin>,
+    world_map: Res<WorldMap>,
+    reverb_zone: Res<ReverbZone>,
+    listener_query: Query<&GlobalTransform, With<SpatialListener>>,
     mut sound_events: EventReader<NetworkData<messages::Sound>>,
 ) {
+    let listener_position = listener_query
+        .get_single()
+        .ok()
+        .map(|transform| origin.0.as_dvec3() + transform.translation().as_dvec3());
+
     for sound in sound_events.read() {
+        let volume = match (sound.position, listener_position) {
+            (Some(position), Some(listener_position)) => {
+                occlusion(&world_map, listener_position, position)
+            }
+            _ => 1.0,
+        };
+
         let position = sound.position.unwrap_or(DVec3::ZERO) - origin.0.as_dvec3();
-        commands
-            .spawn(TransformBundle::from_transform(
-                Transform::from_translation(position.as_vec3()),
-            ))
-            .insert(AudioBundle {
-                source: asset_server.load(&sound.sound),
-                settings: PlaybackSettings::DESPAWN.with_spatial(sound.position.is_some()),
-            });
+        spawn_sound(
+            &mut commands,
+            &asset_server,
+            *reverb_zone,
+            &sound.sound,
+            Transform::from_translation(position.as_vec3()),
+            PlaybackSettings::DESPAWN
+                .with_spatial(sound.position.is_some())
+                .with_volume(Volume::new_relative(volume))
+                .with_speed(sound.speed),
+        );
     }
 }
 
@@ -60,6 +316,7 @@ fn play_walking_sound(
     asset_server: Res<AssetServer>,
     origin: Res<Origin>,
     world_map: Res<WorldMap>,
+    reverb_zone: Res<ReverbZone>,
     client_side_audio: Res<ClientSideAudio>,
     player_position: Query<(&GlobalTransform, &Aabb), (With<Player>, Changed<GlobalTransform>)>,
     mut last_position: Local<DVec3>,
@@ -97,12 +354,21 @@ fn play_walking_sound(
 
     *distance = 0.0;
 
-    commands
-        .spawn(TransformBundle::from_transform(
-            Transform::from_translation(global_transform.translation() + Vec3::from(aabb.center)),
-        ))
-        .insert(AudioBundle {
-            source: asset_server.load(sound),
-            settings: PlaybackSettings::DESPAWN.with_spatial(false),
-        });
+    // Not very random, but footsteps are far enough apart for it not to matter.
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos();
+    let speed = 1.0 - PITCH_VARIATION + (nanos % 1000) as f32 / 1000.0 * PITCH_VARIATION * 2.0;
+
+    spawn_sound(
+        &mut commands,
+        &asset_server,
+        *reverb_zone,
+        sound,
+        Transform::from_translation(global_transform.translation() + Vec3::from(aabb.center)),
+        PlaybackSettings::DESPAWN
+            .with_spatial(false)
+            .with_speed(speed),
+    );
 }
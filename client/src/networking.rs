@@ -1,29 +1,175 @@
+use std::{collections::HashMap, net::SocketAddr, time::Instant};
+
 use bevy::prelude::*;
-use fmc_networking::{messages, ClientNetworkEvent, NetworkClient, NetworkData};
+use fmc_networking::{messages, ClientNetworkEvent, NetworkClient, NetworkData, NetworkSettings};
 
-use crate::game_state::GameState;
+use crate::{assets::Translations, game_state::GameState};
 
 pub struct ClientPlugin;
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(fmc_networking::ClientPlugin)
-            .add_systems(PreUpdate, (handle_connection, handle_server_config));
+            .init_resource::<Account>()
+            .init_resource::<ServerTick>()
+            .add_systems(Startup, handle_command_line_arguments)
+            .add_systems(
+                PreUpdate,
+                (
+                    handle_connection,
+                    handle_server_config,
+                    store_session_token,
+                    log_disconnect_reason,
+                    update_server_tick.after(handle_connection),
+                ),
+            );
+    }
+}
+
+/// The account used to log in to servers.
+#[derive(Resource, Default)]
+pub struct Account {
+    pub username: String,
+    pub password: String,
+    // Tokens the servers have given out, they can be used instead of the password the next time
+    // the client connects. Each can only be used once.
+    session_tokens: HashMap<SocketAddr, String>,
+}
+
+// A session can be recorded with '--record <file>' and played back with '--replay <file>', this
+// makes it possible to reproduce bugs without the server they happened on.
+fn handle_command_line_arguments(
+    mut net: ResMut<NetworkClient>,
+    mut network_settings: ResMut<NetworkSettings>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" | "--replay" => {
+                let Some(path) = args.next() else {
+                    error!("The '{}' argument needs a file", arg);
+                    continue;
+                };
+
+                if arg == "--record" {
+                    network_settings.record_path = Some(path.into());
+                } else {
+                    match net.replay(&path) {
+                        Ok(()) => game_state.set(GameState::Connecting),
+                        Err(err) => error!("Could not replay '{}': {}", path, err),
+                    }
+                }
+            }
+            // Handled by the settings, before they are loaded.
+            "--export" | "--import" => {
+                args.next();
+            }
+            _ => warn!("Unknown command line argument '{}'", arg),
+        }
+    }
+}
+
+// The server's tick rate isn't fixed, it is measured from the updates and corrected for every
+// second.
+const TICK_RATE_SAMPLE_INTERVAL: f64 = 1.0;
+// How many ticks behind the newest update the client shows the world. Updates arrive unevenly, so
+// the delay makes sure there is an update to interpolate towards.
+const INTERPOLATION_DELAY: f64 = 6.0;
+// If the shown tick is further off than this it is moved to where it should be, instead of
+// catching up gradually.
+const MAX_TICK_DRIFT: f64 = 60.0;
+
+/// The server's tick clock, as seen by the client. Transform updates are stamped with the tick
+/// they are from, and are shown at the pace they happened on the server by following this.
+#[derive(Resource)]
+pub struct ServerTick {
+    // The newest tick received, and when it arrived.
+    latest: Option<(u32, Instant)>,
+    // Where the current tick rate measurement started.
+    rate_sample: Option<(u32, Instant)>,
+    // Ticks per second.
+    rate: f64,
+    // The tick that is shown, it trails the server by the interpolation delay.
+    shown: f64,
+}
+
+impl Default for ServerTick {
+    fn default() -> Self {
+        return Self {
+            latest: None,
+            rate_sample: None,
+            // What the server aims for, until it has been measured.
+            rate: 60.0,
+            shown: 0.0,
+        };
+    }
+}
+
+impl ServerTick {
+    /// The server tick the world is shown at, it is between two ticks most of the time.
+    pub fn shown(&self) -> f64 {
+        return self.shown;
+    }
+
+    fn observe(&mut self, tick: u32, now: Instant) {
+        if self.latest.is_some_and(|(latest, _)| latest >= tick) {
+            return;
+        }
+        self.latest = Some((tick, now));
+
+        match self.rate_sample {
+            Some((sample_tick, sample_time)) => {
+                let elapsed = (now - sample_time).as_secs_f64();
+                if elapsed >= TICK_RATE_SAMPLE_INTERVAL {
+                    let rate = (tick - sample_tick) as f64 / elapsed;
+                    self.rate = self.rate * 0.8 + rate * 0.2;
+                    self.rate_sample = Some((tick, now));
+                }
+            }
+            None => self.rate_sample = Some((tick, now)),
+        }
+    }
+
+    fn advance(&mut self, now: Instant, delta_seconds: f64) {
+        let Some((latest, received)) = self.latest else {
+            return;
+        };
+
+        let target =
+            latest as f64 + (now - received).as_secs_f64() * self.rate - INTERPOLATION_DELAY;
+
+        self.shown += delta_seconds * self.rate;
+        if (target - self.shown).abs() > MAX_TICK_DRIFT {
+            self.shown = target;
+        } else {
+            // Small differences are caught up to over a second or so, so movement stays smooth.
+            self.shown += (target - self.shown) * (delta_seconds * 2.0).min(1.0);
+        }
     }
 }
 
 // TODO: Disconnect and error message should be shown to player through the ui.
 fn handle_connection(
     net: Res<NetworkClient>,
+    mut account: ResMut<Account>,
+    mut server_tick: ResMut<ServerTick>,
     mut network_events: EventReader<ClientNetworkEvent>,
     mut game_state: ResMut<NextState<GameState>>,
 ) {
     for event in network_events.read() {
         match event {
             ClientNetworkEvent::Connected => {
-                net.send_message(messages::ClientIdentification {
-                    name: "test".to_owned(),
-                });
+                *server_tick = ServerTick::default();
+                let server_addr = net.connection_id().address();
+                let credentials = match account.session_tokens.remove(&server_addr) {
+                    Some(token) => messages::Credentials::SessionToken(token),
+                    None => messages::Credentials::Password(account.password.clone()),
+                };
+                net.send_message(messages::ClientIdentification::new(
+                    account.username.clone(),
+                    credentials,
+                ));
                 info!("Connected to server");
             }
             ClientNetworkEvent::Disconnected(_message) => {
@@ -47,3 +193,49 @@ fn handle_server_config(
         commands.insert_resource(server_config);
     }
 }
+
+fn store_session_token(
+    net: Res<NetworkClient>,
+    mut account: ResMut<Account>,
+    mut session_token_events: EventReader<NetworkData<messages::SessionToken>>,
+) {
+    for event in session_token_events.read() {
+        account
+            .session_tokens
+            .insert(net.connection_id().address(), event.token.clone());
+    }
+}
+
+// The translations aren't loaded if the server disconnects before the assets are, the message is
+// then shown untranslated.
+fn log_disconnect_reason(
+    translations: Option<Res<Translations>>,
+    mut disconnect_events: EventReader<NetworkData<messages::Disconnect>>,
+) {
+    for disconnect in disconnect_events.read() {
+        let reason = match (&disconnect.message_args, &translations) {
+            (Some(args), Some(translations)) => translations.format(&disconnect.message, args),
+            _ => disconnect.message.clone(),
+        };
+        info!("Disconnected by server: {}", reason);
+    }
+}
+
+fn update_server_tick(
+    time: Res<Time>,
+    mut server_tick: ResMut<ServerTick>,
+    mut transform_updates: EventReader<NetworkData<messages::ModelUpdateTransform>>,
+    mut position_updates: EventReader<NetworkData<messages::PlayerPosition>>,
+) {
+    let now = Instant::now();
+
+    for tick in transform_updates
+        .read()
+        .map(|update| update.tick)
+        .chain(position_updates.read().map(|update| update.tick))
+    {
+        server_tick.observe(tick, now);
+    }
+
+    server_tick.advance(now, time.delta_seconds_f64());
+}
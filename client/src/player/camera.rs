@@ -9,6 +9,7 @@ use fmc_networking::{messages, NetworkClient, NetworkData};
 use crate::{
     constants::CHUNK_SIZE,
     game_state::GameState,
+    rendering::BlendedBiomeVisuals,
     settings::Settings,
     world::{blocks::Blocks, world_map::WorldMap, Origin},
 };
@@ -19,7 +20,8 @@ impl Plugin for CameraPlugin {
         app.add_systems(
             Update,
             (
-                (camera_rotation, fog).run_if(GameState::in_game),
+                (camera_rotation.run_if(super::cutscene::input_unlocked), fog)
+                    .run_if(GameState::in_game),
                 update_render_distance.run_if(resource_changed::<Settings>()),
             ),
         );
@@ -61,13 +63,21 @@ impl Default for CameraBundle {
 }
 
 #[derive(Component, Default)]
-struct CameraState {
+pub(super) struct CameraState {
     /// Vertical angle
     pub pitch: f32,
     /// Horizontal angle
     pub yaw: f32,
 }
 
+impl CameraState {
+    /// Where the player is looking.
+    pub fn rotation(&self) -> Quat {
+        return Quat::from_axis_angle(Vec3::Y, self.yaw)
+            * Quat::from_axis_angle(Vec3::X, self.pitch);
+    }
+}
+
 #[derive(Component, Default)]
 pub struct PlayerCameraMarker;
 
@@ -116,8 +126,7 @@ fn camera_rotation(
 
         camera_state.pitch = camera_state.pitch.clamp(-1.54, 1.54);
 
-        transform.rotation = Quat::from_axis_angle(Vec3::Y, camera_state.yaw)
-            * Quat::from_axis_angle(Vec3::X, camera_state.pitch);
+        transform.rotation = camera_state.rotation();
     }
 
     if should_send {
@@ -141,13 +150,19 @@ fn handle_camera_rotation_from_server(
 fn fog(
     settings: Res<Settings>,
     origin: Res<Origin>,
+    biome_visuals: Res<BlendedBiomeVisuals>,
     mut camera_transform_query: Query<
-        (&GlobalTransform, &Projection, &mut FogSettings),
-        (With<PlayerCameraMarker>, Changed<GlobalTransform>),
+        (Ref<GlobalTransform>, &Projection, &mut FogSettings),
+        With<PlayerCameraMarker>,
     >,
     world_map: Res<WorldMap>,
 ) {
     for (transform, projection, mut fog_settings) in camera_transform_query.iter_mut() {
+        // The biome fog changes gradually even when the camera stands still.
+        if !transform.is_changed() && !biome_visuals.is_changed() {
+            continue;
+        }
+
         let (angle, near) = match projection {
             Projection::Perspective(projection) => (projection.fov, projection.near),
             _ => unreachable!(),
@@ -174,6 +189,12 @@ fn fog(
         let block_config = blocks.get_config(block_id);
         if let Some(fog) = block_config.fog_settings() {
             *fog_settings = fog.clone();
+        } else if let Some(color) = biome_visuals.fog_color() {
+            *fog_settings = FogSettings {
+                color,
+                falloff: FogFalloff::from_visibility(biome_visuals.fog_distance),
+                ..settings.fog.clone()
+            };
         } else {
             *fog_settings = settings.fog.clone();
         }
@@ -0,0 +1,223 @@
+use std::time::Instant;
+
+use bevy::{prelude::*, transform::TransformSystem};
+use fmc_networking::{messages, NetworkClient, NetworkData};
+
+use crate::{game_state::GameState, world::Origin};
+
+use super::{
+    camera::{CameraState, PlayerCameraMarker},
+    Player,
+};
+
+// The server can take control of the camera to play cutscenes, e.g. to introduce a place or to
+// hide a teleport behind a fade to black. The player can always get out of one by pausing, in
+// case the server doesn't end it.
+pub struct CutscenePlugin;
+impl Plugin for CutscenePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveCutscene>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (start_cutscenes, end_cutscenes_from_server).run_if(GameState::in_game),
+            )
+            // Runs after the player has moved the camera, so the cutscene has the last say.
+            .add_systems(
+                PostUpdate,
+                play_cutscene
+                    .run_if(GameState::in_game)
+                    .before(TransformSystem::TransformPropagate),
+            )
+            .add_systems(OnEnter(GameState::Paused), skip_cutscene)
+            .add_systems(OnEnter(GameState::MainMenu), reset_cutscene);
+    }
+}
+
+/// The cutscene that is playing, if any.
+#[derive(Resource, Default)]
+pub struct ActiveCutscene(Option<PlayingCutscene>);
+
+impl ActiveCutscene {
+    /// If the player's input should be ignored.
+    pub fn locks_input(&self) -> bool {
+        return self
+            .0
+            .as_ref()
+            .is_some_and(|cutscene| cutscene.cutscene.lock_input);
+    }
+}
+
+/// Run condition for systems that handle the player's input.
+pub fn input_unlocked(active_cutscene: Res<ActiveCutscene>) -> bool {
+    return !active_cutscene.locks_input();
+}
+
+struct PlayingCutscene {
+    cutscene: messages::Cutscene,
+    start: Instant,
+    // Where the camera was relative to the player before the cutscene, it is put back after.
+    camera_translation: Vec3,
+}
+
+// Covers the screen when the cutscene fades to black.
+#[derive(Component)]
+struct FadeOverlay;
+
+fn setup(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+            z_index: ZIndex::Global(i32::MAX),
+            ..default()
+        },
+        FadeOverlay,
+    ));
+}
+
+fn start_cutscenes(
+    mut active_cutscene: ResMut<ActiveCutscene>,
+    camera_query: Query<&Transform, With<PlayerCameraMarker>>,
+    mut cutscene_events: EventReader<NetworkData<messages::Cutscene>>,
+) {
+    for event in cutscene_events.read() {
+        if event.keyframes.is_empty() {
+            continue;
+        }
+
+        // A cutscene that replaces another still returns the camera to where it was before the
+        // first.
+        let camera_translation = match &active_cutscene.0 {
+            Some(playing) => playing.camera_translation,
+            None => camera_query.single().translation,
+        };
+
+        active_cutscene.0 = Some(PlayingCutscene {
+            cutscene: (**event).clone(),
+            start: Instant::now(),
+            camera_translation,
+        });
+    }
+}
+
+fn end_cutscenes_from_server(
+    mut active_cutscene: ResMut<ActiveCutscene>,
+    mut camera_query: Query<(&mut Transform, &CameraState), With<PlayerCameraMarker>>,
+    mut fade_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+    mut end_events: EventReader<NetworkData<messages::CutsceneEnd>>,
+) {
+    if end_events.read().count() == 0 {
+        return;
+    }
+
+    end_cutscene(&mut active_cutscene, &mut camera_query, &mut fade_query);
+}
+
+// The server is told when the player gets out of the cutscene before it is over.
+fn skip_cutscene(
+    net: Res<NetworkClient>,
+    mut active_cutscene: ResMut<ActiveCutscene>,
+    mut camera_query: Query<(&mut Transform, &CameraState), With<PlayerCameraMarker>>,
+    mut fade_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    if active_cutscene.0.is_none() {
+        return;
+    }
+
+    end_cutscene(&mut active_cutscene, &mut camera_query, &mut fade_query);
+    net.send_message(messages::CutsceneEnd);
+}
+
+fn reset_cutscene(
+    mut active_cutscene: ResMut<ActiveCutscene>,
+    mut camera_query: Query<(&mut Transform, &CameraState), With<PlayerCameraMarker>>,
+    mut fade_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    end_cutscene(&mut active_cutscene, &mut camera_query, &mut fade_query);
+}
+
+fn end_cutscene(
+    active_cutscene: &mut ActiveCutscene,
+    camera_query: &mut Query<(&mut Transform, &CameraState), With<PlayerCameraMarker>>,
+    fade_query: &mut Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    let Some(playing) = active_cutscene.0.take() else {
+        return;
+    };
+
+    let (mut camera_transform, camera_state) = camera_query.single_mut();
+    camera_transform.translation = playing.camera_translation;
+    camera_transform.rotation = camera_state.rotation();
+
+    fade_query.single_mut().0.set_a(0.0);
+}
+
+fn play_cutscene(
+    net: Res<NetworkClient>,
+    origin: Res<Origin>,
+    mut active_cutscene: ResMut<ActiveCutscene>,
+    player_query: Query<&Transform, (With<Player>, Without<PlayerCameraMarker>)>,
+    mut camera_query: Query<(&mut Transform, &CameraState), With<PlayerCameraMarker>>,
+    mut fade_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    let Some(playing) = &active_cutscene.0 else {
+        return;
+    };
+
+    let elapsed = playing.start.elapsed();
+    if elapsed >= playing.cutscene.duration() {
+        let cut_short = playing
+            .cutscene
+            .keyframes
+            .last()
+            .is_some_and(|keyframe| keyframe.time > messages::Cutscene::MAX_DURATION);
+        end_cutscene(&mut active_cutscene, &mut camera_query, &mut fade_query);
+        if cut_short {
+            net.send_message(messages::CutsceneEnd);
+        }
+        return;
+    }
+
+    let keyframes = &playing.cutscene.keyframes;
+    // The last keyframe at or before the current time, and the one after it.
+    let next_index = keyframes
+        .iter()
+        .position(|keyframe| keyframe.time > elapsed)
+        .unwrap_or(keyframes.len() - 1);
+    let current = &keyframes[next_index.saturating_sub(1)];
+    let next = &keyframes[next_index];
+
+    let interpolation = if next.time > current.time && elapsed >= current.time {
+        (elapsed - current.time).as_secs_f32() / (next.time - current.time).as_secs_f32()
+    } else {
+        0.0
+    };
+
+    let player_transform = player_query.single();
+    let (mut camera_transform, camera_state) = camera_query.single_mut();
+
+    // The camera is a child of the player, so the position is made relative to it.
+    let position = match (current.position, next.position) {
+        (Some(current), Some(next)) => Some(current.lerp(next, interpolation as f64)),
+        (position, _) => position,
+    };
+    camera_transform.translation = match position {
+        Some(position) => (position - origin.as_dvec3()).as_vec3() - player_transform.translation,
+        None => playing.camera_translation,
+    };
+
+    let rotation = match (current.rotation, next.rotation) {
+        (Some(current), Some(next)) => Some(current.slerp(next, interpolation)),
+        (rotation, _) => rotation,
+    };
+    camera_transform.rotation = rotation.unwrap_or(camera_state.rotation());
+
+    let fade = current.fade + (next.fade - current.fade) * interpolation;
+    fade_query.single_mut().0.set_a(fade.clamp(0.0, 1.0));
+}
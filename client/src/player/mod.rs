@@ -4,11 +4,13 @@ use fmc_networking::{messages, NetworkData};
 use crate::{game_state::GameState, settings::Settings, world::MovesWithOrigin};
 
 mod camera;
+mod cutscene;
 // TODO: This is pub because of asset loading, remove when redone
 mod movement;
 mod physics;
 
 pub use camera::PlayerCameraMarker;
+pub use cutscene::{input_unlocked, ActiveCutscene};
 
 // Used at setup to set camera position and define the AABB, but should be changed by the server.
 const DEFAULT_PLAYER_WIDTH: f32 = 0.6;
@@ -20,6 +22,7 @@ impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(movement::MovementPlugin)
             .add_plugins(camera::CameraPlugin)
+            .add_plugins(cutscene::CutscenePlugin)
             .add_systems(Startup, setup_player)
             .add_systems(Update, handle_player_config.run_if(GameState::in_game));
     }
@@ -37,6 +40,9 @@ pub struct Player {
     pub is_swimming: bool,
     // If the player is against a block. (in any direction)
     pub is_grounded: BVec3,
+    // Time between swings while the left mouse button is held, zero if the server only counts
+    // one swing per click.
+    pub swing_interval: std::time::Duration,
 }
 
 impl Player {
@@ -88,15 +94,16 @@ fn setup_player(mut commands: Commands, settings: Res<Settings>) {
 // defaults, but should be updated by the server on connection.
 fn handle_player_config(
     mut config_events: EventReader<NetworkData<messages::PlayerConfiguration>>,
-    mut aabb_query: Query<&mut Aabb, With<Player>>,
+    mut player_query: Query<(&mut Aabb, &mut Player)>,
     mut camera_query: Query<&mut Transform, With<Camera>>,
 ) {
     for config in config_events.read() {
-        let mut aabb = aabb_query.single_mut();
+        let (mut aabb, mut player) = player_query.single_mut();
         let mut camera_transform = camera_query.single_mut();
 
         camera_transform.translation = config.camera_position;
 
-        *aabb = Aabb::from_min_max(Vec3::ZERO, config.aabb_dimensions)
+        *aabb = Aabb::from_min_max(Vec3::ZERO, config.aabb_dimensions);
+        player.swing_interval = config.swing_interval;
     }
 }
@@ -11,7 +11,8 @@ use fmc_networking::{messages, NetworkClient, NetworkData};
 
 use crate::{
     game_state::GameState,
-    player::Player,
+    networking::ServerTick,
+    player::{ActiveCutscene, Player},
     world::{
         blocks::{Blocks, Friction},
         world_map::WorldMap,
@@ -69,11 +70,13 @@ impl Default for Timer {
 fn handle_position_updates_from_server(
     origin: Res<Origin>,
     mut position_events: EventReader<NetworkData<messages::PlayerPosition>>,
-    mut player_query: Query<&mut Transform, With<Player>>,
+    mut player_query: Query<(&mut Transform, &mut Player)>,
 ) {
     for event in position_events.read() {
-        let mut transform = player_query.single_mut();
+        let (mut transform, mut player) = player_query.single_mut();
         transform.translation = (event.position - origin.as_dvec3()).as_vec3();
+        // Knockback is sent as velocity.
+        player.velocity = event.velocity.as_vec3();
     }
 }
 
@@ -83,10 +86,11 @@ fn toggle_flight(
     keys: Res<Input<KeyCode>>,
     window: Query<&Window, With<PrimaryWindow>>,
     mut query: Query<&mut Player>,
+    active_cutscene: Res<ActiveCutscene>,
     mut timer: Local<Timer>,
 ) {
     let window = window.single();
-    if window.cursor.grab_mode == CursorGrabMode::None {
+    if window.cursor.grab_mode == CursorGrabMode::None || active_cutscene.locks_input() {
         return;
     }
 
@@ -114,6 +118,7 @@ fn change_player_acceleration(
     window: Query<&Window, With<PrimaryWindow>>,
     mut player_query: Query<&mut Player>,
     camera_query: Query<&Transform, With<Camera>>,
+    active_cutscene: Res<ActiveCutscene>,
     mut last_jump: Local<Timer>,
 ) {
     let mut player = player_query.single_mut();
@@ -132,7 +137,7 @@ fn change_player_acceleration(
     let mut horizontal_acceleration = Vec3::ZERO;
     let mut vertical_acceleration = Vec3::ZERO;
     for key in keys.get_pressed() {
-        if window.cursor.grab_mode != CursorGrabMode::None {
+        if window.cursor.grab_mode != CursorGrabMode::None && !active_cutscene.locks_input() {
             match key {
                 KeyCode::W => horizontal_acceleration += forward,
                 KeyCode::S => horizontal_acceleration -= forward,
@@ -206,6 +211,7 @@ fn simulate_player_physics(
     world_map: Res<WorldMap>,
     fixed_time: Res<Time>,
     net: Res<NetworkClient>,
+    server_tick: Res<ServerTick>,
     mut player: Query<(&mut Player, &mut Transform, &Aabb)>,
     mut last_position_sent_to_server: Local<Vec3>,
 ) {
@@ -369,6 +375,7 @@ fn simulate_player_physics(
     {
         *last_position_sent_to_server = transform.translation;
         net.send_message(messages::PlayerPosition {
+            tick: server_tick.shown() as u32,
             position: transform.translation.as_dvec3() + origin.as_dvec3(),
             velocity: player.velocity.as_dvec3(),
         });
@@ -0,0 +1,205 @@
+use bevy::prelude::*;
+
+use crate::{
+    assets::{BiomeTint, BiomeVisuals, Biomes, TintedMaterials},
+    game_state::GameState,
+    player::PlayerCameraMarker,
+    rendering::{
+        materials::{BlockMaterial, SkyMaterial},
+        weather::Weather,
+    },
+    world::{world_map::WorldMap, Origin},
+};
+
+// The biomes are sampled in a grid of block columns around the camera, this many on each side,
+// with this much space between them.
+const SAMPLE_RADIUS: i32 = 2;
+const SAMPLE_SPACING: i32 = 4;
+// How much of the way to the visuals of the surrounding biomes it moves each second.
+const BLEND_SPEED: f32 = 0.5;
+// Once this close it stops, so the materials aren't changed every frame.
+const BLEND_THRESHOLD: f32 = 0.001;
+
+// The fog, sky and the colors of some materials change with the biomes around the camera. They
+// move gradually towards the average of the biomes nearby, so walking into another biome fades
+// the look of the old one into the new.
+pub struct BiomeVisualsPlugin;
+impl Plugin for BiomeVisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlendedBiomeVisuals>().add_systems(
+            Update,
+            (
+                blend_biome_visuals,
+                tint_sky.run_if(
+                    resource_changed::<BlendedBiomeVisuals>()
+                        .or_else(resource_changed::<Weather>()),
+                ),
+                tint_materials.run_if(
+                    resource_changed::<BlendedBiomeVisuals>()
+                        .or_else(resource_exists_and_changed::<TintedMaterials>()),
+                ),
+            )
+                .chain()
+                .run_if(GameState::in_game),
+        );
+    }
+}
+
+/// The visuals of the biomes around the camera blended together. The colors are linear.
+#[derive(Resource, Clone, Copy)]
+pub struct BlendedBiomeVisuals {
+    pub fog_color: Vec4,
+    pub fog_distance: f32,
+    pub sky_tint: Vec4,
+    pub water_color: Vec4,
+    pub foliage_color: Vec4,
+}
+
+// Until a biome is known nothing is changed.
+impl Default for BlendedBiomeVisuals {
+    fn default() -> Self {
+        Self {
+            fog_color: Vec4::ZERO,
+            fog_distance: 0.0,
+            sky_tint: Vec4::ONE,
+            water_color: Vec4::ONE,
+            foliage_color: Vec4::ONE,
+        }
+    }
+}
+
+impl From<&BiomeVisuals> for BlendedBiomeVisuals {
+    fn from(visuals: &BiomeVisuals) -> Self {
+        Self {
+            fog_color: Vec4::from(visuals.fog_color.as_linear_rgba_f32()),
+            fog_distance: visuals.fog_distance,
+            sky_tint: Vec4::from(visuals.sky_tint.as_linear_rgba_f32()),
+            water_color: Vec4::from(visuals.water_color.as_linear_rgba_f32()),
+            foliage_color: Vec4::from(visuals.foliage_color.as_linear_rgba_f32()),
+        }
+    }
+}
+
+impl BlendedBiomeVisuals {
+    fn lerp(&self, other: &Self, amount: f32) -> Self {
+        Self {
+            fog_color: self.fog_color.lerp(other.fog_color, amount),
+            fog_distance: self.fog_distance + (other.fog_distance - self.fog_distance) * amount,
+            sky_tint: self.sky_tint.lerp(other.sky_tint, amount),
+            water_color: self.water_color.lerp(other.water_color, amount),
+            foliage_color: self.foliage_color.lerp(other.foliage_color, amount),
+        }
+    }
+
+    // The largest difference between any of the values. The fog distance is counted in
+    // hundreds of blocks so it is comparable to the colors.
+    fn difference(&self, other: &Self) -> f32 {
+        return (self.fog_color - other.fog_color)
+            .abs()
+            .max_element()
+            .max(((self.fog_distance - other.fog_distance) / 100.0).abs())
+            .max((self.sky_tint - other.sky_tint).abs().max_element())
+            .max((self.water_color - other.water_color).abs().max_element())
+            .max(
+                (self.foliage_color - other.foliage_color)
+                    .abs()
+                    .max_element(),
+            );
+    }
+
+    /// Color of the fog, None if the biomes don't have any.
+    pub fn fog_color(&self) -> Option<Color> {
+        if self.fog_color.w <= 0.0 || self.fog_distance <= 0.0 {
+            return None;
+        }
+        let color = self.fog_color;
+        return Some(Color::rgba_linear(color.x, color.y, color.z, color.w));
+    }
+}
+
+fn blend_biome_visuals(
+    time: Res<Time>,
+    origin: Res<Origin>,
+    world_map: Res<WorldMap>,
+    biomes: Option<Res<Biomes>>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    mut blended: ResMut<BlendedBiomeVisuals>,
+) {
+    let Some(biomes) = biomes else {
+        return;
+    };
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+    let camera_position = camera_transform.translation().floor().as_ivec3() + origin.0;
+
+    let mut samples = Vec::new();
+    for x in -SAMPLE_RADIUS..=SAMPLE_RADIUS {
+        for z in -SAMPLE_RADIUS..=SAMPLE_RADIUS {
+            let position = camera_position + IVec3::new(x, 0, z) * SAMPLE_SPACING;
+            if let Some(visuals) = world_map
+                .biome_at(&position)
+                .and_then(|biome_id| biomes.get_visuals(biome_id))
+            {
+                samples.push(BlendedBiomeVisuals::from(visuals));
+            }
+        }
+    }
+
+    // Keeps the old visuals while no chunks are loaded around the camera.
+    if samples.is_empty() {
+        return;
+    }
+
+    let mut target = samples[0];
+    for (index, sample) in samples.iter().enumerate().skip(1) {
+        // A running average
+        target = target.lerp(sample, 1.0 / (index + 1) as f32);
+    }
+
+    if blended.difference(&target) < BLEND_THRESHOLD {
+        return;
+    }
+
+    let amount = (BLEND_SPEED * time.delta_seconds()).min(1.0);
+    *blended = blended.lerp(&target, amount);
+}
+
+// The weather darkens the sky on top of the tint of the biome.
+fn tint_sky(
+    blended: Res<BlendedBiomeVisuals>,
+    weather: Res<Weather>,
+    sky_material_query: Query<&Handle<SkyMaterial>>,
+    mut sky_materials: ResMut<Assets<SkyMaterial>>,
+) {
+    for handle in sky_material_query.iter() {
+        if let Some(material) = sky_materials.get_mut(handle) {
+            let brightness = weather.sky_brightness();
+            material.tint = (blended.sky_tint.truncate() * brightness).extend(blended.sky_tint.w);
+        }
+    }
+}
+
+fn tint_materials(
+    blended: Res<BlendedBiomeVisuals>,
+    tinted_materials: Option<Res<TintedMaterials>>,
+    mut block_materials: ResMut<Assets<BlockMaterial>>,
+) {
+    let Some(tinted_materials) = tinted_materials else {
+        return;
+    };
+
+    for (handle, biome_tint, base_color) in tinted_materials.iter() {
+        let Some(material) = block_materials.get_mut(handle) else {
+            continue;
+        };
+
+        let tint = match biome_tint {
+            BiomeTint::Foliage => blended.foliage_color,
+            BiomeTint::Water => blended.water_color,
+        };
+        // The tint doesn't change the transparency.
+        let color = Vec4::from(base_color.as_linear_rgba_f32()) * tint.truncate().extend(1.0);
+        material.base_color = Color::rgba_linear(color.x, color.y, color.z, color.w);
+    }
+}
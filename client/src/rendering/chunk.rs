@@ -272,6 +272,8 @@ async fn build_mesh(
     // Blocks that use Model to render
     Vec<(Handle<Scene>, Transform)>,
 ) {
+    let _span = info_span!("build_mesh").entered();
+
     let mut mesh_builders = HashMap::new();
     let mut scene_bundles = Vec::new();
 
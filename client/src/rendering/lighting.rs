@@ -9,6 +9,8 @@ use fmc_networking::{messages, NetworkData};
 use crate::{
     constants::CHUNK_SIZE,
     game_state::GameState,
+    player::PlayerCameraMarker,
+    settings::Settings,
     utils,
     world::{
         blocks::Blocks,
@@ -26,12 +28,14 @@ pub struct LightingPlugin;
 impl Plugin for LightingPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(LightMap::default())
+            .insert_resource(HandLight::default())
             .add_event::<TestFinishedLightingEvent>()
             .insert_resource(Queues::default())
             .add_systems(
                 Update,
                 (
                     handle_block_updates.before(propagate_light),
+                    move_hand_light.before(propagate_light),
                     propagate_light.after(handle_new_chunks),
                     send_chunk_mesh_events.after(propagate_light),
                     handle_new_chunks,
@@ -558,6 +562,8 @@ fn propagate_light(
             continue;
         };
 
+        let _span = info_span!("propagate_light_chunk").entered();
+
         if update_queue.timer.elapsed() < QUEUE_DELAY
             && !(light_chunk.is_sunlit || update_queue.sunlit)
         {
@@ -574,7 +580,8 @@ fn propagate_light(
         while let Some(removal) = update_queue.removal.pop() {
             let light = match &mut light_chunk.light {
                 LightStorage::Uniform(uniform_light) => {
-                    if uniform_light.sunlight() != 0 {
+                    // Uniform chunks never contain artificial light, only sunlight can be removed.
+                    if uniform_light.sunlight() != 0 && removal.light.sunlight() != 0 {
                         light_chunk.light =
                             LightStorage::Normal(vec![*uniform_light; CHUNK_SIZE.pow(3)]);
                         &mut light_chunk[removal.index]
@@ -585,7 +592,26 @@ fn propagate_light(
                 LightStorage::Normal(light_chunk) => &mut light_chunk[removal.index],
             };
 
-            if light.sunlight() <= removal.light.sunlight() && removal.light.sunlight() != 0 {
+            let removes_sunlight =
+                removal.light.sunlight() != 0 && light.sunlight() <= removal.light.sunlight();
+            let removes_artificial = removal.light.artificial() != 0
+                && light.artificial() != 0
+                && light.artificial() <= removal.light.artificial();
+
+            if removes_sunlight || removes_artificial {
+                // Only the kind of light that is removed spreads, the other is left as it is.
+                let mut removed = *light;
+                if removes_sunlight {
+                    light.decrement_sun(15);
+                } else {
+                    removed.decrement_sun(15);
+                }
+                if removes_artificial {
+                    light.set_artificial(0);
+                } else {
+                    removed.set_artificial(0);
+                }
+
                 for block_offset in [
                     IVec3::X,
                     IVec3::NEG_X,
@@ -616,13 +642,11 @@ fn propagate_light(
 
                     update_queue.removal.push(LightUpdate {
                         index,
-                        light: light.decrement(
-                            (light.sunlight() != 15 || block_offset != IVec3::NEG_Y) as u8,
+                        light: removed.decrement(
+                            (removed.sunlight() != 15 || block_offset != IVec3::NEG_Y) as u8,
                         ),
                     });
                 }
-
-                *light = Light::new(0, 0);
             } else if light.can_propagate() {
                 for block_offset in [
                     IVec3::NEG_Y,
@@ -869,6 +893,79 @@ fn propagate_light(
     });
 }
 
+/// Light level of the item the player is holding. It is placed where the player is, so that
+/// carrying e.g. a torch lights up caves.
+#[derive(Resource, Default)]
+pub struct HandLight(pub u8);
+
+// Where the hand light was last placed and how bright it was.
+struct PlacedHandLight {
+    position: IVec3,
+    light: u8,
+}
+
+// Propagating light is expensive, so instead of following the player every frame, the hand light
+// is only removed and placed again when the player moves into another block.
+fn move_hand_light(
+    settings: Res<Settings>,
+    origin: Res<Origin>,
+    hand_light: Res<HandLight>,
+    light_map: Res<LightMap>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    mut light_update_queues: ResMut<Queues>,
+    mut placed_hand_light: Local<Option<PlacedHandLight>>,
+) {
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    let light = if settings.hand_light { hand_light.0 } else { 0 };
+    let position = camera_transform.translation().floor().as_ivec3() + origin.0;
+
+    match placed_hand_light.as_ref() {
+        Some(placed) if placed.position == position && placed.light == light => return,
+        None if light == 0 => return,
+        _ => (),
+    }
+
+    if let Some(placed) = placed_hand_light.take() {
+        let (chunk_position, index) =
+            utils::world_position_to_chunk_position_and_block_index(placed.position);
+        // If the chunk has been unloaded the light went with it.
+        if light_map.chunks.contains_key(&chunk_position) {
+            light_update_queues
+                .entry(chunk_position)
+                .or_insert(LightUpdateQueue::new())
+                .removal
+                .push(LightUpdate {
+                    index,
+                    light: Light::new(0, placed.light),
+                });
+        }
+    }
+
+    if light == 0 {
+        return;
+    }
+
+    let (chunk_position, index) = utils::world_position_to_chunk_position_and_block_index(position);
+    // Tried again next frame, the chunk might not have been received yet.
+    if !light_map.chunks.contains_key(&chunk_position) {
+        return;
+    }
+
+    light_update_queues
+        .entry(chunk_position)
+        .or_insert(LightUpdateQueue::new())
+        .propagation
+        .push_back(LightUpdate {
+            index,
+            light: Light::new(0, light),
+        });
+
+    *placed_hand_light = Some(PlacedHandLight { position, light });
+}
+
 fn light_chunk_unloading(world_map: Res<WorldMap>, mut light_map: ResMut<LightMap>) {
     for position in light_map.chunks.keys().cloned().collect::<Vec<_>>().iter() {
         if !world_map.contains_chunk(position) {
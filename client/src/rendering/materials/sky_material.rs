@@ -13,6 +13,8 @@ pub struct SkyMaterial {
     pub mie_k_coefficient: Vec4,
     pub primaries: Vec4,
     pub sun_position: Vec4,
+    // Multiplied with the color of the sky
+    pub tint: Vec4,
     pub depolarization_factor: f32,
     pub luminance: f32,
     pub mie_coefficient: f32,
@@ -37,6 +39,7 @@ impl Default for SkyMaterial {
             mie_k_coefficient: Vec4::new(0.686, 0.678, 0.666, 0.0),
             primaries: Vec4::new(6.8e-7, 5.5e-7, 4.5e-7, 0.0),
             sun_position: Vec4::ZERO,
+            tint: Vec4::ONE,
             depolarization_factor: 0.02,
             luminance: 1.00,
             mie_coefficient: 0.005,
@@ -202,6 +205,7 @@ pub struct SkyMaterialUniform {
     pub mie_k_coefficient: Vec4,
     pub primaries: Vec4,
     pub sun_position: Vec4,
+    pub tint: Vec4,
     pub depolarization_factor: f32,
     pub luminance: f32,
     pub mie_coefficient: f32,
@@ -225,6 +229,7 @@ impl From<&SkyMaterial> for SkyMaterialUniform {
             mie_k_coefficient: material.mie_k_coefficient,
             primaries: material.primaries,
             sun_position: material.sun_position,
+            tint: material.tint,
             depolarization_factor: material.depolarization_factor,
             luminance: material.luminance,
             mie_coefficient: material.mie_coefficient,
@@ -3,10 +3,16 @@ use bevy::prelude::*;
 // TODO: This pub is needed for ExpandedChunk, move the struct to the chunk file and close this off.
 pub mod chunk;
 
-mod lighting;
+mod biome_visuals;
+pub mod lighting;
 pub mod materials;
 mod models;
+mod name_tags;
+mod paintings;
 mod sky;
+mod weather;
+
+pub use biome_visuals::BlendedBiomeVisuals;
 
 pub struct RenderingPlugin;
 impl Plugin for RenderingPlugin {
@@ -15,7 +21,11 @@ impl Plugin for RenderingPlugin {
             .add_plugins(chunk::ChunkMeshPlugin)
             .add_plugins(lighting::LightingPlugin)
             .add_plugins(sky::SkyPlugin)
-            .add_plugins(models::ModelPlugin);
+            .add_plugins(weather::WeatherPlugin)
+            .add_plugins(biome_visuals::BiomeVisualsPlugin)
+            .add_plugins(models::ModelPlugin)
+            .add_plugins(name_tags::NameTagPlugin)
+            .add_plugins(paintings::PaintingPlugin);
         app.configure_sets(
             Update,
             (RenderSet::UpdateBlocks, RenderSet::Light, RenderSet::Mesh).chain(),
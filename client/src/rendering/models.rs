@@ -1,19 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use bevy::{
+    animation::RepeatAnimation,
     gltf::Gltf,
+    math::DVec3,
     pbr::NotShadowCaster,
     prelude::*,
     render::{mesh::Indices, primitives::Aabb},
 };
-use fmc_networking::{messages, NetworkData};
+use fmc_networking::{messages, NetworkClient, NetworkData};
 
 use crate::{
     assets::models::Models,
     game_state::GameState,
-    world::{MovesWithOrigin, Origin},
+    networking::ServerTick,
+    player::PlayerCameraMarker,
+    world::{world_map::WorldMap, MovesWithOrigin, Origin},
 };
 
+use super::name_tags::NameTag;
+
+// How far away models can be hovered, same as the reach of the player.
+const HOVER_DISTANCE: f32 = 5.0;
+// Most transform updates a model keeps, more than this means the updates aren't being shown and
+// the oldest are dropped.
+const MAX_BUFFERED_TRANSFORMS: usize = 64;
+
 pub struct ModelPlugin;
 impl Plugin for ModelPlugin {
     fn build(&self, app: &mut App) {
@@ -21,9 +33,14 @@ impl Plugin for ModelPlugin {
             Update,
             (
                 handle_model_add_delete,
+                handle_name_tags.after(handle_model_add_delete),
+                play_animations.after(handle_model_add_delete),
                 update_model_asset,
                 render_aabb,
-                update_transforms,
+                (buffer_transforms, interpolate_transforms).chain(),
+                update_tints,
+                tint_new_meshes,
+                highlight_hovered_hitbox,
             )
                 .run_if(GameState::in_game),
         );
@@ -33,6 +50,30 @@ impl Plugin for ModelPlugin {
 #[derive(Component)]
 struct ModelMarker;
 
+/// The id of the asset the model uses.
+#[derive(Component)]
+struct ModelAsset(u32);
+
+/// Color the materials of the model are multiplied by.
+#[derive(Component)]
+struct ModelTint(Vec4);
+
+/// Transform updates from the server that haven't been shown yet, and the last one that has. They
+/// are ordered by tick.
+#[derive(Component, Default)]
+struct TransformBuffer(VecDeque<TransformUpdate>);
+
+struct TransformUpdate {
+    tick: u32,
+    position: DVec3,
+    rotation: Quat,
+    scale: Vec3,
+}
+
+/// The material a mesh of a model had before it was tinted.
+#[derive(Component)]
+struct UntintedMaterial(Handle<StandardMaterial>);
+
 /// A map from model id to entity in the ecs
 #[derive(Resource, Deref, DerefMut, Default)]
 struct ModelEntities(HashMap<u32, Entity>);
@@ -86,12 +127,113 @@ fn handle_model_add_delete(
             })
             .insert(MovesWithOrigin)
             .insert(ModelMarker)
+            .insert(ModelAsset(new_model.asset))
+            .insert(ModelTint(new_model.tint))
+            .insert(TransformBuffer::default())
             .id();
 
         model_entities.insert(new_model.id, entity);
     }
 }
 
+fn handle_name_tags(
+    mut commands: Commands,
+    net: Res<NetworkClient>,
+    model_entities: Res<ModelEntities>,
+    mut name_tag_updates: EventReader<NetworkData<messages::ModelNameTag>>,
+) {
+    for name_tag_update in name_tag_updates.read() {
+        let Some(entity) = model_entities.get(&name_tag_update.id) else {
+            continue;
+        };
+
+        if name_tag_update.name.is_empty() {
+            commands.entity(*entity).remove::<NameTag>();
+            continue;
+        }
+
+        let color = match Color::hex(&name_tag_update.color) {
+            Ok(color) => color,
+            Err(_) => {
+                net.disconnect(&format!(
+                    "Server sent a name tag with a malformed color, '{}' is not a valid hex color.",
+                    &name_tag_update.color
+                ));
+                return;
+            }
+        };
+
+        commands.entity(*entity).insert(NameTag {
+            name: name_tag_update.name.clone(),
+            color,
+            always_visible: name_tag_update.always_visible,
+        });
+    }
+}
+
+// The animation player is put on the root of the model's scene, the same way the hand plays the
+// animations of items.
+fn play_animations(
+    mut commands: Commands,
+    models: Res<Models>,
+    gltf_assets: Res<Assets<Gltf>>,
+    model_entities: Res<ModelEntities>,
+    mut model_query: Query<(&ModelAsset, Option<&mut AnimationPlayer>), With<ModelMarker>>,
+    mut animation_events: EventReader<NetworkData<messages::ModelPlayAnimation>>,
+) {
+    for event in animation_events.read() {
+        let Some(entity) = model_entities.get(&event.id) else {
+            continue;
+        };
+        let Ok((model_asset, animation_player)) = model_query.get_mut(*entity) else {
+            continue;
+        };
+
+        if event.animation.is_empty() {
+            if let Some(mut animation_player) = animation_player {
+                animation_player.seek_to(0.0).pause();
+            }
+            continue;
+        }
+
+        let Some(model) = models.get(&model_asset.0) else {
+            continue;
+        };
+        let Some(animation) = gltf_assets
+            .get(&model.handle)
+            .and_then(|gltf| gltf.named_animations.get(&event.animation))
+        else {
+            // Models only have the animations they were made with, the server can't know which
+            // they are.
+            debug!(
+                "Model {} does not have the animation '{}'",
+                event.id, event.animation
+            );
+            continue;
+        };
+
+        let repeat = if event.repeat {
+            RepeatAnimation::Forever
+        } else {
+            RepeatAnimation::Never
+        };
+
+        match animation_player {
+            Some(mut animation_player) => {
+                animation_player
+                    .start(animation.clone())
+                    .set_repeat(repeat)
+                    .resume();
+            }
+            None => {
+                let mut animation_player = AnimationPlayer::default();
+                animation_player.start(animation.clone()).set_repeat(repeat);
+                commands.entity(*entity).insert(animation_player);
+            }
+        }
+    }
+}
+
 fn render_aabb(
     mut commands: Commands,
     mut materials: ResMut<Assets<StandardMaterial>>,
@@ -154,13 +296,10 @@ fn render_aabb(
     }
 }
 
-// TODO: This needs interpolation, it's not bad at 60 ticks but the server should run at a lower
-// rate probably.
-fn update_transforms(
-    origin: Res<Origin>,
+fn buffer_transforms(
     model_entities: Res<ModelEntities>,
     mut transform_updates: EventReader<NetworkData<messages::ModelUpdateTransform>>,
-    mut model_query: Query<&mut Transform, With<ModelMarker>>,
+    mut model_query: Query<&mut TransformBuffer, With<ModelMarker>>,
 ) {
     for transform_update in transform_updates.read() {
         if let Some(entity) = model_entities.get(&transform_update.id) {
@@ -168,12 +307,79 @@ fn update_transforms(
             // transform updated. But there is 1-frame delay for model entity spawn for command
             // application. Should be disconnect I think, if bevy every gets immediate application
             // of commands.
-            let mut transform = match model_query.get_mut(*entity) {
+            let mut buffer = match model_query.get_mut(*entity) {
                 Ok(m) => m,
                 Err(_) => continue,
             };
-            transform.translation = (transform_update.position - origin.as_dvec3()).as_vec3();
-            transform.rotation = transform_update.rotation;
+
+            let tick = transform_update.tick;
+
+            // Updates are only sent when the model moves, so if there are ticks missing it was
+            // standing still. The last update is repeated so it doesn't start moving early.
+            if let Some(last) = buffer.0.back() {
+                if last.tick >= tick {
+                    continue;
+                } else if last.tick + 1 < tick {
+                    let still = TransformUpdate {
+                        tick: tick - 1,
+                        ..*last
+                    };
+                    buffer.0.push_back(still);
+                }
+            }
+
+            buffer.0.push_back(TransformUpdate {
+                tick,
+                position: transform_update.position,
+                rotation: transform_update.rotation,
+                scale: transform_update.scale,
+            });
+
+            while buffer.0.len() > MAX_BUFFERED_TRANSFORMS {
+                buffer.0.pop_front();
+            }
+        }
+    }
+}
+
+// Models are shown where they were at the server tick that is being shown, between the two updates
+// around it.
+fn interpolate_transforms(
+    origin: Res<Origin>,
+    server_tick: Res<ServerTick>,
+    mut model_query: Query<(&mut Transform, &mut TransformBuffer), With<ModelMarker>>,
+) {
+    let shown_tick = server_tick.shown();
+
+    for (mut transform, mut buffer) in model_query.iter_mut() {
+        // Only the last update before the shown tick is needed.
+        while buffer.0.len() > 1 && buffer.0[1].tick as f64 <= shown_tick {
+            buffer.0.pop_front();
+        }
+
+        let (position, rotation, scale) = match (buffer.0.get(0), buffer.0.get(1)) {
+            (Some(from), Some(to)) if from.tick as f64 <= shown_tick => {
+                let t = (shown_tick - from.tick as f64) / (to.tick - from.tick) as f64;
+                (
+                    from.position.lerp(to.position, t),
+                    from.rotation.slerp(to.rotation, t as f32),
+                    from.scale.lerp(to.scale, t as f32),
+                )
+            }
+            // Updates that are ahead of the shown tick wait for it, unless it is the only one.
+            (Some(_), Some(_)) => continue,
+            (Some(last), None) => (last.position, last.rotation, last.scale),
+            (None, _) => continue,
+        };
+
+        let translation = (position - origin.as_dvec3()).as_vec3();
+        if transform.translation != translation
+            || transform.rotation != rotation
+            || transform.scale != scale
+        {
+            transform.translation = translation;
+            transform.rotation = rotation;
+            transform.scale = scale;
         }
     }
 }
@@ -183,11 +389,24 @@ fn update_model_asset(
     models: Res<Models>,
     gltf_assets: Res<Assets<Gltf>>,
     mut asset_updates: EventReader<NetworkData<messages::ModelUpdateAsset>>,
-    mut model_query: Query<&mut Handle<Scene>, With<ModelMarker>>,
+    mut model_query: Query<
+        (&mut Handle<Scene>, &mut ModelAsset, &mut ModelTint),
+        With<ModelMarker>,
+    >,
 ) {
     for asset_update in asset_updates.read() {
         if let Some(entity) = model_entities.get(&asset_update.id) {
-            let mut handle = model_query.get_mut(*entity).unwrap();
+            let (mut handle, mut model_asset, mut tint) = model_query.get_mut(*entity).unwrap();
+
+            // Only marked as changed when it is, every mesh of the model has to be updated.
+            if tint.0 != asset_update.tint {
+                tint.0 = asset_update.tint;
+            }
+
+            if model_asset.0 == asset_update.asset {
+                continue;
+            }
+            model_asset.0 = asset_update.asset;
 
             *handle = if let Some(model) = models.get(&asset_update.asset) {
                 gltf_assets.get(&model.handle).unwrap().scenes[0].clone()
@@ -198,3 +417,176 @@ fn update_model_asset(
         }
     }
 }
+
+// Copies the material with the tint applied. The materials are shared between all models that use
+// the same asset, so they can't be changed directly.
+fn tint_material(
+    materials: &mut Assets<StandardMaterial>,
+    original: &Handle<StandardMaterial>,
+    tint: Vec4,
+) -> Handle<StandardMaterial> {
+    if tint == Vec4::ONE {
+        return original.clone();
+    }
+
+    let Some(material) = materials.get(original) else {
+        return original.clone();
+    };
+
+    let mut material = material.clone();
+    let tint = Vec4::from(Color::rgba(tint.x, tint.y, tint.z, tint.w).as_linear_rgba_f32());
+    let color = Vec4::from(material.base_color.as_linear_rgba_f32()) * tint;
+    material.base_color = Color::rgba_linear(color.x, color.y, color.z, color.w);
+    if color.w < 1.0 && material.alpha_mode == AlphaMode::Opaque {
+        material.alpha_mode = AlphaMode::Blend;
+    }
+
+    return materials.add(material);
+}
+
+fn update_tints(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    tint_query: Query<(Entity, &ModelTint), Changed<ModelTint>>,
+    children_query: Query<&Children>,
+    mut mesh_query: Query<(&mut Handle<StandardMaterial>, Option<&UntintedMaterial>)>,
+) {
+    for (entity, tint) in tint_query.iter() {
+        for child in children_query.iter_descendants(entity) {
+            let Ok((mut material, untinted)) = mesh_query.get_mut(child) else {
+                continue;
+            };
+
+            let original = match untinted {
+                Some(untinted) => untinted.0.clone(),
+                None => {
+                    commands
+                        .entity(child)
+                        .insert(UntintedMaterial(material.clone()));
+                    material.clone()
+                }
+            };
+
+            *material = tint_material(&mut materials, &original, tint.0);
+        }
+    }
+}
+
+// The meshes of a model are spawned some time after the model, and again when its asset changes.
+fn tint_new_meshes(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    tint_query: Query<&ModelTint>,
+    parent_query: Query<&Parent>,
+    mut mesh_query: Query<(Entity, &mut Handle<StandardMaterial>), Added<Handle<StandardMaterial>>>,
+) {
+    for (entity, mut material) in mesh_query.iter_mut() {
+        let Some(tint) = parent_query
+            .iter_ancestors(entity)
+            .find_map(|ancestor| tint_query.get(ancestor).ok())
+        else {
+            continue;
+        };
+
+        if tint.0 == Vec4::ONE {
+            continue;
+        }
+
+        commands
+            .entity(entity)
+            .insert(UntintedMaterial(material.clone()));
+        *material = tint_material(&mut materials, &material, tint.0);
+    }
+}
+
+// How far along the ray it is to where it enters the box, measured in lengths of the direction.
+fn ray_box_intersection(origin: Vec3, direction: Vec3, min: Vec3, max: Vec3) -> Option<f32> {
+    let inverse_direction = 1.0 / direction;
+    let t_min = (min - origin) * inverse_direction;
+    let t_max = (max - origin) * inverse_direction;
+
+    let near = t_min.min(t_max).max_element();
+    let far = t_min.max(t_max).min_element();
+
+    if near > far || far < 0.0 {
+        return None;
+    }
+
+    return Some(near.max(0.0));
+}
+
+// Outlines the hitbox the player is looking at, unless there is a block in front of it.
+fn highlight_hovered_hitbox(
+    mut gizmos: Gizmos,
+    origin: Res<Origin>,
+    models: Res<Models>,
+    world_map: Res<WorldMap>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    model_query: Query<(&GlobalTransform, &ModelAsset), With<ModelMarker>>,
+) {
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+    let camera_transform = camera_transform.compute_transform();
+    let forward = camera_transform.forward();
+
+    let mut closest_distance = HOVER_DISTANCE;
+    if let Some((block_position, ..)) =
+        world_map.raycast_to_block(&camera_transform, origin.0, HOVER_DISTANCE)
+    {
+        let min = (block_position - origin.0).as_vec3();
+        if let Some(distance) =
+            ray_box_intersection(camera_transform.translation, forward, min, min + Vec3::ONE)
+        {
+            closest_distance = distance;
+        }
+    }
+
+    let mut hovered = None;
+
+    for (model_transform, model_asset) in model_query.iter() {
+        let Some(model) = models.get(&model_asset.0) else {
+            continue;
+        };
+
+        if model.hitboxes.is_empty() {
+            continue;
+        }
+
+        // The ray is moved into the model's coordinates, the distances stay the same since the
+        // direction is transformed along with it.
+        let inverse = model_transform.affine().inverse();
+        let local_origin = inverse.transform_point3(camera_transform.translation);
+        let local_direction = inverse.transform_vector3(forward);
+
+        for hitbox in model.hitboxes.iter() {
+            let Some(distance) =
+                ray_box_intersection(local_origin, local_direction, hitbox.min, hitbox.max)
+            else {
+                continue;
+            };
+
+            // Hitboxes the camera is inside of would cover the whole screen.
+            if distance == 0.0 {
+                continue;
+            }
+
+            if distance < closest_distance {
+                closest_distance = distance;
+                hovered = Some((model_transform, hitbox));
+            }
+        }
+    }
+
+    if let Some((model_transform, hitbox)) = hovered {
+        let hitbox_transform = Transform {
+            translation: (hitbox.min + hitbox.max) / 2.0,
+            scale: hitbox.max - hitbox.min,
+            ..default()
+        };
+        gizmos.cuboid(
+            model_transform.mul_transform(hitbox_transform),
+            Color::WHITE,
+        );
+    }
+}
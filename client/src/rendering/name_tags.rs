@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+
+use crate::{game_state::GameState, player::PlayerCameraMarker, ui::DEFAULT_FONT_HANDLE};
+
+// Name tags are shown this high above the origin of their model, a little above a player's head.
+const NAME_TAG_HEIGHT: f32 = 2.1;
+// Name tags that aren't always visible are hidden when the model is further away than this.
+const NAME_TAG_DISTANCE: f32 = 24.0;
+const NAME_TAG_FONT_SIZE: f32 = 7.0;
+
+pub struct NameTagPlugin;
+impl Plugin for NameTagPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (update_labels, position_labels).chain());
+    }
+}
+
+/// A name shown above a model, set by the server.
+#[derive(Component)]
+pub struct NameTag {
+    pub name: String,
+    pub color: Color,
+    /// Shown at any distance, not only when the model is close.
+    pub always_visible: bool,
+}
+
+// The text of a name tag. It is a ui node so it is drawn on top of the world, which means it can't
+// be a child of the model, and has to follow it around on its own.
+#[derive(Component)]
+struct NameTagLabel {
+    model: Entity,
+}
+
+// The label of the model's name tag.
+#[derive(Component)]
+struct LabelEntity(Entity);
+
+fn update_labels(
+    mut commands: Commands,
+    name_tag_query: Query<(Entity, &NameTag, Option<&LabelEntity>), Changed<NameTag>>,
+    mut label_query: Query<&mut Text, With<NameTagLabel>>,
+) {
+    for (model_entity, name_tag, label_entity) in name_tag_query.iter() {
+        let text = Text::from_section(
+            name_tag.name.clone(),
+            TextStyle {
+                font: DEFAULT_FONT_HANDLE,
+                font_size: NAME_TAG_FONT_SIZE,
+                color: name_tag.color,
+            },
+        );
+
+        // The label is gone if the name tag has been removed before.
+        if let Some(mut label_text) =
+            label_entity.and_then(|label_entity| label_query.get_mut(label_entity.0).ok())
+        {
+            *label_text = text;
+            continue;
+        }
+
+        let label_entity = commands
+            .spawn((
+                TextBundle {
+                    text,
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.3).into(),
+                    visibility: Visibility::Hidden,
+                    ..default()
+                },
+                NameTagLabel {
+                    model: model_entity,
+                },
+            ))
+            .id();
+        commands
+            .entity(model_entity)
+            .insert(LabelEntity(label_entity));
+    }
+}
+
+fn position_labels(
+    mut commands: Commands,
+    game_state: Res<State<GameState>>,
+    ui_scale: Res<UiScale>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<PlayerCameraMarker>>,
+    name_tag_query: Query<(&NameTag, &GlobalTransform)>,
+    mut label_query: Query<(Entity, &NameTagLabel, &Node, &mut Style, &mut Visibility)>,
+) {
+    // Models are kept when leaving a server, so the labels are hidden outside of games.
+    let in_game = GameState::in_game(game_state);
+    let camera = camera_query.get_single().ok().filter(|_| in_game);
+
+    for (label_entity, label, node, mut style, mut visibility) in label_query.iter_mut() {
+        // Both when the model has been deleted and when its name tag has been removed.
+        let Ok((name_tag, model_transform)) = name_tag_query.get(label.model) else {
+            commands.entity(label_entity).despawn();
+            continue;
+        };
+
+        let position = model_transform.translation() + Vec3::Y * NAME_TAG_HEIGHT;
+
+        let screen_position = camera
+            .filter(|(_, camera_transform)| {
+                name_tag.always_visible
+                    || camera_transform.translation().distance(position) < NAME_TAG_DISTANCE
+            })
+            .and_then(|(camera, camera_transform)| {
+                camera.world_to_viewport(camera_transform, position)
+            });
+
+        let Some(screen_position) = screen_position else {
+            if *visibility != Visibility::Hidden {
+                *visibility = Visibility::Hidden;
+            }
+            continue;
+        };
+
+        // Centered above the position
+        let size = node.size();
+        style.left = Val::Px((screen_position.x - size.x / 2.0) / ui_scale.0 as f32);
+        style.top = Val::Px((screen_position.y - size.y) / ui_scale.0 as f32);
+        if *visibility != Visibility::Inherited {
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData};
+
+use crate::{
+    game_state::GameState,
+    world::{MovesWithOrigin, Origin},
+};
+
+const PAINTING_TEXTURE_PATH: &str = "server_assets/textures/paintings/";
+
+pub struct PaintingPlugin;
+impl Plugin for PaintingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PaintingEntities::default())
+            .add_systems(
+                Update,
+                handle_painting_add_delete.run_if(GameState::in_game),
+            );
+    }
+}
+
+/// A map from painting id to entity in the ecs
+#[derive(Resource, Deref, DerefMut, Default)]
+struct PaintingEntities(HashMap<u32, Entity>);
+
+fn handle_painting_add_delete(
+    mut commands: Commands,
+    origin: Res<Origin>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut painting_entities: ResMut<PaintingEntities>,
+    mut deleted_paintings: EventReader<NetworkData<messages::DeletePainting>>,
+    mut new_paintings: EventReader<NetworkData<messages::NewPainting>>,
+) {
+    for painting in deleted_paintings.read() {
+        if let Some(entity) = painting_entities.remove(&painting.id) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for painting in new_paintings.read() {
+        if let Some(old_entity) = painting_entities.remove(&painting.id) {
+            commands.entity(old_entity).despawn();
+        }
+
+        let size = Vec2::new(painting.width as f32, painting.height as f32);
+        let normal = painting.normal.as_vec3();
+        let right = Vec3::Y.cross(normal);
+
+        // Center of the blocks it covers, moved back to the wall. It's offset slightly from the
+        // wall so that it doesn't z-fight with it.
+        let center = (painting.position - origin.0).as_vec3()
+            + Vec3::splat(0.5)
+            + right * (size.x - 1.0) / 2.0
+            + Vec3::Y * (size.y - 1.0) / 2.0
+            - normal * 0.49;
+
+        let texture_path = PAINTING_TEXTURE_PATH.to_owned() + &painting.name + ".png";
+
+        let entity = commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(shape::Quad::new(size).into()),
+                material: materials.add(StandardMaterial {
+                    base_color_texture: Some(asset_server.load(texture_path)),
+                    perceptual_roughness: 1.0,
+                    reflectance: 0.0,
+                    ..default()
+                }),
+                transform: Transform {
+                    translation: center,
+                    // The quad faces +Z, turn it to face out of the wall.
+                    rotation: Quat::from_rotation_y(normal.x.atan2(normal.z)),
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(MovesWithOrigin)
+            .id();
+
+        painting_entities.insert(painting.id, entity);
+    }
+}
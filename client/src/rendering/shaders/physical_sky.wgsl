@@ -9,6 +9,7 @@ struct SkyMaterialUniform {
     mieKCoefficient: vec4<f32>,
     primaries: vec4<f32>,
     sunPosition: vec4<f32>,
+    tint: vec4<f32>,
     depolarizationFactor: f32,
     luminance: f32,
     mieCoefficient: f32,
@@ -112,7 +113,7 @@ fn fragment(
     let color: vec3<f32> = curr * whiteScale;
     let retColor: vec3<f32> = pow(color, vec3<f32>(1. / (1.2 + 1.2 * sunfade)));
 
-    let output_color = apply_fog(fog, vec4(retColor, 1.0), in.world_position.xyz, view.world_position.xyz);
+    let output_color = apply_fog(fog, vec4(retColor * sky_material_uniform.tint.rgb, 1.0), in.world_position.xyz, view.world_position.xyz);
     return output_color;
 } 
 
@@ -7,7 +7,11 @@ use bevy::{
 };
 use fmc_networking::{messages, NetworkData};
 
-use crate::{game_state::GameState, player::Player, rendering::materials};
+use crate::{
+    game_state::GameState,
+    player::Player,
+    rendering::{materials, weather::Weather},
+};
 
 const BRIGHTNESS: f32 = 1.0;
 
@@ -57,6 +61,7 @@ fn setup(
 }
 
 fn pass_time(
+    weather: Res<Weather>,
     sky_material_query: Query<&Handle<materials::SkyMaterial>>,
     mut ambient_light: ResMut<AmbientLight>,
     mut materials: ResMut<Assets<materials::SkyMaterial>>,
@@ -68,7 +73,7 @@ fn pass_time(
         return;
     };
 
-    ambient_light.brightness = angle.sin() * BRIGHTNESS;
+    ambient_light.brightness = angle.sin() * BRIGHTNESS * weather.sky_brightness();
 
     let position = Vec3::new(angle.cos(), angle.sin(), 0.0);
     let handle = sky_material_query.single();
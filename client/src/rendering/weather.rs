@@ -0,0 +1,229 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use fmc_networking::{messages, NetworkData};
+
+use crate::{
+    game_state::GameState,
+    player::PlayerCameraMarker,
+    world::{
+        blocks::{Blocks, Friction},
+        world_map::WorldMap,
+        MovesWithOrigin, Origin,
+    },
+};
+
+// How much of the light of the sky is taken away in a storm, rain takes away less.
+const STORM_DARKNESS: f32 = 0.6;
+// How far the intensity moves towards that of the new weather each second.
+const FADE_SPEED: f32 = 0.2;
+// Number of rain drops in a storm, rain has fewer.
+const MAX_RAIN_DROPS: usize = 600;
+// The drops fall within this horizontal distance of the camera, from this high above it.
+const RAIN_RADIUS: f32 = 12.0;
+const RAIN_HEIGHT: f32 = 16.0;
+// Blocks per second
+const FALL_SPEED: f32 = 14.0;
+
+// The server tells which weather it is, and the client fades the sky darker and lets rain fall
+// around the camera to match. The rain drops stop at the first solid block they fall into, so it
+// doesn't rain indoors.
+pub struct WeatherPlugin;
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Weather>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (change_weather, (spawn_rain_drops, move_rain_drops).chain())
+                    .run_if(GameState::in_game),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), reset_weather);
+    }
+}
+
+/// The weather of the server, faded in over a few seconds when it changes.
+#[derive(Resource, Default)]
+pub struct Weather {
+    weather: messages::Weather,
+    // How heavy the weather is, from 0 when clear to 1 in a storm.
+    intensity: f32,
+}
+
+impl Weather {
+    /// How bright the sky is compared to when it is clear.
+    pub fn sky_brightness(&self) -> f32 {
+        return 1.0 - self.intensity * STORM_DARKNESS;
+    }
+
+    fn target_intensity(&self) -> f32 {
+        match self.weather {
+            messages::Weather::Clear => 0.0,
+            messages::Weather::Rain => 0.4,
+            messages::Weather::Storm => 1.0,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct RainDropAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+#[derive(Component)]
+struct RainDrop;
+
+// Positions of the drops don't need to be very random, a xorshift is enough.
+struct RainRandom(u32);
+
+impl Default for RainRandom {
+    fn default() -> Self {
+        Self(0x9e3779b9)
+    }
+}
+
+impl RainRandom {
+    // Random number in the range -1..1
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        return (self.0 as f32 / u32::MAX as f32) * 2.0 - 1.0;
+    }
+
+    // Random position inside the area the rain falls in, relative to the camera.
+    fn offset(&mut self, height: f32) -> Vec3 {
+        return Vec3::new(self.next() * RAIN_RADIUS, height, self.next() * RAIN_RADIUS);
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(RainDropAssets {
+        mesh: meshes.add(Mesh::from(shape::Box::new(0.02, 0.5, 0.02))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgba(0.6, 0.7, 0.9, 0.5),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn change_weather(
+    time: Res<Time>,
+    mut weather: ResMut<Weather>,
+    mut weather_events: EventReader<NetworkData<messages::Weather>>,
+) {
+    if let Some(new_weather) = weather_events.read().last() {
+        weather.weather = **new_weather;
+    }
+
+    // Only changed while fading, so the sky isn't updated every frame.
+    let target = weather.target_intensity();
+    if weather.intensity == target {
+        return;
+    }
+
+    let step = FADE_SPEED * time.delta_seconds();
+    weather.intensity = if weather.intensity < target {
+        (weather.intensity + step).min(target)
+    } else {
+        (weather.intensity - step).max(target)
+    };
+}
+
+// Adds or removes drops until there are as many as the intensity calls for.
+fn spawn_rain_drops(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    rain_drop_assets: Res<RainDropAssets>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    rain_drop_query: Query<Entity, With<RainDrop>>,
+    mut random: Local<RainRandom>,
+) {
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+
+    let drop_count = (weather.intensity * MAX_RAIN_DROPS as f32) as usize;
+    let current_count = rain_drop_query.iter().len();
+
+    if current_count > drop_count {
+        for entity in rain_drop_query.iter().take(current_count - drop_count) {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    for _ in current_count..drop_count {
+        // Spread over the whole height so the rain doesn't start as a single sheet.
+        let height = random.next() * RAIN_HEIGHT;
+        commands.spawn((
+            PbrBundle {
+                mesh: rain_drop_assets.mesh.clone(),
+                material: rain_drop_assets.material.clone(),
+                transform: Transform::from_translation(
+                    camera_transform.translation() + random.offset(height),
+                ),
+                ..default()
+            },
+            NotShadowCaster,
+            MovesWithOrigin,
+            RainDrop,
+        ));
+    }
+}
+
+// Drops that land, or that the camera has moved away from, start over from above the camera.
+fn move_rain_drops(
+    time: Res<Time>,
+    origin: Res<Origin>,
+    world_map: Res<WorldMap>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    mut rain_drop_query: Query<&mut Transform, With<RainDrop>>,
+    mut random: Local<RainRandom>,
+) {
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+    let camera_position = camera_transform.translation();
+
+    let blocks = Blocks::get();
+    let is_solid = |position: Vec3| {
+        let block_position = position.floor().as_ivec3() + origin.0;
+        let Some(block_id) = world_map.get_block(&block_position) else {
+            return false;
+        };
+        matches!(
+            blocks.get_config(block_id).friction(),
+            Friction::Static { .. }
+        )
+    };
+
+    for mut transform in rain_drop_query.iter_mut() {
+        transform.translation.y -= FALL_SPEED * time.delta_seconds();
+
+        let offset = transform.translation - camera_position;
+        if offset.y < -RAIN_HEIGHT
+            || offset.x.abs() > RAIN_RADIUS
+            || offset.z.abs() > RAIN_RADIUS
+            || is_solid(transform.translation)
+        {
+            transform.translation = camera_position + random.offset(RAIN_HEIGHT);
+        }
+    }
+}
+
+fn reset_weather(
+    mut commands: Commands,
+    mut weather: ResMut<Weather>,
+    rain_drop_query: Query<Entity, With<RainDrop>>,
+) {
+    *weather = Weather::default();
+    for entity in rain_drop_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
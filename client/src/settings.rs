@@ -1,20 +1,54 @@
-use bevy::prelude::*;
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+    winit::WinitWindows,
+};
 
-use fmc_networking::{messages, NetworkClient, NetworkData};
+use fmc_networking::{messages, NetworkClient, NetworkData, NetworkSettings, TlsSettings};
 
 pub(super) struct SettingsPlugin;
 impl Plugin for SettingsPlugin {
     fn build(&self, app: &mut App) {
+        // Before loading, so imported settings are used right away.
+        handle_command_line_arguments();
+
         app.insert_resource(Settings::load())
-            .add_systems(Update, set_render_distance);
+            .add_systems(Startup, (apply_window_settings, apply_tls_settings))
+            .add_systems(
+                Update,
+                (
+                    set_render_distance,
+                    apply_effective_render_distance,
+                    track_window_state,
+                ),
+            )
+            .add_systems(Last, save_on_exit);
     }
 }
 
+const SETTINGS_PATH: &str = "./settings.txt";
+
+/// Version of the settings file format. When a setting is renamed or the meaning of its value
+/// changes, bump this and add a step to `MIGRATIONS` that converts the old file.
+const SETTINGS_VERSION: u32 = 1;
+
+// Each step migrates the (name, value) pairs of the settings file from the version at its index to
+// the next version.
+const MIGRATIONS: [fn(&mut Vec<(String, String)>); SETTINGS_VERSION as usize] = [
+    // 0 -> 1: Files written before versioning was added, nothing changed.
+    |_| {},
+];
+
+/// Version of the format of the file client data is exported to. When the layout of the export
+/// changes, bump this and make `import_client_data` read the old layout too.
+const EXPORT_VERSION: u32 = 1;
+// Files with the player's data that are included in exports.
+const EXPORTED_FILES: [&str; 1] = [SETTINGS_PATH];
+
 // TODO: Serialization for better saving/loading? Easy to forget to add a field.
 // I don't think serde supports an easy way to fall back to the default on invalid value without
 // writing a custom fallback function for each value.
-// This makes it useless probably. It needs to replace invalids and then write the result to the
-// file again to resolve. To avoid having to notify the user through the program.
 #[derive(Resource)]
 pub struct Settings {
     /// Render distance in chunks
@@ -29,21 +63,371 @@ pub struct Settings {
     pub flight_speed: f32,
     /// Fog that limits visibility
     pub fog: FogSettings,
+    /// If held items that emit light, like torches, light up the area around the player
+    pub hand_light: bool,
+    /// Size of the window in logical pixels
+    pub window_size: Vec2,
+    /// Position of the window's top left corner in physical pixels. If it is not set, or the
+    /// monitor it was on is no longer connected, the window is centered on the primary monitor.
+    pub window_position: Option<IVec2>,
+    /// Windowed, borderless fullscreen or fullscreen
+    pub window_mode: WindowMode,
+    /// Name of the language file used to translate text from the server
+    pub language: String,
+    /// How connections to servers are encrypted, they are not if None. It must match the server,
+    /// it is set with 'tls_fingerprint', or with 'tls_server_name' and optionally
+    /// 'tls_ca_certificate' in the settings file.
+    pub tls: Option<TlsSettings>,
+    // The render distance from the settings file, 'render_distance' is limited by what the server
+    // allows.
+    preferred_render_distance: u32,
+    // Settings in the file that weren't recognized. They are written back when saving so that
+    // settings from newer versions or typos aren't lost.
+    unknown: Vec<(String, String)>,
 }
 
 impl Settings {
     fn load() -> Self {
         //let path = dirs::config_dir().unwrap().join("fmc/config.txt");
-        let settings = Settings::default();
+        let mut settings = Settings::default();
+
+        let contents = match std::fs::read_to_string(SETTINGS_PATH) {
+            Ok(c) => c,
+            Err(_) => {
+                settings.save();
+                return settings;
+            }
+        };
+
+        let mut version = 0;
+        let mut entries = Vec::new();
+        let mut tls_fingerprint = None;
+        let mut tls_server_name = None;
+        let mut tls_ca_certificate = None;
+
+        for (line_num, line) in contents.lines().enumerate() {
+            // comments
+            if line.starts_with("#") || line.trim().is_empty() {
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once("=") else {
+                warn!(
+                    "Ignoring line {} in the settings file, all settings must be of the format \
+                    'name = setting', it cannot be '{}'",
+                    line_num + 1,
+                    line
+                );
+                continue;
+            };
+            let name = name.trim();
+            let value = value.trim();
+
+            if name == "version" {
+                version = value.parse::<u32>().unwrap_or_else(|_| {
+                    warn!(
+                        "Settings file version must be a number, cannot be: {}",
+                        value
+                    );
+                    0
+                });
+            } else {
+                entries.push((name.to_owned(), value.to_owned()));
+            }
+        }
+
+        if version > SETTINGS_VERSION {
+            warn!(
+                "The settings file is from a newer version of the game ({} > {}), settings that \
+                aren't recognized will be kept as is.",
+                version, SETTINGS_VERSION
+            );
+        }
+
+        for migration in MIGRATIONS.iter().skip(version as usize) {
+            migration(&mut entries);
+        }
+
+        for (name, value) in entries {
+            match name.as_str() {
+                "render_distance" => {
+                    settings.render_distance =
+                        parse_or_default(&name, &value, settings.render_distance);
+                    settings.preferred_render_distance = settings.render_distance;
+                }
+                "fov" => settings.fov = parse_or_default(&name, &value, settings.fov),
+                "volume" => settings.volume = parse_or_default(&name, &value, settings.volume),
+                "sensitivity" => {
+                    settings.sensitivity = parse_or_default(&name, &value, settings.sensitivity)
+                }
+                "flight_speed" => {
+                    settings.flight_speed = parse_or_default(&name, &value, settings.flight_speed)
+                }
+                "hand_light" => {
+                    settings.hand_light = parse_or_default(&name, &value, settings.hand_light)
+                }
+                "window_width" => {
+                    settings.window_size.x = parse_or_default(&name, &value, settings.window_size.x)
+                }
+                "window_height" => {
+                    settings.window_size.y = parse_or_default(&name, &value, settings.window_size.y)
+                }
+                "window_x" => {
+                    let position = settings.window_position.get_or_insert(IVec2::ZERO);
+                    position.x = parse_or_default(&name, &value, position.x);
+                }
+                "window_y" => {
+                    let position = settings.window_position.get_or_insert(IVec2::ZERO);
+                    position.y = parse_or_default(&name, &value, position.y);
+                }
+                "window_mode" => {
+                    settings.window_mode = match value.as_str() {
+                        "windowed" => WindowMode::Windowed,
+                        "borderless" => WindowMode::BorderlessFullscreen,
+                        "fullscreen" => WindowMode::Fullscreen,
+                        _ => {
+                            warn!(
+                                "Invalid value for setting 'window_mode': '{}', must be one of \
+                                'windowed/borderless/fullscreen', using the default instead.",
+                                value
+                            );
+                            settings.window_mode
+                        }
+                    }
+                }
+                "language" => settings.language = value,
+                "tls_fingerprint" => match parse_fingerprint(&value) {
+                    Some(fingerprint) => tls_fingerprint = Some(fingerprint),
+                    None => warn!(
+                        "Invalid value for setting 'tls_fingerprint': '{}', it must be the sha256 \
+                        hash of the server's certificate as 64 hex digits, connections will not be \
+                        encrypted.",
+                        value
+                    ),
+                },
+                "tls_server_name" => tls_server_name = Some(value),
+                "tls_ca_certificate" => tls_ca_certificate = Some(value),
+                _ => {
+                    warn!("Unknown setting in settings file: {}", name);
+                    settings.unknown.push((name, value));
+                }
+            }
+        }
+
+        settings.tls = match (tls_fingerprint, tls_server_name) {
+            (Some(fingerprint), None) => Some(TlsSettings::Fingerprint(fingerprint)),
+            (None, Some(server_name)) => Some(TlsSettings::CertificateAuthority {
+                certificate: tls_ca_certificate.map(Into::into),
+                server_name,
+            }),
+            (Some(_), Some(_)) => {
+                warn!(
+                    "The settings 'tls_fingerprint' and 'tls_server_name' can't both be set, \
+                    connections will not be encrypted."
+                );
+                None
+            }
+            (None, None) => None,
+        };
+
+        if version < SETTINGS_VERSION {
+            // Keep the old file around in case the migration loses something.
+            let backup_path = format!("{}.v{}.bak", SETTINGS_PATH, version);
+            if let Err(e) = std::fs::copy(SETTINGS_PATH, &backup_path) {
+                error!(
+                    "Failed to back up the settings file to {} before migrating it, it will not \
+                    be rewritten.\nError: {}",
+                    backup_path, e
+                );
+            } else {
+                settings.save();
+            }
+        }
 
         return settings;
     }
-    //fn save(&self) {
-    //    let contents = "".to_owned()
-    //    + "render_distance = " + &self.render_distance.to_string() + "\n"
-    //    + "fov = " + &self.fov.to_string();
-    //
-    //}
+
+    #[rustfmt::skip]
+    fn save(&self) {
+        let mut contents = "".to_owned()
+            + "version = " + &SETTINGS_VERSION.to_string() + "\n"
+            + "render_distance = " + &self.preferred_render_distance.to_string() + "\n"
+            + "fov = " + &self.fov.to_string() + "\n"
+            + "volume = " + &self.volume.to_string() + "\n"
+            + "sensitivity = " + &self.sensitivity.to_string() + "\n"
+            + "flight_speed = " + &self.flight_speed.to_string() + "\n"
+            + "hand_light = " + &self.hand_light.to_string() + "\n"
+            + "window_width = " + &self.window_size.x.to_string() + "\n"
+            + "window_height = " + &self.window_size.y.to_string() + "\n"
+            + "window_mode = " + match self.window_mode {
+                WindowMode::BorderlessFullscreen => "borderless",
+                WindowMode::Fullscreen | WindowMode::SizedFullscreen => "fullscreen",
+                WindowMode::Windowed => "windowed",
+            } + "\n"
+            + "language = " + &self.language + "\n";
+
+        if let Some(position) = self.window_position {
+            contents = contents
+                + "window_x = " + &position.x.to_string() + "\n"
+                + "window_y = " + &position.y.to_string() + "\n";
+        }
+
+        match &self.tls {
+            Some(TlsSettings::Fingerprint(fingerprint)) => {
+                let fingerprint: String =
+                    fingerprint.iter().map(|byte| format!("{:02x}", byte)).collect();
+                contents = contents + "tls_fingerprint = " + &fingerprint + "\n";
+            }
+            Some(TlsSettings::CertificateAuthority { certificate, server_name }) => {
+                contents = contents + "tls_server_name = " + server_name + "\n";
+                if let Some(certificate) = certificate {
+                    contents = contents
+                        + "tls_ca_certificate = " + &certificate.to_string_lossy() + "\n";
+                }
+            }
+            _ => (),
+        }
+
+        for (name, value) in self.unknown.iter() {
+            contents = contents + name + " = " + value + "\n";
+        }
+
+        if let Err(e) = std::fs::write(SETTINGS_PATH, contents) {
+            error!("Failed to write settings to {}: {}", SETTINGS_PATH, e);
+        }
+    }
+}
+
+// The player's data can be exported to a single file with '--export <file>' and imported on
+// another machine with '--import <file>'.
+fn handle_command_line_arguments() {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg != "--export" && arg != "--import" {
+            continue;
+        }
+
+        let Some(path) = args.next() else {
+            error!("The '{}' argument needs a file", arg);
+            continue;
+        };
+
+        let result = if arg == "--export" {
+            export_client_data(&path)
+        } else {
+            import_client_data(&path)
+        };
+
+        match result {
+            Ok(()) => info!("Finished {}ing the client data at '{}'", &arg[2..], path),
+            Err(e) => error!(
+                "Failed to {} the client data at '{}': {}",
+                &arg[2..],
+                path,
+                e
+            ),
+        }
+    }
+}
+
+// The export starts with the version of the export format, followed by each of the exported files
+// as a '[path]' line and its contents. The files keep their own versions, and are migrated as usual
+// when they are loaded after an import.
+fn export_client_data(path: &str) -> std::io::Result<()> {
+    let mut contents = format!("export_version = {}\n", EXPORT_VERSION);
+
+    for file_path in EXPORTED_FILES {
+        let file_contents = match std::fs::read_to_string(file_path) {
+            Ok(c) => c,
+            // Files that haven't been written yet have nothing to export.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        contents = contents + "[" + file_path + "]\n" + &file_contents;
+        if !contents.ends_with('\n') {
+            contents.push('\n');
+        }
+    }
+
+    return std::fs::write(path, contents);
+}
+
+fn import_client_data(path: &str) -> std::io::Result<()> {
+    let invalid_data =
+        |message: String| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let contents = std::fs::read_to_string(path)?;
+    let mut lines = contents.lines();
+
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix("export_version = "))
+        .and_then(|version| version.trim().parse::<u32>().ok());
+    match version {
+        Some(version) if version <= EXPORT_VERSION => (),
+        Some(version) => {
+            return Err(invalid_data(format!(
+                "it is from a newer version of the game ({} > {})",
+                version, EXPORT_VERSION
+            )))
+        }
+        None => return Err(invalid_data("it is not an export".to_owned())),
+    }
+
+    let mut files: Vec<(&str, String)> = Vec::new();
+    for line in lines {
+        if let Some(file_path) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            // Only the exported files can be imported, otherwise an import could overwrite any
+            // file the player has access to.
+            let Some(file_path) = EXPORTED_FILES.iter().find(|path| **path == file_path) else {
+                return Err(invalid_data(format!("unknown file '{}'", file_path)));
+            };
+            files.push((file_path, String::new()));
+        } else if let Some((_, file_contents)) = files.last_mut() {
+            file_contents.push_str(line);
+            file_contents.push('\n');
+        } else {
+            return Err(invalid_data(format!("'{}' is not part of any file", line)));
+        }
+    }
+
+    // Nothing is written before the whole export has been read, so a broken export doesn't leave
+    // the data half imported.
+    for (file_path, file_contents) in files {
+        std::fs::write(file_path, file_contents)?;
+    }
+
+    return Ok(());
+}
+
+// Invalid values fall back to the default so a bad edit doesn't stop the game from starting.
+fn parse_or_default<T: std::str::FromStr>(name: &str, value: &str, default: T) -> T {
+    return value.parse::<T>().unwrap_or_else(|_| {
+        warn!(
+            "Invalid value for setting '{}': '{}', using the default instead.",
+            name, value
+        );
+        default
+    });
+}
+
+// The fingerprint is 64 hex digits, they can be separated by colons like 'openssl x509 -noout
+// -fingerprint -sha256 -in <certificate>' prints it.
+fn parse_fingerprint(value: &str) -> Option<[u8; 32]> {
+    let hex: String = value.chars().filter(|c| *c != ':').collect();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut fingerprint = [0; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    return Some(fingerprint);
 }
 
 impl Default for Settings {
@@ -58,20 +442,18 @@ impl Default for Settings {
                 color: Color::NONE,
                 ..default()
             },
+            hand_light: true,
+            window_size: Vec2::new(1280.0, 720.0),
+            window_position: None,
+            window_mode: WindowMode::Windowed,
+            language: "english".to_owned(),
+            tls: None,
+            preferred_render_distance: 16,
+            unknown: Vec::new(),
         }
     }
 }
 
-//fn save_settings(
-//    settings: Res<Settings>
-//) {
-//    // Writes on addition too to remove any invalid values that might have been introduced by the
-//    //user.
-//    if settings.is_changed() {
-//        settings.save();
-//    }
-//}
-
 // TODO: This is a placeholder since there's no settings menu yet.
 fn set_render_distance(
     net: Res<NetworkClient>,
@@ -79,9 +461,98 @@ fn set_render_distance(
     mut server_config_events: EventReader<NetworkData<messages::ServerConfig>>,
 ) {
     for server_config in server_config_events.read() {
-        settings.render_distance = settings.render_distance.min(server_config.render_distance);
+        settings.render_distance = settings
+            .preferred_render_distance
+            .min(server_config.render_distance);
         net.send_message(messages::RenderDistance {
             render_distance: settings.render_distance,
         });
     }
 }
+
+// The server answers with the render distance it will actually provide, and sends it again if it
+// has to change it, e.g. when it is overloaded.
+fn apply_effective_render_distance(
+    mut settings: ResMut<Settings>,
+    mut render_distance_events: EventReader<NetworkData<messages::EffectiveRenderDistance>>,
+) {
+    for event in render_distance_events.read() {
+        if settings.render_distance != event.render_distance {
+            settings.render_distance = event.render_distance;
+        }
+    }
+}
+
+fn apply_tls_settings(settings: Res<Settings>, mut network_settings: ResMut<NetworkSettings>) {
+    network_settings.tls = settings.tls.clone();
+}
+
+fn apply_window_settings(
+    settings: Res<Settings>,
+    winit_windows: NonSend<WinitWindows>,
+    mut window_query: Query<(Entity, &mut Window), With<PrimaryWindow>>,
+) {
+    let Ok((entity, mut window)) = window_query.get_single_mut() else {
+        return;
+    };
+
+    window
+        .resolution
+        .set(settings.window_size.x, settings.window_size.y);
+    window.mode = settings.window_mode;
+
+    let Some(position) = settings.window_position else {
+        return;
+    };
+
+    // The monitor the window was on when it was closed might have been disconnected.
+    let on_monitor = winit_windows
+        .get_window(entity)
+        .map(|winit_window| {
+            winit_window.available_monitors().any(|monitor| {
+                let monitor_position = monitor.position();
+                let monitor_size = monitor.size();
+                position.x >= monitor_position.x
+                    && position.y >= monitor_position.y
+                    && position.x < monitor_position.x + monitor_size.width as i32
+                    && position.y < monitor_position.y + monitor_size.height as i32
+            })
+        })
+        .unwrap_or(false);
+
+    if on_monitor {
+        window.position = WindowPosition::At(position);
+    } else {
+        window.position = WindowPosition::Centered(MonitorSelection::Primary);
+    }
+}
+
+// Keeps the window settings in sync with the window so they can be saved on exit.
+fn track_window_state(
+    mut settings: ResMut<Settings>,
+    window: Query<&Window, (With<PrimaryWindow>, Changed<Window>)>,
+) {
+    let Ok(window) = window.get_single() else {
+        return;
+    };
+
+    // Bypassed so that systems that react to settings changes don't run every time the window
+    // is changed.
+    let settings = settings.bypass_change_detection();
+    settings.window_mode = window.mode;
+
+    // The size and position of fullscreen windows are those of the monitor, keep the windowed
+    // values so it can be restored.
+    if window.mode == WindowMode::Windowed {
+        settings.window_size = Vec2::new(window.resolution.width(), window.resolution.height());
+        if let WindowPosition::At(position) = window.position {
+            settings.window_position = Some(position);
+        }
+    }
+}
+
+fn save_on_exit(settings: Res<Settings>, mut exit_events: EventReader<AppExit>) {
+    if exit_events.read().next().is_some() {
+        settings.save();
+    }
+}
@@ -1,22 +1,81 @@
-use bevy::prelude::*;
+use base64::Engine;
+use bevy::{
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageSampler, ImageType},
+};
+use fmc_networking::{
+    messages, LanServers, NetworkClient, NetworkData, NetworkSettings, ServerStatusEvent, Transport,
+};
 
-use crate::{game_state::GameState, ui::widgets::*};
+use crate::{
+    game_state::GameState,
+    networking::Account,
+    ui::{widgets::*, DEFAULT_FONT_HANDLE},
+};
 
 use super::{InterfaceBundle, Interfaces, UiState};
 
+// The status of the server is requested once the address has stayed the same for this many
+// seconds, so it isn't done for every letter that is typed.
+const STATUS_QUERY_DELAY: f32 = 0.5;
+// Addresses that start with this are connected to over WebSocket instead of tcp.
+const WEBSOCKET_SCHEME: &str = "ws://";
+
 pub struct MultiPlayerPlugin;
 impl Plugin for MultiPlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup).add_systems(
-            Update,
-            press_play_button.run_if(in_state(UiState::MultiPlayer)),
-        );
+        app.init_resource::<StatusQuery>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                OnEnter(UiState::MultiPlayer),
+                (reset_status_query, start_lan_discovery),
+            )
+            .add_systems(OnExit(UiState::MultiPlayer), stop_lan_discovery)
+            .add_systems(
+                Update,
+                (
+                    press_play_button,
+                    query_server_status,
+                    show_server_status,
+                    show_login_queue,
+                    update_lan_server_list,
+                    press_lan_server_button,
+                )
+                    .run_if(in_state(UiState::MultiPlayer)),
+            );
     }
 }
 
 #[derive(Component)]
 struct ServerIp;
 
+#[derive(Component)]
+struct ServerStatusText;
+
+#[derive(Component)]
+struct ServerIcon;
+
+#[derive(Component)]
+struct LanServerList;
+
+#[derive(Component)]
+struct LanServerButton(String);
+
+#[derive(Resource, Default)]
+struct StatusQuery {
+    /// The address in the textbox and how long it has been there.
+    address: String,
+    unchanged_for: f32,
+    /// The address whose status is shown.
+    queried: Option<String>,
+}
+
+#[derive(Component)]
+struct Username;
+
+#[derive(Component)]
+struct Password;
+
 #[derive(Component)]
 struct PlayButton;
 
@@ -38,6 +97,50 @@ fn setup(mut commands: Commands, mut interfaces: ResMut<Interfaces>) {
         })
         .with_children(|parent| {
             parent.spawn_textbox(41.5, "127.0.0.1").insert(ServerIp);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        ImageBundle {
+                            style: Style {
+                                width: Val::Px(32.0),
+                                height: Val::Px(32.0),
+                                display: Display::None,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        ServerIcon,
+                    ));
+                    parent.spawn((
+                        TextBundle::from_section("", status_style()),
+                        ServerStatusText,
+                    ));
+                });
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        row_gap: Val::Px(2.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                LanServerList,
+            ));
+            parent.spawn_textbox(41.5, "").insert(Username);
+            parent
+                .spawn_textbox(41.5, "")
+                .insert((Password, MaskedTextBox));
             parent.spawn_button(200.0, "PLAY").insert(PlayButton);
         })
         .id();
@@ -46,8 +149,12 @@ fn setup(mut commands: Commands, mut interfaces: ResMut<Interfaces>) {
 
 fn press_play_button(
     mut net: ResMut<fmc_networking::NetworkClient>,
+    network_settings: Res<fmc_networking::NetworkSettings>,
+    mut account: ResMut<Account>,
     keys: Res<Input<KeyCode>>,
     server_ip: Query<&TextBox, With<ServerIp>>,
+    username: Query<&TextBox, With<Username>>,
+    password: Query<&TextBox, With<Password>>,
     play_button: Query<&Interaction, (Changed<Interaction>, With<PlayButton>)>,
     mut game_state: ResMut<NextState<GameState>>,
 ) {
@@ -56,13 +163,262 @@ fn press_play_button(
         .is_ok_and(|interaction| *interaction == Interaction::Pressed)
         || keys.just_pressed(KeyCode::Return)
     {
-        let mut ip = server_ip.single().text.to_owned();
+        let ip = server_address(&server_ip.single().text);
 
-        if !ip.contains(":") {
-            ip.push_str(":42069");
-        }
+        account.username = username.single().text.to_owned();
+        account.password = password.single().text.to_owned();
+
+        let (address, transport) = split_scheme(&ip);
+        let network_settings = NetworkSettings {
+            transport,
+            ..network_settings.clone()
+        };
 
-        net.connect(ip.clone());
+        net.connect(address.to_owned(), &network_settings);
         game_state.set(GameState::Connecting);
     }
 }
+
+fn server_address(ip: &str) -> String {
+    let mut address = ip.to_owned();
+    // The scheme has a colon too, only the port should be looked for.
+    if !address.trim_start_matches(WEBSOCKET_SCHEME).contains(":") {
+        address.push_str(":42069");
+    }
+    return address;
+}
+
+// Returns the address without the scheme, and the transport it asks for.
+fn split_scheme(address: &str) -> (&str, Transport) {
+    return match address.strip_prefix(WEBSOCKET_SCHEME) {
+        Some(address) => (address, Transport::WebSocket),
+        None => (address, Transport::Tcp),
+    };
+}
+
+fn status_style() -> TextStyle {
+    return TextStyle {
+        font: DEFAULT_FONT_HANDLE,
+        font_size: 9.0,
+        color: Color::GRAY,
+    };
+}
+
+fn set_status_text(text: &mut Text, value: String) {
+    text.sections = vec![TextSection::new(value, status_style())];
+}
+
+// Splits the message of the day into sections by its markup. Text after '[#rrggbb]' gets that
+// color, and '[/]' goes back to the default color. Markup that can't be read is shown as it is.
+fn format_motd(motd: &str) -> Vec<TextSection> {
+    let mut sections = Vec::new();
+    let mut style = status_style();
+    let mut text = String::new();
+    let mut rest = motd;
+
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+
+        let color = match &rest[1..end] {
+            "/" => Some(status_style().color),
+            tag => tag.strip_prefix('#').and_then(|hex| Color::hex(hex).ok()),
+        };
+
+        if let Some(color) = color {
+            if !text.is_empty() {
+                sections.push(TextSection::new(std::mem::take(&mut text), style.clone()));
+            }
+            style.color = color;
+            rest = &rest[end + 1..];
+        } else {
+            text.push('[');
+            rest = &rest[1..];
+        }
+    }
+
+    text.push_str(rest);
+    sections.push(TextSection::new(text, style));
+
+    return sections;
+}
+
+// The icon is a base64 encoded png, None if it can't be read.
+fn decode_icon(icon: &str) -> Option<Image> {
+    let png = base64::engine::general_purpose::STANDARD
+        .decode(icon)
+        .ok()?;
+    return Image::from_buffer(
+        &png,
+        ImageType::MimeType("image/png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+    )
+    .ok();
+}
+
+// Ask for the status again when the screen is opened, it may have changed since last time.
+fn reset_status_query(mut status_query: ResMut<StatusQuery>) {
+    status_query.queried = None;
+}
+
+fn query_server_status(
+    time: Res<Time>,
+    net: Res<NetworkClient>,
+    network_settings: Res<NetworkSettings>,
+    mut status_query: ResMut<StatusQuery>,
+    server_ip: Query<&TextBox, With<ServerIp>>,
+    mut status_text: Query<&mut Text, With<ServerStatusText>>,
+    mut server_icon: Query<&mut Style, With<ServerIcon>>,
+) {
+    let address = server_address(&server_ip.single().text);
+
+    if address != status_query.address {
+        status_query.address = address;
+        status_query.unchanged_for = 0.0;
+        return;
+    }
+
+    if status_query.queried.as_ref() == Some(&address) {
+        return;
+    }
+
+    status_query.unchanged_for += time.delta_seconds();
+    if status_query.unchanged_for < STATUS_QUERY_DELAY {
+        return;
+    }
+
+    let (stripped_address, transport) = split_scheme(&address);
+    let network_settings = NetworkSettings {
+        transport,
+        ..network_settings.clone()
+    };
+
+    net.query_status(stripped_address.to_owned(), &network_settings);
+    status_query.queried = Some(address);
+    set_status_text(
+        &mut status_text.single_mut(),
+        "Asking the server for its status...".to_owned(),
+    );
+    server_icon.single_mut().display = Display::None;
+}
+
+fn show_server_status(
+    status_query: Res<StatusQuery>,
+    mut images: ResMut<Assets<Image>>,
+    mut status_events: EventReader<ServerStatusEvent>,
+    mut status_text: Query<&mut Text, With<ServerStatusText>>,
+    mut server_icon: Query<(&mut UiImage, &mut Style), With<ServerIcon>>,
+) {
+    for status_event in status_events.read() {
+        // Answers for addresses that have since been replaced are ignored.
+        if status_query
+            .queried
+            .as_deref()
+            .map(|queried| split_scheme(queried).0)
+            != Some(status_event.address.as_str())
+        {
+            continue;
+        }
+
+        let mut text = status_text.single_mut();
+        let (mut icon_image, mut icon_style) = server_icon.single_mut();
+
+        let status = match &status_event.status {
+            Ok(status) => status,
+            Err(err) => {
+                set_status_text(&mut text, format!("Could not reach the server: {}", err));
+                icon_style.display = Display::None;
+                continue;
+            }
+        };
+
+        let info = if status.protocol_version != messages::PROTOCOL_VERSION {
+            format!(
+                "\nThe server runs version {}, which is incompatible with this game",
+                status.version
+            )
+        } else {
+            format!(
+                "\n{} players online, version {}",
+                status.players, status.version
+            )
+        };
+
+        text.sections = format_motd(&status.motd);
+        text.sections.push(TextSection::new(info, status_style()));
+
+        match status.icon.as_deref().and_then(decode_icon) {
+            Some(icon) => {
+                icon_image.texture = images.add(icon);
+                icon_style.display = Display::Flex;
+            }
+            None => icon_style.display = Display::None,
+        }
+    }
+}
+
+// The screen stays open while connecting, so the place in the queue is shown where the status was
+// when the server is full.
+fn show_login_queue(
+    mut login_queue_events: EventReader<NetworkData<messages::LoginQueue>>,
+    mut status_text: Query<&mut Text, With<ServerStatusText>>,
+) {
+    if let Some(login_queue) = login_queue_events.read().last() {
+        set_status_text(
+            &mut status_text.single_mut(),
+            format!(
+                "The server is full, you are number {} in the queue",
+                login_queue.position
+            ),
+        );
+    }
+}
+
+fn start_lan_discovery(mut net: ResMut<NetworkClient>) {
+    net.discover_lan_servers();
+}
+
+fn stop_lan_discovery(mut net: ResMut<NetworkClient>) {
+    net.stop_lan_discovery();
+}
+
+// Servers on the local network are listed under the address, pressing one fills in its address.
+fn update_lan_server_list(
+    mut commands: Commands,
+    lan_servers: Res<LanServers>,
+    lan_server_list: Query<Entity, With<LanServerList>>,
+) {
+    if !lan_servers.is_changed() {
+        return;
+    }
+
+    let entity = lan_server_list.single();
+    commands
+        .entity(entity)
+        .despawn_descendants()
+        .with_children(|parent| {
+            for server in lan_servers.iter() {
+                let address = server.address.to_string();
+                parent
+                    .spawn_button(200.0, &format!("LAN: {}", address))
+                    .insert(LanServerButton(address));
+            }
+        });
+}
+
+fn press_lan_server_button(
+    lan_server_buttons: Query<(&Interaction, &LanServerButton), Changed<Interaction>>,
+    mut server_ip: Query<&mut TextBox, With<ServerIp>>,
+) {
+    for (interaction, button) in lan_server_buttons.iter() {
+        if *interaction == Interaction::Pressed {
+            server_ip.single_mut().text = button.0.clone();
+        }
+    }
+}
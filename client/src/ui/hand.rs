@@ -12,7 +12,8 @@ use fmc_networking::{messages, NetworkClient, NetworkData};
 use crate::{
     assets::models::Models,
     game_state::GameState,
-    player::{Player, PlayerCameraMarker},
+    player::{input_unlocked, ActiveCutscene, Player, PlayerCameraMarker},
+    rendering::lighting::HandLight,
     utils,
     world::{
         blocks::{Block, BlockFace, Blocks},
@@ -22,7 +23,7 @@ use crate::{
 };
 
 use super::server::{
-    items::{ItemBox, ItemBoxSection, ItemStack, Items, SelectedItemBox},
+    items::{ItemBox, ItemBoxSection, ItemId, ItemStack, Items, SelectedItemBox},
     InterfacePath,
 };
 
@@ -30,18 +31,20 @@ pub struct HandPlugin;
 impl Plugin for HandPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(SwitchAnimation::default())
-            .add_systems(PostStartup, setup)
+            .add_systems(PostStartup, (setup, setup_tool_hint))
             .add_systems(
                 Update,
                 (
                     equip_item,
                     play_use_animation,
                     play_switch_animation,
-                    place_block,
-                    send_clicks,
+                    place_block.run_if(input_unlocked),
                 )
                     .run_if(in_state(GameState::Playing)),
-            );
+            )
+            // Also runs while paused, so the server is told the button was released.
+            .add_systems(Update, send_clicks.run_if(GameState::in_game))
+            .add_systems(Update, show_tool_hint);
     }
 }
 
@@ -53,6 +56,12 @@ fn setup(mut commands: Commands, player_camera: Query<Entity, Added<PlayerCamera
     }
 }
 
+// Blocks further away than this can't be interacted with.
+const REACH: f32 = 5.0;
+// The tool hint is shown this far above the bottom of the screen, right above the hotbar.
+const TOOL_HINT_BOTTOM: f32 = 40.0;
+const TOOL_HINT_SIZE: f32 = 16.0;
+
 #[derive(Bundle, Default)]
 struct HandBundle {
     scene: SceneBundle,
@@ -93,6 +102,7 @@ fn equip_item(
     meshes: Res<Assets<Mesh>>,
     animation_clips: Res<Assets<AnimationClip>>,
     mut switch_animation: ResMut<SwitchAnimation>,
+    mut hand_light: ResMut<HandLight>,
     changed_interface_query: Query<
         (&InterfacePath, &ItemBoxSection, &SelectedItemBox),
         Changed<SelectedItemBox>,
@@ -136,6 +146,11 @@ fn equip_item(
 
         let mut new_transform = Transform::default();
 
+        hand_light.0 = item_box
+            .item_stack
+            .item
+            .map_or(0, |item_id| items.get(&item_id).light);
+
         if let Some(item_id) = item_box.item_stack.item {
             let item = items.get(&item_id);
             let model = models.get(&item.model_id).unwrap();
@@ -265,6 +280,7 @@ fn play_use_animation(
     mouse_button_input: Res<Input<MouseButton>>,
     mut hand_animation_query: Query<&mut AnimationPlayer, With<HandMarker>>,
     equipped_item_query: Query<&ItemBox, With<EquippedItem>>,
+    player_query: Query<&Player>,
 ) {
     let Ok(equipped_item) = equipped_item_query.get_single() else {
         return;
@@ -290,10 +306,19 @@ fn play_use_animation(
         let animation_handle = gltf.named_animations.get("left_click").unwrap();
         let animation_clip = animation_clips.get(animation_handle).unwrap();
 
-        if mouse_button_input.just_pressed(MouseButton::Left)
-            || player.elapsed() >= animation_clip.duration()
+        // The animation is repeated at the same rate the server counts swings, and sped up if it
+        // is too long to fit.
+        let swing_interval = player_query.single().swing_interval.as_secs_f32();
+        let period = if swing_interval > 0.0 {
+            swing_interval
+        } else {
+            animation_clip.duration()
+        };
+        let speed = (animation_clip.duration() / period).max(1.0);
+
+        if mouse_button_input.just_pressed(MouseButton::Left) || player.elapsed() >= period * speed
         {
-            player.start(animation_handle.clone());
+            player.start(animation_handle.clone()).set_speed(speed);
         }
     } else if mouse_button_input.just_pressed(MouseButton::Right) {
         player.start_with_transition(
@@ -314,13 +339,26 @@ fn send_clicks(
     window: Query<&Window, With<PrimaryWindow>>,
     mouse_button_input: Res<Input<MouseButton>>,
     net: Res<NetworkClient>,
+    active_cutscene: Res<ActiveCutscene>,
+    mut left_held: Local<bool>,
 ) {
-    if window.single().cursor.grab_mode != CursorGrabMode::None {
-        if mouse_button_input.pressed(MouseButton::Left) {
-            net.send_message(messages::LeftClick);
-        } else if mouse_button_input.just_pressed(MouseButton::Right) {
-            net.send_message(messages::RightClick);
-        }
+    let cursor_grabbed = window.single().cursor.grab_mode != CursorGrabMode::None;
+    let can_click = cursor_grabbed && !active_cutscene.locks_input();
+
+    // The button counts as released when the cursor is freed, e.g. by opening an interface, or
+    // when a cutscene takes control.
+    let held = can_click && mouse_button_input.pressed(MouseButton::Left);
+    if held != *left_held {
+        *left_held = held;
+        net.send_message(if held {
+            messages::LeftClick::Press
+        } else {
+            messages::LeftClick::Release
+        });
+    }
+
+    if can_click && !held && mouse_button_input.just_pressed(MouseButton::Right) {
+        net.send_message(messages::RightClick);
     }
 }
 
@@ -352,7 +390,7 @@ fn place_block(
         let (mut block_position, _block_id, block_face) = match world_map.raycast_to_block(
             &camera_transform.compute_transform(),
             origin.0,
-            5.0,
+            REACH,
         ) {
             Some(i) => i,
             None => return,
@@ -404,3 +442,79 @@ fn place_block(
         block_updates_events.send(NetworkData::new(net.connection_id(), message))
     }
 }
+
+// The image of the equipped item, shown in red while it is used to mine a block it is the wrong
+// tool for, so it is clear why the block breaks slowly.
+#[derive(Component)]
+struct ToolHint;
+
+fn setup_tool_hint(mut commands: Commands) {
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(TOOL_HINT_SIZE),
+                height: Val::Px(TOOL_HINT_SIZE),
+                bottom: Val::Px(TOOL_HINT_BOTTOM),
+                left: Val::Percent(50.0),
+                margin: UiRect::left(Val::Px(-TOOL_HINT_SIZE / 2.0)),
+                ..default()
+            },
+            background_color: Color::rgb(1.0, 0.3, 0.3).into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        ToolHint,
+    ));
+}
+
+fn show_tool_hint(
+    asset_server: Res<AssetServer>,
+    game_state: Res<State<GameState>>,
+    world_map: Res<WorldMap>,
+    items: Option<Res<Items>>,
+    origin: Res<Origin>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    equipped_query: Query<&ItemBox, With<EquippedItem>>,
+    camera_query: Query<&GlobalTransform, With<PlayerCameraMarker>>,
+    mut hint_query: Query<(&mut UiImage, &mut Visibility), With<ToolHint>>,
+    mut shown_item: Local<Option<ItemId>>,
+) {
+    let Ok((mut image, mut visibility)) = hint_query.get_single_mut() else {
+        return;
+    };
+
+    let mining =
+        *game_state.get() == GameState::Playing && mouse_button_input.pressed(MouseButton::Left);
+
+    let wrong_tool = items.filter(|_| mining).and_then(|items| {
+        let item_id = equipped_query.get_single().ok()?.item_stack.item?;
+        let camera_transform = camera_query.get_single().ok()?;
+        let (_, block_id, _) =
+            world_map.raycast_to_block(&camera_transform.compute_transform(), origin.0, REACH)?;
+
+        let item_config = items.get(&item_id);
+        if !Blocks::get()
+            .get_config(block_id)
+            .is_wrong_tool(item_config.categories.as_ref())
+        {
+            return None;
+        }
+
+        if *shown_item != Some(item_id) {
+            *image = asset_server.load(&item_config.image_path).into();
+            *shown_item = Some(item_id);
+        }
+
+        return Some(item_id);
+    });
+
+    let new_visibility = if wrong_tool.is_some() {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if *visibility != new_visibility {
+        *visibility = new_visibility;
+    }
+}
@@ -1,5 +1,8 @@
 use bevy::{
-    asset::load_internal_binary_asset, prelude::*, window::WindowResized, winit::WinitWindows,
+    asset::load_internal_binary_asset,
+    prelude::*,
+    window::{WindowMoved, WindowResized},
+    winit::WinitWindows,
 };
 
 // The ui module handles two different ui systems. The 'server' system which handles in-game ui
@@ -10,6 +13,8 @@ mod gui;
 // Hand/equipped item is a special type of interface.
 mod hand;
 pub mod server;
+// Server performance stats for operators.
+mod stats;
 // Common widgets used between the two ui systems.
 mod widgets;
 
@@ -27,9 +32,19 @@ impl Plugin for UiPlugin {
             gui::GuiPlugin,
             hand::HandPlugin,
             server::ServerInterfacesPlugin,
+            stats::StatsPlugin,
         ))
-        .add_systems(Startup, setup)
-        .add_systems(Update, scale_ui.run_if(on_event::<WindowResized>()));
+        .add_systems(Startup, set_monitor_width)
+        .add_systems(
+            Update,
+            (
+                // The window might have been moved to a monitor with a different resolution.
+                set_monitor_width.run_if(on_event::<WindowMoved>()),
+                scale_ui.run_if(
+                    on_event::<WindowResized>().or_else(resource_changed::<LogicalMonitorWidth>()),
+                ),
+            ),
+        );
 
         // TODO: It would be nice to overwrite bevy's default handle
         // instead, so it never has to be specified by any entity, but doing it increases compile time
@@ -43,23 +58,34 @@ impl Plugin for UiPlugin {
     }
 }
 
-fn setup(
+fn set_monitor_width(
     mut commands: Commands,
     winit_windows: NonSend<WinitWindows>,
     windows: Query<Entity, &Window>,
+    current_width: Option<Res<LogicalMonitorWidth>>,
 ) {
     let entity = windows.single();
-    let id = winit_windows.entity_to_winit.get(&entity).unwrap();
-    let monitor = winit_windows
-        .windows
-        .get(id)
-        .unwrap()
+    let winit_window = winit_windows.get_window(entity).unwrap();
+    // The monitor can't always be determined, e.g. while it is being disconnected, fall back to
+    // the primary monitor, and then the window itself.
+    let width = match winit_window
         .current_monitor()
-        .unwrap();
-    let resolution = monitor.size().to_logical(monitor.scale_factor());
-    commands.insert_resource(LogicalMonitorWidth {
-        width: resolution.width,
-    });
+        .or_else(|| winit_window.primary_monitor())
+    {
+        Some(monitor) => monitor.size().to_logical(monitor.scale_factor()).width,
+        None => {
+            warn!("Could not find the monitor the window is on, the ui might be scaled wrong.");
+            winit_window
+                .inner_size()
+                .to_logical(winit_window.scale_factor())
+                .width
+        }
+    };
+
+    if current_width.is_some_and(|current| current.width == width) {
+        return;
+    }
+    commands.insert_resource(LogicalMonitorWidth { width });
 }
 
 #[derive(Resource)]
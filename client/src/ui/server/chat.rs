@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkClient, NetworkData};
+
+use crate::{game_state::GameState, ui::widgets::FocusedTextBox};
+
+use super::{InterfacePath, Interfaces};
+
+/// The textbox the player writes chat messages in, defined by the server's interfaces.
+pub const CHAT_INPUT_PATH: &str = "chat/input";
+// The textbox chat messages are shown in.
+const CHAT_HISTORY_PATH: &str = "chat/history";
+
+const CHAT_FONT_SIZE: f32 = 8.0;
+const CHAT_TEXT_COLOR: &str = "#ffffff";
+
+pub struct ChatPlugin;
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedChatChannel>().add_systems(
+            Update,
+            (select_chat_channel, show_chat_messages).run_if(GameState::in_game),
+        );
+    }
+}
+
+/// The channel the messages written in the chat input are sent to. Changed by pressing tab while
+/// writing.
+#[derive(Resource)]
+pub struct SelectedChatChannel(pub messages::ChatChannel);
+
+impl Default for SelectedChatChannel {
+    fn default() -> Self {
+        Self(messages::ChatChannel::Global)
+    }
+}
+
+fn channel_name(channel: messages::ChatChannel) -> &'static str {
+    match channel {
+        messages::ChatChannel::Global => "global",
+        messages::ChatChannel::System => "system",
+        messages::ChatChannel::Local => "local",
+        messages::ChatChannel::Team => "team",
+        messages::ChatChannel::Whisper => "whisper",
+    }
+}
+
+fn select_chat_channel(
+    net: Res<NetworkClient>,
+    keyboard: Res<Input<KeyCode>>,
+    interfaces: Res<Interfaces>,
+    mut selected_channel: ResMut<SelectedChatChannel>,
+    focused_text_box: Query<&InterfacePath, With<FocusedTextBox>>,
+    mut text_box_updates: EventWriter<NetworkData<messages::InterfaceTextBoxUpdate>>,
+) {
+    if !keyboard.just_pressed(KeyCode::Tab) {
+        return;
+    }
+
+    match focused_text_box.get_single() {
+        Ok(interface_path) if interface_path.0 == CHAT_INPUT_PATH => (),
+        _ => return,
+    }
+
+    // Whispers are sent with '/msg', they don't have their own channel to write in.
+    selected_channel.0 = match selected_channel.0 {
+        messages::ChatChannel::Global => messages::ChatChannel::Local,
+        messages::ChatChannel::Local => messages::ChatChannel::Team,
+        _ => messages::ChatChannel::Global,
+    };
+
+    if interfaces.contains_key(CHAT_HISTORY_PATH) {
+        let mut chat_update = messages::InterfaceTextBoxUpdate::new(CHAT_HISTORY_PATH);
+        chat_update.append_line().with_text(
+            format!(
+                "Chatting in the {} channel",
+                channel_name(selected_channel.0)
+            ),
+            CHAT_FONT_SIZE,
+            CHAT_TEXT_COLOR,
+        );
+        text_box_updates.send(NetworkData::new(net.connection_id(), chat_update));
+    }
+}
+
+// Chat messages are shown by turning them into lines of the chat history textbox.
+fn show_chat_messages(
+    net: Res<NetworkClient>,
+    interfaces: Res<Interfaces>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageServer>>,
+    mut text_box_updates: EventWriter<NetworkData<messages::InterfaceTextBoxUpdate>>,
+) {
+    // Servers without a chat can't send chat messages.
+    if !interfaces.contains_key(CHAT_HISTORY_PATH) {
+        return;
+    }
+
+    for chat_message in chat_messages.read() {
+        let color = chat_message.color.as_deref().unwrap_or(CHAT_TEXT_COLOR);
+
+        let mut chat_update = messages::InterfaceTextBoxUpdate::new(CHAT_HISTORY_PATH);
+        let line = chat_update.append_line();
+
+        // Messages that aren't seen by everyone are marked with their channel.
+        match chat_message.channel {
+            messages::ChatChannel::Global | messages::ChatChannel::System => (),
+            channel => {
+                line.with_text(
+                    format!("({}) ", channel_name(channel)),
+                    CHAT_FONT_SIZE,
+                    color,
+                );
+            }
+        }
+
+        if let Some(sender) = &chat_message.sender {
+            line.with_text(format!("[{}] ", sender), CHAT_FONT_SIZE, color);
+        }
+
+        match &chat_message.translation_args {
+            Some(args) => {
+                line.with_translation(&chat_message.message, args.clone(), CHAT_FONT_SIZE, color)
+            }
+            None => line.with_text(chat_message.message.clone(), CHAT_FONT_SIZE, color),
+        };
+
+        text_box_updates.send(NetworkData::new(net.connection_id(), chat_update));
+    }
+}
@@ -2,10 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use bevy::{gltf::Gltf, prelude::*};
 
-use fmc_networking::{
-    messages::{self, ServerConfig},
-    BlockId, NetworkClient, NetworkData,
-};
+use fmc_networking::{messages, BlockId, NetworkClient, NetworkData};
 use serde::{Deserialize, Serialize};
 
 use crate::{assets::models::Models, game_state::GameState, world::blocks::Blocks};
@@ -53,6 +50,8 @@ pub struct ItemConfig {
     pub categories: Option<HashSet<String>>,
     /// Block that is placed when the item is used on a surface.
     pub block: Option<BlockId>,
+    /// Light level the item emits while it is held.
+    pub light: u8,
 }
 
 #[derive(Deserialize)]
@@ -63,6 +62,7 @@ struct ItemConfigJson {
     stack_size: u32,
     categories: Option<HashSet<String>>,
     block: Option<String>,
+    light: Option<u8>,
     //properties: serde_json::Map<String, serde_json::Value>,
 }
 
@@ -81,40 +81,60 @@ impl Items {
     }
 }
 
-// Ran while loading assets
-pub fn load_items(
-    mut commands: Commands,
-    server_config: Res<ServerConfig>,
-    net: Res<NetworkClient>,
-    models: Res<Models>,
-    gltf_assets: Res<Assets<Gltf>>,
-) {
-    let blocks = Blocks::get();
-    let mut configs = HashMap::new();
+/// The item configs as they were read from the resource pack, they are turned into [Items] once
+/// the blocks and models have been loaded.
+#[derive(Resource)]
+pub struct ItemConfigFiles(Vec<(String, ItemId, ItemConfigJson)>);
+
+/// Read the config of each item. Ran on the task pool while loading assets, errors are formatted
+/// to be shown to the player.
+pub fn read_item_configs(item_ids: HashMap<String, ItemId>) -> Result<ItemConfigFiles, String> {
+    let mut configs = Vec::with_capacity(item_ids.len());
 
-    for (filename, id) in server_config.item_ids.iter() {
-        let file_path = "server_assets/items/configurations/".to_owned() + filename + ".json";
+    for (filename, id) in item_ids {
+        let file_path = "server_assets/items/configurations/".to_owned() + &filename + ".json";
 
         let file = match std::fs::File::open(&file_path) {
             Ok(f) => f,
-            Err(e) => panic!(
-                "Failed to open item config at path: {}\nError: {}",
-                &file_path, e
-            ),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to open item config at path: {}\nError: {}",
+                    &file_path, e
+                ));
+            }
         };
 
         let json_config: ItemConfigJson = match serde_json::from_reader(&file) {
             Ok(c) => c,
             Err(e) => {
-                net.disconnect(&format!(
+                return Err(format!(
                     "Misconfigured resource pack: failed to read item config at: {}.\n\
                         Error: {}",
                     &file_path, e
                 ));
-                return;
             }
         };
 
+        configs.push((file_path, id, json_config));
+    }
+
+    return Ok(ItemConfigFiles(configs));
+}
+
+// Ran while loading assets
+pub fn load_items(
+    mut commands: Commands,
+    net: Res<NetworkClient>,
+    models: Res<Models>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut config_files: ResMut<ItemConfigFiles>,
+) {
+    let blocks = Blocks::get();
+    let mut configs = HashMap::new();
+
+    commands.remove_resource::<ItemConfigFiles>();
+
+    for (file_path, id, json_config) in config_files.0.drain(..) {
         let model_id = match models.get_id_by_filename(&json_config.equip_model) {
             Some(id) => id,
             None => {
@@ -163,6 +183,7 @@ pub fn load_items(
             stack_size: json_config.stack_size,
             categories: json_config.categories,
             block: block_id,
+            light: json_config.light.unwrap_or(0).min(15),
         };
 
         if !std::path::Path::new(&config.image_path).exists() {
@@ -174,7 +195,7 @@ pub fn load_items(
             return;
         }
 
-        configs.insert(*id, config);
+        configs.insert(id, config);
     }
 
     commands.insert_resource(Items { configs });
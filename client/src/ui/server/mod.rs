@@ -9,14 +9,16 @@ use bevy::{
 use fmc_networking::{messages, NetworkClient, NetworkData};
 use serde::Deserialize;
 
-use crate::{game_state::GameState, ui::widgets::TextBox};
+use crate::{assets::Translations, game_state::GameState, ui::widgets::TextBox};
 
 use self::items::{CursorItemBox, ItemBoxSection};
 use super::widgets::Widgets;
 
+mod chat;
 pub mod items;
 pub mod key_bindings;
 mod textbox;
+mod variables;
 
 const INTERFACE_CONFIG_PATH: &str = "server_assets/interfaces/";
 const INTERFACE_TEXTURE_PATH: &str = "server_assets/textures/interfaces/";
@@ -30,7 +32,9 @@ impl Plugin for ServerInterfacesPlugin {
             .add_plugins((
                 items::ItemPlugin,
                 textbox::TextBoxPlugin,
+                chat::ChatPlugin,
                 key_bindings::KeyBindingsPlugin,
+                variables::VariablesPlugin,
             ))
             .add_systems(
                 Update,
@@ -60,89 +64,134 @@ pub struct InterfacePath(pub String);
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct Interfaces(HashMap<String, Entity>);
 
-// Called when loading assets.
-pub fn load_interfaces(
-    mut commands: Commands,
-    net: Res<NetworkClient>,
-    asset_server: Res<AssetServer>,
-) {
-    let mut interfaces = Interfaces::default();
+/// The interface configs as they were read from the resource pack, along with the dimensions of
+/// the images they use.
+#[derive(Resource)]
+pub struct InterfaceConfigs {
+    configs: Vec<NodeConfig>,
+    // Image path -> dimensions
+    image_dimensions: HashMap<String, Vec2>,
+}
+
+/// Read the interface configs. Ran on the task pool while loading assets, errors are formatted to
+/// be shown to the player.
+pub fn read_interface_configs() -> Result<InterfaceConfigs, String> {
     let directory = match std::fs::read_dir(INTERFACE_CONFIG_PATH) {
         Ok(dir) => dir,
         Err(e) => {
-            net.disconnect(&format!(
+            return Err(format!(
                 "Misconfigured resource pack: Failed to read interface configuration directory '{}'\n\
                 Error: {}",
                 INTERFACE_CONFIG_PATH, e
             ));
-            return;
         }
     };
 
+    // NOTE(WORKAROUND): When spawning an ImageBundle, the dimensions of the image are
+    // inferred, but if it has children it's discarded and it uses the size of the children
+    // instead. Images must therefore be spawned with defined width/height to display correctly.
+    fn read_image_dimensions(image_path: &str) -> Vec2 {
+        let image_data = match std::fs::read(INTERFACE_TEXTURE_PATH.to_owned() + image_path) {
+            Ok(i) => i,
+            Err(_) => {
+                return Vec2::ZERO;
+            }
+        };
+
+        let image = match Image::from_buffer(
+            &image_data,
+            bevy::render::texture::ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            false,
+            ImageSampler::Default,
+        ) {
+            Ok(i) => i,
+            Err(_) => {
+                return Vec2::ZERO;
+            }
+        };
+
+        return image.size_f32();
+    }
+
+    fn read_all_image_dimensions(
+        config: &NodeConfig,
+        image_dimensions: &mut HashMap<String, Vec2>,
+    ) {
+        if let Some(image_path) = &config.image {
+            if !image_dimensions.contains_key(image_path) {
+                image_dimensions.insert(image_path.clone(), read_image_dimensions(image_path));
+            }
+        }
+
+        match &config.content {
+            NodeContent::Nodes(nodes) | NodeContent::Button(nodes) => {
+                for child_config in nodes.iter() {
+                    read_all_image_dimensions(child_config, image_dimensions);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut configs = Vec::new();
+    let mut image_dimensions = HashMap::new();
+
     for dir_entry in directory {
         let file_path = match dir_entry {
             Ok(d) => d.path(),
             Err(e) => {
-                net.disconnect(&format!(
+                return Err(format!(
                     "Misconfigured resource pack: Failed to read the file path of an interface config\n\
                     Error: {}",
                     e
                 ));
-                return;
             }
         };
         let file = match std::fs::File::open(&file_path) {
             Ok(f) => f,
             Err(e) => {
-                net.disconnect(&format!(
+                return Err(format!(
                     "Misconfigured resource pack: Failed to open interface configuration at: '{}'\n\
                     Error: {}",
                     &file_path.display(),
                     e
                 ));
-                return;
             }
         };
         let node_config: NodeConfig = match serde_json::from_reader(&file) {
             Ok(c) => c,
             Err(e) => {
-                net.disconnect(&format!(
-                "Misconfigured resource pack: Failed to read interface configuration at: '{}'\n\
-                Error: {}",
-                &file_path.display(),
-                e
-            ));
-                return;
+                return Err(format!(
+                    "Misconfigured resource pack: Failed to read interface configuration at: '{}'\n\
+                    Error: {}",
+                    &file_path.display(),
+                    e
+                ));
             }
         };
 
-        // NOTE(WORKAROUND): When spawning an ImageBundle, the dimensions of the image are
-        // inferred, but if it has children it's discarded and it uses the size of the children
-        // instead. Images must therefore be spawned with defined width/height to display correctly.
-        fn read_image_dimensions(image_path: &str) -> Vec2 {
-            let image_data = match std::fs::read(INTERFACE_TEXTURE_PATH.to_owned() + image_path) {
-                Ok(i) => i,
-                Err(_) => {
-                    return Vec2::ZERO;
-                }
-            };
+        read_all_image_dimensions(&node_config, &mut image_dimensions);
+        configs.push(node_config);
+    }
 
-            let image = match Image::from_buffer(
-                &image_data,
-                bevy::render::texture::ImageType::Extension("png"),
-                CompressedImageFormats::NONE,
-                false,
-                ImageSampler::Default,
-            ) {
-                Ok(i) => i,
-                Err(_) => {
-                    return Vec2::ZERO;
-                }
-            };
+    return Ok(InterfaceConfigs {
+        configs,
+        image_dimensions,
+    });
+}
 
-            return image.size_f32();
-        }
+// Called when loading assets.
+pub fn load_interfaces(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    translations: Res<Translations>,
+    interface_configs: Res<InterfaceConfigs>,
+) {
+    let mut interfaces = Interfaces::default();
+    commands.remove_resource::<InterfaceConfigs>();
 
+    for node_config in interface_configs.configs.iter() {
         // TODO: The server needs to validate that no interfaces share a name. The client doesn't
         // need to care, it will just overwrite. It is hard to do with this recursion too.
         fn spawn_interface(
@@ -150,7 +199,9 @@ pub fn load_interfaces(
             parent_path: String,
             config: &NodeConfig,
             interfaces: &mut Interfaces,
+            image_dimensions: &HashMap<String, Vec2>,
             asset_server: &AssetServer,
+            translations: &Translations,
         ) {
             let interface_path = if let Some(name) = &config.name {
                 let interface_path = if parent_path == "" {
@@ -168,7 +219,7 @@ pub fn load_interfaces(
             };
 
             let style = if let Some(image_path) = &config.image {
-                let dimensions = read_image_dimensions(&image_path);
+                let dimensions = image_dimensions[image_path];
                 let mut style = Style::from(config.style.clone());
                 style.width = Val::Px(dimensions.x);
                 style.height = Val::Px(dimensions.y);
@@ -209,7 +260,9 @@ pub fn load_interfaces(
                                 interface_path.clone(),
                                 child_config,
                                 interfaces,
+                                image_dimensions,
                                 asset_server,
+                                translations,
                             )
                         }
                     });
@@ -227,7 +280,9 @@ pub fn load_interfaces(
                                 interface_path.clone(),
                                 child_config,
                                 interfaces,
+                                image_dimensions,
                                 asset_server,
+                                translations,
                             )
                         }
                     });
@@ -254,9 +309,11 @@ pub fn load_interfaces(
                     font_size,
                     color,
                 } => {
+                    // Interface text is always treated as a translation key, if there is no
+                    // translation for it, it is shown as is.
                     entity_commands.with_children(|parent| {
                         parent.spawn_text(
-                            text,
+                            translations.get(text),
                             *font_size,
                             *color,
                             style.flex_direction,
@@ -265,6 +322,82 @@ pub fn load_interfaces(
                         );
                     });
                 }
+                NodeContent::Variable {
+                    text,
+                    variables,
+                    font_size,
+                    color,
+                } => {
+                    entity_commands.insert(variables::BoundText {
+                        text: text.clone(),
+                        variables: variables.clone(),
+                    });
+                    // Filled in when the variables are set.
+                    entity_commands.with_children(|parent| {
+                        parent.spawn_text(
+                            "",
+                            *font_size,
+                            *color,
+                            style.flex_direction,
+                            style.justify_content,
+                            style.align_items,
+                        );
+                    });
+                }
+                NodeContent::Bar {
+                    variable,
+                    max,
+                    color,
+                    image,
+                    vertical,
+                } => {
+                    // The node itself is the background of the bar, the fill is laid over it and
+                    // grows from the left, or from the bottom if it is vertical.
+                    let fill_style = if *vertical {
+                        Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Px(0.0),
+                            bottom: Val::Px(0.0),
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(0.0),
+                            ..default()
+                        }
+                    } else {
+                        Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Px(0.0),
+                            top: Val::Px(0.0),
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        }
+                    };
+
+                    let mut fill = Entity::PLACEHOLDER;
+                    entity_commands.with_children(|parent| {
+                        fill = parent
+                            .spawn((
+                                NodeBundle {
+                                    style: fill_style,
+                                    background_color: (*color).into(),
+                                    ..default()
+                                },
+                                image.as_ref().map_or(UiImage::default(), |path| {
+                                    asset_server
+                                        .load(INTERFACE_TEXTURE_PATH.to_owned() + &path)
+                                        .into()
+                                }),
+                            ))
+                            .id();
+                    });
+
+                    entity_commands.insert(variables::Bar {
+                        variable: variable.clone(),
+                        max: *max,
+                        vertical: *vertical,
+                        fill,
+                    });
+                }
                 NodeContent::None => (),
             }
         }
@@ -284,9 +417,11 @@ pub fn load_interfaces(
                 spawn_interface(
                     &mut entity_commands,
                     String::new(),
-                    &node_config,
+                    node_config,
                     &mut interfaces,
+                    &interface_configs.image_dimensions,
                     &asset_server,
+                    &translations,
                 );
 
                 let interface_config = InterfaceRootConfig {
@@ -305,6 +440,8 @@ pub fn load_interfaces(
     }
 
     commands.insert_resource(interfaces);
+    // Variables from the last server would show up in interfaces with the same names.
+    commands.insert_resource(variables::InterfaceVariables::default());
 
     commands
         .spawn((
@@ -408,6 +545,24 @@ enum NodeContent {
         font_size: f32,
         color: Color,
     },
+    // Text filled with variables set by the server, e.g. "Quest: {}" bound to "quest_name".
+    Variable {
+        text: String,
+        #[serde(default)]
+        variables: Vec<String>,
+        font_size: f32,
+        color: Color,
+    },
+    // Bar filled by how large a variable set by the server is compared to the max, e.g. a mana
+    // bar. The color is used for the filled part, it can tint the image.
+    Bar {
+        variable: String,
+        max: f32,
+        color: Color,
+        image: Option<String>,
+        #[serde(default)]
+        vertical: bool,
+    },
 }
 
 #[derive(Deserialize, Default, Clone, Debug)]
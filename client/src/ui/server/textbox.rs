@@ -2,6 +2,7 @@ use bevy::{prelude::*, text::TextLayoutInfo};
 use fmc_networking::{messages, NetworkClient, NetworkData};
 
 use crate::{
+    assets::Translations,
     game_state::GameState,
     ui::{
         widgets::{FocusedTextBox, TextBox},
@@ -9,7 +10,10 @@ use crate::{
     },
 };
 
-use super::{InterfacePath, Interfaces};
+use super::{
+    chat::{SelectedChatChannel, CHAT_INPUT_PATH},
+    InterfacePath, Interfaces,
+};
 
 pub struct TextBoxPlugin;
 impl Plugin for TextBoxPlugin {
@@ -46,6 +50,7 @@ fn handle_text_box_updates(
     mut commands: Commands,
     net: Res<NetworkClient>,
     interfaces: Res<Interfaces>,
+    translations: Res<Translations>,
     text_box_query: Query<(Option<&Children>, &TextBox, Has<FadeLines>)>,
     mut text_box_update_events: EventReader<NetworkData<messages::InterfaceTextBoxUpdate>>,
 ) {
@@ -87,8 +92,12 @@ fn handle_text_box_updates(
                         return;
                     }
                 };
+                let text = match &section.translation_args {
+                    Some(args) => translations.format(&section.text, args),
+                    None => section.text.clone(),
+                };
                 sections.push(TextSection {
-                    value: section.text.clone(),
+                    value: text.clone(),
                     style: TextStyle {
                         font: DEFAULT_FONT_HANDLE,
                         font_size: section.font_size,
@@ -96,7 +105,7 @@ fn handle_text_box_updates(
                     },
                 });
                 shadow_sections.push(TextSection {
-                    value: section.text.clone(),
+                    value: text,
                     style: TextStyle {
                         font: DEFAULT_FONT_HANDLE,
                         font_size: section.font_size,
@@ -212,6 +221,7 @@ fn size_textbox_lines(
 
 fn send_text(
     net: Res<NetworkClient>,
+    selected_channel: Res<SelectedChatChannel>,
     mut focused_text_box: Query<(&mut TextBox, &InterfacePath), With<FocusedTextBox>>,
     keyboard: Res<Input<KeyCode>>,
 ) {
@@ -220,10 +230,17 @@ fn send_text(
     }
 
     if let Ok((mut text_box, interface_path)) = focused_text_box.get_single_mut() {
-        net.send_message(messages::InterfaceTextInput {
-            interface_path: interface_path.0.clone(),
-            text: text_box.text.clone(),
-        });
+        if interface_path.0 == CHAT_INPUT_PATH {
+            net.send_message(messages::ChatMessageClient {
+                channel: selected_channel.0,
+                message: text_box.text.clone(),
+            });
+        } else {
+            net.send_message(messages::InterfaceTextInput {
+                interface_path: interface_path.0.clone(),
+                text: text_box.text.clone(),
+            });
+        }
         text_box.text.clear();
     }
 }
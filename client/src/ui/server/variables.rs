@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkClient, NetworkData};
+
+use crate::{assets::Translations, game_state::GameState};
+
+// Variables let the server change what custom HUD elements show without the client knowing what
+// they are. A mana bar is just a bar bound to "mana", the server sets the variable and the bar
+// follows.
+pub struct VariablesPlugin;
+impl Plugin for VariablesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InterfaceVariables>().add_systems(
+            Update,
+            (
+                handle_variable_updates,
+                (update_bound_text, update_bars).run_if(resource_changed::<InterfaceVariables>()),
+            )
+                .chain()
+                .run_if(GameState::in_game),
+        );
+    }
+}
+
+/// The latest value of each variable the server has set.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct InterfaceVariables(HashMap<String, String>);
+
+/// Text that is filled with variables. The text is a translation key, its '{}'s are replaced by
+/// the variables in order.
+#[derive(Component)]
+pub struct BoundText {
+    pub text: String,
+    pub variables: Vec<String>,
+}
+
+/// A bar filled by the value of a variable, relative to its max.
+#[derive(Component)]
+pub struct Bar {
+    pub variable: String,
+    pub max: f32,
+    pub vertical: bool,
+    /// The node that is resized to show the value.
+    pub fill: Entity,
+}
+
+fn handle_variable_updates(
+    mut variables: ResMut<InterfaceVariables>,
+    mut variable_update_events: EventReader<NetworkData<messages::InterfaceVariableUpdate>>,
+) {
+    for variable_update in variable_update_events.read() {
+        for (name, value) in variable_update.updates.iter() {
+            variables.insert(name.clone(), value.clone());
+        }
+    }
+}
+
+fn update_bound_text(
+    variables: Res<InterfaceVariables>,
+    translations: Res<Translations>,
+    bound_text_query: Query<(&BoundText, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (bound_text, children) in bound_text_query.iter() {
+        // Variables the server has not set yet are left empty.
+        let args: Vec<String> = bound_text
+            .variables
+            .iter()
+            .map(|name| variables.get(name).cloned().unwrap_or_default())
+            .collect();
+        let formatted = translations.format(&bound_text.text, &args);
+
+        // Both the text and its shadow.
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != formatted {
+                    text.sections[0].value = formatted.clone();
+                }
+            }
+        }
+    }
+}
+
+fn update_bars(
+    net: Res<NetworkClient>,
+    variables: Res<InterfaceVariables>,
+    bar_query: Query<&Bar>,
+    mut style_query: Query<&mut Style>,
+) {
+    for bar in bar_query.iter() {
+        let value = match variables.get(&bar.variable) {
+            Some(value) => match value.parse::<f32>() {
+                Ok(v) => v,
+                Err(_) => {
+                    net.disconnect(&format!(
+                        "Server set the variable '{}' to '{}', but it is used by a bar and must be a number.",
+                        &bar.variable, value
+                    ));
+                    return;
+                }
+            },
+            None => 0.0,
+        };
+
+        let fraction = if bar.max > 0.0 {
+            (value / bar.max).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        let mut style = style_query.get_mut(bar.fill).unwrap();
+        if bar.vertical {
+            style.height = Val::Percent(fraction * 100.0);
+        } else {
+            style.width = Val::Percent(fraction * 100.0);
+        }
+    }
+}
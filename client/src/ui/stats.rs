@@ -0,0 +1,284 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{diagnostic::DiagnosticsStore, prelude::*, time::common_conditions::on_timer};
+use fmc_networking::{messages, NetworkData, NetworkDiagnostics, ServerLatency};
+
+use crate::game_state::GameState;
+
+use super::DEFAULT_FONT_HANDLE;
+
+// Number of ticks shown in the graph, 1 second at the server's 60 ticks a second.
+const GRAPH_LENGTH: usize = 60;
+// The tick time that fills the whole height of the graph, in milliseconds.
+const GRAPH_MAX_TICK_TIME: f32 = 50.0;
+// The server sleeps 16ms between each tick, ticks that take longer than this lower the tick rate.
+const TICK_BUDGET: f32 = 16.0;
+// The bandwidth graph never scales below this, so a quiet connection doesn't fill it. In bytes per
+// second.
+const GRAPH_MIN_BANDWIDTH: f64 = 1024.0;
+
+/// Performance stats of the server, only operators are sent them. Toggled with F3. The ping to
+/// the server and the bandwidth used by the connection are shown to everyone.
+pub struct StatsPlugin;
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TickHistory(VecDeque::from([0.0; GRAPH_LENGTH])))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    toggle_visibility.run_if(GameState::in_game),
+                    update_stats.run_if(on_event::<NetworkData<messages::ServerStats>>()),
+                    update_ping.run_if(resource_changed::<ServerLatency>()),
+                    // The bandwidth is measured once a second.
+                    update_bandwidth.run_if(on_timer(Duration::from_secs(1))),
+                ),
+            )
+            .add_systems(OnEnter(GameState::MainMenu), hide);
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct TickHistory(VecDeque<f32>);
+
+#[derive(Component)]
+struct StatsPanel;
+
+#[derive(Component)]
+struct StatsText;
+
+#[derive(Component)]
+struct PingText;
+
+#[derive(Component)]
+struct BandwidthText;
+
+// Index of the tick in the history the bar represents.
+#[derive(Component)]
+struct GraphBar(usize);
+
+// Index of the second in the bandwidth history the bar represents.
+#[derive(Component)]
+struct BandwidthBar(usize);
+
+fn setup(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(2.0),
+                    right: Val::Px(2.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(1.0)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            StatsPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "ping: -",
+                    TextStyle {
+                        font: DEFAULT_FONT_HANDLE,
+                        font_size: 5.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                PingText,
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    "in: - out: -",
+                    TextStyle {
+                        font: DEFAULT_FONT_HANDLE,
+                        font_size: 5.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                BandwidthText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        height: Val::Px(10.0),
+                        align_items: AlignItems::FlexEnd,
+                        margin: UiRect::vertical(Val::Px(1.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for i in 0..GRAPH_LENGTH {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(1.0),
+                                    height: Val::Percent(0.0),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(Color::CYAN),
+                                ..default()
+                            },
+                            BandwidthBar(i),
+                        ));
+                    }
+                });
+            parent.spawn((
+                TextBundle::from_section(
+                    "No stats received, they are only sent to operators.",
+                    TextStyle {
+                        font: DEFAULT_FONT_HANDLE,
+                        font_size: 5.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                StatsText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        height: Val::Px(20.0),
+                        align_items: AlignItems::FlexEnd,
+                        margin: UiRect::top(Val::Px(1.0)),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for i in 0..GRAPH_LENGTH {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Px(1.0),
+                                    height: Val::Percent(0.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            GraphBar(i),
+                        ));
+                    }
+                });
+        });
+}
+
+fn toggle_visibility(
+    keys: Res<Input<KeyCode>>,
+    mut panel_query: Query<&mut Visibility, With<StatsPanel>>,
+) {
+    if !keys.just_pressed(KeyCode::F3) {
+        return;
+    }
+
+    let mut visibility = panel_query.single_mut();
+    *visibility = if *visibility == Visibility::Hidden {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+}
+
+fn hide(
+    mut tick_history: ResMut<TickHistory>,
+    mut panel_query: Query<&mut Visibility, With<StatsPanel>>,
+) {
+    *panel_query.single_mut() = Visibility::Hidden;
+    for tick_time in tick_history.iter_mut() {
+        *tick_time = 0.0;
+    }
+}
+
+fn update_stats(
+    mut tick_history: ResMut<TickHistory>,
+    mut stats_events: EventReader<NetworkData<messages::ServerStats>>,
+    mut text_query: Query<&mut Text, With<StatsText>>,
+    mut bar_query: Query<(&mut Style, &mut BackgroundColor, &GraphBar)>,
+) {
+    let Some(stats) = stats_events.read().last() else {
+        return;
+    };
+
+    for tick_time in stats.tick_times.iter() {
+        tick_history.pop_front();
+        tick_history.push_back(*tick_time);
+    }
+
+    let mut text = text_query.single_mut();
+    text.sections[0].value = format!(
+        "mspt: {:.2} (max {:.2})\nchunks: {}\nentities: {}\nplayers: {}\ndb queue: {} (stalls: {})",
+        stats.tick_time,
+        stats.max_tick_time,
+        stats.loaded_chunks,
+        stats.entities,
+        stats.players,
+        stats.persistence_queue,
+        stats.persistence_stalls
+    );
+
+    for (mut style, mut color, bar) in bar_query.iter_mut() {
+        let tick_time = tick_history[bar.0];
+        style.height = Val::Percent((tick_time / GRAPH_MAX_TICK_TIME).min(1.0) * 100.0);
+        color.0 = if tick_time < TICK_BUDGET * 0.5 {
+            Color::GREEN
+        } else if tick_time < TICK_BUDGET {
+            Color::YELLOW
+        } else {
+            Color::RED
+        };
+    }
+}
+
+fn update_ping(
+    server_latency: Res<ServerLatency>,
+    mut text_query: Query<&mut Text, With<PingText>>,
+) {
+    let mut text = text_query.single_mut();
+    text.sections[0].value = if server_latency.round_trip.is_zero() {
+        "ping: -".to_owned()
+    } else {
+        format!("ping: {} ms", server_latency.round_trip.as_millis())
+    };
+}
+
+// The graph shows the bandwidth of the received data over the last minute.
+fn update_bandwidth(
+    diagnostics: Res<DiagnosticsStore>,
+    mut text_query: Query<&mut Text, With<BandwidthText>>,
+    mut bar_query: Query<(&mut Style, &BandwidthBar)>,
+) {
+    let (Some(received), Some(sent)) = (
+        diagnostics.get(NetworkDiagnostics::BYTES_RECEIVED),
+        diagnostics.get(NetworkDiagnostics::BYTES_SENT),
+    ) else {
+        return;
+    };
+
+    let mut text = text_query.single_mut();
+    text.sections[0].value = match (received.value(), sent.value()) {
+        (Some(received), Some(sent)) => format!(
+            "in: {:.1} KiB/s out: {:.1} KiB/s",
+            received / 1024.0,
+            sent / 1024.0
+        ),
+        _ => "in: - out: -".to_owned(),
+    };
+
+    let history: Vec<f64> = received.values().copied().collect();
+    let max = history.iter().copied().fold(GRAPH_MIN_BANDWIDTH, f64::max);
+    // The newest measurement is the last bar, bars without a measurement are left empty.
+    let offset = GRAPH_LENGTH.saturating_sub(history.len());
+    for (mut style, bar) in bar_query.iter_mut() {
+        let bandwidth = bar
+            .0
+            .checked_sub(offset)
+            .and_then(|index| history.get(index))
+            .copied()
+            .unwrap_or(0.0);
+        style.height = Val::Percent((bandwidth / max * 100.0) as f32);
+    }
+}
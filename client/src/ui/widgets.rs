@@ -281,6 +281,19 @@ pub struct TextBox {
     pub text_background_color: Color,
 }
 
+/// Input textboxes with this show a '*' in place of each character, e.g. for passwords.
+#[derive(Component)]
+pub struct MaskedTextBox;
+
+// The text shown in the textbox.
+fn displayed_text(text_box: &TextBox, is_masked: bool) -> String {
+    if is_masked {
+        return "*".repeat(text_box.text.chars().count());
+    } else {
+        return text_box.text.clone();
+    }
+}
+
 #[derive(Component, Default)]
 struct TextInput {
     cursor: usize,
@@ -291,9 +304,9 @@ struct TextMarker;
 
 fn text_input_setup(
     mut commands: Commands,
-    input_query: Query<(Entity, &TextBox, &Style), Added<TextBox>>,
+    input_query: Query<(Entity, &TextBox, &Style, Has<MaskedTextBox>), Added<TextBox>>,
 ) {
-    for (entity, text_box, style) in input_query.iter() {
+    for (entity, text_box, style, is_masked) in input_query.iter() {
         if !text_box.is_input {
             continue;
         }
@@ -303,7 +316,7 @@ fn text_input_setup(
             .insert(TextInput::default())
             .with_children(|parent| {
                 parent.spawn_text(
-                    &text_box.text,
+                    &displayed_text(text_box, is_masked),
                     FONT_SIZE,
                     Color::WHITE,
                     style.flex_direction,
@@ -373,12 +386,12 @@ fn edit_text_box(
 
 fn update_textbox_text(
     mut text_query: Query<&mut Text, With<TextMarker>>,
-    text_box_query: Query<(&TextBox, &Children), Changed<TextBox>>,
+    text_box_query: Query<(&TextBox, &Children, Has<MaskedTextBox>), Changed<TextBox>>,
 ) {
-    for (text_box, children) in text_box_query.iter() {
+    for (text_box, children, is_masked) in text_box_query.iter() {
         for child in children {
             if let Ok(mut text) = text_query.get_mut(*child) {
-                text.sections[0].value = text_box.text.clone();
+                text.sections[0].value = displayed_text(text_box, is_masked);
             }
         }
     }
@@ -1,4 +1,7 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
 use bevy::prelude::*;
 use fmc_networking::{messages, BlockId, NetworkClient};
@@ -15,6 +18,12 @@ const MODEL_PATH: &str = "server_assets/textures/models/";
 
 const BLOCK_CONFIG_PATH: &str = "server_assets/blocks/";
 
+const SOUND_GROUPS_PATH: &str = "server_assets/audio/sound_groups.json";
+
+// Items in this category are tools. Tools that are not suited for breaking a block break it
+// slower than an empty hand.
+const TOOL_CATEGORY: &str = "tool";
+
 const FACE_VERTICES: [[[f32; 3]; 4]; 6] = [
     // Top
     [
@@ -86,6 +95,75 @@ const CROSS_VERTICES: [[[f32; 3]; 4]; 2] = [
 
 const CROSS_NORMALS: [[f32; 3]; 2] = [[1.0, 0.0, -1.0], [-1.0, 0.0, -1.0]];
 
+/// The block configurations of the resource pack. They are read from disk in parallel with the
+/// textures and models, and are kept between connections so they can be reused when connecting to
+/// a server that uses the same assets.
+#[derive(Resource)]
+pub struct BlockConfigs {
+    /// Hash of the assets the configurations were read from.
+    pub assets_hash: Vec<u8>,
+    configs: Vec<(PathBuf, serde_json::Value)>,
+}
+
+impl BlockConfigs {
+    /// Read all block configurations, errors are formatted to be shown to the player.
+    pub fn read(assets_hash: Vec<u8>) -> Result<Self, String> {
+        // Recursively walk block configuration directory
+        fn walk_dir<T: AsRef<std::path::Path>>(
+            dir: T,
+        ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+            let mut files = Vec::new();
+
+            let directory = std::fs::read_dir(dir)?;
+
+            for entry in directory {
+                let file_path = entry?.path();
+
+                if file_path.is_dir() {
+                    let sub_files = walk_dir(&file_path)?;
+                    files.extend(sub_files);
+                } else {
+                    files.push(file_path);
+                }
+            }
+
+            Ok(files)
+        }
+
+        let files = match walk_dir(BLOCK_CONFIG_PATH) {
+            Ok(f) => f,
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read file paths from the block configuration directory.\nError: {}",
+                    e
+                ));
+            }
+        };
+
+        let mut configs = Vec::with_capacity(files.len());
+
+        for file_path in files {
+            let block_config_json = match BlockConfig::read_as_json(&file_path) {
+                Ok(c) => c,
+                Err(e) => {
+                    return Err(format!(
+                        "Misconfigured resource pack, failed to read block config at {}\nError: {}",
+                        file_path.display(),
+                        e
+                    ));
+                }
+            };
+
+            configs.push((file_path, block_config_json));
+        }
+
+        return Ok(Self {
+            assets_hash,
+            configs,
+        });
+    }
+}
+
 // TODO: Idk if it makes sense to have this here. Might makes sense to move the load_blocks
 // function over to the assets, but keep the Blocks struct here, as it is where you would expect to
 // find it.
@@ -93,6 +171,7 @@ pub fn load_blocks(
     asset_server: Res<AssetServer>,
     net: Res<NetworkClient>,
     server_config: Res<messages::ServerConfig>,
+    block_configs: Res<BlockConfigs>,
     block_textures: Res<assets::BlockTextures>,
     material_handles: Res<assets::Materials>,
     materials: Res<Assets<BlockMaterial>>,
@@ -106,58 +185,24 @@ pub fn load_blocks(
         return;
     }
 
-    let mut block_ids = server_config.block_ids.clone();
-    let mut maybe_blocks = Vec::new();
-    maybe_blocks.resize_with(block_ids.len(), Option::default);
-
-    // Recursively walk block configuration directory
-    fn walk_dir<T: AsRef<std::path::Path>>(
-        dir: T,
-    ) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
-        let mut files = Vec::new();
-
-        let directory = std::fs::read_dir(dir)?;
-
-        for entry in directory {
-            let file_path = entry?.path();
-
-            if file_path.is_dir() {
-                let sub_files = walk_dir(&file_path)?;
-                files.extend(sub_files);
-            } else {
-                files.push(file_path);
-            }
-        }
-
-        Ok(files)
-    }
-
-    let files = match walk_dir(BLOCK_CONFIG_PATH) {
-        Ok(f) => f,
+    let sound_groups = match SoundGroupJson::read() {
+        Ok(groups) => groups,
         Err(e) => {
             net.disconnect(&format!(
-                "Failed to read file paths from the block configuration directory.\nError: {}",
-                e
+                "Misconfigured resource pack, failed to read the sound groups at {}\nError: {}",
+                SOUND_GROUPS_PATH, e
             ));
             return;
         }
     };
 
-    for file_path in files {
-        let block_config_json = match BlockConfig::read_as_json(&file_path) {
-            Ok(c) => c,
-            Err(e) => {
-                net.disconnect(&format!(
-                    "Misconfigured resource pack, failed to read block config at {}\nError: {}",
-                    file_path.display(),
-                    e
-                ));
-                return;
-            }
-        };
+    let mut block_ids = server_config.block_ids.clone();
+    let mut maybe_blocks = Vec::new();
+    maybe_blocks.resize_with(block_ids.len(), Option::default);
 
+    for (file_path, block_config_json) in block_configs.configs.iter() {
         let block_config = if block_config_json.get("name").is_some() {
-            match serde_json::from_value(block_config_json) {
+            match serde_json::from_value(block_config_json.clone()) {
                 Ok(result) => result,
                 Err(e) => {
                     net.disconnect(&format!(
@@ -185,8 +230,17 @@ pub fn load_blocks(
                 is_rotatable,
                 light_attenuation,
                 fog,
-                sound,
+                sound_group,
+                tools,
             } => {
+                let sound = match step_sounds(&sound_groups, &name, sound_group) {
+                    Ok(sound) => sound,
+                    Err(e) => {
+                        net.disconnect(&e);
+                        return;
+                    }
+                };
+
                 let material_handle = if let Some(m) = material_handles.get(&material) {
                     m.clone().typed()
                 } else {
@@ -366,6 +420,7 @@ pub fn load_blocks(
                     light_attenuation: light_attenuation.unwrap_or(15).min(15),
                     fog_settings,
                     sound,
+                    tools,
                 })
             }
 
@@ -375,8 +430,17 @@ pub fn load_blocks(
                 side_model,
                 friction,
                 interactable,
-                sound,
+                sound_group,
+                tools,
             } => {
+                let sound = match step_sounds(&sound_groups, &name, sound_group) {
+                    Ok(sound) => sound,
+                    Err(e) => {
+                        net.disconnect(&e);
+                        return;
+                    }
+                };
+
                 let center_model = if let Some(center_model) = center_model {
                     let path = MODEL_PATH.to_owned() + &center_model.name + ".glb#Scene0";
                     Some((
@@ -421,6 +485,7 @@ pub fn load_blocks(
                     friction,
                     interactable,
                     sound,
+                    tools,
                 })
             }
         };
@@ -518,6 +583,8 @@ pub struct Cube {
     pub fog_settings: Option<FogSettings>,
     // Sounds played when walked on or in (random pick)
     sound: Vec<String>,
+    // Kinds of tools suited for breaking the block.
+    tools: HashSet<String>,
 }
 
 // TODO: This was made before the Models collection was made. This could hold model ids instead of
@@ -545,6 +612,8 @@ pub struct BlockModel {
     interactable: bool,
     // Sounds played when walked on or in (random pick)
     sound: Vec<String>,
+    // Kinds of tools suited for breaking the block.
+    tools: HashSet<String>,
 }
 
 #[derive(Debug)]
@@ -632,6 +701,18 @@ impl Block {
         }
     }
 
+    /// If an item with the categories is a tool that is not suited for breaking the block.
+    pub fn is_wrong_tool(&self, item_categories: Option<&HashSet<String>>) -> bool {
+        let tools = match self {
+            Block::Cube(c) => &c.tools,
+            Block::Model(m) => &m.tools,
+        };
+
+        return item_categories.is_some_and(|categories| {
+            !tools.is_empty() && categories.contains(TOOL_CATEGORY) && tools.is_disjoint(categories)
+        });
+    }
+
     pub fn walking_sound(&self) -> Option<&String> {
         // Random index, don't know if correct
         let index = std::time::SystemTime::now()
@@ -718,9 +799,11 @@ enum BlockConfig {
         light_attenuation: Option<u8>,
         /// If fog should be rendered when the player camera is inside the block.
         fog: Option<FogJson>,
-        /// Sounds played when walking on/in block
+        /// The group of sounds the block makes, e.g. "stone".
+        sound_group: Option<String>,
+        /// Kinds of tools suited for breaking the block, e.g. "axe".
         #[serde(default)]
-        sound: Vec<String>,
+        tools: HashSet<String>,
     },
     Model {
         /// Name of the block, must be unique
@@ -734,12 +817,50 @@ enum BlockConfig {
         /// If the block is interactable
         #[serde(default)]
         interactable: bool,
-        /// Sounds played when walking on/in block
+        /// The group of sounds the block makes, e.g. "stone".
+        sound_group: Option<String>,
+        /// Kinds of tools suited for breaking the block, e.g. "axe".
         #[serde(default)]
-        sound: Vec<String>,
+        tools: HashSet<String>,
     },
 }
 
+// Only the step sounds are played by the client, the rest of the group is played by the server.
+#[derive(Deserialize)]
+struct SoundGroupJson {
+    #[serde(default)]
+    step: Vec<String>,
+}
+
+impl SoundGroupJson {
+    fn read() -> Result<HashMap<String, Self>, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(SOUND_GROUPS_PATH)?;
+        return Ok(serde_json::from_reader(file)?);
+    }
+}
+
+// The sounds played when walking on/in a block.
+fn step_sounds(
+    sound_groups: &HashMap<String, SoundGroupJson>,
+    block_name: &str,
+    sound_group: Option<String>,
+) -> Result<Vec<String>, String> {
+    let Some(sound_group) = sound_group else {
+        return Ok(Vec::new());
+    };
+
+    match sound_groups.get(&sound_group) {
+        Some(group) => return Ok(group.step.clone()),
+        None => {
+            return Err(format!(
+                "Misconfigured resource pack, the block '{}' uses the sound group '{}', but \
+                there is no sound group by that name in {}",
+                block_name, sound_group, SOUND_GROUPS_PATH
+            ))
+        }
+    }
+}
+
 impl BlockConfig {
     fn read_as_json(
         path: &std::path::Path,
@@ -7,7 +7,7 @@ use fmc_networking::BlockId;
 
 use crate::constants::*;
 use crate::utils;
-use crate::world::blocks::BlockState;
+use crate::world::blocks::{BlockState, Blocks};
 
 #[derive(Component)]
 pub struct ChunkMarker;
@@ -19,6 +19,8 @@ pub struct ChunkMarker;
 /// Chunks with blocks:
 ///     entity = Some
 ///     blocks = Vec::with_capacity(CHUNK_SIZE^3)
+/// Chunks with blocks that are far away from the player are kept compressed, see
+/// [Chunk::compress].
 #[derive(Clone)]
 pub struct Chunk {
     // Entity in the ECS. Stores mesh, None if the chunk shouldn't have one.
@@ -27,9 +29,30 @@ pub struct Chunk {
     /// out of the screen. 0,0,0 is the bottom left FAR corner. Not bottom left NEAR.
     /// A CHUNK_SIZE^3 array containing all the blocks in the chunk.
     /// Indexed by x*CHUNK_SIZE^2 + z*CHUNK_SIZE + y
+    /// Empty while the chunk is compressed.
     blocks: Vec<BlockId>,
+    /// Run length encoded blocks, used instead of 'blocks' for chunks that are too far away to be
+    /// interacted with.
+    compressed: Option<CompressedBlocks>,
     /// Optional block state
     block_state: HashMap<usize, BlockState>,
+    /// The biome of each block column, indexed by x*CHUNK_SIZE + z
+    pub biomes: Vec<u8>,
+}
+
+#[derive(Clone)]
+struct CompressedBlocks {
+    // Block index one past the end of each run, ascending.
+    run_ends: Vec<u16>,
+    // The block each run consists of.
+    blocks: Vec<BlockId>,
+}
+
+impl CompressedBlocks {
+    fn get(&self, index: usize) -> &BlockId {
+        let run = self.run_ends.partition_point(|&end| end as usize <= index);
+        return &self.blocks[run];
+    }
 }
 
 impl Chunk {
@@ -38,22 +61,31 @@ impl Chunk {
         entity: Entity,
         blocks: Vec<BlockId>,
         block_state: HashMap<usize, BlockState>,
+        biomes: Vec<u8>,
     ) -> Self {
         return Self {
             entity: Some(entity),
             blocks,
+            compressed: None,
             block_state,
+            biomes,
         };
     }
 
     /// Create a new chunk of only air blocks; to be filled after creation.
-    pub fn new_air(blocks: Vec<BlockId>, block_state: HashMap<usize, BlockState>) -> Self {
+    pub fn new_air(
+        blocks: Vec<BlockId>,
+        block_state: HashMap<usize, BlockState>,
+        biomes: Vec<u8>,
+    ) -> Self {
         assert!(blocks.len() == 1);
 
         return Self {
             entity: None,
             block_state,
             blocks,
+            compressed: None,
+            biomes,
         };
     }
 
@@ -69,6 +101,75 @@ impl Chunk {
         return self.blocks.len() == 1;
     }
 
+    pub fn is_compressed(&self) -> bool {
+        return self.compressed.is_some();
+    }
+
+    /// Run length encode the blocks to save memory. The chunk can still be indexed, but lookups
+    /// are slower, and it will be decompressed if it is modified. Chunks that don't compress well
+    /// are left as is.
+    pub fn compress(&mut self) {
+        if self.is_uniform() || self.is_compressed() {
+            return;
+        }
+
+        let mut run_ends = Vec::new();
+        let mut blocks = Vec::new();
+        for (index, block_id) in self.blocks.iter().enumerate() {
+            if blocks.last() == Some(block_id) {
+                *run_ends.last_mut().unwrap() = index as u16 + 1;
+            } else {
+                run_ends.push(index as u16 + 1);
+                blocks.push(*block_id);
+            }
+        }
+
+        // Each run takes twice the space of a block.
+        if blocks.len() * 2 >= self.blocks.len() {
+            return;
+        }
+
+        run_ends.shrink_to_fit();
+        blocks.shrink_to_fit();
+        self.compressed = Some(CompressedBlocks { run_ends, blocks });
+        self.blocks = Vec::new();
+    }
+
+    /// Restore the blocks of a compressed chunk to the fast indexable form.
+    pub fn decompress(&mut self) {
+        let Some(compressed) = self.compressed.take() else {
+            return;
+        };
+
+        let mut blocks = Vec::with_capacity(CHUNK_SIZE.pow(3));
+        let mut start = 0;
+        for (end, block_id) in compressed.run_ends.iter().zip(compressed.blocks.iter()) {
+            blocks.extend(std::iter::repeat(*block_id).take(*end as usize - start));
+            start = *end as usize;
+        }
+        self.blocks = blocks;
+    }
+
+    fn get(&self, index: usize) -> &BlockId {
+        if self.is_uniform() {
+            return &self.blocks[0];
+        } else if let Some(compressed) = &self.compressed {
+            return compressed.get(index);
+        } else {
+            return &self.blocks[index];
+        }
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut BlockId {
+        self.decompress();
+
+        if self.is_uniform() {
+            return &mut self.blocks[0];
+        } else {
+            return &mut self.blocks[index];
+        }
+    }
+
     pub fn set_block_state(&mut self, block_index: usize, state: BlockState) {
         self.block_state.insert(block_index, state);
     }
@@ -77,6 +178,35 @@ impl Chunk {
         self.block_state.remove(&block_index);
     }
 
+    /// Check if any of the blocks along the face of the chunk can be seen through.
+    pub fn is_face_transparent(&self, face: ChunkFace) -> bool {
+        let blocks = Blocks::get();
+
+        if self.is_uniform() {
+            return blocks.get_config(self.blocks[0]).is_transparent();
+        }
+
+        const LAST: usize = CHUNK_SIZE - 1;
+        for i in 0..CHUNK_SIZE {
+            for j in 0..CHUNK_SIZE {
+                let index = match face {
+                    ChunkFace::Top => [i, LAST, j],
+                    ChunkFace::Bottom => [i, 0, j],
+                    ChunkFace::Right => [LAST, i, j],
+                    ChunkFace::Left => [0, i, j],
+                    ChunkFace::Front => [i, j, LAST],
+                    ChunkFace::Back => [i, j, 0],
+                    ChunkFace::None => return false,
+                };
+                if blocks.get_config(self[index]).is_transparent() {
+                    return true;
+                }
+            }
+        }
+
+        return false;
+    }
+
     pub fn get_block_state(&self, x: usize, y: usize, z: usize) -> Option<BlockState> {
         let index = x << 8 | z << 4 | y;
         return self.block_state.get(&index).copied();
@@ -87,21 +217,13 @@ impl Index<usize> for Chunk {
     type Output = BlockId;
 
     fn index(&self, idx: usize) -> &Self::Output {
-        if self.is_uniform() {
-            return &self.blocks[0];
-        } else {
-            return &self.blocks[idx];
-        }
+        return self.get(idx);
     }
 }
 
 impl IndexMut<usize> for Chunk {
     fn index_mut(&mut self, idx: usize) -> &mut Self::Output {
-        if self.is_uniform() {
-            return &mut self.blocks[0];
-        } else {
-            return &mut self.blocks[idx];
-        }
+        return self.get_mut(idx);
     }
 }
 
@@ -109,21 +231,13 @@ impl Index<[usize; 3]> for Chunk {
     type Output = BlockId;
 
     fn index(&self, idx: [usize; 3]) -> &Self::Output {
-        if self.is_uniform() {
-            return &self.blocks[0];
-        } else {
-            return &self.blocks[idx[0] * CHUNK_SIZE.pow(2) + idx[2] * CHUNK_SIZE + idx[1]];
-        }
+        return self.get(idx[0] * CHUNK_SIZE.pow(2) + idx[2] * CHUNK_SIZE + idx[1]);
     }
 }
 
 impl IndexMut<[usize; 3]> for Chunk {
     fn index_mut(&mut self, idx: [usize; 3]) -> &mut Self::Output {
-        if self.is_uniform() {
-            return &mut self.blocks[0];
-        } else {
-            return &mut self.blocks[idx[0] * CHUNK_SIZE.pow(2) + idx[2] * CHUNK_SIZE + idx[1]];
-        }
+        return self.get_mut(idx[0] * CHUNK_SIZE.pow(2) + idx[2] * CHUNK_SIZE + idx[1]);
     }
 }
 
@@ -131,23 +245,13 @@ impl Index<IVec3> for Chunk {
     type Output = BlockId;
 
     fn index(&self, idx: IVec3) -> &Self::Output {
-        if self.is_uniform() {
-            return &self.blocks[0];
-        } else {
-            let idx = utils::world_position_to_block_index(idx);
-            return &self.blocks[idx];
-        }
+        return self.get(utils::world_position_to_block_index(idx));
     }
 }
 
 impl IndexMut<IVec3> for Chunk {
     fn index_mut(&mut self, idx: IVec3) -> &mut Self::Output {
-        if self.is_uniform() {
-            return &mut self.blocks[0];
-        } else {
-            let idx = utils::world_position_to_block_index(idx);
-            return &mut self.blocks[idx];
-        }
+        return self.get_mut(utils::world_position_to_block_index(idx));
     }
 }
 
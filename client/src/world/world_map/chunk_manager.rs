@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use fmc_networking::{messages, NetworkClient, NetworkData};
 
@@ -24,6 +27,7 @@ pub struct ChunkManagerPlugin;
 impl Plugin for ChunkManagerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Pause>()
+            .init_resource::<MissingChunks>()
             .add_event::<NewChunkEvent>()
             .add_systems(
                 Update,
@@ -34,6 +38,10 @@ impl Plugin for ChunkManagerPlugin {
                         .after(handle_new_chunks)
                         .in_set(RenderSet::UpdateBlocks),
                     pause_system,
+                    request_missing_chunks,
+                    compress_distant_chunks
+                        .after(handle_new_chunks)
+                        .run_if(resource_changed::<Origin>()),
                 )
                     .run_if(GameState::in_game),
             )
@@ -100,6 +108,109 @@ fn unload_chunks(
     });
 }
 
+// Chunks further away than this, measured in chunks, are compressed. They are decompressed again
+// when they come closer than the distance minus one, so that walking back and forth over a chunk
+// border doesn't cause them to be compressed over and over.
+const COMPRESSION_DISTANCE: i32 = 4;
+
+fn chunk_distance(origin: IVec3, chunk_position: IVec3) -> i32 {
+    return ((chunk_position - origin).abs() / IVec3::splat(CHUNK_SIZE as i32)).max_element();
+}
+
+// Distant chunks are only used for meshing when their neighbours change, and are kept compressed
+// to save memory at large render distances. Chunks that come close enough to be collided with or
+// edited are decompressed so block lookups stay fast.
+fn compress_distant_chunks(origin: Res<Origin>, mut world_map: ResMut<WorldMap>) {
+    for (chunk_position, chunk) in world_map.chunks.iter_mut() {
+        let distance = chunk_distance(origin.0, *chunk_position);
+        if distance > COMPRESSION_DISTANCE {
+            chunk.compress();
+        } else if distance < COMPRESSION_DISTANCE {
+            chunk.decompress();
+        }
+    }
+}
+
+// How often the client looks for chunks it should have received.
+const MISSING_CHUNK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// How long a chunk can be missing before it is requested. The server sends the chunks as they are
+// generated, so it needs some slack before a chunk is considered lost.
+const MISSING_CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Chunks that are expected from the server but haven't arrived, along with when they were first
+/// noticed missing or last requested.
+#[derive(Resource, Default)]
+struct MissingChunks(HashMap<IVec3, Instant>);
+
+// Chunks can be lost if the connection hiccups, leaving permanent holes in the world. A chunk is
+// expected if it is within the render distance and can be seen through a face of a chunk that has
+// been received. If it stays missing for too long it is requested from the server.
+fn request_missing_chunks(
+    net: Res<NetworkClient>,
+    origin: Res<Origin>,
+    settings: Res<settings::Settings>,
+    world_map: Res<WorldMap>,
+    mut missing_chunks: ResMut<MissingChunks>,
+    mut last_check: Local<Option<Instant>>,
+) {
+    if last_check.is_some_and(|last| last.elapsed() < MISSING_CHUNK_CHECK_INTERVAL) {
+        return;
+    }
+    *last_check = Some(Instant::now());
+
+    let is_within_render_distance = |chunk_position: IVec3| {
+        let distance = (chunk_position - origin.0).abs() / IVec3::splat(CHUNK_SIZE as i32);
+        !distance
+            .cmpgt(IVec3::splat(settings.render_distance as i32))
+            .any()
+    };
+
+    let mut expected = HashSet::new();
+    for (chunk_position, chunk) in world_map.chunks.iter() {
+        if !is_within_render_distance(*chunk_position) {
+            continue;
+        }
+
+        for face in [
+            ChunkFace::Top,
+            ChunkFace::Bottom,
+            ChunkFace::Right,
+            ChunkFace::Left,
+            ChunkFace::Front,
+            ChunkFace::Back,
+        ] {
+            let neighbour_position = face.shift_position(*chunk_position);
+            if !world_map.chunks.contains_key(&neighbour_position)
+                && is_within_render_distance(neighbour_position)
+                && chunk.is_face_transparent(face)
+            {
+                expected.insert(neighbour_position);
+            }
+        }
+    }
+
+    // Forget the chunks that have arrived or are no longer needed.
+    missing_chunks
+        .0
+        .retain(|chunk_position, _| expected.contains(chunk_position));
+
+    let now = Instant::now();
+    let mut request = Vec::new();
+
+    for chunk_position in expected {
+        let noticed = missing_chunks.0.entry(chunk_position).or_insert(now);
+        if noticed.elapsed() >= MISSING_CHUNK_TIMEOUT {
+            // Reset so it is requested again if this one gets lost too.
+            *noticed = now;
+            request.push(chunk_position);
+        }
+    }
+
+    if !request.is_empty() {
+        net.send_message(messages::ChunkRequest { positions: request });
+    }
+}
+
 // The frustum chunk loading system needs some help. This loads the 3x3x3 chunks that are closest.
 // This is for when the player walks into a chunk without looking at it first. The player might
 // also collide with these without having looked at them (or collide with a chunk that isn't
@@ -304,6 +415,16 @@ fn handle_new_chunks(
             }
         }
 
+        if chunk.biomes.len() != CHUNK_SIZE.pow(2) {
+            net.disconnect(format!(
+                "Server sent chunk with {} biomes, there should be one for each of the {} block \
+                columns",
+                chunk.biomes.len(),
+                CHUNK_SIZE.pow(2)
+            ));
+            return;
+        }
+
         new_chunk_events.send(NewChunkEvent {
             position: chunk.position,
         });
@@ -325,6 +446,7 @@ fn handle_new_chunks(
                         .iter()
                         .map(|(&k, &v)| (k, BlockState(v)))
                         .collect(),
+                    chunk.biomes.clone(),
                 ),
             );
         } else {
@@ -338,18 +460,22 @@ fn handle_new_chunks(
                 .insert(ChunkMarker)
                 .id();
 
-            world_map.insert(
-                chunk.position,
-                Chunk::new(
-                    entity,
-                    chunk.blocks.clone(),
-                    chunk
-                        .block_state
-                        .iter()
-                        .map(|(&k, &v)| (k, BlockState(v)))
-                        .collect(),
-                ),
+            let mut new_chunk = Chunk::new(
+                entity,
+                chunk.blocks.clone(),
+                chunk
+                    .block_state
+                    .iter()
+                    .map(|(&k, &v)| (k, BlockState(v)))
+                    .collect(),
+                chunk.biomes.clone(),
             );
+
+            if chunk_distance(origin.0, chunk.position) > COMPRESSION_DISTANCE {
+                new_chunk.compress();
+            }
+
+            world_map.insert(chunk.position, new_chunk);
         }
     }
 }
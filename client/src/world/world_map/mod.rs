@@ -110,6 +110,15 @@ impl WorldMap {
         }
     }
 
+    /// The id of the biome at the position, if its chunk is loaded. See [Biomes](crate::assets::Biomes)
+    /// for what it is.
+    pub fn biome_at(&self, position: &IVec3) -> Option<u8> {
+        let chunk_position = utils::world_position_to_chunk_pos(*position);
+        let chunk = self.get_chunk(&chunk_position)?;
+        let column = (*position - chunk_position) * IVec3::new(CHUNK_SIZE as i32, 0, 1);
+        return Some(chunk.biomes[(column.x + column.z) as usize]);
+    }
+
     /// Find which block the transform is looking at, if any.
     pub fn raycast_to_block(
         &self,
@@ -17,8 +17,14 @@ pub fn derive_network_message(input: TokenStream) -> TokenStream {
     let (impl_generics, type_generics, where_clause) = &ast.generics.split_for_impl();
 
     TokenStream::from(quote! {
-        #[typetag::serde]
         impl #impl_generics crate::network_message::NetworkMessage for #struct_name #type_generics #where_clause {
+            fn serialized_size(&self) -> bincode::Result<u64> {
+                bincode::serialized_size(self)
+            }
+
+            fn serialize_into(&self, buffer: &mut [u8]) -> bincode::Result<()> {
+                bincode::serialize_into(buffer, self)
+            }
         }
     })
 }
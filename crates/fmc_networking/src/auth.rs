@@ -0,0 +1,253 @@
+use std::{
+    num::NonZeroU32,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::messages::Credentials;
+
+// How long a session token can be used to log in after it was issued.
+const SESSION_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: usize = 32;
+/// The longest name a player can log in with, in bytes.
+pub const MAX_USERNAME_LENGTH: usize = 32;
+pub(crate) const MAX_PASSWORD_LENGTH: usize = 128;
+// Session tokens are random bytes written as hex.
+pub(crate) const SESSION_TOKEN_LENGTH: usize = 64;
+
+/// An account as it is kept by the [AccountStorage].
+#[derive(Debug, Clone)]
+pub struct Account {
+    /// The id of the player, it never changes, even if the player changes their name.
+    pub id: String,
+    /// None if the account has not been claimed yet, e.g. if it was created for a player that
+    /// played before accounts were required. The first password used to log in to it is kept.
+    pub password_hash: Option<Vec<u8>>,
+}
+
+/// Where the server keeps the accounts of its players. Only the password hashes are stored, the
+/// hashing is done by the network server.
+///
+/// The functions are called from the network threads, and may block.
+pub trait AccountStorage: Send + Sync + 'static {
+    /// The account with the name, None if there is no account with the name.
+    fn account(&self, username: &str) -> Result<Option<Account>, String>;
+    /// Store a new account.
+    fn create_account(
+        &self,
+        id: &str,
+        username: &str,
+        password_hash: Vec<u8>,
+    ) -> Result<(), String>;
+    /// Set the password hash of an account that has not been claimed. Returns false if it had
+    /// already been claimed, the hash is then left as it was.
+    fn set_password_hash(&self, id: &str, password_hash: Vec<u8>) -> Result<bool, String>;
+}
+
+/// A new random player id, formatted as a version 4 UUID.
+pub fn new_player_id() -> Result<String, &'static str> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| "The server could not create a player id")?;
+    // Version 4, variant 1
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    return Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ));
+}
+
+/// A client that has been authenticated.
+pub(crate) struct Login {
+    pub player_id: String,
+    pub session_token: String,
+}
+
+struct Session {
+    player_id: String,
+    username: String,
+    expires: Instant,
+}
+
+/// Verifies the credentials clients identify themselves with.
+///
+/// Logging in with a password gives the client a session token it can use instead of the password
+/// the next time it connects. If there is no account with the name, one is created with the
+/// password.
+pub(crate) struct Authenticator {
+    storage: Box<dyn AccountStorage>,
+    sessions: DashMap<String, Session>,
+    rng: SystemRandom,
+}
+
+impl Authenticator {
+    pub(crate) fn new(storage: impl AccountStorage) -> Arc<Self> {
+        return Arc::new(Self {
+            storage: Box::new(storage),
+            sessions: DashMap::new(),
+            rng: SystemRandom::new(),
+        });
+    }
+
+    /// Returns the player's id and a new session token if the credentials are valid, or the
+    /// reason they were refused.
+    pub(crate) fn authenticate(
+        &self,
+        username: &str,
+        credentials: &Credentials,
+    ) -> Result<Login, &'static str> {
+        if username.is_empty() || username.len() > MAX_USERNAME_LENGTH {
+            return Err("Usernames must be between 1 and 32 characters long");
+        }
+
+        let player_id = match credentials {
+            Credentials::Password(password) => {
+                if password.len() > MAX_PASSWORD_LENGTH {
+                    return Err("Passwords can be at most 128 characters long");
+                }
+
+                let account = self
+                    .storage
+                    .account(username)
+                    .map_err(|_| "The server could not verify the account")?;
+
+                match account {
+                    Some(Account {
+                        id,
+                        password_hash: Some(password_hash),
+                    }) => {
+                        if !verify_password(password, &password_hash) {
+                            return Err("Wrong password");
+                        }
+                        id
+                    }
+                    Some(Account {
+                        id,
+                        password_hash: None,
+                    }) => {
+                        let password_hash = self.hash_password(password)?;
+                        let claimed = self
+                            .storage
+                            .set_password_hash(&id, password_hash)
+                            .map_err(|_| "The server could not create the account")?;
+
+                        // Another login claimed it first, the password must match theirs.
+                        if !claimed {
+                            let stored_hash = self
+                                .storage
+                                .account(username)
+                                .map_err(|_| "The server could not verify the account")?
+                                .filter(|account| account.id == id)
+                                .and_then(|account| account.password_hash);
+                            if !stored_hash.is_some_and(|hash| verify_password(password, &hash)) {
+                                return Err("Wrong password");
+                            }
+                        }
+
+                        id
+                    }
+                    None => {
+                        let id = new_player_id()?;
+                        let password_hash = self.hash_password(password)?;
+                        self.storage
+                            .create_account(&id, username, password_hash)
+                            .map_err(|_| "The server could not create the account")?;
+                        id
+                    }
+                }
+            }
+            Credentials::SessionToken(token) => {
+                // Tokens can only be used once, a new one is issued each time.
+                match self.sessions.remove(token) {
+                    Some((_, session))
+                        if session.username == username && session.expires > Instant::now() =>
+                    {
+                        session.player_id
+                    }
+                    _ => return Err("The session has expired, log in again"),
+                }
+            }
+        };
+
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires > now);
+
+        let token = self.new_token()?;
+        self.sessions.insert(
+            token.clone(),
+            Session {
+                player_id: player_id.clone(),
+                username: username.to_owned(),
+                expires: now + SESSION_DURATION,
+            },
+        );
+
+        return Ok(Login {
+            player_id,
+            session_token: token,
+        });
+    }
+
+    fn hash_password(&self, password: &str) -> Result<Vec<u8>, &'static str> {
+        let mut password_hash = vec![0; SALT_LENGTH + HASH_LENGTH];
+        let (salt, hash) = password_hash.split_at_mut(SALT_LENGTH);
+        self.rng
+            .fill(salt)
+            .map_err(|_| "The server could not create the account")?;
+
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations(),
+            salt,
+            password.as_bytes(),
+            hash,
+        );
+
+        return Ok(password_hash);
+    }
+
+    fn new_token(&self) -> Result<String, &'static str> {
+        let mut bytes = [0u8; SESSION_TOKEN_LENGTH / 2];
+        self.rng
+            .fill(&mut bytes)
+            .map_err(|_| "The server could not create a session")?;
+        return Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect());
+    }
+}
+
+fn iterations() -> NonZeroU32 {
+    return NonZeroU32::new(PBKDF2_ITERATIONS).unwrap_or(NonZeroU32::MIN);
+}
+
+// The password hash is the salt followed by the derived key.
+fn verify_password(password: &str, password_hash: &[u8]) -> bool {
+    if password_hash.len() != SALT_LENGTH + HASH_LENGTH {
+        return false;
+    }
+
+    let (salt, hash) = password_hash.split_at(SALT_LENGTH);
+    return pbkdf2::verify(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations(),
+        salt,
+        password.as_bytes(),
+        hash,
+    )
+    .is_ok();
+}
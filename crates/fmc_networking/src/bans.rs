@@ -0,0 +1,76 @@
+use std::net::IpAddr;
+
+use dashmap::DashMap;
+
+/// Who a ban applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Ban {
+    /// The player with this id, whatever address they connect from.
+    Player(String),
+    /// Anyone connecting from this address.
+    Address(IpAddr),
+}
+
+/// Where the server keeps its bans so they persist through restarts.
+///
+/// The functions are called from the thread that changes the bans, and may block.
+pub trait BanStorage: Send + Sync + 'static {
+    /// All the bans, with the reason they were given.
+    fn bans(&self) -> Result<Vec<(Ban, String)>, String>;
+    /// Store a ban, it replaces the reason if it already exists.
+    fn add_ban(&self, ban: &Ban, reason: &str) -> Result<(), String>;
+    /// Remove a ban, it is not an error if it does not exist.
+    fn remove_ban(&self, ban: &Ban) -> Result<(), String>;
+}
+
+/// The bans checked when clients connect. They are kept in memory, and written through to the
+/// [BanStorage] if there is one.
+#[derive(Default)]
+pub(crate) struct BanList {
+    storage: Option<Box<dyn BanStorage>>,
+    bans: DashMap<Ban, String>,
+}
+
+impl BanList {
+    /// Replaces the bans with the ones in the storage.
+    pub(crate) fn set_storage(&mut self, storage: impl BanStorage) -> Result<(), String> {
+        let bans = storage.bans()?;
+        self.bans.clear();
+        self.bans.extend(bans);
+        self.storage = Some(Box::new(storage));
+        return Ok(());
+    }
+
+    pub(crate) fn add(&self, ban: Ban, reason: &str) -> Result<(), String> {
+        if let Some(storage) = &self.storage {
+            storage.add_ban(&ban, reason)?;
+        }
+        self.bans.insert(ban, reason.to_owned());
+        return Ok(());
+    }
+
+    /// Returns false if there was no such ban.
+    pub(crate) fn remove(&self, ban: &Ban) -> Result<bool, String> {
+        if let Some(storage) = &self.storage {
+            storage.remove_ban(ban)?;
+        }
+        return Ok(self.bans.remove(ban).is_some());
+    }
+
+    pub(crate) fn list(&self) -> Vec<(Ban, String)> {
+        return self
+            .bans
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+    }
+
+    /// The reason the player or address is banned, None if neither is.
+    pub(crate) fn reason(&self, player_id: &str, ip: IpAddr) -> Option<String> {
+        return self
+            .bans
+            .get(&Ban::Player(player_id.to_owned()))
+            .or_else(|| self.bans.get(&Ban::Address(ip)))
+            .map(|reason| reason.clone());
+    }
+}
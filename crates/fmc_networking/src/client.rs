@@ -1,6 +1,10 @@
 use std::{
     net::{IpAddr, Ipv4Addr, SocketAddr},
-    sync::Arc,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use bevy::prelude::*;
@@ -15,11 +19,25 @@ use tokio::{
 };
 
 use crate::{
+    compress_packet, decompress_packet,
+    diagnostics::NetworkDiagnostics,
     error::ClientNetworkError,
-    network_message::{ClientBound, NetworkMessage, ServerBound},
-    ClientNetworkEvent, ConnectionId, NetworkData, NetworkPacket, NetworkSettings, SyncChannel,
+    lan::{self, LanServer},
+    messages,
+    network_message::{self, ClientBound, DeserializeFn, MessageId, NetworkMessage, ServerBound},
+    read_exact_with_timeout,
+    recording::{Recorder, Replay},
+    tls,
+    transport::{self, BoxedSocket, Transport},
+    with_timeout, ClientNetworkEvent, ConnectionId, NetworkData, NetworkPacket, NetworkSettings,
+    ServerStatusEvent, SyncChannel, COMPRESSED_FLAG, LENGTH_PREFIX_SIZE,
 };
 
+// How long a server has to answer a status request.
+const STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+// The status is small, anything larger than this is not a status response.
+const MAX_STATUS_LENGTH: usize = 64 * 1024;
+
 #[derive(Display)]
 #[display(fmt = "Server connection to {}", peer_addr)]
 struct ServerConnection {
@@ -27,6 +45,8 @@ struct ServerConnection {
     receive_task: JoinHandle<()>,
     send_task: JoinHandle<()>,
     send_message: UnboundedSender<NetworkPacket>,
+    /// If packets sent to the server should be compressed.
+    compression: Arc<AtomicBool>,
 }
 
 impl ServerConnection {
@@ -42,9 +62,14 @@ impl ServerConnection {
 pub struct NetworkClient {
     runtime: Runtime,
     server_connection: Option<ServerConnection>,
-    recv_message_map: Arc<DashMap<&'static str, Vec<Box<dyn NetworkMessage>>>>,
+    recv_message_map: Arc<DashMap<u16, Vec<Box<dyn NetworkMessage>>>>,
+    message_deserializers: Arc<DashMap<u16, DeserializeFn>>,
     network_events: SyncChannel<ClientNetworkEvent>,
-    connection_events: SyncChannel<(TcpStream, SocketAddr)>,
+    connection_events: SyncChannel<(BoxedSocket, SocketAddr, Option<Recorder>)>,
+    status_events: SyncChannel<ServerStatusEvent>,
+    replay_task: Option<JoinHandle<()>>,
+    pub(crate) lan_servers: SyncChannel<LanServer>,
+    lan_discovery_task: Option<JoinHandle<()>>,
 }
 
 impl std::fmt::Debug for NetworkClient {
@@ -68,8 +93,13 @@ impl NetworkClient {
                 .expect("Could not build tokio runtime"),
             server_connection: None,
             recv_message_map: Arc::new(DashMap::new()),
+            message_deserializers: Arc::new(DashMap::new()),
             network_events: SyncChannel::new(),
             connection_events: SyncChannel::new(),
+            status_events: SyncChannel::new(),
+            replay_task: None,
+            lan_servers: SyncChannel::new(),
+            lan_discovery_task: None,
         }
     }
 
@@ -77,6 +107,7 @@ impl NetworkClient {
     pub fn connect(
         &mut self,
         addr: impl ToSocketAddrs + Send + 'static,
+        network_settings: &NetworkSettings,
     ) {
         debug!("Starting connection");
 
@@ -87,6 +118,34 @@ impl NetworkClient {
         let network_events_sender = self.network_events.sender.clone();
         let connection_event_sender = self.connection_events.sender.clone();
 
+        let tls_connector = match network_settings.tls.as_ref().map(tls::connector) {
+            Some(Ok(connector)) => Some(connector),
+            Some(Err(err)) => {
+                self.network_events
+                    .sender
+                    .send(ClientNetworkEvent::Error(ClientNetworkError::Tls(err)))
+                    .ok();
+                return;
+            }
+            None => None,
+        };
+        let transport = network_settings.transport;
+
+        let recorder = match &network_settings.record_path {
+            Some(path) => match Recorder::create(path) {
+                Ok(recorder) => Some(recorder),
+                Err(err) => {
+                    error!(
+                        "Could not create the recording at '{}': {}",
+                        path.display(),
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
         self.runtime.spawn(async move {
             let stream = match TcpStream::connect(addr).await {
                 Ok(stream) => stream,
@@ -108,7 +167,36 @@ impl NetworkClient {
                 .peer_addr()
                 .expect("Could not fetch peer_addr of existing stream");
 
-            match connection_event_sender.send((stream, addr)) {
+            let stream: BoxedSocket = match tls_connector {
+                Some((connector, server_name)) => {
+                    match connector.connect(server_name, stream).await {
+                        Ok(tls_stream) => Box::new(tls_stream),
+                        Err(err) => {
+                            network_events_sender
+                                .send(ClientNetworkEvent::Error(ClientNetworkError::Tls(
+                                    err.to_string(),
+                                )))
+                                .ok();
+                            return;
+                        }
+                    }
+                }
+                None => Box::new(stream),
+            };
+
+            let stream = match transport::connect(transport, stream, addr).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    network_events_sender
+                        .send(ClientNetworkEvent::Error(
+                            ClientNetworkError::ConnectionRefused(err),
+                        ))
+                        .ok();
+                    return;
+                }
+            };
+
+            match connection_event_sender.send((stream, addr, recorder)) {
                 Ok(_) => (),
                 Err(err) => {
                     error!("Could not initiate connection: {}", err);
@@ -119,6 +207,131 @@ impl NetworkClient {
         });
     }
 
+    /// Replay a recording made with [NetworkSettings::record_path]. The recorded packets are
+    /// turned into events at the pace they were received, as if they came from a server. A
+    /// [ClientNetworkEvent::Connected] is sent when it starts, and a
+    /// [ClientNetworkEvent::Disconnected] when it ends. Messages sent while replaying are
+    /// dropped.
+    pub fn replay(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        if self.is_connected() {
+            panic!("The client is already connected")
+        }
+
+        let mut replay = Replay::open(path.as_ref(), NetworkSettings::default().max_packet_length)?;
+
+        if let Some(replay_task) = self.replay_task.take() {
+            replay_task.abort();
+        }
+
+        let recv_message_map = self.recv_message_map.clone();
+        let message_deserializers = self.message_deserializers.clone();
+        let network_events_sender = self.network_events.sender.clone();
+
+        network_events_sender.send(ClientNetworkEvent::Connected).ok();
+
+        self.replay_task = Some(self.runtime.spawn(async move {
+            let start = tokio::time::Instant::now();
+            loop {
+                let (timestamp, packet) = match replay.next_packet() {
+                    Ok(Some(packet)) => packet,
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!("Failed to read the recording: {}", err);
+                        break;
+                    }
+                };
+
+                tokio::time::sleep_until(start + timestamp).await;
+
+                let Some((id, message)) = NetworkPacket::split_id(&packet) else {
+                    error!("Recorded packet without a message id");
+                    break;
+                };
+
+                // The application may not listen for all the messages that were recorded.
+                let Some(deserialize) = message_deserializers.get(&id).map(|f| *f) else {
+                    continue;
+                };
+
+                match deserialize(message) {
+                    Ok(message) => {
+                        if let Some(mut packets) = recv_message_map.get_mut(&id) {
+                            packets.push(message);
+                        }
+                    }
+                    Err(err) => {
+                        error!("Failed to decode recorded packet: {}", err);
+                        break;
+                    }
+                }
+            }
+
+            network_events_sender
+                .send(ClientNetworkEvent::Disconnected(
+                    "The replay has ended".to_owned(),
+                ))
+                .ok();
+        }));
+
+        return Ok(());
+    }
+
+    /// Ask a server for its status without connecting to it. The answer is sent as a
+    /// [ServerStatusEvent]. Any number of servers can be queried at once, also while connected.
+    pub fn query_status(&self, address: String, network_settings: &NetworkSettings) {
+        let tls_connector = match network_settings.tls.as_ref().map(tls::connector) {
+            Some(Ok(connector)) => Some(connector),
+            Some(Err(err)) => {
+                self.status_events
+                    .sender
+                    .send(ServerStatusEvent {
+                        address,
+                        status: Err(err),
+                    })
+                    .ok();
+                return;
+            }
+            None => None,
+        };
+
+        let status_events = self.status_events.sender.clone();
+        let transport = network_settings.transport;
+
+        self.runtime.spawn(async move {
+            let status = match tokio::time::timeout(
+                STATUS_TIMEOUT,
+                request_status(&address, tls_connector, transport),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => Err("The server did not answer".to_owned()),
+            };
+
+            status_events
+                .send(ServerStatusEvent { address, status })
+                .ok();
+        });
+    }
+
+    /// Start looking for servers that announce themselves on the local network, the servers that
+    /// are found are kept in the [LanServers](crate::LanServers) resource.
+    pub fn discover_lan_servers(&mut self) {
+        if self.lan_discovery_task.is_some() {
+            return;
+        }
+
+        let lan_servers = self.lan_servers.sender.clone();
+        self.lan_discovery_task = Some(self.runtime.spawn(lan::listen(lan_servers)));
+    }
+
+    /// Stop looking for servers on the local network.
+    pub fn stop_lan_discovery(&mut self) {
+        if let Some(task) = self.lan_discovery_task.take() {
+            task.abort();
+        }
+    }
+
     /// Initiate a disconnect, it will not disconnect before the next update cycle.
     /// The message is shown to the player.
     #[track_caller]
@@ -145,10 +358,7 @@ impl NetworkClient {
             None => return,
         };
 
-        let packet = NetworkPacket {
-            kind: String::from(T::NAME),
-            data: Box::new(message),
-        };
+        let packet = NetworkPacket::new(message);
 
         // XXX: If the receiver half of 'self.send_message' has been closed, the server
         // unexpectedly disconnected. This code is the only thing that will discover it.
@@ -182,6 +392,77 @@ impl NetworkClient {
     }
 }
 
+async fn request_status(
+    address: &str,
+    tls_connector: Option<(tokio_rustls::TlsConnector, rustls::ServerName)>,
+    transport: Transport,
+) -> Result<messages::StatusResponse, String> {
+    let stream = TcpStream::connect(address)
+        .await
+        .map_err(|err| err.to_string())?;
+    let addr = stream.peer_addr().map_err(|err| err.to_string())?;
+
+    let socket: BoxedSocket = match tls_connector {
+        Some((connector, server_name)) => Box::new(
+            connector
+                .connect(server_name, stream)
+                .await
+                .map_err(|err| err.to_string())?,
+        ),
+        None => Box::new(stream),
+    };
+
+    let mut socket = transport::connect(transport, socket, addr)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let packet = NetworkPacket::new(messages::StatusRequest);
+    let mut buffer = vec![0; packet.serialized_size().map_err(|err| err.to_string())?];
+    packet
+        .serialize_into(&mut buffer)
+        .map_err(|err| err.to_string())?;
+    socket
+        .write_u32(buffer.len() as u32)
+        .await
+        .map_err(|err| err.to_string())?;
+    socket
+        .write_all(&buffer)
+        .await
+        .map_err(|err| err.to_string())?;
+    socket.flush().await.map_err(|err| err.to_string())?;
+
+    // The server never compresses the status.
+    let length = socket.read_u32().await.map_err(|err| err.to_string())? as usize;
+    if length > MAX_STATUS_LENGTH {
+        return Err("The status is too large".to_owned());
+    }
+
+    let mut buffer = vec![0; length];
+    socket
+        .read_exact(&mut buffer)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    return match NetworkPacket::split_id(&buffer) {
+        Some((messages::StatusResponse::ID, message)) => {
+            bincode::deserialize(message).map_err(|err| err.to_string())
+        }
+        Some((messages::Disconnect::ID, message)) => {
+            let disconnect: messages::Disconnect =
+                bincode::deserialize(message).map_err(|err| err.to_string())?;
+            Err(disconnect.message)
+        }
+        _ => Err("The server sent an invalid status".to_owned()),
+    };
+}
+
+pub(crate) fn send_status_events(
+    net: Res<NetworkClient>,
+    mut status_events: EventWriter<ServerStatusEvent>,
+) {
+    status_events.send_batch(net.status_events.receiver.try_iter());
+}
+
 /// A utility trait on [`AppBuilder`] to easily register [`ClientMessage`]s
 pub trait AppNetworkClientMessage {
     /// Register a client message type
@@ -201,11 +482,14 @@ impl AppNetworkClientMessage for App {
         debug!("Registered a new ClientMessage: {}", T::NAME);
 
         assert!(
-            !client.recv_message_map.contains_key(T::NAME),
+            !client.recv_message_map.contains_key(&T::ID),
             "Duplicate registration of ClientMessage: {}",
             T::NAME
         );
-        client.recv_message_map.insert(T::NAME, Vec::new());
+        client.recv_message_map.insert(T::ID, Vec::new());
+        client
+            .message_deserializers
+            .insert(T::ID, network_message::deserialize_message::<T>);
 
         self.add_event::<NetworkData<T>>();
         self.add_systems(PreUpdate, register_client_message::<T>)
@@ -218,7 +502,7 @@ fn register_client_message<T>(
 ) where
     T: ClientBound,
 {
-    let mut messages = match net_res.recv_message_map.get_mut(T::NAME) {
+    let mut messages = match net_res.recv_message_map.get_mut(&T::ID) {
         Some(messages) => messages,
         None => return,
     };
@@ -233,9 +517,10 @@ fn register_client_message<T>(
 
 pub fn handle_connection_event(
     mut net_res: ResMut<NetworkClient>,
+    diagnostics: Res<NetworkDiagnostics>,
     mut events: EventWriter<ClientNetworkEvent>,
 ) {
-    let (connection, peer_addr) =
+    let (connection, peer_addr, mut recorder) =
         match net_res.connection_events.receiver.try_recv() {
             Ok(event) => event,
             Err(_) => {
@@ -243,10 +528,16 @@ pub fn handle_connection_event(
             }
         };
 
-    let (read_socket, send_socket) = connection.into_split();
+    let (read_socket, send_socket) = tokio::io::split(connection);
     let recv_message_map = net_res.recv_message_map.clone();
-    let (send_message, recv_message) = unbounded_channel();
+    let message_deserializers = net_res.message_deserializers.clone();
+    let (send_message, recv_message) = unbounded_channel::<NetworkPacket>();
     let send_settings = NetworkSettings::default();
+    let compression = Arc::new(AtomicBool::new(false));
+    let send_compression = compression.clone();
+    let send_diagnostics = diagnostics.clone();
+    let receive_diagnostics = diagnostics.clone();
+    let network_events_sender = net_res.network_events.sender.clone();
 
     net_res.server_connection = Some(ServerConnection {
         peer_addr,
@@ -254,10 +545,24 @@ pub fn handle_connection_event(
             let mut recv_message = recv_message;
             let mut send_socket = send_socket;
             let mut buffer: Vec<u8> = vec![0; send_settings.max_packet_length];
+            let mut compressed_buffer: Vec<u8> = Vec::new();
 
-            while let Some(message) = recv_message.recv().await {
-                let size = match bincode::serialized_size(&message) {
-                    Ok(size) => size as usize,
+            loop {
+                let message = match tokio::time::timeout(
+                    send_settings.keepalive_interval,
+                    recv_message.recv(),
+                )
+                .await
+                {
+                    Ok(Some(message)) => message,
+                    Ok(None) => return,
+                    // Nothing has been sent for a while, let the server know the connection is
+                    // alive.
+                    Err(_) => NetworkPacket::new(messages::KeepAlive),
+                };
+
+                let size = match message.serialized_size() {
+                    Ok(size) => size,
                     Err(err) => {
                         error!(
                             "Could not get the size of the packet {:?}: {}",
@@ -267,7 +572,9 @@ pub fn handle_connection_event(
                     }
                 };
 
-                match bincode::serialize_into(&mut buffer[0..size], &message) {
+                match info_span!("serialize_packet")
+                    .in_scope(|| message.serialize_into(&mut buffer[0..size]))
+                {
                     Ok(_) => (),
                     Err(err) => {
                         error!(
@@ -280,7 +587,16 @@ pub fn handle_connection_event(
 
                 debug!("Sending a new message of size: {}", size);
 
-                match send_socket.write_u32(size as u32).await {
+                // Compression is only used after the server has said it supports it.
+                let threshold = if send_compression.load(Ordering::Relaxed) {
+                    send_settings.compression_threshold
+                } else {
+                    None
+                };
+                let (length, packet) =
+                    compress_packet(&buffer[0..size], threshold, &mut compressed_buffer);
+
+                match send_socket.write_u32(length).await {
                     Ok(_) => (),
                     Err(err) => {
                         error!("Could not send packet length: {:?}: {}", size, err);
@@ -290,7 +606,7 @@ pub fn handle_connection_event(
 
                 trace!("Sending the content of the message");
 
-                match send_socket.write_all(&buffer[0..size]).await {
+                match send_socket.write_all(packet).await {
                     Ok(_) => (),
                     Err(err) => {
                         error!("Could not send packet: {:?}: {}", message, err);
@@ -298,6 +614,14 @@ pub fn handle_connection_event(
                     }
                 }
 
+                // Encrypted sockets buffer the data until they are flushed.
+                if let Err(err) = send_socket.flush().await {
+                    error!("Could not flush packet: {:?}: {}", message, err);
+                    return;
+                }
+
+                send_diagnostics.record_sent(message.id, LENGTH_PREFIX_SIZE + packet.len());
+
                 trace!("Succesfully sent message");
             }
         }),
@@ -307,17 +631,31 @@ pub fn handle_connection_event(
             let recv_message_map = recv_message_map;
 
             let mut buffer: Vec<u8> = vec![0; network_settings.max_packet_length];
+            let mut compressed_buffer: Vec<u8> = Vec::new();
             loop {
-                let length = match read_socket.read_u32().await {
-                    Ok(len) => len as usize,
-                    Err(err) => {
-                        error!(
-                            "Encountered error while fetching length [{}]: {}",
-                            peer_addr, err
-                        );
-                        break;
-                    }
-                };
+                let (length, is_compressed) =
+                    match with_timeout(network_settings.idle_timeout, read_socket.read_u32()).await
+                    {
+                        Some(Ok(len)) => (
+                            (len & !COMPRESSED_FLAG) as usize,
+                            len & COMPRESSED_FLAG != 0,
+                        ),
+                        Some(Err(err)) => {
+                            error!(
+                                "Encountered error while fetching length [{}]: {}",
+                                peer_addr, err
+                            );
+                            break;
+                        }
+                        None => {
+                            network_events_sender
+                                .send(ClientNetworkEvent::Disconnected(
+                                    "The server stopped responding".to_owned(),
+                                ))
+                                .ok();
+                            break;
+                        }
+                    };
 
                 if length > network_settings.max_packet_length {
                     error!(
@@ -327,49 +665,128 @@ pub fn handle_connection_event(
                     break;
                 }
 
-                match read_socket.read_exact(&mut buffer[..length]).await {
-                    Ok(_) => (),
-                    Err(err) => {
+                let read_buffer = if is_compressed {
+                    compressed_buffer.resize(length, 0);
+                    &mut compressed_buffer[..]
+                } else {
+                    &mut buffer[..length]
+                };
+
+                match read_exact_with_timeout(
+                    network_settings.idle_timeout,
+                    &mut read_socket,
+                    read_buffer,
+                )
+                .await
+                {
+                    Some(Ok(_)) => (),
+                    Some(Err(err)) => {
                         error!(
                             "Encountered error while fetching stream of length {} [{}]: {}",
                             length, peer_addr, err
                         );
                         break;
                     }
+                    None => {
+                        network_events_sender
+                            .send(ClientNetworkEvent::Disconnected(
+                                "The server stopped responding".to_owned(),
+                            ))
+                            .ok();
+                        break;
+                    }
                 }
 
-                let packet: NetworkPacket = match bincode::deserialize(&buffer[..length]) {
-                    Ok(packet) => packet,
-                    Err(err) => {
-                        error!(
-                            "Failed to decode network packet from [{}]: {}",
-                            peer_addr, err
-                        );
-                        break;
+                // The length prefix is counted as part of the packet.
+                let wire_length = LENGTH_PREFIX_SIZE + length;
+
+                let length = if is_compressed {
+                    match decompress_packet(&compressed_buffer, &mut buffer) {
+                        Ok(length) => length,
+                        Err(err) => {
+                            error!(
+                                "Failed to decompress network packet from [{}]: {}",
+                                peer_addr, err
+                            );
+                            break;
+                        }
                     }
+                } else {
+                    length
                 };
 
-                match recv_message_map.get_mut(&packet.kind[..]) {
-                    Some(mut packets) => packets.push(packet.data),
-                    None => {
-                        error!(
-                            "Could not find existing entries for message kinds: {:?}",
-                            packet
-                        );
-                    }
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&buffer[..length]);
+                }
+
+                let Some((id, message)) = NetworkPacket::split_id(&buffer[..length]) else {
+                    error!("Received packet without a message id from [{}]", peer_addr);
+                    break;
+                };
+
+                receive_diagnostics.record_received(id, wire_length);
+
+                // Keepalives have done their job by arriving.
+                if id == messages::KeepAlive::ID {
+                    continue;
+                }
+
+                let Some(deserialize) = message_deserializers.get(&id).map(|f| *f) else {
+                    error!(
+                        "Could not find existing entries for message kinds: {:?}",
+                        messages::MESSAGE_NAMES
+                            .get(id as usize)
+                            .unwrap_or(&"unknown")
+                    );
+                    continue;
+                };
+
+                let message =
+                    match info_span!("deserialize_packet").in_scope(|| deserialize(message)) {
+                        Ok(message) => message,
+                        Err(err) => {
+                            error!(
+                                "Failed to decode network packet from [{}]: {}",
+                                peer_addr, err
+                            );
+                            break;
+                        }
+                    };
+
+                if let Some(mut packets) = recv_message_map.get_mut(&id) {
+                    packets.push(message);
                 }
                 debug!("Received message from: {}", peer_addr);
             }
         }),
         send_message,
+        compression,
     });
 
     events.send(ClientNetworkEvent::Connected);
 }
 
+// If the server supports compression, start compressing and tell it to do the same.
+pub(crate) fn enable_compression(
+    net: Res<NetworkClient>,
+    mut server_config_events: EventReader<NetworkData<messages::ServerConfig>>,
+) {
+    for server_config in server_config_events.read() {
+        if !server_config.compression || NetworkSettings::default().compression_threshold.is_none()
+        {
+            continue;
+        }
+
+        if let Some(connection) = net.server_connection.as_ref() {
+            connection.compression.store(true, Ordering::Relaxed);
+            net.send_message(messages::EnableCompression);
+        }
+    }
+}
+
 pub fn handle_client_network_events(
     mut net: ResMut<NetworkClient>,
-    mut server_disconnect_events: EventReader<NetworkData<crate::messages::Disconnect>>,
+    mut server_disconnect_events: EventReader<NetworkData<messages::Disconnect>>,
     mut client_network_events: EventWriter<ClientNetworkEvent>,
 ) {
     for event in server_disconnect_events.read() {
@@ -386,6 +803,9 @@ pub fn handle_client_network_events(
                 if let Some(connection) = net.server_connection.take() {
                     connection.stop();
                 }
+                if let Some(replay_task) = net.replay_task.take() {
+                    replay_task.abort();
+                }
                 client_network_events.send(event);
                 // There might be many errors when something bad happens, so just send the first
                 // one. The others will be cleared on event buffer rotation at the end of the
@@ -0,0 +1,243 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+};
+
+use crate::messages;
+
+// How many seconds of measurements the bevy diagnostics keep.
+const HISTORY_LENGTH: usize = 60;
+
+/// Amount of data that has been sent or received.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Bytes on the wire, this is after compression and includes the length that precedes each
+    /// packet.
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+impl std::ops::Sub for Traffic {
+    type Output = Traffic;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        return Traffic {
+            bytes: self.bytes.saturating_sub(rhs.bytes),
+            packets: self.packets.saturating_sub(rhs.packets),
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrafficCounter {
+    bytes: AtomicU64,
+    packets: AtomicU64,
+}
+
+impl TrafficCounter {
+    fn record(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.packets.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Traffic {
+        return Traffic {
+            bytes: self.bytes.load(Ordering::Relaxed),
+            packets: self.packets.load(Ordering::Relaxed),
+        };
+    }
+}
+
+/// The traffic of one kind of message.
+#[derive(Clone, Copy, Debug)]
+pub struct MessageTraffic {
+    pub name: &'static str,
+    pub received: Traffic,
+    pub sent: Traffic,
+}
+
+#[derive(Debug)]
+struct Counters {
+    received: TrafficCounter,
+    sent: TrafficCounter,
+    // Indexed by message id.
+    received_by_message: Vec<TrafficCounter>,
+    sent_by_message: Vec<TrafficCounter>,
+}
+
+/// Totals of everything that has been sent and received since the app started, for all
+/// connections together. The counters are updated by the tasks that read and write the packets.
+///
+/// The rates are also registered as bevy diagnostics, measured once a second, see
+/// [NetworkDiagnostics::BYTES_RECEIVED] and the other ids.
+#[derive(Resource, Clone, Debug)]
+pub struct NetworkDiagnostics(Arc<Counters>);
+
+impl Default for NetworkDiagnostics {
+    fn default() -> Self {
+        let message_count = messages::MESSAGE_NAMES.len();
+        return Self(Arc::new(Counters {
+            received: TrafficCounter::default(),
+            sent: TrafficCounter::default(),
+            received_by_message: (0..message_count)
+                .map(|_| TrafficCounter::default())
+                .collect(),
+            sent_by_message: (0..message_count)
+                .map(|_| TrafficCounter::default())
+                .collect(),
+        }));
+    }
+}
+
+impl NetworkDiagnostics {
+    /// Bytes received per second.
+    pub const BYTES_RECEIVED: DiagnosticId =
+        DiagnosticId::from_u128(0x5a1d3e0c_7b42_4f8e_9c61_2d0a8b3f4e01);
+    /// Bytes sent per second.
+    pub const BYTES_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x5a1d3e0c_7b42_4f8e_9c61_2d0a8b3f4e02);
+    /// Packets received per second.
+    pub const PACKETS_RECEIVED: DiagnosticId =
+        DiagnosticId::from_u128(0x5a1d3e0c_7b42_4f8e_9c61_2d0a8b3f4e03);
+    /// Packets sent per second.
+    pub const PACKETS_SENT: DiagnosticId =
+        DiagnosticId::from_u128(0x5a1d3e0c_7b42_4f8e_9c61_2d0a8b3f4e04);
+
+    pub fn received(&self) -> Traffic {
+        return self.0.received.get();
+    }
+
+    pub fn sent(&self) -> Traffic {
+        return self.0.sent.get();
+    }
+
+    /// The traffic of each kind of message, ordered by message id.
+    pub fn messages(&self) -> impl Iterator<Item = MessageTraffic> + '_ {
+        return messages::MESSAGE_NAMES
+            .iter()
+            .enumerate()
+            .map(|(id, name)| MessageTraffic {
+                name,
+                received: self.0.received_by_message[id].get(),
+                sent: self.0.sent_by_message[id].get(),
+            });
+    }
+
+    pub(crate) fn record_received(&self, message_id: u16, bytes: usize) {
+        self.0.received.record(bytes);
+        if let Some(counter) = self.0.received_by_message.get(message_id as usize) {
+            counter.record(bytes);
+        }
+    }
+
+    pub(crate) fn record_sent(&self, message_id: u16, bytes: usize) {
+        self.0.sent.record(bytes);
+        if let Some(counter) = self.0.sent_by_message.get(message_id as usize) {
+            counter.record(bytes);
+        }
+    }
+}
+
+/// Traffic of a single connection, it is inserted on the entity of each connection alongside
+/// its [Latency](crate::Latency).
+#[derive(Component, Clone, Debug, Default)]
+pub struct ConnectionTraffic(Arc<ConnectionCounters>);
+
+#[derive(Debug, Default)]
+struct ConnectionCounters {
+    received: TrafficCounter,
+    sent: TrafficCounter,
+}
+
+impl ConnectionTraffic {
+    pub fn received(&self) -> Traffic {
+        return self.0.received.get();
+    }
+
+    pub fn sent(&self) -> Traffic {
+        return self.0.sent.get();
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.0.received.record(bytes);
+    }
+
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.0.sent.record(bytes);
+    }
+}
+
+pub(crate) fn register_diagnostics(app: &mut App) {
+    app.init_resource::<NetworkDiagnostics>()
+        .register_diagnostic(
+            Diagnostic::new(
+                NetworkDiagnostics::BYTES_RECEIVED,
+                "bytes_received",
+                HISTORY_LENGTH,
+            )
+            .with_suffix("B/s"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(NetworkDiagnostics::BYTES_SENT, "bytes_sent", HISTORY_LENGTH)
+                .with_suffix("B/s"),
+        )
+        .register_diagnostic(Diagnostic::new(
+            NetworkDiagnostics::PACKETS_RECEIVED,
+            "packets_received",
+            HISTORY_LENGTH,
+        ))
+        .register_diagnostic(Diagnostic::new(
+            NetworkDiagnostics::PACKETS_SENT,
+            "packets_sent",
+            HISTORY_LENGTH,
+        ))
+        .add_systems(Last, measure_traffic);
+}
+
+// Measured once a second, measuring every frame would make the rates jump between zero and the
+// size of a chunk.
+fn measure_traffic(
+    network_diagnostics: Res<NetworkDiagnostics>,
+    mut diagnostics: Diagnostics,
+    mut last_measurement: Local<Option<(Instant, Traffic, Traffic)>>,
+) {
+    let now = Instant::now();
+    let received = network_diagnostics.received();
+    let sent = network_diagnostics.sent();
+
+    let Some((last_time, last_received, last_sent)) = *last_measurement else {
+        *last_measurement = Some((now, received, sent));
+        return;
+    };
+
+    let elapsed = (now - last_time).as_secs_f64();
+    if elapsed < 1.0 {
+        return;
+    }
+
+    let received_delta = received - last_received;
+    let sent_delta = sent - last_sent;
+
+    diagnostics.add_measurement(NetworkDiagnostics::BYTES_RECEIVED, || {
+        received_delta.bytes as f64 / elapsed
+    });
+    diagnostics.add_measurement(NetworkDiagnostics::BYTES_SENT, || {
+        sent_delta.bytes as f64 / elapsed
+    });
+    diagnostics.add_measurement(NetworkDiagnostics::PACKETS_RECEIVED, || {
+        received_delta.packets as f64 / elapsed
+    });
+    diagnostics.add_measurement(NetworkDiagnostics::PACKETS_SENT, || {
+        sent_delta.packets as f64 / elapsed
+    });
+
+    *last_measurement = Some((now, received, sent));
+}
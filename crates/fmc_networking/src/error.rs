@@ -24,4 +24,6 @@ pub enum ClientNetworkError {
     NotConnected,
     #[error("Failed to connect to server: {0}")]
     ConnectionRefused(std::io::Error),
+    #[error("Failed to establish an encrypted connection: {0}")]
+    Tls(String),
 }
@@ -0,0 +1,180 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, sync::watch};
+
+use crate::{messages, server::ServerStatus, NetworkClient};
+
+// Servers on the local network announce themselves by broadcasting to this port, and clients that
+// look for servers listen on it. Only one client on a machine can listen at a time.
+const LAN_DISCOVERY_PORT: u16 = 42070;
+// How often servers announce themselves.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(1500);
+// Servers that haven't been heard from in this long are assumed to have shut down.
+const LAN_SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+// Announcements start with this, so that other programs that broadcast to the port are ignored.
+const ANNOUNCEMENT_MAGIC: &[u8] = b"fmc-lan";
+// Announcements are small, larger packets are not announcements.
+const MAX_ANNOUNCEMENT_LENGTH: usize = 1024;
+
+#[derive(Serialize, Deserialize)]
+struct LanAnnouncement {
+    protocol_version: u32,
+    /// The port the server listens for connections on. The address is taken from the packet.
+    port: u16,
+    motd: String,
+}
+
+/// A server that has announced itself on the local network.
+#[derive(Debug, Clone)]
+pub struct LanServer {
+    /// Where the server can be connected to.
+    pub address: SocketAddr,
+    /// The [PROTOCOL_VERSION](messages::PROTOCOL_VERSION) of the server.
+    pub protocol_version: u32,
+    /// Message of the day.
+    pub motd: String,
+    last_seen: Instant,
+}
+
+/// The servers found on the local network, kept up to date while
+/// [NetworkClient::discover_lan_servers] is running.
+#[derive(Resource, Debug, Default)]
+pub struct LanServers {
+    servers: Vec<LanServer>,
+}
+
+impl LanServers {
+    /// The servers in the order they were found.
+    pub fn iter(&self) -> impl Iterator<Item = &LanServer> {
+        return self.servers.iter();
+    }
+
+    // Returns true if the server is new or has changed.
+    fn insert(&mut self, server: LanServer) -> bool {
+        match self
+            .servers
+            .iter_mut()
+            .find(|known| known.address == server.address)
+        {
+            Some(known) => {
+                let changed =
+                    known.motd != server.motd || known.protocol_version != server.protocol_version;
+                *known = server;
+                changed
+            }
+            None => {
+                self.servers.push(server);
+                true
+            }
+        }
+    }
+}
+
+/// Broadcasts the status of the server to the local network until the task is stopped.
+pub(crate) async fn announce(port: u16, status: watch::Receiver<ServerStatus>) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!(
+                "Could not announce the server on the local network: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    if let Err(err) = socket.set_broadcast(true) {
+        error!(
+            "Could not announce the server on the local network: {}",
+            err
+        );
+        return;
+    }
+
+    let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let announcement = LanAnnouncement {
+            protocol_version: messages::PROTOCOL_VERSION,
+            port,
+            motd: status.borrow().motd.clone(),
+        };
+
+        let mut packet = ANNOUNCEMENT_MAGIC.to_vec();
+        if bincode::serialize_into(&mut packet, &announcement).is_err() {
+            return;
+        }
+
+        // Fails when there's no network, it may come back so it's not treated as an error.
+        socket
+            .send_to(&packet, (Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT))
+            .await
+            .ok();
+    }
+}
+
+/// Listens for servers that announce themselves until the task is stopped.
+pub(crate) async fn listen(lan_servers: crossbeam_channel::Sender<LanServer>) {
+    let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT)).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!("Could not look for servers on the local network: {}", err);
+            return;
+        }
+    };
+
+    let mut buffer = vec![0; MAX_ANNOUNCEMENT_LENGTH];
+    loop {
+        let (length, sender) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(err) => {
+                error!("Stopped looking for servers on the local network: {}", err);
+                return;
+            }
+        };
+
+        let Some(announcement) = buffer[..length].strip_prefix(ANNOUNCEMENT_MAGIC) else {
+            continue;
+        };
+
+        let Ok(announcement) = bincode::deserialize::<LanAnnouncement>(announcement) else {
+            continue;
+        };
+
+        let server = LanServer {
+            address: SocketAddr::new(sender.ip(), announcement.port),
+            protocol_version: announcement.protocol_version,
+            motd: announcement.motd,
+            last_seen: Instant::now(),
+        };
+
+        if lan_servers.send(server).is_err() {
+            return;
+        }
+    }
+}
+
+pub(crate) fn update_lan_servers(net: Res<NetworkClient>, mut lan_servers: ResMut<LanServers>) {
+    let mut changed = false;
+    for server in net.lan_servers.receiver.try_iter() {
+        changed |= lan_servers.bypass_change_detection().insert(server);
+    }
+
+    let count = lan_servers.servers.len();
+    lan_servers
+        .bypass_change_detection()
+        .servers
+        .retain(|server| server.last_seen.elapsed() < LAN_SERVER_TIMEOUT);
+
+    // The servers announce themselves every few seconds, change detection is only triggered when
+    // something other than when they were last seen changes.
+    if changed || count != lan_servers.servers.len() {
+        lan_servers.set_changed();
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+
+use crate::{messages, ConnectionId, NetworkClient, NetworkData, NetworkServer};
+
+// How often the server measures the round trip time of a connection.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Round trip time between the server and a client, it is inserted on the entity of each
+/// connection. The time includes how long it takes the client to handle the message, so it is
+/// what it takes to get an answer from the client, not only the time on the wire.
+#[derive(Component, Debug, Default)]
+pub struct Latency {
+    /// Average of the recent round trips, zero until the first ping has been answered.
+    pub round_trip: Duration,
+    // The ping waiting for an answer, and when it was sent.
+    pending: Option<(u32, Instant)>,
+    last_ping: Option<Instant>,
+    next_id: u32,
+}
+
+impl Latency {
+    fn add_sample(&mut self, round_trip: Duration) {
+        // Averaged so a single slow packet doesn't make the ping jump around.
+        self.round_trip = if self.round_trip.is_zero() {
+            round_trip
+        } else {
+            self.round_trip.mul_f32(0.875) + round_trip.mul_f32(0.125)
+        };
+    }
+}
+
+/// Round trip time to the server, as measured by the server.
+#[derive(Resource, Debug, Default)]
+pub struct ServerLatency {
+    /// Zero until it has been measured.
+    pub round_trip: Duration,
+}
+
+pub(crate) fn send_pings(
+    net: Res<NetworkServer>,
+    mut latency_query: Query<(&ConnectionId, &mut Latency)>,
+) {
+    let now = Instant::now();
+
+    for (connection_id, mut latency) in latency_query.iter_mut() {
+        // Only one ping is sent at a time, a client that doesn't answer keeps its last latency.
+        if latency.pending.is_some()
+            || latency
+                .last_ping
+                .is_some_and(|last_ping| now - last_ping < PING_INTERVAL)
+        {
+            continue;
+        }
+
+        let id = latency.next_id;
+        latency.next_id = latency.next_id.wrapping_add(1);
+        latency.pending = Some((id, now));
+        latency.last_ping = Some(now);
+
+        net.send_one(
+            *connection_id,
+            messages::Ping {
+                id,
+                round_trip: latency.round_trip,
+            },
+        );
+    }
+}
+
+pub(crate) fn handle_pongs(
+    mut latency_query: Query<&mut Latency>,
+    mut pong_events: EventReader<NetworkData<messages::Pong>>,
+) {
+    for pong in pong_events.read() {
+        let Ok(mut latency) = latency_query.get_mut(pong.source.entity()) else {
+            continue;
+        };
+
+        match latency.pending {
+            Some((id, sent)) if id == pong.id => {
+                latency.pending = None;
+                latency.add_sample(sent.elapsed());
+            }
+            // Answers to pings that weren't sent are ignored.
+            _ => continue,
+        }
+    }
+}
+
+pub(crate) fn answer_pings(
+    net: Res<NetworkClient>,
+    mut server_latency: ResMut<ServerLatency>,
+    mut ping_events: EventReader<NetworkData<messages::Ping>>,
+) {
+    for ping in ping_events.read() {
+        net.send_message(messages::Pong { id: ping.id });
+        server_latency.round_trip = ping.round_trip;
+    }
+}
@@ -13,14 +13,33 @@
 )]
 #![allow(clippy::type_complexity)]
 
+mod auth;
+mod bans;
 mod client;
+mod diagnostics;
 mod error;
+mod lan;
+mod latency;
 mod network_message;
+mod rate_limit;
+mod recording;
 mod server;
+mod tls;
+mod transport;
+#[cfg(feature = "upnp")]
+mod upnp;
 
 pub mod messages;
+pub use auth::{new_player_id, Account, AccountStorage, MAX_USERNAME_LENGTH};
+pub use bans::{Ban, BanStorage};
 pub use client::NetworkClient;
-pub use server::NetworkServer;
+pub use diagnostics::{ConnectionTraffic, MessageTraffic, NetworkDiagnostics, Traffic};
+pub use lan::{LanServer, LanServers};
+pub use latency::{Latency, ServerLatency};
+pub use rate_limit::RateLimit;
+pub use server::{NetworkServer, ServerStatus};
+pub use tls::{certificate_fingerprint, TlsSettings};
+pub use transport::Transport;
 
 use std::{hash::Hash, net::SocketAddr};
 
@@ -30,7 +49,6 @@ use crossbeam_channel::{unbounded, Receiver, Sender};
 use derive_more::{Deref, DerefMut, Display};
 use error::{ClientNetworkError, ServerNetworkError};
 use network_message::NetworkMessage;
-use serde::{Deserialize, Serialize};
 use server::AppNetworkServerMessage;
 
 // TODO: I want to increase block ids from u16 to u32. Doubling the memory size is bad. Instead
@@ -94,17 +112,50 @@ impl Hash for ConnectionId {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-/// [`NetworkPacket`]s are untyped packets to be sent over the wire
+/// [`NetworkPacket`]s are untyped packets to be sent over the wire. On the wire they are the id
+/// of the message followed by the message itself.
 struct NetworkPacket {
-    kind: String,
+    /// Id of the message, assigned by the message registry.
+    id: u16,
     data: Box<dyn NetworkMessage>,
 }
 
+impl NetworkPacket {
+    /// Size of the message id that precedes the message.
+    const ID_SIZE: usize = 2;
+
+    fn new<T: NetworkMessage + network_message::MessageId>(message: T) -> Self {
+        Self {
+            id: T::ID,
+            data: Box::new(message),
+        }
+    }
+
+    fn serialized_size(&self) -> bincode::Result<usize> {
+        return Ok(Self::ID_SIZE + self.data.serialized_size()? as usize);
+    }
+
+    /// Serialize the packet into the buffer, it must be at least 'serialized_size' long.
+    fn serialize_into(&self, buffer: &mut [u8]) -> bincode::Result<()> {
+        buffer[..Self::ID_SIZE].copy_from_slice(&self.id.to_be_bytes());
+        return self.data.serialize_into(&mut buffer[Self::ID_SIZE..]);
+    }
+
+    /// Splits a received packet into the message id and the serialized message.
+    fn split_id(bytes: &[u8]) -> Option<(u16, &[u8])> {
+        if bytes.len() < Self::ID_SIZE {
+            return None;
+        }
+        let (id, message) = bytes.split_at(Self::ID_SIZE);
+        return Some((u16::from_be_bytes([id[0], id[1]]), message));
+    }
+}
+
 impl std::fmt::Debug for NetworkPacket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NetworkPacket")
-            .field("kind", &self.kind)
+            .field("id", &self.id)
+            .field("kind", &messages::MESSAGE_NAMES.get(self.id as usize))
             .finish()
     }
 }
@@ -117,10 +168,22 @@ pub enum ServerNetworkEvent {
         // TODO: Most places I access this ends up mapping the entity to the connection instead of
         // the other way around. Just send the ConnectionId. Same for disconnect.
         entity: Entity,
+        /// The name the client logged in with. It has been verified if the server was given an
+        /// [AccountStorage] through [NetworkServer::set_account_storage].
         username: String,
+        /// The id of the player's account. It stays the same if the player changes their name.
+        /// Without an [AccountStorage] there are no accounts, and the username is used as the id.
+        player_id: String,
     },
     /// A client has disconnected. It will be removed at the end of the update cycle.
     Disconnected { entity: Entity },
+    /// A client was kicked by [NetworkServer::kick], or because it was banned. It is followed by
+    /// a [ServerNetworkEvent::Disconnected].
+    Kicked { entity: Entity, reason: String },
+    /// A ban was added with [NetworkServer::ban].
+    Banned { ban: Ban, reason: String },
+    /// A ban was removed with [NetworkServer::unban].
+    Unbanned { ban: Ban },
     /// An error occured while trying to do a network operation
     Error(ServerNetworkError),
 }
@@ -136,6 +199,15 @@ pub enum ClientNetworkEvent {
     Error(ClientNetworkError),
 }
 
+/// The answer to [NetworkClient::query_status].
+#[derive(Debug, Event)]
+pub struct ServerStatusEvent {
+    /// The address that was queried, as it was given.
+    pub address: String,
+    /// The status of the server, or why it couldn't be retrieved.
+    pub status: Result<messages::StatusResponse, String>,
+}
+
 /// [`NetworkData`] are bevy events that should be handled by the receiver.
 #[derive(Debug, Deref, DerefMut, Event)]
 pub struct NetworkData<T> {
@@ -166,14 +238,131 @@ pub struct NetworkSettings {
     /// Maximum packet size in bytes. If a client ever exceeds this size, it will be disconnected
     /// The default is set to 10MiB
     pub max_packet_length: usize,
+    /// Packets larger than this many bytes are compressed, if the other end supports it. None
+    /// disables compression.
+    pub compression_threshold: Option<usize>,
+    /// Encrypt the connection with TLS, None sends everything as plain text.
+    pub tls: Option<TlsSettings>,
+    /// How the packets are carried, the server listens for and the client connects with it.
+    pub transport: Transport,
+    /// Clients that keep exceeding a [RateLimit] for this long are disconnected.
+    pub rate_limit_timeout: std::time::Duration,
+    /// How many clients the server lets in at once. Clients that log in while it is full wait in
+    /// a queue until someone leaves. None lets everyone in.
+    pub max_connections: Option<usize>,
+    /// A [KeepAlive](messages::KeepAlive) is sent when nothing else has been sent for this long,
+    /// so the other end knows the connection is still alive.
+    pub keepalive_interval: std::time::Duration,
+    /// The connection is considered dead and closed when nothing has been received for this long.
+    /// It must be longer than the keepalive interval of the other end. None never times out.
+    pub idle_timeout: Option<std::time::Duration>,
+    /// The client writes the packets it receives to this file, so the session can be replayed
+    /// with [NetworkClient::replay]. It is overwritten each time the client connects.
+    pub record_path: Option<std::path::PathBuf>,
+    /// The server announces itself to clients on the local network, so they can find it without
+    /// knowing its address.
+    pub lan_discovery: bool,
+    /// The server asks the router to forward its port with UPnP, so it can be reached from
+    /// outside the local network. Only available when built with the 'upnp' feature.
+    pub upnp: bool,
 }
 
 impl Default for NetworkSettings {
     fn default() -> Self {
         NetworkSettings {
             max_packet_length: 10 * 1024 * 1024,
+            compression_threshold: Some(1024),
+            tls: None,
+            transport: Transport::Tcp,
+            rate_limit_timeout: std::time::Duration::from_secs(5),
+            max_connections: None,
+            keepalive_interval: std::time::Duration::from_secs(5),
+            idle_timeout: Some(std::time::Duration::from_secs(30)),
+            record_path: None,
+            lan_discovery: false,
+            upnp: false,
+        }
+    }
+}
+
+// Packets are sent as a u32 length prefix followed by the packet. If the highest bit of the
+// length is set, the packet is lz4 compressed, and starts with its uncompressed size.
+//
+// Compression is negotiated through the 'compression' field of ServerConfig. The client answers
+// with EnableCompression if it supports it, and until then the server sends everything
+// uncompressed. This way old clients that don't know about it can still connect.
+const COMPRESSED_FLAG: u32 = 1 << 31;
+/// Size of the length that precedes each packet.
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Waits for the future to complete, returns None if it takes longer than the timeout. A timeout
+/// of None waits forever.
+async fn with_timeout<F: std::future::Future>(
+    timeout: Option<std::time::Duration>,
+    future: F,
+) -> Option<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Fills the buffer from the reader, returns None if nothing arrives for longer than the timeout.
+/// The timeout starts over each time some of it arrives, so large packets are not cut off on slow
+/// connections, only connections that stop sending are.
+async fn read_exact_with_timeout<R: tokio::io::AsyncRead + Unpin>(
+    timeout: Option<std::time::Duration>,
+    reader: &mut R,
+    buffer: &mut [u8],
+) -> Option<std::io::Result<()>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match with_timeout(timeout, reader.read(&mut buffer[filled..])).await? {
+            Ok(0) => return Some(Err(std::io::ErrorKind::UnexpectedEof.into())),
+            Ok(read) => filled += read,
+            Err(err) => return Some(Err(err)),
         }
     }
+
+    return Some(Ok(()));
+}
+
+/// Compresses the packet if it is larger than the threshold, returns the length prefix and the
+/// bytes that should be sent.
+fn compress_packet<'a>(
+    packet: &'a [u8],
+    threshold: Option<usize>,
+    compressed_buffer: &'a mut Vec<u8>,
+) -> (u32, &'a [u8]) {
+    match threshold {
+        Some(threshold) if packet.len() > threshold => {
+            *compressed_buffer = lz4_flex::compress_prepend_size(packet);
+            (
+                compressed_buffer.len() as u32 | COMPRESSED_FLAG,
+                compressed_buffer.as_slice(),
+            )
+        }
+        _ => (packet.len() as u32, packet),
+    }
+}
+
+/// Decompresses a packet into the buffer, returns the length of the decompressed packet.
+fn decompress_packet(compressed: &[u8], buffer: &mut [u8]) -> Result<usize, String> {
+    let (size, compressed) =
+        lz4_flex::block::uncompressed_size(compressed).map_err(|err| err.to_string())?;
+
+    if size > buffer.len() {
+        return Err(format!(
+            "decompressed packet is too large: {} > {}",
+            size,
+            buffer.len()
+        ));
+    }
+
+    return lz4_flex::decompress_into(compressed, &mut buffer[..size])
+        .map_err(|err| err.to_string());
 }
 
 #[derive(Default, Copy, Clone, Debug)]
@@ -182,36 +371,34 @@ pub struct ServerPlugin;
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
+        diagnostics::register_diagnostics(app);
         app.insert_resource(server::NetworkServer::new())
             .add_event::<ServerNetworkEvent>()
             .init_resource::<NetworkSettings>()
+            // Connections and their messages are handled first, so that the rest of the tick can
+            // react to them. A connection's entity is kept until the end of the tick it
+            // disconnected in, the application can use it while handling the disconnection.
             .add_systems(
-                PreUpdate,
+                First,
                 (
                     server::handle_connections,
-                    // TODO: I don't know how I feel about this order trickery. I would like it to
-                    // be just 'Client Disconnected' -> 'immediately despawn connection entity',
-                    // but what do you do with the network messages that arrived in the span
-                    // between the last event registration and the disconnection? It would be nice
-                    // to handle them, but they are probably not that important. The bigger problem
-                    // is that messages have already been added to the message pool, and so are
-                    // hard to filter out again. Probably means separate message queues for each
-                    // connection, and that is a headache. Maybe a way to this with channels?
-                    // HashMap<"packet kind", Sender<NetworkMessage>> passed to recv_task, same but
-                    // with Receiver as entity component. Doesn't need to be mutable anywhere so
-                    // systems can transfer them to events in parallel.
-                    //
-                    // It is purposefully 'before' and not 'after' here, so it can go:
-                    // 1. Send disconnect event
-                    // 2. Application reacts to event, saves player state etc and processes left
-                    //    over accumulated network events.
-                    // 3. A tick after, the connection entity is despawned
-                    server::handle_disconnection_events.before(server::send_disconnection_events),
                     server::send_disconnection_events,
+                    server::send_moderation_events.after(server::send_disconnection_events),
                 ),
             )
+            .add_systems(
+                Update,
+                (
+                    server::enable_compression,
+                    latency::send_pings,
+                    latency::handle_pongs,
+                ),
+            )
+            .add_systems(Last, server::handle_disconnection_events)
             .listen_for_server_message::<messages::ClientFinishedLoading>()
+            .listen_for_server_message::<messages::EnableCompression>()
             .listen_for_server_message::<messages::RenderDistance>()
+            .listen_for_server_message::<messages::ChunkRequest>()
             .listen_for_server_message::<messages::PlayerCameraRotation>()
             .listen_for_server_message::<messages::PlayerPosition>()
             .listen_for_server_message::<messages::LeftClick>()
@@ -221,7 +408,10 @@ impl Plugin for ServerPlugin {
             .listen_for_server_message::<messages::InterfaceEquipItem>()
             .listen_for_server_message::<messages::InterfaceButtonPress>()
             .listen_for_server_message::<messages::InterfaceTextInput>()
-            .listen_for_server_message::<messages::AssetRequest>();
+            .listen_for_server_message::<messages::ChatMessageClient>()
+            .listen_for_server_message::<messages::AssetRequest>()
+            .listen_for_server_message::<messages::Pong>()
+            .listen_for_server_message::<messages::CutsceneEnd>();
     }
 }
 
@@ -231,23 +421,49 @@ pub struct ClientPlugin;
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
+        diagnostics::register_diagnostics(app);
         app.insert_resource(client::NetworkClient::new())
             .add_event::<ClientNetworkEvent>()
+            .add_event::<ServerStatusEvent>()
             .init_resource::<NetworkSettings>()
-            .add_systems(PreUpdate, client::handle_connection_event)
-            .add_systems(Update, client::handle_client_network_events)
+            .init_resource::<ServerLatency>()
+            .init_resource::<LanServers>()
+            .add_systems(
+                PreUpdate,
+                (
+                    client::handle_connection_event,
+                    client::send_status_events,
+                    lan::update_lan_servers,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    client::handle_client_network_events,
+                    client::enable_compression,
+                    latency::answer_pings,
+                ),
+            )
+            .listen_for_client_message::<messages::SessionToken>()
+            .listen_for_client_message::<messages::LoginQueue>()
             .listen_for_client_message::<messages::InterfaceTextBoxUpdate>()
             .listen_for_client_message::<messages::InterfaceVisibilityUpdate>()
+            .listen_for_client_message::<messages::InterfaceVariableUpdate>()
             .listen_for_client_message::<messages::InterfaceItemBoxUpdate>()
             .listen_for_client_message::<messages::InterfaceOpen>()
             .listen_for_client_message::<messages::InterfaceClose>()
             .listen_for_client_message::<messages::NewModel>()
             .listen_for_client_message::<messages::DeleteModel>()
             .listen_for_client_message::<messages::ModelUpdateTransform>()
+            .listen_for_client_message::<messages::ModelNameTag>()
+            .listen_for_client_message::<messages::ModelPlayAnimation>()
             .listen_for_client_message::<messages::ModelUpdateAsset>()
+            .listen_for_client_message::<messages::NewPainting>()
+            .listen_for_client_message::<messages::DeletePainting>()
             .listen_for_client_message::<messages::Chunk>()
             .listen_for_client_message::<messages::BlockUpdates>()
             .listen_for_client_message::<messages::ServerConfig>()
+            .listen_for_client_message::<messages::EffectiveRenderDistance>()
             .listen_for_client_message::<messages::AssetResponse>()
             .listen_for_client_message::<messages::Disconnect>()
             .listen_for_client_message::<messages::PlayerConfiguration>()
@@ -255,6 +471,12 @@ impl Plugin for ClientPlugin {
             .listen_for_client_message::<messages::PlayerPosition>()
             .listen_for_client_message::<messages::Sound>()
             .listen_for_client_message::<messages::EnableClientAudio>()
-            .listen_for_client_message::<messages::Time>();
+            .listen_for_client_message::<messages::Time>()
+            .listen_for_client_message::<messages::Weather>()
+            .listen_for_client_message::<messages::ServerStats>()
+            .listen_for_client_message::<messages::Ping>()
+            .listen_for_client_message::<messages::ChatMessageServer>()
+            .listen_for_client_message::<messages::Cutscene>()
+            .listen_for_client_message::<messages::CutsceneEnd>();
     }
 }
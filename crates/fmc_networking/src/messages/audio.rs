@@ -13,6 +13,9 @@ pub struct Sound {
     //
     /// Sound that should be played.
     pub sound: String,
+    /// How fast the sound is played, 1.0 is normal speed. The pitch is raised or lowered along
+    /// with it.
+    pub speed: f32,
 }
 
 /// For responsiveness the client is able to play the sound of walking on/in blocks, this allows the server
@@ -0,0 +1,79 @@
+use fmc_networking_derive::{ClientBound, NetworkMessage, ServerBound};
+use serde::{Deserialize, Serialize};
+
+/// Who a chat message is meant for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatChannel {
+    /// Everyone on the server.
+    Global,
+    /// Notices from the server, e.g. that a player joined.
+    System,
+    /// Players close to the sender.
+    Local,
+    /// Players on the same team as the sender.
+    Team,
+    /// A private message between two players, sent with the '/msg' command.
+    Whisper,
+}
+
+/// A chat message written by the player. Messages that start with a '/' are commands, they are
+/// handled by the server and not shown to other players.
+#[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessageClient {
+    /// Channel the player wants the message sent to.
+    pub channel: ChatChannel,
+    /// Content of the message.
+    pub message: String,
+}
+
+/// A chat message the client should show.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessageServer {
+    /// Channel the message was sent in.
+    pub channel: ChatChannel,
+    /// Name of the player that wrote it, None if it is from the server.
+    pub sender: Option<String>,
+    /// Content of the message, or a translation key if there are translation arguments.
+    pub message: String,
+    /// If set, the message is translated by the client and these replace the '{}'s of the
+    /// translation.
+    pub translation_args: Option<Vec<String>>,
+    /// Color of the text as hex, e.g. "#ffffff". The client picks one if it is not set.
+    pub color: Option<String>,
+}
+
+impl ChatMessageServer {
+    /// A message from a player.
+    pub fn from_player(channel: ChatChannel, sender: &str, message: &str) -> Self {
+        Self {
+            channel,
+            sender: Some(sender.to_owned()),
+            message: message.to_owned(),
+            translation_args: None,
+            color: None,
+        }
+    }
+
+    /// A notice from the server, translated by the client.
+    pub fn translated(key: &str, args: Vec<String>) -> Self {
+        Self {
+            channel: ChatChannel::System,
+            sender: None,
+            message: key.to_owned(),
+            translation_args: Some(args),
+            color: None,
+        }
+    }
+
+    /// Set the channel the message is shown as part of.
+    pub fn with_channel(mut self, channel: ChatChannel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Set the color of the text, as hex.
+    pub fn with_color(mut self, color: &str) -> Self {
+        self.color = Some(color.to_owned());
+        self
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use crate::BlockId;
 use bevy::prelude::*;
-use fmc_networking_derive::{ClientBound, NetworkMessage};
+use fmc_networking_derive::{ClientBound, NetworkMessage, ServerBound};
 use serde::{Deserialize, Serialize};
 
 /// A chunk of blocks sent to a client
@@ -22,4 +22,16 @@ pub struct Chunk {
     //      ^---centered
     //     ^----upside down
     pub block_state: HashMap<usize, u16>,
+    /// The biome id of each block column, indexed by x * CHUNK_SIZE + z. Which biome each id is
+    /// is sent in the [ServerConfig](super::ServerConfig).
+    pub biomes: Vec<u8>,
+}
+
+/// Sent by the client when it has been missing chunks it expected to receive for a while, e.g.
+/// because they were lost during a reconnect. The server sends them again if they are within the
+/// client's render distance.
+#[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkRequest {
+    /// Positions of the chunks
+    pub positions: Vec<IVec3>,
 }
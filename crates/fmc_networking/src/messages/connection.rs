@@ -3,7 +3,7 @@ use fmc_networking_derive::{ClientBound, NetworkMessage, ServerBound};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-use crate::BlockId;
+use crate::{messages::Hitbox, BlockId};
 
 /// Sent by client to notify the server that it has processed all assets and is ready to be served.
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
@@ -18,12 +18,28 @@ pub struct ServerConfig {
     pub block_ids: HashMap<String, BlockId>,
     /// Map from model name to id on the server.
     pub model_ids: HashMap<String, u32>,
+    /// Hitboxes of the models that can be interacted with, by model id.
+    pub model_hitboxes: HashMap<u32, Vec<Hitbox>>,
     /// Map from item name to id on the server.
     pub item_ids: HashMap<String, u32>,
+    /// Map from biome name to the id the chunks use for it.
+    pub biome_ids: HashMap<String, u8>,
     /// Maximum render distance allowed by server, measured in chunks.
     pub render_distance: u32,
+    /// If the server can send compressed packets. Clients that support it answer with
+    /// [EnableCompression].
+    pub compression: bool,
 }
 
+/// Sent by the client if it supports compression, after the server has said it does too.
+#[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct EnableCompression;
+
+/// Sent by both ends when they haven't sent anything for a while, so the other end knows the
+/// connection is still alive. It is dropped as soon as it is received.
+#[derive(NetworkMessage, ClientBound, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct KeepAlive;
+
 /// A request for the server to send less chunks than the maximum it can provide.
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
 pub struct RenderDistance {
@@ -31,18 +47,99 @@ pub struct RenderDistance {
     pub render_distance: u32,
 }
 
+/// The render distance the server will send chunks for. Sent in response to [RenderDistance], and
+/// whenever the server changes it, e.g. when it lowers it because it is under heavy load.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct EffectiveRenderDistance {
+    /// Render distance in chunks.
+    pub render_distance: u32,
+}
+
 /// Clients send this immediately on established connection to identify themselves.
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug)]
 pub struct ClientIdentification {
+    /// The [PROTOCOL_VERSION](super::PROTOCOL_VERSION) of the client. This must stay the first
+    /// field, the server reads it before trying to deserialize the rest of the message.
+    pub protocol_version: u32,
     /// The name the player wants to use.
     pub name: String,
+    /// Hash of the message registry, the client is disconnected if it doesn't match the server's.
+    pub message_registry_hash: u64,
+    /// Proof that the player owns the name.
+    pub credentials: Credentials,
+}
+
+impl ClientIdentification {
+    pub fn new(name: String, credentials: Credentials) -> Self {
+        Self {
+            protocol_version: super::PROTOCOL_VERSION,
+            name,
+            message_registry_hash: super::MESSAGE_REGISTRY_HASH,
+            credentials,
+        }
+    }
+}
+
+/// Ask the server about itself without logging in, e.g. to show it in the server list. It is sent
+/// instead of [ClientIdentification], the server answers with a [StatusResponse] and closes the
+/// connection.
+#[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct StatusRequest;
+
+/// Information about the server that can be shown before connecting. Clients of all versions must
+/// be able to read it, so fields can only be added at the end.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct StatusResponse {
+    /// The [PROTOCOL_VERSION](super::PROTOCOL_VERSION) of the server, clients with a different
+    /// version can't connect.
+    pub protocol_version: u32,
+    /// Version of the server, only for display.
+    pub version: String,
+    /// Message of the day. Parts of it can be colored by putting them after '[#rrggbb]', and
+    /// '[/]' goes back to the default color.
+    pub motd: String,
+    /// Number of players that are connected.
+    pub players: u32,
+    /// Hash of the server's icon, None if it doesn't have one.
+    pub icon_hash: Option<Vec<u8>>,
+    /// Base64 encoded png image of the server's icon.
+    pub icon: Option<String>,
+}
+
+/// How the client proves who it is.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Credentials {
+    /// The password of the account. If there is no account with the name, it is created.
+    Password(String),
+    /// A token from a [SessionToken] the server sent earlier.
+    SessionToken(String),
+}
+
+/// Sent to the client after it has been authenticated. The token can be used instead of the
+/// password the next time it connects to the server, it is only valid once.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct SessionToken {
+    pub token: String,
+}
+
+/// Sent to clients that have logged in while the server is full. They wait in line until someone
+/// leaves, and are sent a new one each time their place in it changes.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct LoginQueue {
+    /// How many are in line in front of the client, plus one.
+    pub position: u32,
 }
 
 /// Forceful disconnection by the server.
+///
+/// Clients of any version must be able to read this, so that they can be told why they were
+/// refused, don't change it.
 #[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug)]
 pub struct Disconnect {
-    /// Reason for the disconnect, optional
+    /// Reason for the disconnect, optional. This is a translation key if 'message_args' is set.
     pub message: String,
+    /// Arguments filled into the translation of the message.
+    pub message_args: Option<Vec<String>>,
 }
 
 // TODO: This is meant to be temporary. As day/night is defined client-side, the server only sends
@@ -54,6 +151,18 @@ pub struct Time {
     pub angle: f32,
 }
 
+/// Sets the weather. Sent when a client has finished loading and when it changes.
+#[derive(
+    NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+pub enum Weather {
+    #[default]
+    Clear,
+    Rain,
+    /// Heavier rain and a darker sky.
+    Storm,
+}
+
 /// A set of assets from the server
 #[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug)]
 pub struct AssetResponse {
@@ -64,3 +173,20 @@ pub struct AssetResponse {
 /// Sent by clients if they don't have assets (or the wrong ones).
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct AssetRequest;
+
+/// Sent periodically by the server to measure the round trip time of the connection. The client
+/// answers each one with a [Pong].
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct Ping {
+    /// Identifies the ping, the pong must have the same id.
+    pub id: u32,
+    /// The round trip time the server has measured so far, zero if it hasn't been measured yet.
+    pub round_trip: std::time::Duration,
+}
+
+/// Answer to a [Ping].
+#[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct Pong {
+    /// Id of the ping it answers.
+    pub id: u32,
+}
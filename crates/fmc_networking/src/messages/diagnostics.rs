@@ -0,0 +1,23 @@
+use fmc_networking_derive::{ClientBound, NetworkMessage};
+use serde::{Deserialize, Serialize};
+
+/// Performance statistics of the server, only sent to operators.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServerStats {
+    /// Average time spent on a tick since the last update, in milliseconds.
+    pub tick_time: f32,
+    /// The longest tick since the last update, in milliseconds.
+    pub max_tick_time: f32,
+    /// Time spent on each tick since the last update, in milliseconds, oldest first.
+    pub tick_times: Vec<f32>,
+    /// Number of chunks kept in memory.
+    pub loaded_chunks: u32,
+    /// Total number of entities.
+    pub entities: u32,
+    /// Number of players online.
+    pub players: u32,
+    /// Number of block changes waiting to be written to the database.
+    pub persistence_queue: u32,
+    /// How many times the game has had to wait for the database since the server started.
+    pub persistence_stalls: u64,
+}
@@ -178,6 +178,9 @@ pub struct Text {
     pub font_size: f32,
     // Hex, if it is malformed it will default to white.
     pub color: String,
+    // If set, 'text' is a translation key and these are the arguments that are filled into the
+    // translation by the client.
+    pub translation_args: Option<Vec<String>>,
 }
 
 // TODO: Same problem as above, should contain TextAlignment and BreakLineOn
@@ -193,6 +196,24 @@ impl Line {
             text,
             font_size,
             color: color.to_owned(),
+            translation_args: None,
+        });
+        self
+    }
+
+    /// Text that is translated by the client, the arguments replace the '{}'s of the translation.
+    pub fn with_translation(
+        &mut self,
+        key: &str,
+        args: Vec<String>,
+        font_size: f32,
+        color: &str,
+    ) -> &mut Self {
+        self.sections.push(Text {
+            text: key.to_owned(),
+            font_size,
+            color: color.to_owned(),
+            translation_args: Some(args),
         });
         self
     }
@@ -256,3 +277,23 @@ pub struct InterfaceTextInput {
     /// The content of the textbox
     pub text: String,
 }
+
+/// Set the variables that interface nodes are bound to. Text nodes fill their translation with
+/// them, and bars are filled by how large the value is compared to their max. Variables are shared
+/// by all interfaces, so several nodes can show the same value.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InterfaceVariableUpdate {
+    /// List of (variable name, value).
+    pub updates: Vec<(String, String)>,
+}
+
+impl InterfaceVariableUpdate {
+    pub fn set_text(&mut self, name: &str, value: &str) {
+        self.updates.push((name.to_owned(), value.to_owned()));
+    }
+
+    /// Numbers are sent as text, bars read them back from it.
+    pub fn set_number(&mut self, name: &str, value: f32) {
+        self.updates.push((name.to_owned(), value.to_string()));
+    }
+}
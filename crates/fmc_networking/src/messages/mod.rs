@@ -1,13 +1,14 @@
 /// Everything that happens on connection and disconnection
 mod connection;
 pub use connection::{
-    AssetRequest, AssetResponse, ClientFinishedLoading, ClientIdentification, Disconnect,
-    RenderDistance, ServerConfig, Time,
+    AssetRequest, AssetResponse, ClientFinishedLoading, ClientIdentification, Credentials,
+    Disconnect, EffectiveRenderDistance, EnableCompression, KeepAlive, LoginQueue, Ping, Pong,
+    RenderDistance, ServerConfig, SessionToken, StatusRequest, StatusResponse, Time, Weather,
 };
 
 /// Chunk management
 mod chunk;
-pub use chunk::Chunk;
+pub use chunk::{Chunk, ChunkRequest};
 
 /// Individual changes to blocks
 mod blocks;
@@ -15,21 +16,136 @@ pub use blocks::BlockUpdates;
 
 /// Things like players, the sun/skybox, arrows. Everything that is not a block.
 mod models;
-pub use models::{DeleteModel, ModelUpdateAsset, ModelUpdateTransform, NewModel};
+pub use models::{
+    DeleteModel, DeletePainting, Hitbox, ModelNameTag, ModelPlayAnimation, ModelUpdateAsset,
+    ModelUpdateTransform, NewModel, NewPainting,
+};
 
 /// Changes to the player.
 mod player;
 pub use player::{
-    LeftClick, PlayerCameraRotation, PlayerConfiguration, PlayerPosition, RightClick,
+    CameraKeyframe, Cutscene, CutsceneEnd, LeftClick, PlayerCameraRotation, PlayerConfiguration,
+    PlayerPosition, RightClick,
 };
 
+/// Chat between players, and messages from the server.
+mod chat;
+pub use chat::{ChatChannel, ChatMessageClient, ChatMessageServer};
+
 /// User interface
 mod interfaces;
 pub use interfaces::{
     InterfaceButtonPress, InterfaceClose, InterfaceEquipItem, InterfaceItemBoxUpdate,
     InterfaceOpen, InterfacePlaceItem, InterfaceTakeItem, InterfaceTextBoxUpdate,
-    InterfaceTextInput, InterfaceVisibilityUpdate,
+    InterfaceTextInput, InterfaceVariableUpdate, InterfaceVisibilityUpdate,
 };
 
 mod audio;
 pub use audio::{EnableClientAudio, Sound};
+
+/// Server performance, for operators
+mod diagnostics;
+pub use diagnostics::ServerStats;
+
+/// Assigns each message an id by the order it is listed in.
+macro_rules! message_registry {
+    (@id $id:expr;) => {};
+    (@id $id:expr; $message:ident, $($rest:ident,)*) => {
+        impl crate::network_message::MessageId for $message {
+            const ID: u16 = $id;
+        }
+        message_registry!(@id $id + 1; $($rest,)*);
+    };
+    ($($message:ident),* $(,)?) => {
+        /// Names of the messages, indexed by id.
+        pub(crate) const MESSAGE_NAMES: &[&str] = &[$(stringify!($message)),*];
+        message_registry!(@id 0; $($message,)*);
+    };
+}
+
+// Messages are sent with a numeric id instead of their name. Both ends of a connection must agree
+// on the ids, so the client sends a hash of this list when it connects, and the server refuses
+// it if it doesn't match its own.
+//
+// The first four must stay in place, they are needed before it is known if the ids match.
+// New messages should be added at the end.
+message_registry! {
+    ClientIdentification,
+    Disconnect,
+    StatusRequest,
+    StatusResponse,
+    ClientFinishedLoading,
+    ServerConfig,
+    RenderDistance,
+    EffectiveRenderDistance,
+    Time,
+    AssetRequest,
+    AssetResponse,
+    Chunk,
+    BlockUpdates,
+    NewModel,
+    DeleteModel,
+    ModelUpdateAsset,
+    ModelUpdateTransform,
+    NewPainting,
+    DeletePainting,
+    PlayerConfiguration,
+    PlayerPosition,
+    PlayerCameraRotation,
+    LeftClick,
+    RightClick,
+    ChatMessageClient,
+    ChatMessageServer,
+    InterfaceOpen,
+    InterfaceClose,
+    InterfaceVisibilityUpdate,
+    InterfaceButtonPress,
+    InterfaceItemBoxUpdate,
+    InterfaceTakeItem,
+    InterfacePlaceItem,
+    InterfaceEquipItem,
+    InterfaceTextBoxUpdate,
+    InterfaceTextInput,
+    EnableClientAudio,
+    Sound,
+    ServerStats,
+    ChunkRequest,
+    EnableCompression,
+    SessionToken,
+    Ping,
+    Pong,
+    Cutscene,
+    CutsceneEnd,
+    InterfaceVariableUpdate,
+    LoginQueue,
+    ModelNameTag,
+    KeepAlive,
+    ModelPlayAnimation,
+    Weather,
+}
+
+/// Version of the network protocol. It must be increased whenever a message is changed in a way
+/// that makes it unreadable to the other end, e.g. when a field is added. Adding or removing
+/// messages is caught by the [MESSAGE_REGISTRY_HASH] and doesn't need a new version.
+pub const PROTOCOL_VERSION: u32 = 11;
+
+/// Hash of the message registry, clients with a different hash can't understand the server.
+pub(crate) const MESSAGE_REGISTRY_HASH: u64 = {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < MESSAGE_NAMES.len() {
+        let name = MESSAGE_NAMES[i].as_bytes();
+        let mut j = 0;
+        while j < name.len() {
+            hash ^= name[j] as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+            j += 1;
+        }
+        // Separator so moving a letter between two names changes the hash.
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+};
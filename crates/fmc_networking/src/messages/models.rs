@@ -24,6 +24,21 @@ pub struct NewModel {
     pub idle_animation: Option<u32>,
     /// Index of animation to use when model is moving.
     pub moving_animation: Option<u32>,
+    /// Color the model is multiplied by, in srgb. The alpha makes the model transparent, 1.0 is
+    /// opaque.
+    pub tint: Vec4,
+}
+
+/// A part of a model that can be interacted with, e.g. the head of a player. The box is in the
+/// model's own coordinates, before it is moved, rotated and scaled.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Hitbox {
+    /// Name of the part it covers.
+    pub name: String,
+    /// Corner with the lowest coordinates.
+    pub min: Vec3,
+    /// Corner with the highest coordinates.
+    pub max: Vec3,
 }
 
 /// Delete an existing model.
@@ -33,7 +48,7 @@ pub struct DeleteModel {
     pub id: u32,
 }
 
-/// Update the asset used by a model.
+/// Update the asset used by a model, and how it is drawn.
 #[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
 pub struct ModelUpdateAsset {
     /// Id of the model.
@@ -44,13 +59,19 @@ pub struct ModelUpdateAsset {
     pub idle_animation: Option<u32>,
     /// Index of animation to use when model is moving.
     pub moving_animation: Option<u32>,
+    /// Color the model is multiplied by, see [NewModel::tint].
+    pub tint: Vec4,
 }
 
-/// Update the transform of a model.
+/// Update the transform of a model. Models are only updated when they move, so a model that
+/// hasn't been updated for a while has been standing still.
 #[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
 pub struct ModelUpdateTransform {
     /// Id of the model.
     pub id: u32,
+    /// The server tick the transform is from. Ticks count up by one for each tick, the client
+    /// can use them to interpolate between updates at the pace they happened on the server.
+    pub tick: u32,
     /// Updated position.
     pub position: DVec3,
     /// Updated rotation.
@@ -58,3 +79,54 @@ pub struct ModelUpdateTransform {
     /// Updated scale.
     pub scale: Vec3,
 }
+
+/// Show a name above a model, e.g. the name of a player.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct ModelNameTag {
+    /// Id of the model.
+    pub id: u32,
+    /// The name that is shown, an empty name removes the name tag.
+    pub name: String,
+    /// Color of the name, as a hex string e.g. "#ffffff".
+    pub color: String,
+    /// Show the name tag however far away the model is, otherwise it is only shown when the
+    /// model is close.
+    pub always_visible: bool,
+}
+
+/// Play an animation on a model, e.g. an emote on a player model.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct ModelPlayAnimation {
+    /// Id of the model.
+    pub id: u32,
+    /// Name of the animation in the model's asset. An empty name stops the animation that is
+    /// playing and returns the model to its first frame.
+    pub animation: String,
+    /// Play the animation until it is stopped, instead of only once.
+    pub repeat: bool,
+}
+
+/// Spawn a painting, a flat picture hung on a wall.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct NewPainting {
+    /// Id used to reference it when deleting. Paintings have their own ids separate from models.
+    pub id: u32,
+    /// Name of the painting, the texture is at 'textures/paintings/{name}.png' in the resource
+    /// pack.
+    pub name: String,
+    /// The lower left block of the space the painting covers, as seen when looking at it.
+    pub position: IVec3,
+    /// Direction the painting faces, out of the wall. Always horizontal.
+    pub normal: IVec3,
+    /// Width in blocks
+    pub width: u32,
+    /// Height in blocks
+    pub height: u32,
+}
+
+/// Delete an existing painting.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct DeletePainting {
+    /// Id used to register the painting.
+    pub id: u32,
+}
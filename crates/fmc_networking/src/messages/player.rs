@@ -9,6 +9,9 @@ pub struct PlayerConfiguration {
     pub camera_position: Vec3,
     /// How large the player's AABB should be.
     pub aabb_dimensions: Vec3,
+    /// Time between swings while the left mouse button is held, zero if the player only swings
+    /// once per click. The hand animation should follow it.
+    pub swing_interval: std::time::Duration,
 }
 
 // TODO: This doesn't need to be f64, the server can just convert it. The velocity is also only
@@ -17,6 +20,9 @@ pub struct PlayerConfiguration {
 /// A player's position. Used by client to report its position or for the server to dictate.
 #[derive(NetworkMessage, ClientBound, ServerBound, Serialize, Deserialize, Debug, Clone)]
 pub struct PlayerPosition {
+    /// From the server, the tick the position was set on. From the client, the server tick it
+    /// was showing when the player moved, so the server knows what the player could see.
+    pub tick: u32,
     /// Position of the player.
     pub position: DVec3,
     /// Velocity of the player
@@ -30,17 +36,63 @@ pub struct PlayerCameraRotation {
     pub rotation: Quat,
 }
 
-/// Send a left click to the server
+/// Sent when the left mouse button is pressed and released. While it is held the player mines the
+/// block it is looking at, and keeps swinging.
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
-pub struct LeftClick;
+pub enum LeftClick {
+    /// The button was pressed.
+    Press,
+    /// The button was released, or the player stopped using it, e.g. by opening an interface.
+    Release,
+}
 
 /// Send a right click to the server.
 #[derive(NetworkMessage, ServerBound, Serialize, Deserialize, Debug, Clone)]
 pub struct RightClick;
 
-/// A chat message, sent by either the client or the server.
-#[derive(NetworkMessage, ClientBound, ServerBound, Serialize, Deserialize, Clone, Debug)]
-pub struct ChatMessage {
-    /// Content of the message.
-    pub message: String,
+/// A point on the camera's path through a cutscene.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CameraKeyframe {
+    /// Time from the start of the cutscene.
+    pub time: std::time::Duration,
+    /// Position of the camera, it stays with the player if not set.
+    pub position: Option<DVec3>,
+    /// Rotation of the camera, it looks where the player looks if not set.
+    pub rotation: Option<Quat>,
+    /// How far the screen is faded to black, from 0.0 to 1.0.
+    pub fade: f32,
 }
+
+/// Takes control of the player's camera. The camera is moved between the keyframes, and control
+/// is given back after the last one. Between two keyframes the position, rotation and fade are
+/// interpolated, unless one of them is unset, then the value of the first is kept.
+///
+/// The player can always skip a cutscene by pausing the game, and the client ends it after
+/// [Cutscene::MAX_DURATION] no matter how long it is. The client sends a [CutsceneEnd] when
+/// either happens.
+#[derive(NetworkMessage, ClientBound, Serialize, Deserialize, Debug, Clone)]
+pub struct Cutscene {
+    /// Keyframes sorted by time.
+    pub keyframes: Vec<CameraKeyframe>,
+    /// If the player can't move, look around or click while it plays.
+    pub lock_input: bool,
+}
+
+impl Cutscene {
+    /// Longest a cutscene can play for.
+    pub const MAX_DURATION: std::time::Duration = std::time::Duration::from_secs(60);
+
+    /// How long the cutscene lasts, this is the time of the last keyframe.
+    pub fn duration(&self) -> std::time::Duration {
+        self.keyframes
+            .last()
+            .map(|keyframe| keyframe.time)
+            .unwrap_or_default()
+            .min(Self::MAX_DURATION)
+    }
+}
+
+/// Ends a cutscene before its last keyframe. Sent by the server to stop it, and by the client when
+/// it was skipped or took too long.
+#[derive(NetworkMessage, ClientBound, ServerBound, Serialize, Deserialize, Debug, Clone)]
+pub struct CutsceneEnd;
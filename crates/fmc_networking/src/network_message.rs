@@ -1,28 +1,38 @@
 use downcast_rs::DowncastSync;
+use serde::de::DeserializeOwned;
 
-#[typetag::serde(tag = "type")]
 /// Any type that should be sent over the wire has to implement [`NetworkMessage`].
 ///
 /// ## Example
-/// ```rust
-/// use fmc_networking::NetworkMessage;
+/// ```ignore
+/// use fmc_networking_derive::{ClientBound, NetworkMessage};
 /// use serde::{Serialize, Deserialize};
 ///
-/// #[derive(Serialize, Deserialize)]
+/// #[derive(NetworkMessage, ClientBound, Serialize, Deserialize)]
 /// struct PlayerInformation {
 ///     health: usize,
 ///     position: (u32, u32, u32)
 /// }
-///
-/// #[typetag::serde]
-/// impl NetworkMessage for PlayerInformation {}
 /// ```
 /// You will also have to mark it with either [`ServerBound`] or [`ClientBound`] (or both)
-/// to signal which direction this message can be sent.
-pub trait NetworkMessage: DowncastSync {}
+/// to signal which direction this message can be sent, and add it to the message registry in
+/// `messages/mod.rs` so it is given an id.
+pub trait NetworkMessage: DowncastSync {
+    /// Size of the message when serialized, not including its id.
+    fn serialized_size(&self) -> bincode::Result<u64>;
+    /// Serialize the message into the buffer, it must be at least 'serialized_size' long.
+    fn serialize_into(&self, buffer: &mut [u8]) -> bincode::Result<()>;
+}
 
 downcast_rs::impl_downcast!(sync NetworkMessage);
 
+/// The id a message is identified by on the wire, instead of its name. Ids are assigned by the
+/// message registry in `messages/mod.rs`, by the order the messages are listed in.
+pub trait MessageId {
+    /// Id of the message
+    const ID: u16;
+}
+
 /**
 A marker trait to signal that this message should be sent *to* a server
 
@@ -30,10 +40,8 @@ A marker trait to signal that this message should be sent *to* a server
 
 You can implement both [`ServerMessage`] and [`ClientMessage`]
 */
-pub trait ServerBound: NetworkMessage {
-    /// A unique name to identify your message, this needs to be unique __across all included crates__
-    ///
-    /// A good combination is crate name + struct name
+pub trait ServerBound: NetworkMessage + MessageId + DeserializeOwned {
+    /// Name of the message, used for debugging.
     const NAME: &'static str;
 }
 
@@ -44,9 +52,17 @@ A marker trait to signal that this message should be sent *to* a client
 
 You can implement both [`ClientMessage`] and [`ServerMessage`]
 */
-pub trait ClientBound: NetworkMessage {
-    /// A unique name to identify your message, this needs to be unique __across all included crates__
-    ///
-    /// A good combination is crate name + struct name
+pub trait ClientBound: NetworkMessage + MessageId + DeserializeOwned {
+    /// Name of the message, used for debugging.
     const NAME: &'static str;
 }
+
+/// Deserializes a message of a known type, used to decode packets once their id has been read.
+pub(crate) type DeserializeFn = fn(&[u8]) -> bincode::Result<Box<dyn NetworkMessage>>;
+
+pub(crate) fn deserialize_message<T: NetworkMessage + DeserializeOwned>(
+    bytes: &[u8],
+) -> bincode::Result<Box<dyn NetworkMessage>> {
+    let message: T = bincode::deserialize(bytes)?;
+    return Ok(Box::new(message));
+}
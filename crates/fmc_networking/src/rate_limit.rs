@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+
+// A client is only considered to have stopped exceeding the limits once it has gone this long
+// without a message being dropped.
+const CALM_DURATION: Duration = Duration::from_secs(1);
+
+/// How often a client can send a kind of message, set with
+/// [NetworkServer::set_rate_limit](crate::NetworkServer::set_rate_limit).
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// How many messages can be sent per second on average.
+    pub per_second: f32,
+    /// How many messages can be sent at once, after the client has been quiet for a while.
+    pub burst: f32,
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f32,
+    last_update: Instant,
+}
+
+/// Keeps track of how many messages a connection has sent. Messages over the limit are dropped,
+/// and if it keeps exceeding them it should be disconnected.
+pub(crate) struct RateLimiter {
+    buckets: HashMap<u16, Bucket>,
+    timeout: Duration,
+    // When the client started exceeding the limits, and when a message was last dropped.
+    exceeding: Option<(Instant, Instant)>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limits: &DashMap<u16, RateLimit>, timeout: Duration) -> Self {
+        let now = Instant::now();
+        let buckets = limits
+            .iter()
+            .map(|entry| {
+                let limit = *entry.value();
+                (
+                    *entry.key(),
+                    Bucket {
+                        limit,
+                        tokens: limit.burst,
+                        last_update: now,
+                    },
+                )
+            })
+            .collect();
+
+        return Self {
+            buckets,
+            timeout,
+            exceeding: None,
+        };
+    }
+
+    /// Returns if the message should be handled. Messages without a limit are always allowed.
+    pub(crate) fn allow(&mut self, message_id: u16) -> bool {
+        let Some(bucket) = self.buckets.get_mut(&message_id) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_update).as_secs_f32();
+        bucket.tokens =
+            (bucket.tokens + elapsed * bucket.limit.per_second).min(bucket.limit.burst.max(1.0));
+        bucket.last_update = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return true;
+        }
+
+        self.exceeding = match self.exceeding {
+            Some((since, last_drop)) if now.duration_since(last_drop) < CALM_DURATION => {
+                Some((since, now))
+            }
+            _ => Some((now, now)),
+        };
+
+        return false;
+    }
+
+    /// If the client has exceeded the limits for so long it should be disconnected.
+    pub(crate) fn should_disconnect(&self) -> bool {
+        return self
+            .exceeding
+            .is_some_and(|(since, last_drop)| last_drop.duration_since(since) >= self.timeout);
+    }
+}
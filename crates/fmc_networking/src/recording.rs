@@ -0,0 +1,133 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use bevy::log::error;
+
+use crate::messages;
+
+// A recording starts with this, followed by the protocol version and the hash of the message
+// registry it was recorded with. Then each packet follows as the microseconds since the recording
+// started, the length of the packet, and the packet itself. All numbers are big endian.
+//
+// The packets are stored uncompressed, as the message id followed by the message.
+const MAGIC: &[u8; 8] = b"fmcrecv1";
+
+/// Writes the packets the client receives to a file, so the session can be replayed later with
+/// [NetworkClient::replay](crate::NetworkClient::replay).
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+    failed: bool,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&messages::PROTOCOL_VERSION.to_be_bytes())?;
+        writer.write_all(&messages::MESSAGE_REGISTRY_HASH.to_be_bytes())?;
+
+        return Ok(Self {
+            writer,
+            start: Instant::now(),
+            failed: false,
+        });
+    }
+
+    /// Record a packet, it must include the message id.
+    pub(crate) fn record(&mut self, packet: &[u8]) {
+        // A recording with holes in it would replay something that never happened.
+        if self.failed {
+            return;
+        }
+
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        let result = self
+            .writer
+            .write_all(&timestamp.to_be_bytes())
+            .and_then(|_| self.writer.write_all(&(packet.len() as u32).to_be_bytes()))
+            .and_then(|_| self.writer.write_all(packet));
+
+        if let Err(err) = result {
+            error!("Failed to record packet, the recording is stopped: {}", err);
+            self.failed = true;
+        }
+    }
+}
+
+/// Reads the packets of a recording in the order they were received.
+#[derive(Debug)]
+pub(crate) struct Replay {
+    reader: BufReader<File>,
+    max_packet_length: usize,
+}
+
+impl Replay {
+    pub(crate) fn open(path: &Path, max_packet_length: usize) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a network recording".to_owned()));
+        }
+
+        let mut protocol_version = [0; 4];
+        reader.read_exact(&mut protocol_version)?;
+        let protocol_version = u32::from_be_bytes(protocol_version);
+        let mut registry_hash = [0; 8];
+        reader.read_exact(&mut registry_hash)?;
+        let registry_hash = u64::from_be_bytes(registry_hash);
+
+        if protocol_version != messages::PROTOCOL_VERSION
+            || registry_hash != messages::MESSAGE_REGISTRY_HASH
+        {
+            return Err(invalid_data(format!(
+                "recorded with protocol version {}, this is version {}, or the messages have changed since",
+                protocol_version,
+                messages::PROTOCOL_VERSION
+            )));
+        }
+
+        return Ok(Self {
+            reader,
+            max_packet_length,
+        });
+    }
+
+    /// The next packet and when it was received, relative to the start of the recording. None at
+    /// the end of the recording.
+    pub(crate) fn next_packet(&mut self) -> std::io::Result<Option<(Duration, Vec<u8>)>> {
+        let mut timestamp = [0; 8];
+        match self.reader.read_exact(&mut timestamp) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let timestamp = Duration::from_micros(u64::from_be_bytes(timestamp));
+
+        let mut length = [0; 4];
+        self.reader.read_exact(&mut length)?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length > self.max_packet_length {
+            return Err(invalid_data(format!(
+                "packet of length {} is larger than the max packet length",
+                length
+            )));
+        }
+
+        let mut packet = vec![0; length];
+        self.reader.read_exact(&mut packet)?;
+
+        return Ok(Some((timestamp, packet)));
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    return std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+}
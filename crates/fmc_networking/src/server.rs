@@ -1,10 +1,17 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use bevy::prelude::*;
 use dashmap::DashMap;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::{tcp, TcpListener, TcpStream, ToSocketAddrs},
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
     runtime::Runtime,
     // TODO: Switch to unbounded so sending is not blocked on the server. It was like this, but
     // there was some unknown memory leak. related perhaps
@@ -12,29 +19,146 @@ use tokio::{
     // still leaks though, just less maybe a bevy issue cause the chunk generator task also blows
     // up a little.
     //sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-    sync::mpsc::{channel, Receiver, Sender},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        watch, OwnedSemaphorePermit, Semaphore,
+    },
     task::JoinHandle,
 };
+use tokio_rustls::TlsAcceptor;
 
 use crate::{
-    messages::ClientIdentification,
-    network_message::{ClientBound, NetworkMessage, ServerBound},
-    ConnectionId, NetworkData, NetworkPacket, NetworkSettings, ServerNetworkEvent, SyncChannel,
+    auth::{self, AccountStorage, Authenticator},
+    bans::{Ban, BanList, BanStorage},
+    compress_packet, decompress_packet,
+    diagnostics::{ConnectionTraffic, NetworkDiagnostics},
+    lan,
+    latency::Latency,
+    messages::{self, ClientIdentification},
+    network_message::{self, ClientBound, DeserializeFn, MessageId, NetworkMessage, ServerBound},
+    rate_limit::{RateLimit, RateLimiter},
+    read_exact_with_timeout, tls,
+    transport::{self, BoxedSocket, Transport},
+    with_timeout, ConnectionId, NetworkData, NetworkPacket, NetworkSettings, ServerNetworkEvent,
+    SyncChannel, COMPRESSED_FLAG, LENGTH_PREFIX_SIZE,
 };
 
 struct NewConnection {
-    socket: TcpStream,
+    socket: BoxedSocket,
+    addr: SocketAddr,
     username: String,
+    player_id: String,
+    /// Issued if the client was authenticated.
+    session_token: Option<String>,
+    /// The connection slot the client was given, if the number of connections is limited.
+    slot: Option<OwnedSemaphorePermit>,
+}
+
+// How often clients waiting in the login queue are told their position. It also finds the ones
+// that have left, as writing to them fails.
+const QUEUE_UPDATE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Limits how many clients can be connected at once, see [NetworkSettings::max_connections].
+/// Clients that log in while all the slots are taken get one in the order they arrived.
+struct ConnectionSlots {
+    slots: Arc<Semaphore>,
+    /// Tickets of the clients that are waiting for a slot, first in line first.
+    waiting: Mutex<VecDeque<u64>>,
+    next_ticket: AtomicU64,
 }
 
+impl ConnectionSlots {
+    fn new(max_connections: usize) -> Self {
+        return Self {
+            slots: Arc::new(Semaphore::new(max_connections)),
+            waiting: Mutex::new(VecDeque::new()),
+            next_ticket: AtomicU64::new(0),
+        };
+    }
+
+    /// Waits until a slot is free, telling the client its position in the queue meanwhile.
+    /// Returns None if the client left before it got one.
+    async fn wait_for_slot(&self, socket: &mut BoxedSocket) -> Option<OwnedSemaphorePermit> {
+        if let Ok(slot) = self.slots.clone().try_acquire_owned() {
+            return Some(slot);
+        }
+
+        // The lock is only poisoned if another client's task panicked while holding it, the
+        // queue can't be trusted after that so the client is turned away.
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        self.waiting.lock().ok()?.push_back(ticket);
+        let _place = QueuePlace {
+            waiting: &self.waiting,
+            ticket,
+        };
+
+        // The semaphore hands out slots in the order they were asked for, so the same request has
+        // to be waited on the whole time.
+        let mut acquire = std::pin::pin!(self.slots.clone().acquire_owned());
+
+        loop {
+            let position = self
+                .waiting
+                .lock()
+                .ok()?
+                .iter()
+                .position(|waiting| *waiting == ticket)?;
+            let queue_position = NetworkPacket::new(messages::LoginQueue {
+                position: position as u32 + 1,
+            });
+            if !write_packet(socket, queue_position).await {
+                return None;
+            }
+
+            match tokio::time::timeout(QUEUE_UPDATE_INTERVAL, &mut acquire).await {
+                Ok(Ok(slot)) => return Some(slot),
+                Ok(Err(_)) => return None,
+                Err(_) => continue,
+            }
+        }
+    }
+}
+
+// Takes the client out of the queue when it stops waiting, both when it gets a slot and when it
+// leaves.
+struct QueuePlace<'a> {
+    waiting: &'a Mutex<VecDeque<u64>>,
+    ticket: u64,
+}
+
+impl Drop for QueuePlace<'_> {
+    fn drop(&mut self) {
+        let Ok(mut waiting) = self.waiting.lock() else {
+            return;
+        };
+        if let Some(index) = waiting.iter().position(|waiting| *waiting == self.ticket) {
+            waiting.remove(index);
+        }
+    }
+}
+
+type MessageSender = crossbeam_channel::Sender<Box<dyn NetworkMessage>>;
+type MessageReceiver = crossbeam_channel::Receiver<Box<dyn NetworkMessage>>;
+
+/// The messages received from a connection, with one channel for each kind of message. It is
+/// attached to the connection's entity, so the messages are dropped with it when the client
+/// disconnects, instead of lingering in a shared queue.
+#[derive(Component)]
+pub(crate) struct ConnectionMessages(HashMap<u16, MessageReceiver>);
+
 /// An established connection
 pub struct ClientConnection {
     username: String,
+    player_id: String,
     id: ConnectionId,
     receive_task: JoinHandle<()>,
     send_task: JoinHandle<()>,
     send_message: Sender<NetworkPacket>,
+    /// If packets sent to the client should be compressed.
+    compression: Arc<AtomicBool>,
     addr: SocketAddr,
+    /// Frees the slot for the next client in the login queue when the connection is dropped.
+    _slot: Option<OwnedSemaphorePermit>,
 }
 
 impl ClientConnection {
@@ -48,25 +172,54 @@ impl std::fmt::Debug for ClientConnection {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ClientConnection")
             .field("username", &self.username)
+            .field("player_id", &self.player_id)
             .field("id", &self.id)
             .field("addr", &self.addr)
             .finish()
     }
 }
 
+/// What the server tells clients that ask about it before connecting, set with
+/// [NetworkServer::set_status].
+#[derive(Debug, Clone, Default)]
+pub struct ServerStatus {
+    /// Message of the day.
+    pub motd: String,
+    /// Version of the server, only for display.
+    pub version: String,
+    /// Hash of the server's icon, if it has one.
+    pub icon_hash: Option<Vec<u8>>,
+    /// Base64 encoded png image of the server's icon. It must fit in the status response along
+    /// with the rest, so it should be small.
+    pub icon: Option<String>,
+}
+
 /// An instance of a [`NetworkServer`] is used to listen for new client connections
 /// using [`NetworkServer::listen`]
 #[derive(Resource)]
 pub struct NetworkServer {
     runtime: Option<Runtime>,
-    /// Map of network messages that should be sent as bevy events
-    recv_message_map: Arc<DashMap<&'static str, Vec<(ConnectionId, Box<dyn NetworkMessage>)>>>,
+    /// How to deserialize the messages that are listened for, by message id
+    message_deserializers: Arc<DashMap<u16, DeserializeFn>>,
+    /// How often clients can send each kind of message, by message id
+    rate_limits: Arc<DashMap<u16, RateLimit>>,
     /// Map of served connections
     established_connections: Arc<DashMap<ConnectionId, ClientConnection>>,
     /// Connections that have been verified and should be added to the established_connections map.
     new_connections: SyncChannel<NewConnection>,
     /// Connections that should be disconnected.
     disconnected_connections: SyncChannel<ConnectionId>,
+    /// Verifies the identity of new connections. If not set, clients can use any name.
+    authenticator: Option<Arc<Authenticator>>,
+    /// Sent to clients that ask for the status of the server.
+    status: watch::Sender<ServerStatus>,
+    /// Players and addresses that are not allowed to connect.
+    bans: BanList,
+    /// Connections that have been kicked, with the reason. They are disconnected a tick later so
+    /// the reason has time to reach the client.
+    kicked_connections: SyncChannel<(ConnectionId, String)>,
+    /// Changes to the bans that should be sent as events.
+    ban_events: SyncChannel<ServerNetworkEvent>,
 }
 
 impl std::fmt::Debug for NetworkServer {
@@ -83,20 +236,79 @@ impl NetworkServer {
     pub(crate) fn new() -> NetworkServer {
         NetworkServer {
             runtime: None,
-            recv_message_map: Arc::new(DashMap::new()),
+            message_deserializers: Arc::new(DashMap::new()),
+            rate_limits: Arc::new(DashMap::new()),
             established_connections: Arc::new(DashMap::new()),
             new_connections: SyncChannel::new(),
             disconnected_connections: SyncChannel::new(),
+            authenticator: None,
+            status: watch::channel(ServerStatus::default()).0,
+            bans: BanList::default(),
+            kicked_connections: SyncChannel::new(),
+            ban_events: SyncChannel::new(),
         }
     }
 
+    /// Require clients to log in to the accounts kept in the storage. Takes effect the next time
+    /// [`NetworkServer::listen`] is called.
+    pub fn set_account_storage(&mut self, storage: impl AccountStorage) {
+        self.authenticator = Some(Authenticator::new(storage));
+    }
+
+    /// Keep the bans in the storage, the bans it already has are loaded and replace the current
+    /// ones.
+    pub fn set_ban_storage(&mut self, storage: impl BanStorage) -> Result<(), String> {
+        return self.bans.set_storage(storage);
+    }
+
+    /// Set the status that is shown in the server list of clients. Takes effect immediately.
+    pub fn set_status(&self, status: ServerStatus) {
+        self.status.send_replace(status);
+    }
+
+    /// Limit how often clients can send a kind of message. Messages over the limit are dropped,
+    /// and clients that keep exceeding it are disconnected, see
+    /// [NetworkSettings::rate_limit_timeout]. Only applies to clients that connect after it is
+    /// set.
+    pub fn set_rate_limit<T: ServerBound>(&self, limit: RateLimit) {
+        self.rate_limits.insert(T::ID, limit);
+    }
+
     /// Start listening for new clients
     ///
     /// ## Note
     /// If you are already listening for new connections, then this will disconnect existing connections first
-    pub fn listen(&mut self, addr: impl ToSocketAddrs + Send + 'static) {
+    pub fn listen(
+        &mut self,
+        addr: impl ToSocketAddrs + Send + 'static,
+        network_settings: &NetworkSettings,
+    ) {
         self.stop();
 
+        let tls_acceptor = match network_settings.tls.as_ref().map(tls::acceptor) {
+            Some(Ok(acceptor)) => Some(acceptor),
+            Some(Err(err)) => {
+                error!("Could not set up encryption, Error: {}", err);
+                return;
+            }
+            None => None,
+        };
+
+        #[cfg(not(feature = "websocket"))]
+        if network_settings.transport == Transport::WebSocket {
+            error!("Could not listen for WebSocket connections, the 'websocket' feature is not enabled");
+            return;
+        }
+        let transport = network_settings.transport;
+
+        #[cfg(not(feature = "upnp"))]
+        if network_settings.upnp {
+            error!("Could not forward the port with UPnP, the 'upnp' feature is not enabled");
+        }
+        #[cfg(feature = "upnp")]
+        let upnp = network_settings.upnp;
+        let lan_discovery = network_settings.lan_discovery;
+
         let runtime = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
@@ -104,6 +316,12 @@ impl NetworkServer {
 
         // Notify of new connection after it's been verified.
         let new_connections = self.new_connections.sender.clone();
+        let authenticator = self.authenticator.clone();
+        let status = self.status.subscribe();
+        let established_connections = self.established_connections.clone();
+        let connection_slots = network_settings
+            .max_connections
+            .map(|max_connections| Arc::new(ConnectionSlots::new(max_connections)));
 
         // Listen for new connections at the bind address
         let listen_loop = async move {
@@ -115,6 +333,23 @@ impl NetworkServer {
                 }
             };
 
+            let port = match listener.local_addr() {
+                Ok(addr) => addr.port(),
+                Err(err) => {
+                    error!("Could not read the listen address, Error: {}", err);
+                    return;
+                }
+            };
+
+            if lan_discovery {
+                tokio::spawn(lan::announce(port, status.clone()));
+            }
+
+            #[cfg(feature = "upnp")]
+            if upnp {
+                tokio::spawn(crate::upnp::forward_port(port));
+            }
+
             loop {
                 let (socket, addr) = match listener.accept().await {
                     Ok(v) => v,
@@ -132,7 +367,17 @@ impl NetworkServer {
                     }
                 }
 
-                tokio::task::spawn(verify_connection(socket, new_connections.clone()));
+                tokio::task::spawn(verify_connection(
+                    socket,
+                    addr,
+                    tls_acceptor.clone(),
+                    transport,
+                    authenticator.clone(),
+                    new_connections.clone(),
+                    status.clone(),
+                    established_connections.clone(),
+                    connection_slots.clone(),
+                ));
             }
         };
 
@@ -150,10 +395,7 @@ impl NetworkServer {
             None => return,
         };
 
-        let packet = NetworkPacket {
-            kind: String::from(T::NAME),
-            data: Box::new(message),
-        };
+        let packet = NetworkPacket::new(message);
 
         connection.send_message.blocking_send(packet).ok();
     }
@@ -171,10 +413,7 @@ impl NetworkServer {
                 None => return,
             };
 
-            let packet = NetworkPacket {
-                kind: String::from(T::NAME),
-                data: Box::new(message.clone()),
-            };
+            let packet = NetworkPacket::new(message.clone());
 
             connection.send_message.blocking_send(packet).ok();
         }
@@ -183,10 +422,7 @@ impl NetworkServer {
     /// Broadcast a message to all connected clients
     pub fn broadcast<T: ClientBound + Clone>(&self, message: T) {
         for connection in self.established_connections.iter() {
-            let packet = NetworkPacket {
-                kind: String::from(T::NAME),
-                data: Box::new(message.clone()),
-            };
+            let packet = NetworkPacket::new(message.clone());
 
             connection.send_message.blocking_send(packet).ok();
         }
@@ -203,9 +439,6 @@ impl NetworkServer {
         }
 
         self.established_connections.clear();
-        self.recv_message_map
-            .iter_mut()
-            .for_each(|mut messages| messages.clear());
         self.new_connections.receiver.try_iter().for_each(|_| ());
     }
 
@@ -216,14 +449,144 @@ impl NetworkServer {
             .try_send(connection_id)
             .unwrap();
     }
+
+    /// Disconnect a client and tell it why. A [ServerNetworkEvent::Kicked] is sent before it
+    /// disconnects.
+    pub fn kick(&self, connection_id: ConnectionId, reason: &str) {
+        self.kick_with_message(
+            connection_id,
+            format!("Kicked from the server: {}", reason),
+            reason,
+        );
+    }
+
+    fn kick_with_message(&self, connection_id: ConnectionId, message: String, reason: &str) {
+        if !self.established_connections.contains_key(&connection_id) {
+            return;
+        }
+
+        self.send_one(
+            connection_id,
+            messages::Disconnect {
+                message,
+                message_args: None,
+            },
+        );
+        self.kicked_connections
+            .sender
+            .try_send((connection_id, reason.to_owned()))
+            .unwrap();
+    }
+
+    /// Stop a player or address from connecting, clients that are already connected are kicked.
+    /// Fails if the ban could not be stored, it is not applied then.
+    pub fn ban(&self, ban: Ban, reason: &str) -> Result<(), String> {
+        self.bans.add(ban.clone(), reason)?;
+
+        let banned_connections: Vec<ConnectionId> = self
+            .established_connections
+            .iter()
+            .filter(|connection| match &ban {
+                Ban::Player(player_id) => connection.player_id == *player_id,
+                Ban::Address(ip) => connection.addr.ip() == *ip,
+            })
+            .map(|connection| connection.id)
+            .collect();
+
+        for connection_id in banned_connections {
+            self.kick_with_message(
+                connection_id,
+                format!("You are banned from the server: {}", reason),
+                reason,
+            );
+        }
+
+        self.ban_events
+            .sender
+            .try_send(ServerNetworkEvent::Banned {
+                ban,
+                reason: reason.to_owned(),
+            })
+            .unwrap();
+
+        return Ok(());
+    }
+
+    /// Allow a player or address to connect again. Returns false if it wasn't banned.
+    pub fn unban(&self, ban: &Ban) -> Result<bool, String> {
+        let was_banned = self.bans.remove(ban)?;
+        if was_banned {
+            self.ban_events
+                .sender
+                .try_send(ServerNetworkEvent::Unbanned { ban: ban.clone() })
+                .unwrap();
+        }
+        return Ok(was_banned);
+    }
+
+    /// All the bans, with the reason they were given.
+    pub fn bans(&self) -> Vec<(Ban, String)> {
+        return self.bans.list();
+    }
 }
 
+// The identification is the largest packet clients can send before they are connected. Its strings
+// are bounded, and each is prefixed by its length as a u64.
+const MAX_IDENTIFICATION_LENGTH: usize = NetworkPacket::ID_SIZE
+    + 4 // protocol version
+    + 8 + auth::MAX_USERNAME_LENGTH
+    + 8 // message registry hash
+    + 4 // credentials variant
+    + 8 + if auth::MAX_PASSWORD_LENGTH > auth::SESSION_TOKEN_LENGTH {
+        auth::MAX_PASSWORD_LENGTH
+    } else {
+        auth::SESSION_TOKEN_LENGTH
+    };
+
 // TODO: This is just a copy of 'recv_task' with all the things that errored removed. Look it over
 // and clean it up if necessary.
 async fn verify_connection(
-    mut socket: TcpStream,
+    socket: TcpStream,
+    addr: SocketAddr,
+    tls_acceptor: Option<TlsAcceptor>,
+    transport: Transport,
+    authenticator: Option<Arc<Authenticator>>,
     new_connections: crossbeam_channel::Sender<NewConnection>,
+    status: watch::Receiver<ServerStatus>,
+    established_connections: Arc<DashMap<ConnectionId, ClientConnection>>,
+    connection_slots: Option<Arc<ConnectionSlots>>,
 ) {
+    let socket: BoxedSocket = match tls_acceptor {
+        Some(acceptor) => match tokio::time::timeout(
+            std::time::Duration::from_millis(500),
+            acceptor.accept(socket),
+        )
+        .await
+        {
+            Ok(Ok(tls_socket)) => Box::new(tls_socket),
+            Ok(Err(err)) => {
+                info!("Failed to encrypt connection from [{}]: {}", addr, err);
+                return;
+            }
+            Err(_) => return,
+        },
+        None => Box::new(socket),
+    };
+
+    let mut socket = match tokio::time::timeout(
+        std::time::Duration::from_millis(500),
+        transport::accept(transport, socket),
+    )
+    .await
+    {
+        Ok(Ok(socket)) => socket,
+        Ok(Err(err)) => {
+            info!("Failed to set up the connection from [{}]: {}", addr, err);
+            return;
+        }
+        Err(_) => return,
+    };
+
     let length = match tokio::time::timeout(
         std::time::Duration::from_millis(500),
         socket.read_u32(),
@@ -234,14 +597,12 @@ async fn verify_connection(
         _ => return,
     };
 
-    const MAX_LENGTH: usize = 100;
-    if length > MAX_LENGTH {
+    if length > MAX_IDENTIFICATION_LENGTH {
         error!(
             "Received too large packet from [{}]: {} > {}",
-            socket.peer_addr().unwrap(),
-            length,
-            MAX_LENGTH
+            addr, length, MAX_IDENTIFICATION_LENGTH
         );
+        refuse_connection(&mut socket, "The name or password is too long").await;
         return;
     }
 
@@ -252,69 +613,205 @@ async fn verify_connection(
         Err(err) => {
             error!(
                 "Encountered error while reading stream of length {} from [{}]: {}",
-                length,
-                socket.peer_addr().unwrap(),
-                err
+                length, addr, err
             );
             return;
         }
     }
 
-    let packet: NetworkPacket = match bincode::deserialize(&buffer[..length]) {
-        Ok(packet) => packet,
+    let message = match NetworkPacket::split_id(&buffer[..length]) {
+        Some((ClientIdentification::ID, message)) => message,
+        Some((messages::StatusRequest::ID, _)) => {
+            let status = status.borrow().clone();
+            let response = messages::StatusResponse {
+                protocol_version: messages::PROTOCOL_VERSION,
+                version: status.version,
+                motd: status.motd,
+                players: established_connections.len() as u32,
+                icon_hash: status.icon_hash,
+                icon: status.icon,
+            };
+            write_packet(&mut socket, NetworkPacket::new(response)).await;
+            return;
+        }
+        _ => return,
+    };
+
+    // The version is checked before the rest of the message, as its layout may have changed
+    // between versions.
+    let Some(protocol_version) = message
+        .get(..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_le_bytes)
+    else {
+        return;
+    };
+
+    if protocol_version != messages::PROTOCOL_VERSION {
+        info!(
+            "Refused connection from [{}], it uses protocol version {}, the server uses {}",
+            addr,
+            protocol_version,
+            messages::PROTOCOL_VERSION
+        );
+        let outdated = if protocol_version < messages::PROTOCOL_VERSION {
+            "Update your game to connect"
+        } else {
+            "The server has not been updated yet"
+        };
+        refuse_connection(
+            &mut socket,
+            &format!(
+                "The server requires protocol version {}, but the game uses version {}. {}.",
+                messages::PROTOCOL_VERSION,
+                protocol_version,
+                outdated
+            ),
+        )
+        .await;
+        return;
+    }
+
+    let identity: ClientIdentification = match bincode::deserialize(message) {
+        Ok(identity) => identity,
         Err(err) => {
-            error!(
-                "Failed to decode network packet from [{}]: {}",
-                socket.peer_addr().unwrap(),
-                err
-            );
+            error!("Failed to decode network packet from [{}]: {}", addr, err);
             return;
         }
     };
 
-    let identity: ClientIdentification = match packet.data.downcast() {
-        Ok(v) => *v,
-        Err(_) => return,
+    if identity.message_registry_hash != messages::MESSAGE_REGISTRY_HASH {
+        info!(
+            "Refused connection from [{}], the client's network messages are not compatible",
+            addr
+        );
+        refuse_connection(
+            &mut socket,
+            "The server is running an incompatible version of the game",
+        )
+        .await;
+        return;
+    }
+
+    let username = identity.name;
+
+    let (player_id, session_token) = match authenticator {
+        Some(authenticator) => {
+            // Account lookups may block, so they are kept off the network threads.
+            let name = username.clone();
+            let credentials = identity.credentials;
+            let result = tokio::task::spawn_blocking(move || {
+                authenticator.authenticate(&name, &credentials)
+            })
+            .await;
+
+            match result {
+                Ok(Ok(login)) => (login.player_id, Some(login.session_token)),
+                Ok(Err(reason)) => {
+                    info!("Refused connection from [{}]: {}", addr, reason);
+                    refuse_connection(&mut socket, reason).await;
+                    return;
+                }
+                Err(err) => {
+                    error!("Failed to authenticate [{}]: {}", addr, err);
+                    return;
+                }
+            }
+        }
+        None => (username.clone(), None),
+    };
+
+    // Clients are held here while the server is full, so the server only has to deal with the
+    // ones it has room for.
+    let slot = match connection_slots {
+        Some(connection_slots) => match connection_slots.wait_for_slot(&mut socket).await {
+            Some(slot) => Some(slot),
+            None => {
+                info!("[{}] left the login queue", addr);
+                return;
+            }
+        },
+        None => None,
     };
 
     if let Err(err) = new_connections.send(NewConnection {
         socket,
-        username: identity.name,
+        addr,
+        username,
+        player_id,
+        session_token,
+        slot,
     }) {
         error!("Cannot accept new connections, channel closed: {}", err);
         return;
     }
 }
 
+// The id of the disconnect message is the same for all versions, so the client can still be told
+// why it can't connect. It hasn't received the translations yet, so it's sent as plain text.
+async fn refuse_connection(socket: &mut BoxedSocket, message: &str) {
+    let packet = NetworkPacket::new(messages::Disconnect {
+        message: message.to_owned(),
+        message_args: None,
+    });
+    write_packet(socket, packet).await;
+}
+
+// Writes a packet to a connection that hasn't been established yet. Returns false if it could not
+// be written, most callers ignore it as the connection is closed right after.
+async fn write_packet(socket: &mut BoxedSocket, packet: NetworkPacket) -> bool {
+    let Ok(size) = packet.serialized_size() else {
+        return false;
+    };
+    let mut buffer = vec![0; size];
+    return packet.serialize_into(&mut buffer).is_ok()
+        && socket.write_u32(size as u32).await.is_ok()
+        && socket.write_all(&buffer).await.is_ok()
+        && socket.flush().await.is_ok();
+}
+
 async fn recv_task(
     conn_id: ConnectionId,
-    recv_message_map: Arc<DashMap<&'static str, Vec<(ConnectionId, Box<dyn NetworkMessage>)>>>,
+    message_senders: HashMap<u16, MessageSender>,
+    message_deserializers: Arc<DashMap<u16, DeserializeFn>>,
+    mut rate_limiter: RateLimiter,
     network_settings: NetworkSettings,
-    mut read_socket: tcp::OwnedReadHalf,
+    diagnostics: NetworkDiagnostics,
+    traffic: ConnectionTraffic,
+    mut read_socket: ReadHalf<BoxedSocket>,
     disconnected_connections: crossbeam_channel::Sender<ConnectionId>,
 ) {
     let mut buffer: Vec<u8> = vec![0; network_settings.max_packet_length];
+    let mut compressed_buffer: Vec<u8> = Vec::new();
 
     trace!("Starting receive task for {}", conn_id);
 
     loop {
         trace!("Listening for length!");
 
-        let length = match read_socket.read_u32().await {
-            Ok(len) => len as usize,
-            Err(err) => {
-                // If we get an EOF here, the connection was broken and we simply report a 'disconnected' signal
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
+        let (length, is_compressed) =
+            match with_timeout(network_settings.idle_timeout, read_socket.read_u32()).await {
+                Some(Ok(len)) => (
+                    (len & !COMPRESSED_FLAG) as usize,
+                    len & COMPRESSED_FLAG != 0,
+                ),
+                Some(Err(err)) => {
+                    // If we get an EOF here, the connection was broken and we simply report a 'disconnected' signal
+                    if err.kind() == std::io::ErrorKind::UnexpectedEof {
+                        break;
+                    }
+
+                    error!(
+                        "Encountered error while reading length [{}]: {}",
+                        conn_id, err
+                    );
                     break;
                 }
-
-                error!(
-                    "Encountered error while reading length [{}]: {}",
-                    conn_id, err
-                );
-                break;
-            }
-        };
+                None => {
+                    info!("Disconnecting [{}], it stopped responding", conn_id);
+                    break;
+                }
+            };
 
         trace!("Received packet with length: {}", length);
 
@@ -326,21 +823,90 @@ async fn recv_task(
             break;
         }
 
-        match read_socket.read_exact(&mut buffer[..length]).await {
-            Ok(_) => (),
-            Err(err) => {
+        let read_buffer = if is_compressed {
+            compressed_buffer.resize(length, 0);
+            &mut compressed_buffer[..]
+        } else {
+            &mut buffer[..length]
+        };
+
+        match read_exact_with_timeout(network_settings.idle_timeout, &mut read_socket, read_buffer)
+            .await
+        {
+            Some(Ok(_)) => (),
+            Some(Err(err)) => {
                 error!(
                     "Encountered error while reading stream of length {} [{}]: {}",
                     length, conn_id, err
                 );
                 break;
             }
+            None => {
+                info!("Disconnecting [{}], it stopped responding", conn_id);
+                break;
+            }
         }
 
         trace!("Read buffer of length {}", length);
 
-        let packet: NetworkPacket = match bincode::deserialize(&buffer[..length]) {
-            Ok(packet) => packet,
+        // The length prefix is counted as part of the packet.
+        let wire_length = LENGTH_PREFIX_SIZE + length;
+
+        let length = if is_compressed {
+            match decompress_packet(&compressed_buffer, &mut buffer) {
+                Ok(length) => length,
+                Err(err) => {
+                    error!(
+                        "Failed to decompress network packet from [{}]: {}",
+                        conn_id, err
+                    );
+                    break;
+                }
+            }
+        } else {
+            length
+        };
+
+        let Some((id, message)) = NetworkPacket::split_id(&buffer[..length]) else {
+            error!("Received packet without a message id from [{}]", conn_id);
+            break;
+        };
+
+        diagnostics.record_received(id, wire_length);
+        traffic.record_received(wire_length);
+
+        // Keepalives have done their job by arriving.
+        if id == messages::KeepAlive::ID {
+            continue;
+        }
+
+        // Dropped before deserialization, so flooding the server costs as little as possible.
+        if !rate_limiter.allow(id) {
+            if rate_limiter.should_disconnect() {
+                info!(
+                    "Disconnecting [{}], it has been sending too many {} messages",
+                    conn_id,
+                    messages::MESSAGE_NAMES
+                        .get(id as usize)
+                        .unwrap_or(&"unknown")
+                );
+                break;
+            }
+            continue;
+        }
+
+        let Some(deserialize) = message_deserializers.get(&id).map(|f| *f) else {
+            error!(
+                "Could not find existing entries for message kind: {:?}",
+                messages::MESSAGE_NAMES
+                    .get(id as usize)
+                    .unwrap_or(&"unknown")
+            );
+            continue;
+        };
+
+        let message = match info_span!("deserialize_packet").in_scope(|| deserialize(message)) {
+            Ok(message) => message,
             Err(err) => {
                 error!(
                     "Failed to decode network packet from [{}]: {}",
@@ -352,14 +918,8 @@ async fn recv_task(
 
         trace!("Created a network packet");
 
-        match recv_message_map.get_mut(&packet.kind[..]) {
-            Some(mut packets) => packets.push((conn_id, packet.data)),
-            None => {
-                error!(
-                    "Could not find existing entries for message kind: {:?}",
-                    packet
-                );
-            }
+        if let Some(sender) = message_senders.get(&id) {
+            sender.send(message).ok();
         }
 
         debug!("Received new message of length: {}", length);
@@ -375,21 +935,37 @@ async fn recv_task(
 
 async fn send_task(
     mut recv_message: Receiver<NetworkPacket>,
-    mut send_socket: tcp::OwnedWriteHalf,
+    mut send_socket: WriteHalf<BoxedSocket>,
     network_settings: NetworkSettings,
+    compression: Arc<AtomicBool>,
+    diagnostics: NetworkDiagnostics,
+    traffic: ConnectionTraffic,
 ) {
     let mut buffer: Vec<u8> = vec![0; network_settings.max_packet_length];
+    let mut compressed_buffer: Vec<u8> = Vec::new();
 
-    while let Some(message) = recv_message.recv().await {
-        let size = match bincode::serialized_size(&message) {
-            Ok(size) => size as usize,
+    loop {
+        let message =
+            match tokio::time::timeout(network_settings.keepalive_interval, recv_message.recv())
+                .await
+            {
+                Ok(Some(message)) => message,
+                Ok(None) => return,
+                // Nothing has been sent for a while, let the client know the connection is alive.
+                Err(_) => NetworkPacket::new(messages::KeepAlive),
+            };
+
+        let size = match message.serialized_size() {
+            Ok(size) => size,
             Err(err) => {
                 error!("Could not encode packet {:?}: {}", message, err);
                 continue;
             }
         };
 
-        match bincode::serialize_into(&mut buffer[0..size], &message) {
+        match info_span!("serialize_packet")
+            .in_scope(|| message.serialize_into(&mut buffer[0..size]))
+        {
             Ok(_) => (),
             Err(err) => {
                 error!(
@@ -400,7 +976,15 @@ async fn send_task(
             }
         };
 
-        match send_socket.write_u32(size as u32).await {
+        // Compression is only used after the client has said it supports it.
+        let threshold = if compression.load(Ordering::Relaxed) {
+            network_settings.compression_threshold
+        } else {
+            None
+        };
+        let (length, packet) = compress_packet(&buffer[0..size], threshold, &mut compressed_buffer);
+
+        match send_socket.write_u32(length).await {
             Ok(_) => (),
             Err(err) => {
                 error!("Could not send packet length: {:?}: {}", size, err);
@@ -408,13 +992,23 @@ async fn send_task(
             }
         }
 
-        match send_socket.write_all(&buffer[0..size]).await {
+        match send_socket.write_all(packet).await {
             Ok(_) => (),
             Err(err) => {
                 error!("Could not send packet: {:?}: {}", message, err);
                 return;
             }
         }
+
+        // Encrypted sockets buffer the data until they are flushed.
+        if let Err(err) = send_socket.flush().await {
+            error!("Could not flush packet: {:?}: {}", message, err);
+            return;
+        }
+
+        let wire_length = LENGTH_PREFIX_SIZE + packet.len();
+        diagnostics.record_sent(message.id, wire_length);
+        traffic.record_sent(wire_length);
     }
 }
 
@@ -422,10 +1016,46 @@ pub(crate) fn handle_connections(
     mut commands: Commands,
     server: Res<NetworkServer>,
     network_settings: Res<NetworkSettings>,
+    diagnostics: Res<NetworkDiagnostics>,
     mut network_events: EventWriter<ServerNetworkEvent>,
 ) {
-    for connection in server.new_connections.receiver.try_iter() {
-        let addr = connection.socket.peer_addr().unwrap();
+    for mut connection in server.new_connections.receiver.try_iter() {
+        let addr = connection.addr;
+
+        if let Some(reason) = server.bans.reason(&connection.player_id, addr.ip()) {
+            info!(
+                "Refused connection from [{}], '{}' is banned: {}",
+                addr, connection.username, reason
+            );
+            if let Some(runtime) = &server.runtime {
+                runtime.spawn(async move {
+                    refuse_connection(
+                        &mut connection.socket,
+                        &format!("You are banned from the server: {}", reason),
+                    )
+                    .await;
+                });
+            }
+            continue;
+        }
+
+        // A player can only be connected once.
+        if server
+            .established_connections
+            .iter()
+            .any(|established| established.player_id == connection.player_id)
+        {
+            info!(
+                "Refused connection from [{}], '{}' is already connected",
+                addr, connection.username
+            );
+            if let Some(runtime) = &server.runtime {
+                runtime.spawn(async move {
+                    refuse_connection(&mut connection.socket, "You are already connected").await;
+                });
+            }
+            continue;
+        }
 
         let mut entity_commands = commands.spawn_empty();
 
@@ -433,23 +1063,51 @@ pub(crate) fn handle_connections(
             entity: entity_commands.id(),
             addr,
         };
-        entity_commands.insert(connection_id.clone());
 
-        let (read_socket, send_socket) = connection.socket.into_split();
+        let mut message_senders = HashMap::new();
+        let mut message_receivers = HashMap::new();
+        for message_id in server.message_deserializers.iter() {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            message_senders.insert(*message_id.key(), sender);
+            message_receivers.insert(*message_id.key(), receiver);
+        }
+
+        let traffic = ConnectionTraffic::default();
+
+        entity_commands.insert((
+            connection_id,
+            ConnectionMessages(message_receivers),
+            Latency::default(),
+            traffic.clone(),
+        ));
+
+        let (read_socket, send_socket) = tokio::io::split(connection.socket);
 
         // TODO: I changed this from an unbounded channel because of some memory issue I could't
         // diagnose.
         let (send_message, recv_message) = channel(10);
+        let compression = Arc::new(AtomicBool::new(false));
+
+        if let Some(token) = connection.session_token {
+            send_message
+                .try_send(NetworkPacket::new(messages::SessionToken { token }))
+                .ok();
+        }
 
         server.established_connections.insert(
             connection_id,
             ClientConnection {
                 username: connection.username.to_owned(),
+                player_id: connection.player_id.clone(),
                 id: connection_id,
                 receive_task: server.runtime.as_ref().unwrap().spawn(recv_task(
                     connection_id,
-                    server.recv_message_map.clone(),
+                    message_senders,
+                    server.message_deserializers.clone(),
+                    RateLimiter::new(&server.rate_limits, network_settings.rate_limit_timeout),
                     network_settings.clone(),
+                    diagnostics.clone(),
+                    traffic.clone(),
                     read_socket,
                     server.disconnected_connections.sender.clone(),
                 )),
@@ -457,15 +1115,21 @@ pub(crate) fn handle_connections(
                     recv_message,
                     send_socket,
                     network_settings.clone(),
+                    compression.clone(),
+                    diagnostics.clone(),
+                    traffic,
                 )),
                 send_message,
+                compression,
                 addr,
+                _slot: connection.slot,
             },
         );
 
         network_events.send(ServerNetworkEvent::Connected {
             entity: connection_id.entity,
             username: connection.username,
+            player_id: connection.player_id,
         });
     }
 }
@@ -473,7 +1137,11 @@ pub(crate) fn handle_connections(
 // TODO: When you disconnnect is prints a bunch of errors because it still has
 // access to the connection even though it's disconnected when trying to send.
 //
+// The messages the client sent before it disconnected are still turned into events this tick, as
+// the systems that do it run after this one. Anything left after that is dropped along with the
+// connection's message channels.
 pub(crate) fn send_disconnection_events(
+    mut commands: Commands,
     server: Res<NetworkServer>,
     mut network_events: EventWriter<ServerNetworkEvent>,
 ) {
@@ -488,12 +1156,49 @@ pub(crate) fn send_disconnection_events(
 
         connection.stop();
 
+        if let Some(mut entity_commands) = commands.get_entity(disconnected_connection.entity) {
+            entity_commands.remove::<ConnectionMessages>();
+        }
+
         network_events.send(ServerNetworkEvent::Disconnected {
             entity: disconnected_connection.entity,
         });
     }
 }
 
+// Kicked connections are disconnected here, after the disconnections of this tick have been
+// handled, so the client gets until the next tick to receive why.
+pub(crate) fn send_moderation_events(
+    server: Res<NetworkServer>,
+    mut network_events: EventWriter<ServerNetworkEvent>,
+) {
+    for (connection_id, reason) in server.kicked_connections.receiver.try_iter() {
+        if !server.established_connections.contains_key(&connection_id) {
+            continue;
+        }
+
+        network_events.send(ServerNetworkEvent::Kicked {
+            entity: connection_id.entity,
+            reason,
+        });
+        server.disconnect(connection_id);
+    }
+
+    network_events.send_batch(server.ban_events.receiver.try_iter());
+}
+
+// Clients that support compression answer the ServerConfig with EnableCompression.
+pub(crate) fn enable_compression(
+    server: Res<NetworkServer>,
+    mut compression_events: EventReader<NetworkData<messages::EnableCompression>>,
+) {
+    for event in compression_events.read() {
+        if let Some(connection) = server.established_connections.get(&event.source) {
+            connection.compression.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
 pub(crate) fn handle_disconnection_events(
     mut commands: Commands,
     mut disconnection_events: EventReader<ServerNetworkEvent>,
@@ -524,30 +1229,148 @@ impl AppNetworkServerMessage for App {
         debug!("Registered a new ServerMessage: {}", T::NAME);
 
         assert!(
-            !server.recv_message_map.contains_key(T::NAME),
+            !server.message_deserializers.contains_key(&T::ID),
             "Duplicate registration of ServerMessage: {}",
             T::NAME
         );
-        server.recv_message_map.insert(T::NAME, Vec::new());
+        server
+            .message_deserializers
+            .insert(T::ID, network_message::deserialize_message::<T>);
         self.add_event::<NetworkData<T>>();
-        self.add_systems(PreUpdate, register_server_message::<T>)
+        self.add_systems(
+            First,
+            register_server_message::<T>.after(send_disconnection_events),
+        )
     }
 }
 
 fn register_server_message<T>(
-    net_res: ResMut<NetworkServer>,
+    connection_query: Query<(&ConnectionId, &ConnectionMessages)>,
     mut events: EventWriter<NetworkData<T>>,
 ) where
     T: ServerBound,
 {
-    let mut messages = match net_res.recv_message_map.get_mut(T::NAME) {
-        Some(messages) => messages,
-        None => return,
-    };
+    for (connection_id, messages) in connection_query.iter() {
+        let Some(receiver) = messages.0.get(&T::ID) else {
+            continue;
+        };
+
+        events.send_batch(receiver.try_iter().flat_map(|msg| {
+            msg.downcast()
+                .map(|msg| NetworkData::new(*connection_id, *msg))
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::Account, messages::Credentials};
+
+    #[derive(Default)]
+    struct Accounts(DashMap<String, Account>);
+
+    impl AccountStorage for Accounts {
+        fn account(&self, username: &str) -> Result<Option<Account>, String> {
+            return Ok(self.0.get(username).map(|account| account.clone()));
+        }
+
+        fn create_account(
+            &self,
+            id: &str,
+            username: &str,
+            password_hash: Vec<u8>,
+        ) -> Result<(), String> {
+            self.0.insert(
+                username.to_owned(),
+                Account {
+                    id: id.to_owned(),
+                    password_hash: Some(password_hash),
+                },
+            );
+            return Ok(());
+        }
 
-    events.send_batch(
-        messages
-            .drain(..)
-            .flat_map(|(conn, msg)| msg.downcast().map(|msg| NetworkData::new(conn, *msg))),
-    );
+        fn set_password_hash(&self, id: &str, password_hash: Vec<u8>) -> Result<bool, String> {
+            for mut account in self.0.iter_mut() {
+                if account.id == id && account.password_hash.is_none() {
+                    account.password_hash = Some(password_hash);
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+    }
+
+    // Sends the identification the way the client does, and returns the connection if it was
+    // accepted.
+    async fn identify(
+        authenticator: &Arc<Authenticator>,
+        identity: ClientIdentification,
+    ) -> Option<NewConnection> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, addr) = listener.accept().await.unwrap();
+
+        let packet = NetworkPacket::new(identity);
+        let mut buffer = vec![0; packet.serialized_size().unwrap()];
+        packet.serialize_into(&mut buffer).unwrap();
+        client.write_u32(buffer.len() as u32).await.unwrap();
+        client.write_all(&buffer).await.unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let (_status_sender, status) = watch::channel(ServerStatus::default());
+        verify_connection(
+            socket,
+            addr,
+            None,
+            Transport::Tcp,
+            Some(authenticator.clone()),
+            sender,
+            status,
+            Arc::new(DashMap::new()),
+            None,
+        )
+        .await;
+
+        return receiver.try_recv().ok();
+    }
+
+    #[test]
+    fn largest_identification_is_accepted() {
+        let runtime = Runtime::new().unwrap();
+        runtime.block_on(async {
+            let authenticator = Authenticator::new(Accounts::default());
+            let name = "a".repeat(auth::MAX_USERNAME_LENGTH);
+
+            let password = Credentials::Password("p".repeat(auth::MAX_PASSWORD_LENGTH));
+            let connection = identify(
+                &authenticator,
+                ClientIdentification::new(name.clone(), password),
+            )
+            .await
+            .expect("The password login was refused");
+            let session_token = connection.session_token.unwrap();
+            assert_eq!(session_token.len(), auth::SESSION_TOKEN_LENGTH);
+
+            let token = Credentials::SessionToken(session_token);
+            let connection = identify(&authenticator, ClientIdentification::new(name, token))
+                .await
+                .expect("The session token login was refused");
+            assert_eq!(connection.username.len(), auth::MAX_USERNAME_LENGTH);
+        });
+    }
+
+    #[test]
+    fn too_long_password_is_refused() {
+        let runtime = Runtime::new().unwrap();
+        runtime.block_on(async {
+            let authenticator = Authenticator::new(Accounts::default());
+            let password = Credentials::Password("p".repeat(auth::MAX_PASSWORD_LENGTH + 1));
+            let identity = ClientIdentification::new("a".to_owned(), password);
+            assert!(identify(&authenticator, identity).await.is_none());
+        });
+    }
 }
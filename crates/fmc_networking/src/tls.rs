@@ -0,0 +1,167 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerConfig,
+    ServerName,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// How the connection should be encrypted. The server and client must agree, a client that
+/// doesn't use TLS can't connect to a server that does, and the other way around.
+#[derive(Clone, Debug)]
+pub enum TlsSettings {
+    /// Used by the server. Paths to the pem encoded certificate chain and its private key.
+    Server {
+        certificate: PathBuf,
+        private_key: PathBuf,
+    },
+    /// Used by the client. Only accept the server if the sha256 hash of its certificate matches.
+    /// This is meant for self-signed certificates.
+    Fingerprint([u8; 32]),
+    /// Used by the client. Verify the server's certificate against a pem encoded CA certificate,
+    /// or the web's root certificates if None. The server name must match the certificate.
+    CertificateAuthority {
+        certificate: Option<PathBuf>,
+        server_name: String,
+    },
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Could not open certificate '{}': {}", path.display(), err))?;
+
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|err| format!("Could not read certificate '{}': {}", path.display(), err))?;
+
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in '{}'", path.display()));
+    }
+
+    return Ok(certificates.into_iter().map(Certificate).collect());
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKey, String> {
+    let file = File::open(path)
+        .map_err(|err| format!("Could not open private key '{}': {}", path.display(), err))?;
+
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|err| format!("Could not read private key '{}': {}", path.display(), err))?;
+
+    for item in items {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => continue,
+        }
+    }
+
+    return Err(format!("No private key found in '{}'", path.display()));
+}
+
+pub(crate) fn acceptor(settings: &TlsSettings) -> Result<TlsAcceptor, String> {
+    let TlsSettings::Server {
+        certificate,
+        private_key,
+    } = settings
+    else {
+        return Err("The server can only use the 'Server' tls settings".to_owned());
+    };
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            load_certificates(certificate)?,
+            load_private_key(private_key)?,
+        )
+        .map_err(|err| format!("Invalid certificate: {}", err))?;
+
+    return Ok(TlsAcceptor::from(Arc::new(config)));
+}
+
+/// Returns the connector along with the name the server's certificate should be verified against.
+pub(crate) fn connector(settings: &TlsSettings) -> Result<(TlsConnector, ServerName), String> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+
+    let (config, server_name) = match settings {
+        TlsSettings::Server { .. } => {
+            return Err("The client can't use the 'Server' tls settings".to_owned())
+        }
+        TlsSettings::Fingerprint(fingerprint) => {
+            let config = builder
+                .with_custom_certificate_verifier(Arc::new(FingerprintVerifier(*fingerprint)))
+                .with_no_client_auth();
+            // The name isn't checked when pinning the certificate, but one has to be given.
+            (config, "localhost")
+        }
+        TlsSettings::CertificateAuthority {
+            certificate,
+            server_name,
+        } => {
+            let mut roots = RootCertStore::empty();
+            if let Some(path) = certificate {
+                for certificate in load_certificates(path)? {
+                    roots
+                        .add(&certificate)
+                        .map_err(|err| format!("Invalid CA certificate: {}", err))?;
+                }
+            } else {
+                roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        anchor.subject,
+                        anchor.spki,
+                        anchor.name_constraints,
+                    )
+                }));
+            }
+
+            let config = builder.with_root_certificates(roots).with_no_client_auth();
+            (config, server_name.as_str())
+        }
+    };
+
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|_| format!("Invalid server name: {}", server_name))?;
+
+    return Ok((TlsConnector::from(Arc::new(config)), server_name));
+}
+
+/// Sha256 hash of a der encoded certificate, for use with [TlsSettings::Fingerprint].
+pub fn certificate_fingerprint(certificate: &[u8]) -> [u8; 32] {
+    let digest = ring::digest::digest(&ring::digest::SHA256, certificate);
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(digest.as_ref());
+    return fingerprint;
+}
+
+// Accepts the server if its certificate is the one that was pinned. The handshake signatures are
+// still verified by the default implementation, so the server must also own the private key.
+struct FingerprintVerifier([u8; 32]);
+
+impl ServerCertVerifier for FingerprintVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if certificate_fingerprint(&end_entity.0) == self.0 {
+            return Ok(ServerCertVerified::assertion());
+        } else {
+            return Err(rustls::Error::General(
+                "The server's certificate does not match the fingerprint".to_owned(),
+            ));
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// What carries the packets between the server and the client. The server only accepts
+/// connections that use the same transport as it does. Encryption is configured separately, and
+/// works with all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Packets are written straight to the tcp stream.
+    #[default]
+    Tcp,
+    /// Packets are sent as binary WebSocket messages, this is what browsers can connect with.
+    /// Only available when built with the 'websocket' feature.
+    WebSocket,
+}
+
+/// A connection after it has been set up by its transport, and encrypted if tls is used.
+/// Sockets are boxed so the same tasks can handle all of them.
+pub(crate) trait Socket: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Socket for T {}
+
+pub(crate) type BoxedSocket = Box<dyn Socket>;
+
+/// Does the client's side of the transport's handshake.
+#[cfg_attr(not(feature = "websocket"), allow(unused_variables))]
+pub(crate) async fn connect(
+    transport: Transport,
+    socket: BoxedSocket,
+    addr: SocketAddr,
+) -> std::io::Result<BoxedSocket> {
+    match transport {
+        Transport::Tcp => return Ok(socket),
+        #[cfg(feature = "websocket")]
+        Transport::WebSocket => {
+            let (stream, _) = tokio_tungstenite::client_async(format!("ws://{}/", addr), socket)
+                .await
+                .map_err(websocket::to_io_error)?;
+            return Ok(Box::new(websocket::WebSocketSocket::new(stream)));
+        }
+        #[cfg(not(feature = "websocket"))]
+        Transport::WebSocket => return Err(websocket_unsupported()),
+    }
+}
+
+/// Does the server's side of the transport's handshake.
+pub(crate) async fn accept(
+    transport: Transport,
+    socket: BoxedSocket,
+) -> std::io::Result<BoxedSocket> {
+    match transport {
+        Transport::Tcp => return Ok(socket),
+        #[cfg(feature = "websocket")]
+        Transport::WebSocket => {
+            let stream = tokio_tungstenite::accept_async(socket)
+                .await
+                .map_err(websocket::to_io_error)?;
+            return Ok(Box::new(websocket::WebSocketSocket::new(stream)));
+        }
+        #[cfg(not(feature = "websocket"))]
+        Transport::WebSocket => return Err(websocket_unsupported()),
+    }
+}
+
+#[cfg(not(feature = "websocket"))]
+fn websocket_unsupported() -> std::io::Error {
+    return std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Built without WebSocket support, enable the 'websocket' feature",
+    );
+}
+
+#[cfg(feature = "websocket")]
+mod websocket {
+    use std::{
+        io,
+        pin::Pin,
+        task::{ready, Context, Poll},
+    };
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+    use super::BoxedSocket;
+
+    pub(super) fn to_io_error(err: tokio_tungstenite::tungstenite::Error) -> io::Error {
+        return match err {
+            tokio_tungstenite::tungstenite::Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::Other, err),
+        };
+    }
+
+    /// Makes the WebSocket look like a stream of bytes, so the packets can be read and written
+    /// like they are for tcp. Everything written between flushes is sent as one binary message,
+    /// which is one packet as they are flushed after each one.
+    pub(super) struct WebSocketSocket {
+        stream: WebSocketStream<BoxedSocket>,
+        // The message that is being read, and how much of it has been read.
+        read_buffer: Vec<u8>,
+        read_position: usize,
+        write_buffer: Vec<u8>,
+    }
+
+    impl WebSocketSocket {
+        pub(super) fn new(stream: WebSocketStream<BoxedSocket>) -> Self {
+            return Self {
+                stream,
+                read_buffer: Vec::new(),
+                read_position: 0,
+                write_buffer: Vec::new(),
+            };
+        }
+    }
+
+    impl AsyncRead for WebSocketSocket {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+
+            while this.read_position == this.read_buffer.len() {
+                match ready!(this.stream.poll_next_unpin(cx)) {
+                    Some(Ok(Message::Binary(data))) => {
+                        this.read_buffer = data;
+                        this.read_position = 0;
+                    }
+                    // The connection was closed, which is read as the end of the stream.
+                    Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                    // Pings are answered by the stream itself, and text isn't used.
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Poll::Ready(Err(to_io_error(err))),
+                }
+            }
+
+            let remaining = &this.read_buffer[this.read_position..];
+            let length = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..length]);
+            this.read_position += length;
+
+            return Poll::Ready(Ok(()));
+        }
+    }
+
+    impl AsyncWrite for WebSocketSocket {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.get_mut().write_buffer.extend_from_slice(buf);
+            return Poll::Ready(Ok(buf.len()));
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+
+            if !this.write_buffer.is_empty() {
+                ready!(this.stream.poll_ready_unpin(cx)).map_err(to_io_error)?;
+                let message = Message::Binary(std::mem::take(&mut this.write_buffer));
+                this.stream.start_send_unpin(message).map_err(to_io_error)?;
+            }
+
+            return this.stream.poll_flush_unpin(cx).map_err(to_io_error);
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            ready!(self.as_mut().poll_flush(cx))?;
+            return self
+                .get_mut()
+                .stream
+                .poll_close_unpin(cx)
+                .map_err(to_io_error);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use igd_next::{aio::tokio::search_gateway, PortMappingProtocol, SearchOptions};
+use tokio::net::UdpSocket;
+
+// The mapping is leased instead of permanent, so it is removed by the router if the server stops
+// without cleaning up after itself. It is renewed well before the lease runs out.
+const LEASE_DURATION: Duration = Duration::from_secs(10 * 60);
+const RENEW_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Asks the router to forward the port to this machine, so the server can be reached from outside
+/// the local network. Keeps renewing the mapping until the task is stopped.
+pub(crate) async fn forward_port(port: u16) {
+    let gateway = match search_gateway(SearchOptions::default()).await {
+        Ok(gateway) => gateway,
+        Err(err) => {
+            error!(
+                "Could not find a router to forward the port with UPnP: {}",
+                err
+            );
+            return;
+        }
+    };
+
+    let local_ip = match local_ip(gateway.addr).await {
+        Ok(ip) => ip,
+        Err(err) => {
+            error!("Could not forward the port with UPnP: {}", err);
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(RENEW_INTERVAL);
+    let mut forwarded = false;
+    loop {
+        interval.tick().await;
+
+        match gateway
+            .add_port(
+                PortMappingProtocol::TCP,
+                port,
+                SocketAddr::new(local_ip, port),
+                LEASE_DURATION.as_secs() as u32,
+                "fmc server",
+            )
+            .await
+        {
+            Ok(()) if !forwarded => {
+                info!("Forwarded port {} with UPnP", port);
+                forwarded = true;
+            }
+            Ok(()) => (),
+            Err(err) => error!("Could not forward the port with UPnP: {}", err),
+        }
+    }
+}
+
+// The router forwards to the address this machine has on the local network, which is the one it
+// uses to reach the router.
+async fn local_ip(gateway: SocketAddr) -> std::io::Result<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(gateway).await?;
+    return Ok(socket.local_addr()?.ip());
+}
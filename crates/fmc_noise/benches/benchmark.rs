@@ -1,59 +1,90 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use noise::Noise;
-//use simdnoise::{avx2, sse2};
 
-fn d1(c: &mut Criterion) {
-    let mut group = c.benchmark_group("gradient_1d");
+// The functions are dispatched at runtime to the widest instruction set the cpu supports, so the
+// numbers are for that target only. Each step of the noise is dispatched on its own, so a target
+// can't be picked for a whole noise. To compare the targets, run the benchmarks on cpus that
+// support different instruction sets. There is no 4d noise to measure.
 
-    let noise = Noise::simplex(0.01, 0);
-    group.bench_function("lib", |b| b.iter(|| noise.generate_1d(0.0, 1000000)));
+fn base_noises() -> [(&'static str, Noise); 2] {
+    return [
+        ("simplex", Noise::simplex(0.01, 0)),
+        ("perlin", Noise::perlin(0.01, 0)),
+    ];
+}
 
-    //let settings = simdnoise::NoiseBuilder::gradient_1d(1000).wrap();
-    //group.bench_function("simdnoise", move |b| {
-    //    b.iter(|| unsafe { avx2::get_1d_noise(&settings) })
-    //});
+fn configure(group: &mut criterion::BenchmarkGroup<criterion::measurement::WallTime>) {
     group
         .sample_size(10)
-        .warm_up_time(std::time::Duration::from_millis(1))
+        .warm_up_time(std::time::Duration::from_millis(500))
         .measurement_time(std::time::Duration::from_secs(5));
 }
 
-fn d2(c: &mut Criterion) {
-    let mut group = c.benchmark_group("gradient_2d");
+fn d1(c: &mut Criterion) {
+    let mut group = c.benchmark_group("1d");
+    configure(&mut group);
+
+    // Perlin noise uses simplex noise for its 1d samples, so only simplex is measured.
     let noise = Noise::simplex(0.01, 0);
-    group.bench_function("lib", move |b| {
-        b.iter(|| noise.generate_2d(0.0, 0.0, 1000, 1000))
-    });
+    group.bench_function("simplex", |b| b.iter(|| noise.generate_1d(0.0, 1_000_000)));
+    group.finish();
+}
 
-    //let setting = simdnoise::NoiseBuilder::gradient_2d(3840, 2160).wrap();
-    //group.bench_function("simdnoise", move |b| {
-    //    b.iter(|| unsafe { avx2::get_2d_noise(&setting) })
-    //});
-    group
-        .sample_size(10)
-        .warm_up_time(std::time::Duration::from_millis(1))
-        .measurement_time(std::time::Duration::from_secs(5));
+fn d2(c: &mut Criterion) {
+    let mut group = c.benchmark_group("2d");
+    configure(&mut group);
+
+    for (name, noise) in base_noises() {
+        group.bench_function(name, |b| b.iter(|| noise.generate_2d(0.0, 0.0, 1000, 1000)));
+    }
+    group.finish();
 }
 
 fn d3(c: &mut Criterion) {
-    let mut group = c.benchmark_group("gradient_3d");
+    let mut group = c.benchmark_group("3d");
+    configure(&mut group);
+
+    for (name, noise) in base_noises() {
+        group.bench_function(name, |b| {
+            b.iter(|| noise.generate_3d(0.0, 0.0, 0.0, 100, 100, 100))
+        });
+    }
+    group.finish();
+}
+
+// Heights that aren't a multiple of the simd width take the slower path for the remainder of each
+// column.
+fn d3_remainder(c: &mut Criterion) {
+    let mut group = c.benchmark_group("3d_remainder");
+    configure(&mut group);
+
     let noise = Noise::simplex(0.01, 0);
-    group.bench_function("lib", move |b| {
-        b.iter(|| noise.generate_3d(0.0, 0.0, 0.0, 100, 100, 100))
-    });
+    for height in [16, 17, 23] {
+        group.bench_with_input(BenchmarkId::from_parameter(height), &height, |b, height| {
+            b.iter(|| noise.generate_3d(0.0, 0.0, 0.0, 64, *height, 64))
+        });
+    }
+    group.finish();
+}
 
-    //let setting = simdnoise::NoiseBuilder::gradient_3d(16, 16, 16).wrap();
-    //group.bench_function("simdnoise", move |b| {
-    //    b.iter(|| unsafe { avx2::get_3d_noise(&setting) })
-    //});
-    group
-        .sample_size(10)
-        .warm_up_time(std::time::Duration::from_millis(1))
-        .measurement_time(std::time::Duration::from_secs(5));
+// Same size as a chunk, sampled on every block and on every fourth block like the terrain is.
+fn lattice_3d(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lattice_3d");
+    configure(&mut group);
+
+    let noise = Noise::simplex(0.01, 0).fbm(4, 0.5, 2.0);
+    for step in [1, 4] {
+        let size = 16 / step as usize;
+        group.bench_with_input(BenchmarkId::from_parameter(step), &step, |b, step| {
+            b.iter(|| noise.generate_3d_lattice([0, 0, 0], *step, size, size, size))
+        });
+    }
+    group.finish();
 }
 
 fn fbm_3d(c: &mut Criterion) {
     let mut group = c.benchmark_group("fbm_3d");
+    configure(&mut group);
 
     let freq = 1.0 / 2.0f32.powi(8);
     let high = Noise::perlin(freq, 2)
@@ -62,49 +93,37 @@ fn fbm_3d(c: &mut Criterion) {
     let low = Noise::perlin(freq, 3)
         .with_frequency(freq, freq, freq)
         .fbm(4, 0.5, 2.0);
-    let noise = Noise::perlin(0.01, 0).fbm(8, 0.5, 2.0)
-            .range(0.1, -0.1, high, low)
-            .mul_value(2.0);
+    let noise = Noise::perlin(0.01, 0)
+        .fbm(8, 0.5, 2.0)
+        .range(0.1, -0.1, high, low)
+        .mul_value(2.0);
     group.bench_function("lib", move |b| {
         b.iter(|| noise.generate_3d(0.0, 0.0, 0.0, 16, 16, 16))
     });
-
-    //let setting = simdnoise::NoiseBuilder::fbm_3d(16, 16, 16)
-    //    .with_octaves(3)
-    //    .wrap();
-    //group.bench_function("simdnoise", move |b| {
-    //    b.iter(|| unsafe { avx2::get_3d_noise(&setting) })
-    //});
-    group
-        .sample_size(10)
-        .warm_up_time(std::time::Duration::from_millis(1))
-        .measurement_time(std::time::Duration::from_secs(5));
+    group.finish();
 }
 
 fn add_3d(c: &mut Criterion) {
     let mut group = c.benchmark_group("add_3d");
+    configure(&mut group);
 
-    let noise = Noise::simplex(0.01, 0).fbm(
-        3,
-        1.0,
-        1.0,
-    );
-    let noise2 = Noise::simplex(0.01, 0).fbm(
-        3,
-        1.0,
-        1.0,
-    );
+    let noise = Noise::simplex(0.01, 0).fbm(3, 1.0, 1.0);
+    let noise2 = Noise::simplex(0.01, 0).fbm(3, 1.0, 1.0);
     let noise = noise.add(noise2);
     group.bench_function("lib", move |b| {
         b.iter(|| noise.generate_3d(0.0, 0.0, 0.0, 100, 100, 100))
     });
-
-    group
-        .sample_size(10)
-        .warm_up_time(std::time::Duration::from_millis(1))
-        .measurement_time(std::time::Duration::from_secs(5));
+    group.finish();
 }
 
-//criterion_group!(benches, d1, d2, d3, fbm_3d, add_3d);
-criterion_group!(benches, fbm_3d);
+criterion_group!(
+    benches,
+    d1,
+    d2,
+    d3,
+    d3_remainder,
+    lattice_3d,
+    fbm_3d,
+    add_3d
+);
 criterion_main!(benches);
@@ -47,6 +47,7 @@ where
 pub fn abs_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -60,6 +61,6 @@ where
 
     let source = &tree.nodes[*source];
     unsafe {
-        return (source.function_3d)(tree, &source, x, y, z).abs();
+        return (source.function_3d)(tree, &source, origin, x, y, z).abs();
     }
 }
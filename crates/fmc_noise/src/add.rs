@@ -59,6 +59,7 @@ where
 pub fn add_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -77,8 +78,8 @@ where
     let left_node = &tree.nodes[*left_source];
     let right_node = &tree.nodes[*right_source];
     unsafe {
-        return (left_node.function_3d)(tree, &left_node, x, y, z)
-            + (right_node.function_3d)(tree, &right_node, x, y, z);
+        return (left_node.function_3d)(tree, &left_node, origin, x, y, z)
+            + (right_node.function_3d)(tree, &right_node, origin, x, y, z);
     }
 }
 
@@ -125,6 +126,7 @@ where
 pub fn add_value_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -138,6 +140,6 @@ where
 
     let source = &tree.nodes[*source];
     unsafe {
-        return (source.function_3d)(tree, &source, x, y, z) + Simd::splat(*value);
+        return (source.function_3d)(tree, &source, origin, x, y, z) + Simd::splat(*value);
     }
 }
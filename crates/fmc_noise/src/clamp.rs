@@ -49,6 +49,7 @@ where
 pub fn clamp_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -62,7 +63,7 @@ where
 
     let source = &tree.nodes[*source];
     unsafe {
-        return (source.function_3d)(tree, &source, x, y, z)
+        return (source.function_3d)(tree, &source, origin, x, y, z)
             .simd_clamp(Simd::splat(*min), Simd::splat(*max));
     }
 }
@@ -41,6 +41,7 @@ where
 pub fn constant_3d<const N: usize>(
     _tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    _origin: [f64; 3],
     _x: Simd<f32, N>,
     _y: Simd<f32, N>,
     _z: Simd<f32, N>,
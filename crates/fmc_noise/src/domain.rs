@@ -0,0 +1,184 @@
+use std::simd::{LaneCount, Simd, StdFloat, SupportedLaneCount};
+
+use multiversion::multiversion;
+
+use crate::noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree};
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn translate_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Translate {
+        x: offset_x,
+        source,
+        ..
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_1d)(tree, &source, x + Simd::splat(*offset_x));
+    }
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn translate_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    // 2d noise is in the xz plane, see base noises.
+    let NoiseNodeSettings::Translate {
+        x: offset_x,
+        z: offset_z,
+        source,
+        ..
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_2d)(
+            tree,
+            &source,
+            x + Simd::splat(*offset_x),
+            y + Simd::splat(*offset_z),
+        );
+    }
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn translate_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Translate {
+        x: offset_x,
+        y: offset_y,
+        z: offset_z,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+
+    // The offset is added to the origin, so it doesn't take precision from the coordinates.
+    let origin = [
+        origin[0] + *offset_x as f64,
+        origin[1] + *offset_y as f64,
+        origin[2] + *offset_z as f64,
+    ];
+
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_3d)(tree, &source, origin, x, y, z);
+    }
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn rotate_y_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::RotateY { source, .. } = &node.settings else {
+        unreachable!()
+    };
+
+    // There's no plane to rotate in, the line is passed through as is.
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_1d)(tree, &source, x);
+    }
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn rotate_y_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::RotateY { sin, cos, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let sin = Simd::splat(*sin);
+    let cos = Simd::splat(*cos);
+
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_2d)(
+            tree,
+            &source,
+            x.mul_add(cos, -(y * sin)),
+            x.mul_add(sin, y * cos),
+        );
+    }
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn rotate_y_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::RotateY { sin, cos, source } = &node.settings else {
+        unreachable!()
+    };
+
+    // The origin is rotated separately, rotation is linear so the sum is still rotated.
+    let (sin_f64, cos_f64) = (*sin as f64, *cos as f64);
+    let origin = [
+        origin[0] * cos_f64 - origin[2] * sin_f64,
+        origin[1],
+        origin[0] * sin_f64 + origin[2] * cos_f64,
+    ];
+
+    let sin = Simd::splat(*sin);
+    let cos = Simd::splat(*cos);
+
+    let source = &tree.nodes[*source];
+    unsafe {
+        return (source.function_3d)(
+            tree,
+            &source,
+            origin,
+            x.mul_add(cos, -(z * sin)),
+            y,
+            x.mul_add(sin, z * cos),
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::simd::{LaneCount, Simd, StdFloat, SupportedLaneCount};
+
+use multiversion::multiversion;
+
+use crate::{
+    noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree},
+    simplex::{simplex_1d_with_derivative, simplex_2d_with_gradient, simplex_3d_with_gradient},
+};
+
+// Each octave is weighted by 1 / (1 + strength * |d|²), where d is the sum of the slopes of the
+// octaves before it. Steep areas get little detail from the higher octaves, while flat areas keep
+// it, like material that has been washed off the slopes and settled in the valleys.
+// https://iquilezles.org/articles/morenoise/
+//
+// The slopes are the gradients the simplex noise computes along with its values, in the
+// coordinates of the noise. The source is always simplex, it is checked when the noise is built.
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn erode_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Erode {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        strength,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let strength = Simd::splat(*strength);
+    let one = Simd::splat(1.0);
+
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+    let mut slope_x = Simd::splat(0.0);
+
+    let NoiseNodeSettings::Simplex {
+        seed, frequency_x, ..
+    } = tree.nodes[*source].settings
+    else {
+        unreachable!()
+    };
+    for _ in 0..(*octaves) {
+        let (noise, derivative) = simplex_1d_with_derivative(seed, frequency_x, x);
+        slope_x += derivative;
+
+        let damping = one / (slope_x * slope_x).mul_add(strength, one);
+        result += noise * amplitude * damping;
+        amplitude *= gain;
+        x *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn erode_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Erode {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        strength,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let strength = Simd::splat(*strength);
+    let one = Simd::splat(1.0);
+
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+    let mut slope_x = Simd::splat(0.0);
+    let mut slope_y = Simd::splat(0.0);
+
+    let NoiseNodeSettings::Simplex {
+        seed,
+        frequency_x,
+        frequency_z,
+        ..
+    } = tree.nodes[*source].settings
+    else {
+        unreachable!()
+    };
+    for _ in 0..(*octaves) {
+        let (noise, [dx, dy]) = simplex_2d_with_gradient(seed, [frequency_x, frequency_z], x, y);
+        slope_x += dx;
+        slope_y += dy;
+
+        let slope_squared = slope_y.mul_add(slope_y, slope_x * slope_x);
+        let damping = one / slope_squared.mul_add(strength, one);
+        result += noise * amplitude * damping;
+        amplitude *= gain;
+        x *= lacunarity;
+        y *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn erode_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut origin: [f64; 3],
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+    mut z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Erode {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        strength,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity_f64 = *lacunarity as f64;
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let strength = Simd::splat(*strength);
+    let one = Simd::splat(1.0);
+
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+    let mut slope_x = Simd::splat(0.0);
+    let mut slope_y = Simd::splat(0.0);
+    let mut slope_z = Simd::splat(0.0);
+
+    let NoiseNodeSettings::Simplex {
+        seed,
+        frequency_x,
+        frequency_y,
+        frequency_z,
+    } = tree.nodes[*source].settings
+    else {
+        unreachable!()
+    };
+    let frequency = [frequency_x, frequency_y, frequency_z];
+    for _ in 0..(*octaves) {
+        let (noise, [dx, dy, dz]) = simplex_3d_with_gradient(seed, frequency, origin, x, y, z);
+        slope_x += dx;
+        slope_y += dy;
+        slope_z += dz;
+
+        let slope_squared = slope_z.mul_add(slope_z, slope_y.mul_add(slope_y, slope_x * slope_x));
+        let damping = one / slope_squared.mul_add(strength, one);
+        result += noise * amplitude * damping;
+        amplitude *= gain;
+        origin = origin.map(|o| o * lacunarity_f64);
+        x *= lacunarity;
+        y *= lacunarity;
+        z *= lacunarity;
+    }
+
+    result
+}
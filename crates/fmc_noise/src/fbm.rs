@@ -2,7 +2,7 @@ use multiversion::multiversion;
 
 use crate::noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree};
 
-use std::simd::{LaneCount, Simd, SupportedLaneCount};
+use std::simd::{num::SimdFloat, LaneCount, Simd, SupportedLaneCount};
 
 #[multiversion(targets = "simd", dispatcher = "pointer")]
 pub fn fbm_1d<const N: usize>(
@@ -80,6 +80,7 @@ where
 pub fn fbm_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    mut origin: [f64; 3],
     mut x: Simd<f32, N>,
     mut y: Simd<f32, N>,
     mut z: Simd<f32, N>,
@@ -97,6 +98,7 @@ where
     else {
         unreachable!()
     };
+    let lacunarity_f64 = *lacunarity as f64;
     let lacunarity = Simd::splat(*lacunarity);
     let gain = Simd::splat(*gain);
     let mut amplitude = Simd::splat(*scale);
@@ -104,9 +106,238 @@ where
 
     let noise_node = &tree.nodes[*source];
     for _ in 0..(*octaves) {
-        let noise = unsafe { (noise_node.function_3d)(tree, &noise_node, x, y, z) };
+        let noise = unsafe { (noise_node.function_3d)(tree, &noise_node, origin, x, y, z) };
         result += noise * amplitude;
         amplitude *= gain;
+        origin = origin.map(|o| o * lacunarity_f64);
+        x *= lacunarity;
+        y *= lacunarity;
+        z *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn billow_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Billow {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_1d)(tree, &noise_node, x) };
+        result += (noise.abs() * Simd::splat(2.0) - Simd::splat(1.0)) * amplitude;
+        amplitude *= gain;
+        x *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn billow_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Billow {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_2d)(tree, &noise_node, x, y) };
+        result += (noise.abs() * Simd::splat(2.0) - Simd::splat(1.0)) * amplitude;
+        amplitude *= gain;
+        x *= lacunarity;
+        y *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn billow_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut origin: [f64; 3],
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+    mut z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Billow {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity_f64 = *lacunarity as f64;
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_3d)(tree, &noise_node, origin, x, y, z) };
+        result += (noise.abs() * Simd::splat(2.0) - Simd::splat(1.0)) * amplitude;
+        amplitude *= gain;
+        origin = origin.map(|o| o * lacunarity_f64);
+        x *= lacunarity;
+        y *= lacunarity;
+        z *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn turbulence_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Turbulence {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_1d)(tree, &noise_node, x) };
+        result += noise.abs() * amplitude;
+        amplitude *= gain;
+        x *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn turbulence_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Turbulence {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_2d)(tree, &noise_node, x, y) };
+        result += noise.abs() * amplitude;
+        amplitude *= gain;
+        x *= lacunarity;
+        y *= lacunarity;
+    }
+
+    result
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn turbulence_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    mut origin: [f64; 3],
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+    mut z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Turbulence {
+        octaves,
+        gain,
+        lacunarity,
+        scale,
+        source,
+    } = &node.settings
+    else {
+        unreachable!()
+    };
+    let lacunarity_f64 = *lacunarity as f64;
+    let lacunarity = Simd::splat(*lacunarity);
+    let gain = Simd::splat(*gain);
+    let mut amplitude = Simd::splat(*scale);
+    let mut result = Simd::splat(0.0);
+
+    let noise_node = &tree.nodes[*source];
+    for _ in 0..(*octaves) {
+        let noise = unsafe { (noise_node.function_3d)(tree, &noise_node, origin, x, y, z) };
+        result += noise.abs() * amplitude;
+        amplitude *= gain;
+        origin = origin.map(|o| o * lacunarity_f64);
         x *= lacunarity;
         y *= lacunarity;
         z *= lacunarity;
@@ -9,6 +9,8 @@ mod abs;
 mod add;
 mod clamp;
 mod constant;
+mod domain;
+mod erosion;
 mod fbm;
 mod gradient;
 mod lerp;
@@ -16,9 +18,14 @@ mod min_and_max;
 mod mul;
 mod noise_tree;
 mod perlin;
+mod pow;
+#[cfg(feature = "image")]
+mod preview;
 mod range;
 mod simplex;
 mod square;
+mod terrace;
+mod value;
 
 // TODO: Make a cargo feature "f64", makes it compile to f64 instead of f32
 //if cfg(f64)
@@ -58,6 +65,21 @@ impl Noise {
         };
     }
 
+    /// Value noise, every cell of the integer lattice is assigned a random value in the -1..1
+    /// range. The values are not interpolated between cells, making it a lot cheaper than the
+    /// gradient noises. It is meant for things like scattering ores and plants where smoothness
+    /// doesn't matter.
+    pub fn value(frequency: f32, seed: i32) -> Self {
+        return Self {
+            settings: NoiseSettings::Value {
+                seed,
+                frequency_x: frequency,
+                frequency_y: frequency,
+                frequency_z: frequency,
+            },
+        };
+    }
+
     pub fn constant(value: f32) -> Self {
         return Self {
             settings: NoiseSettings::Constant { value },
@@ -87,31 +109,101 @@ impl Noise {
                 *frequency_y = y;
                 *frequency_z = z;
             }
+            NoiseSettings::Value {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            } => {
+                *frequency_x = x;
+                *frequency_y = y;
+                *frequency_z = z;
+            }
             _ => {
-                // TODO: Recursively call and change the frequency for all noises?
-                panic!("Frequency can only be changed when no other steps have been added.")
+                panic!(
+                    "Frequency can only be changed when no other steps have been added, use \
+                    'scale_frequency' to change the frequency of composed noises."
+                )
             }
         }
         self
     }
 
+    /// Multiply the frequency of all the base noises the noise is composed of. Can be used at any
+    /// point to stretch or squash the noise, the relation between the frequencies of the base
+    /// noises is kept.
+    pub fn scale_frequency(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.settings.scale_frequency(x, y, z);
+        self
+    }
+
     /// Fractal Brownian Motion (layered noise)
     pub fn fbm(mut self, octaves: u32, gain: f32, lacunarity: f32) -> Self {
-        let mut amp = gain;
-        let mut scale = 1.0;
+        self.settings = NoiseSettings::Fbm {
+            octaves,
+            gain,
+            lacunarity,
+            scale: fbm_scale(octaves, gain),
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Fbm where each octave is folded, 'abs(noise) * 2 - 1', making billowy, cloud like noise.
+    /// The output stays in the -1..1 range.
+    pub fn billow(mut self, octaves: u32, gain: f32, lacunarity: f32) -> Self {
+        self.settings = NoiseSettings::Billow {
+            octaves,
+            gain,
+            lacunarity,
+            scale: fbm_scale(octaves, gain),
+            source: Box::new(self.settings),
+        };
+        self
+    }
 
-        for _ in 1..octaves {
-            scale += amp;
-            amp *= gain;
+    /// Fbm where the absolute value of each octave is summed. Produces sharp creases where the
+    /// noise crosses zero. The output is in the 0..1 range.
+    pub fn turbulence(mut self, octaves: u32, gain: f32, lacunarity: f32) -> Self {
+        self.settings = NoiseSettings::Turbulence {
+            octaves,
+            gain,
+            lacunarity,
+            scale: fbm_scale(octaves, gain),
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Turn fbm into eroded fbm. The detail of each octave is dampened where the octaves before
+    /// it are steep, so slopes are smooth while valleys and plateaus stay rough, much like real
+    /// mountains. Higher strength erodes more, 0.2 is a good starting point.
+    ///
+    /// The slopes are the derivatives of the simplex noise, so the fbm has to be made from
+    /// simplex noise. It costs little more than the fbm itself.
+    pub fn erode(mut self, strength: f32) -> Self {
+        let NoiseSettings::Fbm {
+            octaves,
+            gain,
+            lacunarity,
+            scale,
+            source,
+        } = self.settings
+        else {
+            panic!("Erosion can only be applied directly to fbm noise.")
+        };
+
+        if !matches!(*source, NoiseSettings::Simplex { .. }) {
+            panic!("Erosion can only be applied to fbm of simplex noise.")
         }
-        scale = 1.0 / scale;
 
-        self.settings = NoiseSettings::Fbm {
+        self.settings = NoiseSettings::Erode {
             octaves,
             gain,
             lacunarity,
             scale,
-            source: Box::new(self.settings),
+            strength,
+            source,
         };
         self
     }
@@ -208,6 +300,65 @@ impl Noise {
         self
     }
 
+    /// Raise the absolute value of the noise to the power of the exponent, the sign is kept.
+    /// Exponents above 1 push values towards zero and sharpen the peaks, below 1 it flattens
+    /// them.
+    pub fn pow(mut self, exponent: f32) -> Self {
+        self.settings = NoiseSettings::Pow {
+            exponent,
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Round the noise down into flat steps. 'steps' is the number of steps per unit of noise,
+    /// i.e. noise in the -1..1 range will have 2 * steps plateaus.
+    pub fn terrace(mut self, steps: u32) -> Self {
+        self.settings = NoiseSettings::Terrace {
+            steps: steps as f32,
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Move the noise by the given offset. Noises that are offset far enough from each other are
+    /// unrelated, which can be used instead of changing the seed. Keep the offsets moderate,
+    /// precision is lost the further from the origin the noise is sampled.
+    pub fn translate(mut self, x: f32, y: f32, z: f32) -> Self {
+        self.settings = NoiseSettings::Translate {
+            x,
+            y,
+            z,
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Rotate the noise around the y axis. 2d noise is rotated in its plane, 1d noise is left as
+    /// is. Turning the noise off the grid hides the axis aligned artifacts that show up in e.g.
+    /// ridged noise.
+    pub fn rotate_y(mut self, radians: f32) -> Self {
+        self.settings = NoiseSettings::RotateY {
+            radians,
+            source: Box::new(self.settings),
+        };
+        self
+    }
+
+    /// Rescale the noise to the -1..1 range. The range is derived from the steps the noise is
+    /// composed of, it is guaranteed to contain all the values, but they might not reach all the
+    /// way to -1 or 1.
+    pub fn normalized(self) -> Self {
+        let (min, max) = self.settings.bounds();
+        if min == max {
+            // Constant noise, there's no range to scale.
+            return self.add_value(-min);
+        }
+        return self
+            .add_value(-(min + max) * 0.5)
+            .mul_value(2.0 / (max - min));
+    }
+
     pub fn generate_1d(&self, x: f32, width: usize) -> (Vec<f32>, f32, f32) {
         generate_1d(self, x, width)
     }
@@ -216,6 +367,10 @@ impl Noise {
         generate_2d(self, x, y, width, height)
     }
 
+    /// Generate a 'width' x 'height' x 'depth' block of noise starting at (x, y, z).
+    ///
+    /// The values are laid out in xzy order, y varies fastest, so each column is contiguous.
+    /// The value at (x, y, z) is at index `x * (depth * height) + z * height + y`.
     pub fn generate_3d(
         &self,
         x: f32,
@@ -227,6 +382,58 @@ impl Noise {
     ) -> (Vec<f32>, f32, f32) {
         generate_3d(self, x, y, z, width, height, depth)
     }
+
+    /// Same as `generate_3d`, but sampled on the integer lattice of block coordinates. The noise
+    /// is sampled every 'step' blocks starting at 'position', so a width of 4 with a step of 4
+    /// covers 16 blocks.
+    ///
+    /// The position is kept in f64 and multiplied by the frequency of each base noise before the
+    /// whole cells are split off, so noise far from the origin is as precise as near it.
+    pub fn generate_3d_lattice(
+        &self,
+        position: impl Into<[i32; 3]>,
+        step: u32,
+        width: usize,
+        height: usize,
+        depth: usize,
+    ) -> (Vec<f32>, f32, f32) {
+        generate_3d_lattice(self, position.into(), step, width, height, depth)
+    }
+
+    /// Same as `generate_3d`, but the work is split along the x axis between as many threads as
+    /// there are available cores. Useful when generating large regions at once, for small ones
+    /// the cost of spawning the threads outweighs the gain.
+    pub fn generate_3d_parallel(
+        &self,
+        x: f32,
+        y: f32,
+        z: f32,
+        width: usize,
+        height: usize,
+        depth: usize,
+    ) -> (Vec<f32>, f32, f32) {
+        generate_3d_parallel(self, x, y, z, width, height, depth)
+    }
+
+    /// Generate 3d noise together with its gradient. Each derivative is the rate of change of the
+    /// noise per unit along the [x, y, z] axes, large values mean the noise is steep at that
+    /// point, values near zero that it is flat.
+    ///
+    /// The derivatives of plain simplex noise are exact, for all other noises they are
+    /// approximated from the neighbouring values.
+    ///
+    /// Returns (noise, derivatives, min, max), both vectors share the layout of `generate_3d`.
+    pub fn generate_3d_with_derivatives(
+        &self,
+        x: f32,
+        y: f32,
+        z: f32,
+        width: usize,
+        height: usize,
+        depth: usize,
+    ) -> (Vec<f32>, Vec<[f32; 3]>, f32, f32) {
+        generate_3d_with_derivatives(self, x, y, z, width, height, depth)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -243,6 +450,12 @@ enum NoiseSettings {
         frequency_y: f32,
         frequency_z: f32,
     },
+    Value {
+        seed: i32,
+        frequency_x: f32,
+        frequency_y: f32,
+        frequency_z: f32,
+    },
     Constant {
         value: f32,
     },
@@ -262,6 +475,31 @@ enum NoiseSettings {
         scale: f32,
         source: Box<NoiseSettings>,
     },
+    // Same settings as Fbm
+    Billow {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        source: Box<NoiseSettings>,
+    },
+    // Same settings as Fbm
+    Turbulence {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        source: Box<NoiseSettings>,
+    },
+    // Same settings as Fbm
+    Erode {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        strength: f32,
+        source: Box<NoiseSettings>,
+    },
     Abs {
         source: Box<NoiseSettings>,
     },
@@ -305,6 +543,270 @@ enum NoiseSettings {
     Square {
         source: Box<NoiseSettings>,
     },
+    Pow {
+        exponent: f32,
+        source: Box<NoiseSettings>,
+    },
+    Terrace {
+        steps: f32,
+        source: Box<NoiseSettings>,
+    },
+    Translate {
+        x: f32,
+        y: f32,
+        z: f32,
+        source: Box<NoiseSettings>,
+    },
+    RotateY {
+        radians: f32,
+        source: Box<NoiseSettings>,
+    },
+}
+
+// Scaling factor that keeps the sum of all the octaves' amplitudes at 1.
+fn fbm_scale(octaves: u32, gain: f32) -> f32 {
+    let mut amp = gain;
+    let mut scale = 1.0;
+
+    for _ in 1..octaves {
+        scale += amp;
+        amp *= gain;
+    }
+
+    return 1.0 / scale;
+}
+
+impl NoiseSettings {
+    // The (min, max) range the values of the noise are guaranteed to be inside.
+    fn bounds(&self) -> (f32, f32) {
+        fn abs_bounds((min, max): (f32, f32)) -> (f32, f32) {
+            if min >= 0.0 {
+                (min, max)
+            } else if max <= 0.0 {
+                (-max, -min)
+            } else {
+                (0.0, max.max(-min))
+            }
+        }
+
+        match self {
+            NoiseSettings::Simplex { .. }
+            | NoiseSettings::Perlin { .. }
+            | NoiseSettings::Value { .. } => (-1.0, 1.0),
+            NoiseSettings::Constant { value } => (*value, *value),
+            // The octave amplitudes sum to 1, so the result is a weighted average of the source.
+            NoiseSettings::Fbm { source, .. } => source.bounds(),
+            // Like fbm, but the octaves are dampened towards zero.
+            NoiseSettings::Erode { source, .. } => {
+                let (min, max) = source.bounds();
+                (min.min(0.0), max.max(0.0))
+            }
+            NoiseSettings::Billow { source, .. } => {
+                let (min, max) = abs_bounds(source.bounds());
+                (min * 2.0 - 1.0, max * 2.0 - 1.0)
+            }
+            NoiseSettings::Turbulence { source, .. } | NoiseSettings::Abs { source } => {
+                abs_bounds(source.bounds())
+            }
+            NoiseSettings::AddNoise { left, right } => {
+                let (left_min, left_max) = left.bounds();
+                let (right_min, right_max) = right.bounds();
+                (left_min + right_min, left_max + right_max)
+            }
+            NoiseSettings::AddValue { value, source } => {
+                let (min, max) = source.bounds();
+                (min + value, max + value)
+            }
+            NoiseSettings::Clamp { min, max, source } => {
+                let (source_min, source_max) = source.bounds();
+                (source_min.clamp(*min, *max), source_max.clamp(*min, *max))
+            }
+            NoiseSettings::Max { left, right } => {
+                let (left_min, left_max) = left.bounds();
+                let (right_min, right_max) = right.bounds();
+                (left_min.max(right_min), left_max.max(right_max))
+            }
+            NoiseSettings::Min { left, right } => {
+                let (left_min, left_max) = left.bounds();
+                let (right_min, right_max) = right.bounds();
+                (left_min.min(right_min), left_max.min(right_max))
+            }
+            NoiseSettings::MulValue { value, source } => {
+                let (min, max) = source.bounds();
+                let (a, b) = (min * value, max * value);
+                (a.min(b), a.max(b))
+            }
+            NoiseSettings::Lerp {
+                selector_source,
+                high_source,
+                low_source,
+            } => {
+                // Same shift of the selector as the lerp implementation.
+                let (selector_min, selector_max) = selector_source.bounds();
+                let (low_min, low_max) = low_source.bounds();
+                let (high_min, high_max) = high_source.bounds();
+
+                // The lerp is linear in each of its inputs, so the extremes are at the corners.
+                let mut min = f32::MAX;
+                let mut max = f32::MIN;
+                for t in [selector_min * 0.5 + 0.5, selector_max * 0.5 + 0.5] {
+                    for low in [low_min, low_max] {
+                        for high in [high_min, high_max] {
+                            let value = (high - low) * t + low;
+                            min = min.min(value);
+                            max = max.max(value);
+                        }
+                    }
+                }
+                (min, max)
+            }
+            NoiseSettings::Range {
+                high_source,
+                low_source,
+                ..
+            } => {
+                let (low_min, low_max) = low_source.bounds();
+                let (high_min, high_max) = high_source.bounds();
+                (low_min.min(high_min), low_max.max(high_max))
+            }
+            NoiseSettings::Square { source } => {
+                let (min, max) = abs_bounds(source.bounds());
+                (min * min, max * max)
+            }
+            NoiseSettings::Pow { exponent, source } => {
+                let (min, max) = source.bounds();
+                (
+                    min.abs().powf(*exponent).copysign(min),
+                    max.abs().powf(*exponent).copysign(max),
+                )
+            }
+            NoiseSettings::Terrace { steps, source } => {
+                let (min, max) = source.bounds();
+                ((min * steps).floor() / steps, (max * steps).floor() / steps)
+            }
+            // Moving the domain doesn't change the values.
+            NoiseSettings::Translate { source, .. } | NoiseSettings::RotateY { source, .. } => {
+                source.bounds()
+            }
+        }
+    }
+
+    // The highest frequency of the base noises the noise is composed of.
+    fn frequency(&self) -> f32 {
+        match self {
+            NoiseSettings::Simplex {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            }
+            | NoiseSettings::Perlin {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            }
+            | NoiseSettings::Value {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            } => frequency_x.max(*frequency_y).max(*frequency_z),
+            NoiseSettings::Constant { .. } => 0.0,
+            NoiseSettings::Fbm { source, .. }
+            | NoiseSettings::Erode { source, .. }
+            | NoiseSettings::Billow { source, .. }
+            | NoiseSettings::Turbulence { source, .. }
+            | NoiseSettings::Abs { source }
+            | NoiseSettings::AddValue { source, .. }
+            | NoiseSettings::Clamp { source, .. }
+            | NoiseSettings::MulValue { source, .. }
+            | NoiseSettings::Square { source }
+            | NoiseSettings::Pow { source, .. }
+            | NoiseSettings::Terrace { source, .. }
+            | NoiseSettings::Translate { source, .. }
+            | NoiseSettings::RotateY { source, .. } => source.frequency(),
+            NoiseSettings::AddNoise { left, right }
+            | NoiseSettings::Max { left, right }
+            | NoiseSettings::Min { left, right } => left.frequency().max(right.frequency()),
+            NoiseSettings::Lerp {
+                selector_source,
+                high_source,
+                low_source,
+            }
+            | NoiseSettings::Range {
+                selector_source,
+                high_source,
+                low_source,
+                ..
+            } => selector_source
+                .frequency()
+                .max(high_source.frequency())
+                .max(low_source.frequency()),
+        }
+    }
+
+    fn scale_frequency(&mut self, x: f32, y: f32, z: f32) {
+        match self {
+            NoiseSettings::Simplex {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            }
+            | NoiseSettings::Perlin {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            }
+            | NoiseSettings::Value {
+                frequency_x,
+                frequency_y,
+                frequency_z,
+                ..
+            } => {
+                *frequency_x *= x;
+                *frequency_y *= y;
+                *frequency_z *= z;
+            }
+            NoiseSettings::Constant { .. } => (),
+            NoiseSettings::Fbm { source, .. }
+            | NoiseSettings::Erode { source, .. }
+            | NoiseSettings::Billow { source, .. }
+            | NoiseSettings::Turbulence { source, .. }
+            | NoiseSettings::Abs { source }
+            | NoiseSettings::AddValue { source, .. }
+            | NoiseSettings::Clamp { source, .. }
+            | NoiseSettings::MulValue { source, .. }
+            | NoiseSettings::Square { source }
+            | NoiseSettings::Pow { source, .. }
+            | NoiseSettings::Terrace { source, .. }
+            | NoiseSettings::Translate { source, .. }
+            | NoiseSettings::RotateY { source, .. } => source.scale_frequency(x, y, z),
+            NoiseSettings::AddNoise { left, right }
+            | NoiseSettings::Max { left, right }
+            | NoiseSettings::Min { left, right } => {
+                left.scale_frequency(x, y, z);
+                right.scale_frequency(x, y, z);
+            }
+            NoiseSettings::Lerp {
+                selector_source,
+                high_source,
+                low_source,
+            }
+            | NoiseSettings::Range {
+                selector_source,
+                high_source,
+                low_source,
+                ..
+            } => {
+                selector_source.scale_frequency(x, y, z);
+                high_source.scale_frequency(x, y, z);
+                low_source.scale_frequency(x, y, z);
+            }
+        }
+    }
 }
 
 #[multiversion(targets = "simd")]
@@ -496,7 +998,9 @@ fn generate_3d(
         for _ in 0..depth {
             let mut y = Simd::from_slice(&y_arr);
             for _ in 0..height / vector_width {
-                let f = unsafe { (tree.nodes[0].function_3d)(&tree, &tree.nodes[0], x, y, z) };
+                let f = unsafe {
+                    (tree.nodes[0].function_3d)(&tree, &tree.nodes[0], [0.0; 3], x, y, z)
+                };
                 max_s = max_s.simd_max(f);
                 min_s = min_s.simd_min(f);
                 f.copy_to_slice(&mut result[i..]);
@@ -504,7 +1008,9 @@ fn generate_3d(
                 y = y + Simd::splat(vector_width as f32);
             }
             if remainder != 0 {
-                let f = unsafe { (tree.nodes[0].function_3d)(&tree, &tree.nodes[0], x, y, z) };
+                let f = unsafe {
+                    (tree.nodes[0].function_3d)(&tree, &tree.nodes[0], [0.0; 3], x, y, z)
+                };
                 for j in 0..remainder {
                     let n = f[j];
                     unsafe {
@@ -533,3 +1039,247 @@ fn generate_3d(
     }
     (result, min, max)
 }
+
+#[multiversion(targets = "simd")]
+fn generate_3d_lattice(
+    noise: &Noise,
+    position: [i32; 3],
+    step: u32,
+    width: usize,
+    height: usize,
+    depth: usize,
+) -> (Vec<f32>, f32, f32) {
+    const N: usize = if let Some(size) = selected_target!().suggested_simd_width::<f32>() {
+        size
+    } else {
+        1
+    };
+    let tree = noise_tree::NoiseTree::<N>::new(noise);
+
+    // The position is passed down as the origin, the samples are given relative to it.
+    let origin = position.map(|p| p as f64);
+    let coordinate = |index: usize| -> f32 { (index as u64 * step as u64) as f32 };
+
+    let mut min_s = Simd::splat(f32::MAX);
+    let mut max_s = Simd::splat(f32::MIN);
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+
+    let mut result = Vec::with_capacity(width * height * depth);
+    let mut y_arr = [0.0; N];
+
+    for x_index in 0..width {
+        let x = Simd::splat(coordinate(x_index));
+        for z_index in 0..depth {
+            let z = Simd::splat(coordinate(z_index));
+            for y_start in (0..height).step_by(N) {
+                for (lane, y) in y_arr.iter_mut().enumerate() {
+                    *y = coordinate(y_start + lane);
+                }
+                let y = Simd::from_array(y_arr);
+
+                let f = unsafe {
+                    (tree.nodes[0].function_3d)(&tree, &tree.nodes[0], origin, x, y, z)
+                };
+
+                let lanes = N.min(height - y_start);
+                if lanes == N {
+                    max_s = max_s.simd_max(f);
+                    min_s = min_s.simd_min(f);
+                    result.extend_from_slice(f.as_array());
+                } else {
+                    for &n in &f.as_array()[..lanes] {
+                        min = min.min(n);
+                        max = max.max(n);
+                        result.push(n);
+                    }
+                }
+            }
+        }
+    }
+
+    min = min.min(min_s.reduce_min());
+    max = max.max(max_s.reduce_max());
+
+    (result, min, max)
+}
+
+// Simplex noise computes its gradient along with the value, so it is used as is when the noise is
+// nothing else. For other noises the derivatives are approximated by central differences over a
+// grid that is padded by one unit in every direction. Doing it analytically would require every
+// node to propagate derivatives through its operation, this works for any composition of noises.
+fn generate_3d_with_derivatives(
+    noise: &Noise,
+    x: f32,
+    y: f32,
+    z: f32,
+    width: usize,
+    height: usize,
+    depth: usize,
+) -> (Vec<f32>, Vec<[f32; 3]>, f32, f32) {
+    if let NoiseSettings::Simplex {
+        seed,
+        frequency_x,
+        frequency_y,
+        frequency_z,
+    } = noise.settings
+    {
+        return generate_3d_simplex_with_derivatives(
+            seed,
+            [frequency_x, frequency_y, frequency_z],
+            [x, y, z],
+            [width, height, depth],
+        );
+    }
+
+    let padded_height = height + 2;
+    let padded_depth = depth + 2;
+    let (padded, _, _) = generate_3d(
+        noise,
+        x - 1.0,
+        y - 1.0,
+        z - 1.0,
+        width + 2,
+        padded_height,
+        padded_depth,
+    );
+
+    let index = |x: usize, y: usize, z: usize| -> usize {
+        x * (padded_depth * padded_height) + z * padded_height + y
+    };
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+
+    let mut result = Vec::with_capacity(width * height * depth);
+    let mut derivatives = Vec::with_capacity(width * height * depth);
+
+    for x in 1..=width {
+        for z in 1..=depth {
+            for y in 1..=height {
+                let n = padded[index(x, y, z)];
+                if n < min {
+                    min = n;
+                }
+                if n > max {
+                    max = n;
+                }
+                result.push(n);
+                derivatives.push([
+                    (padded[index(x + 1, y, z)] - padded[index(x - 1, y, z)]) * 0.5,
+                    (padded[index(x, y + 1, z)] - padded[index(x, y - 1, z)]) * 0.5,
+                    (padded[index(x, y, z + 1)] - padded[index(x, y, z - 1)]) * 0.5,
+                ]);
+            }
+        }
+    }
+
+    (result, derivatives, min, max)
+}
+
+#[multiversion(targets = "simd")]
+fn generate_3d_simplex_with_derivatives(
+    seed: i32,
+    frequency: [f32; 3],
+    position: [f32; 3],
+    size: [usize; 3],
+) -> (Vec<f32>, Vec<[f32; 3]>, f32, f32) {
+    const N: usize = if let Some(size) = selected_target!().suggested_simd_width::<f32>() {
+        size
+    } else {
+        1
+    };
+
+    let [start_x, start_y, start_z] = position;
+    let [width, height, depth] = size;
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+
+    let mut result = Vec::with_capacity(width * height * depth);
+    let mut derivatives = Vec::with_capacity(width * height * depth);
+    let mut y_arr = [0.0; N];
+
+    for x_index in 0..width {
+        let x = Simd::splat(start_x + x_index as f32);
+        for z_index in 0..depth {
+            let z = Simd::splat(start_z + z_index as f32);
+            for y_start in (0..height).step_by(N) {
+                for (lane, y) in y_arr.iter_mut().enumerate() {
+                    *y = start_y + (y_start + lane) as f32;
+                }
+                let y = Simd::from_array(y_arr);
+
+                let (f, gradient) =
+                    simplex::simplex_3d_with_gradient(seed, frequency, [0.0; 3], x, y, z);
+
+                for lane in 0..N.min(height - y_start) {
+                    let n = f[lane];
+                    min = min.min(n);
+                    max = max.max(n);
+                    result.push(n);
+                    // The gradient is in the coordinates of the noise, scale it back to units.
+                    derivatives.push([
+                        gradient[0][lane] * frequency[0],
+                        gradient[1][lane] * frequency[1],
+                        gradient[2][lane] * frequency[2],
+                    ]);
+                }
+            }
+        }
+    }
+
+    (result, derivatives, min, max)
+}
+
+// x is the outermost axis of the output, so splitting along it lets each thread produce a
+// contiguous part of the result.
+fn generate_3d_parallel(
+    noise: &Noise,
+    x: f32,
+    y: f32,
+    z: f32,
+    width: usize,
+    height: usize,
+    depth: usize,
+) -> (Vec<f32>, f32, f32) {
+    let thread_count = std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(width);
+
+    if thread_count <= 1 {
+        return generate_3d(noise, x, y, z, width, height, depth);
+    }
+
+    let slice_width = width.div_ceil(thread_count);
+
+    let slices: Vec<(Vec<f32>, f32, f32)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..width)
+            .step_by(slice_width)
+            .map(|start| {
+                let slice_width = slice_width.min(width - start);
+                scope.spawn(move || {
+                    generate_3d(noise, x + start as f32, y, z, slice_width, height, depth)
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    let mut min = f32::MAX;
+    let mut max = f32::MIN;
+    let mut result = Vec::with_capacity(width * height * depth);
+
+    for (slice, slice_min, slice_max) in slices {
+        result.extend(slice);
+        min = min.min(slice_min);
+        max = max.max(slice_max);
+    }
+
+    (result, min, max)
+}
@@ -66,6 +66,7 @@ where
 pub fn max_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -84,10 +85,11 @@ where
     let left_node = &tree.nodes[*left_source];
     let right_node = &tree.nodes[*right_source];
     unsafe {
-        return (left_node.function_3d)(tree, &left_node, x, y, z).simd_max((right_node
+        return (left_node.function_3d)(tree, &left_node, origin, x, y, z).simd_max((right_node
             .function_3d)(
             tree,
             &right_node,
+            origin,
             x,
             y,
             z,
@@ -157,6 +159,7 @@ where
 pub fn min_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -175,10 +178,11 @@ where
     let left_node = &tree.nodes[*left_source];
     let right_node = &tree.nodes[*right_source];
     unsafe {
-        return (left_node.function_3d)(tree, &left_node, x, y, z).simd_min((right_node
+        return (left_node.function_3d)(tree, &left_node, origin, x, y, z).simd_min((right_node
             .function_3d)(
             tree,
             &right_node,
+            origin,
             x,
             y,
             z,
@@ -1,4 +1,4 @@
-use std::simd::{LaneCount, Simd, SupportedLaneCount};
+use std::simd::{LaneCount, Simd, StdFloat, SupportedLaneCount};
 
 use crate::{Noise, NoiseSettings};
 
@@ -56,6 +56,24 @@ where
                         function_3d: crate::perlin::perlin_3d(),
                     });
                 }
+                NoiseSettings::Value {
+                    seed,
+                    frequency_x,
+                    frequency_y,
+                    frequency_z,
+                } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Value {
+                            seed: *seed,
+                            frequency_x: *frequency_x,
+                            frequency_y: *frequency_y,
+                            frequency_z: *frequency_z,
+                        },
+                        function_1d: crate::value::value_1d(),
+                        function_2d: crate::value::value_2d(),
+                        function_3d: crate::value::value_3d(),
+                    });
+                }
                 NoiseSettings::Constant { value } => {
                     nodes.push(NoiseNode {
                         settings: NoiseNodeSettings::Constant { value: *value },
@@ -85,6 +103,71 @@ where
                     });
                     add_node(nodes, source);
                 }
+                NoiseSettings::Billow {
+                    octaves,
+                    gain,
+                    lacunarity,
+                    scale,
+                    source,
+                } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Billow {
+                            octaves: *octaves,
+                            gain: *gain,
+                            lacunarity: *lacunarity,
+                            scale: *scale,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::fbm::billow_1d(),
+                        function_2d: crate::fbm::billow_2d(),
+                        function_3d: crate::fbm::billow_3d(),
+                    });
+                    add_node(nodes, source);
+                }
+                NoiseSettings::Turbulence {
+                    octaves,
+                    gain,
+                    lacunarity,
+                    scale,
+                    source,
+                } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Turbulence {
+                            octaves: *octaves,
+                            gain: *gain,
+                            lacunarity: *lacunarity,
+                            scale: *scale,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::fbm::turbulence_1d(),
+                        function_2d: crate::fbm::turbulence_2d(),
+                        function_3d: crate::fbm::turbulence_3d(),
+                    });
+                    add_node(nodes, source);
+                }
+                NoiseSettings::Erode {
+                    octaves,
+                    gain,
+                    lacunarity,
+                    scale,
+                    strength,
+                    source,
+                } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Erode {
+                            octaves: *octaves,
+                            gain: *gain,
+                            lacunarity: *lacunarity,
+                            scale: *scale,
+                            strength: *strength,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::erosion::erode_1d(),
+                        function_2d: crate::erosion::erode_2d(),
+                        function_3d: crate::erosion::erode_3d(),
+                    });
+                    add_node(nodes, source);
+                }
                 NoiseSettings::Abs { source } => {
                     nodes.push(NoiseNode {
                         settings: NoiseNodeSettings::Abs {
@@ -306,6 +389,57 @@ where
                     });
                     add_node(nodes, source);
                 }
+                NoiseSettings::Pow { exponent, source } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Pow {
+                            exponent: *exponent,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::pow::pow_1d(),
+                        function_2d: crate::pow::pow_2d(),
+                        function_3d: crate::pow::pow_3d(),
+                    });
+                    add_node(nodes, source);
+                }
+                NoiseSettings::Terrace { steps, source } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Terrace {
+                            steps: *steps,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::terrace::terrace_1d(),
+                        function_2d: crate::terrace::terrace_2d(),
+                        function_3d: crate::terrace::terrace_3d(),
+                    });
+                    add_node(nodes, source);
+                }
+                NoiseSettings::Translate { x, y, z, source } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::Translate {
+                            x: *x,
+                            y: *y,
+                            z: *z,
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::domain::translate_1d(),
+                        function_2d: crate::domain::translate_2d(),
+                        function_3d: crate::domain::translate_3d(),
+                    });
+                    add_node(nodes, source);
+                }
+                NoiseSettings::RotateY { radians, source } => {
+                    nodes.push(NoiseNode {
+                        settings: NoiseNodeSettings::RotateY {
+                            sin: radians.sin(),
+                            cos: radians.cos(),
+                            source: nodes.len() + 1,
+                        },
+                        function_1d: crate::domain::rotate_y_1d(),
+                        function_2d: crate::domain::rotate_y_2d(),
+                        function_3d: crate::domain::rotate_y_3d(),
+                    });
+                    add_node(nodes, source);
+                }
             };
         }
         let mut nodes = Vec::with_capacity(8);
@@ -329,6 +463,12 @@ pub(crate) enum NoiseNodeSettings {
         frequency_y: f32,
         frequency_z: f32,
     },
+    Value {
+        seed: i32,
+        frequency_x: f32,
+        frequency_y: f32,
+        frequency_z: f32,
+    },
     Constant {
         value: f32,
     },
@@ -339,6 +479,29 @@ pub(crate) enum NoiseNodeSettings {
         scale: f32,
         source: usize,
     },
+    Billow {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        source: usize,
+    },
+    Turbulence {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        source: usize,
+    },
+    Erode {
+        octaves: u32,
+        gain: f32,
+        lacunarity: f32,
+        scale: f32,
+        strength: f32,
+        // Always simplex
+        source: usize,
+    },
     Abs {
         source: usize,
     },
@@ -382,6 +545,25 @@ pub(crate) enum NoiseNodeSettings {
     Square {
         source: usize,
     },
+    Pow {
+        exponent: f32,
+        source: usize,
+    },
+    Terrace {
+        steps: f32,
+        source: usize,
+    },
+    Translate {
+        x: f32,
+        y: f32,
+        z: f32,
+        source: usize,
+    },
+    RotateY {
+        sin: f32,
+        cos: f32,
+        source: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -398,9 +580,12 @@ where
         x: Simd<f32, N>,
         y: Simd<f32, N>,
     ) -> Simd<f32, N>,
+    /// The coordinates are relative to the origin. It is kept in f64 so that noise far from
+    /// the world origin keeps its precision, see `split_origin`.
     pub function_3d: unsafe fn(
         noise_tree: &NoiseTree<N>,
         node: &NoiseNode<N>,
+        origin: [f64; 3],
         x: Simd<f32, N>,
         y: Simd<f32, N>,
         z: Simd<f32, N>,
@@ -435,3 +620,23 @@ where
 //        return traverse(value);
 //    }
 //}
+
+/// Multiplies a coordinate by the frequency, with the origin multiplied in f64. The whole cells of
+/// the origin are split off and returned separately, wrapped to i32 the same way the hashes wrap,
+/// so the f32 coordinate only has to hold the fraction.
+#[inline(always)]
+pub(crate) fn split_origin<const N: usize>(
+    origin: f64,
+    frequency: f32,
+    x: Simd<f32, N>,
+) -> (Simd<i32, N>, Simd<f32, N>)
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let scaled = origin * frequency as f64;
+    let cell = scaled.floor();
+    return (
+        Simd::splat(cell as i64 as i32),
+        x.mul_add(Simd::splat(frequency), Simd::splat((scaled - cell) as f32)),
+    );
+}
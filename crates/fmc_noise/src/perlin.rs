@@ -7,7 +7,7 @@ use crate::gradient::grad3d_dot;
 use crate::gradient::hash2d;
 use crate::gradient::hash3d;
 use crate::gradient::{grad1, grad2};
-use crate::noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree};
+use crate::noise_tree::{split_origin, NoiseNode, NoiseNodeSettings, NoiseTree};
 
 pub const X_PRIME: i32 = 501125321;
 pub const Y_PRIME: i32 = 1136930381;
@@ -75,9 +75,10 @@ where
 pub fn perlin_3d<const N: usize>(
     _tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
-    mut x: Simd<f32, N>,
-    mut y: Simd<f32, N>,
-    mut z: Simd<f32, N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
 ) -> Simd<f32, N>
 where
     LaneCount<N>: SupportedLaneCount,
@@ -94,17 +95,17 @@ where
 
     let seed = Simd::splat(seed);
 
-    x *= Simd::splat(frequency_x);
-    y *= Simd::splat(frequency_y);
-    z *= Simd::splat(frequency_z);
+    let (cell_x, x) = split_origin(origin[0], frequency_x, x);
+    let (cell_y, y) = split_origin(origin[1], frequency_y, y);
+    let (cell_z, z) = split_origin(origin[2], frequency_z, z);
 
     let mut xs = x.floor();
     let mut ys = y.floor();
     let mut zs = z.floor();
 
-    let x0 = unsafe { xs.to_int_unchecked() * Simd::splat(X_PRIME) };
-    let y0 = unsafe { ys.to_int_unchecked() * Simd::splat(Y_PRIME) };
-    let z0 = unsafe { zs.to_int_unchecked() * Simd::splat(Z_PRIME) };
+    let x0 = unsafe { (xs.to_int_unchecked() + cell_x) * Simd::splat(X_PRIME) };
+    let y0 = unsafe { (ys.to_int_unchecked() + cell_y) * Simd::splat(Y_PRIME) };
+    let z0 = unsafe { (zs.to_int_unchecked() + cell_z) * Simd::splat(Z_PRIME) };
     let x1 = x0 + Simd::splat(X_PRIME);
     let y1 = y0 + Simd::splat(Y_PRIME);
     let z1 = z0 + Simd::splat(Z_PRIME);
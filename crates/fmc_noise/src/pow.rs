@@ -0,0 +1,81 @@
+use std::simd::{LaneCount, Simd, SupportedLaneCount};
+
+use multiversion::multiversion;
+
+use crate::noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree};
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn pow_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Pow { exponent, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_1d)(tree, &source, x) };
+    // TODO: There's no simd powf, so it is done one lane at a time.
+    // The sign is kept so that negative values stay negative, the curve is symmetric around 0.
+    return Simd::from_array(
+        source_result
+            .to_array()
+            .map(|value| value.abs().powf(*exponent).copysign(value)),
+    );
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn pow_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Pow { exponent, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_2d)(tree, &source, x, y) };
+    // TODO: There's no simd powf, so it is done one lane at a time.
+    // The sign is kept so that negative values stay negative, the curve is symmetric around 0.
+    return Simd::from_array(
+        source_result
+            .to_array()
+            .map(|value| value.abs().powf(*exponent).copysign(value)),
+    );
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn pow_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Pow { exponent, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_3d)(tree, &source, origin, x, y, z) };
+    // TODO: There's no simd powf, so it is done one lane at a time.
+    // The sign is kept so that negative values stay negative, the curve is symmetric around 0.
+    return Simd::from_array(
+        source_result
+            .to_array()
+            .map(|value| value.abs().powf(*exponent).copysign(value)),
+    );
+}
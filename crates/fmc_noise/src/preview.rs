@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use image::{GrayImage, ImageResult, Luma};
+
+use crate::Noise;
+
+impl Noise {
+    /// Render a horizontal slice of the noise at 'slice_y' to a grayscale png, one pixel per
+    /// unit, with x along the width and z along the height. The values are scaled so the lowest
+    /// one in the slice is black and the highest white.
+    ///
+    /// Useful for tuning noise settings without having to run the server and fly around.
+    pub fn save_preview(
+        &self,
+        path: impl AsRef<Path>,
+        width: usize,
+        height: usize,
+        slice_y: f32,
+    ) -> ImageResult<()> {
+        let (noise, min, max) = self.generate_3d(0.0, slice_y, 0.0, width, 1, height);
+        let range = if max > min { max - min } else { 1.0 };
+
+        let image = GrayImage::from_fn(width as u32, height as u32, |x, z| {
+            let value = noise[x as usize * height + z as usize];
+            Luma([((value - min) / range * 255.0) as u8])
+        });
+
+        return image.save(path);
+    }
+}
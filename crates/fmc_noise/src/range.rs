@@ -95,6 +95,7 @@ where
 pub fn range_3d<const N: usize>(
     tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
+    origin: [f64; 3],
     x: Simd<f32, N>,
     y: Simd<f32, N>,
     z: Simd<f32, N>,
@@ -120,9 +121,9 @@ where
     let high_node = &tree.nodes[*high_source];
 
     unsafe {
-        let selection_noise = (selector.function_3d)(tree, &selector, x, y, z);
-        let low_noise = (low_node.function_3d)(tree, &low_node, x, y, z);
-        let high_noise = (high_node.function_3d)(tree, &high_node, x, y, z);
+        let selection_noise = (selector.function_3d)(tree, &selector, origin, x, y, z);
+        let low_noise = (low_node.function_3d)(tree, &low_node, origin, x, y, z);
+        let high_noise = (high_node.function_3d)(tree, &high_node, origin, x, y, z);
 
         let high_clipped = selection_noise.simd_gt(high);
         let low_clipped = selection_noise.simd_lt(low);
@@ -50,19 +50,39 @@ const PERM: [i32; 512] = [
 pub fn simplex_1d<const N: usize>(
     _tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
-    mut x: Simd<f32, N>,
+    x: Simd<f32, N>,
 ) -> Simd<f32, N>
 where
     LaneCount<N>: SupportedLaneCount,
 {
-    let NoiseNodeSettings::Simplex {
+    // Perlin noise uses this for its 1d samples too.
+    let (NoiseNodeSettings::Simplex {
         seed, frequency_x, ..
-    } = node.settings
+    }
+    | NoiseNodeSettings::Perlin {
+        seed, frequency_x, ..
+    }) = node.settings
     else {
         unreachable!()
     };
+
+    return simplex_1d_with_derivative(seed, frequency_x, x).0;
+}
+
+/// Samples 1-dimensional simplex noise together with its derivative. The derivative is taken
+/// along the coordinate after it has been multiplied by the frequency. When only the value is
+/// used, the derivative is optimized away.
+#[inline(always)]
+pub fn simplex_1d_with_derivative<const N: usize>(
+    seed: i32,
+    frequency: f32,
+    mut x: Simd<f32, N>,
+) -> (Simd<f32, N>, Simd<f32, N>)
+where
+    LaneCount<N>: SupportedLaneCount,
+{
     let seed = Simd::splat(seed);
-    let freq = Simd::splat(frequency_x);
+    let freq = Simd::splat(frequency);
     x *= freq;
 
     // Gradients are selected deterministically based on the whole part of `x`
@@ -112,11 +132,10 @@ where
     const SCALE: f32 = 256.0 / (81.0 * 7.0);
 
     let value = (n0 + n1) * Simd::splat(SCALE);
-    //let derivative = ((t20 * t0 * gx0 * x20 + t21 * t1 * gx1 * x21) * Simd::splat(-8.0)
-    //    + t40 * gx0
-    //    + t41 * gx1)
-    //    * Simd::splat(SCALE);
-    value
+    let derivative =
+        ((t20 * t0 * gx0 * x20 + t21 * t1 * gx1 * x21) * Simd::splat(-8.0) + t40 * gx0 + t41 * gx1)
+            * Simd::splat(SCALE);
+    (value, derivative)
 }
 
 /// Samples 2-dimensional simplex noise
@@ -126,16 +145,12 @@ where
 pub fn simplex_2d<const N: usize>(
     _tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
-    mut x: Simd<f32, N>,
-    mut y: Simd<f32, N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
 ) -> Simd<f32, N>
 where
     LaneCount<N>: SupportedLaneCount,
 {
-    const SQRT3: f32 = 1.7320508075688772935274463415059;
-    const F2: f32 = 0.5 * (SQRT3 - 1.0);
-    const G2: f32 = (3.0 - SQRT3) / 6.0;
-
     let NoiseNodeSettings::Simplex {
         seed,
         frequency_x,
@@ -145,9 +160,31 @@ where
     else {
         unreachable!()
     };
+
+    return simplex_2d_with_gradient(seed, [frequency_x, frequency_z], x, y).0;
+}
+
+/// Samples 2-dimensional simplex noise together with its gradient. The gradient is taken in the
+/// coordinates after they have been multiplied by the frequency. When only the value is used,
+/// the gradient is optimized away.
+#[inline(always)]
+pub fn simplex_2d_with_gradient<const N: usize>(
+    seed: i32,
+    frequency: [f32; 2],
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+) -> (Simd<f32, N>, [Simd<f32, N>; 2])
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    const SQRT3: f32 = 1.7320508075688772935274463415059;
+    const F2: f32 = 0.5 * (SQRT3 - 1.0);
+    const G2: f32 = (3.0 - SQRT3) / 6.0;
+    const SCALE: f32 = 38.283687591552734375;
+
     let seed = Simd::splat(seed);
-    x *= Simd::splat(frequency_x);
-    y *= Simd::splat(frequency_z);
+    x *= Simd::splat(frequency[0]);
+    y *= Simd::splat(frequency[1]);
 
     let f = Simd::splat(F2) * (x + y);
     let mut x0 = (x + f).floor();
@@ -177,6 +214,11 @@ where
     t1 = t1.simd_max(Simd::splat(0.0));
     t2 = t2.simd_max(Simd::splat(0.0));
 
+    // t = 0.5 - |d|², so the gradient of t⁴ is 4t³ * -2d.
+    let dt0 = Simd::splat(-8.0) * t0 * t0 * t0;
+    let dt1 = Simd::splat(-8.0) * t1 * t1 * t1;
+    let dt2 = Simd::splat(-8.0) * t2 * t2 * t2;
+
     t0 *= t0;
     t0 *= t0;
     t1 *= t1;
@@ -184,31 +226,48 @@ where
     t2 *= t2;
     t2 *= t2;
 
-    let n0 = grad2(hash2d(seed, i, j), x0, y0);
+    let h0 = hash2d(seed, i, j);
+    let n0 = grad2(h0, x0, y0);
     let j1 = i1.select(j, j + Simd::splat(Y_PRIME));
     let i1 = i1.select(i + Simd::splat(X_PRIME), i);
-    let n1 = grad2(hash2d(seed, i1, j1), x1, y1);
+    let h1 = hash2d(seed, i1, j1);
+    let n1 = grad2(h1, x1, y1);
     let i2 = i + Simd::splat(X_PRIME);
     let j2 = j + Simd::splat(Y_PRIME);
-    let n2 = grad2(hash2d(seed, i2, j2), x2, y2);
-
-    return Simd::splat(38.283687591552734375) * n0.mul_add(t0, n1.mul_add(t1, n2 * t2));
+    let h2 = hash2d(seed, i2, j2);
+    let n2 = grad2(h2, x2, y2);
+
+    let value = Simd::splat(SCALE) * n0.mul_add(t0, n1.mul_add(t1, n2 * t2));
+
+    // The gradient functions are linear, the components of a gradient are its dot product with
+    // the axes.
+    let one = Simd::splat(1.0);
+    let zero = Simd::splat(0.0);
+    let mut gradient = [zero; 2];
+    for (h, t, dt, n, d) in [
+        (h0, t0, dt0, n0, [x0, y0]),
+        (h1, t1, dt1, n1, [x1, y1]),
+        (h2, t2, dt2, n2, [x2, y2]),
+    ] {
+        gradient[0] += t * grad2(h, one, zero) + dt * n * d[0];
+        gradient[1] += t * grad2(h, zero, one) + dt * n * d[1];
+    }
+
+    (value, gradient.map(|g| g * Simd::splat(SCALE)))
 }
 
 #[multiversion(targets = "simd", dispatcher = "pointer")]
 pub fn simplex_3d<const N: usize>(
     _tree: &NoiseTree<N>,
     node: &NoiseNode<N>,
-    mut x: Simd<f32, N>,
-    mut y: Simd<f32, N>,
-    mut z: Simd<f32, N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
 ) -> Simd<f32, N>
 where
     LaneCount<N>: SupportedLaneCount,
 {
-    const F3: f32 = 1.0 / 3.0;
-    const G3: f32 = 1.0 / 2.0;
-
     let NoiseNodeSettings::Simplex {
         seed,
         frequency_x,
@@ -219,16 +278,49 @@ where
         unreachable!()
     };
 
+    let frequency = [frequency_x, frequency_y, frequency_z];
+    return simplex_3d_with_gradient(seed, frequency, origin, x, y, z).0;
+}
+
+/// Samples 3-dimensional simplex noise together with its gradient. The gradient is taken in the
+/// coordinates after they have been multiplied by the frequency. When only the value is used,
+/// the gradient is optimized away.
+#[inline(always)]
+pub fn simplex_3d_with_gradient<const N: usize>(
+    seed: i32,
+    frequency: [f32; 3],
+    origin: [f64; 3],
+    mut x: Simd<f32, N>,
+    mut y: Simd<f32, N>,
+    mut z: Simd<f32, N>,
+) -> (Simd<f32, N>, [Simd<f32, N>; 3])
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 2.0;
+    const SCALE: f32 = 32.69428253173828125;
+
     let seed = Simd::splat(seed);
 
-    x *= Simd::splat(frequency_x);
-    y *= Simd::splat(frequency_y);
-    z *= Simd::splat(frequency_z);
+    // The origin is multiplied and skewed in f64, the whole cells of it are added back when
+    // hashing, only the fraction is added to the coordinates.
+    let origin = [0, 1, 2].map(|axis| origin[axis] * frequency[axis] as f64);
+    let origin_skew = F3 as f64 * (origin[0] + origin[1] + origin[2]);
+    let origin = origin.map(|o| o + origin_skew);
+    let cell = origin.map(|o| o.floor());
+    let fraction: [Simd<f32, N>; 3] =
+        [0, 1, 2].map(|axis| Simd::splat((origin[axis] - cell[axis]) as f32));
+    let cell: [Simd<i32, N>; 3] = cell.map(|c| Simd::splat(c as i64 as i32));
+
+    x *= Simd::splat(frequency[0]);
+    y *= Simd::splat(frequency[1]);
+    z *= Simd::splat(frequency[2]);
 
     let s = Simd::splat(F3) * (x + y + z);
-    x += s;
-    y += s;
-    z += s;
+    x += s + fraction[0];
+    y += s + fraction[1];
+    z += s + fraction[2];
 
     let mut x0 = x.floor();
     let mut y0 = y.floor();
@@ -237,9 +329,9 @@ where
     let yi = y - y0;
     let zi = z - z0;
 
-    let i = unsafe { x0.to_int_unchecked() * Simd::splat(X_PRIME) };
-    let j = unsafe { y0.to_int_unchecked() * Simd::splat(Y_PRIME) };
-    let k = unsafe { z0.to_int_unchecked() * Simd::splat(Z_PRIME) };
+    let i = unsafe { (x0.to_int_unchecked() + cell[0]) * Simd::splat(X_PRIME) };
+    let j = unsafe { (y0.to_int_unchecked() + cell[1]) * Simd::splat(Y_PRIME) };
+    let k = unsafe { (z0.to_int_unchecked() + cell[2]) * Simd::splat(Z_PRIME) };
 
     let x_ge_y = xi.simd_ge(yi);
     let y_ge_z = yi.simd_ge(zi);
@@ -278,6 +370,12 @@ where
     t2 = t2.simd_max(Simd::splat(0.0));
     t3 = t3.simd_max(Simd::splat(0.0));
 
+    // t = 0.6 - |d|², so the gradient of t⁴ is 4t³ * -2d.
+    let dt0 = Simd::splat(-8.0) * t0 * t0 * t0;
+    let dt1 = Simd::splat(-8.0) * t1 * t1 * t1;
+    let dt2 = Simd::splat(-8.0) * t2 * t2 * t2;
+    let dt3 = Simd::splat(-8.0) * t3 * t3 * t3;
+
     // Square twice
     t0 *= t0;
     t0 *= t0;
@@ -288,20 +386,47 @@ where
     t3 *= t3;
     t3 *= t3;
 
-    let n0 = grad3d_dot(hash3d(seed, i, j, k), x0, y0, z0);
+    let h0 = hash3d(seed, i, j, k);
+    let n0 = grad3d_dot(h0, x0, y0, z0);
     let i1 = i1.select(i + Simd::splat(X_PRIME), i);
     let j1 = j1.select(j + Simd::splat(Y_PRIME), j);
     let k1 = k1.select(k + Simd::splat(Z_PRIME), k);
-    let n1 = grad3d_dot(hash3d(seed, i1, j1, k1), x1, y1, z1);
+    let h1 = hash3d(seed, i1, j1, k1);
+    let n1 = grad3d_dot(h1, x1, y1, z1);
     let i2 = i2.select(i + Simd::splat(X_PRIME), i);
     let j2 = j2.select(j + Simd::splat(Y_PRIME), j);
     let k2 = k2.select(k, k + Simd::splat(Z_PRIME));
-    let n2 = grad3d_dot(hash3d(seed, i2, j2, k2), x2, y2, z2);
+    let h2 = hash3d(seed, i2, j2, k2);
+    let n2 = grad3d_dot(h2, x2, y2, z2);
     let i3 = i + Simd::splat(X_PRIME);
     let j3 = j + Simd::splat(Y_PRIME);
     let k3 = k + Simd::splat(Z_PRIME);
-    let n3 = grad3d_dot(hash3d(seed, i3, j3, k3), x3, y3, z3);
-
-    return Simd::splat(32.69428253173828125)
-        * n0.mul_add(t0, n1.mul_add(t1, n2.mul_add(t2, n3 * t3)));
+    let h3 = hash3d(seed, i3, j3, k3);
+    let n3 = grad3d_dot(h3, x3, y3, z3);
+
+    let value = Simd::splat(SCALE) * n0.mul_add(t0, n1.mul_add(t1, n2.mul_add(t2, n3 * t3)));
+
+    // The gradient functions are linear, the components of a gradient are its dot product with
+    // the axes.
+    let one = Simd::splat(1.0);
+    let zero = Simd::splat(0.0);
+    let mut gradient = [zero; 3];
+    for (h, t, dt, n, d) in [
+        (h0, t0, dt0, n0, [x0, y0, z0]),
+        (h1, t1, dt1, n1, [x1, y1, z1]),
+        (h2, t2, dt2, n2, [x2, y2, z2]),
+        (h3, t3, dt3, n3, [x3, y3, z3]),
+    ] {
+        gradient[0] += t * grad3d_dot(h, one, zero, zero) + dt * n * d[0];
+        gradient[1] += t * grad3d_dot(h, zero, one, zero) + dt * n * d[1];
+        gradient[2] += t * grad3d_dot(h, zero, zero, one) + dt * n * d[2];
+    }
+
+    // The gradient is for the offsets to the corners, which move with the skewed coordinates.
+    // Along each axis they change by 1 + F3 - G3 * (1 + 3 * F3) for the offset along the same
+    // axis and by F3 - G3 * (1 + 3 * F3) for the others.
+    const SKEW: f32 = F3 - G3 * (1.0 + 3.0 * F3);
+    let skewed = (gradient[0] + gradient[1] + gradient[2]) * Simd::splat(SKEW);
+
+    (value, gradient.map(|g| (g + skewed) * Simd::splat(SCALE)))
 }
@@ -0,0 +1,66 @@
+use std::simd::{LaneCount, Simd, StdFloat, SupportedLaneCount};
+
+use multiversion::multiversion;
+
+use crate::noise_tree::{NoiseNode, NoiseNodeSettings, NoiseTree};
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn terrace_1d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Terrace { steps, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_1d)(tree, &source, x) };
+    let steps = Simd::splat(*steps);
+    return (source_result * steps).floor() / steps;
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn terrace_2d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Terrace { steps, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_2d)(tree, &source, x, y) };
+    let steps = Simd::splat(*steps);
+    return (source_result * steps).floor() / steps;
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn terrace_3d<const N: usize>(
+    tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Terrace { steps, source } = &node.settings else {
+        unreachable!()
+    };
+
+    let source = &tree.nodes[*source];
+    let source_result = unsafe { (source.function_3d)(tree, &source, origin, x, y, z) };
+    let steps = Simd::splat(*steps);
+    return (source_result * steps).floor() / steps;
+}
@@ -0,0 +1,113 @@
+use std::simd::prelude::*;
+use std::simd::{LaneCount, StdFloat, SupportedLaneCount};
+
+use multiversion::multiversion;
+
+use crate::gradient::{hash2d, hash3d};
+use crate::noise_tree::{split_origin, NoiseNode, NoiseNodeSettings, NoiseTree};
+
+pub const X_PRIME: i32 = 501125321;
+pub const Y_PRIME: i32 = 1136930381;
+pub const Z_PRIME: i32 = 1720413743;
+
+// The hash is always positive, this maps it from 0..i32::MAX to 0..2
+const HASH_SCALE: f32 = 2.0 / 2147483648.0;
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn value_1d<const N: usize>(
+    _tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Value {
+        seed, frequency_x, ..
+    } = node.settings
+    else {
+        unreachable!()
+    };
+
+    // NOTE: See simplex for unsafe
+    let i = unsafe { (x * Simd::splat(frequency_x)).floor().to_int_unchecked() };
+    let hash = hash2d(Simd::splat(seed), i * Simd::splat(X_PRIME), Simd::splat(0));
+
+    return hash
+        .cast::<f32>()
+        .mul_add(Simd::splat(HASH_SCALE), Simd::splat(-1.0));
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn value_2d<const N: usize>(
+    _tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Value {
+        seed,
+        frequency_x,
+        frequency_z,
+        ..
+    } = node.settings
+    else {
+        unreachable!()
+    };
+
+    let i = unsafe { (x * Simd::splat(frequency_x)).floor().to_int_unchecked() };
+    let j = unsafe { (y * Simd::splat(frequency_z)).floor().to_int_unchecked() };
+    let hash = hash2d(
+        Simd::splat(seed),
+        i * Simd::splat(X_PRIME),
+        j * Simd::splat(Y_PRIME),
+    );
+
+    return hash
+        .cast::<f32>()
+        .mul_add(Simd::splat(HASH_SCALE), Simd::splat(-1.0));
+}
+
+#[multiversion(targets = "simd", dispatcher = "pointer")]
+pub fn value_3d<const N: usize>(
+    _tree: &NoiseTree<N>,
+    node: &NoiseNode<N>,
+    origin: [f64; 3],
+    x: Simd<f32, N>,
+    y: Simd<f32, N>,
+    z: Simd<f32, N>,
+) -> Simd<f32, N>
+where
+    LaneCount<N>: SupportedLaneCount,
+{
+    let NoiseNodeSettings::Value {
+        seed,
+        frequency_x,
+        frequency_y,
+        frequency_z,
+    } = node.settings
+    else {
+        unreachable!()
+    };
+
+    let (cell_x, x) = split_origin(origin[0], frequency_x, x);
+    let (cell_y, y) = split_origin(origin[1], frequency_y, y);
+    let (cell_z, z) = split_origin(origin[2], frequency_z, z);
+
+    let i = unsafe { x.floor().to_int_unchecked() + cell_x };
+    let j = unsafe { y.floor().to_int_unchecked() + cell_y };
+    let k = unsafe { z.floor().to_int_unchecked() + cell_z };
+    let hash = hash3d(
+        Simd::splat(seed),
+        i * Simd::splat(X_PRIME),
+        j * Simd::splat(Y_PRIME),
+        k * Simd::splat(Z_PRIME),
+    );
+
+    return hash
+        .cast::<f32>()
+        .mul_add(Simd::splat(HASH_SCALE), Simd::splat(-1.0));
+}
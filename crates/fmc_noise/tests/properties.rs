@@ -0,0 +1,208 @@
+use noise::Noise;
+use proptest::prelude::*;
+
+// Rounding lets the base noises reach a hair past their bounds.
+const EPSILON: f32 = 1e-5;
+
+fn base_noise() -> impl Strategy<Value = Noise> {
+    (0..3, any::<i32>(), 0.001f32..1.0).prop_map(|(kind, seed, frequency)| match kind {
+        0 => Noise::simplex(frequency, seed),
+        1 => Noise::perlin(frequency, seed),
+        _ => Noise::value(frequency, seed),
+    })
+}
+
+// Perlin noise is continuous everywhere. Value noise jumps between its cells and simplex noise
+// where its simplices meet, rounding at those edges can land on either side.
+fn continuous_noise() -> impl Strategy<Value = Noise> {
+    (any::<i32>(), 0.001f32..1.0).prop_map(|(seed, frequency)| Noise::perlin(frequency, seed))
+}
+
+// Whole numbers, the offsets from the start can then be added without rounding, so a value
+// sampled alone is the same as when it is sampled as part of a larger area.
+fn coordinate() -> impl Strategy<Value = f32> {
+    (-100_000i32..100_000).prop_map(|c| c as f32)
+}
+
+fn assert_in_bounds(values: &[f32], min: f32, max: f32) -> Result<(), TestCaseError> {
+    for value in values {
+        prop_assert!(
+            (-1.0 - EPSILON..=1.0 + EPSILON).contains(value),
+            "{} is outside of -1..1",
+            value
+        );
+    }
+
+    // The reported range is collected separately for the full simd vectors and for the
+    // remainder, it must be the same as that of the values.
+    let actual_min = values.iter().copied().fold(f32::MAX, f32::min);
+    let actual_max = values.iter().copied().fold(f32::MIN, f32::max);
+    prop_assert_eq!(min, actual_min);
+    prop_assert_eq!(max, actual_max);
+
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn base_noise_1d_is_in_bounds(noise in base_noise(), x in coordinate(), width in 1usize..100) {
+        let (values, min, max) = noise.generate_1d(x, width);
+        prop_assert_eq!(values.len(), width);
+        assert_in_bounds(&values, min, max)?;
+    }
+
+    #[test]
+    fn base_noise_2d_is_in_bounds(
+        noise in base_noise(),
+        x in coordinate(),
+        y in coordinate(),
+        width in 1usize..40,
+        height in 1usize..40
+    ) {
+        let (values, min, max) = noise.generate_2d(x, y, width, height);
+        prop_assert_eq!(values.len(), width * height);
+        assert_in_bounds(&values, min, max)?;
+    }
+
+    #[test]
+    fn base_noise_3d_is_in_bounds(
+        noise in base_noise(),
+        position in (coordinate(), coordinate(), coordinate()),
+        size in (1usize..20, 1usize..20, 1usize..20)
+    ) {
+        let (x, y, z) = position;
+        let (width, height, depth) = size;
+        let (values, min, max) = noise.generate_3d(x, y, z, width, height, depth);
+        prop_assert_eq!(values.len(), width * height * depth);
+        assert_in_bounds(&values, min, max)?;
+    }
+
+    // Each step that derives the bounds of its values is covered, applied to base noises.
+    #[test]
+    fn normalized_noise_is_in_bounds(
+        noise in base_noise(),
+        other in base_noise(),
+        seed in any::<i32>(),
+        octaves in 1u32..6,
+        step in 0..22,
+        position in (coordinate(), coordinate(), coordinate())
+    ) {
+        let noise = match step {
+            0 => noise,
+            1 => Noise::constant(0.25),
+            2 => noise.fbm(octaves, 0.5, 2.0),
+            3 => noise.billow(octaves, 0.5, 2.0),
+            4 => noise.turbulence(octaves, 0.5, 2.0),
+            5 => Noise::simplex(0.05, seed).fbm(octaves, 0.5, 2.0).erode(0.2),
+            6 => noise.abs(),
+            7 => noise.add(other).mul_value(3.0),
+            8 => noise.add_value(0.5),
+            9 => noise.clamp(-0.5, 0.3),
+            10 => noise.max(other.abs()).add_value(0.5),
+            11 => noise.min(other.mul_value(0.5)),
+            12 => noise.mul_value(-3.0),
+            // The high source is below the low one, so the lerp runs the opposite way.
+            13 => noise.lerp(Noise::constant(-1.0), Noise::constant(1.0)),
+            14 => noise.lerp(other.add_value(2.0), Noise::constant(0.5)),
+            15 => noise.range(0.2, -0.2, other.add_value(1.0), Noise::constant(0.5)),
+            16 => noise.square(),
+            17 => noise.pow(3.0),
+            18 => noise.pow(0.5),
+            19 => noise.terrace(3),
+            20 => noise.translate(100.0, -50.0, 25.0),
+            _ => noise.rotate_y(1.0),
+        }
+        .normalized();
+
+        let (x, y, z) = position;
+        let (values, min, max) = noise.generate_2d(x, z, 8, 8);
+        assert_in_bounds(&values, min, max)?;
+        let (values, min, max) = noise.generate_3d(x, y, z, 8, 17, 8);
+        assert_in_bounds(&values, min, max)?;
+    }
+
+    #[test]
+    fn values_2d_are_laid_out_by_row(
+        noise in base_noise(),
+        x in coordinate(),
+        y in coordinate(),
+        width in 1usize..20,
+        height in 1usize..20
+    ) {
+        // Each row is 'width' values along y, and the rows advance along x.
+        let (values, _, _) = noise.generate_2d(x, y, width, height);
+        for row in 0..height {
+            for column in 0..width {
+                let (single, _, _) = noise.generate_2d(x + row as f32, y + column as f32, 1, 1);
+                prop_assert_eq!(values[row * width + column], single[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn values_3d_are_laid_out_xzy(
+        noise in base_noise(),
+        position in (coordinate(), coordinate(), coordinate()),
+        size in (1usize..6, 1usize..20, 1usize..6)
+    ) {
+        let (x, y, z) = position;
+        let (width, height, depth) = size;
+        let (values, _, _) = noise.generate_3d(x, y, z, width, height, depth);
+        for i in 0..width {
+            for k in 0..depth {
+                for j in 0..height {
+                    let (single, _, _) = noise.generate_3d(
+                        x + i as f32,
+                        y + j as f32,
+                        z + k as f32,
+                        1,
+                        1,
+                        1,
+                    );
+                    let index = i * (depth * height) + k * height + j;
+                    prop_assert_eq!(values[index], single[0]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_matches_generate_3d(
+        noise in base_noise(),
+        position in (coordinate(), coordinate(), coordinate()),
+        size in (1usize..20, 1usize..20, 1usize..20)
+    ) {
+        let (x, y, z) = position;
+        let (width, height, depth) = size;
+        let expected = noise.generate_3d(x, y, z, width, height, depth);
+        let parallel = noise.generate_3d_parallel(x, y, z, width, height, depth);
+        prop_assert_eq!(&expected, &parallel);
+    }
+
+    // Past 2^24 f32 can't hold every whole number, so generate_3d can't tell neighbouring blocks
+    // apart out there. The lattice keeps the position in f64, sampled far out it should match the
+    // same noise moved back next to the origin.
+    #[test]
+    fn lattice_is_precise_far_from_the_origin(
+        noise in continuous_noise(),
+        far in (-7i32..8, -7i32..8, -7i32..8),
+        near in (-1000i32..1000, -1000i32..1000, -1000i32..1000),
+        size in (1usize..10, 1usize..20, 1usize..10)
+    ) {
+        // Multiples of 2^27 are exact in f32, so the translation isn't rounded.
+        let far = [far.0 << 27, far.1 << 27, far.2 << 27];
+        let near = [near.0, near.1, near.2];
+        let position = [far[0] + near[0], far[1] + near[1], far[2] + near[2]];
+        let (width, height, depth) = size;
+
+        let (values, min, max) = noise.generate_3d_lattice(position, 1, width, height, depth);
+        assert_in_bounds(&values, min, max)?;
+
+        let (expected, _, _) = noise
+            .translate(far[0] as f32, far[1] as f32, far[2] as f32)
+            .generate_3d(near[0] as f32, near[1] as f32, near[2] as f32, width, height, depth);
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            prop_assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+        }
+    }
+}
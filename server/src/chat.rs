@@ -1,7 +1,11 @@
 use bevy::prelude::*;
-use fmc_networking::{messages, NetworkData, NetworkServer, ServerNetworkEvent};
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer, ServerNetworkEvent};
 
-use crate::players::Player;
+use crate::{
+    bevy_extensions::f64_transform::F64Transform,
+    players::{Player, TeamMember},
+    settings::Settings,
+};
 
 pub const CHAT_FONT_SIZE: f32 = 8.0;
 pub const CHAT_TEXT_COLOR: &str = "#ffffff";
@@ -9,27 +13,150 @@ pub const CHAT_TEXT_COLOR: &str = "#ffffff";
 pub struct ChatPlugin;
 impl Plugin for ChatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (handle_chat_messages, send_connection_messages));
+        app.add_systems(
+            Update,
+            (
+                handle_chat_messages,
+                handle_whispers,
+                send_connection_messages,
+            ),
+        );
     }
 }
 
+// Routes the messages players write to the players that should see them.
 fn handle_chat_messages(
     net: Res<NetworkServer>,
-    player_query: Query<&Player>,
-    mut chat_message_query: EventReader<NetworkData<messages::InterfaceTextInput>>,
+    settings: Res<Settings>,
+    player_query: Query<(&Player, &ConnectionId, &F64Transform, &TeamMember)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
 ) {
-    for chat_message in chat_message_query.read() {
-        if &chat_message.interface_path != "chat/input" {
+    for chat_message in chat_messages.read() {
+        // Commands are handled by whoever owns them, they are not shown in the chat.
+        if chat_message.message.starts_with('/') {
+            continue;
+        }
+        let Ok((player, _, transform, team_member)) =
+            player_query.get(chat_message.source.entity())
+        else {
             continue;
+        };
+
+        let message = messages::ChatMessageServer::from_player(
+            chat_message.channel,
+            &player.username,
+            &chat_message.message,
+        );
+
+        match chat_message.channel {
+            messages::ChatChannel::Global => {
+                net.broadcast(message);
+            }
+            messages::ChatChannel::Local => {
+                let radius = settings.local_chat_radius as f64;
+                let receivers = player_query
+                    .iter()
+                    .filter(|(_, _, other_transform, _)| {
+                        other_transform.translation.distance(transform.translation) <= radius
+                    })
+                    .map(|(_, connection_id, _, _)| connection_id);
+                net.send_many(receivers, message);
+            }
+            messages::ChatChannel::Team => {
+                if team_member.team.is_none() {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("chat.no_team", vec![]),
+                    );
+                    continue;
+                }
+                let receivers = player_query
+                    .iter()
+                    .filter(|(_, _, _, other_member)| other_member.team == team_member.team)
+                    .map(|(_, connection_id, _, _)| connection_id);
+                net.send_many(receivers, message);
+            }
+            // Whispers are sent with the '/msg' command, and only the server can send system
+            // messages.
+            messages::ChatChannel::Whisper | messages::ChatChannel::System => continue,
         }
-        let player = player_query.get(chat_message.source.entity()).unwrap();
-        let mut chat_history_update = messages::InterfaceTextBoxUpdate::new("chat/history");
-        chat_history_update.append_line().with_text(
-            format!("[{}] {}", &player.username, &chat_message.text),
-            CHAT_FONT_SIZE,
-            CHAT_TEXT_COLOR,
+    }
+}
+
+// Private messages are sent with '/msg <player> <message>', only the two players can see them.
+fn handle_whispers(
+    net: Res<NetworkServer>,
+    player_query: Query<(&Player, &ConnectionId)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let Some(arguments) = chat_message.message.strip_prefix("/msg") else {
+            continue;
+        };
+        // Other commands that start with the same letters.
+        if !arguments.is_empty() && !arguments.starts_with(' ') {
+            continue;
+        }
+
+        let Ok((sender, _)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        let Some((name, message)) = arguments.trim_start().split_once(' ') else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("chat.whisper.usage", vec![]),
+            );
+            continue;
+        };
+        let message = message.trim();
+        if message.is_empty() {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("chat.whisper.usage", vec![]),
+            );
+            continue;
+        }
+
+        let Some((receiver, connection_id)) = player_query
+            .iter()
+            .find(|(player, _)| player.username == name)
+        else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(
+                    "chat.whisper.unknown_player",
+                    vec![name.to_owned()],
+                ),
+            );
+            continue;
+        };
+
+        if receiver.id == sender.id {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("chat.whisper.self", vec![]),
+            );
+            continue;
+        }
+
+        net.send_one(
+            *connection_id,
+            messages::ChatMessageServer::from_player(
+                messages::ChatChannel::Whisper,
+                &sender.username,
+                message,
+            ),
+        );
+        // The sender is shown who they sent it to, as a reminder that it was private.
+        net.send_one(
+            chat_message.source,
+            messages::ChatMessageServer::translated(
+                "chat.whisper.sent",
+                vec![receiver.username.clone(), message.to_owned()],
+            )
+            .with_channel(messages::ChatChannel::Whisper),
         );
-        net.broadcast(chat_history_update);
     }
 }
 
@@ -45,23 +172,17 @@ fn send_connection_messages(
     for event in network_events.read() {
         match event {
             ServerNetworkEvent::Connected { username, .. } => {
-                let mut chat_update = messages::InterfaceTextBoxUpdate::new("chat/history");
-                chat_update.append_line().with_text(
-                    format!("{} joined the game", username),
-                    CHAT_FONT_SIZE,
-                    CHAT_TEXT_COLOR,
-                );
-                net.broadcast(chat_update);
+                net.broadcast(messages::ChatMessageServer::translated(
+                    "chat.player_joined",
+                    vec![username.to_owned()],
+                ));
             }
             ServerNetworkEvent::Disconnected { entity } => {
                 let player = player_query.get(*entity).unwrap();
-                let mut chat_update = messages::InterfaceTextBoxUpdate::new("chat/history");
-                chat_update.append_line().with_text(
-                    format!("{} left the game", player.username),
-                    CHAT_FONT_SIZE,
-                    CHAT_TEXT_COLOR,
-                );
-                net.broadcast(chat_update);
+                net.broadcast(messages::ChatMessageServer::translated(
+                    "chat.player_left",
+                    vec![player.username.to_owned()],
+                ));
             }
             _ => (),
         }
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use crossbeam_channel::Receiver;
+
+/// Reads commands typed into the terminal the server was started from. Each line is sent as a
+/// [ConsoleCommand] event, the plugins that handle a command read the events and pick out
+/// their own.
+pub struct ConsolePlugin;
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConsoleCommand>()
+            .add_systems(Startup, start_reader)
+            .add_systems(PreUpdate, send_console_commands);
+    }
+}
+
+/// A line that was typed into the console, split into words.
+#[derive(Event)]
+pub struct ConsoleCommand(pub Vec<String>);
+
+impl ConsoleCommand {
+    pub fn name(&self) -> Option<&str> {
+        return self.0.first().map(String::as_str);
+    }
+
+    /// The words after the name of the command.
+    pub fn args(&self) -> &[String] {
+        return self.0.get(1..).unwrap_or_default();
+    }
+}
+
+#[derive(Resource)]
+struct ConsoleLines(Receiver<String>);
+
+// Reading from stdin blocks, so it is done on a thread of its own. It stops when stdin is closed,
+// e.g. when the server runs as a service.
+fn start_reader(mut commands: Commands) {
+    let (sender, receiver) = crossbeam_channel::unbounded();
+
+    std::thread::Builder::new()
+        .name("console".to_owned())
+        .spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("Failed to start the console thread");
+
+    commands.insert_resource(ConsoleLines(receiver));
+}
+
+fn send_console_commands(
+    console_lines: Res<ConsoleLines>,
+    mut console_commands: EventWriter<ConsoleCommand>,
+) {
+    for line in console_lines.0.try_iter() {
+        let words: Vec<String> = line.split_whitespace().map(str::to_owned).collect();
+        if !words.is_empty() {
+            console_commands.send(ConsoleCommand(words));
+        }
+    }
+}
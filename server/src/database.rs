@@ -1,17 +1,33 @@
-use std::{collections::HashMap, hash::Hash, sync::Arc};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use bevy::prelude::*;
-use fmc_networking::BlockId;
+use bevy::{app::AppExit, prelude::*};
+use fmc_networking::{Account, AccountStorage, Ban, BanStorage, BlockId};
+use sha1::Digest;
 
 use crate::{
     constants::CHUNK_SIZE,
-    players::PlayerSave,
+    players::{Letter, PlayerSave, QuestProgress, Team},
     settings::Settings,
+    utils,
     world::{
         blocks::{BlockState, Blocks},
-        items::ItemId,
+        items::{ItemId, ItemStack, ItemStorage},
         models::Model,
-        world_map::chunk::Chunk,
+        paintings::Painting,
+        world_map::{
+            chunk::{Chunk, GeneratedChunk},
+            terrain_generation::{PlannedStructure, TerrainFeature},
+        },
         WorldProperties,
     },
 };
@@ -46,7 +62,7 @@ use crate::{
 //
 // players:
 //      CREATE TABLE players (
-//            name TEXT PRIMARY KEY
+//            id TEXT PRIMARY KEY
 //            save BLOB NOT NULL
 //            );
 //
@@ -55,6 +71,197 @@ use crate::{
 //
 //      All data about a player is stored in the save field. Its format is decided by the program.
 //
+// accounts:
+//      CREATE TABLE accounts (
+//            id TEXT PRIMARY KEY,
+//            name TEXT NOT NULL UNIQUE,
+//            password_hash BLOB
+//            );
+//
+//      Accounts players log in to, the password hash is created by fmc_networking. The id is
+//      what the player is known by, so the name can be changed. Accounts without a password
+//      hash are claimed by the first player to log in to them.
+//
+// mail:
+//      CREATE TABLE mail (
+//            id INTEGER PRIMARY KEY,
+//            recipient TEXT NOT NULL,
+//            sender TEXT NOT NULL,
+//            message TEXT NOT NULL,
+//            item TEXT
+//            );
+//
+//      Letters waiting to be delivered, by the id of the recipient. The sender is stored by name
+//      as it was when the letter was sent. The item is an attached item stack stored as json, it
+//      is removed when the recipient collects it.
+//
+// quests:
+//      CREATE TABLE quests (
+//            player TEXT NOT NULL,
+//            quest TEXT NOT NULL,
+//            progress TEXT NOT NULL,
+//            PRIMARY KEY (player, quest)
+//            );
+//
+//      The quests each player has started, by player id and the name of the quest. The progress
+//      is stored as json.
+//
+// bans:
+//      CREATE TABLE bans (
+//            kind TEXT NOT NULL,
+//            target TEXT NOT NULL,
+//            reason TEXT NOT NULL,
+//            PRIMARY KEY (kind, target)
+//            );
+//
+//      Players and addresses that can't connect. The kind is either "player", in which case the
+//      target is a player id, or "address" for ip addresses.
+//
+// balances:
+//      CREATE TABLE balances (
+//            player TEXT PRIMARY KEY,
+//            balance INTEGER NOT NULL
+//            );
+//
+//      How much money each player has, by player id. Players that have never had any are not
+//      in it.
+//
+// transactions:
+//      CREATE TABLE transactions (
+//            id INTEGER PRIMARY KEY,
+//            player TEXT NOT NULL,
+//            amount INTEGER NOT NULL,
+//            balance INTEGER NOT NULL,
+//            reason TEXT NOT NULL,
+//            time INTEGER NOT NULL
+//            );
+//
+//      Every change to a balance, so it can be traced where money came from. The amount is
+//      negative when money was taken, the balance is what the player had after, and the time is
+//      in seconds since the unix epoch.
+//
+// teams:
+//      CREATE TABLE teams (
+//            name TEXT PRIMARY KEY,
+//            color TEXT NOT NULL,
+//            friendly_fire INTEGER NOT NULL,
+//            shared_visibility INTEGER NOT NULL
+//            );
+//
+//      The teams players can be put on, the color is a hex string.
+//
+// team_members:
+//      CREATE TABLE team_members (
+//            player TEXT PRIMARY KEY,
+//            team TEXT NOT NULL
+//            );
+//
+//      Which team each player is on, by player id. Players that aren't on a team are not in it.
+//
+// structures:
+//      CREATE TABLE structures (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            name TEXT NOT NULL,
+//            PRIMARY KEY (x,y,z)
+//            );
+//
+//      The structures that have been planned during terrain generation, by the position of their
+//      origin.
+//
+// structure_pieces:
+//      CREATE TABLE structure_pieces (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            origin_x INTEGER,
+//            origin_y INTEGER,
+//            origin_z INTEGER,
+//            piece BLOB NOT NULL,
+//            checksum BLOB,
+//            PRIMARY KEY (x,y,z,origin_x,origin_y,origin_z)
+//            );
+//
+//      The part of a structure that is inside a chunk, by the position of the chunk and the
+//      origin of the structure. The piece holds the blocks and what they can replace, its format
+//      is decided by the program. Chunks apply their pieces every time they are generated. The
+//      checksum is the sha1 hash of the piece, pieces saved before it was added don't have one.
+//
+// block_entities:
+//      CREATE TABLE block_entities (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            storage TEXT NOT NULL,
+//            state TEXT,
+//            PRIMARY KEY (x,y,z)
+//            );
+//
+//      The items stored in blocks like chests, by block position. The storage is stored as json.
+//      Block entities that have never held anything are not in it. The state is what the block
+//      needs to remember besides its items, like how far along a furnace is, also as json.
+//
+// beacons:
+//      CREATE TABLE beacons (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            effect TEXT NOT NULL,
+//            PRIMARY KEY (x,y,z)
+//            );
+//
+//      The status effect chosen for each beacon, by name. Beacons that have never been given one
+//      are not in it.
+//
+// generated_chunks:
+//      CREATE TABLE generated_chunks (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            generator BLOB NOT NULL,
+//            chunk BLOB NOT NULL,
+//            PRIMARY KEY (x,y,z)
+//            );
+//
+//      Chunks that were generated ahead of time by the 'pregen' console command, by chunk
+//      position. They are stored as they were generated, the changes from the other tables are
+//      applied when they are loaded. The generator is the fingerprint of the terrain generator
+//      that made the chunk, chunks made by another one are generated again. The chunk is
+//      compressed with zstd, its format is decided by the program.
+//
+// corrupt_chunk_data:
+//      CREATE TABLE corrupt_chunk_data (
+//            id INTEGER PRIMARY KEY,
+//            source TEXT NOT NULL,
+//            key TEXT NOT NULL,
+//            data BLOB NOT NULL,
+//            reason TEXT NOT NULL,
+//            time INTEGER NOT NULL
+//            );
+//
+//      Backups of the blocks, structure pieces, block entities and generated chunks that could
+//      not be read when their chunk was loaded. They are moved here so the chunk can be
+//      generated without them. The source is the table the record came from, the key its
+//      primary key joined by commas, and the data what it held. The time is in seconds since the
+//      unix epoch.
+//
+// paintings:
+//      CREATE TABLE paintings (
+//            x INTEGER,
+//            y INTEGER,
+//            z INTEGER,
+//            name TEXT NOT NULL,
+//            normal_x INTEGER,
+//            normal_z INTEGER,
+//            width INTEGER,
+//            height INTEGER,
+//            PRIMARY KEY (x,y,z)
+//            );
+//
+//      The paintings hanging on walls, by their lower left block. The normal points out of the
+//      wall, it is always horizontal.
+//
 // storage:
 //      CREATE TABLE storage (
 //                name TEXT PRIMARY KEY,
@@ -74,22 +281,125 @@ impl Plugin for DatabasePlugin {
 
         let database = Database::new(settings.database_path.clone());
 
-        database.build();
-        database.save_block_ids();
-        database.save_items();
-        database.save_models();
+        // The server can't run without these, so it is fine to panic.
+        database
+            .build()
+            .unwrap_or_else(|err| panic!("Could not create the database tables: {}", err));
+        database
+            .save_block_ids()
+            .unwrap_or_else(|err| panic!("Could not save the block ids to the database: {}", err));
+        database
+            .save_items()
+            .unwrap_or_else(|err| panic!("Could not save the item ids to the database: {}", err));
+        database
+            .save_models()
+            .unwrap_or_else(|err| panic!("Could not save the model ids to the database: {}", err));
+
+        let operator_ids = database
+            .operator_ids(&settings.operators)
+            .unwrap_or_else(|err| panic!("Could not look up the operators: {}", err));
+        app.world.resource_mut::<Settings>().operator_ids = operator_ids;
+
         //    setup_new_world_database(&settings.world_database_path);
         //} else if rusqlite::Connection::open(&settings.world_database_path).is_err() {
         //    panic!("Could not open the world file at '{}', make sure it is the correct file, else it might be corrupt", settings.world_database_path);
         //}
 
-        app.insert_resource(database);
+        app.insert_resource(database)
+            .add_systems(Update, shutdown_on_database_failure);
+    }
+}
+
+// How many times an operation is tried before giving up on it.
+const RETRY_ATTEMPTS: u32 = 5;
+// The delay before the first retry, it is doubled for each one after.
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+thread_local! {
+    // Set on threads the game doesn't wait for, see 'Database::allow_waiting'.
+    static CAN_WAIT: Cell<bool> = Cell::new(false);
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// The database is locked by another connection or the disk is temporarily unavailable. Off
+    /// the game's threads the operation has already been retried by the time this is returned.
+    Transient(rusqlite::Error),
+    /// The database can't be used anymore, e.g. the file is corrupt or the disk is full. The
+    /// server is shut down when this happens.
+    Unrecoverable(rusqlite::Error),
+    /// The change would break a constraint, e.g. a name that must be unique is already taken.
+    /// Nothing was changed.
+    Rejected(rusqlite::Error),
+    /// Something that was stored can't be decoded.
+    Corrupt(String),
+    /// The config files the ids are made from can't be read.
+    Config(String),
+}
+
+impl DatabaseError {
+    fn is_transient(&self) -> bool {
+        return matches!(self, Self::Transient(_));
+    }
+}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(rusqlite::ErrorCode::DatabaseBusy)
+            | Some(rusqlite::ErrorCode::DatabaseLocked)
+            | Some(rusqlite::ErrorCode::SystemIoFailure)
+            | Some(rusqlite::ErrorCode::CannotOpen) => Self::Transient(err),
+            Some(rusqlite::ErrorCode::ConstraintViolation) => Self::Rejected(err),
+            _ => Self::Unrecoverable(err),
+        }
+    }
+}
+
+impl std::fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transient(err) => write!(f, "database unavailable: {}", err),
+            Self::Unrecoverable(err) => write!(f, "database failure: {}", err),
+            Self::Rejected(err) => write!(f, "rejected by the database: {}", err),
+            Self::Corrupt(err) => write!(f, "corrupt data: {}", err),
+            Self::Config(err) => write!(f, "unreadable config: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+// An unrecoverable error means nothing more can be saved, stop the server before the world
+// diverges any further from what is stored. Systems that save on exit still get a chance to run.
+fn shutdown_on_database_failure(
+    database: Res<Database>,
+    mut exit_events: EventWriter<AppExit>,
+    mut shutting_down: Local<bool>,
+) {
+    if !*shutting_down && database.failed.load(Ordering::Relaxed) {
+        error!("Shutting down because of an unrecoverable database error");
+        exit_events.send(AppExit);
+        *shutting_down = true;
     }
 }
 
 #[derive(Resource, Deref, Clone)]
 pub struct Database(Arc<DatabaseInner>);
 
+/// Returned by [Database::allow_waiting].
+pub struct AllowWaiting {
+    previous: bool,
+    // It has to be dropped on the thread it was made on.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for AllowWaiting {
+    fn drop(&mut self) {
+        CAN_WAIT.with(|can_wait| can_wait.set(self.previous));
+    }
+}
+
 // TODO: Two modes, one where it saves only changes to disk and one where it saves all chunk data.
 //       Changes are best for single instances that don't care about the cpu load of re-generating
 //       chunks. For large servers it would be less expensive to save all of it.
@@ -97,6 +407,8 @@ pub struct Database(Arc<DatabaseInner>);
 // TODO: Currently passed around as ArcDatabase(Arc<Database>), should just be Database.
 pub struct DatabaseInner {
     path: String,
+    // Set when an unrecoverable error has occured.
+    failed: AtomicBool,
     //pub pool: Mutex<Vec<rusqlite::Connection>>
 }
 
@@ -114,11 +426,14 @@ pub struct DatabaseInner {
 // TODO: Extract functions and have them take a connection instead?
 impl Database {
     pub fn new(path: String) -> Self {
-        return Self(Arc::new(DatabaseInner { path }));
+        return Self(Arc::new(DatabaseInner {
+            path,
+            failed: AtomicBool::new(false),
+        }));
     }
 
-    pub fn get_connection(&self) -> rusqlite::Connection {
-        return rusqlite::Connection::open(&self.path).unwrap();
+    pub fn get_connection(&self) -> Result<rusqlite::Connection, DatabaseError> {
+        return Ok(rusqlite::Connection::open(&self.path)?);
         //return rusqlite::Connection::open_with_flags(
         //    MEMORY_DATABASE_PATH,
         //    rusqlite::OpenFlags::default() | rusqlite::OpenFlags::SQLITE_OPEN_SHARED_CACHE,
@@ -126,16 +441,54 @@ impl Database {
         //.unwrap();
     }
 
-    pub fn build(&self) {
-        let conn = self.get_connection();
-        conn.pragma_update(None, "journal_mode", "wal").unwrap();
+    /// Run a database operation, retrying it with increasing delays if it fails for a transient
+    /// reason. The systems can't wait without stalling the server, so it is only retried where
+    /// waiting is allowed, see [Database::allow_waiting]. Elsewhere the error is returned right
+    /// away. Unrecoverable errors shut down the server.
+    pub fn retry<T>(
+        &self,
+        mut operation: impl FnMut() -> Result<T, DatabaseError>,
+    ) -> Result<T, DatabaseError> {
+        let can_wait = CAN_WAIT.with(Cell::get);
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(err) if err.is_transient() && can_wait && attempt < RETRY_ATTEMPTS => {
+                    warn!(
+                        "Database operation failed, retrying in {:?}: {}",
+                        delay, err
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(DatabaseError::Unrecoverable(err)) => {
+                    self.failed.store(true, Ordering::Relaxed);
+                    return Err(DatabaseError::Unrecoverable(err));
+                }
+                result => return result,
+            }
+        }
+    }
 
-        //conn.execute("drop table if exists blocks", []).unwrap();
-        conn.execute("drop table if exists block_ids", []).unwrap();
-        conn.execute("drop table if exists item_ids", []).unwrap();
-        conn.execute("drop table if exists model_ids", []).unwrap();
-        conn.execute("drop table if exists players", []).unwrap();
-        conn.execute("drop table if exists storage", []).unwrap();
+    /// Lets failed operations on the current thread wait between retries until the returned
+    /// guard is dropped. For the chunk tasks and other work the game doesn't wait for.
+    pub fn allow_waiting() -> AllowWaiting {
+        return AllowWaiting {
+            previous: CAN_WAIT.with(|can_wait| can_wait.replace(true)),
+            _not_send: PhantomData,
+        };
+    }
+
+    pub fn build(&self) -> Result<(), DatabaseError> {
+        let mut conn = self.get_connection()?;
+        conn.pragma_update(None, "journal_mode", "wal")?;
+
+        //conn.execute("drop table if exists blocks", [])?;
+        conn.execute("drop table if exists block_ids", [])?;
+        conn.execute("drop table if exists item_ids", [])?;
+        conn.execute("drop table if exists model_ids", [])?;
 
         // TODO: Test WITHOUT ROWID, it's better maybe.
         // TODO: Test with r*tree, it is already included just need to enable.
@@ -149,8 +502,7 @@ impl Database {
                 PRIMARY KEY (x,y,z)
              )",
             [],
-        )
-        .expect("Could not create block table");
+        )?;
 
         conn.execute(
             "create table if not exists block_ids (
@@ -158,8 +510,7 @@ impl Database {
                 name TEXT NOT NULL UNIQUE
                 )",
             [],
-        )
-        .expect("Could not create block_ids table");
+        )?;
 
         conn.execute(
             "create table if not exists item_ids (
@@ -167,8 +518,7 @@ impl Database {
                 name TEXT NOT NULL UNIQUE
                 )",
             [],
-        )
-        .expect("Could not create item_ids table");
+        )?;
 
         conn.execute(
             "create table if not exists model_ids (
@@ -176,17 +526,186 @@ impl Database {
                 id INTEGER
                 )",
             [],
-        )
-        .expect("Could not create model_ids table");
+        )?;
+
+        migrate_to_player_ids(&mut conn)?;
+        conn.execute(PLAYERS_TABLE, [])?;
+        conn.execute(ACCOUNTS_TABLE, [])?;
+        conn.execute(
+            "create table if not exists operators (
+                name TEXT PRIMARY KEY,
+                player TEXT NOT NULL
+                )",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists mail (
+                id INTEGER PRIMARY KEY,
+                recipient TEXT NOT NULL,
+                sender TEXT NOT NULL,
+                message TEXT NOT NULL,
+                item TEXT
+                )",
+            [],
+        )?;
+        conn.execute(
+            "create index if not exists mail_recipient on mail (recipient)",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists quests (
+                player TEXT NOT NULL,
+                quest TEXT NOT NULL,
+                progress TEXT NOT NULL,
+                PRIMARY KEY (player, quest)
+                )",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists bans (
+                kind TEXT NOT NULL,
+                target TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                PRIMARY KEY (kind, target)
+                )",
+            [],
+        )?;
 
         conn.execute(
-            "create table if not exists players (
+            "create table if not exists balances (
+                player TEXT PRIMARY KEY,
+                balance INTEGER NOT NULL
+                )",
+            [],
+        )?;
+        conn.execute(
+            "create table if not exists transactions (
+                id INTEGER PRIMARY KEY,
+                player TEXT NOT NULL,
+                amount INTEGER NOT NULL,
+                balance INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                time INTEGER NOT NULL
+                )",
+            [],
+        )?;
+        conn.execute(
+            "create index if not exists transactions_player on transactions (player)",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists teams (
                 name TEXT PRIMARY KEY,
-                save BLOB NOT NULL
+                color TEXT NOT NULL,
+                friendly_fire INTEGER NOT NULL,
+                shared_visibility INTEGER NOT NULL
+                )",
+            [],
+        )?;
+        conn.execute(
+            "create table if not exists team_members (
+                player TEXT PRIMARY KEY,
+                team TEXT NOT NULL
+                )",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists structures (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                name TEXT NOT NULL,
+                PRIMARY KEY (x,y,z)
+                )",
+            [],
+        )?;
+        conn.execute(
+            "create table if not exists structure_pieces (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                origin_x INTEGER,
+                origin_y INTEGER,
+                origin_z INTEGER,
+                piece BLOB NOT NULL,
+                checksum BLOB,
+                PRIMARY KEY (x,y,z,origin_x,origin_y,origin_z)
+                )",
+            [],
+        )?;
+        if !has_column(&conn, "structure_pieces", "checksum")? {
+            conn.execute("alter table structure_pieces add column checksum BLOB", [])?;
+        }
+
+        conn.execute(
+            "create table if not exists block_entities (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                storage TEXT NOT NULL,
+                state TEXT,
+                PRIMARY KEY (x,y,z)
+                )",
+            [],
+        )?;
+        if !has_column(&conn, "block_entities", "state")? {
+            conn.execute("alter table block_entities add column state TEXT", [])?;
+        }
+
+        conn.execute(
+            "create table if not exists beacons (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                effect TEXT NOT NULL,
+                PRIMARY KEY (x,y,z)
+                )",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists generated_chunks (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                generator BLOB NOT NULL,
+                chunk BLOB NOT NULL,
+                PRIMARY KEY (x,y,z)
                 )",
             [],
-        )
-        .expect("Could not create players table");
+        )?;
+
+        conn.execute(
+            "create table if not exists corrupt_chunk_data (
+                id INTEGER PRIMARY KEY,
+                source TEXT NOT NULL,
+                key TEXT NOT NULL,
+                data BLOB NOT NULL,
+                reason TEXT NOT NULL,
+                time INTEGER NOT NULL
+                )",
+            [],
+        )?;
+
+        conn.execute(
+            "create table if not exists paintings (
+                x INTEGER,
+                y INTEGER,
+                z INTEGER,
+                name TEXT NOT NULL,
+                normal_x INTEGER,
+                normal_z INTEGER,
+                width INTEGER,
+                height INTEGER,
+                PRIMARY KEY (x,y,z)
+                )",
+            [],
+        )?;
 
         // Stores structs that should persist through shutdowns as json
         conn.execute(
@@ -195,8 +714,9 @@ impl Database {
                 data TEXT NOT NULL
                 )",
             [],
-        )
-        .expect("Could not create struct storage table");
+        )?;
+
+        return Ok(());
     }
 
     // TODO: rusqlite doesn't drop stuff correctly so there's all kinds of errors when you don't
@@ -306,17 +826,19 @@ impl Database {
     //    }
     //}
 
+    /// The blocks that have been changed in the chunk, by block index. Blocks that can't be read
+    /// are moved to 'corrupt_chunk_data' and left out.
     pub fn load_chunk_blocks(
         &self,
         position: &IVec3,
-    ) -> HashMap<usize, (BlockId, Option<BlockState>)> {
-        let conn = self.get_connection();
+    ) -> Result<HashMap<usize, (BlockId, Option<BlockState>)>, DatabaseError> {
+        let (blocks, corrupt) = self.retry(|| {
+            let conn = self.get_connection()?;
 
-        let mut block_stmt = conn
-            .prepare(
+            let mut block_stmt = conn.prepare(
                 r#"
             select
-                x, z, y, block_id, block_state
+                x, y, z, block_id, block_state
             from
                 blocks
             where
@@ -325,38 +847,66 @@ impl Database {
                 (y between ? and ?)
             and
                 (z between ? and ?)"#,
-            )
-            .unwrap();
+            )?;
 
-        const OFFSET: i32 = CHUNK_SIZE as i32 - 1;
-        let mut rows = block_stmt
-            .query([
+            const OFFSET: i32 = CHUNK_SIZE as i32 - 1;
+            let mut rows = block_stmt.query([
                 &position.x,
                 &(position.x + OFFSET),
                 &position.y,
                 &(position.y + OFFSET),
                 &position.z,
                 &(position.z + OFFSET),
-            ])
-            .unwrap();
-
-        let mut blocks = HashMap::new();
-
-        while let Some(row) = rows.next().unwrap() {
-            let index = (((row.get::<_, i32>(0).unwrap() & OFFSET) << 8)
-                | ((row.get::<_, i32>(1).unwrap() & OFFSET) << 4)
-                | (row.get::<_, i32>(2).unwrap() & OFFSET)) as usize;
+            ])?;
+
+            let block_configs = Blocks::get();
+            let mut blocks = HashMap::new();
+            let mut corrupt = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                let block_position = IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?);
+
+                let block_id = match row.get::<_, BlockId>(3) {
+                    Ok(block_id) if block_configs.is_valid_id(block_id) => Ok(block_id),
+                    Ok(block_id) => Err(format!("there is no block with the id {}", block_id)),
+                    Err(err) => Err(format!("invalid block id: {}", err)),
+                };
+                let block_state = row
+                    .get::<_, Option<u16>>(4)
+                    .map_err(|err| format!("invalid block state: {}", err));
+
+                match (block_id, block_state) {
+                    (Ok(block_id), Ok(block_state)) => {
+                        let (_, index) =
+                            utils::world_position_to_chunk_position_and_block_index(block_position);
+                        blocks.insert(index, (block_id, block_state.map(BlockState)));
+                    }
+                    (Err(reason), _) | (_, Err(reason)) => {
+                        let data = format!(
+                            "block_id: {:?}, block_state: {:?}",
+                            row.get::<_, rusqlite::types::Value>(3)?,
+                            row.get::<_, rusqlite::types::Value>(4)?
+                        );
+                        corrupt.push((block_position, data, reason));
+                    }
+                }
+            }
 
-            blocks.insert(
-                index,
-                (
-                    row.get::<_, BlockId>(3).unwrap(),
-                    row.get::<_, u16>(4).ok().map(BlockState),
-                ),
+            return Ok((blocks, corrupt));
+        })?;
+
+        for (block_position, data, reason) in corrupt {
+            self.back_up_corrupt_record(
+                position,
+                "blocks",
+                &["x", "y", "z"],
+                &block_position.to_array(),
+                data.as_bytes(),
+                &reason,
             );
         }
 
-        return blocks;
+        return Ok(blocks);
     }
 
     //pub async fn save_chunk(&self, position: &IVec3, chunk: &Chunk) {
@@ -440,78 +990,965 @@ impl Database {
     //    transaction.commit().unwrap();
     //}
 
-    pub fn load_player(&self, username: &str) -> Option<PlayerSave> {
-        let conn = self.get_connection();
+    pub fn load_player(&self, player_id: &str) -> Result<Option<PlayerSave>, DatabaseError> {
+        let bytes: Option<Vec<u8>> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT save FROM players WHERE id = ?")?;
+            let mut rows = stmt.query([player_id])?;
 
-        let mut stmt = conn
-            .prepare("SELECT save FROM players WHERE name = ?")
-            .unwrap();
-        let mut rows = if let Ok(rows) = stmt.query([username]) {
-            rows
-        } else {
-            return None;
+            if let Some(row) = rows.next()? {
+                return Ok(Some(row.get(0)?));
+            } else {
+                return Ok(None);
+            }
+        })?;
+
+        let Some(bytes) = bytes else {
+            return Ok(None);
         };
 
-        if let Some(row) = rows.next().unwrap() {
-            let bytes: Vec<u8> = row.get(0).unwrap();
-            let save: PlayerSave = bincode::deserialize(&bytes).unwrap();
-            return Some(save);
-        } else {
-            return None;
+        return bincode::deserialize(&bytes)
+            .map(Some)
+            .map_err(|err| DatabaseError::Corrupt(format!("save of '{}': {}", player_id, err)));
+    }
+
+    /// Save a structure, and a piece of it for each chunk it covers. Returns false and saves
+    /// nothing if a structure has already been saved with the same origin.
+    pub fn save_structure(&self, structure: &PlannedStructure) -> Result<bool, DatabaseError> {
+        let pieces: Vec<(IVec3, Vec<u8>)> = structure
+            .feature
+            .blocks
+            .iter()
+            .map(|(chunk_position, blocks)| {
+                let piece = (blocks, &structure.feature.can_replace);
+                (*chunk_position, bincode::serialize(&piece).unwrap())
+            })
+            .collect();
+
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
+
+            let origin = structure.origin;
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO structures (x, y, z, name) VALUES (?,?,?,?)",
+                rusqlite::params![origin.x, origin.y, origin.z, structure.name],
+            )?;
+            if inserted == 0 {
+                return Ok(false);
+            }
+
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                INSERT OR REPLACE INTO
+                    structure_pieces (x, y, z, origin_x, origin_y, origin_z, piece, checksum)
+                VALUES
+                    (?,?,?,?,?,?,?,?)"#,
+                )?;
+                for (chunk_position, piece) in pieces.iter() {
+                    stmt.execute(rusqlite::params![
+                        chunk_position.x,
+                        chunk_position.y,
+                        chunk_position.z,
+                        origin.x,
+                        origin.y,
+                        origin.z,
+                        piece,
+                        sha1::Sha1::digest(piece).to_vec()
+                    ])?;
+                }
+            }
+
+            tx.commit()?;
+
+            return Ok(true);
+        });
+    }
+
+    /// The pieces of the structures that cover the chunk. Pieces that can't be read are moved
+    /// to 'corrupt_chunk_data' and left out.
+    pub fn load_structure_pieces(
+        &self,
+        chunk_position: &IVec3,
+    ) -> Result<Vec<TerrainFeature>, DatabaseError> {
+        let rows: Vec<(IVec3, Vec<u8>, Option<Vec<u8>>)> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                r#"
+            SELECT
+                origin_x, origin_y, origin_z, piece, checksum
+            FROM
+                structure_pieces
+            WHERE
+                x = ? AND y = ? AND z = ?"#,
+            )?;
+            let mut rows = stmt.query([chunk_position.x, chunk_position.y, chunk_position.z])?;
+
+            let mut pieces = Vec::new();
+            while let Some(row) = rows.next()? {
+                let origin = IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?);
+                pieces.push((origin, row.get(3)?, row.get(4)?));
+            }
+
+            return Ok(pieces);
+        })?;
+
+        let mut pieces = Vec::with_capacity(rows.len());
+        for (origin, bytes, checksum) in rows {
+            match read_structure_piece(&bytes, checksum.as_deref()) {
+                Ok((blocks, can_replace)) => pieces.push(TerrainFeature {
+                    blocks: HashMap::from([(*chunk_position, blocks)]),
+                    can_replace,
+                    loot: Vec::new(),
+                }),
+                Err(reason) => self.back_up_corrupt_record(
+                    chunk_position,
+                    "structure_pieces",
+                    &["x", "y", "z", "origin_x", "origin_y", "origin_z"],
+                    &[
+                        chunk_position.x,
+                        chunk_position.y,
+                        chunk_position.z,
+                        origin.x,
+                        origin.y,
+                        origin.z,
+                    ],
+                    &bytes,
+                    &reason,
+                ),
+            }
+        }
+
+        return Ok(pieces);
+    }
+
+    /// The item storage of the block entities in the chunk, by block position.
+    pub fn load_block_entities(
+        &self,
+        chunk_position: &IVec3,
+    ) -> Result<Vec<(IVec3, ItemStorage)>, DatabaseError> {
+        let rows: Vec<(IVec3, String)> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                r#"
+            select
+                x, y, z, storage
+            from
+                block_entities
+            where
+                (x between ? and ?)
+            and
+                (y between ? and ?)
+            and
+                (z between ? and ?)"#,
+            )?;
+
+            const OFFSET: i32 = CHUNK_SIZE as i32 - 1;
+            let mut rows = stmt.query([
+                &chunk_position.x,
+                &(chunk_position.x + OFFSET),
+                &chunk_position.y,
+                &(chunk_position.y + OFFSET),
+                &chunk_position.z,
+                &(chunk_position.z + OFFSET),
+            ])?;
+
+            let mut block_entities = Vec::new();
+            while let Some(row) = rows.next()? {
+                let position = IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?);
+                block_entities.push((position, row.get(3)?));
+            }
+
+            return Ok(block_entities);
+        })?;
+
+        let mut block_entities = Vec::with_capacity(rows.len());
+        for (position, json) in rows {
+            match serde_json::from_str(&json) {
+                Ok(storage) => block_entities.push((position, storage)),
+                Err(err) => self.back_up_corrupt_record(
+                    chunk_position,
+                    "block_entities",
+                    &["x", "y", "z"],
+                    &position.to_array(),
+                    json.as_bytes(),
+                    &err.to_string(),
+                ),
+            }
+        }
+
+        return Ok(block_entities);
+    }
+
+    // Moves a record that can't be read out of its table and into 'corrupt_chunk_data', so the
+    // chunk can be loaded without it. The key columns are the primary key of the table.
+    fn back_up_corrupt_record(
+        &self,
+        chunk_position: &IVec3,
+        source: &str,
+        key_columns: &[&str],
+        key: &[i32],
+        data: &[u8],
+        reason: &str,
+    ) {
+        error!(
+            "Found corrupt data in the '{}' table while loading the chunk at {}, it will be \
+            generated without it. The data has been moved to 'corrupt_chunk_data'.\nReason: {}",
+            source, chunk_position, reason
+        );
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        let key_string = key
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let condition = key_columns
+            .iter()
+            .map(|column| format!("{} = ?", column))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+
+        let result = self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO corrupt_chunk_data (source, key, data, reason, time) VALUES (?,?,?,?,?)",
+                rusqlite::params![source, key_string, data, reason, time],
+            )?;
+            tx.execute(
+                &format!("DELETE FROM {} WHERE {}", source, condition),
+                rusqlite::params_from_iter(key),
+            )?;
+            tx.commit()?;
+            return Ok(());
+        });
+
+        if let Err(err) = result {
+            error!(
+                "Failed to move the corrupt data out of the '{}' table: {}",
+                source, err
+            );
+        }
+    }
+
+    pub fn save_block_entity(
+        &self,
+        position: IVec3,
+        storage: &ItemStorage,
+    ) -> Result<(), DatabaseError> {
+        let storage = serde_json::to_string(storage).unwrap();
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            // Upsert so the state is kept.
+            let mut stmt = conn.prepare(
+                "INSERT INTO block_entities (x, y, z, storage) VALUES (?,?,?,?)
+                ON CONFLICT (x, y, z) DO UPDATE SET storage = excluded.storage",
+            )?;
+            stmt.execute(rusqlite::params![
+                position.x, position.y, position.z, storage
+            ])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The state of the block entity, None if it has not been saved. The state can only be saved
+    /// after the block entity's storage.
+    pub fn load_block_entity_state(
+        &self,
+        position: IVec3,
+    ) -> Result<Option<String>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt =
+                conn.prepare("SELECT state FROM block_entities WHERE x = ? AND y = ? AND z = ?")?;
+            let mut rows = stmt.query([position.x, position.y, position.z])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(row.get(0)?);
+            } else {
+                return Ok(None);
+            }
+        });
+    }
+
+    /// Save the state of a block entity that has had its storage saved, it is ignored otherwise.
+    pub fn save_block_entity_state(
+        &self,
+        position: IVec3,
+        state: &str,
+    ) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn
+                .prepare("UPDATE block_entities SET state = ? WHERE x = ? AND y = ? AND z = ?")?;
+            stmt.execute(rusqlite::params![state, position.x, position.y, position.z])?;
+
+            return Ok(());
+        });
+    }
+
+    pub fn delete_block_entity(&self, position: IVec3) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt =
+                conn.prepare("DELETE FROM block_entities WHERE x = ? AND y = ? AND z = ?")?;
+            stmt.execute([position.x, position.y, position.z])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The effects of all the beacons, by block position. The effects are stored by name.
+    pub fn load_beacons(&self) -> Result<Vec<(IVec3, String)>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT x, y, z, effect FROM beacons")?;
+            let mut rows = stmt.query([])?;
+
+            let mut beacons = Vec::new();
+            while let Some(row) = rows.next()? {
+                let position = IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?);
+                beacons.push((position, row.get(3)?));
+            }
+
+            return Ok(beacons);
+        });
+    }
+
+    pub fn save_beacon(&self, position: IVec3, effect: &str) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("INSERT OR REPLACE INTO beacons VALUES (?,?,?,?)")?;
+            stmt.execute(rusqlite::params![
+                position.x, position.y, position.z, effect
+            ])?;
+
+            return Ok(());
+        });
+    }
+
+    pub fn delete_beacon(&self, position: IVec3) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("DELETE FROM beacons WHERE x = ? AND y = ? AND z = ?")?;
+            stmt.execute([position.x, position.y, position.z])?;
+
+            return Ok(());
+        });
+    }
+
+    /// Save a chunk as it was generated by the terrain generator with the fingerprint.
+    pub fn save_generated_chunk(
+        &self,
+        position: &IVec3,
+        generator: &[u8],
+        chunk: &GeneratedChunk,
+    ) -> Result<(), DatabaseError> {
+        let bytes = bincode::serialize(chunk).unwrap();
+        let compressed = zstd::stream::encode_all(bytes.as_slice(), 0).unwrap();
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO generated_chunks (x, y, z, generator, chunk) VALUES (?,?,?,?,?)",
+            )?;
+            stmt.execute(rusqlite::params![
+                position.x,
+                position.y,
+                position.z,
+                generator,
+                compressed
+            ])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The chunk as it was generated, if it has been generated ahead of time by the terrain
+    /// generator with the fingerprint. Chunks that can't be read are moved to
+    /// 'corrupt_chunk_data' and left out.
+    pub fn load_generated_chunk(
+        &self,
+        position: &IVec3,
+        generator: &[u8],
+    ) -> Result<Option<GeneratedChunk>, DatabaseError> {
+        let compressed: Option<Vec<u8>> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "SELECT chunk FROM generated_chunks WHERE x = ? AND y = ? AND z = ? AND generator = ?",
+            )?;
+            let mut rows = stmt.query(rusqlite::params![
+                position.x, position.y, position.z, generator
+            ])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(Some(row.get(0)?));
+            } else {
+                return Ok(None);
+            }
+        })?;
+
+        let Some(compressed) = compressed else {
+            return Ok(None);
         };
+
+        match read_generated_chunk(&compressed) {
+            Ok(chunk) => return Ok(Some(chunk)),
+            Err(reason) => {
+                self.back_up_corrupt_record(
+                    position,
+                    "generated_chunks",
+                    &["x", "y", "z"],
+                    &position.to_array(),
+                    &compressed,
+                    &reason,
+                );
+                return Ok(None);
+            }
+        }
+    }
+
+    /// If the chunk has been generated ahead of time by the terrain generator with the
+    /// fingerprint.
+    pub fn has_generated_chunk(
+        &self,
+        position: &IVec3,
+        generator: &[u8],
+    ) -> Result<bool, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "SELECT 1 FROM generated_chunks WHERE x = ? AND y = ? AND z = ? AND generator = ?",
+            )?;
+            return Ok(stmt.exists(rusqlite::params![
+                position.x, position.y, position.z, generator
+            ])?);
+        });
+    }
+
+    /// Remove all the chunks that were generated ahead of time. Returns how many there were.
+    pub fn clear_generated_chunks(&self) -> Result<usize, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+            return Ok(conn.execute("DELETE FROM generated_chunks", [])?);
+        });
     }
 
     /// Save a player's information
-    pub fn save_player(&self, username: &str, save: &PlayerSave) {
-        let conn = self.get_connection();
+    pub fn save_player(&self, player_id: &str, save: &PlayerSave) -> Result<(), DatabaseError> {
+        let bytes = bincode::serialize(save).unwrap();
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("INSERT OR REPLACE INTO players VALUES (?,?)")?;
+            stmt.execute(rusqlite::params![player_id, bytes])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The id of the player with the name, None if there is no player with the name.
+    pub fn player_id(&self, username: &str) -> Result<Option<String>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT id FROM accounts WHERE name = ?")?;
+            let mut rows = stmt.query([username])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(Some(row.get(0)?));
+            } else {
+                return Ok(None);
+            }
+        });
+    }
+
+    /// The id of the player with the name. If there is no player with the name, an account is
+    /// created for it, which the first player to log in with the name can claim.
+    pub fn player_id_or_create(&self, username: &str) -> Result<String, DatabaseError> {
+        if let Some(player_id) = self.player_id(username)? {
+            return Ok(player_id);
+        }
+
+        let player_id = new_player_id();
+        self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("INSERT INTO accounts (id, name) VALUES (?,?)")?;
+            stmt.execute(rusqlite::params![player_id, username])?;
+
+            return Ok(());
+        })?;
+
+        return Ok(player_id);
+    }
+
+    /// Change the name of a player. Fails with [DatabaseError::Rejected] if the name is taken.
+    pub fn rename_player(&self, player_id: &str, username: &str) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("UPDATE accounts SET name = ? WHERE id = ?")?;
+            stmt.execute(rusqlite::params![username, player_id])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The ids of the operators with the names. A name is tied to the player that has it the first
+    /// time it is looked up, so the player stays an operator when they change their name, and
+    /// whoever takes the name after them doesn't become one. Names that no one has yet are
+    /// reserved, like with [Database::player_id_or_create]. Names that are no longer listed are
+    /// forgotten, so they are tied to whoever has them if they are listed again.
+    pub fn operator_ids(&self, names: &[String]) -> Result<HashSet<String>, DatabaseError> {
+        let mut operator_ids = HashSet::new();
+
+        for name in names {
+            let player_id = self.retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt = conn.prepare("SELECT player FROM operators WHERE name = ?")?;
+                let mut rows = stmt.query([name])?;
+                match rows.next()? {
+                    Some(row) => return Ok(Some(row.get(0)?)),
+                    None => return Ok(None),
+                }
+            })?;
+
+            let player_id = match player_id {
+                Some(player_id) => player_id,
+                None => {
+                    let player_id = self.player_id_or_create(name)?;
+                    self.retry(|| {
+                        let conn = self.get_connection()?;
+
+                        let mut stmt =
+                            conn.prepare("INSERT INTO operators (name, player) VALUES (?,?)")?;
+                        stmt.execute(rusqlite::params![name, player_id])?;
+
+                        return Ok(());
+                    })?;
+                    player_id
+                }
+            };
+
+            operator_ids.insert(player_id);
+        }
+
+        self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut select = conn.prepare("SELECT name FROM operators")?;
+            let mut delete = conn.prepare("DELETE FROM operators WHERE name = ?")?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                if !names.contains(&name) {
+                    delete.execute([name])?;
+                }
+            }
+
+            return Ok(());
+        })?;
+
+        return Ok(operator_ids);
+    }
+
+    /// Store a letter until the recipient collects it.
+    pub fn send_mail(
+        &self,
+        recipient_id: &str,
+        sender: &str,
+        message: &str,
+        item_stack: Option<&ItemStack>,
+    ) -> Result<(), DatabaseError> {
+        let item = item_stack.map(|item_stack| serde_json::to_string(item_stack).unwrap());
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn
+                .prepare("INSERT INTO mail (recipient, sender, message, item) VALUES (?,?,?,?)")?;
+            stmt.execute(rusqlite::params![recipient_id, sender, message, item])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The letters sent to a player, oldest first.
+    pub fn load_mail(&self, recipient_id: &str) -> Result<Vec<Letter>, DatabaseError> {
+        let rows: Vec<(i64, String, String, Option<String>)> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "SELECT id, sender, message, item FROM mail WHERE recipient = ? ORDER BY id",
+            )?;
+            let mut rows = stmt.query([recipient_id])?;
+
+            let mut letters = Vec::new();
+            while let Some(row) = rows.next()? {
+                letters.push((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?));
+            }
+
+            return Ok(letters);
+        })?;
+
+        let mut letters = Vec::with_capacity(rows.len());
+        for (id, sender, message, item) in rows {
+            let item_stack = match item {
+                Some(json) => Some(serde_json::from_str(&json).map_err(|err| {
+                    DatabaseError::Corrupt(format!("item of letter {}: {}", id, err))
+                })?),
+                None => None,
+            };
+
+            letters.push(Letter {
+                id,
+                sender,
+                message,
+                item_stack,
+            });
+        }
+
+        return Ok(letters);
+    }
+
+    /// How many letters are waiting for a player.
+    pub fn count_mail(&self, recipient_id: &str) -> Result<usize, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let count: i64 = conn.query_row(
+                "SELECT count(*) FROM mail WHERE recipient = ?",
+                [recipient_id],
+                |row| row.get(0),
+            )?;
+
+            return Ok(count as usize);
+        });
+    }
+
+    /// Replace the item attached to a letter, None removes it.
+    pub fn update_mail_item(
+        &self,
+        letter_id: i64,
+        item_stack: Option<&ItemStack>,
+    ) -> Result<(), DatabaseError> {
+        let item = item_stack.map(|item_stack| serde_json::to_string(item_stack).unwrap());
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("UPDATE mail SET item = ? WHERE id = ?")?;
+            stmt.execute(rusqlite::params![item, letter_id])?;
+
+            return Ok(());
+        });
+    }
+
+    pub fn delete_mail(&self, letter_id: i64) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("DELETE FROM mail WHERE id = ?")?;
+            stmt.execute([letter_id])?;
+
+            return Ok(());
+        });
+    }
+
+    /// The progress of all the quests a player has started, by quest name.
+    pub fn load_quests(
+        &self,
+        player_id: &str,
+    ) -> Result<HashMap<String, QuestProgress>, DatabaseError> {
+        let rows: Vec<(String, String)> = self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT quest, progress FROM quests WHERE player = ?")?;
+            let mut rows = stmt.query([player_id])?;
+
+            let mut quests = Vec::new();
+            while let Some(row) = rows.next()? {
+                quests.push((row.get(0)?, row.get(1)?));
+            }
+
+            return Ok(quests);
+        })?;
+
+        let mut quests = HashMap::with_capacity(rows.len());
+        for (quest, progress) in rows {
+            let progress = serde_json::from_str(&progress).map_err(|err| {
+                DatabaseError::Corrupt(format!("progress of quest '{}': {}", quest, err))
+            })?;
+            quests.insert(quest, progress);
+        }
+
+        return Ok(quests);
+    }
+
+    pub fn save_quest(
+        &self,
+        player_id: &str,
+        quest: &str,
+        progress: &QuestProgress,
+    ) -> Result<(), DatabaseError> {
+        let progress = serde_json::to_string(progress).unwrap();
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO quests (player, quest, progress) VALUES (?,?,?)",
+            )?;
+            stmt.execute(rusqlite::params![player_id, quest, progress])?;
+
+            return Ok(());
+        });
+    }
+
+    pub fn delete_quest(&self, player_id: &str, quest: &str) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("DELETE FROM quests WHERE player = ? AND quest = ?")?;
+            stmt.execute([player_id, quest])?;
+
+            return Ok(());
+        });
+    }
+
+    /// How much money a player has.
+    pub fn balance(&self, player_id: &str) -> Result<u64, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT balance FROM balances WHERE player = ?")?;
+            let mut rows = stmt.query([player_id])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(row.get::<_, i64>(0)? as u64);
+            } else {
+                return Ok(0);
+            }
+        });
+    }
+
+    /// Add the amounts to the balances of the players, and log them as transactions with the
+    /// reason. Either all of them are changed or none are. If a balance would drop below zero,
+    /// nothing is changed and None is returned, otherwise the new balances in the same order.
+    pub fn change_balances(
+        &self,
+        changes: &[(&str, i64)],
+        reason: &str,
+    ) -> Result<Option<Vec<u64>>, DatabaseError> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
+
+            let mut balances = Vec::with_capacity(changes.len());
+            for (player_id, amount) in changes {
+                let balance: i64 = {
+                    let mut stmt = tx.prepare("SELECT balance FROM balances WHERE player = ?")?;
+                    let mut rows = stmt.query([player_id])?;
+                    match rows.next()? {
+                        Some(row) => row.get(0)?,
+                        None => 0,
+                    }
+                };
+
+                let Some(balance) = balance.checked_add(*amount).filter(|balance| *balance >= 0)
+                else {
+                    // Dropping the transaction rolls back the changes made so far.
+                    return Ok(None);
+                };
+
+                tx.execute(
+                    "INSERT OR REPLACE INTO balances (player, balance) VALUES (?,?)",
+                    rusqlite::params![player_id, balance],
+                )?;
+                tx.execute(
+                    "INSERT INTO transactions (player, amount, balance, reason, time) VALUES (?,?,?,?,?)",
+                    rusqlite::params![player_id, amount, balance, reason, time],
+                )?;
+
+                balances.push(balance as u64);
+            }
+
+            tx.commit()?;
+
+            return Ok(Some(balances));
+        });
+    }
+
+    pub fn load_teams(&self) -> Result<HashMap<String, Team>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt =
+                conn.prepare("SELECT name, color, friendly_fire, shared_visibility FROM teams")?;
+            let mut rows = stmt.query([])?;
+
+            let mut teams = HashMap::new();
+            while let Some(row) = rows.next()? {
+                teams.insert(
+                    row.get(0)?,
+                    Team {
+                        color: row.get(1)?,
+                        friendly_fire: row.get(2)?,
+                        shared_visibility: row.get(3)?,
+                    },
+                );
+            }
+
+            return Ok(teams);
+        });
+    }
+
+    /// Store a team, it replaces the team with the same name.
+    pub fn save_team(&self, name: &str, team: &Team) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO teams (name, color, friendly_fire, shared_visibility) VALUES (?,?,?,?)",
+            )?;
+            stmt.execute(rusqlite::params![
+                name,
+                team.color,
+                team.friendly_fire,
+                team.shared_visibility
+            ])?;
+
+            return Ok(());
+        });
+    }
+
+    /// Remove a team, its members are left without a team.
+    pub fn delete_team(&self, name: &str) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
+
+            tx.execute("DELETE FROM team_members WHERE team = ?", [name])?;
+            tx.execute("DELETE FROM teams WHERE name = ?", [name])?;
+
+            tx.commit()?;
+
+            return Ok(());
+        });
+    }
+
+    /// The name of the team the player is on.
+    pub fn player_team(&self, player_id: &str) -> Result<Option<String>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("SELECT team FROM team_members WHERE player = ?")?;
+            let mut rows = stmt.query([player_id])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(Some(row.get(0)?));
+            } else {
+                return Ok(None);
+            }
+        });
+    }
+
+    /// Put the player on a team, None removes them from the team they are on.
+    pub fn set_player_team(
+        &self,
+        player_id: &str,
+        team: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            match team {
+                Some(team) => {
+                    let mut stmt = conn.prepare(
+                        "INSERT OR REPLACE INTO team_members (player, team) VALUES (?,?)",
+                    )?;
+                    stmt.execute([player_id, team])?;
+                }
+                None => {
+                    let mut stmt = conn.prepare("DELETE FROM team_members WHERE player = ?")?;
+                    stmt.execute([player_id])?;
+                }
+            }
 
-        let mut stmt = conn
-            .prepare("INSERT OR REPLACE INTO players VALUES (?,?)")
-            .unwrap();
-        stmt.execute(rusqlite::params![
-            username,
-            bincode::serialize(save).unwrap()
-        ])
-        .unwrap();
+            return Ok(());
+        });
     }
 
     /// Add new block ids to the database. The ids will be constant and cannot change.
-    pub fn save_block_ids(&self) {
-        fn walk_dir<P: AsRef<std::path::Path>>(dir: P) -> Vec<std::path::PathBuf> {
+    pub fn save_block_ids(&self) -> Result<(), DatabaseError> {
+        fn walk_dir(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>, DatabaseError> {
             let mut files = Vec::new();
 
-            let directory = std::fs::read_dir(dir).expect(
-                "Could not read files from block configuration directory, make sure it is present.",
-            );
+            let directory = std::fs::read_dir(dir).map_err(|err| {
+                DatabaseError::Config(format!("block config directory {}: {}", dir.display(), err))
+            })?;
 
             for entry in directory {
                 let file_path = entry
-                    .expect("Failed to read the filename of a block config")
+                    .map_err(|err| {
+                        DatabaseError::Config(format!("block config in {}: {}", dir.display(), err))
+                    })?
                     .path();
 
                 if file_path.is_dir() {
-                    let sub_files = walk_dir(&file_path);
+                    let sub_files = walk_dir(&file_path)?;
                     files.extend(sub_files);
                 } else {
                     files.push(file_path);
                 }
             }
 
-            files
+            return Ok(files);
         }
 
         let mut block_names: Vec<String> = Vec::new();
 
-        for file_path in walk_dir(&crate::world::blocks::BLOCK_CONFIG_PATH) {
-            let file = std::fs::File::open(&file_path).unwrap();
-            let config: serde_json::Value = match serde_json::from_reader(file) {
-                Ok(c) => c,
-                Err(e) => panic!(
-                    "Failed to read block config at path: {}\nError: {}",
-                    file_path.display(),
-                    e
-                ),
-            };
+        let block_config_path = std::path::Path::new(crate::world::blocks::BLOCK_CONFIG_PATH);
+        for file_path in walk_dir(block_config_path)? {
+            let config: serde_json::Value = std::fs::File::open(&file_path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| serde_json::from_reader(file).map_err(|err| err.to_string()))
+                .map_err(|err| {
+                    DatabaseError::Config(format!(
+                        "block config at {}: {}",
+                        file_path.display(),
+                        err
+                    ))
+                })?;
 
             let block_name = match config.get("name").and_then(|name| name.as_str()) {
                 Some(n) => n,
@@ -522,90 +1959,100 @@ impl Database {
             block_names.push(block_name.to_owned());
         }
 
-        let mut conn = self.get_connection();
-        let tx = conn.transaction().unwrap();
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
 
-        let mut stmt = tx
-            .prepare("INSERT INTO block_ids (id, name) VALUES (?, ?)")
-            .unwrap();
+            let mut stmt = tx.prepare("INSERT INTO block_ids (id, name) VALUES (?, ?)")?;
 
-        for (id, name) in block_names.into_iter().enumerate() {
-            stmt.execute(rusqlite::params![id, name]).unwrap();
-        }
+            for (id, name) in block_names.iter().enumerate() {
+                stmt.execute(rusqlite::params![id, name])?;
+            }
 
-        stmt.finalize().unwrap();
-        tx.commit().expect("Failed to update block ids in database");
+            stmt.finalize()?;
+            tx.commit()?;
+
+            return Ok(());
+        });
     }
 
-    pub fn load_block_ids(&self) -> HashMap<String, BlockId> {
-        let conn = self.get_connection();
-        let mut stmt = conn.prepare("SELECT * FROM block_ids").unwrap();
-        let mut rows = stmt.query([]).unwrap();
+    pub fn load_block_ids(&self) -> Result<HashMap<String, BlockId>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+            let mut stmt = conn.prepare("SELECT * FROM block_ids")?;
+            let mut rows = stmt.query([])?;
 
-        let mut blocks = HashMap::new();
-        while let Some(row) = rows.next().unwrap() {
-            blocks.insert(row.get(1).unwrap(), row.get(0).unwrap());
-        }
+            let mut blocks = HashMap::new();
+            while let Some(row) = rows.next()? {
+                blocks.insert(row.get(1)?, row.get(0)?);
+            }
 
-        return blocks;
+            return Ok(blocks);
+        });
     }
 
-    pub fn save_items(&self) {
+    pub fn save_items(&self) -> Result<(), DatabaseError> {
         let mut item_names = Vec::new();
 
-        let directory = std::fs::read_dir(crate::world::items::ITEM_CONFIG_PATH).expect(
-            "Could not read files from item configuration directory, make sure it is present.\n",
-        );
+        let config_error = |err: String| {
+            DatabaseError::Config(format!(
+                "item config directory {}: {}",
+                crate::world::items::ITEM_CONFIG_PATH,
+                err
+            ))
+        };
+
+        let directory = std::fs::read_dir(crate::world::items::ITEM_CONFIG_PATH)
+            .map_err(|err| config_error(err.to_string()))?;
 
         for dir_entry in directory {
-            let file_path = match dir_entry {
-                Ok(d) => d.path(),
-                Err(e) => panic!(
-                    "Failed to read the filename of a block config, Error: {}",
-                    e
-                ),
+            let file_path = dir_entry
+                .map_err(|err| config_error(err.to_string()))?
+                .path();
+
+            let Some(name) = file_path.file_stem().and_then(|name| name.to_str()) else {
+                return Err(config_error(format!(
+                    "'{}' is not a valid item name",
+                    file_path.display()
+                )));
             };
 
-            item_names.push(
-                file_path
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_lowercase(),
-            );
+            item_names.push(name.to_lowercase());
         }
 
-        let mut conn = self.get_connection();
-        let tx = conn.transaction().unwrap();
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
 
-        let mut stmt = tx
-            .prepare("INSERT INTO item_ids (name) VALUES (?)")
-            .unwrap();
+            let mut stmt = tx.prepare("INSERT INTO item_ids (name) VALUES (?)")?;
 
-        for name in item_names {
-            stmt.execute(rusqlite::params![name]).unwrap();
-        }
+            for name in item_names.iter() {
+                stmt.execute(rusqlite::params![name])?;
+            }
+
+            stmt.finalize()?;
+            tx.commit()?;
 
-        stmt.finalize().unwrap();
-        tx.commit()
-            .expect("Failed to save item ids to the database");
+            return Ok(());
+        });
     }
 
-    pub fn load_item_ids(&self) -> HashMap<String, ItemId> {
-        let conn = self.get_connection();
-        let mut stmt = conn.prepare("SELECT * FROM item_ids").unwrap();
-        let mut rows = stmt.query([]).unwrap();
+    pub fn load_item_ids(&self) -> Result<HashMap<String, ItemId>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+            let mut stmt = conn.prepare("SELECT * FROM item_ids")?;
+            let mut rows = stmt.query([])?;
 
-        let mut blocks = HashMap::new();
-        while let Some(row) = rows.next().unwrap() {
-            blocks.insert(row.get(1).unwrap(), row.get(0).unwrap());
-        }
+            let mut items = HashMap::new();
+            while let Some(row) = rows.next()? {
+                items.insert(row.get(1)?, row.get(0)?);
+            }
 
-        return blocks;
+            return Ok(items);
+        });
     }
 
-    pub fn save_models(&self) {
+    pub fn save_models(&self) -> Result<(), DatabaseError> {
         let mut model_names = Vec::new();
 
         let directory = std::fs::read_dir(crate::world::models::MODEL_PATH)
@@ -627,60 +2074,428 @@ impl Database {
             );
         }
 
-        let mut conn = self.get_connection();
-        let tx = conn.transaction().unwrap();
+        return self.retry(|| {
+            let mut conn = self.get_connection()?;
+            let tx = conn.transaction()?;
 
-        let mut stmt = tx
-            .prepare("INSERT INTO model_ids (name, id) VALUES (?, ?)")
-            .unwrap();
+            let mut stmt = tx.prepare("INSERT INTO model_ids (name, id) VALUES (?, ?)")?;
 
-        for (id, name) in model_names.into_iter().enumerate() {
-            stmt.execute(rusqlite::params![name, id]).unwrap();
-        }
+            for (id, name) in model_names.iter().enumerate() {
+                stmt.execute(rusqlite::params![name, id])?;
+            }
 
-        stmt.finalize().unwrap();
-        tx.commit()
-            .expect("Failed to save item ids to the database");
+            stmt.finalize()?;
+            tx.commit()?;
+
+            return Ok(());
+        });
     }
 
-    pub fn load_model_ids(&self) -> HashMap<String, u32> {
-        let conn = self.get_connection();
-        let mut stmt = conn.prepare("SELECT name, id FROM model_ids").unwrap();
-        let mut rows = stmt.query([]).unwrap();
+    pub fn load_model_ids(&self) -> Result<HashMap<String, u32>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+            let mut stmt = conn.prepare("SELECT name, id FROM model_ids")?;
+            let mut rows = stmt.query([])?;
 
-        let mut models = HashMap::new();
-        while let Some(row) = rows.next().unwrap() {
-            models.insert(row.get(0).unwrap(), row.get(1).unwrap());
-        }
+            let mut models = HashMap::new();
+            while let Some(row) = rows.next()? {
+                models.insert(row.get(0)?, row.get(1)?);
+            }
+
+            return Ok(models);
+        });
+    }
+
+    /// All the paintings in the world.
+    pub fn load_paintings(&self) -> Result<Vec<Painting>, DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "SELECT x, y, z, name, normal_x, normal_z, width, height FROM paintings",
+            )?;
+            let mut rows = stmt.query([])?;
+
+            let mut paintings = Vec::new();
+            while let Some(row) = rows.next()? {
+                let normal = IVec3::new(row.get(4)?, 0, row.get(5)?);
+                paintings.push(Painting {
+                    name: row.get(3)?,
+                    position: IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?),
+                    normal,
+                    right: IVec3::Y.cross(normal),
+                    width: row.get(6)?,
+                    height: row.get(7)?,
+                });
+            }
+
+            return Ok(paintings);
+        });
+    }
 
-        return models;
+    pub fn save_painting(&self, painting: &Painting) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare(
+                "INSERT OR REPLACE INTO paintings (x, y, z, name, normal_x, normal_z, width, height) VALUES (?,?,?,?,?,?,?,?)",
+            )?;
+            stmt.execute(rusqlite::params![
+                painting.position.x,
+                painting.position.y,
+                painting.position.z,
+                painting.name,
+                painting.normal.x,
+                painting.normal.z,
+                painting.width,
+                painting.height
+            ])?;
+
+            return Ok(());
+        });
     }
 
-    pub fn save_world_properties(&self, properties: &WorldProperties) {
-        let conn = self.get_connection();
-        let mut stmt = conn
-            .prepare("INSERT OR REPLACE INTO storage (name, data) VALUES (?,?)")
-            .unwrap();
+    pub fn delete_painting(&self, position: IVec3) -> Result<(), DatabaseError> {
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+
+            let mut stmt = conn.prepare("DELETE FROM paintings WHERE x = ? AND y = ? AND z = ?")?;
+            stmt.execute([position.x, position.y, position.z])?;
 
-        stmt.execute(rusqlite::params![
-            "world_properties",
-            serde_json::to_string(properties).unwrap()
-        ])
-        .unwrap();
+            return Ok(());
+        });
     }
 
-    pub fn load_world_properties(&self) -> Option<WorldProperties> {
-        let conn = self.get_connection();
-        let mut stmt = conn
-            .prepare("SELECT data FROM storage WHERE name = ?")
-            .unwrap();
+    pub fn save_world_properties(&self, properties: &WorldProperties) -> Result<(), DatabaseError> {
+        let data = serde_json::to_string(properties).unwrap();
+
+        return self.retry(|| {
+            let conn = self.get_connection()?;
+            let mut stmt =
+                conn.prepare("INSERT OR REPLACE INTO storage (name, data) VALUES (?,?)")?;
+
+            stmt.execute(rusqlite::params!["world_properties", data])?;
 
-        let data: String = match stmt.query_row(["world_properties"], |row| row.get(0)) {
-            Ok(data) => data,
-            Err(_) => return None,
+            return Ok(());
+        });
+    }
+
+    pub fn load_world_properties(&self) -> Result<Option<WorldProperties>, DatabaseError> {
+        let data: Option<String> = self.retry(|| {
+            let conn = self.get_connection()?;
+            let mut stmt = conn.prepare("SELECT data FROM storage WHERE name = ?")?;
+            let mut rows = stmt.query(["world_properties"])?;
+
+            if let Some(row) = rows.next()? {
+                return Ok(Some(row.get(0)?));
+            } else {
+                return Ok(None);
+            }
+        })?;
+
+        let Some(data) = data else {
+            return Ok(None);
         };
 
-        let properties: WorldProperties = serde_json::from_str(&data).unwrap();
-        return Some(properties);
+        return serde_json::from_str(&data)
+            .map(Some)
+            .map_err(|err| DatabaseError::Corrupt(format!("world properties: {}", err)));
+    }
+}
+
+impl AccountStorage for Database {
+    fn account(&self, username: &str) -> Result<Option<Account>, String> {
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt =
+                    conn.prepare("SELECT id, password_hash FROM accounts WHERE name = ?")?;
+                let mut rows = stmt.query([username])?;
+
+                if let Some(row) = rows.next()? {
+                    return Ok(Some(Account {
+                        id: row.get(0)?,
+                        password_hash: row.get(1)?,
+                    }));
+                } else {
+                    return Ok(None);
+                }
+            })
+            .map_err(|err| err.to_string());
+    }
+
+    fn create_account(
+        &self,
+        id: &str,
+        username: &str,
+        password_hash: Vec<u8>,
+    ) -> Result<(), String> {
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt =
+                    conn.prepare("INSERT INTO accounts (id, name, password_hash) VALUES (?,?,?)")?;
+                stmt.execute(rusqlite::params![id, username, password_hash])?;
+
+                return Ok(());
+            })
+            .map_err(|err| err.to_string());
+    }
+
+    fn set_password_hash(&self, id: &str, password_hash: Vec<u8>) -> Result<bool, String> {
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt = conn.prepare(
+                    "UPDATE accounts SET password_hash = ? WHERE id = ? AND password_hash IS NULL",
+                )?;
+                let changed = stmt.execute(rusqlite::params![password_hash, id])?;
+
+                return Ok(changed == 1);
+            })
+            .map_err(|err| err.to_string());
+    }
+}
+
+impl BanStorage for Database {
+    fn bans(&self) -> Result<Vec<(Ban, String)>, String> {
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt = conn.prepare("SELECT kind, target, reason FROM bans")?;
+                let mut rows = stmt.query([])?;
+
+                let mut bans = Vec::new();
+                while let Some(row) = rows.next()? {
+                    let kind: String = row.get(0)?;
+                    let target: String = row.get(1)?;
+                    let ban = match kind.as_str() {
+                        "player" => Ban::Player(target),
+                        "address" => match target.parse() {
+                            Ok(ip) => Ban::Address(ip),
+                            Err(_) => {
+                                return Err(DatabaseError::Corrupt(format!(
+                                    "banned address '{}' is not an ip address",
+                                    target
+                                )))
+                            }
+                        },
+                        _ => {
+                            return Err(DatabaseError::Corrupt(format!(
+                                "unknown kind of ban '{}'",
+                                kind
+                            )))
+                        }
+                    };
+                    bans.push((ban, row.get(2)?));
+                }
+
+                return Ok(bans);
+            })
+            .map_err(|err| err.to_string());
+    }
+
+    fn add_ban(&self, ban: &Ban, reason: &str) -> Result<(), String> {
+        let (kind, target) = ban_key(ban);
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt = conn
+                    .prepare("INSERT OR REPLACE INTO bans (kind, target, reason) VALUES (?,?,?)")?;
+                stmt.execute(rusqlite::params![kind, target, reason])?;
+
+                return Ok(());
+            })
+            .map_err(|err| err.to_string());
+    }
+
+    fn remove_ban(&self, ban: &Ban) -> Result<(), String> {
+        let (kind, target) = ban_key(ban);
+        return self
+            .retry(|| {
+                let conn = self.get_connection()?;
+
+                let mut stmt = conn.prepare("DELETE FROM bans WHERE kind = ? AND target = ?")?;
+                stmt.execute(rusqlite::params![kind, target])?;
+
+                return Ok(());
+            })
+            .map_err(|err| err.to_string());
+    }
+}
+
+// How a ban is stored in the bans table.
+fn ban_key(ban: &Ban) -> (&'static str, String) {
+    return match ban {
+        Ban::Player(player_id) => ("player", player_id.clone()),
+        Ban::Address(ip) => ("address", ip.to_string()),
+    };
+}
+
+const PLAYERS_TABLE: &str = "create table if not exists players (
+    id TEXT PRIMARY KEY,
+    save BLOB NOT NULL
+    )";
+
+const ACCOUNTS_TABLE: &str = "create table if not exists accounts (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    password_hash BLOB
+    )";
+
+fn new_player_id() -> String {
+    // This only fails if the operating system can't provide random numbers.
+    return fmc_networking::new_player_id().unwrap_or_else(|err| panic!("{}", err));
+}
+
+// Decodes a structure piece, checking that it is intact and only holds blocks that exist.
+fn read_structure_piece(
+    bytes: &[u8],
+    checksum: Option<&[u8]>,
+) -> Result<(Vec<(usize, BlockId, Option<u16>)>, HashSet<BlockId>), String> {
+    if checksum.is_some_and(|checksum| checksum != sha1::Sha1::digest(bytes).as_slice()) {
+        return Err("the checksum does not match".to_owned());
+    }
+
+    let (blocks, can_replace): (Vec<(usize, BlockId, Option<u16>)>, HashSet<BlockId>) =
+        bincode::deserialize(bytes).map_err(|err| err.to_string())?;
+
+    let block_configs = Blocks::get();
+    for (index, block_id, _) in blocks.iter() {
+        if *index >= CHUNK_SIZE.pow(3) {
+            return Err(format!("block index {} is outside the chunk", index));
+        }
+        if !block_configs.is_valid_id(*block_id) {
+            return Err(format!("there is no block with the id {}", block_id));
+        }
+    }
+    if let Some(block_id) = can_replace
+        .iter()
+        .find(|block_id| !block_configs.is_valid_id(**block_id))
+    {
+        return Err(format!("there is no block with the id {}", block_id));
+    }
+
+    return Ok((blocks, can_replace));
+}
+
+// Decompresses a generated chunk, checking that it has the right size and only holds blocks that
+// exist.
+fn read_generated_chunk(compressed: &[u8]) -> Result<GeneratedChunk, String> {
+    let bytes = zstd::stream::decode_all(compressed).map_err(|err| err.to_string())?;
+    let chunk: GeneratedChunk = bincode::deserialize(&bytes).map_err(|err| err.to_string())?;
+
+    if chunk.blocks.len() != 1 && chunk.blocks.len() != CHUNK_SIZE.pow(3) {
+        return Err(format!("the chunk has {} blocks", chunk.blocks.len()));
+    }
+    if chunk.biomes.len() != CHUNK_SIZE.pow(2) {
+        return Err(format!("the chunk has {} biomes", chunk.biomes.len()));
+    }
+
+    let block_configs = Blocks::get();
+    let feature_blocks = chunk.terrain_features.iter().flat_map(|feature| {
+        feature
+            .blocks
+            .values()
+            .flatten()
+            .map(|(_, block_id, _)| block_id)
+    });
+    if let Some(block_id) = chunk
+        .blocks
+        .iter()
+        .chain(feature_blocks)
+        .find(|block_id| !block_configs.is_valid_id(**block_id))
+    {
+        return Err(format!("there is no block with the id {}", block_id));
+    }
+
+    return Ok(chunk);
+}
+
+fn has_column(
+    conn: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+) -> Result<bool, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("pragma table_info({})", table))?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        if row.get::<_, String>(1)? == column {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+// Players used to be stored by their name. They are now stored by an id that stays the same when
+// they change their name. Databases that were made before this are converted when the server
+// starts. Saves that don't have an account get one without a password, so they can be claimed.
+fn migrate_to_player_ids(conn: &mut rusqlite::Connection) -> Result<(), DatabaseError> {
+    let old_accounts =
+        has_column(conn, "accounts", "name")? && !has_column(conn, "accounts", "id")?;
+    let old_players = has_column(conn, "players", "name")?;
+
+    if !old_accounts && !old_players {
+        return Ok(());
     }
+
+    info!("Converting players to be stored by id...");
+
+    let tx = conn.transaction()?;
+
+    if old_accounts {
+        tx.execute("alter table accounts rename to old_accounts", [])?;
+    }
+    if old_players {
+        tx.execute("alter table players rename to old_players", [])?;
+    }
+    tx.execute(PLAYERS_TABLE, [])?;
+    tx.execute(ACCOUNTS_TABLE, [])?;
+
+    if old_accounts {
+        let mut select = tx.prepare("SELECT name, password_hash FROM old_accounts")?;
+        let mut insert =
+            tx.prepare("INSERT INTO accounts (id, name, password_hash) VALUES (?,?,?)")?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let password_hash: Vec<u8> = row.get(1)?;
+            insert.execute(rusqlite::params![new_player_id(), name, password_hash])?;
+        }
+    }
+
+    if old_players {
+        let mut select = tx.prepare("SELECT name, save FROM old_players")?;
+        let mut find_account = tx.prepare("SELECT id FROM accounts WHERE name = ?")?;
+        let mut insert_account = tx.prepare("INSERT INTO accounts (id, name) VALUES (?,?)")?;
+        let mut insert_player = tx.prepare("INSERT INTO players (id, save) VALUES (?,?)")?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let save: Vec<u8> = row.get(1)?;
+
+            let mut accounts = find_account.query([&name])?;
+            let id: String = match accounts.next()? {
+                Some(account) => account.get(0)?,
+                None => {
+                    let id = new_player_id();
+                    insert_account.execute(rusqlite::params![id, name])?;
+                    id
+                }
+            };
+
+            insert_player.execute(rusqlite::params![id, save])?;
+        }
+    }
+
+    tx.execute("drop table if exists old_accounts", [])?;
+    tx.execute("drop table if exists old_players", [])?;
+    tx.commit()?;
+
+    return Ok(());
 }
@@ -0,0 +1,281 @@
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+
+use crate::{
+    database::{Database, DatabaseError},
+    players::Player,
+    settings::Settings,
+};
+
+// Players have a balance of money that is kept in the database. They can see it with
+// '/balance' and give money to others with '/pay <name> <amount>'. Operators can create and
+// remove money with '/money give <name> <amount>' and '/money take <name> <amount>'.
+//
+// Anything that wants to charge or reward players, like shops, should do it through the
+// [Economy], so every change is logged with the reason it was made.
+pub struct EconomyPlugin;
+impl Plugin for EconomyPlugin {
+    fn build(&self, app: &mut App) {
+        let database = app.world.resource::<Database>().clone();
+        app.insert_resource(Economy { database })
+            .add_systems(Update, handle_economy_commands);
+    }
+}
+
+/// Credits and debits the balances of players. Each change is stored as a transaction in the
+/// database along with its reason.
+#[derive(Resource, Clone)]
+pub struct Economy {
+    database: Database,
+}
+
+impl Economy {
+    /// How much money the player has.
+    pub fn balance(&self, player_id: &str) -> Result<u64, DatabaseError> {
+        return self.database.balance(player_id);
+    }
+
+    /// Give money to a player. Returns the new balance, or None if the balance can't hold that
+    /// much.
+    pub fn credit(
+        &self,
+        player_id: &str,
+        amount: u64,
+        reason: &str,
+    ) -> Result<Option<u64>, DatabaseError> {
+        let amount = i64::try_from(amount).unwrap_or(i64::MAX);
+        return Ok(self
+            .database
+            .change_balances(&[(player_id, amount)], reason)?
+            .map(|balances| balances[0]));
+    }
+
+    /// Take money from a player. Returns the new balance, or None if the player doesn't have
+    /// enough, in which case nothing is taken.
+    pub fn debit(
+        &self,
+        player_id: &str,
+        amount: u64,
+        reason: &str,
+    ) -> Result<Option<u64>, DatabaseError> {
+        let amount = i64::try_from(amount).unwrap_or(i64::MAX);
+        return Ok(self
+            .database
+            .change_balances(&[(player_id, -amount)], reason)?
+            .map(|balances| balances[0]));
+    }
+
+    /// Move money from one player to another. Returns the new balance of the payer, or None if
+    /// they don't have enough, in which case nothing is moved.
+    pub fn transfer(
+        &self,
+        from: &str,
+        to: &str,
+        amount: u64,
+        reason: &str,
+    ) -> Result<Option<u64>, DatabaseError> {
+        let amount = i64::try_from(amount).unwrap_or(i64::MAX);
+        return Ok(self
+            .database
+            .change_balances(&[(from, -amount), (to, amount)], reason)?
+            .map(|balances| balances[0]));
+    }
+}
+
+enum Action {
+    Pay,
+    Give,
+    Take,
+}
+
+fn handle_economy_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    economy: Res<Economy>,
+    player_query: Query<(&Player, &ConnectionId)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        let command = words.next();
+        if command != Some("/balance") && command != Some("/pay") && command != Some("/money") {
+            continue;
+        }
+
+        let Ok((sender, _)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        let (action, name, amount) = match (command, words.next(), words.next(), words.next()) {
+            (Some("/balance"), None, None, None) => {
+                let reply = match economy.balance(&sender.id) {
+                    Ok(balance) => messages::ChatMessageServer::translated(
+                        "economy.balance",
+                        vec![balance.to_string()],
+                    ),
+                    Err(err) => {
+                        error!(
+                            "Failed to load the balance of '{}': {}",
+                            sender.username, err
+                        );
+                        messages::ChatMessageServer::translated("economy.failed", vec![])
+                    }
+                };
+                net.send_one(chat_message.source, reply);
+                continue;
+            }
+            (Some("/pay"), Some(name), Some(amount), None) => (Action::Pay, name, amount),
+            (Some("/money"), Some(action @ ("give" | "take")), Some(name), Some(amount))
+                if words.next().is_none() =>
+            {
+                if !settings.is_operator(&sender.id) {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("economy.not_operator", vec![]),
+                    );
+                    continue;
+                }
+                let action = if action == "give" {
+                    Action::Give
+                } else {
+                    Action::Take
+                };
+                (action, name, amount)
+            }
+            (Some("/money"), ..) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("economy.money.usage", vec![]),
+                );
+                continue;
+            }
+            _ => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("economy.usage", vec![]),
+                );
+                continue;
+            }
+        };
+
+        let amount = match amount.parse::<u64>() {
+            Ok(amount) if amount > 0 => amount,
+            _ => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        "economy.invalid_amount",
+                        vec![amount.to_owned()],
+                    ),
+                );
+                continue;
+            }
+        };
+
+        // Money can be sent to players that are offline, they see it the next time they check.
+        let online_player = player_query
+            .iter()
+            .find(|(player, _)| player.username == name);
+        let player_id = match online_player {
+            Some((player, _)) => player.id.clone(),
+            None => match database.player_id(name) {
+                Ok(Some(player_id)) => player_id,
+                Ok(None) => {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated(
+                            "economy.unknown_player",
+                            vec![name.to_owned()],
+                        ),
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    error!("Failed to look up the player '{}': {}", name, err);
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("economy.failed", vec![]),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        if matches!(action, Action::Pay) && player_id == sender.id {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("economy.pay.self", vec![]),
+            );
+            continue;
+        }
+
+        let (result, done, notification, failed) = match action {
+            Action::Pay => (
+                economy.transfer(
+                    &sender.id,
+                    &player_id,
+                    amount,
+                    &format!("paid by {} to {}", sender.username, name),
+                ),
+                "economy.pay.sent",
+                "economy.pay.received",
+                "economy.pay.not_enough",
+            ),
+            Action::Give => (
+                economy.credit(
+                    &player_id,
+                    amount,
+                    &format!("given by the operator {}", sender.username),
+                ),
+                "economy.money.given",
+                "economy.received",
+                "economy.money.too_much",
+            ),
+            Action::Take => (
+                economy.debit(
+                    &player_id,
+                    amount,
+                    &format!("taken by the operator {}", sender.username),
+                ),
+                "economy.money.taken",
+                "economy.taken",
+                "economy.money.not_enough",
+            ),
+        };
+
+        match result {
+            Ok(Some(_)) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        done,
+                        vec![amount.to_string(), name.to_owned()],
+                    ),
+                );
+                if let Some((_, connection_id)) = online_player {
+                    net.send_one(
+                        *connection_id,
+                        messages::ChatMessageServer::translated(
+                            notification,
+                            vec![amount.to_string(), sender.username.clone()],
+                        ),
+                    );
+                }
+            }
+            Ok(None) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(failed, vec![name.to_owned()]),
+                );
+            }
+            Err(err) => {
+                error!("Failed to change the balance of '{}': {}", name, err);
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("economy.failed", vec![]),
+                );
+            }
+        }
+    }
+}
@@ -8,13 +8,18 @@ mod api;
 mod assets;
 mod bevy_extensions;
 mod chat;
+mod console;
 mod constants;
 mod database;
+mod economy;
+mod mobs;
 mod networking;
 mod physics;
 mod players;
 mod settings;
+mod stats;
 mod utils;
+mod vote;
 mod world;
 
 fn main() {
@@ -52,6 +57,11 @@ fn main() {
         .add_plugins(world::WorldPlugin)
         .add_plugins(physics::PhysicsPlugin)
         .add_plugins(players::PlayersPlugin)
+        .add_plugins(mobs::MobsPlugin)
         .add_plugins(chat::ChatPlugin)
+        .add_plugins(console::ConsolePlugin)
+        .add_plugins(vote::VotePlugin)
+        .add_plugins(economy::EconomyPlugin)
+        .add_plugins(stats::StatsPlugin)
         .run();
 }
@@ -0,0 +1,251 @@
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+};
+use rand::Rng;
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    physics::Velocity,
+    players::{DamageEvent, DamageSource, Health, Player},
+    world::{
+        blocks::{Blocks, Friction},
+        world_map::WorldMap,
+    },
+};
+
+use super::{Hostility, Mob, MobConfigs};
+
+// Seconds between each time a mob looks around for something to chase or run from.
+const LOOK_INTERVAL: f32 = 0.5;
+// Inclusive range of how many seconds a mob stands still between wandering.
+const IDLE_TIME: [f32; 2] = [2.0, 8.0];
+// How far away a mob wanders to, and how long it tries to get there before giving up.
+const WANDER_DISTANCE: f64 = 8.0;
+const WANDER_TIME: f32 = 6.0;
+// How close a chasing mob gets to its target.
+const CHASE_DISTANCE: f64 = 1.0;
+// How close it has to be to attack, and the seconds between each attack.
+const ATTACK_DISTANCE: f64 = 1.5;
+const ATTACK_INTERVAL: f32 = 1.0;
+// Enough to get up on a block.
+const JUMP_VELOCITY: f64 = 8.5;
+
+// Mobs stand around for a while, then walk to a random place nearby. Hostile mobs chase players
+// that come within their sight and attack them when close enough, and passive mobs run from
+// hostile mobs. The movement is done by setting the velocity of the mob, and letting the physics
+// move it. Mobs jump when they walk into a block they can step up on, and can't steer while in the
+// air, so knockback carries them.
+pub(super) struct AiPlugin;
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (look_around, move_mobs.after(look_around)));
+    }
+}
+
+enum Behavior {
+    Idle { time_left: f32 },
+    Wander { target: DVec3, time_left: f32 },
+    Chase(Entity),
+    Flee(Entity),
+}
+
+/// What the mob is currently doing.
+#[derive(Component)]
+pub struct MobAi {
+    behavior: Behavior,
+    // Seconds until it looks around again
+    look_timer: f32,
+    // Seconds until it can attack again
+    attack_timer: f32,
+}
+
+impl Default for MobAi {
+    fn default() -> Self {
+        return Self {
+            behavior: idle(),
+            // Spread out so they don't all look around at the same time.
+            look_timer: rand::thread_rng().gen_range(0.0..LOOK_INTERVAL),
+            attack_timer: 0.0,
+        };
+    }
+}
+
+fn idle() -> Behavior {
+    return Behavior::Idle {
+        time_left: rand::thread_rng().gen_range(IDLE_TIME[0]..=IDLE_TIME[1]),
+    };
+}
+
+// The closest of the candidates that is within the range.
+fn closest(
+    position: DVec3,
+    range: f64,
+    candidates: impl Iterator<Item = (Entity, DVec3)>,
+) -> Option<Entity> {
+    return candidates
+        .map(|(entity, candidate)| (entity, candidate.distance_squared(position)))
+        .filter(|(_, distance)| *distance < range.powi(2))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity);
+}
+
+fn look_around(
+    time: Res<Time>,
+    mob_configs: Res<MobConfigs>,
+    player_query: Query<(Entity, &F64GlobalTransform, &Health), With<Player>>,
+    mut mob_query: Query<(Entity, &Mob, &F64Transform, &mut MobAi)>,
+) {
+    let hostile_mobs: Vec<(Entity, DVec3)> = mob_query
+        .iter()
+        .filter(|(_, mob, _, _)| mob_configs.get(mob.id).hostility == Hostility::Hostile)
+        .map(|(entity, _, transform, _)| (entity, transform.translation))
+        .collect();
+
+    for (_, mob, transform, mut ai) in mob_query.iter_mut() {
+        ai.look_timer -= time.delta_seconds();
+        if ai.look_timer > 0.0 {
+            continue;
+        }
+        ai.look_timer = LOOK_INTERVAL;
+
+        let config = mob_configs.get(mob.id);
+        let position = transform.translation;
+
+        match config.hostility {
+            Hostility::Hostile => {
+                let players = player_query
+                    .iter()
+                    .filter(|(_, _, health)| health.hearts != 0)
+                    .map(|(entity, transform, _)| (entity, transform.translation()));
+                match closest(position, config.sight_range, players) {
+                    Some(player) => ai.behavior = Behavior::Chase(player),
+                    None if matches!(ai.behavior, Behavior::Chase(_)) => ai.behavior = idle(),
+                    None => (),
+                }
+            }
+            Hostility::Passive => {
+                match closest(position, config.sight_range, hostile_mobs.iter().copied()) {
+                    Some(hostile_mob) => ai.behavior = Behavior::Flee(hostile_mob),
+                    None if matches!(ai.behavior, Behavior::Flee(_)) => ai.behavior = idle(),
+                    None => (),
+                }
+            }
+            Hostility::Neutral => (),
+        }
+    }
+}
+
+fn move_mobs(
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    mob_configs: Res<MobConfigs>,
+    target_query: Query<&F64GlobalTransform>,
+    mut mob_query: Query<(Entity, &Mob, &mut MobAi, &mut F64Transform, &mut Velocity)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let blocks = Blocks::get();
+    let is_solid = |position: IVec3| match world_map.get_block(position) {
+        Some(block_id) => !matches!(blocks.get_config(&block_id).friction, Friction::Drag(_)),
+        None => false,
+    };
+
+    let mut rng = rand::thread_rng();
+
+    for (entity, mob, mut ai, mut transform, mut velocity) in mob_query.iter_mut() {
+        let config = mob_configs.get(mob.id);
+        let position = transform.translation;
+
+        ai.attack_timer = (ai.attack_timer - time.delta_seconds()).max(0.0);
+
+        // Where it is headed, and at what speed.
+        let mut heading = None;
+
+        match &mut ai.behavior {
+            Behavior::Idle { time_left } => {
+                *time_left -= time.delta_seconds();
+                if *time_left <= 0.0 {
+                    let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+                    let distance = rng.gen_range(1.0..=WANDER_DISTANCE);
+                    ai.behavior = Behavior::Wander {
+                        target: position + DVec3::new(angle.cos(), 0.0, angle.sin()) * distance,
+                        time_left: WANDER_TIME,
+                    };
+                }
+            }
+            Behavior::Wander { target, time_left } => {
+                *time_left -= time.delta_seconds();
+                let direction = (*target - position) * DVec3::new(1.0, 0.0, 1.0);
+                if *time_left <= 0.0 || direction.length_squared() < 0.25 {
+                    ai.behavior = idle();
+                } else {
+                    heading = Some((direction, config.speed / 2.0));
+                }
+            }
+            Behavior::Chase(target_entity) => match target_query.get(*target_entity) {
+                Ok(target) => {
+                    let target_entity = *target_entity;
+                    let direction = (target.translation() - position) * DVec3::new(1.0, 0.0, 1.0);
+                    if direction.length_squared() > CHASE_DISTANCE.powi(2) {
+                        heading = Some((direction, config.speed));
+                    }
+
+                    if config.damage != 0
+                        && ai.attack_timer == 0.0
+                        && target.translation().distance_squared(position) < ATTACK_DISTANCE.powi(2)
+                    {
+                        ai.attack_timer = ATTACK_INTERVAL;
+                        damage_events.send(DamageEvent {
+                            entity: target_entity,
+                            damage: config.damage,
+                            source: DamageSource::Attack(entity),
+                        });
+                    }
+                }
+                Err(_) => ai.behavior = idle(),
+            },
+            Behavior::Flee(target_entity) => match target_query.get(*target_entity) {
+                Ok(target) => {
+                    let direction = (position - target.translation()) * DVec3::new(1.0, 0.0, 1.0);
+                    heading = Some((direction, config.speed));
+                }
+                Err(_) => ai.behavior = idle(),
+            },
+        }
+
+        // Can't steer while in the air.
+        if velocity.y != 0.0 {
+            continue;
+        }
+
+        let Some((direction, speed)) = heading else {
+            // Only touched when it has to be, so the physics can leave mobs that stand still
+            // alone.
+            if velocity.x != 0.0 || velocity.z != 0.0 {
+                velocity.x = 0.0;
+                velocity.z = 0.0;
+            }
+            continue;
+        };
+
+        let direction = direction.normalize_or_zero();
+        if direction == DVec3::ZERO {
+            continue;
+        }
+
+        velocity.x = direction.x * speed;
+        velocity.z = direction.z * speed;
+
+        let rotation = DQuat::from_rotation_y(f64::atan2(-direction.x, -direction.z));
+        if !transform.rotation.abs_diff_eq(rotation, 0.01) {
+            transform.rotation = rotation;
+        }
+
+        let ahead = (position + direction * (0.5 * config.scale + 0.5))
+            .floor()
+            .as_ivec3();
+        if is_solid(ahead) && !is_solid(ahead + IVec3::Y) && !is_solid(ahead + IVec3::Y * 2) {
+            velocity.y = JUMP_VELOCITY;
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::BlockId;
+use serde::Deserialize;
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    physics::PhysicsBundle,
+    players::{DeathEvent, Player},
+    utils,
+    world::{
+        blocks::{Blocks, SpawnMob, BLOCK_CONFIG_PATH},
+        models::{Model, ModelBundle, ModelId, ModelVisibility, Models, MODEL_PATH},
+        world_map::WorldMap,
+    },
+};
+
+mod ai;
+mod spawning;
+
+pub use ai::MobAi;
+
+pub const MOB_CONFIG_PATH: &str = "./resources/server/mobs/";
+
+// Mobs that are this far away from all players are removed.
+const DESPAWN_DISTANCE: f64 = 128.0;
+
+// Mobs are creatures that move around the world on their own. What they look like, how sturdy
+// and fast they are and how they act towards players is configured for each kind of mob at
+// resources/server/mobs/. They appear naturally on the surface around the players, or from
+// spawners. Mobs are not saved, they are removed when they die, when no players are near or when
+// their chunk is unloaded.
+pub struct MobsPlugin;
+impl Plugin for MobsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ai::AiPlugin)
+            .add_plugins(spawning::SpawningPlugin)
+            .add_systems(Startup, load_mob_configs)
+            .add_systems(
+                Update,
+                (spawn_mobs_from_spawners, despawn_mobs, despawn_dead_mobs),
+            );
+    }
+}
+
+/// How a mob acts towards players.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Hostility {
+    /// Wanders, and runs away from hostile mobs that come close.
+    Passive,
+    /// Wanders, and pays no attention to anything.
+    Neutral,
+    /// Chases and attacks the players it sees.
+    Hostile,
+}
+
+pub type MobId = usize;
+
+pub struct MobConfig {
+    pub name: String,
+    pub model_id: ModelId,
+    /// Color the model is multiplied by
+    pub tint: Vec4,
+    /// Size of the model, scales the collision box too.
+    pub scale: f64,
+    pub health: u32,
+    /// How much damage its attacks do, mobs that do none don't attack.
+    pub damage: u32,
+    /// Blocks per second when running, it walks at half the speed.
+    pub speed: f64,
+    pub hostility: Hostility,
+    /// How far away it notices players and other mobs.
+    pub sight_range: f64,
+    /// How the mob appears on its own, None if it only comes from spawners.
+    pub spawning: Option<NaturalSpawning>,
+}
+
+pub struct NaturalSpawning {
+    /// The blocks the mob can appear on top of.
+    pub blocks: HashSet<BlockId>,
+    /// Inclusive range of how many appear together.
+    pub group_size: [u32; 2],
+    /// Relative chance of it being picked among the mobs that can appear on the same block.
+    pub weight: u32,
+    /// The part of the day it can appear in.
+    pub time: SpawnTime,
+}
+
+/// When during the day a mob can appear on its own.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SpawnTime {
+    #[default]
+    Any,
+    Day,
+    Night,
+}
+
+#[derive(Deserialize)]
+struct MobConfigJson {
+    // Name of the model, from the model directory
+    model: String,
+    // Color the model is multiplied by, as rgba
+    #[serde(default = "default_tint")]
+    tint: [f32; 4],
+    #[serde(default = "default_scale")]
+    scale: f64,
+    health: u32,
+    #[serde(default)]
+    damage: u32,
+    speed: f64,
+    hostility: Hostility,
+    sight_range: f64,
+    spawning: Option<NaturalSpawningJson>,
+}
+
+fn default_tint() -> [f32; 4] {
+    [1.0; 4]
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct NaturalSpawningJson {
+    blocks: Vec<String>,
+    group_size: [u32; 2],
+    weight: u32,
+    #[serde(default)]
+    time: SpawnTime,
+}
+
+#[derive(Resource)]
+pub struct MobConfigs {
+    configs: Vec<MobConfig>,
+    ids: HashMap<String, MobId>,
+}
+
+impl MobConfigs {
+    pub fn get(&self, id: MobId) -> &MobConfig {
+        return &self.configs[id];
+    }
+
+    pub fn get_id(&self, name: &str) -> Option<MobId> {
+        return self.ids.get(name).copied();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (MobId, &MobConfig)> {
+        return self.configs.iter().enumerate();
+    }
+}
+
+fn load_mob_configs(mut commands: Commands, models: Res<Models>) {
+    let blocks = Blocks::get();
+    let model_ids = models.clone_ids();
+
+    let directory = std::fs::read_dir(MOB_CONFIG_PATH).expect(&format!(
+        "Could not read files from mob config directory, make sure it is present as '{}'",
+        MOB_CONFIG_PATH
+    ));
+
+    let mut configs = Vec::new();
+    let mut ids = HashMap::new();
+    for entry in directory {
+        let file_path = entry
+            .expect("Failed to read the filenames of the mob configs")
+            .path();
+
+        let file = match std::fs::File::open(&file_path) {
+            Ok(f) => f,
+            Err(e) => panic!(
+                "Failed to open mob config at: {}\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        let json: MobConfigJson = match serde_json::from_reader(&file) {
+            Ok(c) => c,
+            Err(e) => panic!(
+                "Couldn't read mob config from '{}'\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        let name = file_path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        let Some(model_id) = model_ids.get(&json.model) else {
+            panic!(
+                "Invalid mob config at '{}', there is no model named '{}'. Make sure a model by \
+                the same name is present at '{}'",
+                file_path.display(),
+                json.model,
+                MODEL_PATH
+            );
+        };
+
+        if json.speed <= 0.0 || json.scale <= 0.0 || json.health == 0 {
+            panic!(
+                "Invalid mob config at '{}', the speed, scale and health must be above zero",
+                file_path.display()
+            );
+        }
+
+        let spawning = json.spawning.map(|spawning| {
+            if spawning.group_size[0] > spawning.group_size[1]
+                || spawning.group_size[0] == 0
+                || spawning.weight == 0
+            {
+                panic!(
+                    "Invalid mob config at '{}', the group size must have the lowest first and \
+                    be above zero, and the weight must be above zero",
+                    file_path.display()
+                );
+            }
+
+            let spawn_blocks = spawning
+                .blocks
+                .iter()
+                .map(|block_name| {
+                    if !blocks.contains_block(block_name) {
+                        panic!(
+                            "Invalid mob config at '{}', there is no block named '{}'. Make sure \
+                            a block by the same name is present at '{}'",
+                            file_path.display(),
+                            block_name,
+                            BLOCK_CONFIG_PATH
+                        );
+                    }
+                    blocks.get_id(block_name)
+                })
+                .collect();
+
+            NaturalSpawning {
+                blocks: spawn_blocks,
+                group_size: spawning.group_size,
+                weight: spawning.weight,
+                time: spawning.time,
+            }
+        });
+
+        ids.insert(name.clone(), configs.len());
+        configs.push(MobConfig {
+            name,
+            model_id: *model_id,
+            tint: Vec4::from(json.tint),
+            scale: json.scale,
+            health: json.health,
+            damage: json.damage,
+            speed: json.speed,
+            hostility: json.hostility,
+            sight_range: json.sight_range,
+            spawning,
+        });
+    }
+
+    commands.insert_resource(MobConfigs { configs, ids });
+}
+
+#[derive(Component)]
+pub struct Mob {
+    pub id: MobId,
+    pub health: u32,
+}
+
+/// Spawn a mob with its feet at the position.
+pub fn spawn_mob(
+    commands: &mut Commands,
+    mob_configs: &MobConfigs,
+    models: &Models,
+    mob_id: MobId,
+    position: DVec3,
+) -> Entity {
+    let config = mob_configs.get(mob_id);
+
+    let mut aabb = models.get(&config.model_id).aabb.clone();
+    aabb.center *= config.scale;
+    aabb.half_extents *= config.scale;
+
+    let mut model = Model::new(config.model_id);
+    model.tint = config.tint;
+
+    return commands
+        .spawn((
+            Mob {
+                id: mob_id,
+                health: config.health,
+            },
+            MobAi::default(),
+            ModelBundle {
+                model,
+                visibility: ModelVisibility::default(),
+                global_transform: F64GlobalTransform::default(),
+                transform: F64Transform {
+                    translation: position,
+                    scale: DVec3::splat(config.scale),
+                    ..default()
+                },
+            },
+            PhysicsBundle::default(),
+            aabb,
+        ))
+        .id();
+}
+
+fn spawn_mobs_from_spawners(
+    mut commands: Commands,
+    mob_configs: Res<MobConfigs>,
+    models: Res<Models>,
+    mut spawn_events: EventReader<SpawnMob>,
+) {
+    for spawn_event in spawn_events.read() {
+        // The spawner configs are validated against the mobs, so it always exists.
+        let mob_id = mob_configs.get_id(&spawn_event.mob).unwrap();
+        spawn_mob(
+            &mut commands,
+            &mob_configs,
+            &models,
+            mob_id,
+            spawn_event.position,
+        );
+    }
+}
+
+fn despawn_mobs(
+    mut commands: Commands,
+    world_map: Res<WorldMap>,
+    mob_query: Query<(Entity, &F64Transform), With<Mob>>,
+    player_query: Query<&F64GlobalTransform, With<Player>>,
+) {
+    for (entity, transform) in mob_query.iter() {
+        let position = transform.translation;
+
+        let chunk_position = utils::world_position_to_chunk_position(position.as_ivec3());
+        let is_loaded = world_map.get_chunk(&chunk_position).is_some();
+
+        let is_near_player = player_query.iter().any(|player_transform| {
+            player_transform.translation().distance_squared(position) < DESPAWN_DISTANCE.powi(2)
+        });
+
+        if !is_loaded || !is_near_player {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn despawn_dead_mobs(
+    mut commands: Commands,
+    mob_query: Query<(), With<Mob>>,
+    mut death_events: EventReader<DeathEvent>,
+) {
+    for death_event in death_events.read() {
+        if mob_query.contains(death_event.entity) {
+            commands.entity(death_event.entity).despawn_recursive();
+        }
+    }
+}
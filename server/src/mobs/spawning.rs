@@ -0,0 +1,147 @@
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::BlockId;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    players::Player,
+    world::{
+        blocks::{Blocks, Friction},
+        models::Models,
+        sky::TimeOfDay,
+        world_map::WorldMap,
+    },
+};
+
+use super::{spawn_mob, Mob, MobConfigs, SpawnTime};
+
+// Seconds between each time mobs try to appear around the players.
+const SPAWN_INTERVAL: f32 = 5.0;
+// Mobs appear at a horizontal distance from the players between these two, so they are not seen
+// popping into existence.
+const MIN_SPAWN_DISTANCE: f64 = 24.0;
+const MAX_SPAWN_DISTANCE: f64 = 64.0;
+// How far above and below the player the surface is searched for.
+const VERTICAL_SEARCH: i32 = 32;
+// No more mobs appear around a player when there are this many within the spawn distance.
+const MOB_CAP: usize = 16;
+
+// Every once in a while a block column is picked at random around each player, and a mob is
+// spawned at its surface if there is one that can appear on the block there. Which mob is
+// picked by weight among those that can.
+pub(super) struct SpawningPlugin;
+impl Plugin for SpawningPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpawnTimer(Timer::from_seconds(
+            SPAWN_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Update, spawn_mobs_around_players);
+    }
+}
+
+#[derive(Resource)]
+struct SpawnTimer(Timer);
+
+// Find the highest block in the column that has room for a mob on top. Returns the position above
+// it, and the block.
+fn find_surface(world_map: &WorldMap, column: IVec3) -> Option<(IVec3, BlockId)> {
+    let blocks = Blocks::get();
+    let is_open =
+        |block_id: BlockId| matches!(blocks.get_config(&block_id).friction, Friction::Drag(_));
+
+    // The two blocks above the one being checked, mobs are two blocks tall.
+    let mut above = [
+        world_map.get_block(column + IVec3::Y * (VERTICAL_SEARCH + 2))?,
+        world_map.get_block(column + IVec3::Y * (VERTICAL_SEARCH + 1))?,
+    ];
+    for y in (-VERTICAL_SEARCH..=VERTICAL_SEARCH).rev() {
+        let position = column + IVec3::Y * y;
+        let block_id = world_map.get_block(position)?;
+        if !is_open(block_id) && is_open(above[0]) && is_open(above[1]) {
+            return Some((position + IVec3::Y, block_id));
+        }
+        above = [above[1], block_id];
+    }
+
+    return None;
+}
+
+fn spawn_mobs_around_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    world_map: Res<WorldMap>,
+    mob_configs: Res<MobConfigs>,
+    models: Res<Models>,
+    mut spawn_timer: ResMut<SpawnTimer>,
+    player_query: Query<&F64GlobalTransform, With<Player>>,
+    mob_query: Query<&F64Transform, With<Mob>>,
+) {
+    spawn_timer.0.tick(time.delta());
+    if !spawn_timer.0.just_finished() {
+        return;
+    }
+
+    let mut rng = rand::thread_rng();
+
+    for player_transform in player_query.iter() {
+        let player_position = player_transform.translation();
+
+        let nearby_mobs = mob_query
+            .iter()
+            .filter(|transform| {
+                transform.translation.distance_squared(player_position) < MAX_SPAWN_DISTANCE.powi(2)
+            })
+            .count();
+        if nearby_mobs >= MOB_CAP {
+            continue;
+        }
+
+        let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+        let distance = rng.gen_range(MIN_SPAWN_DISTANCE..MAX_SPAWN_DISTANCE);
+        let column = (player_position + DVec3::new(angle.cos(), 0.0, angle.sin()) * distance)
+            .floor()
+            .as_ivec3();
+
+        let Some((position, ground_block)) = find_surface(&world_map, column) else {
+            continue;
+        };
+
+        let candidates: Vec<_> = mob_configs
+            .iter()
+            .filter_map(|(mob_id, config)| {
+                let spawning = config.spawning.as_ref()?;
+                let right_time = match spawning.time {
+                    SpawnTime::Any => true,
+                    SpawnTime::Day => !time_of_day.is_night(),
+                    SpawnTime::Night => time_of_day.is_night(),
+                };
+                (right_time && spawning.blocks.contains(&ground_block))
+                    .then_some((mob_id, spawning))
+            })
+            .collect();
+
+        if candidates.is_empty() {
+            continue;
+        }
+
+        // The weights are validated to be above zero.
+        let weights =
+            WeightedIndex::new(candidates.iter().map(|(_, spawning)| spawning.weight)).unwrap();
+        let (mob_id, spawning) = candidates[weights.sample(&mut rng)];
+
+        let group_size = rng.gen_range(spawning.group_size[0]..=spawning.group_size[1]);
+        for _ in 0..group_size {
+            // Spread out a little so they don't all stand in the same spot.
+            let offset = DVec3::new(rng.gen_range(-0.4..0.4), 0.0, rng.gen_range(-0.4..0.4));
+            spawn_mob(
+                &mut commands,
+                &mob_configs,
+                &models,
+                mob_id,
+                position.as_dvec3() + DVec3::new(0.5, 0.0, 0.5) + offset,
+            );
+        }
+    }
+}
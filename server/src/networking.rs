@@ -1,11 +1,23 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
+use base64::Engine;
 use bevy::prelude::*;
-use fmc_networking::{messages, ConnectionId, NetworkServer, ServerNetworkEvent};
+use fmc_networking::{
+    messages, ConnectionId, MessageTraffic, NetworkDiagnostics, NetworkServer, NetworkSettings,
+    RateLimit, ServerNetworkEvent, ServerStatus, TlsSettings, Transport,
+};
+use sha1::Digest;
 
 use crate::{
+    database::Database,
     settings::Settings,
-    world::{blocks::Blocks, items::Items, models::Models},
+    world::{
+        blocks::Blocks, items::Items, models::Models,
+        world_map::terrain_generation::TerrainGenerator,
+    },
 };
 
 // TODO: I stripped this for most of its functionality, and it's a little too lean now. Move server
@@ -15,33 +27,152 @@ impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(fmc_networking::ServerPlugin)
             .add_systems(PostStartup, server_setup)
-            .add_systems(Update, handle_network_events);
+            .add_systems(Update, (handle_network_events, log_network_stats));
     }
 }
 
+// How many kinds of messages are listed when the network traffic is logged.
+const LOGGED_MESSAGE_KINDS: usize = 5;
+// The icon is sent with the rest of the status, which clients only accept up to 64KB. Base64 makes
+// it a third larger.
+const MAX_ICON_SIZE: usize = 32 * 1024;
+
+// The limits are generous, they are only meant to stop clients that spam messages. Movement is
+// sent every frame, so it has to allow for high frame rates.
+fn set_rate_limits(net: &NetworkServer) {
+    let every_frame = RateLimit {
+        per_second: 500.0,
+        burst: 100.0,
+    };
+    net.set_rate_limit::<messages::PlayerPosition>(every_frame);
+    net.set_rate_limit::<messages::PlayerCameraRotation>(every_frame);
+
+    // Sent on both press and release.
+    net.set_rate_limit::<messages::LeftClick>(RateLimit {
+        per_second: 40.0,
+        burst: 20.0,
+    });
+
+    net.set_rate_limit::<messages::RightClick>(RateLimit {
+        per_second: 20.0,
+        burst: 10.0,
+    });
+
+    let interface = RateLimit {
+        per_second: 100.0,
+        burst: 50.0,
+    };
+    net.set_rate_limit::<messages::InterfaceTakeItem>(interface);
+    net.set_rate_limit::<messages::InterfacePlaceItem>(interface);
+    net.set_rate_limit::<messages::InterfaceEquipItem>(interface);
+    net.set_rate_limit::<messages::InterfaceButtonPress>(interface);
+
+    let text = RateLimit {
+        per_second: 10.0,
+        burst: 10.0,
+    };
+    net.set_rate_limit::<messages::InterfaceTextInput>(text);
+    net.set_rate_limit::<messages::ChatMessageClient>(text);
+    // The server only pings once a second.
+    net.set_rate_limit::<messages::Pong>(RateLimit {
+        per_second: 5.0,
+        burst: 5.0,
+    });
+    net.set_rate_limit::<messages::CutsceneEnd>(RateLimit {
+        per_second: 1.0,
+        burst: 5.0,
+    });
+}
+
 fn server_setup(
     mut commands: Commands,
     mut net: ResMut<NetworkServer>,
     assets_hash: Res<crate::assets::AssetArchiveHash>,
     models: Res<Models>,
     items: Res<Items>,
+    terrain_generator: Res<TerrainGenerator>,
     settings: Res<Settings>,
+    database: Res<Database>,
+    mut network_settings: ResMut<NetworkSettings>,
 ) {
-    let socket_address: SocketAddr = "127.0.0.1:42069".parse().unwrap();
+    // Other machines can only connect if the server listens on all interfaces.
+    let socket_address: SocketAddr = if settings.lan_discovery || settings.upnp {
+        "0.0.0.0:42069".parse().unwrap()
+    } else {
+        "127.0.0.1:42069".parse().unwrap()
+    };
+
+    if let (Some(certificate), Some(private_key)) =
+        (&settings.tls_certificate, &settings.tls_private_key)
+    {
+        network_settings.tls = Some(TlsSettings::Server {
+            certificate: certificate.into(),
+            private_key: private_key.into(),
+        });
+    }
+
+    if settings.websocket {
+        network_settings.transport = Transport::WebSocket;
+    }
+
+    network_settings.lan_discovery = settings.lan_discovery;
+    network_settings.upnp = settings.upnp;
 
-    net.listen(socket_address);
+    network_settings.max_connections = settings.max_players.map(|max| max as usize);
+    network_settings.idle_timeout = match settings.idle_timeout {
+        0 => None,
+        timeout => Some(Duration::from_secs(timeout as u64)),
+    };
+
+    net.set_account_storage(database.clone());
+    // Banned players would be let in otherwise.
+    if let Err(err) = net.set_ban_storage(database.clone()) {
+        panic!("Failed to load the bans from the database: {}", err);
+    }
+    set_rate_limits(&net);
+    let icon = settings.icon.as_ref().map(|path| load_icon(path));
+    net.set_status(ServerStatus {
+        motd: settings.motd.clone(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        icon_hash: icon.as_ref().map(|icon| sha1::Sha1::digest(icon).to_vec()),
+        icon: icon.map(|icon| base64::engine::general_purpose::STANDARD.encode(icon)),
+    });
+    net.listen(socket_address, &network_settings);
 
     commands.insert_resource(messages::ServerConfig {
         assets_hash: assets_hash.hash.clone(),
         block_ids: Blocks::get().clone_ids(),
         model_ids: models.clone_ids(),
+        model_hitboxes: models.clone_hitboxes(),
         item_ids: items.clone_ids(),
+        biome_ids: terrain_generator.biome_ids(),
         render_distance: settings.render_distance,
+        compression: network_settings.compression_threshold.is_some(),
     });
 
     info!("Started listening for new connections!");
 }
 
+fn load_icon(path: &str) -> Vec<u8> {
+    let icon = std::fs::read(path).unwrap_or_else(|err| {
+        panic!("Failed to read the server icon at '{}': {}", path, err);
+    });
+
+    if !icon.starts_with(b"\x89PNG") {
+        panic!("The server icon at '{}' must be a png image", path);
+    }
+
+    if icon.len() > MAX_ICON_SIZE {
+        panic!(
+            "The server icon at '{}' is too large, it can be at most {}KB",
+            path,
+            MAX_ICON_SIZE / 1024
+        );
+    }
+
+    return icon;
+}
+
 fn handle_network_events(
     net: Res<NetworkServer>,
     server_config: Res<messages::ServerConfig>,
@@ -58,3 +189,72 @@ fn handle_network_events(
         }
     }
 }
+
+// Logs the traffic since the last time, and the messages that took up most of it.
+fn log_network_stats(
+    settings: Res<Settings>,
+    network_diagnostics: Res<NetworkDiagnostics>,
+    mut last_log: Local<Option<(Instant, Vec<MessageTraffic>)>>,
+) {
+    if settings.network_stats_interval == 0 {
+        return;
+    }
+
+    let now = Instant::now();
+    let interval = Duration::from_secs(settings.network_stats_interval as u64 * 60);
+
+    let Some((last_time, last_messages)) = last_log.as_ref() else {
+        *last_log = Some((now, network_diagnostics.messages().collect()));
+        return;
+    };
+
+    if now - *last_time < interval {
+        return;
+    }
+
+    let seconds = (now - *last_time).as_secs_f64();
+    let messages: Vec<MessageTraffic> = network_diagnostics.messages().collect();
+
+    // (name, bytes received, bytes sent, packets)
+    let mut message_traffic: Vec<(&str, u64, u64, u64)> = messages
+        .iter()
+        .zip(last_messages.iter())
+        .map(|(message, last)| {
+            let received = message.received - last.received;
+            let sent = message.sent - last.sent;
+            (
+                message.name,
+                received.bytes,
+                sent.bytes,
+                received.packets + sent.packets,
+            )
+        })
+        .collect();
+
+    let bytes_received: u64 = message_traffic.iter().map(|traffic| traffic.1).sum();
+    let bytes_sent: u64 = message_traffic.iter().map(|traffic| traffic.2).sum();
+
+    info!(
+        "Network traffic the last {} minutes: {:.1} KiB/s received, {:.1} KiB/s sent",
+        settings.network_stats_interval,
+        bytes_received as f64 / 1024.0 / seconds,
+        bytes_sent as f64 / 1024.0 / seconds
+    );
+
+    message_traffic.sort_by_key(|traffic| std::cmp::Reverse(traffic.1 + traffic.2));
+    for (name, received, sent, packets) in message_traffic
+        .into_iter()
+        .take(LOGGED_MESSAGE_KINDS)
+        .filter(|traffic| traffic.3 != 0)
+    {
+        info!(
+            "    {}: {:.1} KiB received, {:.1} KiB sent, {} packets",
+            name,
+            received as f64 / 1024.0,
+            sent as f64 / 1024.0,
+            packets
+        );
+    }
+
+    *last_log = Some((now, messages));
+}
@@ -49,6 +49,23 @@ impl Aabb {
     pub fn max(&self) -> DVec3 {
         self.center + self.half_extents
     }
+
+    /// How far along the ray it is to where it enters the aabb, measured in lengths of the
+    /// direction. None if the ray misses it.
+    pub fn ray_intersection(&self, origin: DVec3, direction: DVec3) -> Option<f64> {
+        let inverse_direction = 1.0 / direction;
+        let t_min = (self.min() - origin) * inverse_direction;
+        let t_max = (self.max() - origin) * inverse_direction;
+
+        let near = t_min.min(t_max).max_element();
+        let far = t_min.max(t_max).min_element();
+
+        if near > far || far < 0.0 {
+            return None;
+        }
+
+        return Some(near.max(0.0));
+    }
 }
 
 //impl From<Sphere> for Aabb {
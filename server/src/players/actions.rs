@@ -1,24 +1,42 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use bevy::{
     math::{DVec3, Vec3A},
     prelude::*,
 };
-use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+use fmc_networking::{messages, ConnectionId, Latency, NetworkData, NetworkServer};
 
 use crate::{
     bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
-    physics::{PhysicsBundle, Velocity},
+    physics::shapes::Aabb,
+    settings::Settings,
     world::{
         blocks::{BlockFace, BlockRotation, BlockState, Blocks, Friction},
-        items::{DroppedItem, Item, ItemStack, ItemStorage, Items},
+        items::{spawn_dropped_item, Item, ItemStack, ItemStorage, Items},
         //blocks::Blocks,
-        models::{Model, ModelBundle, ModelVisibility, Models},
-        world_map::{BlockUpdate, WorldMap},
+        models::{
+            raycast_to_model, Model, ModelBundle, ModelMap, ModelVisibility, Models,
+            TransformHistory,
+        },
+        sounds::{SoundGroups, SoundKind},
+        world_map::{chunk_manager::ChunkSubscriptions, BlockUpdate, WorldMap},
     },
 };
 
-use super::player::{Camera, EquippedItem, Player};
+use super::{
+    player::{Camera, EquippedItem, Player},
+    quests::{QuestAction, QuestTrigger},
+    reach::Reach,
+    status_effects::{StatusEffect, StatusEffects},
+};
+
+// How much faster blocks break when the player has haste
+const HASTE_MULTIPLIER: f32 = 1.5;
+// How much faster blocks break when the player uses the wrong tool for them.
+const WRONG_TOOL_MULTIPLIER: f32 = 0.3;
 
 // Keeps the state of how far along a block is to breaking
 #[derive(Debug)]
@@ -31,44 +49,218 @@ pub struct BreakingBlock {
 #[derive(Component)]
 pub struct BreakingBlockTag;
 
-// Left clicks are used for block breaking or attacking.
-// TODO: Need spatial partitioning of item/mobs/players to do hit detection.
+/// If the player is holding the left mouse button, and when it swings next.
+#[derive(Component, Default)]
+pub struct LeftClickState {
+    pub held: bool,
+    next_swing: Option<Instant>,
+}
+
+/// Sent when a player swings, once when the left mouse button is pressed, and then at the
+/// configured interval while it is held.
+#[derive(Event)]
+pub struct Swing {
+    pub player_entity: Entity,
+    /// The model that was hit, and the name of the hitbox.
+    pub target: Option<(Entity, String)>,
+}
+
+fn camera_transform(position: &F64GlobalTransform, camera: &Camera) -> F64Transform {
+    return F64Transform {
+        translation: position.translation() + camera.translation,
+        rotation: camera.rotation,
+        ..default()
+    };
+}
+
 pub fn handle_left_clicks(
-    mut commands: Commands,
     mut clicks: EventReader<NetworkData<messages::LeftClick>>,
+    mut player_query: Query<&mut LeftClickState>,
+) {
+    for click in clicks.read() {
+        let Ok(mut state) = player_query.get_mut(click.source.entity()) else {
+            continue;
+        };
+
+        match **click {
+            messages::LeftClick::Press => {
+                state.held = true;
+                state.next_swing = Some(Instant::now());
+            }
+            messages::LeftClick::Release => {
+                state.held = false;
+                state.next_swing = None;
+            }
+        }
+    }
+}
+
+// TODO: Need spatial partitioning of item/mobs/players to do hit detection.
+pub fn swing(
+    settings: Res<Settings>,
+    models: Res<Models>,
+    model_map: Res<ModelMap>,
+    mut player_query: Query<(
+        Entity,
+        &F64GlobalTransform,
+        &Camera,
+        &Reach,
+        &Latency,
+        &mut LeftClickState,
+    )>,
+    parent_query: Query<&Parent>,
+    hitbox_query: Query<
+        (
+            &Model,
+            &ModelVisibility,
+            &F64GlobalTransform,
+            Option<&TransformHistory>,
+        ),
+        Without<BreakingBlockTag>,
+    >,
+    mut swing_events: EventWriter<Swing>,
+) {
+    let now = Instant::now();
+
+    for (player_entity, player_position, player_camera, reach, latency, mut state) in
+        player_query.iter_mut()
+    {
+        if !state.next_swing.is_some_and(|next_swing| next_swing <= now) {
+            continue;
+        }
+
+        state.next_swing = if settings.swing_interval == 0 {
+            None
+        } else {
+            Some(now + Duration::from_millis(settings.swing_interval as u64))
+        };
+
+        let is_own_model = |entity| {
+            parent_query
+                .get(entity)
+                .is_ok_and(|parent| parent.get() == player_entity)
+        };
+        let target = raycast_to_model(
+            &models,
+            &model_map,
+            &hitbox_query,
+            &camera_transform(player_position, player_camera),
+            reach.distance,
+            latency.round_trip,
+            is_own_model,
+        )
+        .map(|(entity, hitbox, _)| (entity, hitbox.to_owned()));
+
+        swing_events.send(Swing {
+            player_entity,
+            target,
+        });
+    }
+}
+
+// Players break the block they are looking at while they hold the left mouse button. When it
+// breaks they continue on the next one.
+pub fn break_blocks(
+    mut commands: Commands,
     mut block_update_writer: EventWriter<BlockUpdate>,
+    mut quest_triggers: EventWriter<QuestTrigger>,
+    net: Res<NetworkServer>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    sound_groups: Res<SoundGroups>,
     world_map: Res<WorldMap>,
     items: Res<Items>,
     models: Res<Models>,
-    player_query: Query<(&F64GlobalTransform, &Camera)>,
+    model_map: Res<ModelMap>,
+    player_query: Query<(
+        Entity,
+        &F64GlobalTransform,
+        &Camera,
+        &ItemStorage,
+        &EquippedItem,
+        &StatusEffects,
+        &Reach,
+        &LeftClickState,
+    )>,
+    parent_query: Query<&Parent>,
+    hitbox_query: Query<
+        (
+            &Model,
+            &ModelVisibility,
+            &F64GlobalTransform,
+            Option<&TransformHistory>,
+        ),
+        Without<BreakingBlockTag>,
+    >,
     mut model_query: Query<(&mut Model, &mut ModelVisibility), With<BreakingBlockTag>>,
     mut being_broken: Local<HashMap<IVec3, BreakingBlock>>,
 ) {
-    let now = std::time::Instant::now();
-
-    for click in clicks.read() {
-        let (player_position, player_camera) = player_query.get(click.source.entity()).unwrap();
+    let now = Instant::now();
+
+    for (
+        player_entity,
+        player_position,
+        player_camera,
+        inventory,
+        equipped_item,
+        status_effects,
+        reach,
+        left_click,
+    ) in player_query.iter()
+    {
+        if !left_click.held {
+            continue;
+        }
 
         // Raycast to the nearest block
-        let camera_transform = F64Transform {
-            translation: player_position.translation() + player_camera.translation,
-            rotation: player_camera.rotation,
-            ..default()
-        };
+        let camera_transform = camera_transform(player_position, player_camera);
 
         let (block_pos, block_id, _block_face) =
-            match world_map.raycast_to_block(&camera_transform, 5.0) {
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
                 Some(b) => b,
                 None => continue,
             };
 
+        // Models that are in front of the block shield it. The player's own model is a child of
+        // the player, and is ignored.
+        let is_own_model = |entity| {
+            parent_query
+                .get(entity)
+                .is_ok_and(|parent| parent.get() == player_entity)
+        };
+        if let Some((_, _, model_distance)) = raycast_to_model(
+            &models,
+            &model_map,
+            &hitbox_query,
+            &camera_transform,
+            reach.distance,
+            Duration::ZERO,
+            is_own_model,
+        ) {
+            let block_aabb =
+                Aabb::from_min_max(block_pos.as_dvec3(), block_pos.as_dvec3() + DVec3::ONE);
+            let block_distance = block_aabb
+                .ray_intersection(camera_transform.translation, camera_transform.forward())
+                .unwrap_or(f64::MAX);
+            if model_distance < block_distance {
+                continue;
+            }
+        }
+
+        let blocks = Blocks::get();
+        let block_config = blocks.get_config(&block_id);
+
+        // Blocks without a hardness can't be broken.
+        let Some(hardness) = block_config.hardness else {
+            continue;
+        };
+
         if let Some(breaking_block) = being_broken.get_mut(&block_pos) {
             if now == breaking_block.prev_hit {
                 // Block has already been hit this tick
                 continue;
             } else if (now - breaking_block.prev_hit).as_secs_f32() > 0.05 {
-                // The interval between two clicks needs to be short in order to be counted as
-                // holding the button down.
+                // It was not hit the previous tick, the player has been mining something else in
+                // between.
                 breaking_block.prev_hit = now;
                 continue;
             } else {
@@ -77,7 +269,20 @@ pub fn handle_left_clicks(
 
                 let prev_progress = breaking_block.progress.as_secs_f32();
 
-                breaking_block.progress += now - breaking_block.prev_hit;
+                // The block breaks when the progress reaches one second, the hardness is how many
+                // seconds that takes by hand. A hardness of 0 breaks it on the next hit.
+                let mut speed = 1.0 / hardness.max(0.01);
+                if status_effects.has(StatusEffect::Haste) {
+                    speed *= HASTE_MULTIPLIER;
+                }
+                let item_categories = inventory[equipped_item.0]
+                    .item()
+                    .and_then(|item| items.get_config(&item.id).categories.as_ref());
+                if block_config.is_wrong_tool(item_categories) {
+                    speed *= WRONG_TOOL_MULTIPLIER;
+                }
+
+                breaking_block.progress += (now - breaking_block.prev_hit).mul_f32(speed);
                 breaking_block.prev_hit = now;
 
                 let progress = breaking_block.progress.as_secs_f32();
@@ -101,67 +306,42 @@ pub fn handle_left_clicks(
                 } else if prev_progress < 0.9 && progress > 0.9 {
                     model.asset_id = models.get_id("breaking_stage_9");
                 } else if progress >= 1.0 {
-                    let blocks = Blocks::get();
+                    if let Some(sound_group) = &block_config.sound_group {
+                        sound_groups.play(
+                            &net,
+                            &chunk_subscriptions,
+                            sound_group,
+                            SoundKind::Break,
+                            block_pos.as_dvec3() + DVec3::splat(0.5),
+                        );
+                    }
                     block_update_writer.send(BlockUpdate::Change {
                         position: block_pos,
                         block_id: blocks.get_id("air"),
                         block_state: None,
                     });
+                    quest_triggers.send(QuestTrigger {
+                        player_entity,
+                        action: QuestAction::BreakBlock(block_id),
+                        amount: 1,
+                    });
 
-                    let block_config = blocks.get_config(&block_id);
                     let (dropped_item_id, count) = match block_config.drop() {
                         Some(drop) => drop,
                         None => continue,
                     };
                     let item_config = items.get_config(&dropped_item_id);
-                    let model_config = models.get(&item_config.model_id);
-
-                    let mut aabb = model_config.aabb.clone();
-
-                    // We want to scale the model down to fit in a 0.15xYx0.15 box so the dropped
-                    // item is fittingly small. Then extending the smallest horizontal dimension so
-                    // that it becomes square.
-                    const WIDTH: f64 = 0.075;
-                    let max = aabb.half_extents.x.max(aabb.half_extents.z);
-                    let scale = WIDTH / max;
-                    aabb.half_extents.x = WIDTH;
-                    aabb.half_extents.y *= scale;
-                    aabb.half_extents.z = WIDTH;
-
-                    let random = rand::random::<f64>() * std::f64::consts::TAU;
-                    let (velocity_x, velocity_z) = random.sin_cos();
-
-                    // For some reason the center has to be zeroed. Does bevy center gltf models?
-                    // When the model is scaled does it shift the center(zeroing it like this would
-                    // then be slightly off)?
-                    aabb.center *= 0.0;
-                    let translation =
-                        block_pos.as_dvec3() + DVec3::splat(0.5) - DVec3::from(aabb.center);
-                    //Offset the aabb slightly downwards to make the item float for clients.
-                    aabb.center += DVec3::new(0.0, -0.1, 0.0);
-                    commands.spawn((
-                        DroppedItem(ItemStack::new(
+                    spawn_dropped_item(
+                        &mut commands,
+                        &items,
+                        &models,
+                        block_pos.as_dvec3() + DVec3::splat(0.5),
+                        ItemStack::new(
                             Item::new(dropped_item_id),
                             count,
                             item_config.max_stack_size,
-                        )),
-                        ModelBundle {
-                            model: Model::new(item_config.model_id),
-                            visibility: ModelVisibility { is_visible: true },
-                            global_transform: F64GlobalTransform::default(),
-                            transform: F64Transform {
-                                translation,
-                                scale: DVec3::splat(scale),
-                                ..default()
-                            },
-                        },
-                        PhysicsBundle {
-                            velocity: Velocity(DVec3::new(velocity_x, 5.5, velocity_z)),
-                            ..default()
-                        },
-                        // TODO: This velocity feels off
-                        aabb,
-                    ));
+                        ),
+                    );
                 }
             }
         } else {
@@ -206,6 +386,9 @@ pub fn handle_left_clicks(
 
 // Process block events sent by the clients. Client should make sure that it is a valid placement.
 pub fn handle_right_clicks(
+    net: Res<NetworkServer>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    sound_groups: Res<SoundGroups>,
     world_map: Res<WorldMap>,
     items: Res<Items>,
     mut clicks: EventReader<NetworkData<messages::RightClick>>,
@@ -215,13 +398,15 @@ pub fn handle_right_clicks(
             &EquippedItem,
             &F64GlobalTransform,
             &Camera,
+            &Reach,
         ),
         With<Player>,
     >,
     mut block_update_writer: EventWriter<BlockUpdate>,
+    mut quest_triggers: EventWriter<QuestTrigger>,
 ) {
     for right_click in clicks.read() {
-        let (mut inventory, equipped_item, player_position, player_camera) =
+        let (mut inventory, equipped_item, player_position, player_camera, reach) =
             player_query.get_mut(right_click.source.entity()).unwrap();
 
         let camera_transform = F64Transform {
@@ -230,15 +415,22 @@ pub fn handle_right_clicks(
             ..default()
         };
 
-        let (block_pos, _, block_face) = match world_map.raycast_to_block(&camera_transform, 5.0) {
-            Some(b) => b,
-            None => continue,
-        };
+        let (block_pos, clicked_block_id, block_face) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        let blocks = Blocks::get();
+
+        // Interactable blocks handle right clicks themselves.
+        if blocks.get_config(&clicked_block_id).interactable {
+            continue;
+        }
 
         let new_block_position = block_face.shift_position(block_pos);
         let block_id = world_map.get_block(new_block_position).unwrap();
 
-        let blocks = Blocks::get();
         let block_config = blocks.get_config(&block_id);
 
         if !matches!(block_config.friction, Friction::Drag(_)) {
@@ -253,6 +445,9 @@ pub fn handle_right_clicks(
         }
 
         let item_config = items.get_config(&equipped_item.item().unwrap().id);
+        let Some(item_block_id) = item_config.block else {
+            continue;
+        };
         equipped_item.subtract(1);
 
         // TODO: Placing blocks like stairs can be annoying, as situations often arise where your
@@ -270,7 +465,7 @@ pub fn handle_right_clicks(
         //  (4 outer trapezoids and one inner square)
         // By comparing which sector was clicked and the angle of the camera I think a more
         // intuitive block placement can be achieved.
-        let block_state = if blocks.get_config(&item_config.block).is_rotatable {
+        let block_state = if blocks.get_config(&item_block_id).is_rotatable {
             let mut block_state = BlockState::default();
 
             if block_face == BlockFace::Bottom {
@@ -303,10 +498,25 @@ pub fn handle_right_clicks(
             None
         };
 
+        if let Some(sound_group) = &blocks.get_config(&item_block_id).sound_group {
+            sound_groups.play(
+                &net,
+                &chunk_subscriptions,
+                sound_group,
+                SoundKind::Place,
+                new_block_position.as_dvec3() + DVec3::splat(0.5),
+            );
+        }
+
         block_update_writer.send(BlockUpdate::Change {
             position: new_block_position,
-            block_id: item_config.block,
+            block_id: item_block_id,
             block_state,
         });
+        quest_triggers.send(QuestTrigger {
+            player_entity: right_click.source.entity(),
+            action: QuestAction::PlaceBlock(item_block_id),
+            amount: 1,
+        });
     }
 }
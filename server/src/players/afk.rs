@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+
+use crate::settings::Settings;
+
+use super::{InCutscene, Player};
+
+// Players that haven't sent any input for a while are marked as AFK, which is announced in the
+// chat and shown on their name tag. If configured, they are kicked after a longer timeout.
+pub struct AfkPlugin;
+impl Plugin for AfkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (add_activity_component, track_activity, mark_idle_players).chain(),
+        );
+    }
+}
+
+/// Marks a player that hasn't sent any input for a while.
+#[derive(Component)]
+pub struct Afk;
+
+// When the player last sent any input.
+#[derive(Component)]
+struct LastActivity(Instant);
+
+fn add_activity_component(mut commands: Commands, new_player_query: Query<Entity, Added<Player>>) {
+    for entity in new_player_query.iter() {
+        commands.entity(entity).insert(LastActivity(Instant::now()));
+    }
+}
+
+fn track_activity(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    mut player_query: Query<(&Player, &mut LastActivity, Option<&Afk>)>,
+    mut position_events: EventReader<NetworkData<messages::PlayerPosition>>,
+    mut rotation_events: EventReader<NetworkData<messages::PlayerCameraRotation>>,
+    mut left_clicks: EventReader<NetworkData<messages::LeftClick>>,
+    mut right_clicks: EventReader<NetworkData<messages::RightClick>>,
+    mut text_inputs: EventReader<NetworkData<messages::InterfaceTextInput>>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+    mut button_presses: EventReader<NetworkData<messages::InterfaceButtonPress>>,
+) {
+    let mut active = Vec::new();
+
+    // The client keeps sending its position while standing still, only count it if it's moving.
+    for position_update in position_events.read() {
+        if position_update.velocity != DVec3::ZERO {
+            active.push(position_update.source.entity());
+        }
+    }
+    active.extend(rotation_events.read().map(|event| event.source.entity()));
+    active.extend(left_clicks.read().map(|event| event.source.entity()));
+    active.extend(right_clicks.read().map(|event| event.source.entity()));
+    active.extend(text_inputs.read().map(|event| event.source.entity()));
+    active.extend(chat_messages.read().map(|event| event.source.entity()));
+    active.extend(button_presses.read().map(|event| event.source.entity()));
+
+    let now = Instant::now();
+
+    for entity in active {
+        let Ok((player, mut last_activity, afk)) = player_query.get_mut(entity) else {
+            continue;
+        };
+
+        last_activity.0 = now;
+
+        if afk.is_some() {
+            commands.entity(entity).remove::<Afk>();
+
+            net.broadcast(messages::ChatMessageServer::translated(
+                "chat.player_no_longer_afk",
+                vec![player.username.clone()],
+            ));
+        }
+    }
+}
+
+fn mark_idle_players(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    player_query: Query<(
+        Entity,
+        &Player,
+        &ConnectionId,
+        &LastActivity,
+        Option<&Afk>,
+        Option<&InCutscene>,
+    )>,
+) {
+    let afk_timeout = Duration::from_secs(settings.afk_timeout as u64 * 60);
+    let kick_timeout = settings
+        .afk_kick_timeout
+        .map(|minutes| Duration::from_secs(minutes as u64 * 60));
+
+    for (entity, player, connection_id, last_activity, afk, in_cutscene) in player_query.iter() {
+        // Players can't give any input while a cutscene has locked it.
+        if in_cutscene.is_some_and(|in_cutscene| in_cutscene.locks_input) {
+            continue;
+        }
+
+        let idle_time = last_activity.0.elapsed();
+
+        if kick_timeout.is_some_and(|timeout| idle_time >= timeout) {
+            net.send_one(
+                *connection_id,
+                messages::Disconnect {
+                    message: "disconnect.afk".to_owned(),
+                    message_args: Some(Vec::new()),
+                },
+            );
+            net.disconnect(*connection_id);
+            // Stops it from being kicked again before the disconnect is processed.
+            commands.entity(entity).remove::<LastActivity>();
+        } else if afk.is_none() && idle_time >= afk_timeout {
+            commands.entity(entity).insert(Afk);
+
+            net.broadcast(messages::ChatMessageServer::translated(
+                "chat.player_afk",
+                vec![player.username.clone()],
+            ));
+        }
+    }
+}
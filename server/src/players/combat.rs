@@ -0,0 +1,336 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+};
+use fmc_networking::{messages, NetworkData, NetworkServer, ServerNetworkEvent};
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    database::Database,
+    mobs::Mob,
+    settings::Settings,
+    world::{
+        items::{spawn_dropped_item, ItemStorage, Items},
+        models::{Model, ModelBundle, ModelVisibility, Models},
+    },
+};
+
+use super::{
+    actions::Swing,
+    health::{DamageEvent, DamageSource},
+    player::{Equipment, Health},
+    Camera, Player, PlayerSave, StatusEffects, TeamMember, Teams,
+};
+
+// How much damage a swing that hits does.
+const SWING_DAMAGE: u32 = 2;
+// Players are in combat for this long after they have hit or been hit by another player.
+const COMBAT_DURATION: Duration = Duration::from_secs(15);
+
+// Players can always hit mobs, and when pvp is enabled, each other. To keep them from escaping a fight by logging
+// out, a player that leaves while in combat leaves a stand-in behind for a while. The stand-in can
+// be killed, which drops the player's items, and they respawn the next time they join. Their save
+// is written once it is known what happened to the stand-in.
+pub struct CombatPlugin;
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KilledWhileAway>()
+            .add_systems(
+                PreUpdate,
+                remove_stand_ins_on_join.before(super::add_and_remove_players),
+            )
+            .add_systems(
+                Update,
+                (
+                    spawn_stand_ins,
+                    handle_hits,
+                    remove_expired_stand_ins,
+                    notify_killed_players,
+                ),
+            );
+    }
+}
+
+/// Marks a player that has recently hit or been hit by another player.
+#[derive(Component)]
+pub struct CombatTag {
+    until: Instant,
+}
+
+impl CombatTag {
+    fn new() -> Self {
+        return Self {
+            until: Instant::now() + COMBAT_DURATION,
+        };
+    }
+
+    pub fn is_active(&self) -> bool {
+        return Instant::now() < self.until;
+    }
+}
+
+/// If the player leaves a stand-in behind when they disconnect, instead of being saved.
+pub(super) fn leaves_stand_in(settings: &Settings, combat_tag: Option<&CombatTag>) -> bool {
+    return settings.combat_log_duration != 0 && combat_tag.is_some_and(CombatTag::is_active);
+}
+
+// Takes the place of a player that left during combat.
+#[derive(Component)]
+struct StandIn {
+    player_id: String,
+    username: String,
+    save: PlayerSave,
+    hearts: u32,
+    expires: Instant,
+}
+
+// Players whose stand-in was killed, they are told when they join. Kept in memory only, after a
+// restart they will only notice that their items are gone.
+#[derive(Resource, Default)]
+struct KilledWhileAway(HashSet<String>);
+
+fn save_stand_in(database: &Database, stand_in: &mut StandIn) {
+    stand_in.save.health_mut().hearts = stand_in.hearts;
+    if let Err(err) = database.save_player(&stand_in.player_id, &stand_in.save) {
+        error!(
+            "Failed to save the player '{}' after they logged out in combat: {}",
+            stand_in.username, err
+        );
+    }
+}
+
+fn spawn_stand_ins(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    models: Res<Models>,
+    player_query: Query<(
+        &Player,
+        &F64Transform,
+        &Camera,
+        &ItemStorage,
+        &Equipment,
+        &Health,
+        &StatusEffects,
+        Option<&CombatTag>,
+    )>,
+    mut network_events: EventReader<ServerNetworkEvent>,
+) {
+    for event in network_events.read() {
+        let ServerNetworkEvent::Disconnected { entity } = event else {
+            continue;
+        };
+
+        let Ok((player, transform, camera, inventory, equipment, health, status_effects, tag)) =
+            player_query.get(*entity)
+        else {
+            continue;
+        };
+
+        if !leaves_stand_in(&settings, tag) || health.hearts == 0 {
+            continue;
+        }
+
+        info!(
+            "Player '{}' logged out in combat, leaving a stand-in",
+            player.username
+        );
+
+        let theta = f64::atan2(camera.rotation.y, camera.rotation.w);
+        let model_transform = F64Transform {
+            translation: transform.translation + DVec3::new(0.3, 0.0, 0.3),
+            rotation: DQuat::from_xyzw(0.0, f64::sin(theta), 0.0, f64::cos(theta)),
+            ..default()
+        };
+
+        commands.spawn((
+            StandIn {
+                player_id: player.id.clone(),
+                username: player.username.clone(),
+                save: PlayerSave::new(
+                    transform,
+                    camera,
+                    inventory,
+                    equipment,
+                    health,
+                    status_effects,
+                ),
+                hearts: health.hearts,
+                expires: Instant::now() + Duration::from_secs(settings.combat_log_duration as u64),
+            },
+            ModelBundle {
+                model: Model::new(models.get_id("player")),
+                visibility: ModelVisibility::default(),
+                global_transform: F64GlobalTransform::from(model_transform),
+                transform: model_transform,
+            },
+        ));
+    }
+}
+
+fn handle_hits(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    items: Res<Items>,
+    models: Res<Models>,
+    teams: Res<Teams>,
+    mut killed_while_away: ResMut<KilledWhileAway>,
+    parent_query: Query<&Parent>,
+    player_query: Query<(&Player, &Health, &TeamMember)>,
+    mob_query: Query<(), With<Mob>>,
+    mut stand_in_query: Query<(&mut StandIn, &F64Transform)>,
+    mut swing_events: EventReader<Swing>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for swing in swing_events.read() {
+        let Some((target, _)) = &swing.target else {
+            continue;
+        };
+
+        let Ok((attacker, attacker_health, attacker_team)) = player_query.get(swing.player_entity)
+        else {
+            continue;
+        };
+
+        if attacker_health.hearts == 0 {
+            continue;
+        }
+
+        if mob_query.contains(*target) {
+            damage_events.send(DamageEvent {
+                entity: *target,
+                damage: SWING_DAMAGE,
+                source: DamageSource::Attack(swing.player_entity),
+            });
+            continue;
+        }
+
+        if !settings.pvp {
+            continue;
+        }
+
+        // Players are hit through their model, which is a child of the player.
+        if let Some(victim_entity) = parent_query
+            .get(*target)
+            .ok()
+            .map(|parent| parent.get())
+            .filter(|parent| player_query.contains(*parent))
+        {
+            let (_, victim_health, victim_team) = player_query.get(victim_entity).unwrap();
+            if victim_health.hearts == 0 || !teams.can_hurt(attacker_team, victim_team) {
+                continue;
+            }
+
+            damage_events.send(DamageEvent {
+                entity: victim_entity,
+                damage: SWING_DAMAGE,
+                source: DamageSource::Attack(swing.player_entity),
+            });
+            commands.entity(victim_entity).insert(CombatTag::new());
+            commands
+                .entity(swing.player_entity)
+                .insert(CombatTag::new());
+            continue;
+        }
+
+        let Ok((mut stand_in, transform)) = stand_in_query.get_mut(*target) else {
+            continue;
+        };
+
+        commands
+            .entity(swing.player_entity)
+            .insert(CombatTag::new());
+
+        stand_in.hearts = stand_in.hearts.saturating_sub(SWING_DAMAGE);
+        if stand_in.hearts != 0 {
+            continue;
+        }
+
+        for item_stack in stand_in.save.item_stacks_mut() {
+            if item_stack.is_empty() {
+                continue;
+            }
+            spawn_dropped_item(
+                &mut commands,
+                &items,
+                &models,
+                transform.translation + DVec3::Y,
+                std::mem::take(item_stack),
+            );
+        }
+
+        stand_in.save.respawn();
+        stand_in.hearts = stand_in.save.health_mut().hearts;
+        save_stand_in(&database, &mut stand_in);
+
+        net.broadcast(messages::ChatMessageServer::translated(
+            "combat_log.stand_in_killed",
+            vec![stand_in.username.clone(), attacker.username.clone()],
+        ));
+        killed_while_away.0.insert(stand_in.player_id.clone());
+        commands.entity(*target).despawn_recursive();
+    }
+}
+
+fn remove_expired_stand_ins(
+    mut commands: Commands,
+    database: Res<Database>,
+    mut stand_in_query: Query<(Entity, &mut StandIn)>,
+) {
+    let now = Instant::now();
+
+    for (entity, mut stand_in) in stand_in_query.iter_mut() {
+        if stand_in.expires <= now {
+            save_stand_in(&database, &mut stand_in);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// The player's save is written before it is loaded, so they continue from where the stand-in was
+// left.
+fn remove_stand_ins_on_join(
+    mut commands: Commands,
+    database: Res<Database>,
+    mut stand_in_query: Query<(Entity, &mut StandIn)>,
+    mut network_events: EventReader<ServerNetworkEvent>,
+) {
+    for event in network_events.read() {
+        let ServerNetworkEvent::Connected { player_id, .. } = event else {
+            continue;
+        };
+
+        for (entity, mut stand_in) in stand_in_query.iter_mut() {
+            if &stand_in.player_id == player_id {
+                save_stand_in(&database, &mut stand_in);
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn notify_killed_players(
+    net: Res<NetworkServer>,
+    mut killed_while_away: ResMut<KilledWhileAway>,
+    player_query: Query<&Player>,
+    mut finished_loading_events: EventReader<NetworkData<messages::ClientFinishedLoading>>,
+) {
+    for event in finished_loading_events.read() {
+        let Ok(player) = player_query.get(event.source.entity()) else {
+            continue;
+        };
+
+        if killed_while_away.0.remove(&player.id) {
+            net.send_one(
+                event.source,
+                messages::ChatMessageServer::translated("combat_log.killed", vec![]),
+            );
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+
+use super::Player;
+
+// Cutscenes take control of a player's camera for a while, e.g. to introduce a place or to hide a
+// teleport behind a fade to black. The client plays them on its own, the server only keeps track
+// of which players are watching one. Players can skip them at any time.
+pub struct CutscenePlugin;
+impl Plugin for CutscenePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayCutscene>()
+            .add_event::<StopCutscene>()
+            .add_systems(
+                Update,
+                (
+                    play_cutscenes,
+                    stop_cutscenes,
+                    handle_skipped_cutscenes,
+                    remove_finished_cutscenes,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Plays a cutscene for a player, it replaces any cutscene that is already playing.
+#[derive(Event)]
+pub struct PlayCutscene {
+    pub player_entity: Entity,
+    pub cutscene: messages::Cutscene,
+}
+
+/// Stops the cutscene a player is watching, if any.
+#[derive(Event)]
+pub struct StopCutscene {
+    pub player_entity: Entity,
+}
+
+/// Marks a player that is watching a cutscene.
+#[derive(Component)]
+pub struct InCutscene {
+    /// If the player's input is ignored by the client while it plays.
+    pub locks_input: bool,
+    ends: Instant,
+}
+
+fn play_cutscenes(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    player_query: Query<&ConnectionId, With<Player>>,
+    mut cutscene_events: EventReader<PlayCutscene>,
+) {
+    for event in cutscene_events.read() {
+        let Ok(connection_id) = player_query.get(event.player_entity) else {
+            continue;
+        };
+
+        commands.entity(event.player_entity).insert(InCutscene {
+            locks_input: event.cutscene.lock_input,
+            ends: Instant::now() + event.cutscene.duration(),
+        });
+        net.send_one(*connection_id, event.cutscene.clone());
+    }
+}
+
+fn stop_cutscenes(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    player_query: Query<&ConnectionId, With<InCutscene>>,
+    mut stop_events: EventReader<StopCutscene>,
+) {
+    for event in stop_events.read() {
+        let Ok(connection_id) = player_query.get(event.player_entity) else {
+            continue;
+        };
+
+        commands.entity(event.player_entity).remove::<InCutscene>();
+        net.send_one(*connection_id, messages::CutsceneEnd);
+    }
+}
+
+fn handle_skipped_cutscenes(
+    mut commands: Commands,
+    player_query: Query<Entity, With<InCutscene>>,
+    mut end_events: EventReader<NetworkData<messages::CutsceneEnd>>,
+) {
+    for event in end_events.read() {
+        if let Ok(entity) = player_query.get(event.source.entity()) {
+            commands.entity(entity).remove::<InCutscene>();
+        }
+    }
+}
+
+fn remove_finished_cutscenes(mut commands: Commands, player_query: Query<(Entity, &InCutscene)>) {
+    let now = Instant::now();
+
+    for (entity, in_cutscene) in player_query.iter() {
+        if in_cutscene.ends <= now {
+            commands.entity(entity).remove::<InCutscene>();
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::{Duration, Instant};
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, NetworkData, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::F64Transform,
+    utils,
+    world::{models::Model, world_map::chunk_manager::ChunkSubscriptions},
+};
+
+use super::Player;
+
+// Shortest time between two emotes of the same player.
+const EMOTE_COOLDOWN: Duration = Duration::from_secs(3);
+// Held emotes stop when the player moves further than this from where they started it.
+const HELD_EMOTE_MOVE_DISTANCE: f64 = 0.1;
+
+// Players play emotes with '/emote <name>'. The emote is an animation of the same name in the
+// player model, it is played for everyone that can see the player.
+pub struct EmotePlugin;
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (handle_emote_commands, stop_held_emotes).chain());
+    }
+}
+
+struct Emote {
+    name: &'static str,
+    /// Played until the player moves, instead of once.
+    held: bool,
+}
+
+const EMOTES: [Emote; 3] = [
+    Emote {
+        name: "wave",
+        held: false,
+    },
+    Emote {
+        name: "point",
+        held: false,
+    },
+    Emote {
+        name: "sit",
+        held: true,
+    },
+];
+
+/// Keeps track of the emotes of a player.
+#[derive(Component, Default)]
+pub struct EmoteState {
+    last_emote: Option<Instant>,
+    // Where the player was when they started a held emote.
+    held_at: Option<DVec3>,
+}
+
+// Sends the animation to everyone that is subscribed to the chunk the player is in.
+fn play_animation(
+    net: &NetworkServer,
+    chunk_subscriptions: &ChunkSubscriptions,
+    model_query: &Query<Entity, With<Model>>,
+    children: &Children,
+    position: DVec3,
+    animation: &str,
+    repeat: bool,
+) {
+    let Some(model_entity) = children
+        .iter()
+        .find_map(|child| model_query.get(*child).ok())
+    else {
+        return;
+    };
+
+    let chunk_position = utils::world_position_to_chunk_position(position.as_ivec3());
+    if let Some(subscribers) = chunk_subscriptions.get_subscribers(&chunk_position) {
+        net.send_many(
+            subscribers,
+            messages::ModelPlayAnimation {
+                id: model_entity.index(),
+                animation: animation.to_owned(),
+                repeat,
+            },
+        );
+    }
+}
+
+fn handle_emote_commands(
+    net: Res<NetworkServer>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    model_query: Query<Entity, With<Model>>,
+    mut player_query: Query<(&F64Transform, &Children, &mut EmoteState), With<Player>>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/emote") {
+            continue;
+        }
+
+        let Ok((transform, children, mut emote_state)) =
+            player_query.get_mut(chat_message.source.entity())
+        else {
+            continue;
+        };
+
+        let names: Vec<&str> = EMOTES.iter().map(|emote| emote.name).collect();
+
+        let (Some(name), None) = (words.next(), words.next()) else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("emote.usage", vec![names.join(", ")]),
+            );
+            continue;
+        };
+
+        let Some(emote) = EMOTES.iter().find(|emote| emote.name == name) else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(
+                    "emote.unknown",
+                    vec![name.to_owned(), names.join(", ")],
+                ),
+            );
+            continue;
+        };
+
+        if let Some(last_emote) = emote_state.last_emote {
+            let elapsed = last_emote.elapsed();
+            if elapsed < EMOTE_COOLDOWN {
+                let remaining = (EMOTE_COOLDOWN - elapsed).as_secs() + 1;
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        "emote.cooldown",
+                        vec![remaining.to_string()],
+                    ),
+                );
+                continue;
+            }
+        }
+
+        emote_state.last_emote = Some(Instant::now());
+        emote_state.held_at = if emote.held {
+            Some(transform.translation)
+        } else {
+            None
+        };
+
+        play_animation(
+            &net,
+            &chunk_subscriptions,
+            &model_query,
+            children,
+            transform.translation,
+            emote.name,
+            emote.held,
+        );
+    }
+}
+
+fn stop_held_emotes(
+    net: Res<NetworkServer>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    model_query: Query<Entity, With<Model>>,
+    mut player_query: Query<(&F64Transform, &Children, &mut EmoteState), Changed<F64Transform>>,
+) {
+    for (transform, children, mut emote_state) in player_query.iter_mut() {
+        let Some(held_at) = emote_state.held_at else {
+            continue;
+        };
+
+        if held_at.distance(transform.translation) < HELD_EMOTE_MOVE_DISTANCE {
+            continue;
+        }
+
+        emote_state.held_at = None;
+        play_animation(
+            &net,
+            &chunk_subscriptions,
+            &model_query,
+            children,
+            transform.translation,
+            "",
+            false,
+        );
+    }
+}
@@ -1,18 +1,63 @@
-use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+use bevy::{core::FrameCount, math::DVec3, prelude::*};
 
 use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
 
-use super::{player::Health, Player, RespawnEvent};
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    mobs::{Mob, MobConfigs},
+    physics::Velocity,
+    world::{
+        blocks::Blocks,
+        items::{spawn_dropped_item, ItemStorage, Items},
+        models::Models,
+        sounds::{SoundGroups, SoundKind},
+        world_map::{chunk_manager::ChunkSubscriptions, WorldMap},
+    },
+};
+
+use super::{
+    player::{Camera, Equipment, Health},
+    Player, RespawnEvent,
+};
+
+// The sound group players use, only its hurt sounds are played.
+const PLAYER_SOUND_GROUP: &str = "player";
+// After being hurt, nothing can hurt the entity again for this long. Keeps a crowd of mobs from
+// killing a player in an instant.
+const INVULNERABILITY_DURATION: Duration = Duration::from_millis(500);
+// The speed attacks knock their target away and up with.
+const KNOCKBACK_HORIZONTAL: f64 = 6.0;
+const KNOCKBACK_VERTICAL: f64 = 5.0;
+// Seconds players can hold their breath, and how often they take damage after they run out.
+const BREATH_DURATION: f32 = 10.0;
+const DROWNING_INTERVAL: f32 = 1.0;
+const DROWNING_DAMAGE: u32 = 2;
+// The world goes on forever downwards, but anything that makes it this far below has left it.
+const VOID_HEIGHT: f64 = -512.0;
 
+// Everything that hurts players and mobs goes through damage events. They are applied in one place
+// so invulnerability, knockback and dying work the same no matter what did the damage. When
+// something runs out of health a death event is sent. Dead players drop their items and are
+// shown the death screen, from which they respawn. Dead mobs are removed.
 pub struct HealthPlugin;
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<DamageEvent>()
             .add_event::<HealEvent>()
-            .add_systems(Update, (fall_damage, heal_on_respawn).before(change_health))
+            .add_event::<DeathEvent>()
             .add_systems(
                 Update,
-                (add_fall_damage_component, change_health, death_interface),
+                (fall_damage, drowning, void_damage, heal_on_respawn).before(change_health),
+            )
+            .add_systems(
+                Update,
+                (
+                    add_damage_components,
+                    change_health,
+                    (drop_items_on_death, announce_deaths, death_interface).after(change_health),
+                ),
             );
     }
 }
@@ -20,24 +65,77 @@ impl Plugin for HealthPlugin {
 #[derive(Component)]
 pub struct FallDamage(u32);
 
+// How long a player can still hold their breath.
+#[derive(Component)]
+struct Breath {
+    seconds_left: f32,
+}
+
+impl Default for Breath {
+    fn default() -> Self {
+        return Self {
+            seconds_left: BREATH_DURATION,
+        };
+    }
+}
+
+/// Entities with this can't be damaged until it runs out.
+#[derive(Component)]
+pub struct Invulnerable {
+    until: Instant,
+}
+
+impl Invulnerable {
+    pub fn new(duration: Duration) -> Self {
+        return Self {
+            until: Instant::now() + duration,
+        };
+    }
+
+    fn is_active(&self) -> bool {
+        return Instant::now() < self.until;
+    }
+}
+
+/// What caused some damage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DamageSource {
+    /// Hit the ground too hard.
+    Fall,
+    /// Hit by the entity, a player or a mob. The target is knocked back away from it.
+    Attack(Entity),
+    /// Ran out of breath.
+    Drowning,
+    /// Fell out of the world.
+    Void,
+}
+
+/// Hurt a player or mob.
 #[derive(Event)]
-struct DamageEvent {
-    entity: Entity,
-    damage: u32,
+pub struct DamageEvent {
+    pub entity: Entity,
+    pub damage: u32,
+    pub source: DamageSource,
 }
 
 #[derive(Event)]
-struct HealEvent {
-    entity: Entity,
-    healing: u32,
+pub struct HealEvent {
+    pub entity: Entity,
+    pub healing: u32,
 }
 
-fn add_fall_damage_component(
-    mut commands: Commands,
-    new_player_query: Query<Entity, Added<Player>>,
-) {
+/// Sent when a player or mob has run out of health, with what dealt the final blow.
+#[derive(Event)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+}
+
+fn add_damage_components(mut commands: Commands, new_player_query: Query<Entity, Added<Player>>) {
     for entity in new_player_query.iter() {
-        commands.entity(entity).insert(FallDamage(0));
+        commands
+            .entity(entity)
+            .insert((FallDamage(0), Breath::default()));
     }
 }
 
@@ -52,10 +150,11 @@ fn fall_damage(
             .unwrap();
 
         if fall_damage.0 != 0 && position_update.velocity.y > -0.1 {
-            //damage_events.send(DamageEvent {
-            //    entity,
-            //    damage: fall_damage.0,
-            //});
+            damage_events.send(DamageEvent {
+                entity,
+                damage: fall_damage.0,
+                source: DamageSource::Fall,
+            });
             fall_damage.0 = 0;
         } else if position_update.velocity.y < 0.0 {
             fall_damage.0 = (position_update.velocity.y.abs() as u32).saturating_sub(15);
@@ -63,34 +162,234 @@ fn fall_damage(
     }
 }
 
+fn drowning(
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    mut player_query: Query<(Entity, &F64GlobalTransform, &Camera, &Health, &mut Breath)>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    let blocks = Blocks::get();
+
+    for (entity, transform, camera, health, mut breath) in player_query.iter_mut() {
+        if health.hearts == 0 {
+            continue;
+        }
+
+        let head_position = (transform.translation() + camera.translation)
+            .floor()
+            .as_ivec3();
+        let is_submerged = world_map
+            .get_block(head_position)
+            .is_some_and(|block_id| blocks.get_config(&block_id).drowning);
+
+        if !is_submerged {
+            if breath.seconds_left != BREATH_DURATION {
+                breath.seconds_left = BREATH_DURATION;
+            }
+            continue;
+        }
+
+        breath.seconds_left -= time.delta_seconds();
+        if breath.seconds_left <= 0.0 {
+            breath.seconds_left += DROWNING_INTERVAL;
+            damage_events.send(DamageEvent {
+                entity,
+                damage: DROWNING_DAMAGE,
+                source: DamageSource::Drowning,
+            });
+        }
+    }
+}
+
+fn void_damage(
+    damageable_query: Query<(Entity, &F64Transform), Or<(With<Health>, With<Mob>)>>,
+    mut damage_events: EventWriter<DamageEvent>,
+) {
+    for (entity, transform) in damageable_query.iter() {
+        if transform.translation.y < VOID_HEIGHT {
+            damage_events.send(DamageEvent {
+                entity,
+                damage: u32::MAX,
+                source: DamageSource::Void,
+            });
+        }
+    }
+}
+
+// Knocks the target away from the attacker, and a little up.
+fn knockback(attacker_position: DVec3, target_position: DVec3) -> DVec3 {
+    let direction =
+        ((target_position - attacker_position) * DVec3::new(1.0, 0.0, 1.0)).normalize_or_zero();
+    return direction * KNOCKBACK_HORIZONTAL + DVec3::Y * KNOCKBACK_VERTICAL;
+}
+
 fn change_health(
+    mut commands: Commands,
     net: Res<NetworkServer>,
-    mut health_query: Query<(&mut Health, &ConnectionId)>,
+    tick: Res<FrameCount>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    sound_groups: Res<SoundGroups>,
+    mut health_query: Query<(&mut Health, &ConnectionId, &F64GlobalTransform)>,
+    mut mob_query: Query<(&mut Mob, &F64GlobalTransform, &mut Velocity)>,
+    attacker_query: Query<&F64GlobalTransform>,
+    invulnerable_query: Query<&Invulnerable>,
     mut damage_events: EventReader<DamageEvent>,
     mut heal_events: EventReader<HealEvent>,
+    mut death_events: EventWriter<DeathEvent>,
 ) {
+    // The invulnerability is inserted through commands, so it isn't visible until the next
+    // update. Damage from the same update is kept out with this instead.
+    let mut damaged = Vec::new();
+
     for damage_event in damage_events.read() {
-        let (mut health, connection_id) = health_query.get_mut(damage_event.entity).unwrap();
-        let interface_update = health.take_damage(damage_event.damage);
-        net.send_one(*connection_id, interface_update);
+        if damaged.contains(&damage_event.entity)
+            || invulnerable_query
+                .get(damage_event.entity)
+                .is_ok_and(Invulnerable::is_active)
+        {
+            continue;
+        }
 
-        if health.hearts == 0 {
-            net.send_one(
-                *connection_id,
-                messages::InterfaceOpen {
-                    interface_path: "death_screen".to_owned(),
-                },
+        let attacker_position = match damage_event.source {
+            DamageSource::Attack(attacker) => attacker_query
+                .get(attacker)
+                .ok()
+                .map(|transform| transform.translation()),
+            _ => None,
+        };
+
+        let is_dead = if let Ok((mut health, connection_id, transform)) =
+            health_query.get_mut(damage_event.entity)
+        {
+            if health.hearts == 0 {
+                continue;
+            }
+
+            let interface_update = health.take_damage(damage_event.damage);
+            net.send_one(*connection_id, interface_update);
+
+            sound_groups.play(
+                &net,
+                &chunk_subscriptions,
+                PLAYER_SOUND_GROUP,
+                SoundKind::Hurt,
+                transform.translation(),
             );
+
+            // The client is in charge of the player's movement, so the knockback has to be
+            // sent to it.
+            if let Some(attacker_position) = attacker_position {
+                net.send_one(
+                    *connection_id,
+                    messages::PlayerPosition {
+                        tick: tick.0,
+                        position: transform.translation(),
+                        velocity: knockback(attacker_position, transform.translation()),
+                    },
+                );
+            }
+
+            health.hearts == 0
+        } else if let Ok((mut mob, transform, mut velocity)) =
+            mob_query.get_mut(damage_event.entity)
+        {
+            if mob.health == 0 {
+                continue;
+            }
+
+            mob.health = mob.health.saturating_sub(damage_event.damage);
+
+            if let Some(attacker_position) = attacker_position {
+                velocity.0 = knockback(attacker_position, transform.translation());
+            }
+
+            mob.health == 0
+        } else {
+            continue;
+        };
+
+        damaged.push(damage_event.entity);
+        commands
+            .entity(damage_event.entity)
+            .insert(Invulnerable::new(INVULNERABILITY_DURATION));
+
+        if is_dead {
+            death_events.send(DeathEvent {
+                entity: damage_event.entity,
+                source: damage_event.source,
+            });
         }
     }
 
     for event in heal_events.read() {
-        let (mut health, connection_id) = health_query.get_mut(event.entity).unwrap();
+        let Ok((mut health, connection_id, _)) = health_query.get_mut(event.entity) else {
+            continue;
+        };
         let interface_update = health.heal(event.healing);
         net.send_one(*connection_id, interface_update);
     }
 }
 
+fn drop_items_on_death(
+    mut commands: Commands,
+    items: Res<Items>,
+    models: Res<Models>,
+    mut player_query: Query<(&F64GlobalTransform, &mut ItemStorage, &mut Equipment)>,
+    mut death_events: EventReader<DeathEvent>,
+) {
+    for death_event in death_events.read() {
+        let Ok((transform, mut inventory, mut equipment)) =
+            player_query.get_mut(death_event.entity)
+        else {
+            continue;
+        };
+
+        for item_stack in inventory.iter_mut().chain(equipment.iter_mut()) {
+            if item_stack.is_empty() {
+                continue;
+            }
+            spawn_dropped_item(
+                &mut commands,
+                &items,
+                &models,
+                transform.translation() + DVec3::Y,
+                std::mem::take(item_stack),
+            );
+        }
+    }
+}
+
+fn announce_deaths(
+    net: Res<NetworkServer>,
+    mob_configs: Res<MobConfigs>,
+    player_query: Query<&Player>,
+    mob_query: Query<&Mob>,
+    mut death_events: EventReader<DeathEvent>,
+) {
+    for death_event in death_events.read() {
+        let Ok(player) = player_query.get(death_event.entity) else {
+            continue;
+        };
+
+        let mut args = vec![player.username.clone()];
+        let key = match death_event.source {
+            DamageSource::Fall => "death.fall",
+            DamageSource::Drowning => "death.drowning",
+            DamageSource::Void => "death.void",
+            DamageSource::Attack(attacker) => {
+                if let Ok(attacker) = player_query.get(attacker) {
+                    args.push(attacker.username.clone());
+                } else if let Ok(mob) = mob_query.get(attacker) {
+                    args.push(mob_configs.get(mob.id).name.clone());
+                }
+                "death.attack"
+            }
+        };
+
+        net.broadcast(messages::ChatMessageServer::translated(key, args));
+    }
+}
+
 fn heal_on_respawn(
     mut respawn_events: EventReader<RespawnEvent>,
     mut heal_events: EventWriter<HealEvent>,
@@ -105,10 +404,24 @@ fn heal_on_respawn(
 
 fn death_interface(
     net: Res<NetworkServer>,
+    connection_query: Query<&ConnectionId>,
     health_query: Query<&Health>,
+    mut death_events: EventReader<DeathEvent>,
     mut respawn_button_events: EventReader<NetworkData<messages::InterfaceButtonPress>>,
     mut respawn_events: EventWriter<RespawnEvent>,
 ) {
+    for death_event in death_events.read() {
+        let Ok(connection_id) = connection_query.get(death_event.entity) else {
+            continue;
+        };
+        net.send_one(
+            *connection_id,
+            messages::InterfaceOpen {
+                interface_path: "death_screen".to_owned(),
+            },
+        );
+    }
+
     for button_press in respawn_button_events.read() {
         if &button_press.interface_path != "death_screen/respawn_button" {
             return;
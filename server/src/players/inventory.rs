@@ -32,10 +32,10 @@ struct PlayerInterfaceUpdate {
     pub inventory: Option<messages::InterfaceItemBoxUpdate>,
 }
 
-// Items that are taken from the interface are stored in this until they are placed again. No new
-// items are allowed to be taken until it has been placed.
+/// Items that are taken from an interface are stored in this until they are placed again. No new
+/// items are allowed to be taken until it has been placed.
 #[derive(Component, Deref, DerefMut)]
-struct HeldItemStack(ItemStack);
+pub struct HeldItemStack(ItemStack);
 
 // Takes care of both the hotbar interface and the inventory interface as the hotbar shares items
 // with the inventory.
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+
+use crate::{
+    database::Database,
+    world::items::{ItemStack, ItemStorage},
+};
+
+use super::{EquippedItem, Player};
+
+// How many letters can wait in a mailbox, it is also the number of item boxes in the interface.
+const MAX_LETTERS: usize = 18;
+const MAX_MESSAGE_LENGTH: usize = 200;
+
+// Players can send letters to each other with '/mail send <name> <message>', and attach the item
+// they are holding with '/mail item <name> [message]'. Letters are stored in the database until
+// the recipient is online to read them. The text is delivered in the chat when they join, and
+// letters with items are kept until the items have been collected from the mailbox interface,
+// which is opened with '/mail'.
+pub struct MailPlugin;
+impl Plugin for MailPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (deliver_mail_on_join, handle_mail_commands, collect_mail),
+        );
+    }
+}
+
+/// A letter waiting in a player's mailbox.
+pub struct Letter {
+    pub id: i64,
+    /// Name of the sender, as it was when the letter was sent.
+    pub sender: String,
+    pub message: String,
+    pub item_stack: Option<ItemStack>,
+}
+
+// Shows the letters in the chat. Letters that only contain text are done after this and are
+// removed, the ones with items stay until the items are collected.
+fn deliver_letters(
+    net: &NetworkServer,
+    database: &Database,
+    connection_id: ConnectionId,
+    player: &Player,
+) {
+    let letters = match database.load_mail(&player.id) {
+        Ok(letters) => letters,
+        Err(err) => {
+            error!("Failed to load the mail of '{}': {}", player.username, err);
+            return;
+        }
+    };
+
+    if letters.is_empty() {
+        return;
+    }
+
+    net.send_one(
+        connection_id,
+        messages::ChatMessageServer::translated("mail.received", vec![letters.len().to_string()]),
+    );
+
+    let mut has_items = false;
+    for letter in letters.iter() {
+        let message = if letter.message.is_empty() {
+            messages::ChatMessageServer::translated("mail.package", vec![letter.sender.clone()])
+        } else {
+            messages::ChatMessageServer::translated(
+                "mail.letter",
+                vec![letter.sender.clone(), letter.message.clone()],
+            )
+        };
+        net.send_one(connection_id, message);
+
+        if letter.item_stack.is_some() {
+            has_items = true;
+        } else if let Err(err) = database.delete_mail(letter.id) {
+            // It will be shown again the next time, which is better than losing it.
+            error!("Failed to remove a delivered letter: {}", err);
+        }
+    }
+
+    if has_items {
+        net.send_one(
+            connection_id,
+            messages::ChatMessageServer::translated("mail.has_items", vec![]),
+        );
+    }
+}
+
+fn deliver_mail_on_join(
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    player_query: Query<&Player>,
+    mut events: EventReader<NetworkData<messages::ClientFinishedLoading>>,
+) {
+    for event in events.read() {
+        let Ok(player) = player_query.get(event.source.entity()) else {
+            continue;
+        };
+
+        deliver_letters(&net, &database, event.source, player);
+    }
+}
+
+fn build_mailbox(letters: &[Letter]) -> messages::InterfaceItemBoxUpdate {
+    let mut mailbox = messages::InterfaceItemBoxUpdate::new(false);
+
+    let mut item_stacks = letters
+        .iter()
+        .filter_map(|letter| letter.item_stack.as_ref());
+
+    for i in 0..MAX_LETTERS as u32 {
+        if let Some(item_stack) = item_stacks.next() {
+            let item = item_stack.item().unwrap();
+            mailbox.add_itembox(
+                "mailbox/items",
+                i,
+                item.id,
+                item_stack.size(),
+                item.properties["durability"].as_u32(),
+                item.properties["description"].as_str(),
+            );
+        } else {
+            mailbox.add_empty_itembox("mailbox/items", i);
+        }
+    }
+
+    return mailbox;
+}
+
+fn handle_mail_commands(
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    mut player_query: Query<(&Player, &ConnectionId, &mut ItemStorage, &EquippedItem)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/mail") {
+            continue;
+        }
+
+        let (with_item, recipient_name) = match (words.next(), words.next()) {
+            (None, _) => {
+                let Ok((player, ..)) = player_query.get(chat_message.source.entity()) else {
+                    continue;
+                };
+                match database.load_mail(&player.id) {
+                    Ok(letters) => {
+                        net.send_one(chat_message.source, build_mailbox(&letters));
+                        net.send_one(
+                            chat_message.source,
+                            messages::InterfaceOpen {
+                                interface_path: "mailbox".to_owned(),
+                            },
+                        );
+                    }
+                    Err(err) => {
+                        error!("Failed to load the mail of '{}': {}", player.username, err);
+                        net.send_one(
+                            chat_message.source,
+                            messages::ChatMessageServer::translated("mail.failed", vec![]),
+                        );
+                    }
+                }
+                continue;
+            }
+            (Some("send"), Some(name)) => (false, name),
+            (Some("item"), Some(name)) => (true, name),
+            _ => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("mail.usage", vec![]),
+                );
+                continue;
+            }
+        };
+
+        let message = words.collect::<Vec<&str>>().join(" ");
+        if message.is_empty() && !with_item {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("mail.usage", vec![]),
+            );
+            continue;
+        }
+
+        if message.chars().count() > MAX_MESSAGE_LENGTH {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(
+                    "mail.too_long",
+                    vec![MAX_MESSAGE_LENGTH.to_string()],
+                ),
+            );
+            continue;
+        }
+
+        let recipient_id = match database.player_id(recipient_name) {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        "mail.unknown_player",
+                        vec![recipient_name.to_owned()],
+                    ),
+                );
+                continue;
+            }
+            Err(err) => {
+                error!("Failed to look up the player '{}': {}", recipient_name, err);
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("mail.failed", vec![]),
+                );
+                continue;
+            }
+        };
+
+        match database.count_mail(&recipient_id) {
+            Ok(count) if count >= MAX_LETTERS => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        "mail.full",
+                        vec![recipient_name.to_owned()],
+                    ),
+                );
+                continue;
+            }
+            Ok(_) => (),
+            Err(err) => {
+                error!("Failed to count the mail of '{}': {}", recipient_name, err);
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("mail.failed", vec![]),
+                );
+                continue;
+            }
+        }
+
+        let Ok((sender, _, mut inventory, equipped_item)) =
+            player_query.get_mut(chat_message.source.entity())
+        else {
+            continue;
+        };
+
+        let item_stack = if with_item {
+            let item_stack = &inventory[equipped_item.0];
+            if item_stack.is_empty() {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("mail.no_item", vec![]),
+                );
+                continue;
+            }
+            Some(item_stack.clone())
+        } else {
+            None
+        };
+
+        if let Err(err) = database.send_mail(
+            &recipient_id,
+            &sender.username,
+            &message,
+            item_stack.as_ref(),
+        ) {
+            error!("Failed to send mail to '{}': {}", recipient_name, err);
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("mail.failed", vec![]),
+            );
+            continue;
+        }
+
+        // The item is only taken once the letter has been stored, so it can't be lost.
+        if with_item {
+            inventory[equipped_item.0] = ItemStack::default();
+        }
+
+        net.send_one(
+            chat_message.source,
+            messages::ChatMessageServer::translated("mail.sent", vec![recipient_name.to_owned()]),
+        );
+
+        // Players that are online get the letter right away.
+        if let Some((recipient, connection_id, ..)) = player_query
+            .iter()
+            .find(|(player, ..)| player.id == recipient_id)
+        {
+            deliver_letters(&net, &database, *connection_id, recipient);
+        }
+    }
+}
+
+// Moves as much of the item stack into the inventory as there is room for. Stacks of the same
+// item are filled before empty slots are used.
+pub(super) fn insert_into_inventory(inventory: &mut ItemStorage, item_stack: &mut ItemStack) {
+    for slot in inventory.iter_mut() {
+        if item_stack.is_empty() {
+            return;
+        }
+        if slot.item() == item_stack.item() {
+            let size = item_stack.size();
+            slot.transfer(item_stack, size);
+        }
+    }
+
+    for slot in inventory.iter_mut() {
+        if item_stack.is_empty() {
+            return;
+        }
+        if slot.is_empty() {
+            let size = item_stack.size();
+            slot.transfer(item_stack, size);
+        }
+    }
+}
+
+fn collect_mail(
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    mut player_query: Query<(&Player, &mut ItemStorage)>,
+    mut button_presses: EventReader<NetworkData<messages::InterfaceButtonPress>>,
+) {
+    for button_press in button_presses.read() {
+        if button_press.interface_path != "mailbox/collect" {
+            continue;
+        }
+
+        let Ok((player, mut inventory)) = player_query.get_mut(button_press.source.entity()) else {
+            continue;
+        };
+
+        let mut letters = match database.load_mail(&player.id) {
+            Ok(letters) => letters,
+            Err(err) => {
+                error!("Failed to load the mail of '{}': {}", player.username, err);
+                net.send_one(
+                    button_press.source,
+                    messages::ChatMessageServer::translated("mail.failed", vec![]),
+                );
+                continue;
+            }
+        };
+
+        let mut inventory_full = false;
+        for letter in letters.iter_mut() {
+            let Some(item_stack) = letter.item_stack.as_mut() else {
+                continue;
+            };
+
+            let size = item_stack.size();
+            insert_into_inventory(&mut inventory, item_stack);
+            if item_stack.size() == size {
+                inventory_full = true;
+                continue;
+            }
+
+            // The items are already in the inventory at this point. If the letter can't be
+            // updated they will be duplicated, which is preferred to them being lost.
+            let result = if item_stack.is_empty() {
+                letter.item_stack = None;
+                database.delete_mail(letter.id)
+            } else {
+                inventory_full = true;
+                database.update_mail_item(letter.id, Some(item_stack))
+            };
+
+            if let Err(err) = result {
+                error!(
+                    "Failed to update a collected letter of '{}': {}",
+                    player.username, err
+                );
+            }
+        }
+
+        if inventory_full {
+            net.send_one(
+                button_press.source,
+                messages::ChatMessageServer::translated("mail.inventory_full", vec![]),
+            );
+        }
+
+        net.send_one(button_press.source, build_mailbox(&letters));
+    }
+}
@@ -1,41 +1,78 @@
 use bevy::{
+    core::FrameCount,
     math::{DQuat, DVec3},
     prelude::*,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer, ServerNetworkEvent};
 
 mod actions;
+mod afk;
+mod combat;
+mod cutscene;
+mod emotes;
 mod health;
 mod inventory;
+mod mail;
 mod player;
+mod quests;
+mod reach;
+mod rename;
+mod starter_kit;
+mod status_effects;
+mod teams;
+mod transfer;
 
 // TODO: Impl save/load for database in player module to not leak.
-pub use player::{Player, PlayerSave};
+pub use afk::Afk;
+pub use combat::CombatTag;
+pub use cutscene::{InCutscene, PlayCutscene, StopCutscene};
+pub use health::{DamageEvent, DamageSource, DeathEvent};
+pub use inventory::HeldItemStack;
+pub use mail::Letter;
+pub use player::{Camera, EquippedItem, Health, Player, PlayerSave};
+pub use quests::{QuestAction, QuestProgress, QuestTrigger};
+pub use reach::Reach;
+pub use status_effects::{StatusEffect, StatusEffects};
+pub use teams::{Team, TeamMember, Teams};
 
 use crate::{
     bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
     constants::CHUNK_SIZE,
     database::Database,
     physics::{shapes::Aabb, Velocity},
+    settings::Settings,
     utils,
     world::{
         blocks::Blocks,
-        models::{Model, ModelBundle, ModelVisibility, Models},
+        items::ItemStorage,
+        models::{Model, ModelBundle, ModelVisibility, Models, NameTag},
         world_map::{chunk::Chunk, terrain_generation::TerrainGenerator},
         WorldProperties,
     },
 };
 
-use self::player::Camera;
-
 pub struct PlayersPlugin;
 impl Plugin for PlayersPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<RespawnEvent>()
+            .add_event::<actions::Swing>()
             .add_plugins(inventory::InventoryPlugin)
             .add_plugins(health::HealthPlugin)
+            .add_plugins(status_effects::StatusEffectPlugin)
+            .add_plugins(afk::AfkPlugin)
+            .add_plugins(starter_kit::StarterKitPlugin)
+            .add_plugins(transfer::TransferPlugin)
+            .add_plugins(rename::RenamePlugin)
+            .add_plugins(mail::MailPlugin)
+            .add_plugins(reach::ReachPlugin)
+            .add_plugins(combat::CombatPlugin)
+            .add_plugins(cutscene::CutscenePlugin)
+            .add_plugins(quests::QuestPlugin)
+            .add_plugins(teams::TeamPlugin)
+            .add_plugins(emotes::EmotePlugin)
             .add_systems(
                 Update,
                 (
@@ -46,6 +83,8 @@ impl Plugin for PlayersPlugin {
                     handle_player_position_updates,
                     handle_player_rotation_updates,
                     actions::handle_left_clicks,
+                    actions::swing,
+                    actions::break_blocks,
                     actions::handle_right_clicks,
                 ),
             )
@@ -55,27 +94,66 @@ impl Plugin for PlayersPlugin {
 
 fn add_and_remove_players(
     mut commands: Commands,
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
     database: Res<Database>,
     player_query: Query<(Option<&Player>, &ConnectionId)>,
+    save_query: Query<(
+        &F64Transform,
+        &Camera,
+        &ItemStorage,
+        &player::Equipment,
+        &player::Health,
+        &StatusEffects,
+        Option<&CombatTag>,
+    )>,
     mut network_events: EventReader<ServerNetworkEvent>,
 ) {
     for event in network_events.read() {
         match event {
-            ServerNetworkEvent::Connected { entity, username } => {
-                let player_bundle = if let Some(player_save) = database.load_player(username) {
-                    player_save.into()
-                } else {
-                    player::PlayerBundle::default()
-                };
+            ServerNetworkEvent::Connected {
+                entity,
+                username,
+                player_id,
+            } => {
+                let (_, connection_id) = player_query.get(*entity).unwrap();
 
+                // The Player is inserted even if the save can't be loaded, so the disconnect is
+                // handled like for any other player.
                 commands.entity(*entity).insert((
                     Player {
+                        id: player_id.to_owned(),
                         username: username.to_owned(),
                     },
-                    player_bundle,
+                    actions::LeftClickState::default(),
+                    reach::Reach::default(),
+                    TeamMember::default(),
+                    emotes::EmoteState::default(),
                 ));
 
-                let (_, connection_id) = player_query.get(*entity).unwrap();
+                let player_bundle = match database.load_player(player_id) {
+                    Ok(Some(player_save)) => player_save.into(),
+                    Ok(None) => {
+                        commands.entity(*entity).insert(starter_kit::FirstJoin);
+                        player::PlayerBundle::default()
+                    }
+                    Err(err) => {
+                        // Giving them a fresh player would lose the save they already have.
+                        error!("Failed to load the player '{}': {}", username, err);
+                        net.send_one(
+                            *connection_id,
+                            messages::Disconnect {
+                                message: "disconnect.database_error".to_owned(),
+                                message_args: Some(Vec::new()),
+                            },
+                        );
+                        net.disconnect(*connection_id);
+                        continue;
+                    }
+                };
+
+                commands.entity(*entity).insert(player_bundle);
+
                 info!(
                     "Player connected, id: {}, username: {}",
                     connection_id, username
@@ -83,11 +161,42 @@ fn add_and_remove_players(
             }
             ServerNetworkEvent::Disconnected { entity } => {
                 let (player, connection_id) = player_query.get(*entity).unwrap();
+                let player = player.unwrap();
                 info!(
                     "Player disconnected, ip: {}, username: {}",
-                    connection_id,
-                    player.unwrap().username
+                    connection_id, player.username
                 );
+
+                // Not there if the save failed to load, it would be overwritten.
+                let Ok((
+                    transform,
+                    camera,
+                    inventory,
+                    equipment,
+                    health,
+                    status_effects,
+                    combat_tag,
+                )) = save_query.get(*entity)
+                else {
+                    continue;
+                };
+
+                // The stand-in saves the player when it is gone.
+                if combat::leaves_stand_in(&settings, combat_tag) {
+                    continue;
+                }
+
+                let save = PlayerSave::new(
+                    transform,
+                    camera,
+                    inventory,
+                    equipment,
+                    health,
+                    status_effects,
+                );
+                if let Err(err) = database.save_player(&player.id, &save) {
+                    error!("Failed to save the player '{}': {}", player.username, err);
+                }
             }
             _ => {}
         }
@@ -96,6 +205,8 @@ fn add_and_remove_players(
 
 fn send_player_configuration(
     net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    tick: Res<FrameCount>,
     player_query: Query<(&ConnectionId, &Aabb, &Camera, &F64Transform), Added<Player>>,
 ) {
     for (connection, aabb, camera, transform) in player_query.iter() {
@@ -104,12 +215,14 @@ fn send_player_configuration(
             messages::PlayerConfiguration {
                 aabb_dimensions: aabb.half_extents.as_vec3() * 2.0,
                 camera_position: camera.translation.as_vec3(),
+                swing_interval: Duration::from_millis(settings.swing_interval as u64),
             },
         );
 
         net.send_one(
             *connection,
             messages::PlayerPosition {
+                tick: tick.0,
                 position: transform.translation,
                 velocity: DVec3::ZERO,
             },
@@ -127,21 +240,29 @@ fn send_player_configuration(
 fn add_player_model(
     mut commands: Commands,
     models: Res<Models>,
-    player_query: Query<(Entity, &Camera), Added<Player>>,
+    player_query: Query<(Entity, &Player, &Camera), Added<Player>>,
 ) {
-    for (entity, camera) in player_query.iter() {
+    for (entity, player, camera) in player_query.iter() {
         commands.entity(entity).with_children(|parent| {
-            parent.spawn(ModelBundle {
-                model: Model::new(models.get_id("player")),
-                visibility: ModelVisibility::default(),
-                global_transform: F64GlobalTransform::default(),
-                transform: F64Transform {
-                    //translation: player_bundle.camera.translation - player_bundle.camera.translation.y,
-                    translation: DVec3::Z * 0.3 + DVec3::X * 0.3,
-                    rotation: camera.rotation,
-                    ..default()
+            parent.spawn((
+                ModelBundle {
+                    model: Model::new(models.get_id("player")),
+                    visibility: ModelVisibility::default(),
+                    global_transform: F64GlobalTransform::default(),
+                    transform: F64Transform {
+                        //translation: player_bundle.camera.translation - player_bundle.camera.translation.y,
+                        translation: DVec3::Z * 0.3 + DVec3::X * 0.3,
+                        rotation: camera.rotation,
+                        ..default()
+                    },
+                },
+                // The color and who sees it from afar is decided by the player's team.
+                NameTag {
+                    name: player.username.clone(),
+                    color: "#ffffff".to_owned(),
+                    always_visible_to: HashSet::new(),
                 },
-            });
+            ));
         });
     }
 }
@@ -208,6 +329,7 @@ fn respawn_players(
     world_properties: Res<WorldProperties>,
     terrain_generator: Res<TerrainGenerator>,
     database: Res<Database>,
+    tick: Res<FrameCount>,
     mut respawn_events: EventReader<RespawnEvent>,
     connection_query: Query<&ConnectionId>,
 ) {
@@ -265,6 +387,7 @@ fn respawn_players(
         net.send_one(
             *connection_id,
             messages::PlayerPosition {
+                tick: tick.0,
                 position: spawn_position.as_dvec3(),
                 velocity: DVec3::ZERO,
             },
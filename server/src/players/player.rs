@@ -12,8 +12,13 @@ use crate::{
     world::items::{crafting::CraftingTable, ItemStack, ItemStorage},
 };
 
+use super::status_effects::StatusEffects;
+
 #[derive(Component, Default)]
 pub struct Player {
+    /// Stays the same for as long as the player exists, saves are stored by it.
+    pub id: String,
+    /// The name the player is shown by, it can be changed.
     pub username: String,
 }
 
@@ -32,13 +37,13 @@ impl Default for Camera {
 }
 
 /// Helmet, chestplate, leggings, boots in order
-#[derive(Component, Default, Deref, DerefMut, Serialize, Deserialize)]
+#[derive(Component, Default, Clone, Deref, DerefMut, Serialize, Deserialize)]
 pub struct Equipment([ItemStack; 4]);
 
 #[derive(Component, Default, Serialize, Deserialize)]
 pub struct EquippedItem(pub usize);
 
-#[derive(Component, Default, Serialize, Deserialize)]
+#[derive(Component, Default, Clone, Serialize, Deserialize)]
 pub struct Health {
     pub hearts: u32,
     pub max: u32,
@@ -71,7 +76,7 @@ impl Health {
 }
 
 #[derive(Component)]
-enum GameMode {
+pub enum GameMode {
     Survival,
     Creative,
 }
@@ -94,6 +99,7 @@ pub struct PlayerBundle {
     health: Health,
     aabb: Aabb,
     gamemode: GameMode,
+    status_effects: StatusEffects,
 }
 
 impl Default for PlayerBundle {
@@ -113,6 +119,7 @@ impl Default for PlayerBundle {
             },
             aabb: Aabb::from_min_max(DVec3::ZERO, DVec3::new(0.6, 1.8, 0.6)),
             gamemode: GameMode::Survival,
+            status_effects: StatusEffects::default(),
         }
     }
 }
@@ -125,6 +132,45 @@ pub struct PlayerSave {
     inventory: ItemStorage,
     equipment: Equipment,
     health: Health,
+    #[serde(default)]
+    status_effects: StatusEffects,
+}
+
+impl PlayerSave {
+    pub fn new(
+        transform: &F64Transform,
+        camera: &Camera,
+        inventory: &ItemStorage,
+        equipment: &Equipment,
+        health: &Health,
+        status_effects: &StatusEffects,
+    ) -> Self {
+        return Self {
+            position: transform.translation,
+            camera_rotation: camera.rotation,
+            inventory: inventory.clone(),
+            equipment: equipment.clone(),
+            health: health.clone(),
+            status_effects: status_effects.clone(),
+        };
+    }
+
+    /// All the item stacks the player holds, in the inventory and equipment.
+    pub fn item_stacks_mut(&mut self) -> impl Iterator<Item = &mut ItemStack> {
+        return self.inventory.iter_mut().chain(self.equipment.iter_mut());
+    }
+
+    pub fn health_mut(&mut self) -> &mut Health {
+        return &mut self.health;
+    }
+
+    /// Makes the player respawn with full health the next time they join, like they do after
+    /// dying. The items they hold are kept.
+    pub fn respawn(&mut self) {
+        self.position = DVec3::ZERO;
+        self.health.hearts = self.health.max;
+        self.status_effects = StatusEffects::default();
+    }
 }
 
 impl From<PlayerSave> for PlayerBundle {
@@ -138,6 +184,7 @@ impl From<PlayerSave> for PlayerBundle {
             inventory: save.inventory,
             equipment: save.equipment,
             health: save.health,
+            status_effects: save.status_effects,
             // TODO: Remember equipped and send to player
             aabb: Aabb::from_min_max(DVec3::ZERO, DVec3::new(0.6, 1.8, 0.6)),
             ..default()
@@ -0,0 +1,749 @@
+use std::collections::HashMap;
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, BlockId, ConnectionId, NetworkData, NetworkServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bevy_extensions::f64_transform::F64GlobalTransform,
+    chat::CHAT_TEXT_COLOR,
+    database::Database,
+    settings::Settings,
+    world::{
+        blocks::Blocks,
+        items::{spawn_dropped_item, Item, ItemId, ItemStack, ItemStorage, Items},
+        models::Models,
+    },
+};
+
+use super::{mail::insert_into_inventory, Player};
+
+const QUEST_PATH: &str = "./resources/server/quests/";
+
+const QUEST_LOG_PATH: &str = "quests/log";
+const QUEST_TRACKER_PATH: &str = "quest_tracker";
+
+const LOG_FONT_SIZE: f32 = 8.0;
+const ACTIVE_COLOR: &str = "#ffff55";
+const COMPLETED_COLOR: &str = "#aaaaaa";
+
+// Quests are defined by the json files in the quest directory, the name of the file is the name of
+// the quest. A quest is a list of objectives that are counted by what players do, e.g. breaking
+// blocks or picking up items. When all of them are done the rewards are given to the player.
+//
+// Quests that are marked 'start_on_join' are started for all players, the others are granted by
+// operators with '/quest grant <name> <quest>', or by other parts of the server. Players see their
+// quests in the quest log, and the one they worked on last in the tracker on their HUD. They can
+// choose which is tracked with '/quest track <quest>'.
+pub struct QuestPlugin;
+impl Plugin for QuestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<QuestTrigger>()
+            .add_systems(Startup, load_quests)
+            .add_systems(
+                Update,
+                (
+                    load_quest_logs,
+                    show_quests_on_join,
+                    handle_quest_commands,
+                    progress_quests,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Something a player did that can count towards the objectives of their quests.
+#[derive(Event)]
+pub struct QuestTrigger {
+    pub player_entity: Entity,
+    pub action: QuestAction,
+    /// How many times it was done.
+    pub amount: u32,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum QuestAction {
+    BreakBlock(BlockId),
+    PlaceBlock(BlockId),
+    CollectItem(ItemId),
+    /// Anything else, named by the part of the server that sends it.
+    Custom(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuestJson {
+    title: String,
+    #[serde(default)]
+    description: String,
+    objectives: Vec<ObjectiveJson>,
+    #[serde(default)]
+    rewards: Vec<RewardJson>,
+    #[serde(default)]
+    start_on_join: bool,
+}
+
+#[derive(Deserialize)]
+struct ObjectiveJson {
+    description: String,
+    #[serde(default = "default_count")]
+    count: u32,
+    #[serde(flatten)]
+    action: ActionJson,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ActionJson {
+    BreakBlock { block: String },
+    PlaceBlock { block: String },
+    CollectItem { item: String },
+    Custom { name: String },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RewardJson {
+    item: String,
+    #[serde(default = "default_count")]
+    count: u32,
+}
+
+fn default_count() -> u32 {
+    return 1;
+}
+
+struct Objective {
+    description: String,
+    action: QuestAction,
+    count: u32,
+}
+
+struct Quest {
+    title: String,
+    description: String,
+    objectives: Vec<Objective>,
+    rewards: Vec<(ItemId, u32)>,
+    start_on_join: bool,
+}
+
+/// The quest definitions, by name.
+#[derive(Resource, Deref)]
+struct Quests(HashMap<String, Quest>);
+
+/// How far a player has come in a quest.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct QuestProgress {
+    /// How many times each objective has been done, in the order they are defined.
+    pub objectives: Vec<u32>,
+    pub completed: bool,
+}
+
+impl QuestProgress {
+    fn new(quest: &Quest) -> Self {
+        return Self {
+            objectives: vec![0; quest.objectives.len()],
+            completed: false,
+        };
+    }
+}
+
+/// The quests a player has started. Players whose quests could not be loaded don't have it, their
+/// quests are left untouched until they can be.
+#[derive(Component, Default)]
+pub struct QuestLog {
+    quests: HashMap<String, QuestProgress>,
+    /// The quest shown in the tracker.
+    tracked: Option<String>,
+    /// How many lines the quest log interface has been filled with.
+    shown_lines: usize,
+}
+
+impl QuestLog {
+    fn is_active(&self, quest: &str) -> bool {
+        return self
+            .quests
+            .get(quest)
+            .is_some_and(|progress| !progress.completed);
+    }
+
+    // Tracks the first active quest if the tracked one is done.
+    fn track_next(&mut self) {
+        if self
+            .tracked
+            .as_ref()
+            .is_some_and(|quest| self.is_active(quest))
+        {
+            return;
+        }
+
+        let mut active: Vec<&String> = self
+            .quests
+            .iter()
+            .filter(|(_, progress)| !progress.completed)
+            .map(|(name, _)| name)
+            .collect();
+        active.sort();
+        self.tracked = active.first().map(|name| (*name).clone());
+    }
+}
+
+fn load_quests(mut commands: Commands, items: Res<Items>) {
+    let directory = match std::fs::read_dir(QUEST_PATH) {
+        Ok(d) => d,
+        Err(e) => panic!(
+            "Failed to read the quest directory at: {}\nError: {}",
+            QUEST_PATH, e
+        ),
+    };
+
+    let blocks = Blocks::get();
+    let block_id = |path: &std::path::Path, name: &str| -> BlockId {
+        if !blocks.contains_block(name) {
+            panic!(
+                "Invalid quest at '{}', there is no block named '{}'",
+                path.display(),
+                name
+            );
+        }
+        return blocks.get_id(name);
+    };
+    let item_id = |path: &std::path::Path, name: &str| -> ItemId {
+        match items.get_id(name) {
+            Some(id) => id,
+            None => panic!(
+                "Invalid quest at '{}', there is no item named '{}'",
+                path.display(),
+                name
+            ),
+        }
+    };
+
+    let mut quests = HashMap::new();
+
+    for dir_entry in directory {
+        let path = dir_entry.unwrap().path();
+
+        let file = match std::fs::File::open(&path) {
+            Ok(f) => f,
+            Err(e) => panic!("Failed to open quest at: {}\nError: {}", path.display(), e),
+        };
+
+        let json: QuestJson = match serde_json::from_reader(&file) {
+            Ok(q) => q,
+            Err(e) => panic!(
+                "Couldn't read quest from '{}'\nError: {}",
+                path.display(),
+                e
+            ),
+        };
+
+        if json.objectives.is_empty() {
+            panic!(
+                "Invalid quest at '{}', it has no objectives",
+                path.display()
+            );
+        }
+
+        let objectives = json
+            .objectives
+            .into_iter()
+            .map(|objective| Objective {
+                description: objective.description,
+                action: match objective.action {
+                    ActionJson::BreakBlock { block } => {
+                        QuestAction::BreakBlock(block_id(&path, &block))
+                    }
+                    ActionJson::PlaceBlock { block } => {
+                        QuestAction::PlaceBlock(block_id(&path, &block))
+                    }
+                    ActionJson::CollectItem { item } => {
+                        QuestAction::CollectItem(item_id(&path, &item))
+                    }
+                    ActionJson::Custom { name } => QuestAction::Custom(name),
+                },
+                count: objective.count,
+            })
+            .collect();
+
+        let rewards = json
+            .rewards
+            .iter()
+            .map(|reward| (item_id(&path, &reward.item), reward.count))
+            .collect();
+
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        quests.insert(
+            name,
+            Quest {
+                title: json.title,
+                description: json.description,
+                objectives,
+                rewards,
+                start_on_join: json.start_on_join,
+            },
+        );
+    }
+
+    commands.insert_resource(Quests(quests));
+}
+
+fn save_progress(database: &Database, player: &Player, quest: &str, progress: &QuestProgress) {
+    if let Err(err) = database.save_quest(&player.id, quest, progress) {
+        error!(
+            "Failed to save the quest '{}' of '{}': {}",
+            quest, player.username, err
+        );
+    }
+}
+
+fn load_quest_logs(
+    mut commands: Commands,
+    database: Res<Database>,
+    quests: Res<Quests>,
+    player_query: Query<(Entity, &Player), Added<Player>>,
+) {
+    for (entity, player) in player_query.iter() {
+        let mut quest_log = match database.load_quests(&player.id) {
+            Ok(progress) => QuestLog {
+                quests: progress,
+                ..default()
+            },
+            Err(err) => {
+                error!(
+                    "Failed to load the quests of '{}': {}",
+                    player.username, err
+                );
+                continue;
+            }
+        };
+
+        for (name, quest) in quests.iter() {
+            match quest_log.quests.get_mut(name) {
+                // Objectives may have been added or removed since it was saved.
+                Some(progress) => progress.objectives.resize(quest.objectives.len(), 0),
+                None if quest.start_on_join => {
+                    let progress = QuestProgress::new(quest);
+                    save_progress(&database, player, name, &progress);
+                    quest_log.quests.insert(name.clone(), progress);
+                }
+                None => (),
+            }
+        }
+
+        // Quests that have been removed from the server are kept in case they come back.
+        quest_log.quests.retain(|name, _| quests.contains_key(name));
+        quest_log.track_next();
+
+        commands.entity(entity).insert(quest_log);
+    }
+}
+
+// A piece of text in the quest log, translation keys are translated by the client.
+enum LogText {
+    Text(String, &'static str),
+    Translation(&'static str, &'static str),
+}
+
+// Fills the quest log with the player's quests, the active ones with their objectives.
+fn build_quest_log(quests: &Quests, quest_log: &mut QuestLog) -> messages::InterfaceTextBoxUpdate {
+    let mut names: Vec<&String> = quest_log.quests.keys().collect();
+    // Active quests first
+    names.sort_by_key(|name| (quest_log.quests[*name].completed, *name));
+
+    let mut lines: Vec<Vec<LogText>> = Vec::new();
+    for name in names {
+        let quest = &quests[name];
+        let progress = &quest_log.quests[name];
+
+        if progress.completed {
+            lines.push(vec![
+                LogText::Text(quest.title.clone(), COMPLETED_COLOR),
+                LogText::Translation("quest.log.completed", COMPLETED_COLOR),
+            ]);
+            continue;
+        }
+
+        lines.push(vec![LogText::Text(quest.title.clone(), ACTIVE_COLOR)]);
+
+        if !quest.description.is_empty() {
+            lines.push(vec![LogText::Text(
+                quest.description.clone(),
+                CHAT_TEXT_COLOR,
+            )]);
+        }
+
+        for (objective, done) in quest.objectives.iter().zip(progress.objectives.iter()) {
+            let color = if *done >= objective.count {
+                COMPLETED_COLOR
+            } else {
+                CHAT_TEXT_COLOR
+            };
+            lines.push(vec![LogText::Text(
+                format!(
+                    "  {} ({}/{})",
+                    objective.description,
+                    (*done).min(objective.count),
+                    objective.count
+                ),
+                color,
+            )]);
+        }
+    }
+
+    if lines.is_empty() {
+        lines.push(vec![LogText::Translation(
+            "quest.log.empty",
+            CHAT_TEXT_COLOR,
+        )]);
+    }
+
+    // Lines that were shown before are replaced, and the ones that are left over are blanked.
+    let mut log_update = messages::InterfaceTextBoxUpdate::new(QUEST_LOG_PATH);
+    let line_count = lines.len().max(quest_log.shown_lines);
+    let mut lines = lines.into_iter();
+    for index in 0..line_count {
+        let line = if index < quest_log.shown_lines {
+            log_update.change_line(index as i32)
+        } else {
+            log_update.prepend_line()
+        };
+
+        let sections = lines
+            .next()
+            .unwrap_or_else(|| vec![LogText::Text(String::new(), CHAT_TEXT_COLOR)]);
+        for section in sections {
+            match section {
+                LogText::Text(text, color) => line.with_text(text, LOG_FONT_SIZE, color),
+                LogText::Translation(key, color) => {
+                    line.with_translation(key, vec![], LOG_FONT_SIZE, color)
+                }
+            };
+        }
+    }
+    quest_log.shown_lines = line_count;
+
+    return log_update;
+}
+
+// Shows the tracked quest on the player's HUD through the tracker's variables, the tracker is
+// hidden when there is nothing to track.
+fn send_tracker(
+    net: &NetworkServer,
+    connection_id: ConnectionId,
+    quests: &Quests,
+    quest_log: &QuestLog,
+) {
+    let Some(name) = &quest_log.tracked else {
+        net.send_one(
+            connection_id,
+            messages::InterfaceClose {
+                interface_path: QUEST_TRACKER_PATH.to_owned(),
+            },
+        );
+        return;
+    };
+
+    let quest = &quests[name];
+    let progress = &quest_log.quests[name];
+
+    let mut done = 0;
+    let mut total = 0;
+    let mut next_objective = None;
+    for (objective, count) in quest.objectives.iter().zip(progress.objectives.iter()) {
+        done += (*count).min(objective.count);
+        total += objective.count;
+        if next_objective.is_none() && *count < objective.count {
+            next_objective = Some(format!(
+                "{} ({}/{})",
+                objective.description, count, objective.count
+            ));
+        }
+    }
+
+    let mut variables = messages::InterfaceVariableUpdate::default();
+    variables.set_text("quest_title", &quest.title);
+    variables.set_text("quest_objective", &next_objective.unwrap_or_default());
+    variables.set_number("quest_progress", done as f32 / total.max(1) as f32);
+
+    net.send_one(connection_id, variables);
+    net.send_one(
+        connection_id,
+        messages::InterfaceOpen {
+            interface_path: QUEST_TRACKER_PATH.to_owned(),
+        },
+    );
+}
+
+fn send_quest_interfaces(
+    net: &NetworkServer,
+    connection_id: ConnectionId,
+    quests: &Quests,
+    quest_log: &mut QuestLog,
+) {
+    net.send_one(connection_id, build_quest_log(quests, quest_log));
+    send_tracker(net, connection_id, quests, quest_log);
+}
+
+fn show_quests_on_join(
+    net: Res<NetworkServer>,
+    quests: Res<Quests>,
+    mut player_query: Query<&mut QuestLog>,
+    mut events: EventReader<NetworkData<messages::ClientFinishedLoading>>,
+) {
+    for event in events.read() {
+        let Ok(mut quest_log) = player_query.get_mut(event.source.entity()) else {
+            continue;
+        };
+
+        // It is a new interface on the client.
+        quest_log.shown_lines = 0;
+        send_quest_interfaces(&net, event.source, &quests, &mut quest_log);
+    }
+}
+
+fn handle_quest_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    quests: Res<Quests>,
+    mut player_query: Query<(&Player, &ConnectionId, Option<&mut QuestLog>)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/quest") {
+            continue;
+        }
+
+        let Ok((sender, ..)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+        let is_operator = settings.is_operator(&sender.id);
+
+        let reply = match (words.next(), words.next(), words.next(), words.next()) {
+            (None, ..) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::InterfaceOpen {
+                        interface_path: "quests".to_owned(),
+                    },
+                );
+                continue;
+            }
+            (Some("track"), Some(quest), None, _) => {
+                let Ok((_, _, Some(mut quest_log))) =
+                    player_query.get_mut(chat_message.source.entity())
+                else {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("quest.failed", vec![]),
+                    );
+                    continue;
+                };
+
+                if !quest_log.is_active(quest) {
+                    messages::ChatMessageServer::translated(
+                        "quest.not_active",
+                        vec![quest.to_owned()],
+                    )
+                } else {
+                    quest_log.tracked = Some(quest.to_owned());
+                    send_tracker(&net, chat_message.source, &quests, &quest_log);
+                    continue;
+                }
+            }
+            (Some("grant" | "reset"), ..) if !is_operator => {
+                messages::ChatMessageServer::translated("quest.not_operator", vec![])
+            }
+            (Some(command @ ("grant" | "reset")), Some(username), Some(quest), None) => {
+                let Some(quest_config) = quests.get(quest) else {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated(
+                            "quest.unknown",
+                            vec![quest.to_owned()],
+                        ),
+                    );
+                    continue;
+                };
+
+                let online_player = player_query
+                    .iter_mut()
+                    .find(|(player, ..)| player.username == username);
+
+                let player_id = match &online_player {
+                    Some((player, ..)) => player.id.clone(),
+                    None => match database.player_id(username) {
+                        Ok(Some(player_id)) => player_id,
+                        Ok(None) => {
+                            net.send_one(
+                                chat_message.source,
+                                messages::ChatMessageServer::translated(
+                                    "quest.unknown_player",
+                                    vec![username.to_owned()],
+                                ),
+                            );
+                            continue;
+                        }
+                        Err(err) => {
+                            error!("Failed to look up the player '{}': {}", username, err);
+                            net.send_one(
+                                chat_message.source,
+                                messages::ChatMessageServer::translated("quest.failed", vec![]),
+                            );
+                            continue;
+                        }
+                    },
+                };
+
+                // Granting a quest the player already has starts it over.
+                let progress = QuestProgress::new(quest_config);
+                let result = if command == "grant" {
+                    database.save_quest(&player_id, quest, &progress)
+                } else {
+                    database.delete_quest(&player_id, quest)
+                };
+
+                if let Err(err) = result {
+                    error!(
+                        "Failed to {} the quest '{}' of '{}': {}",
+                        command, quest, username, err
+                    );
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("quest.failed", vec![]),
+                    );
+                    continue;
+                }
+
+                if let Some((_, connection_id, Some(mut quest_log))) = online_player {
+                    if command == "grant" {
+                        quest_log.quests.insert(quest.to_owned(), progress);
+                        quest_log.tracked = Some(quest.to_owned());
+                        net.send_one(
+                            *connection_id,
+                            messages::ChatMessageServer::translated(
+                                "quest.started",
+                                vec![quest_config.title.clone()],
+                            ),
+                        );
+                    } else {
+                        quest_log.quests.remove(quest);
+                        quest_log.track_next();
+                    }
+                    send_quest_interfaces(&net, *connection_id, &quests, &mut quest_log);
+                }
+
+                messages::ChatMessageServer::translated(
+                    &format!("quest.{}.done", command),
+                    vec![quest.to_owned(), username.to_owned()],
+                )
+            }
+            _ if is_operator => {
+                messages::ChatMessageServer::translated("quest.usage.operator", vec![])
+            }
+            _ => messages::ChatMessageServer::translated("quest.usage", vec![]),
+        };
+
+        net.send_one(chat_message.source, reply);
+    }
+}
+
+fn progress_quests(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    quests: Res<Quests>,
+    items: Res<Items>,
+    models: Res<Models>,
+    mut player_query: Query<(
+        &Player,
+        &ConnectionId,
+        &F64GlobalTransform,
+        &mut QuestLog,
+        &mut ItemStorage,
+    )>,
+    mut trigger_events: EventReader<QuestTrigger>,
+) {
+    for trigger in trigger_events.read() {
+        let Ok((player, connection_id, transform, mut quest_log, mut inventory)) =
+            player_query.get_mut(trigger.player_entity)
+        else {
+            continue;
+        };
+
+        let mut changed = false;
+        let quest_log = quest_log.as_mut();
+        for (name, progress) in quest_log.quests.iter_mut() {
+            if progress.completed {
+                continue;
+            }
+
+            let quest = &quests[name];
+            let mut progressed = false;
+            for (objective, count) in quest.objectives.iter().zip(progress.objectives.iter_mut()) {
+                if objective.action == trigger.action && *count < objective.count {
+                    *count = (*count + trigger.amount).min(objective.count);
+                    progressed = true;
+                }
+            }
+
+            if !progressed {
+                continue;
+            }
+
+            changed = true;
+            quest_log.tracked = Some(name.clone());
+
+            progress.completed = quest
+                .objectives
+                .iter()
+                .zip(progress.objectives.iter())
+                .all(|(objective, count)| *count >= objective.count);
+
+            save_progress(&database, player, name, progress);
+
+            if !progress.completed {
+                continue;
+            }
+
+            // Rewards that don't fit in the inventory are dropped at the player's feet.
+            for (item_id, count) in quest.rewards.iter() {
+                let item_config = items.get_config(item_id);
+                let mut remaining = *count;
+                while remaining > 0 {
+                    let size = remaining.min(item_config.max_stack_size);
+                    remaining -= size;
+
+                    let mut item_stack =
+                        ItemStack::new(Item::new(*item_id), size, item_config.max_stack_size);
+                    insert_into_inventory(&mut inventory, &mut item_stack);
+                    if !item_stack.is_empty() {
+                        spawn_dropped_item(
+                            &mut commands,
+                            &items,
+                            &models,
+                            transform.translation() + DVec3::Y,
+                            item_stack,
+                        );
+                    }
+                }
+            }
+
+            net.send_one(
+                *connection_id,
+                messages::ChatMessageServer::translated(
+                    "quest.completed",
+                    vec![quest.title.clone()],
+                ),
+            );
+        }
+
+        if changed {
+            quest_log.track_next();
+            send_quest_interfaces(&net, *connection_id, &quests, quest_log);
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use bevy::{math::DVec3, prelude::*};
+
+use crate::{physics::shapes::Aabb, settings::Settings};
+
+use super::player::{GameMode, Player};
+
+// Violations are forgotten once a player has gone this long without one.
+const VIOLATION_RESET: Duration = Duration::from_secs(60);
+// Players are logged when they reach this many violations, and again every time it is reached
+// after that. A few are expected from lag, or from players walking away with an interface open.
+const LOGGED_VIOLATIONS: u32 = 5;
+
+pub struct ReachPlugin;
+impl Plugin for ReachPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, set_reach_distance);
+    }
+}
+
+/// How far a player can reach to interact with blocks and models, measured from the camera. The
+/// server raycasts clicks up to this distance, so they can't reach further. Targets that are
+/// remembered between messages, like the block of an open interface, have to be checked again
+/// with [Reach::check].
+#[derive(Component, Default)]
+pub struct Reach {
+    pub distance: f64,
+    violations: u32,
+    last_violation: Option<Instant>,
+}
+
+impl Reach {
+    /// Returns if the target is within reach of the camera. Interactions that are out of reach
+    /// should be rejected, they are counted and players that keep doing them are logged so they
+    /// can be looked into.
+    pub fn check(&mut self, player: &Player, camera_position: DVec3, target: &Aabb) -> bool {
+        let closest_point = camera_position.clamp(target.min(), target.max());
+        let distance = camera_position.distance(closest_point);
+        if distance <= self.distance {
+            return true;
+        }
+
+        let now = Instant::now();
+        if self
+            .last_violation
+            .is_some_and(|last_violation| now - last_violation > VIOLATION_RESET)
+        {
+            self.violations = 0;
+        }
+        self.violations += 1;
+        self.last_violation = Some(now);
+
+        if self.violations % LOGGED_VIOLATIONS == 0 {
+            warn!(
+                "Player '{}' has interacted out of reach {} times, the last at a distance of {:.1} blocks, the limit is {:.1}",
+                player.username, self.violations, distance, self.distance
+            );
+        }
+
+        return false;
+    }
+}
+
+fn set_reach_distance(
+    settings: Res<Settings>,
+    mut player_query: Query<(&GameMode, &mut Reach), Changed<GameMode>>,
+) {
+    for (gamemode, mut reach) in player_query.iter_mut() {
+        reach.distance = match gamemode {
+            GameMode::Survival => settings.reach,
+            GameMode::Creative => settings.creative_reach,
+        };
+    }
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData, NetworkServer, MAX_USERNAME_LENGTH};
+
+use crate::{
+    database::{Database, DatabaseError},
+    settings::Settings,
+};
+
+use super::Player;
+
+// Players can change their name with '/rename <name>'. Their save is kept by their id, so nothing
+// is lost. The new name must be used to log in the next time.
+pub struct RenamePlugin;
+impl Plugin for RenamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_rename_commands);
+    }
+}
+
+fn handle_rename_commands(
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    settings: Res<Settings>,
+    mut player_query: Query<&mut Player>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/rename") {
+            continue;
+        }
+
+        let (Some(new_name), None) = (words.next(), words.next()) else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("rename.usage", vec![]),
+            );
+            continue;
+        };
+
+        if new_name.len() > MAX_USERNAME_LENGTH {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(
+                    "rename.too_long",
+                    vec![MAX_USERNAME_LENGTH.to_string()],
+                ),
+            );
+            continue;
+        }
+
+        let Ok(mut player) = player_query.get_mut(chat_message.source.entity()) else {
+            continue;
+        };
+
+        // Operators are known by their id, but a name from the list would still look like one in
+        // the chat.
+        if settings.operators.iter().any(|name| name == new_name)
+            && !settings.is_operator(&player.id)
+        {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("rename.taken", vec![new_name.to_owned()]),
+            );
+            continue;
+        }
+
+        match database.rename_player(&player.id, new_name) {
+            Ok(()) => {
+                info!("Player '{}' renamed to '{}'", player.username, new_name);
+                net.broadcast(messages::ChatMessageServer::translated(
+                    "chat.player_renamed",
+                    vec![player.username.clone(), new_name.to_owned()],
+                ));
+                player.username = new_name.to_owned();
+            }
+            Err(DatabaseError::Rejected(_)) => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated(
+                        "rename.taken",
+                        vec![new_name.to_owned()],
+                    ),
+                );
+            }
+            Err(err) => {
+                error!("Failed to rename the player '{}': {}", player.username, err);
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("rename.failed", vec![]),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::F64Transform,
+    chat::{CHAT_FONT_SIZE, CHAT_TEXT_COLOR},
+    settings::Settings,
+    world::items::{Item, ItemId, ItemStack, ItemStorage, Items},
+};
+
+use super::Player;
+
+// Players that join the server for the first time are given the items of the starter kit, and
+// are welcomed in the chat once their client has finished loading. If a tutorial spawn is
+// configured they are placed there instead of at the world spawn.
+pub struct StarterKitPlugin;
+impl Plugin for StarterKitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_starter_kit).add_systems(
+            Update,
+            (
+                give_starter_kit
+                    .before(super::respawn_new_players)
+                    .before(super::send_player_configuration),
+                welcome_new_players,
+            ),
+        );
+    }
+}
+
+/// Marks a player that has no save, it is removed after they have been welcomed.
+#[derive(Component)]
+pub struct FirstJoin;
+
+#[derive(Resource)]
+struct StarterKit(Vec<(ItemId, u32)>);
+
+fn load_starter_kit(mut commands: Commands, settings: Res<Settings>, items: Res<Items>) {
+    let kit = settings
+        .starter_kit
+        .iter()
+        .map(|(name, count)| {
+            let Some(item_id) = items.get_id(name) else {
+                panic!(
+                    "Server property 'starter-kit' is misconfigured, there is no item named '{}'",
+                    name
+                );
+            };
+            (item_id, *count)
+        })
+        .collect();
+
+    commands.insert_resource(StarterKit(kit));
+}
+
+fn give_starter_kit(
+    settings: Res<Settings>,
+    items: Res<Items>,
+    starter_kit: Res<StarterKit>,
+    mut player_query: Query<(&mut ItemStorage, &mut F64Transform), Added<FirstJoin>>,
+) {
+    for (mut inventory, mut transform) in player_query.iter_mut() {
+        // Moving the player before it has been spawned stops it from being sent to the world
+        // spawn, and the position is sent along with the player configuration.
+        if let Some(tutorial_spawn) = settings.tutorial_spawn {
+            transform.translation = tutorial_spawn.as_dvec3();
+        }
+
+        let mut slots = inventory.iter_mut().filter(|stack| stack.is_empty());
+
+        'kit: for (item_id, count) in starter_kit.0.iter() {
+            let max_stack_size = items.get_config(item_id).max_stack_size;
+            let mut remaining = *count;
+
+            while remaining > 0 {
+                let Some(slot) = slots.next() else {
+                    warn!("The starter kit has more items than fits in the inventory");
+                    break 'kit;
+                };
+
+                let size = remaining.min(max_stack_size);
+                *slot = ItemStack::new(Item::new(*item_id), size, max_stack_size);
+                remaining -= size;
+            }
+        }
+    }
+}
+
+fn welcome_new_players(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    player_query: Query<&Player, With<FirstJoin>>,
+    mut events: EventReader<NetworkData<messages::ClientFinishedLoading>>,
+) {
+    for event in events.read() {
+        let entity = event.source.entity();
+        let Ok(player) = player_query.get(entity) else {
+            continue;
+        };
+
+        let mut chat_update = messages::InterfaceTextBoxUpdate::new("chat/history");
+        if let Some(welcome_message) = &settings.welcome_message {
+            chat_update.append_line().with_text(
+                welcome_message.replace("{}", &player.username),
+                CHAT_FONT_SIZE,
+                CHAT_TEXT_COLOR,
+            );
+        } else {
+            chat_update.append_line().with_translation(
+                "chat.welcome",
+                vec![player.username.clone()],
+                CHAT_FONT_SIZE,
+                CHAT_TEXT_COLOR,
+            );
+        }
+        net.send_one(event.source, chat_update);
+
+        commands.entity(entity).remove::<FirstJoin>();
+    }
+}
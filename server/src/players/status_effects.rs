@@ -0,0 +1,94 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::health::HealEvent;
+
+// How often regeneration heals a heart
+const REGENERATION_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct StatusEffectPlugin;
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (tick_status_effects, regenerate_health));
+    }
+}
+
+/// Temporary effects that change how the player interacts with the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StatusEffect {
+    /// Heals the player over time.
+    Regeneration,
+    /// Blocks break faster.
+    Haste,
+}
+
+impl StatusEffect {
+    pub const ALL: [StatusEffect; 2] = [StatusEffect::Regeneration, StatusEffect::Haste];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Regeneration => "regeneration",
+            Self::Haste => "haste",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return Self::ALL.into_iter().find(|effect| effect.name() == name);
+    }
+}
+
+/// The status effects that currently affect a player, and how much longer they last.
+#[derive(Component, Default, Clone, Serialize, Deserialize)]
+pub struct StatusEffects {
+    active: HashMap<StatusEffect, Duration>,
+}
+
+impl StatusEffects {
+    /// Apply the effect for the given duration. If the effect is already active it lasts for
+    /// whichever is the longest of the remaining and the new duration.
+    pub fn apply(&mut self, effect: StatusEffect, duration: Duration) {
+        let remaining = self.active.entry(effect).or_default();
+        *remaining = (*remaining).max(duration);
+    }
+
+    pub fn has(&self, effect: StatusEffect) -> bool {
+        return self.active.contains_key(&effect);
+    }
+}
+
+fn tick_status_effects(time: Res<Time>, mut status_effects_query: Query<&mut StatusEffects>) {
+    for mut status_effects in status_effects_query.iter_mut() {
+        if status_effects.active.is_empty() {
+            continue;
+        }
+
+        status_effects.active.retain(|_, remaining| {
+            *remaining = remaining.saturating_sub(time.delta());
+            !remaining.is_zero()
+        });
+    }
+}
+
+fn regenerate_health(
+    time: Res<Time>,
+    status_effects_query: Query<(Entity, &StatusEffects)>,
+    mut heal_events: EventWriter<HealEvent>,
+    mut timer: Local<Timer>,
+) {
+    if timer.duration().is_zero() {
+        *timer = Timer::new(REGENERATION_INTERVAL, TimerMode::Repeating);
+    }
+
+    timer.tick(time.delta());
+    if !timer.just_finished() {
+        return;
+    }
+
+    for (entity, status_effects) in status_effects_query.iter() {
+        if status_effects.has(StatusEffect::Regeneration) {
+            heal_events.send(HealEvent { entity, healing: 1 });
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData, NetworkServer};
+
+use crate::{database::Database, settings::Settings, world::models::NameTag};
+
+use super::{Afk, Player};
+
+// Color of the names of players that aren't on a team.
+const DEFAULT_NAME_COLOR: &str = "#ffffff";
+// Added after the names of players that are AFK.
+const AFK_SUFFIX: &str = " (AFK)";
+
+// Operators can put players on teams with the '/team' command. The names of the members are shown
+// in the color of their team, and the team decides if its members can hurt each other (friendly
+// fire), and if they can see each other's name tags from any distance (shared visibility).
+//
+// Teams and who is on them are stored in the database, so players stay on their team when they
+// leave.
+pub struct TeamPlugin;
+impl Plugin for TeamPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, load_teams).add_systems(
+            Update,
+            (load_team_members, handle_team_commands, update_name_tags).chain(),
+        );
+    }
+}
+
+#[derive(Clone)]
+pub struct Team {
+    /// Color of the names of the members, as a hex string.
+    pub color: String,
+    /// If the members can hurt each other.
+    pub friendly_fire: bool,
+    /// If the members see each other's name tags however far away they are.
+    pub shared_visibility: bool,
+}
+
+impl Default for Team {
+    fn default() -> Self {
+        return Self {
+            color: DEFAULT_NAME_COLOR.to_owned(),
+            friendly_fire: false,
+            shared_visibility: true,
+        };
+    }
+}
+
+/// All the teams, by name.
+#[derive(Resource, Default)]
+pub struct Teams(HashMap<String, Team>);
+
+impl Teams {
+    /// If the two players are on the same team. Players that aren't on a team have no allies.
+    pub fn are_allies(&self, first: &TeamMember, second: &TeamMember) -> bool {
+        return first.team.is_some() && first.team == second.team;
+    }
+
+    /// If the attacker is allowed to hurt the victim.
+    pub fn can_hurt(&self, attacker: &TeamMember, victim: &TeamMember) -> bool {
+        if !self.are_allies(attacker, victim) {
+            return true;
+        }
+
+        return attacker
+            .team
+            .as_ref()
+            .and_then(|team| self.0.get(team))
+            .map_or(true, |team| team.friendly_fire);
+    }
+}
+
+/// The team a player is on.
+#[derive(Component, Default)]
+pub struct TeamMember {
+    pub team: Option<String>,
+}
+
+fn load_teams(mut commands: Commands, database: Res<Database>) {
+    let teams = match database.load_teams() {
+        Ok(teams) => teams,
+        Err(err) => panic!("Failed to load the teams from the database: {}", err),
+    };
+    commands.insert_resource(Teams(teams));
+}
+
+fn load_team_members(
+    database: Res<Database>,
+    teams: Res<Teams>,
+    mut player_query: Query<(&Player, &mut TeamMember), Added<Player>>,
+) {
+    for (player, mut team_member) in player_query.iter_mut() {
+        match database.player_team(&player.id) {
+            // Teams that no longer exist are ignored, the player will not be on any team.
+            Ok(team) => team_member.team = team.filter(|team| teams.0.contains_key(team)),
+            Err(err) => error!("Failed to load the team of '{}': {}", player.username, err),
+        }
+    }
+}
+
+// Colors are written as '#rrggbb'.
+fn is_color(color: &str) -> bool {
+    return color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+}
+
+fn handle_team_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    mut teams: ResMut<Teams>,
+    mut player_query: Query<(&Player, &mut TeamMember)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/team") {
+            continue;
+        }
+
+        let Ok((sender, team_member)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        let subcommand = words.next();
+        match subcommand {
+            None => {
+                let reply = match &team_member.team {
+                    Some(team) => {
+                        messages::ChatMessageServer::translated("team.current", vec![team.clone()])
+                    }
+                    None => messages::ChatMessageServer::translated("team.none", vec![]),
+                };
+                net.send_one(chat_message.source, reply);
+                continue;
+            }
+            Some("list") => {
+                let mut names: Vec<&String> = teams.0.keys().collect();
+                names.sort();
+                let reply = if names.is_empty() {
+                    messages::ChatMessageServer::translated("team.list.empty", vec![])
+                } else {
+                    let names: Vec<&str> = names.into_iter().map(String::as_str).collect();
+                    messages::ChatMessageServer::translated("team.list", vec![names.join(", ")])
+                };
+                net.send_one(chat_message.source, reply);
+                continue;
+            }
+            _ => (),
+        }
+
+        if !settings.is_operator(&sender.id) {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("team.not_operator", vec![]),
+            );
+            continue;
+        }
+
+        let reply = match (subcommand, words.next(), words.next(), words.next()) {
+            (Some("create"), Some(name), color, None) => {
+                let color = color.unwrap_or(DEFAULT_NAME_COLOR);
+                if teams.0.contains_key(name) {
+                    messages::ChatMessageServer::translated("team.exists", vec![name.to_owned()])
+                } else if !is_color(color) {
+                    messages::ChatMessageServer::translated(
+                        "team.invalid_color",
+                        vec![color.to_owned()],
+                    )
+                } else {
+                    let team = Team {
+                        color: color.to_owned(),
+                        ..default()
+                    };
+                    match database.save_team(name, &team) {
+                        Ok(()) => {
+                            teams.0.insert(name.to_owned(), team);
+                            messages::ChatMessageServer::translated(
+                                "team.create.done",
+                                vec![name.to_owned()],
+                            )
+                        }
+                        Err(err) => {
+                            error!("Failed to create the team '{}': {}", name, err);
+                            messages::ChatMessageServer::translated("team.failed", vec![])
+                        }
+                    }
+                }
+            }
+            (Some("delete"), Some(name), None, None) => {
+                if !teams.0.contains_key(name) {
+                    messages::ChatMessageServer::translated("team.unknown", vec![name.to_owned()])
+                } else {
+                    match database.delete_team(name) {
+                        Ok(()) => {
+                            teams.0.remove(name);
+                            for (_, mut team_member) in player_query.iter_mut() {
+                                if team_member.team.as_deref() == Some(name) {
+                                    team_member.team = None;
+                                }
+                            }
+                            messages::ChatMessageServer::translated(
+                                "team.delete.done",
+                                vec![name.to_owned()],
+                            )
+                        }
+                        Err(err) => {
+                            error!("Failed to delete the team '{}': {}", name, err);
+                            messages::ChatMessageServer::translated("team.failed", vec![])
+                        }
+                    }
+                }
+            }
+            (Some("add"), Some(name), Some(username), None) => {
+                if !teams.0.contains_key(name) {
+                    messages::ChatMessageServer::translated("team.unknown", vec![name.to_owned()])
+                } else {
+                    set_team(&database, &mut player_query, username, Some(name))
+                }
+            }
+            (Some("remove"), Some(username), None, None) => {
+                set_team(&database, &mut player_query, username, None)
+            }
+            (Some("set"), Some(name), Some(rule), Some(value)) => {
+                let Some(team) = teams.0.get(name) else {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated(
+                            "team.unknown",
+                            vec![name.to_owned()],
+                        ),
+                    );
+                    continue;
+                };
+
+                let mut team = team.clone();
+                let valid = match (rule, value.parse::<bool>()) {
+                    ("color", _) if is_color(value) => {
+                        team.color = value.to_owned();
+                        true
+                    }
+                    ("friendly-fire", Ok(value)) => {
+                        team.friendly_fire = value;
+                        true
+                    }
+                    ("shared-visibility", Ok(value)) => {
+                        team.shared_visibility = value;
+                        true
+                    }
+                    _ => false,
+                };
+
+                if !valid {
+                    messages::ChatMessageServer::translated("team.set.usage", vec![])
+                } else {
+                    match database.save_team(name, &team) {
+                        Ok(()) => {
+                            teams.0.insert(name.to_owned(), team);
+                            messages::ChatMessageServer::translated(
+                                "team.set.done",
+                                vec![rule.to_owned(), name.to_owned(), value.to_owned()],
+                            )
+                        }
+                        Err(err) => {
+                            error!("Failed to change the team '{}': {}", name, err);
+                            messages::ChatMessageServer::translated("team.failed", vec![])
+                        }
+                    }
+                }
+            }
+            _ => messages::ChatMessageServer::translated("team.usage", vec![]),
+        };
+
+        net.send_one(chat_message.source, reply);
+    }
+}
+
+// Players that are offline are put on the team in the database, they join it when they log in.
+fn set_team(
+    database: &Database,
+    player_query: &mut Query<(&Player, &mut TeamMember)>,
+    username: &str,
+    team: Option<&str>,
+) -> messages::ChatMessageServer {
+    let online_player = player_query
+        .iter_mut()
+        .find(|(player, _)| player.username == username);
+
+    let player_id = match &online_player {
+        Some((player, _)) => player.id.clone(),
+        None => match database.player_id(username) {
+            Ok(Some(player_id)) => player_id,
+            Ok(None) => {
+                return messages::ChatMessageServer::translated(
+                    "team.unknown_player",
+                    vec![username.to_owned()],
+                )
+            }
+            Err(err) => {
+                error!("Failed to look up the player '{}': {}", username, err);
+                return messages::ChatMessageServer::translated("team.failed", vec![]);
+            }
+        },
+    };
+
+    if let Err(err) = database.set_player_team(&player_id, team) {
+        error!("Failed to change the team of '{}': {}", username, err);
+        return messages::ChatMessageServer::translated("team.failed", vec![]);
+    }
+
+    if let Some((_, mut team_member)) = online_player {
+        team_member.team = team.map(str::to_owned);
+    }
+
+    return match team {
+        Some(team) => messages::ChatMessageServer::translated(
+            "team.add.done",
+            vec![username.to_owned(), team.to_owned()],
+        ),
+        None => {
+            messages::ChatMessageServer::translated("team.remove.done", vec![username.to_owned()])
+        }
+    };
+}
+
+// The name tags of all players are updated when anyone changes teams, as it changes who their
+// teammates see from afar. Players that are AFK have it shown after their name.
+fn update_name_tags(
+    teams: Res<Teams>,
+    player_query: Query<(Entity, &Player, &TeamMember, &Children, Has<Afk>)>,
+    changed_query: Query<
+        (),
+        (
+            With<Player>,
+            Or<(
+                Changed<Player>,
+                Changed<TeamMember>,
+                Changed<Children>,
+                Added<Afk>,
+            )>,
+        ),
+    >,
+    mut name_tag_query: Query<&mut NameTag>,
+    mut removed_players: RemovedComponents<Player>,
+    mut removed_afk: RemovedComponents<Afk>,
+) {
+    let players_left = removed_players.read().count() > 0;
+    let players_returned = removed_afk.read().count() > 0;
+    if !teams.is_changed() && changed_query.is_empty() && !players_left && !players_returned {
+        return;
+    }
+
+    let mut members: HashMap<&str, HashSet<Entity>> = HashMap::new();
+    for (entity, _, team_member, _, _) in player_query.iter() {
+        if let Some(team) = &team_member.team {
+            members.entry(team).or_default().insert(entity);
+        }
+    }
+
+    for (entity, player, team_member, children, is_afk) in player_query.iter() {
+        let team = team_member
+            .team
+            .as_ref()
+            .and_then(|name| teams.0.get(name).map(|team| (name, team)));
+
+        let color = team.map_or(DEFAULT_NAME_COLOR, |(_, team)| team.color.as_str());
+        let mut always_visible_to = match team {
+            Some((name, team)) if team.shared_visibility => members[name.as_str()].clone(),
+            _ => HashSet::new(),
+        };
+        always_visible_to.remove(&entity);
+
+        let name = if is_afk {
+            player.username.clone() + AFK_SUFFIX
+        } else {
+            player.username.clone()
+        };
+
+        for child in children.iter() {
+            let Ok(mut name_tag) = name_tag_query.get_mut(*child) else {
+                continue;
+            };
+
+            // Only changed when needed, each change is sent to everyone that can see the model.
+            if name_tag.name != name
+                || name_tag.color != color
+                || name_tag.always_visible_to != always_visible_to
+            {
+                name_tag.name = name.clone();
+                name_tag.color = color.to_owned();
+                name_tag.always_visible_to = always_visible_to.clone();
+            }
+        }
+    }
+}
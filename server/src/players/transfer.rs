@@ -0,0 +1,219 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData, NetworkServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bevy_extensions::f64_transform::F64Transform,
+    database::Database,
+    settings::Settings,
+    world::items::{ItemId, ItemStorage, Items},
+};
+
+use super::{
+    player::{Equipment, Health},
+    Camera, Player, PlayerSave, StatusEffects,
+};
+
+// Where exported players are written.
+const EXPORT_DIRECTORY: &str = "./player_exports";
+
+// Operators can export a player's save to a json file with '/player export <name>', and import
+// it with '/player import <name> <file>'. This is meant for debugging and for moving players
+// between servers.
+//
+// Players that are online are exported as they are, offline players are exported from the
+// database. Players can only be imported while they are offline, the save is used the next time
+// they join. If there is no player with the name, an account is made for it that the first player
+// to log in with the name gets.
+pub struct TransferPlugin;
+impl Plugin for TransferPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_player_commands);
+    }
+}
+
+/// The exported file. Item ids are not the same on all servers, so the names of the items are
+/// included so they can be converted when imported.
+#[derive(Serialize, Deserialize)]
+struct PlayerExport {
+    item_ids: HashMap<String, ItemId>,
+    save: PlayerSave,
+}
+
+fn handle_player_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    items: Res<Items>,
+    player_query: Query<(
+        &Player,
+        &F64Transform,
+        &Camera,
+        &ItemStorage,
+        &Equipment,
+        &Health,
+        &StatusEffects,
+    )>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/player") {
+            continue;
+        }
+
+        let Ok((sender, ..)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        if !settings.is_operator(&sender.id) {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("player.not_operator", vec![]),
+            );
+            continue;
+        }
+
+        let reply = match (words.next(), words.next(), words.next()) {
+            (Some("export"), Some(username), None) => {
+                let online_player = player_query
+                    .iter()
+                    .find(|(player, ..)| player.username == username);
+
+                let save = match online_player {
+                    Some((_, transform, camera, inventory, equipment, health, status_effects)) => {
+                        Ok(Some(PlayerSave::new(
+                            transform,
+                            camera,
+                            inventory,
+                            equipment,
+                            health,
+                            status_effects,
+                        )))
+                    }
+                    None => match database.player_id(username) {
+                        Ok(Some(player_id)) => database
+                            .load_player(&player_id)
+                            .map_err(|err| err.to_string()),
+                        Ok(None) => Ok(None),
+                        Err(err) => Err(err.to_string()),
+                    },
+                };
+
+                match save {
+                    Ok(Some(save)) => match export_player(username, save, &items) {
+                        Ok(path) => messages::ChatMessageServer::translated(
+                            "player.export.done",
+                            vec![username.to_owned(), path.display().to_string()],
+                        ),
+                        Err(err) => messages::ChatMessageServer::translated(
+                            "player.export.failed",
+                            vec![err],
+                        ),
+                    },
+                    Ok(None) => messages::ChatMessageServer::translated(
+                        "player.not_found",
+                        vec![username.to_owned()],
+                    ),
+                    Err(err) => {
+                        messages::ChatMessageServer::translated("player.export.failed", vec![err])
+                    }
+                }
+            }
+            (Some("import"), Some(username), Some(file)) => {
+                if player_query
+                    .iter()
+                    .any(|(player, ..)| player.username == username)
+                {
+                    messages::ChatMessageServer::translated(
+                        "player.import.online",
+                        vec![username.to_owned()],
+                    )
+                } else {
+                    match import_player(username, file, &items, &database) {
+                        Ok(0) => messages::ChatMessageServer::translated(
+                            "player.import.done",
+                            vec![username.to_owned()],
+                        ),
+                        Ok(lost_stacks) => messages::ChatMessageServer::translated(
+                            "player.import.missing_items",
+                            vec![username.to_owned(), lost_stacks.to_string()],
+                        ),
+                        Err(err) => messages::ChatMessageServer::translated(
+                            "player.import.failed",
+                            vec![err],
+                        ),
+                    }
+                }
+            }
+            _ => messages::ChatMessageServer::translated("player.usage", vec![]),
+        };
+
+        net.send_one(chat_message.source, reply);
+    }
+}
+
+fn export_player(username: &str, save: PlayerSave, items: &Items) -> Result<PathBuf, String> {
+    let export = PlayerExport {
+        item_ids: items.clone_ids(),
+        save,
+    };
+
+    let json = serde_json::to_string_pretty(&export).map_err(|err| err.to_string())?;
+
+    // The name is used as the filename, it can't be allowed to point outside the directory.
+    if std::path::Path::new(username).file_name() != Some(username.as_ref()) {
+        return Err(format!("'{}' can't be used as a filename", username));
+    }
+
+    std::fs::create_dir_all(EXPORT_DIRECTORY).map_err(|err| err.to_string())?;
+    let path = PathBuf::from(EXPORT_DIRECTORY).join(username.to_owned() + ".json");
+    std::fs::write(&path, json).map_err(|err| err.to_string())?;
+
+    info!("Exported the player '{}' to '{}'", username, path.display());
+
+    return Ok(path);
+}
+
+// Returns how many item stacks were removed because the items don't exist on this server.
+fn import_player(
+    username: &str,
+    file: &str,
+    items: &Items,
+    database: &Database,
+) -> Result<usize, String> {
+    let json = std::fs::read_to_string(file).map_err(|err| err.to_string())?;
+    let PlayerExport { item_ids, mut save } =
+        serde_json::from_str(&json).map_err(|err| err.to_string())?;
+
+    let item_names: HashMap<ItemId, String> =
+        item_ids.into_iter().map(|(name, id)| (id, name)).collect();
+
+    let mut lost_stacks = 0;
+    for item_stack in save.item_stacks_mut() {
+        let Some(item) = item_stack.item.as_mut() else {
+            continue;
+        };
+
+        match item_names.get(&item.id).and_then(|name| items.get_id(name)) {
+            Some(item_id) => item.id = item_id,
+            None => {
+                *item_stack = Default::default();
+                lost_stacks += 1;
+            }
+        }
+    }
+
+    let player_id = database
+        .player_id_or_create(username)
+        .map_err(|err| err.to_string())?;
+    database
+        .save_player(&player_id, &save)
+        .map_err(|err| err.to_string())?;
+
+    info!("Imported the player '{}' from '{}'", username, file);
+
+    return Ok(lost_stacks);
+}
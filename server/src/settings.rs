@@ -1,6 +1,20 @@
 use bevy::prelude::*;
 
-use std::io::{BufRead, BufReader};
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader},
+};
+
+/// Which kind of terrain the world is generated with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Generator {
+    /// Noise based terrain with all the biomes.
+    Default,
+    /// Flat layers of blocks, see [Settings::flat_layers].
+    Flat,
+    /// Noise based terrain with the same biome everywhere, see [Settings::generator_biome].
+    SingleBiome,
+}
 
 #[derive(Resource)]
 pub struct Settings {
@@ -8,10 +22,79 @@ pub struct Settings {
     pub database_path: String,
     /// Seed used for terrain generation
     pub seed: i32,
+    /// The kind of terrain that is generated.
+    pub generator: Generator,
+    /// Layers of the flat generator from the bottom up, by block name and thickness.
+    pub flat_layers: Vec<(String, u32)>,
+    /// Name of the biome used by the single biome generator.
+    pub generator_biome: String,
     /// Should pvp be enabled
     pub pvp: bool,
     /// The max render distance the server will provide for.
     pub render_distance: u32,
+    /// The render distance will not be lowered below this when the server is under heavy load.
+    pub min_render_distance: u32,
+    /// Names of the players that are allowed to administer the server.
+    pub operators: Vec<String>,
+    /// Ids of the players in [Settings::operators], filled in when the database is opened. Each
+    /// name is tied to the player that had it when it was first listed.
+    pub operator_ids: HashSet<String>,
+    /// Minutes without input before a player is marked as AFK.
+    pub afk_timeout: u32,
+    /// Minutes without input before an AFK player is kicked, never if None.
+    pub afk_kick_timeout: Option<u32>,
+    /// Percentage of the players that must vote yes for a vote to pass.
+    pub vote_threshold: u32,
+    /// Seconds a player must wait between starting votes.
+    pub vote_cooldown: u32,
+    /// Items given to players the first time they join, by item name and amount.
+    pub starter_kit: Vec<(String, u32)>,
+    /// Message shown to players the first time they join, '{}' is replaced by their name.
+    pub welcome_message: Option<String>,
+    /// Where players are placed the first time they join, the world spawn if None.
+    pub tutorial_spawn: Option<IVec3>,
+    /// Path to the pem encoded certificate chain used to encrypt connections.
+    pub tls_certificate: Option<String>,
+    /// Path to the pem encoded private key of the certificate.
+    pub tls_private_key: Option<String>,
+    /// Message shown in the server list.
+    pub motd: String,
+    /// Path to the png image shown next to the server in the server list.
+    pub icon: Option<String>,
+    /// Milliseconds between swings while players hold the left mouse button, 0 to only swing once
+    /// per click.
+    pub swing_interval: u32,
+    /// How far players can reach to interact with blocks and models, in blocks.
+    pub reach: f64,
+    /// Reach of players in creative mode.
+    pub creative_reach: f64,
+    /// Accept connections over WebSocket instead of tcp, so browsers can connect.
+    pub websocket: bool,
+    /// Announce the server to clients on the local network.
+    pub lan_discovery: bool,
+    /// Ask the router to forward the server's port with UPnP.
+    pub upnp: bool,
+    /// Seconds the stand-in of a player that logs out during combat stays, 0 disables it.
+    pub combat_log_duration: u32,
+    /// Minutes between each time the network traffic is logged, 0 disables it.
+    pub network_stats_interval: u32,
+    /// How many players can be connected at once, the rest wait in a queue. No limit if None.
+    pub max_players: Option<u32>,
+    /// Seconds a client can go without sending anything before it is disconnected, 0 disables
+    /// it.
+    pub idle_timeout: u32,
+    /// How many blocks away players can be to see messages sent in the local chat.
+    pub local_chat_radius: u32,
+    /// How many blocks are picked from each chunk every tick to receive a random tick.
+    pub random_tick_speed: u32,
+    /// How many chunks away from the players blocks receive random ticks.
+    pub random_tick_distance: u32,
+    /// How many dropped items there can be in a chunk before the oldest are removed.
+    pub max_dropped_items_per_chunk: u32,
+    /// How many mobs there can be in a chunk before the oldest are removed.
+    pub max_mobs_per_chunk: u32,
+    /// How many block updates a chain of updates, like spreading water, can make each second.
+    pub max_cascade_updates: u32,
 }
 
 impl Default for Settings {
@@ -19,8 +102,45 @@ impl Default for Settings {
         Self {
             database_path: "world.sqlite".to_owned(),
             seed: 0,
+            generator: Generator::Default,
+            flat_layers: vec![
+                ("stone".to_owned(), 8),
+                ("dirt".to_owned(), 3),
+                ("grass".to_owned(), 1),
+            ],
+            generator_biome: "plains".to_owned(),
             pvp: false,
             render_distance: 16,
+            min_render_distance: 4,
+            operators: Vec::new(),
+            operator_ids: HashSet::new(),
+            afk_timeout: 5,
+            afk_kick_timeout: None,
+            vote_threshold: 50,
+            vote_cooldown: 300,
+            starter_kit: Vec::new(),
+            welcome_message: None,
+            tutorial_spawn: None,
+            tls_certificate: None,
+            tls_private_key: None,
+            motd: "A fmc server".to_owned(),
+            icon: None,
+            swing_interval: 250,
+            reach: 5.0,
+            creative_reach: 7.0,
+            websocket: false,
+            lan_discovery: false,
+            upnp: false,
+            combat_log_duration: 30,
+            network_stats_interval: 0,
+            max_players: None,
+            idle_timeout: 30,
+            local_chat_radius: 64,
+            random_tick_speed: 3,
+            random_tick_distance: 4,
+            max_dropped_items_per_chunk: 256,
+            max_mobs_per_chunk: 32,
+            max_cascade_updates: 500,
         }
     }
 }
@@ -70,6 +190,37 @@ impl Settings {
                     });
                     server_settings.seed = value;
                 }
+                "generator" => {
+                    server_settings.generator = match value {
+                        "default" => Generator::Default,
+                        "flat" => Generator::Flat,
+                        "single-biome" => Generator::SingleBiome,
+                        _ => panic!(
+                            "Server property 'generator' must be one of 'default/flat/single-biome', cannot be: {}",
+                            value
+                        ),
+                    };
+                }
+                "flat-layers" => {
+                    server_settings.flat_layers = value
+                        .split(",")
+                        .map(|layer| layer.trim())
+                        .filter(|layer| !layer.is_empty())
+                        .map(|layer| {
+                            let (name, thickness) = layer.split_once(":").unwrap_or((layer, "1"));
+                            let thickness = thickness.trim().parse::<u32>().unwrap_or_else(|_| {
+                                panic!(
+                                    "Server property 'flat-layers' must be a comma separated list of 'block:thickness', cannot be: {}",
+                                    value
+                                )
+                            });
+                            (name.trim().to_owned(), thickness)
+                        })
+                        .collect();
+                }
+                "generator-biome" => {
+                    server_settings.generator_biome = value.to_owned();
+                }
                 "pvp" => {
                     let value = value.parse::<bool>().unwrap_or_else(|_| {
                         panic!(
@@ -79,6 +230,275 @@ impl Settings {
                     });
                     server_settings.pvp = value;
                 }
+                "render-distance" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'render-distance' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.render_distance = value;
+                }
+                "min-render-distance" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'min-render-distance' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.min_render_distance = value;
+                }
+                "afk-timeout" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'afk-timeout' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.afk_timeout = value;
+                }
+                "afk-kick-timeout" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'afk-kick-timeout' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.afk_kick_timeout = Some(value);
+                }
+                "vote-threshold" => {
+                    let value = match value.parse::<u32>() {
+                        Ok(percentage) if percentage <= 100 => percentage,
+                        _ => panic!(
+                            "Server property 'vote-threshold' must be a percentage between 0 and 100, cannot be: {}",
+                            value
+                        ),
+                    };
+                    server_settings.vote_threshold = value;
+                }
+                "vote-cooldown" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'vote-cooldown' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.vote_cooldown = value;
+                }
+                "starter-kit" => {
+                    server_settings.starter_kit = value
+                        .split(",")
+                        .map(|item| item.trim())
+                        .filter(|item| !item.is_empty())
+                        .map(|item| {
+                            let (name, count) = item.split_once(":").unwrap_or((item, "1"));
+                            let count = count.trim().parse::<u32>().unwrap_or_else(|_| {
+                                panic!(
+                                    "Server property 'starter-kit' must be a comma separated list of 'item:amount', cannot be: {}",
+                                    value
+                                )
+                            });
+                            (name.trim().to_owned(), count)
+                        })
+                        .collect();
+                }
+                "welcome-message" => {
+                    if !value.is_empty() {
+                        server_settings.welcome_message = Some(value.to_owned());
+                    }
+                }
+                "tutorial-spawn" => {
+                    let coordinates: Vec<i32> = value
+                        .split(",")
+                        .map(|coordinate| coordinate.trim().parse::<i32>())
+                        .collect::<Result<_, _>>()
+                        .unwrap_or_default();
+                    if coordinates.len() != 3 {
+                        panic!(
+                            "Server property 'tutorial-spawn' must be a position of the format 'x, y, z', cannot be: {}",
+                            value
+                        );
+                    }
+                    server_settings.tutorial_spawn = Some(IVec3::from_slice(&coordinates));
+                }
+                "tls-certificate" => {
+                    if !value.is_empty() {
+                        server_settings.tls_certificate = Some(value.to_owned());
+                    }
+                }
+                "tls-private-key" => {
+                    if !value.is_empty() {
+                        server_settings.tls_private_key = Some(value.to_owned());
+                    }
+                }
+                "swing-interval" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'swing-interval' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.swing_interval = value;
+                }
+                "reach" => {
+                    let value = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|value| *value >= 0.0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Server property 'reach' must be a positive number, cannot be: {}",
+                                value
+                            )
+                        });
+                    server_settings.reach = value;
+                }
+                "creative-reach" => {
+                    let value = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|value| *value >= 0.0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Server property 'creative-reach' must be a positive number, cannot be: {}",
+                                value
+                            )
+                        });
+                    server_settings.creative_reach = value;
+                }
+                "websocket" => {
+                    let value = value.parse::<bool>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'websocket' must be one of 'true/false', cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.websocket = value;
+                }
+                "lan-discovery" => {
+                    let value = value.parse::<bool>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'lan-discovery' must be one of 'true/false', cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.lan_discovery = value;
+                }
+                "upnp" => {
+                    let value = value.parse::<bool>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'upnp' must be one of 'true/false', cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.upnp = value;
+                }
+                "combat-log-duration" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'combat-log-duration' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.combat_log_duration = value;
+                }
+                "network-stats-interval" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'network-stats-interval' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.network_stats_interval = value;
+                }
+                "max-players" => {
+                    let value = match value.parse::<u32>() {
+                        Ok(max_players) if max_players > 0 => max_players,
+                        _ => panic!(
+                            "Server property 'max-players' must be a number above 0, cannot be: {}",
+                            value
+                        ),
+                    };
+                    server_settings.max_players = Some(value);
+                }
+                "idle-timeout" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'idle-timeout' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.idle_timeout = value;
+                }
+                "local-chat-radius" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'local-chat-radius' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.local_chat_radius = value;
+                }
+                "random-tick-speed" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'random-tick-speed' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.random_tick_speed = value;
+                }
+                "random-tick-distance" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'random-tick-distance' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.random_tick_distance = value;
+                }
+                "max-dropped-items-per-chunk" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'max-dropped-items-per-chunk' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.max_dropped_items_per_chunk = value;
+                }
+                "max-mobs-per-chunk" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'max-mobs-per-chunk' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.max_mobs_per_chunk = value;
+                }
+                "max-cascade-updates" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'max-cascade-updates' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.max_cascade_updates = value;
+                }
+                "motd" => {
+                    server_settings.motd = value.to_owned();
+                }
+                "icon" => {
+                    if !value.is_empty() {
+                        server_settings.icon = Some(value.to_owned());
+                    }
+                }
+                "operators" => {
+                    server_settings.operators = value
+                        .split(",")
+                        .map(|name| name.trim().to_owned())
+                        .filter(|name| !name.is_empty())
+                        .collect();
+                }
                 _ => {
                     panic!(
                         "Undefined setting in settings file, there is no setting with the name: {}",
@@ -88,16 +508,110 @@ impl Settings {
             }
         }
 
+        if server_settings.tls_certificate.is_some() != server_settings.tls_private_key.is_some() {
+            panic!("Server properties 'tls-certificate' and 'tls-private-key' must be set together");
+        }
+
         return server_settings;
     }
 
+    pub fn is_operator(&self, player_id: &str) -> bool {
+        return self.operator_ids.contains(player_id);
+    }
+
     // Writes a default config to the server directory.
     #[rustfmt::skip]
     fn write_default() {
         let settings = Self::default();
+        let flat_layers = settings
+            .flat_layers
+            .iter()
+            .map(|(name, thickness)| format!("{}:{}", name, thickness))
+            .collect::<Vec<_>>()
+            .join(", ");
         let contents = "".to_owned()
             + "#world-name = " + &settings.database_path + "\n"
-            + "#pvp = " + &settings.pvp.to_string();
+            + "# The terrain of new chunks, one of 'default', 'flat' or 'single-biome'\n"
+            + "#generator = default\n"
+            + "# Layers of the flat generator from the bottom up, as 'block:thickness'\n"
+            + "#flat-layers = " + &flat_layers + "\n"
+            + "# The biome used everywhere by the single-biome generator\n"
+            + "#generator-biome = " + &settings.generator_biome + "\n"
+            + "#pvp = " + &settings.pvp.to_string() + "\n"
+            + "# Seconds players that log out during pvp combat leave a stand-in behind that can be\n"
+            + "# killed, 0 to disable\n"
+            + "#combat-log-duration = " + &settings.combat_log_duration.to_string() + "\n"
+            + "# Max render distance in chunks\n"
+            + "#render-distance = " + &settings.render_distance.to_string() + "\n"
+            + "# The render distance is lowered towards this when the server is overloaded\n"
+            + "#min-render-distance = " + &settings.min_render_distance.to_string() + "\n"
+            + "# Minutes without input before a player is shown as AFK\n"
+            + "#afk-timeout = " + &settings.afk_timeout.to_string() + "\n"
+            + "# Minutes without input before a player is kicked, unset to never kick\n"
+            + "#afk-kick-timeout = \n"
+            + "# Percentage of players that must vote yes for a vote to pass\n"
+            + "#vote-threshold = " + &settings.vote_threshold.to_string() + "\n"
+            + "# Seconds a player must wait between starting votes\n"
+            + "#vote-cooldown = " + &settings.vote_cooldown.to_string() + "\n"
+            + "# Items given to players the first time they join, e.g. 'torch:8, bread:4'\n"
+            + "#starter-kit = \n"
+            + "# Chat message shown to new players, '{}' is replaced by their name\n"
+            + "#welcome-message = \n"
+            + "# Where new players are placed instead of the world spawn, as 'x, y, z'\n"
+            + "#tutorial-spawn = \n"
+            + "# Encrypt connections with TLS, paths to pem encoded files. Clients must set either\n"
+            + "# 'tls_fingerprint' to the sha256 fingerprint of the certificate, or 'tls_server_name'\n"
+            + "# when it is signed by a certificate authority, in their settings.\n"
+            + "#tls-certificate = \n"
+            + "#tls-private-key = \n"
+            + "# Milliseconds between swings while the attack button is held, 0 to swing once per click\n"
+            + "#swing-interval = " + &settings.swing_interval.to_string() + "\n"
+            + "# How many blocks away players can interact with blocks and models\n"
+            + "#reach = " + &settings.reach.to_string() + "\n"
+            + "# Reach of players in creative mode\n"
+            + "#creative-reach = " + &settings.creative_reach.to_string() + "\n"
+            + "# Accept connections over WebSocket instead of tcp, the server must be built with the\n"
+            + "# 'websocket' feature\n"
+            + "#websocket = " + &settings.websocket.to_string() + "\n"
+            + "# Announce the server to players on the local network, so it shows up in their server\n"
+            + "# list\n"
+            + "#lan-discovery = " + &settings.lan_discovery.to_string() + "\n"
+            + "# Ask the router to forward the port with UPnP, so players outside the local network can\n"
+            + "# connect. The server must be built with the 'upnp' feature\n"
+            + "#upnp = " + &settings.upnp.to_string() + "\n"
+            + "# Minutes between each time the network traffic and the busiest messages are logged,\n"
+            + "# 0 to disable\n"
+            + "#network-stats-interval = " + &settings.network_stats_interval.to_string() + "\n"
+            + "# How many players can be connected at once, the rest wait in a queue to join. Unset\n"
+            + "# for no limit\n"
+            + "#max-players = \n"
+            + "# Seconds a client can go without sending anything before it is disconnected, clients\n"
+            + "# send something at least every 5 seconds. 0 to disable\n"
+            + "#idle-timeout = " + &settings.idle_timeout.to_string() + "\n"
+            + "# How many blocks away players can be to see messages sent in the local chat\n"
+            + "#local-chat-radius = " + &settings.local_chat_radius.to_string() + "\n"
+            + "# How many blocks are picked from each chunk every tick to be updated, makes grass spread\n"
+            + "# and other slow changes happen faster. 0 to disable\n"
+            + "#random-tick-speed = " + &settings.random_tick_speed.to_string() + "\n"
+            + "# How many chunks away from the players blocks are updated by random ticks\n"
+            + "#random-tick-distance = " + &settings.random_tick_distance.to_string() + "\n"
+            + "# How many dropped items there can be in a chunk, the oldest are removed when there are\n"
+            + "# more. Operators are told when it happens. 0 for no limit\n"
+            + "#max-dropped-items-per-chunk = " + &settings.max_dropped_items_per_chunk.to_string() + "\n"
+            + "# How many mobs there can be in a chunk, the oldest are removed when there are more. 0 for\n"
+            + "# no limit\n"
+            + "#max-mobs-per-chunk = " + &settings.max_mobs_per_chunk.to_string() + "\n"
+            + "# How many blocks a chain of block updates that started from a single change, like water\n"
+            + "# spreading, can change each second. The rest are held back until later. 0 for no limit\n"
+            + "#max-cascade-updates = " + &settings.max_cascade_updates.to_string() + "\n"
+            + "# Message shown in the server list. Text after '[#rrggbb]' is colored, and '[/]' goes\n"
+            + "# back to the default color\n"
+            + "#motd = " + &settings.motd + "\n"
+            + "# Path to a png image shown next to the server in the server list, at most 32KB\n"
+            + "#icon = \n"
+            + "# Comma separated list of player names. A name stays with the player that had it when\n"
+            + "# it was added, also if they change it\n"
+            + "#operators = ";
 
         std::fs::write("./server_settings.txt", contents).unwrap();
     }
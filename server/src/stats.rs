@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkServer};
+
+use crate::{
+    players::Player,
+    settings::Settings,
+    world::world_map::{persistence::BlockPersistence, WorldMap},
+};
+
+// How often operators are sent updated stats.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Measures server performance and sends it to operators so they can monitor it in-game.
+pub struct StatsPlugin;
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AverageTickTime::default())
+            .insert_resource(TickTimes {
+                tick_start: Instant::now(),
+                last_update: Instant::now(),
+                tick_times: Vec::new(),
+            })
+            .add_systems(First, start_tick)
+            .add_systems(Last, (end_tick, send_stats).chain());
+    }
+}
+
+/// Average time spent on a tick during the last update interval, in milliseconds.
+#[derive(Resource, Default)]
+pub struct AverageTickTime(pub f32);
+
+#[derive(Resource)]
+struct TickTimes {
+    tick_start: Instant,
+    last_update: Instant,
+    // Milliseconds spent on each tick since the last update.
+    tick_times: Vec<f32>,
+}
+
+fn start_tick(mut tick_times: ResMut<TickTimes>) {
+    tick_times.tick_start = Instant::now();
+}
+
+fn end_tick(mut tick_times: ResMut<TickTimes>) {
+    let tick_time = tick_times.tick_start.elapsed().as_secs_f32() * 1000.0;
+    tick_times.tick_times.push(tick_time);
+}
+
+fn send_stats(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
+    persistence: Res<BlockPersistence>,
+    mut tick_times: ResMut<TickTimes>,
+    mut average_tick_time: ResMut<AverageTickTime>,
+    players: Query<(&Player, &ConnectionId)>,
+    entities: Query<Entity>,
+) {
+    if tick_times.last_update.elapsed() < UPDATE_INTERVAL {
+        return;
+    }
+    tick_times.last_update = Instant::now();
+
+    let tick_times = std::mem::take(&mut tick_times.tick_times);
+    average_tick_time.0 = tick_times.iter().sum::<f32>() / tick_times.len().max(1) as f32;
+
+    if settings.operators.is_empty() {
+        return;
+    }
+
+    let operators = players
+        .iter()
+        .filter(|(player, _)| settings.is_operator(&player.id))
+        .map(|(_, connection_id)| connection_id);
+
+    net.send_many(
+        operators,
+        messages::ServerStats {
+            tick_time: average_tick_time.0,
+            max_tick_time: tick_times.iter().cloned().fold(0.0, f32::max),
+            tick_times,
+            loaded_chunks: world_map.chunk_count() as u32,
+            entities: entities.iter().len() as u32,
+            players: players.iter().len() as u32,
+            persistence_queue: persistence.queue_length() as u32,
+            persistence_stalls: persistence.stalls(),
+        },
+    );
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkData, NetworkServer};
+
+use crate::{
+    players::{Afk, Player},
+    settings::Settings,
+    world::{sky::TimeOfDay, weather::Weather},
+};
+
+// How long players have to cast their votes
+const VOTE_DURATION: Duration = Duration::from_secs(30);
+// Seconds left of the vote when the players are reminded of it.
+const REMINDERS: [u64; 3] = [20, 10, 5];
+
+// Votes are started from the chat with '/vote <kind>', other players then answer with '/vote yes'
+// or '/vote no'. Only one vote can run at a time. Players that are AFK are not counted, so they
+// can't hold a vote back.
+//
+// To add a new kind of vote, add it to VoteKind, along with the translations for its messages
+// and what should happen when it passes.
+pub struct VotePlugin;
+impl Plugin for VotePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Votes::default())
+            .add_systems(Update, (handle_vote_commands, count_votes).chain());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VoteKind {
+    /// Skip the night
+    Day,
+    /// Stop the rain
+    ClearWeather,
+}
+
+impl VoteKind {
+    const ALL: [VoteKind; 2] = [VoteKind::Day, VoteKind::ClearWeather];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::ClearWeather => "clear",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        return Self::ALL.into_iter().find(|kind| kind.name() == name);
+    }
+}
+
+struct Vote {
+    kind: VoteKind,
+    yes: HashSet<Entity>,
+    no: HashSet<Entity>,
+    timer: Timer,
+    // Index into REMINDERS of the next reminder to send.
+    next_reminder: usize,
+}
+
+#[derive(Resource, Default)]
+struct Votes {
+    active: Option<Vote>,
+    // When each player last started a vote, by id so it can't be reset by reconnecting or by
+    // changing name.
+    started_at: HashMap<String, Instant>,
+}
+
+fn handle_vote_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    time_of_day: Res<TimeOfDay>,
+    weather: Res<Weather>,
+    mut votes: ResMut<Votes>,
+    player_query: Query<&Player>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/vote") {
+            continue;
+        }
+
+        let entity = chat_message.source.entity();
+        let player = player_query.get(entity).unwrap();
+
+        match words.next() {
+            Some("yes") | Some("no") if votes.active.is_none() => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("vote.none_active", vec![]),
+                );
+            }
+            Some("yes") => {
+                let vote = votes.active.as_mut().unwrap();
+                vote.no.remove(&entity);
+                vote.yes.insert(entity);
+            }
+            Some("no") => {
+                let vote = votes.active.as_mut().unwrap();
+                vote.yes.remove(&entity);
+                vote.no.insert(entity);
+            }
+            Some(name) => {
+                let Some(kind) = VoteKind::from_name(name) else {
+                    let available: Vec<&str> = VoteKind::ALL.iter().map(VoteKind::name).collect();
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated(
+                            "vote.unknown",
+                            vec![available.join(", ")],
+                        ),
+                    );
+                    continue;
+                };
+
+                if votes.active.is_some() {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("vote.already_active", vec![]),
+                    );
+                    continue;
+                }
+
+                let cooldown = Duration::from_secs(settings.vote_cooldown as u64);
+                if let Some(started_at) = votes.started_at.get(&player.id) {
+                    let elapsed = started_at.elapsed();
+                    if elapsed < cooldown {
+                        let remaining = (cooldown - elapsed).as_secs() + 1;
+                        net.send_one(
+                            chat_message.source,
+                            messages::ChatMessageServer::translated(
+                                "vote.cooldown",
+                                vec![remaining.to_string()],
+                            ),
+                        );
+                        continue;
+                    }
+                }
+
+                if kind == VoteKind::Day && !time_of_day.is_night() {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("vote.day.not_night", vec![]),
+                    );
+                    continue;
+                }
+
+                if kind == VoteKind::ClearWeather && weather.get() == messages::Weather::Clear {
+                    net.send_one(
+                        chat_message.source,
+                        messages::ChatMessageServer::translated("vote.clear.not_raining", vec![]),
+                    );
+                    continue;
+                }
+
+                votes.started_at.insert(player.id.clone(), Instant::now());
+                votes.active = Some(Vote {
+                    kind,
+                    yes: HashSet::from([entity]),
+                    no: HashSet::new(),
+                    timer: Timer::new(VOTE_DURATION, TimerMode::Once),
+                    next_reminder: 0,
+                });
+
+                net.broadcast(messages::ChatMessageServer::translated(
+                    &format!("vote.{}.started", kind.name()),
+                    vec![player.username.clone()],
+                ));
+            }
+            None => {
+                net.send_one(
+                    chat_message.source,
+                    messages::ChatMessageServer::translated("vote.usage", vec![]),
+                );
+            }
+        }
+    }
+}
+
+fn count_votes(
+    time: Res<Time>,
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    mut votes: ResMut<Votes>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut weather: ResMut<Weather>,
+    player_query: Query<Entity, (With<Player>, With<ConnectionId>, Without<Afk>)>,
+) {
+    let Some(vote) = votes.active.as_mut() else {
+        return;
+    };
+
+    vote.timer.tick(time.delta());
+
+    // Players that have disconnected or gone AFK since they voted don't count.
+    vote.yes.retain(|entity| player_query.contains(*entity));
+    vote.no.retain(|entity| player_query.contains(*entity));
+
+    let voters = player_query.iter().count() as u32;
+    let yes = vote.yes.len() as u32;
+    let no = vote.no.len() as u32;
+
+    let passed = yes > 0 && yes * 100 >= settings.vote_threshold * voters;
+    // The threshold can't be reached anymore.
+    let failed = (voters - no) * 100 < settings.vote_threshold * voters;
+
+    if passed {
+        match vote.kind {
+            VoteKind::Day => time_of_day.skip_to_dawn(),
+            VoteKind::ClearWeather => weather.clear(),
+        }
+        net.broadcast(messages::ChatMessageServer::translated(
+            &format!("vote.{}.passed", vote.kind.name()),
+            vec![],
+        ));
+        votes.active = None;
+    } else if failed || vote.timer.finished() {
+        net.broadcast(messages::ChatMessageServer::translated(
+            "vote.failed",
+            vec![yes.to_string(), voters.to_string()],
+        ));
+        votes.active = None;
+    } else if let Some(reminder) = REMINDERS.get(vote.next_reminder) {
+        if vote.timer.remaining() <= Duration::from_secs(*reminder) {
+            vote.next_reminder += 1;
+            net.broadcast(messages::ChatMessageServer::translated(
+                "vote.reminder",
+                vec![reminder.to_string(), yes.to_string(), voters.to_string()],
+            ));
+        }
+    }
+}
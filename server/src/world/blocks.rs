@@ -11,12 +11,21 @@
 // add extra json data as the blocks state. This is useful e.g. for furnaces that need to keep
 // track of what is being smelted and what interface should be show to the player when it is
 // interacted with.
+// Blocks that hold items, like chests and furnaces, are registered as block entities. They get an
+// entity with an item storage that is saved with the chunk, see block_entities.rs.
+// Spawners are blocks that spawn mobs around them, see spawner.rs.
+// Blocks can switch to another block depending on if it is day or night, like lamps that light up
+// when it gets dark, see daylight.rs.
 //
 // TODO: It should store block configs in the worlds database so that worlds are more portable.
 //       Addendum: It should store the entire resource folder.
 //       It should instead emit warnings when configs(and other things it was initialized with) go
 //       missing, and update the database if a config has been changed.
-use std::{collections::HashMap, ops::Deref, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Deref,
+    path::Path,
+};
 
 use bevy::prelude::*;
 use fmc_networking::BlockId;
@@ -25,14 +34,33 @@ use serde::Deserialize;
 
 use crate::database::Database;
 
-use super::items::ItemId;
+use super::{
+    items::ItemId,
+    sounds::{SoundGroups, SOUND_GROUPS_PATH},
+};
 
+mod beacon;
+mod block_entities;
+mod chest;
+mod daylight;
 mod furnace;
+mod grass;
+mod item_frame;
+mod random_tick;
+mod spawner;
 mod water;
 
+pub use block_entities::{BlockEntities, BlockEntity, RegisterBlockEntity};
+pub use random_tick::{BlockTick, RegisterRandomTick};
+pub use spawner::SpawnMob;
+
 pub const BLOCK_CONFIG_PATH: &str = "./resources/client/blocks/";
 const BLOCK_MATERIAL_PATH: &str = "./resources/client/materials/";
 
+// Items in this category are tools. Tools that are not suited for breaking a block break it
+// slower than an empty hand.
+const TOOL_CATEGORY: &str = "tool";
+
 static BLOCKS: once_cell::sync::OnceCell<Blocks> = once_cell::sync::OnceCell::new();
 
 pub struct BlockPlugin;
@@ -44,14 +72,22 @@ impl Plugin for BlockPlugin {
         // resource and then at the end of startup move it.
         //let database = app.world.resource::<DatabaseArc>();
         //Blocks::load(database.as_ref());
-        app.add_plugins(water::WaterPlugin);
-        //.add_plugins(furnace::FurnacePlugin);
+        app.add_plugins(random_tick::RandomTickPlugin)
+            .add_plugins(block_entities::BlockEntityPlugin)
+            .add_plugins(chest::ChestPlugin)
+            .add_plugins(daylight::DaylightPlugin)
+            .add_plugins(furnace::FurnacePlugin)
+            .add_plugins(spawner::SpawnerPlugin)
+            .add_plugins(water::WaterPlugin)
+            .add_plugins(grass::GrassPlugin)
+            .add_plugins(item_frame::ItemFramePlugin)
+            .add_plugins(beacon::BeaconPlugin);
     }
 }
 
 // Loads the blocks from file. At first launch, block ids will be generated. These persist between
 // launches.
-fn load_blocks(database: Res<Database>) {
+fn load_blocks(database: Res<Database>, sound_groups: Res<SoundGroups>) {
     fn walk_dir<P: AsRef<std::path::Path>>(dir: P) -> Vec<std::path::PathBuf> {
         let mut files = Vec::new();
 
@@ -77,10 +113,14 @@ fn load_blocks(database: Res<Database>) {
 
     let mut blocks = Blocks {
         blocks: Vec::new(),
-        ids: database.load_block_ids(),
+        ids: database
+            .load_block_ids()
+            .unwrap_or_else(|err| panic!("Could not load the block ids: {}", err)),
     };
 
-    let item_ids = database.load_item_ids();
+    let item_ids = database
+        .load_item_ids()
+        .unwrap_or_else(|err| panic!("Could not load the item ids: {}", err));
 
     let mut block_ids = blocks.clone_ids();
     let mut maybe_blocks = Vec::new();
@@ -124,14 +164,47 @@ fn load_blocks(database: Res<Database>) {
             true
         };
 
+        if let Some(sound_group) = &block_config_json.sound_group {
+            if !sound_groups.contains(sound_group) {
+                panic!(
+                    "Failed to find the sound group '{}' of the block '{}', make sure it is \
+                    present in '{}'",
+                    sound_group, block_config_json.name, SOUND_GROUPS_PATH
+                );
+            }
+        }
+
+        let daylight = block_config_json.daylight.map(|daylight| {
+            for block_name in [&daylight.day, &daylight.night] {
+                if !blocks.contains_block(block_name) {
+                    panic!(
+                        "Failed to read 'daylight' field for block at: {}\nError: No block by \
+                        the name {}",
+                        file_path.display(),
+                        block_name
+                    );
+                }
+            }
+            Daylight {
+                day: blocks.get_id(&daylight.day),
+                night: blocks.get_id(&daylight.night),
+                needs_sky: daylight.needs_sky,
+            }
+        });
+
         if let Some(block_id) = block_ids.remove(&block_config_json.name) {
             let block_config = BlockConfig {
                 name: block_config_json.name,
                 friction: block_config_json.friction,
                 hardness: block_config_json.hardness,
+                tools: block_config_json.tools,
+                sound_group: block_config_json.sound_group,
                 drop,
                 is_rotatable: block_config_json.is_rotatable,
                 is_transparent,
+                interactable: block_config_json.interactable,
+                drowning: block_config_json.drowning,
+                daylight,
             };
 
             maybe_blocks[block_id as usize] = Some(Block::new(block_config));
@@ -249,6 +322,11 @@ impl Blocks {
     pub fn contains_block(&self, block_name: &str) -> bool {
         return self.ids.contains_key(block_name);
     }
+
+    /// If there is a block with the id.
+    pub fn is_valid_id(&self, block_id: BlockId) -> bool {
+        return (block_id as usize) < self.blocks.len();
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -327,16 +405,40 @@ struct BlockConfigJson {
     friction: Friction,
     // How long it takes to break the block without a tool
     hardness: Option<f32>,
+    // Kinds of tools suited for breaking the block, e.g. "axe".
+    #[serde(default)]
+    tools: HashSet<String>,
+    // The sounds the block makes, e.g. "stone".
+    sound_group: Option<String>,
     // Which item(s) the block drops
     drop: Option<BlockDropJson>,
     #[serde(default)]
     is_rotatable: bool,
+    // If right clicking the block is an interaction instead of a block placement.
+    #[serde(default)]
+    interactable: bool,
+    // If players can't breathe while their head is inside the block.
+    #[serde(default)]
+    drowning: bool,
+    // The blocks it switches between at day and night.
+    daylight: Option<DaylightJson>,
     // Renderding material, used to deduce transparency.
     // None if it's a model block, the transparency is set to true.
     // If the string is not "opaque", the transparency is set to true.
     material: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct DaylightJson {
+    // Name of the block used while there is daylight
+    day: String,
+    // Name of the block used while there isn't
+    night: String,
+    // If the block has to see the sky to get daylight.
+    #[serde(default)]
+    needs_sky: bool,
+}
+
 impl BlockConfigJson {
     fn from_file(path: &Path) -> Option<Self> {
         fn read_as_json_value(
@@ -397,15 +499,46 @@ pub struct BlockConfig {
     pub friction: Friction,
     /// How long it takes to break the block without a tool, None if unbreakable.
     pub hardness: Option<f32>,
+    /// Kinds of tools suited for breaking the block. They are matched against the categories of
+    /// the item that is used.
+    pub tools: HashSet<String>,
+    /// The group of sounds played when the block is placed or broken.
+    pub sound_group: Option<String>,
     // Which item(s) the block drops.
     drop: Option<BlockDrop>,
     // If the block is rotatable around the y axis
     pub is_rotatable: bool,
     // If the block can be seen through
     pub is_transparent: bool,
+    // If right clicking the block is an interaction instead of a block placement.
+    pub interactable: bool,
+    // If players can't breathe while their head is inside the block.
+    pub drowning: bool,
+    /// The blocks it switches between at day and night, None if it doesn't react to the time.
+    pub daylight: Option<Daylight>,
+}
+
+/// A pair of blocks that are swapped for each other as the day turns to night and back.
+#[derive(Debug, Clone, Copy)]
+pub struct Daylight {
+    /// The block used while there is daylight.
+    pub day: BlockId,
+    /// The block used while there isn't.
+    pub night: BlockId,
+    /// If the sky has to be visible from the block for it to get daylight.
+    pub needs_sky: bool,
 }
 
 impl BlockConfig {
+    /// If an item with the categories is a tool that is not suited for breaking the block.
+    pub fn is_wrong_tool(&self, item_categories: Option<&HashSet<String>>) -> bool {
+        return item_categories.is_some_and(|categories| {
+            !self.tools.is_empty()
+                && categories.contains(TOOL_CATEGORY)
+                && self.tools.is_disjoint(categories)
+        });
+    }
+
     pub fn drop(&self) -> Option<(ItemId, u32)> {
         if let Some(drop) = &self.drop {
             return Some(drop.drop());
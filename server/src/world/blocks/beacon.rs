@@ -0,0 +1,367 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, BlockId, NetworkData, NetworkServer};
+use serde::Deserialize;
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    database::Database,
+    physics::shapes::Aabb,
+    players::{Camera, Player, Reach, StatusEffect, StatusEffects},
+    world::{
+        models::{Model, ModelBundle, ModelVisibility, Models},
+        world_map::{BlockUpdate, WorldMap},
+    },
+};
+
+use super::Blocks;
+
+const BEACON_CONFIG_PATH: &str = "./resources/server/beacon.json";
+
+// How far the beam reaches into the sky
+const BEAM_LENGTH: f64 = 64.0;
+const BEAM_WIDTH: f64 = 0.3;
+
+// Right clicking a beacon opens an interface where one of the status effects can be chosen. When
+// the 3x3 layer of blocks beneath it is made out of the configured base blocks, the effect is
+// periodically applied to all players within range, and a beam shoots up from the beacon. The
+// chosen effects are saved to the database, and all the beacons are loaded when the server starts.
+pub struct BeaconPlugin;
+impl Plugin for BeaconPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Beacons::default())
+            .insert_resource(OpenBeacons::default())
+            .add_systems(Startup, (load_beacon_config, load_beacons))
+            .add_systems(
+                Update,
+                (
+                    open_beacon_interface,
+                    choose_beacon_effect,
+                    apply_beacon_effects,
+                    remove_broken_beacons,
+                ),
+            );
+    }
+}
+
+#[derive(Deserialize)]
+struct BeaconConfigJson {
+    // How far away from the beacon players are affected
+    radius: f64,
+    // Seconds between each time the effect is applied
+    interval: f32,
+    // Seconds the effect lasts each time it is applied
+    effect_duration: f32,
+    // Names of the blocks the base can be built from
+    base_blocks: Vec<String>,
+}
+
+#[derive(Resource)]
+struct BeaconConfig {
+    radius: f64,
+    timer: Timer,
+    effect_duration: Duration,
+    base_blocks: HashSet<BlockId>,
+}
+
+fn load_beacon_config(mut commands: Commands) {
+    let file = match std::fs::File::open(BEACON_CONFIG_PATH) {
+        Ok(f) => f,
+        Err(e) => panic!(
+            "Failed to open beacon config at: {}\nError: {}",
+            BEACON_CONFIG_PATH, e
+        ),
+    };
+
+    let json: BeaconConfigJson = match serde_json::from_reader(&file) {
+        Ok(c) => c,
+        Err(e) => panic!(
+            "Couldn't read beacon config from '{}'\nError: {}",
+            BEACON_CONFIG_PATH, e
+        ),
+    };
+
+    let blocks = Blocks::get();
+    let base_blocks = json
+        .base_blocks
+        .iter()
+        .map(|name| {
+            if !blocks.contains_block(name) {
+                panic!(
+                    "Invalid beacon config at '{}', there is no block named '{}'",
+                    BEACON_CONFIG_PATH, name
+                );
+            }
+            blocks.get_id(name)
+        })
+        .collect();
+
+    commands.insert_resource(BeaconConfig {
+        radius: json.radius,
+        timer: Timer::from_seconds(json.interval, TimerMode::Repeating),
+        effect_duration: Duration::from_secs_f32(json.effect_duration),
+        base_blocks,
+    });
+}
+
+/// The beacons that have been given an effect, by block position.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct Beacons(HashMap<IVec3, Entity>);
+
+/// The beacon each player last opened the interface of.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct OpenBeacons(HashMap<Entity, IVec3>);
+
+#[derive(Component)]
+struct Beacon {
+    position: IVec3,
+    effect: StatusEffect,
+}
+
+impl Beacon {
+    fn has_base(&self, world_map: &WorldMap, base_blocks: &HashSet<BlockId>) -> bool {
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let position = self.position + IVec3::new(x, -1, z);
+                match world_map.get_block(position) {
+                    Some(block_id) if base_blocks.contains(&block_id) => continue,
+                    _ => return false,
+                }
+            }
+        }
+
+        return true;
+    }
+}
+
+fn spawn_beacon(
+    commands: &mut Commands,
+    models: &Models,
+    beacons: &mut Beacons,
+    position: IVec3,
+    effect: StatusEffect,
+) {
+    // The beam is hidden until the beacon has been checked for a base.
+    let entity = commands
+        .spawn((
+            Beacon { position, effect },
+            ModelBundle {
+                model: Model::new(models.get_id("beacon_beam")),
+                visibility: ModelVisibility { is_visible: false },
+                global_transform: F64GlobalTransform::default(),
+                transform: F64Transform {
+                    // Block models have their origin in the corner, not the center.
+                    translation: position.as_dvec3()
+                        + DVec3::new(0.5 - BEAM_WIDTH / 2.0, 1.0, 0.5 - BEAM_WIDTH / 2.0),
+                    scale: DVec3::new(BEAM_WIDTH, BEAM_LENGTH, BEAM_WIDTH),
+                    ..default()
+                },
+            },
+        ))
+        .id();
+
+    beacons.insert(position, entity);
+}
+
+fn load_beacons(
+    mut commands: Commands,
+    database: Res<Database>,
+    models: Res<Models>,
+    mut beacons: ResMut<Beacons>,
+) {
+    let saved_beacons = match database.load_beacons() {
+        Ok(saved_beacons) => saved_beacons,
+        Err(err) => {
+            error!("Failed to load the beacons: {}", err);
+            return;
+        }
+    };
+
+    for (position, effect_name) in saved_beacons {
+        let Some(effect) = StatusEffect::from_name(&effect_name) else {
+            warn!(
+                "The beacon at {} has no effect, there is no status effect named '{}'",
+                position, effect_name
+            );
+            continue;
+        };
+
+        spawn_beacon(&mut commands, &models, &mut beacons, position, effect);
+    }
+}
+
+fn open_beacon_interface(
+    net: Res<NetworkServer>,
+    world_map: Res<WorldMap>,
+    mut open_beacons: ResMut<OpenBeacons>,
+    player_query: Query<(&F64GlobalTransform, &Camera, &Reach), With<Player>>,
+    mut clicks: EventReader<NetworkData<messages::RightClick>>,
+) {
+    let beacon_id = Blocks::get().get_id("beacon");
+
+    for right_click in clicks.read() {
+        let (player_position, player_camera, reach) =
+            player_query.get(right_click.source.entity()).unwrap();
+
+        let camera_transform = F64Transform {
+            translation: player_position.translation() + player_camera.translation,
+            rotation: player_camera.rotation,
+            ..default()
+        };
+
+        let (block_position, block_id, _) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        if block_id != beacon_id {
+            continue;
+        }
+
+        open_beacons.insert(right_click.source.entity(), block_position);
+        net.send_one(
+            right_click.source,
+            messages::InterfaceOpen {
+                interface_path: "beacon".to_owned(),
+            },
+        );
+    }
+}
+
+fn choose_beacon_effect(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    models: Res<Models>,
+    mut beacons: ResMut<Beacons>,
+    mut open_beacons: ResMut<OpenBeacons>,
+    mut beacon_query: Query<&mut Beacon>,
+    mut player_query: Query<(&Player, &F64GlobalTransform, &Camera, &mut Reach)>,
+    mut button_presses: EventReader<NetworkData<messages::InterfaceButtonPress>>,
+) {
+    for button_press in button_presses.read() {
+        let Some(effect_name) = button_press.interface_path.strip_prefix("beacon/") else {
+            continue;
+        };
+
+        let Some(effect) = StatusEffect::from_name(effect_name) else {
+            continue;
+        };
+
+        let Some(position) = open_beacons.remove(&button_press.source.entity()) else {
+            continue;
+        };
+
+        net.send_one(
+            button_press.source,
+            messages::InterfaceClose {
+                interface_path: "beacon".to_owned(),
+            },
+        );
+
+        let Ok((player, player_position, player_camera, mut reach)) =
+            player_query.get_mut(button_press.source.entity())
+        else {
+            continue;
+        };
+
+        // The player might have walked away while the interface was open.
+        let camera_position = player_position.translation() + player_camera.translation;
+        let block_aabb = Aabb::from_min_max(position.as_dvec3(), position.as_dvec3() + DVec3::ONE);
+        if !reach.check(player, camera_position, &block_aabb) {
+            continue;
+        }
+
+        // The beacon might have been broken while the interface was open.
+        if world_map.get_block(position) != Some(Blocks::get().get_id("beacon")) {
+            continue;
+        }
+
+        if let Err(err) = database.save_beacon(position, effect.name()) {
+            error!("Failed to save the beacon at {}: {}", position, err);
+        }
+
+        if let Some(entity) = beacons.get(&position) {
+            match beacon_query.get_mut(*entity) {
+                Ok(mut beacon) => beacon.effect = effect,
+                // The beacon was given its first effect this tick and hasn't been spawned yet,
+                // the new one replaces it when it is.
+                Err(_) => {
+                    commands.entity(*entity).insert(Beacon { position, effect });
+                }
+            }
+            continue;
+        }
+
+        spawn_beacon(&mut commands, &models, &mut beacons, position, effect);
+    }
+}
+
+fn apply_beacon_effects(
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    mut beacon_config: ResMut<BeaconConfig>,
+    mut beacon_query: Query<(&Beacon, &mut ModelVisibility)>,
+    mut player_query: Query<(&F64GlobalTransform, &mut StatusEffects), With<Player>>,
+) {
+    beacon_config.timer.tick(time.delta());
+    if !beacon_config.timer.just_finished() {
+        return;
+    }
+
+    for (beacon, mut visibility) in beacon_query.iter_mut() {
+        let is_active = beacon.has_base(&world_map, &beacon_config.base_blocks);
+        if visibility.is_visible != is_active {
+            visibility.is_visible = is_active;
+        }
+
+        if !is_active {
+            continue;
+        }
+
+        let center = beacon.position.as_dvec3() + DVec3::splat(0.5);
+        for (player_position, mut status_effects) in player_query.iter_mut() {
+            if player_position.translation().distance(center) <= beacon_config.radius {
+                status_effects.apply(beacon.effect, beacon_config.effect_duration);
+            }
+        }
+    }
+}
+
+fn remove_broken_beacons(
+    mut commands: Commands,
+    database: Res<Database>,
+    mut beacons: ResMut<Beacons>,
+    mut block_updates: EventReader<BlockUpdate>,
+) {
+    if beacons.is_empty() {
+        block_updates.clear();
+        return;
+    }
+
+    let beacon_id = Blocks::get().get_id("beacon");
+
+    for block_update in block_updates.read() {
+        let BlockUpdate::Change {
+            position, block_id, ..
+        } = block_update;
+
+        if *block_id == beacon_id {
+            continue;
+        }
+
+        if let Some(entity) = beacons.remove(position) {
+            commands.entity(entity).despawn();
+
+            if let Err(err) = database.delete_beacon(*position) {
+                error!("Failed to delete the beacon at {}: {}", position, err);
+            }
+        }
+    }
+}
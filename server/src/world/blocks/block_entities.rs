@@ -0,0 +1,573 @@
+use bevy::{
+    math::DVec3,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use fmc_networking::{messages, BlockId, ConnectionId, NetworkData, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    database::Database,
+    players::{Camera, HeldItemStack, Player, Reach},
+    utils,
+    world::{
+        items::{spawn_dropped_item, ItemStack, ItemStorage, Items},
+        models::Models,
+        world_map::{
+            chunk_manager::{ChunkLoadEvent, ChunkUnloadEvent},
+            BlockUpdate, WorldMap,
+        },
+    },
+};
+
+use super::Blocks;
+
+// Blocks like chests that hold items are backed by an entity with an ItemStorage. The entities
+// live as long as their chunk is loaded, and their storage is saved to the database every time it
+// changes. Block entities that have never held anything are not saved, they are spawned the first
+// time a player opens them instead.
+//
+// Right clicking a block entity opens its interface. The interface has a "storage" section for
+// the block's items, and "hotbar" and "inventory" sections that mirror the player's inventory so
+// items can be moved between them. Block entities registered without an interface, like item
+// frames, have their items managed by their own systems instead.
+pub(super) struct BlockEntityPlugin;
+impl Plugin for BlockEntityPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlockEntityConfigs>()
+            .insert_resource(BlockEntities::default())
+            .insert_resource(OpenBlockEntities::default())
+            .add_systems(Startup, resolve_block_entity_configs)
+            .add_systems(
+                Update,
+                (
+                    spawn_loaded_block_entities,
+                    despawn_unloaded_block_entities,
+                    remove_broken_block_entities,
+                    open_block_entity_interface,
+                    handle_interface_events,
+                    update_block_entity_interfaces.after(handle_interface_events),
+                    save_block_entities,
+                ),
+            );
+    }
+}
+
+/// The entities of the block entities that are loaded, by block position.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct BlockEntities(HashMap<IVec3, Entity>);
+
+/// Marks the entity of a block entity.
+#[derive(Component)]
+pub struct BlockEntity {
+    pub position: IVec3,
+    pub block_id: BlockId,
+}
+
+#[derive(Clone)]
+struct BlockEntityConfig {
+    // How many item stacks the block can hold
+    storage_size: usize,
+    // Name of the interface that is opened when the block is right clicked, None if it has none
+    interface: Option<String>,
+}
+
+#[derive(Resource, Default)]
+struct BlockEntityConfigs {
+    // Names are registered before the blocks are loaded, they are converted to ids at startup.
+    names: HashMap<String, BlockEntityConfig>,
+    ids: HashMap<BlockId, BlockEntityConfig>,
+}
+
+pub trait RegisterBlockEntity {
+    /// Make the block hold items. Right clicking it opens the interface, which must have a
+    /// "storage" section for the block's items. Must be done before startup.
+    fn register_block_entity(
+        &mut self,
+        block_name: &str,
+        storage_size: usize,
+        interface: &str,
+    ) -> &mut Self;
+
+    /// Make the block hold items without an interface, the items are only changed by other
+    /// systems through its [ItemStorage]. Must be done before startup.
+    fn register_storage_block(&mut self, block_name: &str, storage_size: usize) -> &mut Self;
+}
+
+impl RegisterBlockEntity for App {
+    fn register_block_entity(
+        &mut self,
+        block_name: &str,
+        storage_size: usize,
+        interface: &str,
+    ) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(BlockEntityConfigs::default)
+            .names
+            .insert(
+                block_name.to_owned(),
+                BlockEntityConfig {
+                    storage_size,
+                    interface: Some(interface.to_owned()),
+                },
+            );
+        return self;
+    }
+
+    fn register_storage_block(&mut self, block_name: &str, storage_size: usize) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(BlockEntityConfigs::default)
+            .names
+            .insert(
+                block_name.to_owned(),
+                BlockEntityConfig {
+                    storage_size,
+                    interface: None,
+                },
+            );
+        return self;
+    }
+}
+
+fn resolve_block_entity_configs(mut block_entity_configs: ResMut<BlockEntityConfigs>) {
+    let blocks = Blocks::get();
+
+    let BlockEntityConfigs { names, ids } = block_entity_configs.as_mut();
+    for (name, config) in names.iter() {
+        if !blocks.contains_block(name) {
+            panic!(
+                "The block '{}' was registered as a block entity, but there is no block by that name",
+                name
+            );
+        }
+        ids.insert(blocks.get_id(name), config.clone());
+    }
+}
+
+/// The players that have a block entity's interface open, by player entity.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub(super) struct OpenBlockEntities(HashMap<Entity, IVec3>);
+
+pub(super) fn spawn_block_entity(
+    commands: &mut Commands,
+    block_entities: &mut BlockEntities,
+    position: IVec3,
+    block_id: BlockId,
+    storage: ItemStorage,
+) -> Entity {
+    let entity = commands
+        .spawn((BlockEntity { position, block_id }, storage))
+        .id();
+    block_entities.insert(position, entity);
+    return entity;
+}
+
+fn spawn_loaded_block_entities(
+    mut commands: Commands,
+    mut world_map: ResMut<WorldMap>,
+    block_entity_configs: Res<BlockEntityConfigs>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut load_chunk_events: EventReader<ChunkLoadEvent>,
+) {
+    for event in load_chunk_events.read() {
+        let Some(chunk) = world_map.get_chunk_mut(&event.0) else {
+            continue;
+        };
+
+        for (position, mut storage) in std::mem::take(&mut chunk.block_entities) {
+            let block_id =
+                chunk[utils::world_position_to_chunk_position_and_block_index(position).1];
+
+            // The block might not be a block entity anymore if it has been unregistered.
+            let Some(config) = block_entity_configs.ids.get(&block_id) else {
+                continue;
+            };
+
+            if storage.len() < config.storage_size {
+                storage.resize_with(config.storage_size, ItemStack::default);
+            }
+
+            spawn_block_entity(
+                &mut commands,
+                &mut block_entities,
+                position,
+                block_id,
+                storage,
+            );
+        }
+    }
+}
+
+fn despawn_unloaded_block_entities(
+    mut commands: Commands,
+    mut block_entities: ResMut<BlockEntities>,
+    mut unload_chunk_events: EventReader<ChunkUnloadEvent>,
+) {
+    if block_entities.is_empty() {
+        unload_chunk_events.clear();
+        return;
+    }
+
+    let unloaded: HashSet<IVec3> = unload_chunk_events.read().map(|event| event.0).collect();
+    if unloaded.is_empty() {
+        return;
+    }
+
+    // Their storage is saved whenever it changes, so there's nothing left to save.
+    block_entities.retain(|position, entity| {
+        if unloaded.contains(&utils::world_position_to_chunk_position(*position)) {
+            commands.entity(*entity).despawn();
+            false
+        } else {
+            true
+        }
+    });
+}
+
+pub(super) fn remove_broken_block_entities(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    items: Res<Items>,
+    models: Res<Models>,
+    block_entity_configs: Res<BlockEntityConfigs>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut open_block_entities: ResMut<OpenBlockEntities>,
+    mut block_entity_query: Query<(&BlockEntity, &mut ItemStorage)>,
+    player_query: Query<&ConnectionId, With<Player>>,
+    mut block_updates: EventReader<BlockUpdate>,
+) {
+    if block_entities.is_empty() {
+        block_updates.clear();
+        return;
+    }
+
+    for block_update in block_updates.read() {
+        let BlockUpdate::Change {
+            position, block_id, ..
+        } = block_update;
+
+        let Some(entity) = block_entities.get(position).cloned() else {
+            continue;
+        };
+
+        let (block_entity, mut storage) = block_entity_query.get_mut(entity).unwrap();
+        if block_entity.block_id == *block_id {
+            continue;
+        }
+
+        for item_stack in storage.iter_mut() {
+            if item_stack.is_empty() {
+                continue;
+            }
+            spawn_dropped_item(
+                &mut commands,
+                &items,
+                &models,
+                position.as_dvec3() + DVec3::splat(0.5),
+                std::mem::take(item_stack),
+            );
+        }
+
+        if let Err(err) = database.delete_block_entity(*position) {
+            error!("Failed to delete the block entity at {}: {}", position, err);
+        }
+
+        if let Some(interface) = &block_entity_configs.ids[&block_entity.block_id].interface {
+            open_block_entities.retain(|player_entity, open_position| {
+                if open_position != position {
+                    return true;
+                }
+                if let Ok(connection_id) = player_query.get(*player_entity) {
+                    net.send_one(
+                        *connection_id,
+                        messages::InterfaceClose {
+                            interface_path: interface.clone(),
+                        },
+                    );
+                }
+                false
+            });
+        }
+
+        block_entities.remove(position);
+        commands.entity(entity).despawn();
+    }
+}
+
+fn add_item_boxes(
+    interface_update: &mut messages::InterfaceItemBoxUpdate,
+    interface_path: &str,
+    item_stacks: &[ItemStack],
+) {
+    for (i, item_stack) in item_stacks.iter().enumerate() {
+        if let Some(item) = item_stack.item() {
+            interface_update.add_itembox(
+                interface_path,
+                i as u32,
+                item.id,
+                item_stack.size(),
+                item.properties["durability"].as_u32(),
+                item.properties["description"].as_str(),
+            );
+        } else {
+            interface_update.add_empty_itembox(interface_path, i as u32);
+        }
+    }
+}
+
+fn build_player_sections(
+    interface: &str,
+    inventory: &ItemStorage,
+) -> messages::InterfaceItemBoxUpdate {
+    let mut interface_update = messages::InterfaceItemBoxUpdate::new(false);
+    add_item_boxes(
+        &mut interface_update,
+        &format!("{}/hotbar", interface),
+        &inventory[0..9],
+    );
+    add_item_boxes(
+        &mut interface_update,
+        &format!("{}/inventory", interface),
+        &inventory[9..36],
+    );
+    return interface_update;
+}
+
+fn open_block_entity_interface(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    world_map: Res<WorldMap>,
+    block_entity_configs: Res<BlockEntityConfigs>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut open_block_entities: ResMut<OpenBlockEntities>,
+    player_query: Query<(&F64GlobalTransform, &Camera, &Reach, &ItemStorage), With<Player>>,
+    block_entity_query: Query<&ItemStorage, Without<Player>>,
+    mut clicks: EventReader<NetworkData<messages::RightClick>>,
+) {
+    for right_click in clicks.read() {
+        let (player_position, player_camera, reach, inventory) =
+            player_query.get(right_click.source.entity()).unwrap();
+
+        let camera_transform = F64Transform {
+            translation: player_position.translation() + player_camera.translation,
+            rotation: player_camera.rotation,
+            ..default()
+        };
+
+        let (block_position, block_id, _) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        let Some(config) = block_entity_configs.ids.get(&block_id) else {
+            continue;
+        };
+        let Some(interface) = &config.interface else {
+            continue;
+        };
+
+        let mut storage_update = messages::InterfaceItemBoxUpdate::new(false);
+        let storage_path = format!("{}/storage", interface);
+        if let Some(storage) = block_entities
+            .get(&block_position)
+            .and_then(|entity| block_entity_query.get(*entity).ok())
+        {
+            add_item_boxes(&mut storage_update, &storage_path, &storage);
+        } else if !block_entities.contains_key(&block_position) {
+            let storage = ItemStorage(vec![ItemStack::default(); config.storage_size]);
+            add_item_boxes(&mut storage_update, &storage_path, &storage);
+            spawn_block_entity(
+                &mut commands,
+                &mut block_entities,
+                block_position,
+                block_id,
+                storage,
+            );
+        }
+
+        open_block_entities.insert(right_click.source.entity(), block_position);
+        net.send_one(
+            right_click.source,
+            messages::InterfaceOpen {
+                interface_path: interface.clone(),
+            },
+        );
+        net.send_one(right_click.source, storage_update);
+        net.send_one(
+            right_click.source,
+            build_player_sections(interface, inventory),
+        );
+    }
+}
+
+// The item stack an interface section and index points to, None if it doesn't exist.
+fn section_item_stack<'a>(
+    section: &str,
+    index: u32,
+    inventory: &'a mut ItemStorage,
+    storage: &'a mut ItemStorage,
+) -> Option<&'a mut ItemStack> {
+    let index = index as usize;
+    match section {
+        "storage" => storage.get_mut(index),
+        "hotbar" if index < 9 => inventory.get_mut(index),
+        "inventory" if index < 27 => inventory.get_mut(9 + index),
+        _ => None,
+    }
+}
+
+// The entity of the block entity whose interface the player has open, and the section of it the
+// interface path points to. None if the path is for another interface.
+fn open_section(
+    player_entity: Entity,
+    interface_path: &str,
+    block_entity_configs: &BlockEntityConfigs,
+    block_entities: &BlockEntities,
+    open_block_entities: &OpenBlockEntities,
+    block_entity_query: &Query<(&BlockEntity, &mut ItemStorage), Without<Player>>,
+) -> Option<(Entity, String)> {
+    let position = open_block_entities.get(&player_entity)?;
+    let entity = *block_entities.get(position)?;
+    let (block_entity, _) = block_entity_query.get(entity).ok()?;
+    let interface = block_entity_configs.ids[&block_entity.block_id]
+        .interface
+        .as_ref()?;
+    let section = interface_path
+        .strip_prefix(interface.as_str())?
+        .strip_prefix('/')?;
+    return Some((entity, section.to_owned()));
+}
+
+fn handle_interface_events(
+    block_entity_configs: Res<BlockEntityConfigs>,
+    block_entities: Res<BlockEntities>,
+    open_block_entities: Res<OpenBlockEntities>,
+    mut player_query: Query<(&mut ItemStorage, &mut HeldItemStack), With<Player>>,
+    mut block_entity_query: Query<(&BlockEntity, &mut ItemStorage), Without<Player>>,
+    mut take_events: EventReader<NetworkData<messages::InterfaceTakeItem>>,
+    mut place_events: EventReader<NetworkData<messages::InterfacePlaceItem>>,
+) {
+    for take_event in take_events.read() {
+        let player_entity = take_event.source.entity();
+        let Some((entity, section)) = open_section(
+            player_entity,
+            &take_event.interface_path,
+            &block_entity_configs,
+            &block_entities,
+            &open_block_entities,
+            &block_entity_query,
+        ) else {
+            continue;
+        };
+        let (_, mut storage) = block_entity_query.get_mut(entity).unwrap();
+        let Ok((mut inventory, mut held_item_stack)) = player_query.get_mut(player_entity) else {
+            continue;
+        };
+
+        let Some(item_stack) =
+            section_item_stack(&section, take_event.from_box, &mut inventory, &mut storage)
+        else {
+            continue;
+        };
+
+        if item_stack.is_empty() {
+            continue;
+        }
+
+        held_item_stack.transfer(item_stack, take_event.quantity);
+    }
+
+    for place_event in place_events.read() {
+        let player_entity = place_event.source.entity();
+        let Some((entity, section)) = open_section(
+            player_entity,
+            &place_event.interface_path,
+            &block_entity_configs,
+            &block_entities,
+            &open_block_entities,
+            &block_entity_query,
+        ) else {
+            continue;
+        };
+        let (_, mut storage) = block_entity_query.get_mut(entity).unwrap();
+        let Ok((mut inventory, mut held_item_stack)) = player_query.get_mut(player_entity) else {
+            continue;
+        };
+
+        if held_item_stack.is_empty() {
+            continue;
+        }
+
+        let Some(item_stack) =
+            section_item_stack(&section, place_event.to_box, &mut inventory, &mut storage)
+        else {
+            continue;
+        };
+
+        // Quantity is only respected if the item box is empty, otherwise it replaces the held
+        // item with the one in the box.
+        item_stack.transfer(&mut held_item_stack, place_event.quantity);
+    }
+}
+
+// Keeps the interfaces of everyone that has a block entity open up to date, both with the block's
+// items and their own inventory.
+fn update_block_entity_interfaces(
+    net: Res<NetworkServer>,
+    block_entity_configs: Res<BlockEntityConfigs>,
+    block_entities: Res<BlockEntities>,
+    mut open_block_entities: ResMut<OpenBlockEntities>,
+    player_query: Query<(&ConnectionId, Ref<ItemStorage>), With<Player>>,
+    block_entity_query: Query<(&BlockEntity, Ref<ItemStorage>), Without<Player>>,
+) {
+    // Players that have disconnected
+    open_block_entities.retain(|player_entity, _| player_query.contains(*player_entity));
+
+    for (player_entity, position) in open_block_entities.iter() {
+        let Some((block_entity, storage)) = block_entities
+            .get(position)
+            .and_then(|entity| block_entity_query.get(*entity).ok())
+        else {
+            continue;
+        };
+        let Some(interface) = &block_entity_configs.ids[&block_entity.block_id].interface else {
+            continue;
+        };
+        let (connection_id, inventory) = player_query.get(*player_entity).unwrap();
+
+        if storage.is_changed() && !storage.is_added() {
+            let mut storage_update = messages::InterfaceItemBoxUpdate::new(false);
+            add_item_boxes(
+                &mut storage_update,
+                &format!("{}/storage", interface),
+                &storage,
+            );
+            net.send_one(*connection_id, storage_update);
+        }
+
+        if inventory.is_changed() {
+            net.send_one(*connection_id, build_player_sections(interface, &inventory));
+        }
+    }
+}
+
+pub(super) fn save_block_entities(
+    database: Res<Database>,
+    block_entity_query: Query<(&BlockEntity, Ref<ItemStorage>), Without<Player>>,
+) {
+    for (block_entity, storage) in block_entity_query.iter() {
+        // Newly spawned block entities were either just loaded or are still empty.
+        if !storage.is_changed() || storage.is_added() {
+            continue;
+        }
+
+        if let Err(err) = database.save_block_entity(block_entity.position, &storage) {
+            error!(
+                "Failed to save the block entity at {}: {}",
+                block_entity.position, err
+            );
+        }
+    }
+}
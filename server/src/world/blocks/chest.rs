@@ -0,0 +1,13 @@
+use bevy::prelude::*;
+
+use super::block_entities::RegisterBlockEntity;
+
+// Three rows of nine.
+const CHEST_SIZE: usize = 27;
+
+pub(super) struct ChestPlugin;
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_block_entity("chest", CHEST_SIZE, "chest");
+    }
+}
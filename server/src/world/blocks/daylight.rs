@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+use fmc_networking::BlockId;
+
+use crate::world::{
+    sky::TimeOfDay,
+    world_map::{BlockUpdate, WorldMap},
+};
+
+use super::{random_tick::RandomTickBlocks, BlockTick, Blocks};
+
+// How far up it looks for something that blocks the sky.
+const SKY_SEARCH: i32 = 64;
+
+// Blocks that have a 'daylight' field in their config are swapped between a day and a night block
+// as the time changes, e.g. lamps that light up when it gets dark, or daylight sensors that turn
+// on in the sun. They are checked on random ticks, so they don't all change at once, but spread
+// out over a little while after dawn and dusk.
+pub(super) struct DaylightPlugin;
+impl Plugin for DaylightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, register_daylight_blocks)
+            .add_systems(Update, swap_daylight_blocks);
+    }
+}
+
+fn register_daylight_blocks(mut random_tick_blocks: ResMut<RandomTickBlocks>) {
+    for (block_id, block) in Blocks::get().blocks.iter().enumerate() {
+        if block.daylight.is_some() {
+            random_tick_blocks.insert(block_id as BlockId);
+        }
+    }
+}
+
+fn swap_daylight_blocks(
+    time: Res<TimeOfDay>,
+    world_map: Res<WorldMap>,
+    mut block_ticks: EventReader<BlockTick>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let blocks = Blocks::get();
+
+    // Light isn't tracked on the server, anything that can be seen through lets the daylight in.
+    // Above the loaded chunks it is assumed to be open.
+    let sees_sky = |position: IVec3| {
+        (1..=SKY_SEARCH).all(|y| match world_map.get_block(position + IVec3::Y * y) {
+            Some(above) => blocks.get_config(&above).is_transparent,
+            None => true,
+        })
+    };
+
+    for tick in block_ticks.read() {
+        let Some(daylight) = blocks.get_config(&tick.block_id).daylight else {
+            continue;
+        };
+
+        let has_daylight = !time.is_night() && (!daylight.needs_sky || sees_sky(tick.position));
+        let block_id = if has_daylight {
+            daylight.day
+        } else {
+            daylight.night
+        };
+
+        if block_id != tick.block_id {
+            block_updates.send(BlockUpdate::Change {
+                position: tick.position,
+                block_id,
+                block_state: None,
+            });
+        }
+    }
+}
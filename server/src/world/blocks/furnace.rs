@@ -1,20 +1,219 @@
-use bevy::prelude::*;
+use bevy::{app::AppExit, prelude::*, utils::HashSet};
+use fmc_networking::{messages, ConnectionId, NetworkServer};
+use serde::{Deserialize, Serialize};
 
-use super::BlockFunctionality;
+use crate::{
+    database::Database,
+    players::Player,
+    utils,
+    world::{
+        items::{
+            crafting::{CraftingTable, Recipes},
+            ItemStack, ItemStorage, Items,
+        },
+        world_map::chunk_manager::ChunkUnloadEvent,
+    },
+};
 
-pub struct FurnacePlugin;
+use super::{
+    block_entities::{save_block_entities, OpenBlockEntities, RegisterBlockEntity},
+    BlockEntities, BlockEntity, Blocks,
+};
+
+// Input, fuel and output
+const FURNACE_SIZE: usize = 3;
+const INPUT_SLOT: usize = 0;
+const FUEL_SLOT: usize = 1;
+const OUTPUT_SLOT: usize = 2;
+
+// Seconds it takes to smelt one item.
+const SMELTING_TIME: f32 = 10.0;
+const SMELTING_COLLECTION: &str = "smelting";
+
+// Furnaces smelt the item in their input slot into the output slot of the "smelting" recipe
+// collection, while burning items with a fuel value from the fuel slot. Fuel only burns while
+// there is something to smelt. How far along the fuel and the smelting are is shown through the
+// "furnace_fuel" and "furnace_progress" interface variables.
+//
+// The burning fuel and smelting progress are saved as the block entity's state. It changes every
+// tick, so it is only saved along with the items, and when the chunk is unloaded or the server
+// stops.
+pub(super) struct FurnacePlugin;
 impl Plugin for FurnacePlugin {
     fn build(&self, app: &mut App) {
-        app //.register_block_functionality("furnace", furnace_setup)
-            .add_systems(Update, furnace_update);
+        app.register_block_entity("furnace", FURNACE_SIZE, "furnace")
+            .add_systems(
+                Update,
+                (
+                    add_furnaces,
+                    smelt,
+                    update_furnace_interfaces.after(smelt),
+                    save_furnaces.after(smelt).after(save_block_entities),
+                ),
+            );
     }
 }
 
-#[derive(Component)]
-struct FurnaceTag;
+#[derive(Component, Default, Serialize, Deserialize)]
+struct Furnace {
+    // Seconds left of the fuel that is burning
+    burn_time: f32,
+    // How long the fuel that is burning lasted when it was put in
+    burn_duration: f32,
+    // Seconds spent smelting the item that is in the input slot
+    progress: f32,
+}
+
+fn add_furnaces(
+    mut commands: Commands,
+    database: Res<Database>,
+    block_entity_query: Query<(Entity, &BlockEntity), Added<BlockEntity>>,
+) {
+    let furnace_id = Blocks::get().get_id("furnace");
+
+    for (entity, block_entity) in block_entity_query.iter() {
+        if block_entity.block_id != furnace_id {
+            continue;
+        }
+
+        let position = block_entity.position;
+        let furnace = match database.load_block_entity_state(position) {
+            Ok(Some(state)) => match serde_json::from_str(&state) {
+                Ok(furnace) => furnace,
+                Err(err) => {
+                    warn!("The furnace at {} has an invalid state: {}", position, err);
+                    Furnace::default()
+                }
+            },
+            Ok(None) => Furnace::default(),
+            Err(err) => {
+                error!("Failed to load the furnace at {}: {}", position, err);
+                Furnace::default()
+            }
+        };
 
-pub fn furnace_setup(commands: &mut Commands, entity: Entity, _: Vec<u8>) {
-    commands.entity(entity).insert(FurnaceTag);
+        commands.entity(entity).insert(furnace);
+    }
 }
 
-pub fn furnace_update() {}
+fn smelt(
+    time: Res<Time>,
+    items: Res<Items>,
+    recipes: Res<Recipes>,
+    mut furnace_query: Query<(&mut Furnace, &mut ItemStorage)>,
+) {
+    let delta = time.delta_seconds();
+    let recipe_collection = recipes.get(SMELTING_COLLECTION);
+
+    for (mut furnace, mut storage) in furnace_query.iter_mut() {
+        let mut input = CraftingTable(vec![storage[INPUT_SLOT].clone()]);
+
+        let output = recipe_collection.get_output(&input);
+        let can_smelt = match output {
+            Some((item, amount)) => {
+                let output = &storage[OUTPUT_SLOT];
+                output.is_empty() || (output.item() == Some(item) && output.capacity() >= amount)
+            }
+            None => false,
+        };
+
+        // The fuel that is burning is kept until there is something to smelt again.
+        if !can_smelt {
+            if furnace.progress > 0.0 {
+                furnace.progress = 0.0;
+            }
+            continue;
+        }
+
+        if furnace.burn_time <= 0.0 {
+            if let Some(fuel) = storage[FUEL_SLOT]
+                .item()
+                .and_then(|item| items.get_config(&item.id).fuel)
+            {
+                storage[FUEL_SLOT].subtract(1);
+                furnace.burn_time = fuel;
+                furnace.burn_duration = fuel;
+            } else {
+                // The smelting is paused until there is more fuel.
+                continue;
+            }
+        }
+
+        furnace.burn_time = (furnace.burn_time - delta).max(0.0);
+        furnace.progress += delta;
+        if furnace.progress < SMELTING_TIME {
+            continue;
+        }
+        furnace.progress = 0.0;
+
+        let (_, output_amount) = output.unwrap();
+        let recipe = recipe_collection.get_recipe(&input).unwrap();
+        if let Some((item, amount)) = recipe.craft(&mut input, output_amount) {
+            let max_stack_size = items.get_config(&item.id).max_stack_size;
+            let mut smelted = ItemStack::new(item, amount, max_stack_size);
+            storage[OUTPUT_SLOT].transfer(&mut smelted, amount);
+        }
+        storage[INPUT_SLOT] = std::mem::take(&mut input[0]);
+    }
+}
+
+fn update_furnace_interfaces(
+    net: Res<NetworkServer>,
+    block_entities: Res<BlockEntities>,
+    open_block_entities: Res<OpenBlockEntities>,
+    player_query: Query<&ConnectionId, With<Player>>,
+    furnace_query: Query<Ref<Furnace>>,
+) {
+    for (player_entity, position) in open_block_entities.iter() {
+        let Some(furnace) = block_entities
+            .get(position)
+            .and_then(|entity| furnace_query.get(*entity).ok())
+        else {
+            continue;
+        };
+
+        // The player might have just opened it.
+        if !furnace.is_changed() && !open_block_entities.is_changed() {
+            continue;
+        }
+
+        let Ok(connection_id) = player_query.get(*player_entity) else {
+            continue;
+        };
+
+        let mut variables = messages::InterfaceVariableUpdate::default();
+        variables.set_number(
+            "furnace_fuel",
+            furnace.burn_time / furnace.burn_duration.max(f32::EPSILON),
+        );
+        variables.set_number("furnace_progress", furnace.progress / SMELTING_TIME);
+        net.send_one(*connection_id, variables);
+    }
+}
+
+fn save_furnaces(
+    database: Res<Database>,
+    furnace_query: Query<(&BlockEntity, &Furnace, Ref<ItemStorage>)>,
+    mut unload_chunk_events: EventReader<ChunkUnloadEvent>,
+    mut exit_events: EventReader<AppExit>,
+) {
+    let unloaded: HashSet<IVec3> = unload_chunk_events.read().map(|event| event.0).collect();
+    let exiting = exit_events.read().count() > 0;
+
+    for (block_entity, furnace, storage) in furnace_query.iter() {
+        let position = block_entity.position;
+        // Same as the storage, newly spawned furnaces were either just loaded or are still empty.
+        let storage_saved = storage.is_changed() && !storage.is_added();
+        if !storage_saved
+            && !exiting
+            && !unloaded.contains(&utils::world_position_to_chunk_position(position))
+        {
+            continue;
+        }
+
+        let state = serde_json::to_string(furnace).unwrap();
+        if let Err(err) = database.save_block_entity_state(position, &state) {
+            error!("Failed to save the furnace at {}: {}", position, err);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::prelude::*;
+
+use crate::world::world_map::{BlockUpdate, WorldMap};
+
+use super::{
+    random_tick::{BlockTick, RegisterRandomTick},
+    Blocks,
+};
+
+// Grass spreads to dirt that is next to it, as long as the dirt has nothing solid on top of it.
+// Grass that is covered by something solid dies and turns back into dirt.
+pub(super) struct GrassPlugin;
+impl Plugin for GrassPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_random_tick("grass")
+            .register_random_tick("dirt")
+            .add_systems(Update, spread_grass);
+    }
+}
+
+fn spread_grass(
+    world_map: Res<WorldMap>,
+    mut block_ticks: EventReader<BlockTick>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let blocks = Blocks::get();
+    let grass = blocks.get_id("grass");
+    let dirt = blocks.get_id("dirt");
+
+    // Light isn't tracked on the server, anything that can be seen through lets the grass grow.
+    let is_covered = |position: IVec3| match world_map.get_block(position + IVec3::Y) {
+        Some(above) => !blocks.get_config(&above).is_transparent,
+        None => true,
+    };
+
+    for tick in block_ticks.read() {
+        if tick.block_id == grass {
+            if is_covered(tick.position) {
+                block_updates.send(BlockUpdate::Change {
+                    position: tick.position,
+                    block_id: dirt,
+                    block_state: None,
+                });
+            }
+        } else if tick.block_id == dirt {
+            if is_covered(tick.position) {
+                continue;
+            }
+
+            // Grass can climb and descend one block.
+            let next_to_grass = (-1..=1).any(|x| {
+                (-1..=1).any(|y| {
+                    (-1..=1).any(|z| {
+                        world_map.get_block(tick.position + IVec3::new(x, y, z)) == Some(grass)
+                    })
+                })
+            });
+
+            if next_to_grass {
+                block_updates.send(BlockUpdate::Change {
+                    position: tick.position,
+                    block_id: grass,
+                    block_state: None,
+                });
+            }
+        }
+    }
+}
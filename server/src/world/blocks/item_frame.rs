@@ -0,0 +1,327 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    math::{DQuat, DVec3},
+    prelude::*,
+};
+use fmc_networking::{messages, NetworkData};
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    database::Database,
+    players::{Camera, EquippedItem, Player, Reach},
+    utils,
+    world::{
+        items::{Item, ItemStack, ItemStorage, Items},
+        models::{Model, ModelBundle, ModelVisibility, Models},
+        world_map::{chunk_manager::ChunkUnloadEvent, BlockUpdate, WorldMap},
+    },
+};
+
+use super::{
+    block_entities::{remove_broken_block_entities, spawn_block_entity, RegisterBlockEntity},
+    BlockEntities, BlockEntity, BlockFace, Blocks,
+};
+
+// Right clicking the frame with an item mounts it on the clicked face, each click after that
+// rotates it by an eighth of a turn. The items are kept in the frame's block entity, one storage
+// slot per face, so they are saved with it. The item is dropped when the frame is broken.
+pub struct ItemFramePlugin;
+impl Plugin for ItemFramePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_storage_block("item_frame", FACES.len())
+            .insert_resource(ItemFrames::default())
+            .add_systems(
+                Update,
+                (
+                    handle_item_frame_clicks,
+                    update_displayed_items,
+                    despawn_unloaded_displays,
+                    remove_displays_from_broken_frames.before(remove_broken_block_entities),
+                ),
+            );
+    }
+}
+
+// The order of the faces in the frame's storage
+const FACES: [BlockFace; 6] = [
+    BlockFace::Front,
+    BlockFace::Back,
+    BlockFace::Right,
+    BlockFace::Left,
+    BlockFace::Top,
+    BlockFace::Bottom,
+];
+
+// Item property that holds the rotation of a mounted item. It is removed before the item leaves
+// the frame so that it still stacks with other items of the same kind.
+const ROTATION_PROPERTY: &str = "frame_rotation";
+
+/// The entities of the models displayed on item frames, by block position and the face they are
+/// mounted on.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ItemFrames(HashMap<(IVec3, BlockFace), Entity>);
+
+// Rotation around the face normal in eighths of a turn
+fn get_rotation(item: &Item) -> u8 {
+    return item
+        .properties
+        .get(ROTATION_PROPERTY)
+        .and_then(|rotation| rotation.as_u64())
+        .unwrap_or(0) as u8;
+}
+
+fn set_rotation(item: &mut Item, rotation: u8) {
+    if !item.properties.is_object() {
+        item.properties = serde_json::Value::Object(serde_json::Map::new());
+    }
+    item.properties[ROTATION_PROPERTY] = rotation.into();
+}
+
+fn remove_rotation(item: &mut Item) {
+    let Some(properties) = item.properties.as_object_mut() else {
+        return;
+    };
+    properties.remove(ROTATION_PROPERTY);
+    if properties.is_empty() {
+        item.properties = serde_json::Value::Null;
+    }
+}
+
+// Rotates the model so it faces out of the block face, then spins it around the face normal.
+fn display_rotation(block_face: BlockFace, rotation: u8) -> DQuat {
+    let normal = block_face.shift_position(IVec3::ZERO).as_dvec3();
+    let spin = rotation as f64 * std::f64::consts::FRAC_PI_4;
+    return DQuat::from_axis_angle(normal, spin) * DQuat::from_rotation_arc(DVec3::Z, normal);
+}
+
+fn handle_item_frame_clicks(
+    mut commands: Commands,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    items: Res<Items>,
+    mut block_entities: ResMut<BlockEntities>,
+    mut player_query: Query<
+        (
+            &mut ItemStorage,
+            &EquippedItem,
+            &F64GlobalTransform,
+            &Camera,
+            &Reach,
+        ),
+        With<Player>,
+    >,
+    mut frame_query: Query<&mut ItemStorage, (With<BlockEntity>, Without<Player>)>,
+    mut clicks: EventReader<NetworkData<messages::RightClick>>,
+) {
+    let item_frame_id = Blocks::get().get_id("item_frame");
+
+    for right_click in clicks.read() {
+        let (mut inventory, equipped_item, player_position, player_camera, reach) =
+            player_query.get_mut(right_click.source.entity()).unwrap();
+
+        let camera_transform = F64Transform {
+            translation: player_position.translation() + player_camera.translation,
+            rotation: player_camera.rotation,
+            ..default()
+        };
+
+        let (block_position, block_id, block_face) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        if block_id != item_frame_id {
+            continue;
+        }
+
+        let face_index = FACES.iter().position(|face| *face == block_face).unwrap();
+
+        let mut frame_storage = match block_entities.get(&block_position) {
+            // Frames spawned this tick don't have their storage yet.
+            Some(entity) => match frame_query.get_mut(*entity) {
+                Ok(storage) => Some(storage),
+                Err(_) => continue,
+            },
+            None => None,
+        };
+
+        if let Some(storage) = frame_storage.as_mut() {
+            if !storage[face_index].is_empty() {
+                let item = storage[face_index].item.as_mut().unwrap();
+                let rotation = (get_rotation(item) + 1) % 8;
+                set_rotation(item, rotation);
+                continue;
+            }
+        }
+
+        let equipped_item = &mut inventory[equipped_item.0];
+        let Some(item) = equipped_item.item().cloned() else {
+            continue;
+        };
+        equipped_item.subtract(1);
+
+        let item_config = items.get_config(&item.id);
+        let item_stack = ItemStack::new(item, 1, item_config.max_stack_size);
+
+        if let Some(storage) = frame_storage.as_mut() {
+            storage[face_index] = item_stack;
+            continue;
+        }
+
+        let mut storage = ItemStorage(vec![ItemStack::default(); FACES.len()]);
+        storage[face_index] = item_stack;
+
+        // Newly spawned block entities are not saved until their storage changes.
+        if let Err(err) = database.save_block_entity(block_position, &storage) {
+            error!(
+                "Failed to save the item frame at {}: {}",
+                block_position, err
+            );
+        }
+
+        spawn_block_entity(
+            &mut commands,
+            &mut block_entities,
+            block_position,
+            block_id,
+            storage,
+        );
+    }
+}
+
+// Spawns the models of the mounted items when a frame is loaded or given an item, and turns them
+// when the items are rotated.
+fn update_displayed_items(
+    mut commands: Commands,
+    items: Res<Items>,
+    models: Res<Models>,
+    mut item_frames: ResMut<ItemFrames>,
+    frame_query: Query<(&BlockEntity, &ItemStorage), Changed<ItemStorage>>,
+    mut display_query: Query<&mut F64Transform>,
+) {
+    let item_frame_id = Blocks::get().get_id("item_frame");
+
+    for (block_entity, storage) in frame_query.iter() {
+        if block_entity.block_id != item_frame_id {
+            continue;
+        }
+
+        let position = block_entity.position;
+
+        for (block_face, item_stack) in FACES.into_iter().zip(storage.iter()) {
+            let Some(item) = item_stack.item() else {
+                continue;
+            };
+            let rotation = display_rotation(block_face, get_rotation(item));
+
+            if let Some(entity) = item_frames.get(&(position, block_face)) {
+                if let Ok(mut transform) = display_query.get_mut(*entity) {
+                    transform.rotation = rotation;
+                }
+                continue;
+            }
+
+            // Scale the model to fit within half a block, and push it out from the face so that
+            // it rests on it.
+            let item_config = items.get_config(&item.id);
+            let aabb = &models.get(&item_config.model_id).aabb;
+            let scale = 0.25 / aabb.half_extents.max_element();
+            let normal = block_face.shift_position(IVec3::ZERO).as_dvec3();
+            let translation = position.as_dvec3()
+                + DVec3::splat(0.5)
+                + normal * (0.5 + aabb.half_extents.z * scale);
+
+            let entity = commands
+                .spawn(ModelBundle {
+                    model: Model::new(item_config.model_id),
+                    visibility: ModelVisibility { is_visible: true },
+                    global_transform: F64GlobalTransform::default(),
+                    transform: F64Transform {
+                        translation,
+                        rotation,
+                        scale: DVec3::splat(scale),
+                    },
+                })
+                .id();
+
+            item_frames.insert((position, block_face), entity);
+        }
+    }
+}
+
+// The frames' block entities are despawned with their chunk, and the models are spawned again
+// when it is loaded.
+fn despawn_unloaded_displays(
+    mut commands: Commands,
+    mut item_frames: ResMut<ItemFrames>,
+    mut unload_chunk_events: EventReader<ChunkUnloadEvent>,
+) {
+    if item_frames.is_empty() {
+        unload_chunk_events.clear();
+        return;
+    }
+
+    let unloaded: HashSet<IVec3> = unload_chunk_events.read().map(|event| event.0).collect();
+    if unloaded.is_empty() {
+        return;
+    }
+
+    item_frames.retain(|(position, _), entity| {
+        if unloaded.contains(&utils::world_position_to_chunk_position(*position)) {
+            commands.entity(*entity).despawn();
+            return false;
+        }
+        return true;
+    });
+}
+
+// The block entity drops the items when the frame is broken, this removes their rotation first
+// and despawns the models.
+fn remove_displays_from_broken_frames(
+    mut commands: Commands,
+    block_entities: Res<BlockEntities>,
+    mut item_frames: ResMut<ItemFrames>,
+    mut frame_query: Query<(&BlockEntity, &mut ItemStorage), Without<Player>>,
+    mut block_updates: EventReader<BlockUpdate>,
+) {
+    if item_frames.is_empty() {
+        block_updates.clear();
+        return;
+    }
+
+    let item_frame_id = Blocks::get().get_id("item_frame");
+
+    for block_update in block_updates.read() {
+        let BlockUpdate::Change {
+            position, block_id, ..
+        } = block_update;
+
+        if *block_id == item_frame_id {
+            continue;
+        }
+
+        for block_face in FACES {
+            if let Some(entity) = item_frames.remove(&(*position, block_face)) {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        let Some(entity) = block_entities.get(position) else {
+            continue;
+        };
+        let Ok((block_entity, mut storage)) = frame_query.get_mut(*entity) else {
+            continue;
+        };
+        if block_entity.block_id != item_frame_id {
+            continue;
+        }
+
+        for item_stack in storage.iter_mut() {
+            if let Some(item) = item_stack.item.as_mut() {
+                remove_rotation(item);
+            }
+        }
+    }
+}
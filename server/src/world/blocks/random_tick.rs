@@ -0,0 +1,126 @@
+use bevy::{prelude::*, utils::HashSet};
+use fmc_networking::BlockId;
+use rand::Rng;
+
+use crate::{
+    bevy_extensions::f64_transform::F64GlobalTransform, constants::CHUNK_SIZE, players::Player,
+    settings::Settings, utils, world::world_map::WorldMap,
+};
+
+use super::Blocks;
+
+// Blocks that change slowly on their own, like grass spreading or crops growing, are updated by
+// random ticks. Each tick a few random blocks are picked from every chunk near the players, and
+// a BlockTick event is sent for the ones whose block has registered for it. How often a block is
+// ticked is left to chance, on average once every CHUNK_SIZE^3/random_tick_speed ticks.
+pub(super) struct RandomTickPlugin;
+impl Plugin for RandomTickPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RandomTickBlocks>()
+            .add_event::<BlockTick>()
+            .add_systems(Startup, resolve_random_tick_blocks)
+            .add_systems(Update, random_tick);
+    }
+}
+
+/// Sent when a block that has registered for random ticks with
+/// [RegisterRandomTick::register_random_tick] is picked.
+#[derive(Event)]
+pub struct BlockTick {
+    pub position: IVec3,
+    pub block_id: BlockId,
+}
+
+#[derive(Resource, Default)]
+pub(super) struct RandomTickBlocks {
+    // Names are registered before the blocks are loaded, they are converted to ids at startup.
+    names: HashSet<String>,
+    ids: HashSet<BlockId>,
+}
+
+impl RandomTickBlocks {
+    /// Register a block by id, for blocks that are only known after they are loaded.
+    pub(super) fn insert(&mut self, block_id: BlockId) {
+        self.ids.insert(block_id);
+    }
+}
+
+pub trait RegisterRandomTick {
+    /// Make the block receive [BlockTick] events. Must be done before startup.
+    fn register_random_tick(&mut self, block_name: &str) -> &mut Self;
+}
+
+impl RegisterRandomTick for App {
+    fn register_random_tick(&mut self, block_name: &str) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(RandomTickBlocks::default)
+            .names
+            .insert(block_name.to_owned());
+        return self;
+    }
+}
+
+fn resolve_random_tick_blocks(mut random_tick_blocks: ResMut<RandomTickBlocks>) {
+    let blocks = Blocks::get();
+
+    let RandomTickBlocks { names, ids } = random_tick_blocks.as_mut();
+    for name in names.iter() {
+        if !blocks.contains_block(name) {
+            panic!(
+                "The block '{}' was registered for random ticks, but there is no block by that name",
+                name
+            );
+        }
+        ids.insert(blocks.get_id(name));
+    }
+}
+
+fn random_tick(
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
+    random_tick_blocks: Res<RandomTickBlocks>,
+    player_query: Query<&F64GlobalTransform, With<Player>>,
+    mut block_ticks: EventWriter<BlockTick>,
+) {
+    if random_tick_blocks.ids.is_empty() || settings.random_tick_speed == 0 {
+        return;
+    }
+
+    // Chunks that are close to several players are only ticked once.
+    let distance = settings.random_tick_distance as i32;
+    let mut chunk_positions = HashSet::new();
+    for transform in player_query.iter() {
+        let origin =
+            utils::world_position_to_chunk_position(transform.translation().floor().as_ivec3());
+        for x in -distance..=distance {
+            for y in -distance..=distance {
+                for z in -distance..=distance {
+                    chunk_positions.insert(origin + IVec3::new(x, y, z) * CHUNK_SIZE as i32);
+                }
+            }
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    for chunk_position in chunk_positions {
+        let Some(chunk) = world_map.get_chunk(&chunk_position) else {
+            continue;
+        };
+
+        // Most chunks are all air or all stone, no need to roll for them.
+        if chunk.is_uniform() && !random_tick_blocks.ids.contains(&chunk[0]) {
+            continue;
+        }
+
+        for _ in 0..settings.random_tick_speed {
+            let index = rng.gen_range(0..CHUNK_SIZE.pow(3));
+            let block_id = chunk[index];
+            if random_tick_blocks.ids.contains(&block_id) {
+                block_ticks.send(BlockTick {
+                    position: chunk_position + utils::block_index_to_position(index),
+                    block_id,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::BlockId;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    bevy_extensions::f64_transform::F64GlobalTransform,
+    mobs::{MobConfigs, MOB_CONFIG_PATH},
+    players::Player,
+    utils,
+    world::world_map::{
+        chunk_manager::{ChunkLoadEvent, ChunkUnloadEvent},
+        BlockUpdate, WorldMap,
+    },
+};
+
+use super::{Blocks, Friction, BLOCK_CONFIG_PATH};
+
+const SPAWNER_CONFIG_PATH: &str = "./resources/server/spawners/";
+
+// How many times it tries to find an open space for each mob before giving up on it.
+const SPAWN_ATTEMPTS: usize = 4;
+
+// Spawners are blocks that periodically spawn mobs around themselves while a player is close by.
+// Which mobs and how often is configured for each kind of spawner block at
+// resources/server/spawners/. The spawners are found by scanning the chunks as they are loaded,
+// so they need no saving of their own.
+pub(super) struct SpawnerPlugin;
+impl Plugin for SpawnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Spawners::default())
+            .add_event::<SpawnMob>()
+            .add_systems(Startup, load_spawner_configs)
+            .add_systems(PostStartup, validate_spawner_mobs)
+            .add_systems(
+                Update,
+                (
+                    add_loaded_spawners,
+                    remove_unloaded_spawners,
+                    update_spawners,
+                    tick_spawners,
+                ),
+            );
+    }
+}
+
+/// Sent by spawners when a mob should be spawned.
+#[derive(Event)]
+pub struct SpawnMob {
+    /// Name of the mob
+    pub mob: String,
+    /// Where its feet should be
+    pub position: DVec3,
+}
+
+#[derive(Deserialize)]
+struct SpawnerConfigJson {
+    // Name of the block that is the spawner
+    block: String,
+    // Name of the mob it spawns
+    mob: String,
+    // Seconds between each time it spawns mobs
+    interval: f32,
+    // How close a player has to be for the spawner to be active
+    activation_range: f64,
+    // How far away from the spawner the mobs can appear, horizontally
+    spawn_range: i32,
+    // Inclusive range of how many mobs are spawned each time
+    count: [u32; 2],
+}
+
+struct SpawnerConfig {
+    mob: String,
+    interval: f32,
+    activation_range: f64,
+    spawn_range: i32,
+    count: [u32; 2],
+}
+
+#[derive(Resource)]
+struct SpawnerConfigs(HashMap<BlockId, SpawnerConfig>);
+
+fn load_spawner_configs(mut commands: Commands) {
+    let blocks = Blocks::get();
+
+    let directory = std::fs::read_dir(SPAWNER_CONFIG_PATH).expect(&format!(
+        "Could not read files from spawner config directory, make sure it is present as '{}'",
+        SPAWNER_CONFIG_PATH
+    ));
+
+    let mut configs = HashMap::new();
+    for entry in directory {
+        let file_path = entry
+            .expect("Failed to read the filenames of the spawner configs")
+            .path();
+
+        let file = match std::fs::File::open(&file_path) {
+            Ok(f) => f,
+            Err(e) => panic!(
+                "Failed to open spawner config at: {}\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        let json: SpawnerConfigJson = match serde_json::from_reader(&file) {
+            Ok(c) => c,
+            Err(e) => panic!(
+                "Couldn't read spawner config from '{}'\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        if !blocks.contains_block(&json.block) {
+            panic!(
+                "Invalid spawner config at '{}', there is no block named '{}'. Make sure a block \
+                by the same name is present at '{}'",
+                file_path.display(),
+                json.block,
+                BLOCK_CONFIG_PATH
+            );
+        }
+
+        if json.count[0] > json.count[1] || json.interval <= 0.0 {
+            panic!(
+                "Invalid spawner config at '{}', the interval must be above zero and the count \
+                must have the lowest first",
+                file_path.display()
+            );
+        }
+
+        let block_id = blocks.get_id(&json.block);
+        if configs.contains_key(&block_id) {
+            panic!(
+                "Invalid spawner config at '{}', there is already a spawner config for the \
+                block '{}'",
+                file_path.display(),
+                json.block
+            );
+        }
+
+        configs.insert(
+            block_id,
+            SpawnerConfig {
+                mob: json.mob,
+                interval: json.interval,
+                activation_range: json.activation_range,
+                spawn_range: json.spawn_range,
+                count: json.count,
+            },
+        );
+    }
+
+    commands.insert_resource(SpawnerConfigs(configs));
+}
+
+// The mobs are loaded at the same time as the spawners, so they are checked once both are done.
+fn validate_spawner_mobs(spawner_configs: Res<SpawnerConfigs>, mob_configs: Res<MobConfigs>) {
+    let blocks = Blocks::get();
+    for (block_id, config) in spawner_configs.0.iter() {
+        if mob_configs.get_id(&config.mob).is_none() {
+            panic!(
+                "Invalid spawner config for the block '{}', there is no mob named '{}'. Make \
+                sure a mob by the same name is present at '{}'",
+                blocks.get_config(block_id).name,
+                config.mob,
+                MOB_CONFIG_PATH
+            );
+        }
+    }
+}
+
+/// The spawners in the loaded chunks, by block position.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct Spawners(HashMap<IVec3, (BlockId, Timer)>);
+
+fn add_loaded_spawners(
+    world_map: Res<WorldMap>,
+    spawner_configs: Res<SpawnerConfigs>,
+    mut spawners: ResMut<Spawners>,
+    mut chunk_load_events: EventReader<ChunkLoadEvent>,
+) {
+    for ChunkLoadEvent(chunk_position) in chunk_load_events.read() {
+        let Some(chunk) = world_map.get_chunk(chunk_position) else {
+            continue;
+        };
+
+        if chunk.is_uniform() {
+            continue;
+        }
+
+        for (index, block_id) in chunk.blocks.iter().enumerate() {
+            if let Some(config) = spawner_configs.0.get(block_id) {
+                spawners.insert(
+                    *chunk_position + utils::block_index_to_position(index),
+                    (
+                        *block_id,
+                        Timer::from_seconds(config.interval, TimerMode::Repeating),
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn remove_unloaded_spawners(
+    mut spawners: ResMut<Spawners>,
+    mut chunk_unload_events: EventReader<ChunkUnloadEvent>,
+) {
+    for ChunkUnloadEvent(chunk_position) in chunk_unload_events.read() {
+        spawners.retain(|position, _| {
+            utils::world_position_to_chunk_position(*position) != *chunk_position
+        });
+    }
+}
+
+// Spawners can be placed and broken like any other block.
+fn update_spawners(
+    spawner_configs: Res<SpawnerConfigs>,
+    mut spawners: ResMut<Spawners>,
+    mut block_updates: EventReader<BlockUpdate>,
+) {
+    for block_update in block_updates.read() {
+        let BlockUpdate::Change {
+            position, block_id, ..
+        } = block_update;
+
+        if let Some(config) = spawner_configs.0.get(block_id) {
+            spawners.insert(
+                *position,
+                (
+                    *block_id,
+                    Timer::from_seconds(config.interval, TimerMode::Repeating),
+                ),
+            );
+        } else {
+            spawners.remove(position);
+        }
+    }
+}
+
+fn tick_spawners(
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    spawner_configs: Res<SpawnerConfigs>,
+    mut spawners: ResMut<Spawners>,
+    player_query: Query<&F64GlobalTransform, With<Player>>,
+    mut spawn_events: EventWriter<SpawnMob>,
+) {
+    let blocks = Blocks::get();
+    let is_open = |position: IVec3| match world_map.get_block(position) {
+        Some(block_id) => matches!(blocks.get_config(&block_id).friction, Friction::Drag(_)),
+        None => false,
+    };
+
+    let mut rng = rand::thread_rng();
+
+    for (position, (block_id, timer)) in spawners.iter_mut() {
+        let config = &spawner_configs.0[block_id];
+
+        // The timer is paused while no players are around.
+        let center = position.as_dvec3() + DVec3::splat(0.5);
+        if !player_query.iter().any(|transform| {
+            transform.translation().distance_squared(center) < config.activation_range.powi(2)
+        }) {
+            continue;
+        }
+
+        timer.tick(time.delta());
+        if !timer.just_finished() {
+            continue;
+        }
+
+        for _ in 0..rng.gen_range(config.count[0]..=config.count[1]) {
+            // Mobs are two blocks tall, and need something to stand on.
+            for _ in 0..SPAWN_ATTEMPTS {
+                let spawn_position = *position
+                    + IVec3::new(
+                        rng.gen_range(-config.spawn_range..=config.spawn_range),
+                        rng.gen_range(-1..=1),
+                        rng.gen_range(-config.spawn_range..=config.spawn_range),
+                    );
+
+                if is_open(spawn_position)
+                    && is_open(spawn_position + IVec3::Y)
+                    && !is_open(spawn_position - IVec3::Y)
+                    && world_map.get_block(spawn_position - IVec3::Y).is_some()
+                {
+                    spawn_events.send(SpawnMob {
+                        mob: config.mob.clone(),
+                        position: spawn_position.as_dvec3() + DVec3::new(0.5, 0.0, 0.5),
+                    });
+                    break;
+                }
+            }
+        }
+    }
+}
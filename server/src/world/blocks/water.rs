@@ -6,7 +6,10 @@ use std::{
 use bevy::prelude::*;
 use fmc_networking::BlockId;
 
-use crate::world::world_map::{BlockUpdate, ChangedBlockEvent, WorldMap};
+use crate::world::{
+    limits::BlockCascades,
+    world_map::{BlockUpdate, ChangedBlockEvent, WorldMap},
+};
 
 use super::{BlockFace, BlockRotation, BlockState, Blocks};
 
@@ -553,7 +556,10 @@ fn pread_water(
     mut update_timer: ResMut<WaterUpdateTimer>,
     mut changed_blocks: EventReader<ChangedBlockEvent>,
     mut block_updates: EventWriter<BlockUpdate>,
+    mut cascades: ResMut<BlockCascades>,
     mut updates: Local<HashMap<IVec3, WaterBlock>>,
+    // The cascade each of the updates is a part of.
+    mut origins: Local<HashMap<IVec3, IVec3>>,
 ) {
     let blocks = Blocks::get();
     let air = blocks.get_id("air");
@@ -561,6 +567,9 @@ fn pread_water(
     for changed_block in changed_blocks.read() {
         // If there's an update waiting to be sent, but the block is changed, the update is stale
         updates.remove(&changed_block.position);
+        origins.remove(&changed_block.position);
+
+        let origin = cascades.take_origin(changed_block.position);
 
         let change_as_water = ChangedBlockAsWater::new(changed_block, &water);
 
@@ -813,11 +822,44 @@ fn pread_water(
                 updates.insert(changed_block.position, water_block);
             }
         }
+
+        // All the updates are made around the changed block, they continue its cascade.
+        for offset in [
+            IVec3::ZERO,
+            IVec3::NEG_Y,
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Z,
+            IVec3::NEG_Z,
+            IVec3::new(1, 0, 1),
+            IVec3::new(1, 0, -1),
+            IVec3::new(-1, 0, 1),
+            IVec3::new(-1, 0, -1),
+        ] {
+            let position = changed_block.position + offset;
+            if updates.contains_key(&position) {
+                origins.insert(position, origin);
+            }
+        }
     }
 
     update_timer.tick(time.delta());
     if update_timer.just_finished() {
-        block_updates.send_batch(updates.drain().filter_map(|(position, water_block)| {
+        // Updates of cascades that have spread too far this second are held back until the next
+        // time.
+        let mut allowed = Vec::with_capacity(updates.len());
+        updates.retain(|position, water_block| {
+            let origin = origins.get(position).copied().unwrap_or(*position);
+            if cascades.allow(origin, *position) {
+                allowed.push((*position, water_block.clone()));
+                false
+            } else {
+                true
+            }
+        });
+        origins.retain(|position, _| updates.contains_key(position));
+
+        block_updates.send_batch(allowed.into_iter().filter_map(|(position, water_block)| {
             let (block_id, block_state) = match water.water_to_block.get(&water_block) {
                 Some(k) => k.clone(),
                 None => (air, None),
@@ -6,6 +6,7 @@ use std::collections::HashMap;
 use super::{Item, ItemId, ItemStack, Items};
 
 mod shaped;
+mod smelting;
 
 pub struct CraftingPlugin;
 impl Plugin for CraftingPlugin {
@@ -47,6 +48,16 @@ fn load_recipes(mut commands: Commands, items: Res<Items>) {
         };
 
         for recipe_json in item_recipes.into_iter() {
+            let output_item = match items.ids.get(&recipe_json.output_item) {
+                Some(id) => *id,
+                None => panic!(
+                    "Error parsing item recipe pattern at: {}\n Item name '{}'\
+                    is not recognized",
+                    file_path.display(),
+                    &recipe_json.output_item
+                ),
+            };
+
             match recipe_json.pattern_type.as_str() {
                 "shaped" => {
                     let (pattern, required_amount): (Vec<Vec<Option<ItemId>>>, Vec<Vec<u32>>) =
@@ -82,16 +93,6 @@ fn load_recipes(mut commands: Commands, items: Res<Items>) {
                         ),
                         };
 
-                    let output_item = match items.ids.get(&recipe_json.output_item) {
-                        Some(id) => *id,
-                        None => panic!(
-                            "Error parsing item recipe pattern at: {}\n Item name '{}'\
-                            is not recognized",
-                            file_path.display(),
-                            &recipe_json.output_item
-                        ),
-                    };
-
                     let recipe = shaped::Recipe {
                         required_amount,
                         output_item: Item {
@@ -110,6 +111,54 @@ fn load_recipes(mut commands: Commands, items: Res<Items>) {
                             Recipe::Shaped(recipe),
                         );
                 }
+                "smelting" => {
+                    let (input_item, required_amount) = match &recipe_json.pattern {
+                        PatternJson::List(list) if list.len() == 1 => {
+                            let (name, amount) = &list[0];
+                            match items.ids.get(name) {
+                                Some(id) if *amount > 0 => (*id, *amount),
+                                Some(_) => panic!(
+                                    "Error parsing item recipe pattern at: {}\n\
+                                    The amount of '{}' must be at least 1",
+                                    file_path.display(),
+                                    name
+                                ),
+                                None => panic!(
+                                    "Error parsing item recipe pattern at: {}\n\
+                                    Item name '{}' is not recognized",
+                                    file_path.display(),
+                                    name
+                                ),
+                            }
+                        }
+                        _ => panic!(
+                            "Error parsing item recipe pattern at: {}\n'pattern_type' is \
+                            'smelting', but the pattern is not a list of a single item. Should \
+                            be like:\n[[\"item\", 1]]\n",
+                            file_path.display()
+                        ),
+                    };
+
+                    let recipe = smelting::Recipe {
+                        required_amount,
+                        output_item: Item {
+                            id: output_item,
+                            properties: serde_json::Value::Object(serde_json::Map::new()),
+                        },
+                        output_amount: recipe_json.output_amount,
+                        data: recipe_json.data,
+                    };
+
+                    recipes
+                        .entry(recipe_json.collection_name)
+                        .or_insert(RecipeCollection::default())
+                        .insert(
+                            Pattern::Smelting(smelting::Pattern {
+                                input: Some(input_item),
+                            }),
+                            Recipe::Smelting(recipe),
+                        );
+                }
                 _ => (),
             }
         }
@@ -152,6 +201,8 @@ enum RequiredJson {
 pub enum Recipe {
     /// Square crafting area where the item position matters.
     Shaped(shaped::Recipe),
+    /// A single item that is smelted in a furnace.
+    Smelting(smelting::Recipe),
     //// Square crafting area where the item position doesn't matter.
     //Unshaped(UnshapedRecipe),
     //// List of crafting items where the order matters
@@ -169,6 +220,7 @@ impl Recipe {
     pub fn craft(&self, input: &mut CraftingTable, amount: u32) -> Option<(Item, u32)> {
         return match self {
             Recipe::Shaped(r) => r.craft(input, amount),
+            Recipe::Smelting(r) => r.craft(input, amount),
         };
     }
 
@@ -176,6 +228,7 @@ impl Recipe {
     fn get_craftable_amount(&self, input: &CraftingTable) -> u32 {
         return match self {
             Recipe::Shaped(r) => r.get_craftable_amount(input),
+            Recipe::Smelting(r) => r.get_craftable_amount(input),
         };
     }
 
@@ -183,6 +236,7 @@ impl Recipe {
     pub fn output_item(&self) -> &Item {
         match self {
             Recipe::Shaped(s) => s.output_item(),
+            Recipe::Smelting(s) => s.output_item(),
         }
     }
 
@@ -190,6 +244,7 @@ impl Recipe {
     fn output_amount(&self) -> u32 {
         match self {
             Recipe::Shaped(s) => s.output_amount,
+            Recipe::Smelting(s) => s.output_amount,
         }
     }
 
@@ -197,6 +252,7 @@ impl Recipe {
     pub fn data(&self) -> &serde_json::Value {
         return match self {
             Recipe::Shaped(s) => s.data(),
+            Recipe::Smelting(s) => s.data(),
         };
     }
 }
@@ -204,6 +260,7 @@ impl Recipe {
 #[derive(Hash, PartialEq, Eq)]
 enum Pattern {
     Shaped(shaped::Pattern),
+    Smelting(smelting::Pattern),
 }
 
 // TODO: Not all recipe collections contain all the Pattern enumerations. Because of this the enumerations are behind a flag.
@@ -214,6 +271,7 @@ enum Pattern {
 #[derive(Default)]
 pub struct RecipeCollection {
     shaped: bool,
+    smelting: bool,
     recipes: HashMap<Pattern, Recipe>,
 }
 
@@ -224,6 +282,10 @@ impl RecipeCollection {
                 self.shaped = true;
                 self.recipes.insert(pattern, recipe);
             }
+            Pattern::Smelting(_) => {
+                self.smelting = true;
+                self.recipes.insert(pattern, recipe);
+            }
         }
     }
 
@@ -231,6 +293,9 @@ impl RecipeCollection {
         if self.shaped {
             let pattern = Pattern::Shaped(shaped::Pattern::from(input.as_slice()));
             return self.recipes.get(&pattern);
+        } else if self.smelting {
+            let pattern = Pattern::Smelting(smelting::Pattern::from(input.as_slice()));
+            return self.recipes.get(&pattern);
         }
         return None;
     }
@@ -0,0 +1,60 @@
+use super::{Item, ItemId, ItemStack};
+
+/// Smelting takes a single stack of items, only which item it is matters.
+#[derive(Hash, PartialEq, Eq)]
+pub struct Pattern {
+    pub(super) input: Option<ItemId>,
+}
+
+impl From<&[ItemStack]> for Pattern {
+    fn from(input: &[ItemStack]) -> Self {
+        // Furnaces have a single input slot, anything after the first stack is ignored.
+        return Pattern {
+            input: input
+                .first()
+                .and_then(|item_stack| item_stack.item())
+                .map(|item| item.id),
+        };
+    }
+}
+
+/// The counterpart to a smelting `Pattern`, holds how many of the input item are consumed to
+/// produce the output.
+pub struct Recipe {
+    pub(super) required_amount: u32,
+    pub(super) output_item: Item,
+    pub(super) output_amount: u32,
+    pub(super) data: serde_json::Value,
+}
+
+// Same as for shaped recipes, the input is assumed to match the pattern.
+impl Recipe {
+    pub(super) fn craft(&self, input: &mut [ItemStack], mut amount: u32) -> Option<(Item, u32)> {
+        amount = std::cmp::min(
+            amount / self.output_amount,
+            self.get_craftable_amount(input) / self.output_amount,
+        );
+
+        if amount > 0 {
+            input[0].subtract(self.required_amount * amount);
+        }
+
+        return Some((self.output_item.clone(), amount * self.output_amount));
+    }
+
+    /// Get how many of the smelting output it is possible to make.
+    pub(super) fn get_craftable_amount(&self, input: &[ItemStack]) -> u32 {
+        return match input.first() {
+            Some(item_stack) => item_stack.size() / self.required_amount * self.output_amount,
+            None => 0,
+        };
+    }
+
+    pub fn output_item(&self) -> &Item {
+        return &self.output_item;
+    }
+
+    pub fn data(&self) -> &serde_json::Value {
+        return &self.data;
+    }
+}
@@ -1,4 +1,4 @@
-use bevy::prelude::*;
+use bevy::{math::DVec3, prelude::*};
 use fmc_networking::BlockId;
 
 use std::collections::{HashMap, HashSet};
@@ -8,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use crate::{
     bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
     database::Database,
+    physics::{PhysicsBundle, Velocity},
+    players::{QuestAction, QuestTrigger},
     utils,
 };
 
@@ -16,7 +18,7 @@ pub mod crafting;
 //pub use dropped::DropItemEvent;
 
 use super::{
-    models::{ModelId, ModelMap},
+    models::{Model, ModelBundle, ModelId, ModelMap, ModelVisibility, Models},
     world_map::BlockUpdate,
 };
 
@@ -34,7 +36,12 @@ impl Plugin for ItemPlugin {
             .add_systems(PreStartup, load_items)
             .add_systems(
                 Update,
-                (pick_up_items, trigger_physics_update_on_block_change),
+                (
+                    merge_dropped_items,
+                    // Items emptied by a merge are despawned at the end of the stage.
+                    pick_up_items.after(merge_dropped_items),
+                    trigger_physics_update_on_block_change,
+                ),
             );
     }
 }
@@ -42,7 +49,9 @@ impl Plugin for ItemPlugin {
 fn load_items(mut commands: Commands, database: Res<Database>) {
     let mut items = Items {
         configs: HashMap::new(),
-        ids: database.load_item_ids(),
+        ids: database
+            .load_item_ids()
+            .unwrap_or_else(|err| panic!("Could not load the item ids: {}", err)),
     };
 
     for (filename, id) in items.ids.iter() {
@@ -64,16 +73,23 @@ fn load_items(mut commands: Commands, database: Res<Database>) {
             ),
         };
 
-        let blocks = database.load_block_ids();
-        let block = match blocks.get(&json.block) {
-            Some(block_id) => *block_id,
-            None => panic!(
-                "Failed to parse item config at: {}\nError: Missing block by the name: {}",
-                &file_path, &json.block
-            ),
+        let blocks = database
+            .load_block_ids()
+            .unwrap_or_else(|err| panic!("Could not load the block ids: {}", err));
+        let block = match &json.block {
+            Some(block_name) => match blocks.get(block_name) {
+                Some(block_id) => Some(*block_id),
+                None => panic!(
+                    "Failed to parse item config at: {}\nError: Missing block by the name: {}",
+                    &file_path, block_name
+                ),
+            },
+            None => None,
         };
 
-        let models = database.load_model_ids();
+        let models = database
+            .load_model_ids()
+            .unwrap_or_else(|err| panic!("Could not load the model ids: {}", err));
         let model_id = match models.get(&json.equip_model) {
             Some(id) => *id,
             None => panic!(
@@ -90,6 +106,7 @@ fn load_items(mut commands: Commands, database: Res<Database>) {
                 model_id,
                 max_stack_size: json.stack_size,
                 categories: json.categories,
+                fuel: json.fuel,
                 properties: json.properties,
             },
         );
@@ -102,13 +119,15 @@ pub struct ItemConfig {
     /// Name shown in interfaces
     pub name: String,
     /// Block placed by the item
-    pub block: BlockId,
+    pub block: Option<BlockId>,
     /// Model used to render the item
     pub model_id: ModelId,
     /// The max amount a stack of this item can store
     pub max_stack_size: u32,
     /// Names used to categorize the item, e.g "helmet". Used to restrict item placement in ui's.
     pub categories: Option<HashSet<String>>,
+    /// How many seconds the item burns for when used as fuel, None if it can't be burned.
+    pub fuel: Option<f32>,
     /// Properties unique to the item
     pub properties: serde_json::Map<String, serde_json::Value>,
 }
@@ -117,11 +136,12 @@ pub struct ItemConfig {
 pub struct ItemConfigJson {
     pub name: String,
     /// Block name of the block this item can place.
-    pub block: String,
+    pub block: Option<String>,
     /// Item model filename
     pub equip_model: String,
     pub stack_size: u32,
     pub categories: Option<HashSet<String>>,
+    pub fuel: Option<f32>,
     #[serde(default)]
     pub properties: serde_json::Map<String, serde_json::Value>,
 }
@@ -140,6 +160,10 @@ impl Items {
         return self.configs.get(item_id).unwrap();
     }
 
+    pub fn get_id(&self, name: &str) -> Option<ItemId> {
+        return self.ids.get(name).copied();
+    }
+
     pub fn clone_ids(&self) -> HashMap<String, ItemId> {
         return self.ids.clone();
     }
@@ -243,21 +267,79 @@ impl ItemStack {
 }
 
 /// Generic component used for entities that need to hold items.
-#[derive(Component, Deref, DerefMut, Serialize, Deserialize)]
+#[derive(Component, Clone, Deref, DerefMut, Serialize, Deserialize)]
 pub struct ItemStorage(pub Vec<ItemStack>);
 
 /// An item that is dropped on the ground.
 #[derive(Component, Deref, DerefMut)]
 pub struct DroppedItem(pub ItemStack);
 
+/// Drop an item stack at the position, it is thrown upwards in a random horizontal direction.
+pub fn spawn_dropped_item(
+    commands: &mut Commands,
+    items: &Items,
+    models: &Models,
+    position: DVec3,
+    item_stack: ItemStack,
+) {
+    let item_config = items.get_config(&item_stack.item().unwrap().id);
+    let model_config = models.get(&item_config.model_id);
+
+    let mut aabb = model_config.aabb.clone();
+
+    // We want to scale the model down to fit in a 0.15xYx0.15 box so the dropped
+    // item is fittingly small. Then extending the smallest horizontal dimension so
+    // that it becomes square.
+    const WIDTH: f64 = 0.075;
+    let max = aabb.half_extents.x.max(aabb.half_extents.z);
+    let scale = WIDTH / max;
+    aabb.half_extents.x = WIDTH;
+    aabb.half_extents.y *= scale;
+    aabb.half_extents.z = WIDTH;
+
+    let random = rand::random::<f64>() * std::f64::consts::TAU;
+    let (velocity_x, velocity_z) = random.sin_cos();
+
+    // For some reason the center has to be zeroed. Does bevy center gltf models?
+    // When the model is scaled does it shift the center(zeroing it like this would
+    // then be slightly off)?
+    aabb.center *= 0.0;
+    let translation = position - DVec3::from(aabb.center);
+    //Offset the aabb slightly downwards to make the item float for clients.
+    aabb.center += DVec3::new(0.0, -0.1, 0.0);
+    commands.spawn((
+        DroppedItem(item_stack),
+        ModelBundle {
+            model: Model::new(item_config.model_id),
+            visibility: ModelVisibility { is_visible: true },
+            global_transform: F64GlobalTransform::default(),
+            transform: F64Transform {
+                translation,
+                scale: DVec3::splat(scale),
+                ..default()
+            },
+        },
+        PhysicsBundle {
+            velocity: Velocity(DVec3::new(velocity_x, 5.5, velocity_z)),
+            ..default()
+        },
+        // TODO: This velocity feels off
+        aabb,
+    ));
+}
+
 fn pick_up_items(
     mut commands: Commands,
     model_map: Res<ModelMap>,
     items: Res<Items>,
-    mut players: Query<(&F64GlobalTransform, &mut ItemStorage), Changed<F64GlobalTransform>>,
+    mut players: Query<
+        (Entity, &F64GlobalTransform, &mut ItemStorage),
+        Changed<F64GlobalTransform>,
+    >,
     mut dropped_items: Query<(Entity, &mut DroppedItem, &F64Transform)>,
+    mut quest_triggers: EventWriter<QuestTrigger>,
 ) {
-    for (player_position, mut player_inventory) in players.iter_mut() {
+    for (player_entity, player_position, mut player_inventory) in players.iter_mut() {
         let chunk_position =
             utils::world_position_to_chunk_position(player_position.translation().as_ivec3());
         let item_entities = match model_map.get_entities(&chunk_position) {
@@ -265,44 +347,66 @@ fn pick_up_items(
             None => continue,
         };
 
-        'outer: for item_entity in item_entities.iter() {
+        for item_entity in item_entities.iter() {
             if let Ok((entity, mut dropped_item, transform)) = dropped_items.get_mut(*item_entity) {
+                // Emptied by a merge or picked up by another player this tick, but not yet
+                // despawned.
+                if dropped_item.is_empty() {
+                    continue;
+                }
+
                 if transform
                     .translation
                     .distance_squared(player_position.translation())
                     < 2.0
                 {
-                    let item_config = items.get_config(&dropped_item.item().unwrap().id);
+                    let item_id = dropped_item.item().unwrap().id;
+                    let item_config = items.get_config(&item_id);
+                    let size = dropped_item.size();
+
+                    'pick_up: {
+                        for item_stack in player_inventory.iter_mut() {
+                            if let Some(item) = item_stack.item() {
+                                if item != dropped_item.item().unwrap()
+                                    || item_stack.capacity() == 0
+                                {
+                                    continue;
+                                }
+                                item_stack.transfer(&mut dropped_item.0, u32::MAX);
+                            }
 
-                    for item_stack in player_inventory.iter_mut() {
-                        if let Some(item) = item_stack.item() {
-                            if item != dropped_item.item().unwrap() || item_stack.capacity() == 0 {
-                                continue;
+                            if dropped_item.is_empty() {
+                                break 'pick_up;
                             }
-                            item_stack.transfer(&mut dropped_item.0, u32::MAX);
                         }
 
-                        if dropped_item.is_empty() {
-                            commands.entity(entity).despawn();
-                            continue 'outer;
+                        // Iterate twice to first fill up existing stacks before filling empty ones.
+                        for item_stack in player_inventory.iter_mut() {
+                            if item_stack.is_empty() {
+                                *item_stack = ItemStack::new(
+                                    dropped_item.item().unwrap().clone(),
+                                    0,
+                                    item_config.max_stack_size,
+                                );
+                                item_stack.transfer(&mut dropped_item.0, u32::MAX);
+                            }
+
+                            if dropped_item.is_empty() {
+                                break 'pick_up;
+                            }
                         }
                     }
 
-                    // Iterate twice to first fill up existing stacks before filling empty ones.
-                    for item_stack in player_inventory.iter_mut() {
-                        if item_stack.is_empty() {
-                            *item_stack = ItemStack::new(
-                                dropped_item.item().unwrap().clone(),
-                                0,
-                                item_config.max_stack_size,
-                            );
-                            item_stack.transfer(&mut dropped_item.0, u32::MAX);
-                        }
+                    if dropped_item.size() < size {
+                        quest_triggers.send(QuestTrigger {
+                            player_entity,
+                            action: QuestAction::CollectItem(item_id),
+                            amount: size - dropped_item.size(),
+                        });
+                    }
 
-                        if dropped_item.is_empty() {
-                            commands.entity(entity).despawn();
-                            continue 'outer;
-                        }
+                    if dropped_item.is_empty() {
+                        commands.entity(entity).despawn();
                     }
                 }
             }
@@ -310,6 +414,60 @@ fn pick_up_items(
     }
 }
 
+// Dropped items of the same kind that come to lie close together are merged into a single stack,
+// so a pile of items doesn't become a pile of entities. Only items that have moved look for others
+// to merge with, those lying still have already been merged.
+fn merge_dropped_items(
+    mut commands: Commands,
+    model_map: Res<ModelMap>,
+    moved_items: Query<(Entity, &F64Transform), (With<DroppedItem>, Changed<F64Transform>)>,
+    mut dropped_items: Query<(&mut DroppedItem, &F64Transform)>,
+) {
+    const MERGE_DISTANCE: f64 = 0.5;
+
+    let mut merged = HashSet::new();
+
+    for (entity, transform) in moved_items.iter() {
+        if merged.contains(&entity) {
+            continue;
+        }
+
+        let chunk_position =
+            utils::world_position_to_chunk_position(transform.translation.floor().as_ivec3());
+        let Some(item_entities) = model_map.get_entities(&chunk_position) else {
+            continue;
+        };
+
+        for other_entity in item_entities.iter() {
+            if *other_entity == entity || merged.contains(other_entity) {
+                continue;
+            }
+
+            let Ok([(mut dropped_item, _), (mut other, other_transform)]) =
+                dropped_items.get_many_mut([entity, *other_entity])
+            else {
+                continue;
+            };
+
+            if dropped_item.is_empty()
+                || other.item() != dropped_item.item()
+                || other.capacity() == 0
+                || other_transform.translation.distance(transform.translation) > MERGE_DISTANCE
+            {
+                continue;
+            }
+
+            other.transfer(&mut dropped_item.0, u32::MAX);
+
+            if dropped_item.is_empty() {
+                commands.entity(entity).despawn();
+                merged.insert(entity);
+                break;
+            }
+        }
+    }
+}
+
 // TODO: This is actually a thing that needs to happen to all physics enabled object. This should
 // be moved to the physics module. All physics objects mapped to their current chunk position as
 // with models.
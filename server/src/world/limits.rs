@@ -0,0 +1,296 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, ConnectionId, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::F64Transform, mobs::Mob, players::Player, settings::Settings,
+    utils,
+};
+
+use super::items::DroppedItem;
+
+// Seconds between each time the chunks are checked for too many entities. It is also how long
+// the block update budget of a cascade lasts.
+const CHECK_INTERVAL: f32 = 1.0;
+// Operators are told about the same kind of problem in the same chunk at most this often.
+const ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Safeguards against contraptions built to lag the server.
+//
+// Chunks can only hold so many dropped items and mobs. When there are more, the oldest are
+// removed until there are few enough.
+//
+// Block updates that cause other block updates, like water spreading, form a cascade that
+// started from a single change. Each cascade can only change so many blocks each second, the
+// systems that drive them hold back the rest until the next second, see [BlockCascades].
+//
+// Operators that are online are told in the chat when any of the limits are hit.
+pub struct LimitsPlugin;
+impl Plugin for LimitsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CheckTimer(Timer::from_seconds(
+            CHECK_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_event::<LimitReached>()
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                number_spawned_entities,
+                (enforce_entity_limits, reset_cascades, alert_operators).chain(),
+            ),
+        );
+    }
+}
+
+/// Keeps count of the block updates of each cascade.
+///
+/// A cascade is named by the position of the change that started it. Systems that react to block
+/// changes by changing other blocks should find the origin of the change they react to with
+/// [BlockCascades::take_origin], and ask [BlockCascades::allow] before making an update that
+/// follows from it.
+#[derive(Resource)]
+pub struct BlockCascades {
+    // Updates allowed per cascade each second, 0 for no limit.
+    limit: u32,
+    // The origin of each update that was allowed, by the position it changed. Entries are moved
+    // to 'old_origins' after a second, and forgotten the second after, so updates that were
+    // overwritten before anything reacted to them don't pile up.
+    origins: HashMap<IVec3, IVec3>,
+    old_origins: HashMap<IVec3, IVec3>,
+    // Updates allowed for each cascade during the current second.
+    counts: HashMap<IVec3, u32>,
+    // Cascades that went over the limit during the current second.
+    limited: HashSet<IVec3>,
+}
+
+impl BlockCascades {
+    /// The origin of the cascade the change at the position is a part of. Changes that were not
+    /// made by a cascade start one of their own.
+    pub fn take_origin(&mut self, position: IVec3) -> IVec3 {
+        return self
+            .origins
+            .remove(&position)
+            .or_else(|| self.old_origins.remove(&position))
+            .unwrap_or(position);
+    }
+
+    /// Ask to change the block at the position as a part of the cascade. Returns false if the
+    /// cascade has made too many updates this second, the update should then be held back and
+    /// asked for again later.
+    pub fn allow(&mut self, origin: IVec3, position: IVec3) -> bool {
+        let count = self.counts.entry(origin).or_default();
+        if self.limit != 0 && *count >= self.limit {
+            self.limited.insert(origin);
+            return false;
+        }
+
+        *count += 1;
+        self.origins.insert(position, origin);
+        return true;
+    }
+}
+
+#[derive(Resource)]
+struct CheckTimer(Timer);
+
+// The order entities were spawned in, the ones with the lowest numbers are removed first.
+#[derive(Component)]
+struct SpawnNumber(u64);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Limit {
+    DroppedItems,
+    Mobs,
+    Cascade,
+}
+
+#[derive(Event)]
+struct LimitReached {
+    limit: Limit,
+    // Chunk the entities were removed from, or origin of the cascade.
+    position: IVec3,
+    // How many entities were removed, or how many updates the cascade was limited to.
+    count: u32,
+}
+
+fn setup(mut commands: Commands, settings: Res<Settings>) {
+    commands.insert_resource(BlockCascades {
+        limit: settings.max_cascade_updates,
+        origins: HashMap::new(),
+        old_origins: HashMap::new(),
+        counts: HashMap::new(),
+        limited: HashSet::new(),
+    });
+}
+
+fn number_spawned_entities(
+    mut commands: Commands,
+    new_entities: Query<Entity, Or<(Added<DroppedItem>, Added<Mob>)>>,
+    mut next_number: Local<u64>,
+) {
+    for entity in new_entities.iter() {
+        commands.entity(entity).insert(SpawnNumber(*next_number));
+        *next_number += 1;
+    }
+}
+
+// Despawns the oldest entities of the chunks that have more than the limit.
+fn remove_oldest<'a>(
+    commands: &mut Commands,
+    entities: impl Iterator<Item = (Entity, &'a F64Transform, &'a SpawnNumber)>,
+    limit: u32,
+) -> Vec<(IVec3, u32)> {
+    let mut chunks: HashMap<IVec3, Vec<(Entity, u64)>> = HashMap::new();
+    for (entity, transform, spawn_number) in entities {
+        let chunk_position =
+            utils::world_position_to_chunk_position(transform.translation.floor().as_ivec3());
+        chunks
+            .entry(chunk_position)
+            .or_default()
+            .push((entity, spawn_number.0));
+    }
+
+    let mut removed = Vec::new();
+    for (chunk_position, mut entities) in chunks {
+        if entities.len() <= limit as usize {
+            continue;
+        }
+
+        entities.sort_unstable_by_key(|(_, spawn_number)| *spawn_number);
+        let excess = entities.len() - limit as usize;
+        for (entity, _) in entities.drain(..excess) {
+            commands.entity(entity).despawn_recursive();
+        }
+        removed.push((chunk_position, excess as u32));
+    }
+
+    return removed;
+}
+
+fn enforce_entity_limits(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut check_timer: ResMut<CheckTimer>,
+    item_query: Query<(Entity, &F64Transform, &SpawnNumber), With<DroppedItem>>,
+    mob_query: Query<(Entity, &F64Transform, &SpawnNumber), With<Mob>>,
+    mut limit_events: EventWriter<LimitReached>,
+) {
+    check_timer.0.tick(time.delta());
+    if !check_timer.0.just_finished() {
+        return;
+    }
+
+    if settings.max_dropped_items_per_chunk != 0 {
+        let limit = settings.max_dropped_items_per_chunk;
+        for (position, count) in remove_oldest(&mut commands, item_query.iter(), limit) {
+            limit_events.send(LimitReached {
+                limit: Limit::DroppedItems,
+                position,
+                count,
+            });
+        }
+    }
+
+    if settings.max_mobs_per_chunk != 0 {
+        let limit = settings.max_mobs_per_chunk;
+        for (position, count) in remove_oldest(&mut commands, mob_query.iter(), limit) {
+            limit_events.send(LimitReached {
+                limit: Limit::Mobs,
+                position,
+                count,
+            });
+        }
+    }
+}
+
+// Starts a new second for the cascades.
+fn reset_cascades(
+    check_timer: Res<CheckTimer>,
+    mut cascades: ResMut<BlockCascades>,
+    mut limit_events: EventWriter<LimitReached>,
+) {
+    if !check_timer.0.just_finished() {
+        return;
+    }
+
+    let limit = cascades.limit;
+    for origin in cascades.limited.drain() {
+        limit_events.send(LimitReached {
+            limit: Limit::Cascade,
+            position: origin,
+            count: limit,
+        });
+    }
+
+    cascades.counts.clear();
+    cascades.old_origins = std::mem::take(&mut cascades.origins);
+}
+
+fn alert_operators(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    player_query: Query<(&Player, &ConnectionId)>,
+    mut limit_events: EventReader<LimitReached>,
+    mut last_alerts: Local<HashMap<(Limit, IVec3), Instant>>,
+) {
+    for event in limit_events.read() {
+        let (key, args) = match event.limit {
+            Limit::DroppedItems => (
+                "limits.dropped_items",
+                vec![
+                    event.count.to_string(),
+                    event.position.x.to_string(),
+                    event.position.y.to_string(),
+                    event.position.z.to_string(),
+                ],
+            ),
+            Limit::Mobs => (
+                "limits.mobs",
+                vec![
+                    event.count.to_string(),
+                    event.position.x.to_string(),
+                    event.position.y.to_string(),
+                    event.position.z.to_string(),
+                ],
+            ),
+            Limit::Cascade => (
+                "limits.cascade",
+                vec![
+                    event.position.x.to_string(),
+                    event.position.y.to_string(),
+                    event.position.z.to_string(),
+                    event.count.to_string(),
+                ],
+            ),
+        };
+
+        let alert = (event.limit, event.position);
+        if last_alerts
+            .get(&alert)
+            .is_some_and(|last_alert| last_alert.elapsed() < ALERT_INTERVAL)
+        {
+            continue;
+        }
+        last_alerts.insert(alert, Instant::now());
+
+        warn!("Limit reached, {}: {}", key, args.join(" "));
+
+        let operators = player_query
+            .iter()
+            .filter(|(player, _)| settings.is_operator(&player.id))
+            .map(|(_, connection_id)| connection_id);
+        net.send_many(
+            operators,
+            messages::ChatMessageServer::translated(key, args),
+        );
+    }
+
+    last_alerts.retain(|_, last_alert| last_alert.elapsed() < ALERT_INTERVAL);
+}
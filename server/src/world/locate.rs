@@ -0,0 +1,206 @@
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, NetworkData, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::F64GlobalTransform,
+    players::{EquippedItem, Player},
+    settings::Settings,
+    world::{
+        items::{Item, ItemStorage, Items, ITEM_CONFIG_PATH},
+        world_map::terrain_generation::TerrainGenerator,
+    },
+};
+
+// How many regions of structures away from the player '/locate' searches. A region is 128 blocks
+// across.
+const LOCATE_DISTANCE: i32 = 32;
+// How many regions away explorer maps search.
+const MAP_DISTANCE: i32 = 16;
+
+// Operators can find the closest structure of a kind with '/locate <structure>'. The structures
+// are found from the seed alone, so the chunks don't need to have been generated.
+//
+// Explorer maps do the same for everyone. They are items with a "structure" property, and the
+// first time one is used it is given the position of the closest structure of that kind. After
+// that it keeps leading to the same structure, wherever it is used.
+pub struct LocatePlugin;
+impl Plugin for LocatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, validate_explorer_maps)
+            .add_systems(Update, (handle_locate_commands, use_explorer_maps));
+    }
+}
+
+fn validate_explorer_maps(items: Res<Items>) {
+    for (item_name, item_id) in items.clone_ids() {
+        let Some(structure) = items.get_config(&item_id).properties.get("structure") else {
+            continue;
+        };
+
+        let is_structure = structure.as_str().is_some_and(|structure| {
+            TerrainGenerator::structure_names().any(|name| name == structure)
+        });
+        if !is_structure {
+            panic!(
+                "Failed while validating the explorer maps. The item '{}' leads to the structure \
+                {}, but there is no such structure. Change its 'structure' property at '{}' to \
+                one of: {}",
+                item_name,
+                structure,
+                ITEM_CONFIG_PATH,
+                TerrainGenerator::structure_names()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+}
+
+fn handle_locate_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    terrain_generator: Res<TerrainGenerator>,
+    player_query: Query<(&Player, &F64GlobalTransform)>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/locate") {
+            continue;
+        }
+
+        let Ok((player, transform)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        if !settings.is_operator(&player.id) {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("locate.not_operator", vec![]),
+            );
+            continue;
+        }
+
+        let structure_names = TerrainGenerator::structure_names()
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let (Some(name), None) = (words.next(), words.next()) else {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("locate.usage", vec![structure_names]),
+            );
+            continue;
+        };
+
+        if !TerrainGenerator::structure_names().any(|structure| structure == name) {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(
+                    "locate.unknown",
+                    vec![name.to_owned(), structure_names],
+                ),
+            );
+            continue;
+        }
+
+        let position = transform.translation().as_ivec3();
+        let reply = match terrain_generator.locate_structure(name, position, LOCATE_DISTANCE) {
+            Some(found) => messages::ChatMessageServer::translated(
+                "locate.found",
+                vec![
+                    name.to_owned(),
+                    found.x.to_string(),
+                    found.y.to_string(),
+                    found.z.to_string(),
+                    distance(position, found).to_string(),
+                ],
+            ),
+            None => {
+                messages::ChatMessageServer::translated("locate.not_found", vec![name.to_owned()])
+            }
+        };
+        net.send_one(chat_message.source, reply);
+    }
+}
+
+// The position of the structure the map leads to, None if it has not been used yet.
+fn map_target(item: &Item) -> Option<IVec3> {
+    let target = item.properties["target"].as_array()?;
+    let coordinate = |index: usize| target.get(index)?.as_i64().map(|value| value as i32);
+    return Some(IVec3::new(coordinate(0)?, coordinate(1)?, coordinate(2)?));
+}
+
+// Horizontal distance in blocks, rounded.
+fn distance(from: IVec3, to: IVec3) -> u32 {
+    let difference = (to - from).as_dvec3() * DVec3::new(1.0, 0.0, 1.0);
+    return difference.length().round() as u32;
+}
+
+fn use_explorer_maps(
+    net: Res<NetworkServer>,
+    items: Res<Items>,
+    terrain_generator: Res<TerrainGenerator>,
+    mut player_query: Query<(&mut ItemStorage, &EquippedItem, &F64GlobalTransform), With<Player>>,
+    mut clicks: EventReader<NetworkData<messages::RightClick>>,
+) {
+    for right_click in clicks.read() {
+        let Ok((mut inventory, equipped_item, transform)) =
+            player_query.get_mut(right_click.source.entity())
+        else {
+            continue;
+        };
+
+        let Some(item) = inventory[equipped_item.0].item() else {
+            continue;
+        };
+        let Some(structure) = items
+            .get_config(&item.id)
+            .properties
+            .get("structure")
+            .and_then(|structure| structure.as_str())
+        else {
+            continue;
+        };
+
+        let position = transform.translation().as_ivec3();
+
+        let target = match map_target(item) {
+            Some(target) => target,
+            None => {
+                let Some(target) =
+                    terrain_generator.locate_structure(structure, position, MAP_DISTANCE)
+                else {
+                    net.send_one(
+                        right_click.source,
+                        messages::ChatMessageServer::translated(
+                            "explorer_map.not_found",
+                            vec![structure.to_owned()],
+                        ),
+                    );
+                    continue;
+                };
+
+                // Changing the item makes the inventory update.
+                let item = inventory[equipped_item.0].item.as_mut().unwrap();
+                item.properties["target"] = serde_json::json!([target.x, target.y, target.z]);
+                item.properties["description"] =
+                    format!("Leads to the {} at {}, {}", structure, target.x, target.z).into();
+                target
+            }
+        };
+
+        net.send_one(
+            right_click.source,
+            messages::ChatMessageServer::translated(
+                "explorer_map.direction",
+                vec![
+                    structure.to_owned(),
+                    distance(position, target).to_string(),
+                    target.x.to_string(),
+                    target.z.to_string(),
+                ],
+            ),
+        );
+    }
+}
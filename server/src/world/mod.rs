@@ -8,40 +8,59 @@ use crate::{database::Database, settings::Settings};
 pub mod blocks;
 /// Manages the items
 pub mod items;
+/// Safeguards against lag machines.
+pub mod limits;
+/// Finding structures with '/locate' and explorer maps.
+mod locate;
 /// Keeps track of models sent to the client.
 pub mod models;
-mod sky;
+/// Decorative pictures hung on walls.
+pub mod paintings;
+/// Day and night cycle.
+pub mod sky;
+/// Sounds of blocks and players.
+pub mod sounds;
+/// Clear skies, rain and storms.
+pub mod weather;
 /// Stores the world map and handles changes.
 pub mod world_map;
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(blocks::BlockPlugin)
+        app.add_plugins(sounds::SoundPlugin)
+            .add_plugins(blocks::BlockPlugin)
             .add_plugins(items::ItemPlugin)
             .add_plugins(models::ModelPlugin)
             .add_plugins(world_map::WorldMapPlugin)
+            .add_plugins(paintings::PaintingPlugin)
+            .add_plugins(locate::LocatePlugin)
+            .add_plugins(limits::LimitsPlugin)
             .add_plugins(sky::SkyPlugin)
+            .add_plugins(weather::WeatherPlugin)
             .add_systems(PreStartup, load_world_properties)
+            // Last, so changes made while the server is shutting down are saved too.
             .add_systems(
-                Update,
+                Last,
                 save_world_properties.run_if(resource_changed::<WorldProperties>()),
             );
     }
 }
 
 fn load_world_properties(mut commands: Commands, database: Res<Database>) {
-    let properties = if let Some(properties) = database.load_world_properties() {
-        properties
-    } else {
-        WorldProperties::default()
+    let properties = match database.load_world_properties() {
+        Ok(Some(properties)) => properties,
+        Ok(None) => WorldProperties::default(),
+        Err(err) => panic!("Could not load the world properties: {}", err),
     };
 
     commands.insert_resource(properties);
 }
 
 fn save_world_properties(database: Res<Database>, properties: Res<WorldProperties>) {
-    database.save_world_properties(&properties);
+    if let Err(err) = database.save_world_properties(&properties) {
+        error!("Failed to save the world properties: {}", err);
+    }
 }
 
 #[derive(Default, Serialize, Deserialize, Resource)]
@@ -49,6 +68,12 @@ pub struct WorldProperties {
     // TODO: This must be set to a valid spawn point when first inserted, currently it is just
     // ignored.
     pub spawn_point: SpawnPoint,
+    /// Seconds since dawn. Stored now and then, so the day continues from where it was after a
+    /// restart.
+    #[serde(default)]
+    pub time_of_day: f32,
+    #[serde(default)]
+    pub weather: weather::WeatherProperties,
 }
 
 /// The default spawn point, as opposed to the unique spawn point of a player.
@@ -1,13 +1,16 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     io::{BufReader, Read},
+    time::{Duration, Instant},
 };
 
-use bevy::{math::DVec3, prelude::*};
+use bevy::{core::FrameCount, ecs::query::ReadOnlyWorldQuery, math::DVec3, prelude::*};
 use fmc_networking::{messages, ConnectionId, NetworkServer};
+use serde::Deserialize;
 
 use crate::{
     bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    constants::CHUNK_SIZE,
     database::Database,
     physics::shapes::Aabb,
     utils,
@@ -18,6 +21,13 @@ use crate::{
 //use super::world_map::chunk_manager::ChunkUnloadEvent;
 
 pub const MODEL_PATH: &str = "./resources/client/textures/models/";
+// Optional collision boxes and hitboxes for the models, by model name. Models that aren't
+// configured collide with the bounds of their mesh, and have no hitboxes.
+const MODEL_CONFIG_PATH: &str = "./resources/server/models.json";
+
+// How long the positions of models with hitboxes are remembered. Hits from players with a
+// higher latency are only compensated for this much.
+const MAX_REWIND: Duration = Duration::from_millis(500);
 
 pub type ModelId = u32;
 
@@ -33,6 +43,9 @@ impl Plugin for ModelPlugin {
                     update_model_transforms,
                     update_model_assets,
                     update_visibility,
+                    update_name_tags.after(update_visibility),
+                    add_transform_history,
+                    record_transform_history,
                 ),
             )
             // TODO: Maybe all of these systems should be PostUpdate. This way Update is the do
@@ -40,12 +53,14 @@ impl Plugin for ModelPlugin {
             //
             // XXX: PostUpdate because RemovedComponents is only available from the stage it was
             // removed up to CoreStage::Last.
-            .add_systems(PostUpdate, remove_models);
+            .add_systems(PostUpdate, (remove_name_tags, remove_models).chain());
     }
 }
 
 fn load_models(mut commands: Commands, database: Res<Database>) {
-    let ids = database.load_model_ids();
+    let ids = database
+        .load_model_ids()
+        .unwrap_or_else(|err| panic!("Could not load the model ids: {}", err));
 
     let mut to_check = ids.clone();
     let mut configs = HashMap::with_capacity(ids.len());
@@ -79,6 +94,7 @@ fn load_models(mut commands: Commands, database: Res<Database>) {
                 id,
                 ModelConfig {
                     aabb: Aabb::from_min_max(DVec3::ZERO, DVec3::ONE),
+                    hitboxes: Vec::new(),
                 },
             );
         } else if extension == "glb" || extension == "gltf" {
@@ -125,6 +141,7 @@ fn load_models(mut commands: Commands, database: Res<Database>) {
                 id,
                 ModelConfig {
                     aabb: Aabb::from_min_max(min, max),
+                    hitboxes: Vec::new(),
                 },
             );
         } else {
@@ -140,9 +157,88 @@ fn load_models(mut commands: Commands, database: Res<Database>) {
         );
     }
 
+    load_model_config(&ids, &mut configs);
+
     commands.insert_resource(Models { ids, configs });
 }
 
+#[derive(Deserialize)]
+struct BoxJson {
+    min: [f64; 3],
+    max: [f64; 3],
+}
+
+impl BoxJson {
+    fn to_aabb(&self, model_name: &str) -> Aabb {
+        let min = DVec3::from(self.min);
+        let max = DVec3::from(self.max);
+        if min.cmpgt(max).any() {
+            panic!(
+                "Invalid model config at '{}', a box of the model '{}' has a min that is larger \
+                than its max",
+                MODEL_CONFIG_PATH, model_name
+            );
+        }
+        return Aabb::from_min_max(min, max);
+    }
+}
+
+#[derive(Deserialize)]
+struct HitboxJson {
+    name: String,
+    #[serde(flatten)]
+    bounds: BoxJson,
+}
+
+#[derive(Deserialize)]
+struct ModelConfigJson {
+    // Replaces the bounds of the mesh when colliding with the world.
+    collision: Option<BoxJson>,
+    #[serde(default)]
+    hitboxes: Vec<HitboxJson>,
+}
+
+fn load_model_config(ids: &HashMap<String, u32>, configs: &mut HashMap<u32, ModelConfig>) {
+    let file = match std::fs::File::open(MODEL_CONFIG_PATH) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+        Err(e) => panic!(
+            "Failed to open model config at: {}\nError: {}",
+            MODEL_CONFIG_PATH, e
+        ),
+    };
+
+    let json: HashMap<String, ModelConfigJson> = match serde_json::from_reader(&file) {
+        Ok(c) => c,
+        Err(e) => panic!(
+            "Couldn't read model config from '{}'\nError: {}",
+            MODEL_CONFIG_PATH, e
+        ),
+    };
+
+    for (name, model_json) in json {
+        let Some(config) = ids.get(&name).and_then(|id| configs.get_mut(id)) else {
+            panic!(
+                "Invalid model config at '{}', there is no model named '{}'",
+                MODEL_CONFIG_PATH, name
+            );
+        };
+
+        if let Some(collision) = &model_json.collision {
+            config.aabb = collision.to_aabb(&name);
+        }
+
+        config.hitboxes = model_json
+            .hitboxes
+            .iter()
+            .map(|hitbox| Hitbox {
+                name: hitbox.name.clone(),
+                aabb: hitbox.bounds.to_aabb(&name),
+            })
+            .collect();
+    }
+}
+
 #[derive(Bundle)]
 pub struct ModelBundle {
     pub model: Model,
@@ -156,6 +252,10 @@ pub struct Model {
     pub asset_id: ModelId,
     pub idle_animation_id: Option<u32>,
     pub moving_animation_id: Option<u32>,
+    /// Color the model is multiplied by, in srgb. Lowering the alpha makes it transparent, which
+    /// can be used to e.g. fade out models or show previews. Mobs of a different size should
+    /// change the scale of their transform instead of having their own asset.
+    pub tint: Vec4,
 }
 
 impl Model {
@@ -164,6 +264,7 @@ impl Model {
             asset_id: id,
             idle_animation_id: None,
             moving_animation_id: None,
+            tint: Vec4::ONE,
         };
     }
 }
@@ -179,7 +280,57 @@ impl Default for ModelVisibility {
     }
 }
 
+/// A name shown above the model, e.g. the name of a player.
+#[derive(Component)]
+pub struct NameTag {
+    pub name: String,
+    /// Color of the name, as a hex string.
+    pub color: String,
+    /// Players that see the name tag however far away the model is, to the rest it is only shown
+    /// up close.
+    pub always_visible_to: HashSet<Entity>,
+}
+
+impl NameTag {
+    fn to_message(&self, model_entity: Entity, viewer: ConnectionId) -> messages::ModelNameTag {
+        return messages::ModelNameTag {
+            id: model_entity.index(),
+            name: self.name.clone(),
+            color: self.color.clone(),
+            always_visible: self.always_visible_to.contains(&viewer.entity()),
+        };
+    }
+}
+
+/// The recent positions of a model that has hitboxes. Players see the world as it was when it
+/// reached them, so hits are checked against where the model was at that time instead of where it
+/// is when the hit arrives at the server.
+#[derive(Component, Default)]
+pub struct TransformHistory(VecDeque<(Instant, F64GlobalTransform)>);
+
+impl TransformHistory {
+    /// The transform the model had at the given time. If it is older than what is remembered,
+    /// the oldest transform is returned.
+    pub fn get(&self, time: Instant) -> Option<&F64GlobalTransform> {
+        return self
+            .0
+            .iter()
+            .rev()
+            .find(|(recorded, _)| *recorded <= time)
+            .or(self.0.front())
+            .map(|(_, transform)| transform);
+    }
+}
+
 pub struct ModelConfig {
+    /// Box used when the model collides with the world.
+    pub aabb: Aabb,
+    /// Parts of the model that can be hit by players.
+    pub hitboxes: Vec<Hitbox>,
+}
+
+pub struct Hitbox {
+    pub name: String,
     pub aabb: Aabb,
 }
 
@@ -208,6 +359,127 @@ impl Models {
     pub fn clone_ids(&self) -> HashMap<String, u32> {
         return self.ids.clone();
     }
+
+    /// The hitboxes of all models that have them, in the form they are sent to the clients.
+    pub fn clone_hitboxes(&self) -> HashMap<u32, Vec<messages::Hitbox>> {
+        return self
+            .configs
+            .iter()
+            .filter(|(_, config)| !config.hitboxes.is_empty())
+            .map(|(id, config)| {
+                let hitboxes = config
+                    .hitboxes
+                    .iter()
+                    .map(|hitbox| messages::Hitbox {
+                        name: hitbox.name.clone(),
+                        min: hitbox.aabb.min().as_vec3(),
+                        max: hitbox.aabb.max().as_vec3(),
+                    })
+                    .collect();
+                (*id, hitboxes)
+            })
+            .collect();
+    }
+}
+
+/// Find the closest model hitbox the transform is looking at within the distance. Returns the
+/// entity of the model, the name of the hitbox, and the distance to it. Models the `ignore`
+/// function returns true for are skipped, e.g. the model of the player that is looking.
+///
+/// Models are moved back to where they were `rewind` ago, usually the latency of the player, so
+/// that what they hit is what they saw on their screen. Only models with a [TransformHistory] can
+/// be moved back, and only by a limited amount.
+pub fn raycast_to_model<'a, F: ReadOnlyWorldQuery>(
+    models: &'a Models,
+    model_map: &ModelMap,
+    model_query: &Query<
+        (
+            &Model,
+            &ModelVisibility,
+            &F64GlobalTransform,
+            Option<&TransformHistory>,
+        ),
+        F,
+    >,
+    transform: &F64Transform,
+    distance: f64,
+    rewind: Duration,
+    ignore: impl Fn(Entity) -> bool,
+) -> Option<(Entity, &'a str, f64)> {
+    let rewound_time = Instant::now() - rewind.min(MAX_REWIND);
+
+    let origin = transform.translation;
+    let forward = transform.forward();
+    let end = origin + forward * distance;
+
+    // Models are stored by the chunk their origin is in, while their hitboxes might extend
+    // into the neighbouring chunks, so a little extra is searched. It also covers models that
+    // have moved to another chunk since the time that is rewound to.
+    const MARGIN: f64 = 2.0;
+    let start_chunk =
+        utils::world_position_to_chunk_position((origin.min(end) - MARGIN).floor().as_ivec3());
+    let end_chunk =
+        utils::world_position_to_chunk_position((origin.max(end) + MARGIN).floor().as_ivec3());
+
+    let mut closest: Option<(Entity, &str, f64)> = None;
+
+    for x in (start_chunk.x..=end_chunk.x).step_by(CHUNK_SIZE) {
+        for y in (start_chunk.y..=end_chunk.y).step_by(CHUNK_SIZE) {
+            for z in (start_chunk.z..=end_chunk.z).step_by(CHUNK_SIZE) {
+                let Some(entities) = model_map.get_entities(&IVec3::new(x, y, z)) else {
+                    continue;
+                };
+
+                for entity in entities.iter() {
+                    if ignore(*entity) {
+                        continue;
+                    }
+
+                    let Ok((model, visibility, global_transform, history)) =
+                        model_query.get(*entity)
+                    else {
+                        continue;
+                    };
+
+                    let config = models.get(&model.asset_id);
+                    if !visibility.is_visible || config.hitboxes.is_empty() {
+                        continue;
+                    }
+
+                    let global_transform = match history {
+                        Some(history) if !rewind.is_zero() => {
+                            history.get(rewound_time).unwrap_or(global_transform)
+                        }
+                        _ => global_transform,
+                    };
+
+                    // Move the ray into the model's coordinates instead of moving the hitboxes.
+                    // The distances stay the same as the direction is transformed too.
+                    let inverse = global_transform.affine().inverse();
+                    let local_origin = inverse.transform_point3(origin);
+                    let local_direction = inverse.transform_vector3(forward);
+
+                    for hitbox in config.hitboxes.iter() {
+                        let Some(hit_distance) =
+                            hitbox.aabb.ray_intersection(local_origin, local_direction)
+                        else {
+                            continue;
+                        };
+
+                        if hit_distance > distance
+                            || closest.is_some_and(|(_, _, closest)| closest <= hit_distance)
+                        {
+                            continue;
+                        }
+
+                        closest = Some((*entity, &hitbox.name, hit_distance));
+                    }
+                }
+            }
+        }
+    }
+
+    return closest;
 }
 
 /// Keeps track of which chunk every entity with a model is currently in.
@@ -279,6 +551,7 @@ fn remove_models(
 // TODO: Split position, rotation and scale into packets?
 fn update_model_transforms(
     net: Res<NetworkServer>,
+    tick: Res<FrameCount>,
     chunk_subscriptions: Res<ChunkSubscriptions>,
     mut model_map: ResMut<ModelMap>,
     model_query: Query<
@@ -305,6 +578,7 @@ fn update_model_transforms(
             subs,
             messages::ModelUpdateTransform {
                 id: entity.index(),
+                tick: tick.0,
                 position: transform.translation,
                 rotation: transform.rotation.as_f32(),
                 scale: transform.scale.as_vec3(),
@@ -313,6 +587,41 @@ fn update_model_transforms(
     }
 }
 
+fn add_transform_history(
+    mut commands: Commands,
+    models: Res<Models>,
+    model_query: Query<(Entity, &Model), Added<Model>>,
+) {
+    for (entity, model) in model_query.iter() {
+        if !models.get(&model.asset_id).hitboxes.is_empty() {
+            commands.entity(entity).insert(TransformHistory::default());
+        }
+    }
+}
+
+fn record_transform_history(
+    mut model_query: Query<
+        (&F64GlobalTransform, &mut TransformHistory),
+        Changed<F64GlobalTransform>,
+    >,
+) {
+    let now = Instant::now();
+
+    for (global_transform, mut history) in model_query.iter_mut() {
+        history.0.push_back((now, *global_transform));
+
+        // The oldest transform is kept until the one after it is old enough to be used in its
+        // place, the model might not have moved since.
+        while history
+            .0
+            .get(1)
+            .is_some_and(|(recorded, _)| now - *recorded >= MAX_REWIND)
+        {
+            history.0.pop_front();
+        }
+    }
+}
+
 fn update_model_assets(
     net: Res<NetworkServer>,
     chunk_subscriptions: Res<ChunkSubscriptions>,
@@ -337,6 +646,7 @@ fn update_model_assets(
                 asset: model.asset_id,
                 idle_animation: model.idle_animation_id,
                 moving_animation: model.moving_animation_id,
+                tint: model.tint,
             },
         );
     }
@@ -346,11 +656,17 @@ fn update_visibility(
     net: Res<NetworkServer>,
     chunk_subscriptions: Res<ChunkSubscriptions>,
     model_query: Query<
-        (Entity, &Model, &ModelVisibility, &F64GlobalTransform),
+        (
+            Entity,
+            &Model,
+            &ModelVisibility,
+            &F64GlobalTransform,
+            Option<&NameTag>,
+        ),
         Changed<ModelVisibility>,
     >,
 ) {
-    for (entity, model, visibility, transform) in model_query.iter() {
+    for (entity, model, visibility, transform, name_tag) in model_query.iter() {
         let transform = transform.compute_transform();
 
         let chunk_pos = utils::world_position_to_chunk_position(transform.translation.as_ivec3());
@@ -361,8 +677,9 @@ fn update_visibility(
         };
 
         if visibility.is_visible {
+            let subs: Vec<ConnectionId> = subs.into_iter().copied().collect();
             net.send_many(
-                subs,
+                &subs,
                 messages::NewModel {
                     parent_id: None,
                     id: entity.index(),
@@ -372,14 +689,72 @@ fn update_visibility(
                     scale: transform.scale.as_vec3(),
                     idle_animation: model.idle_animation_id,
                     moving_animation: model.moving_animation_id,
+                    tint: model.tint,
                 },
             );
+
+            if let Some(name_tag) = name_tag {
+                for connection_id in subs {
+                    net.send_one(connection_id, name_tag.to_message(entity, connection_id));
+                }
+            }
         } else {
             net.send_many(subs, messages::DeleteModel { id: entity.index() });
         }
     }
 }
 
+// Name tags are sent to each player by themselves, as whether they are always visible depends on
+// who is looking.
+fn update_name_tags(
+    net: Res<NetworkServer>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    model_query: Query<(Entity, &NameTag, &ModelVisibility, &F64GlobalTransform), Changed<NameTag>>,
+) {
+    for (entity, name_tag, visibility, transform) in model_query.iter() {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        let transform = transform.compute_transform();
+        let chunk_pos = utils::world_position_to_chunk_position(transform.translation.as_ivec3());
+
+        let Some(subs) = chunk_subscriptions.get_subscribers(&chunk_pos) else {
+            continue;
+        };
+
+        for connection_id in subs {
+            net.send_one(*connection_id, name_tag.to_message(entity, *connection_id));
+        }
+    }
+}
+
+// Runs before the models are removed, so it is still known where they are.
+fn remove_name_tags(
+    net: Res<NetworkServer>,
+    model_map: Res<ModelMap>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    mut removed_name_tags: RemovedComponents<NameTag>,
+) {
+    for entity in removed_name_tags.read() {
+        let Some(chunk_pos) = model_map.reverse.get(&entity) else {
+            continue;
+        };
+
+        if let Some(subs) = chunk_subscriptions.get_subscribers(chunk_pos) {
+            net.send_many(
+                subs,
+                messages::ModelNameTag {
+                    id: entity.index(),
+                    name: String::new(),
+                    color: String::new(),
+                    always_visible: false,
+                },
+            );
+        }
+    }
+}
+
 fn send_models_on_chunk_subscription(
     net: Res<NetworkServer>,
     model_map: Res<ModelMap>,
@@ -389,13 +764,15 @@ fn send_models_on_chunk_subscription(
         &Model,
         &F64GlobalTransform,
         &ModelVisibility,
+        Option<&NameTag>,
     )>,
     mut chunk_sub_events: EventReader<SubscribeToChunk>,
 ) {
     for chunk_sub in chunk_sub_events.read() {
         if let Some(model_entities) = model_map.get_entities(&chunk_sub.chunk_position) {
             for entity in model_entities.iter() {
-                let Ok((maybe_player_parent, model, transform, visibility)) = models.get(*entity)
+                let Ok((maybe_player_parent, model, transform, visibility, name_tag)) =
+                    models.get(*entity)
                 else {
                     continue;
                 };
@@ -425,8 +802,16 @@ fn send_models_on_chunk_subscription(
                         asset: model.asset_id,
                         idle_animation: model.idle_animation_id,
                         moving_animation: model.moving_animation_id,
+                        tint: model.tint,
                     },
                 );
+
+                if let Some(name_tag) = name_tag {
+                    net.send_one(
+                        chunk_sub.connection_id,
+                        name_tag.to_message(*entity, chunk_sub.connection_id),
+                    );
+                }
             }
         }
     }
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, NetworkData, NetworkServer};
+use serde::Deserialize;
+
+use crate::{
+    bevy_extensions::f64_transform::{F64GlobalTransform, F64Transform},
+    database::Database,
+    players::{Camera, EquippedItem, Player, Reach},
+    utils,
+    world::{
+        blocks::{BlockFace, Blocks, Friction},
+        items::{spawn_dropped_item, Item, ItemStack, ItemStorage, Items},
+        models::Models,
+        world_map::{
+            chunk_manager::{ChunkSubscriptions, SubscribeToChunk},
+            ChangedBlockEvent, WorldMap,
+        },
+    },
+};
+
+const PAINTING_PATH: &str = "./resources/client/paintings/";
+
+// Paintings are placed by right clicking the side of a block with a painting item. The largest
+// painting that fits on the wall around the clicked block is chosen. They are taken down, and the
+// item dropped, when they are hit, when any of the blocks they hang on is removed, or when
+// something is placed in front of them. They are saved to the database as they are placed and
+// removed, and all of them are loaded when the server starts.
+pub struct PaintingPlugin;
+impl Plugin for PaintingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PaintingMap::default())
+            .add_systems(PreStartup, load_paintings)
+            .add_systems(Startup, load_saved_paintings)
+            .add_systems(
+                Update,
+                (
+                    place_paintings,
+                    remove_paintings,
+                    send_paintings_on_chunk_subscription,
+                ),
+            );
+    }
+}
+
+#[derive(Deserialize)]
+struct PaintingConfigJson {
+    width: u32,
+    height: u32,
+}
+
+struct PaintingConfig {
+    // Name of the config file, the texture uses the same name.
+    name: String,
+    width: u32,
+    height: u32,
+}
+
+/// The paintings in the resource pack, from largest to smallest.
+#[derive(Resource)]
+struct Paintings(Vec<PaintingConfig>);
+
+fn load_paintings(mut commands: Commands) {
+    let directory = std::fs::read_dir(PAINTING_PATH).expect(&format!(
+        "Could not read files from painting directory, make sure it is present at '{}'.",
+        PAINTING_PATH
+    ));
+
+    let mut paintings = Vec::new();
+
+    for dir_entry in directory {
+        let file_path = match dir_entry {
+            Ok(d) => d.path(),
+            Err(e) => panic!("Failed to read the filename of a painting, Error: {}", e),
+        };
+
+        let file = match std::fs::File::open(&file_path) {
+            Ok(f) => f,
+            Err(e) => panic!(
+                "Failed to open painting config at: {}\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        let json: PaintingConfigJson = match serde_json::from_reader(&file) {
+            Ok(c) => c,
+            Err(e) => panic!(
+                "Couldn't read painting config from '{}'\nError: {}",
+                file_path.display(),
+                e
+            ),
+        };
+
+        if json.width == 0 || json.height == 0 {
+            panic!(
+                "Invalid painting config at '{}', the width and height must be at least 1",
+                file_path.display()
+            );
+        }
+
+        paintings.push(PaintingConfig {
+            name: file_path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            width: json.width,
+            height: json.height,
+        });
+    }
+
+    paintings.sort_by_key(|painting| std::cmp::Reverse(painting.width * painting.height));
+
+    commands.insert_resource(Paintings(paintings));
+}
+
+#[derive(Component)]
+pub struct Painting {
+    /// Name of the painting in the resource pack
+    pub name: String,
+    /// Lower left block in front of the wall
+    pub position: IVec3,
+    /// Points out of the wall
+    pub normal: IVec3,
+    /// Points to the right along the wall when looking at the painting
+    pub right: IVec3,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Painting {
+    // The blocks in front of the wall that the painting covers.
+    fn covered_blocks(&self) -> impl Iterator<Item = IVec3> + '_ {
+        (0..self.width as i32).flat_map(move |x| {
+            (0..self.height as i32).map(move |y| self.position + self.right * x + IVec3::Y * y)
+        })
+    }
+
+    fn chunk_position(&self) -> IVec3 {
+        utils::world_position_to_chunk_position(self.position)
+    }
+
+    fn to_message(&self, entity: Entity) -> messages::NewPainting {
+        messages::NewPainting {
+            id: entity.index(),
+            name: self.name.clone(),
+            position: self.position,
+            normal: self.normal,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// The block positions covered by paintings
+#[derive(Resource, Default)]
+struct PaintingMap {
+    blocks: HashMap<IVec3, Entity>,
+    // Paintings by the chunk their lower left block is in.
+    chunks: HashMap<IVec3, Vec<Entity>>,
+}
+
+impl PaintingMap {
+    fn insert(&mut self, entity: Entity, painting: &Painting) {
+        for position in painting.covered_blocks() {
+            self.blocks.insert(position, entity);
+        }
+        self.chunks
+            .entry(painting.chunk_position())
+            .or_default()
+            .push(entity);
+    }
+
+    fn remove(&mut self, entity: Entity, painting: &Painting) {
+        for position in painting.covered_blocks() {
+            self.blocks.remove(&position);
+        }
+        if let Some(entities) = self.chunks.get_mut(&painting.chunk_position()) {
+            entities.retain(|e| *e != entity);
+        }
+    }
+}
+
+fn load_saved_paintings(
+    mut commands: Commands,
+    database: Res<Database>,
+    paintings: Res<Paintings>,
+    mut painting_map: ResMut<PaintingMap>,
+) {
+    let saved_paintings = match database.load_paintings() {
+        Ok(saved_paintings) => saved_paintings,
+        Err(err) => {
+            error!("Failed to load the paintings: {}", err);
+            return;
+        }
+    };
+
+    for painting in saved_paintings {
+        // They are kept in the database, so they come back if the painting is added back to the
+        // resource pack.
+        if !paintings
+            .0
+            .iter()
+            .any(|config| config.name == painting.name)
+        {
+            warn!(
+                "The painting at {} is not shown, there is no painting named '{}'",
+                painting.position, painting.name
+            );
+            continue;
+        }
+
+        let entity = commands.spawn_empty().id();
+        painting_map.insert(entity, &painting);
+        commands.entity(entity).insert(painting);
+    }
+}
+
+// The painting can hang at the position if the block is open and the block behind it is solid.
+fn can_hang(world_map: &WorldMap, position: IVec3, normal: IVec3) -> bool {
+    let blocks = Blocks::get();
+    let is_solid = |position: IVec3| match world_map.get_block(position) {
+        Some(block_id) => matches!(
+            blocks.get_config(&block_id).friction,
+            Friction::Static { .. }
+        ),
+        None => false,
+    };
+
+    return !is_solid(position) && is_solid(position - normal);
+}
+
+fn place_paintings(
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    items: Res<Items>,
+    paintings: Res<Paintings>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    mut painting_map: ResMut<PaintingMap>,
+    mut commands: Commands,
+    mut player_query: Query<
+        (
+            &mut ItemStorage,
+            &EquippedItem,
+            &F64GlobalTransform,
+            &Camera,
+            &Reach,
+        ),
+        With<Player>,
+    >,
+    mut clicks: EventReader<NetworkData<messages::RightClick>>,
+) {
+    let Some(painting_item_id) = items.get_id("painting") else {
+        clicks.clear();
+        return;
+    };
+
+    for right_click in clicks.read() {
+        let (mut inventory, equipped_item, player_position, player_camera, reach) =
+            player_query.get_mut(right_click.source.entity()).unwrap();
+
+        let equipped_item = &mut inventory[equipped_item.0];
+        if !equipped_item
+            .item()
+            .is_some_and(|item| item.id == painting_item_id)
+        {
+            continue;
+        }
+
+        let camera_transform = F64Transform {
+            translation: player_position.translation() + player_camera.translation,
+            rotation: player_camera.rotation,
+            ..default()
+        };
+
+        let (block_position, _, block_face) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        if block_face == BlockFace::Top || block_face == BlockFace::Bottom {
+            continue;
+        }
+
+        let normal = block_face.shift_position(IVec3::ZERO);
+        let right = IVec3::Y.cross(normal);
+        let clicked_position = block_position + normal;
+
+        // Try every painting from largest to smallest, at every offset that still covers the
+        // clicked block.
+        let placement = paintings.0.iter().find_map(|config| {
+            for x in 0..config.width as i32 {
+                for y in 0..config.height as i32 {
+                    let painting = Painting {
+                        name: config.name.clone(),
+                        position: clicked_position - right * x - IVec3::Y * y,
+                        normal,
+                        right,
+                        width: config.width,
+                        height: config.height,
+                    };
+
+                    let fits = painting.covered_blocks().all(|position| {
+                        !painting_map.blocks.contains_key(&position)
+                            && can_hang(&world_map, position, normal)
+                    });
+
+                    if fits {
+                        return Some(painting);
+                    }
+                }
+            }
+            None
+        });
+
+        let Some(painting) = placement else {
+            continue;
+        };
+
+        equipped_item.subtract(1);
+
+        let entity = commands.spawn_empty().id();
+
+        if let Some(subscribers) = chunk_subscriptions.get_subscribers(&painting.chunk_position()) {
+            net.send_many(subscribers, painting.to_message(entity));
+        }
+
+        if let Err(err) = database.save_painting(&painting) {
+            error!(
+                "Failed to save the painting at {}: {}",
+                painting.position, err
+            );
+        }
+
+        painting_map.insert(entity, &painting);
+        commands.entity(entity).insert(painting);
+    }
+}
+
+fn remove_paintings(
+    mut commands: Commands,
+    net: Res<NetworkServer>,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    items: Res<Items>,
+    models: Res<Models>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    mut painting_map: ResMut<PaintingMap>,
+    painting_query: Query<&Painting>,
+    player_query: Query<(&F64GlobalTransform, &Camera, &Reach), With<Player>>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+    mut clicks: EventReader<NetworkData<messages::LeftClick>>,
+) {
+    let mut broken = Vec::new();
+
+    for changed_block in changed_blocks.read() {
+        let position = changed_block.position;
+
+        // Either the block in front or behind the painting may have changed.
+        for position in [
+            position,
+            position + IVec3::X,
+            position - IVec3::X,
+            position + IVec3::Z,
+            position - IVec3::Z,
+        ] {
+            let Some(entity) = painting_map.blocks.get(&position) else {
+                continue;
+            };
+            if !broken.contains(entity) {
+                broken.push(*entity);
+            }
+        }
+    }
+
+    let mut removed = Vec::new();
+
+    for entity in broken {
+        // Paintings placed this tick don't have their component yet.
+        let Ok(painting) = painting_query.get(entity) else {
+            continue;
+        };
+
+        // The changed block might have been replaced with something it can still hang on.
+        if painting
+            .covered_blocks()
+            .all(|position| can_hang(&world_map, position, painting.normal))
+        {
+            continue;
+        }
+
+        removed.push(entity);
+    }
+
+    // Paintings lie flat against the wall, so a painting is hit when the face of the wall block it
+    // covers is.
+    for click in clicks.read() {
+        if !matches!(**click, messages::LeftClick::Press) {
+            continue;
+        }
+
+        let Ok((player_position, player_camera, reach)) = player_query.get(click.source.entity())
+        else {
+            continue;
+        };
+
+        let camera_transform = F64Transform {
+            translation: player_position.translation() + player_camera.translation,
+            rotation: player_camera.rotation,
+            ..default()
+        };
+
+        let (block_position, _, block_face) =
+            match world_map.raycast_to_block(&camera_transform, reach.distance) {
+                Some(b) => b,
+                None => continue,
+            };
+
+        let normal = block_face.shift_position(IVec3::ZERO);
+        let Some(entity) = painting_map.blocks.get(&(block_position + normal)) else {
+            continue;
+        };
+        let Ok(painting) = painting_query.get(*entity) else {
+            continue;
+        };
+
+        if painting.normal == normal && !removed.contains(entity) {
+            removed.push(*entity);
+        }
+    }
+
+    for entity in removed {
+        let painting = painting_query.get(entity).unwrap();
+
+        painting_map.remove(entity, painting);
+
+        if let Err(err) = database.delete_painting(painting.position) {
+            error!(
+                "Failed to delete the painting at {}: {}",
+                painting.position, err
+            );
+        }
+
+        if let Some(subscribers) = chunk_subscriptions.get_subscribers(&painting.chunk_position()) {
+            net.send_many(subscribers, messages::DeletePainting { id: entity.index() });
+        }
+
+        if let Some(painting_item_id) = items.get_id("painting") {
+            let item_config = items.get_config(&painting_item_id);
+            spawn_dropped_item(
+                &mut commands,
+                &items,
+                &models,
+                painting.position.as_dvec3() + DVec3::splat(0.5),
+                ItemStack::new(Item::new(painting_item_id), 1, item_config.max_stack_size),
+            );
+        }
+
+        commands.entity(entity).despawn();
+    }
+}
+
+fn send_paintings_on_chunk_subscription(
+    net: Res<NetworkServer>,
+    painting_map: Res<PaintingMap>,
+    painting_query: Query<&Painting>,
+    mut chunk_sub_events: EventReader<SubscribeToChunk>,
+) {
+    for chunk_sub in chunk_sub_events.read() {
+        let Some(entities) = painting_map.chunks.get(&chunk_sub.chunk_position) else {
+            continue;
+        };
+
+        for entity in entities {
+            let Ok(painting) = painting_query.get(*entity) else {
+                continue;
+            };
+            net.send_one(chunk_sub.connection_id, painting.to_message(*entity));
+        }
+    }
+}
@@ -1,23 +1,81 @@
-use bevy::prelude::*;
+use bevy::{app::AppExit, prelude::*};
 use fmc_networking::{messages, NetworkServer};
 
+use super::WorldProperties;
+
+// Seconds between each time the time of day is stored in the world properties.
+const SAVE_INTERVAL: f32 = 60.0;
+
+// The time of day continues from where it was stored in the world properties when the server
+// starts. It is stored back every once in a while, and when the server shuts down.
 pub struct SkyPlugin;
 impl Plugin for SkyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, day_night_cycle);
+        app.insert_resource(TimeOfDay::default())
+            .insert_resource(SaveTimer(Timer::from_seconds(
+                SAVE_INTERVAL,
+                TimerMode::Repeating,
+            )))
+            .add_systems(Startup, load_time_of_day)
+            .add_systems(Update, day_night_cycle)
+            .add_systems(
+                PostUpdate,
+                (
+                    save_time_of_day,
+                    save_time_of_day_on_exit.run_if(on_event::<AppExit>()),
+                ),
+            );
     }
 }
 
 const DAY_LENGTH: f32 = 1200.0;
 
+/// Seconds since dawn.
 // time == 0, dawn
 // time == 600, dusk
-fn day_night_cycle(bevy_time: Res<Time>, net: Res<NetworkServer>, mut time: Local<f32>) {
-    *time += bevy_time.delta_seconds();
-    *time %= DAY_LENGTH;
+#[derive(Resource, Default)]
+pub struct TimeOfDay(f32);
+
+impl TimeOfDay {
+    pub fn is_night(&self) -> bool {
+        return self.0 >= DAY_LENGTH / 2.0;
+    }
+
+    /// Skip ahead to the next dawn.
+    pub fn skip_to_dawn(&mut self) {
+        self.0 = 0.0;
+    }
+}
+
+#[derive(Resource)]
+struct SaveTimer(Timer);
+
+fn load_time_of_day(properties: Res<WorldProperties>, mut time: ResMut<TimeOfDay>) {
+    time.0 = properties.time_of_day.rem_euclid(DAY_LENGTH);
+}
+
+fn day_night_cycle(bevy_time: Res<Time>, net: Res<NetworkServer>, mut time: ResMut<TimeOfDay>) {
+    time.0 += bevy_time.delta_seconds();
+    time.0 %= DAY_LENGTH;
 
     let message = messages::Time {
-        angle: *time * std::f32::consts::TAU / DAY_LENGTH,
+        angle: time.0 * std::f32::consts::TAU / DAY_LENGTH,
     };
     net.broadcast(message);
 }
+
+fn save_time_of_day(
+    bevy_time: Res<Time>,
+    time: Res<TimeOfDay>,
+    mut save_timer: ResMut<SaveTimer>,
+    mut properties: ResMut<WorldProperties>,
+) {
+    save_timer.0.tick(bevy_time.delta());
+    if save_timer.0.just_finished() {
+        properties.time_of_day = time.0;
+    }
+}
+
+fn save_time_of_day_on_exit(time: Res<TimeOfDay>, mut properties: ResMut<WorldProperties>) {
+    properties.time_of_day = time.0;
+}
@@ -0,0 +1,114 @@
+// Sounds are organized in groups, e.g. "stone" or "wood", that have a set of sounds for each
+// thing that can happen, like a block being placed or broken. Blocks name the group they use in
+// their config, so all blocks of the same material sound the same. The groups are defined in the
+// resource pack, the client reads the same file to know which sounds to play when walking.
+use std::collections::HashMap;
+
+use bevy::{math::DVec3, prelude::*};
+use fmc_networking::{messages, NetworkServer};
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{utils, world::world_map::chunk_manager::ChunkSubscriptions};
+
+pub const SOUND_GROUPS_PATH: &str = "./resources/client/audio/sound_groups.json";
+
+// How far the pitch of a sound can stray from the original, so the same sound repeated doesn't
+// become monotonous.
+const PITCH_VARIATION: f32 = 0.1;
+
+pub struct SoundPlugin;
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        // The blocks are validated against the groups, they need to be there before startup.
+        app.insert_resource(SoundGroups::load());
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SoundKind {
+    Place,
+    Break,
+    Hurt,
+}
+
+// The groups also have "step" sounds, they are played by the client when walking, and are not
+// read here.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct SoundGroup {
+    place: Vec<String>,
+    #[serde(rename = "break")]
+    breaking: Vec<String>,
+    hurt: Vec<String>,
+}
+
+impl SoundGroup {
+    fn sounds(&self, kind: SoundKind) -> &Vec<String> {
+        return match kind {
+            SoundKind::Place => &self.place,
+            SoundKind::Break => &self.breaking,
+            SoundKind::Hurt => &self.hurt,
+        };
+    }
+}
+
+#[derive(Resource)]
+pub struct SoundGroups {
+    groups: HashMap<String, SoundGroup>,
+}
+
+impl SoundGroups {
+    fn load() -> Self {
+        let file = std::fs::File::open(SOUND_GROUPS_PATH).expect(&format!(
+            "Could not open the sound groups, make sure they are present at '{}'",
+            SOUND_GROUPS_PATH
+        ));
+        let groups = match serde_json::from_reader(file) {
+            Ok(groups) => groups,
+            Err(e) => panic!(
+                "Failed to read the sound groups at '{}': {}",
+                SOUND_GROUPS_PATH, e
+            ),
+        };
+
+        return Self { groups };
+    }
+
+    pub fn contains(&self, group: &str) -> bool {
+        return self.groups.contains_key(group);
+    }
+
+    /// Play a random sound of the kind from the group, for everyone that can see the position.
+    /// Nothing is played if the group doesn't have any sounds of that kind.
+    pub fn play(
+        &self,
+        net: &NetworkServer,
+        chunk_subscriptions: &ChunkSubscriptions,
+        group: &str,
+        kind: SoundKind,
+        position: DVec3,
+    ) {
+        let mut rng = rand::thread_rng();
+
+        let Some(sound) = self
+            .groups
+            .get(group)
+            .and_then(|group| group.sounds(kind).choose(&mut rng))
+        else {
+            return;
+        };
+
+        let chunk_position = utils::world_position_to_chunk_position(position.floor().as_ivec3());
+        if let Some(subscribers) = chunk_subscriptions.get_subscribers(&chunk_position) {
+            net.send_many(
+                subscribers,
+                messages::Sound {
+                    position: Some(position),
+                    sound: sound.clone(),
+                    speed: rng.gen_range(1.0 - PITCH_VARIATION..=1.0 + PITCH_VARIATION),
+                },
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::{app::AppExit, prelude::*};
+use fmc_networking::{messages, NetworkData, NetworkServer};
+use noise::Noise;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+use super::WorldProperties;
+
+// Seconds between each time the weather is reconsidered.
+const UPDATE_INTERVAL: f32 = 10.0;
+// Frequency of the weather noise, it takes around this many seconds to go from one extreme to
+// the other.
+const WEATHER_PERIOD: f32 = 1800.0;
+// The weather changes when the noise crosses these. Each state is left at a lower value than it
+// is entered at, so the weather doesn't flicker back and forth while the noise hovers around a
+// threshold.
+const RAIN_START: f32 = 0.3;
+const RAIN_STOP: f32 = 0.2;
+const STORM_START: f32 = 0.6;
+const STORM_STOP: f32 = 0.5;
+
+// The weather moves between clear, rain and storm as a noise is sampled over the time the world
+// has been running. The noise is seeded by the world seed, so each world has its own weather.
+// How far along the noise the world is, and the current weather, are kept in the world
+// properties so they carry over restarts.
+pub struct WeatherPlugin;
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(UpdateTimer(Timer::from_seconds(
+            UPDATE_INTERVAL,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (change_weather, send_weather_on_join))
+        .add_systems(
+            PostUpdate,
+            save_weather_time_on_exit.run_if(on_event::<AppExit>()),
+        );
+    }
+}
+
+/// The weather as it is stored in the world properties.
+#[derive(Default, Serialize, Deserialize, Clone, Copy)]
+pub struct WeatherProperties {
+    pub weather: messages::Weather,
+    /// Seconds the world has been running, the noise is sampled at this time.
+    pub time: f64,
+}
+
+/// The current weather.
+#[derive(Resource)]
+pub struct Weather {
+    weather: messages::Weather,
+    time: f64,
+    noise: Noise,
+}
+
+impl Weather {
+    pub fn get(&self) -> messages::Weather {
+        return self.weather;
+    }
+
+    // Step the state machine once with the noise at the current time. Storms only come after
+    // rain, and clear up into rain before the sky is clear again.
+    fn next(&self) -> messages::Weather {
+        let (value, _, _) = self.noise.generate_1d(self.time as f32, 1);
+        let value = value[0];
+
+        match self.weather {
+            messages::Weather::Clear if value > RAIN_START => messages::Weather::Rain,
+            messages::Weather::Rain if value > STORM_START => messages::Weather::Storm,
+            messages::Weather::Rain if value < RAIN_STOP => messages::Weather::Clear,
+            messages::Weather::Storm if value < STORM_STOP => messages::Weather::Rain,
+            weather => weather,
+        }
+    }
+
+    /// Clear the sky. The noise is skipped ahead past the current spell of bad weather, so it
+    /// doesn't start raining again right away.
+    pub fn clear(&mut self) {
+        // Bounded by a full period in case the noise stays high.
+        for _ in 0..(WEATHER_PERIOD / UPDATE_INTERVAL) as usize {
+            let (value, _, _) = self.noise.generate_1d(self.time as f32, 1);
+            if value[0] < RAIN_STOP {
+                break;
+            }
+            self.time += UPDATE_INTERVAL as f64;
+        }
+        self.weather = messages::Weather::Clear;
+    }
+}
+
+#[derive(Resource)]
+struct UpdateTimer(Timer);
+
+fn setup(mut commands: Commands, settings: Res<Settings>, properties: Res<WorldProperties>) {
+    commands.insert_resource(Weather {
+        weather: properties.weather.weather,
+        time: properties.weather.time,
+        noise: Noise::simplex(1.0 / WEATHER_PERIOD, settings.seed.wrapping_add(100))
+            .fbm(3, 0.5, 2.0),
+    });
+}
+
+fn change_weather(
+    net: Res<NetworkServer>,
+    time: Res<Time>,
+    mut update_timer: ResMut<UpdateTimer>,
+    mut weather: ResMut<Weather>,
+    mut properties: ResMut<WorldProperties>,
+) {
+    // Changes made here bypass change detection, so it only catches changes made elsewhere, like
+    // when the weather is cleared by a vote.
+    if weather.is_changed() && !weather.is_added() {
+        properties.weather = WeatherProperties {
+            weather: weather.weather,
+            time: weather.time,
+        };
+        net.broadcast(weather.weather);
+    }
+
+    let weather = weather.bypass_change_detection();
+    weather.time += time.delta_seconds_f64();
+
+    update_timer.0.tick(time.delta());
+    if !update_timer.0.just_finished() {
+        return;
+    }
+
+    let next = weather.next();
+    if next == weather.weather {
+        return;
+    }
+
+    weather.weather = next;
+    properties.weather = WeatherProperties {
+        weather: next,
+        time: weather.time,
+    };
+    net.broadcast(next);
+}
+
+fn send_weather_on_join(
+    net: Res<NetworkServer>,
+    weather: Res<Weather>,
+    mut events: EventReader<NetworkData<messages::ClientFinishedLoading>>,
+) {
+    for event in events.read() {
+        net.send_one(event.source, weather.get());
+    }
+}
+
+fn save_weather_time_on_exit(weather: Res<Weather>, mut properties: ResMut<WorldProperties>) {
+    properties.weather = WeatherProperties {
+        weather: weather.weather,
+        time: weather.time,
+    };
+}
@@ -4,10 +4,12 @@ use std::ops::{Index, IndexMut};
 
 use crate::database::Database;
 use crate::world::blocks::{BlockState, Blocks};
+use crate::world::items::ItemStorage;
 use crate::{constants::*, utils};
 use fmc_networking::BlockId;
+use serde::{Deserialize, Serialize};
 
-use super::terrain_generation::{TerrainFeature, TerrainGenerator};
+use super::terrain_generation::{PlannedStructure, TerrainFeature, TerrainGenerator};
 
 // TODO: Block state is a small state covering universal things like block rotation. Another
 // storage type should be available for storing larger states required by specific blocks.
@@ -19,11 +21,19 @@ pub struct Chunk {
     pub changed_blocks: HashMap<usize, (BlockId, Option<BlockState>)>,
     // Generated features like trees etc.
     pub terrain_features: Vec<TerrainFeature>,
+    // Structures that were planned while generating the chunk. They are taken out when the chunk
+    // is added to the world map, and placed in the other chunks they cover that are loaded.
+    pub planned_structures: Vec<PlannedStructure>,
+    // The block entities of the chunk, by block position. Either saved, or filled with loot during
+    // generation. They are taken out and spawned when the chunk is added to the world map.
+    pub block_entities: Vec<(IVec3, ItemStorage)>,
     // Blocks are stored as one contiguous array. To access a block at the coordinate x,y,z
     // (zero indexed) the formula x * CHUNK_SIZE^2 + z * CHUNK_SIZE + y is used.
     pub blocks: Vec<BlockId>,
     // Block state containing optional information, see `BlockState` for bit layout.
     pub block_state: HashMap<usize, u16>,
+    // The biome of each block column, indexed by x * CHUNK_SIZE + z. Only used by the clients.
+    pub biomes: Vec<u8>,
     // A map of which chunk faces within the chunk are visible from one another.
     visible_faces: HashSet<(ChunkFace, ChunkFace)>,
 }
@@ -34,18 +44,118 @@ impl Chunk {
         terrain_generator: TerrainGenerator,
         database: Database,
     ) -> (IVec3, Chunk) {
-        let changed_blocks = database.load_chunk_blocks(&position);
+        let _waiting = Database::allow_waiting();
+
+        // Chunks that were generated ahead of time only need the saved changes applied to them.
+        let generated =
+            match database.load_generated_chunk(&position, terrain_generator.fingerprint()) {
+                Ok(generated) => generated,
+                Err(err) => {
+                    error!(
+                        "Failed to load the pregenerated chunk at {}, it will be generated \
+                        again: {}",
+                        position, err
+                    );
+                    None
+                }
+            };
+        // Which chunk faces are visible from one another isn't saved, it has to be found again
+        // when the blocks have changed since the chunk was generated.
+        let mut check_visible_faces = generated.is_some();
+        let mut chunk = match generated {
+            Some(generated) => Self::from(generated),
+            None => Self::generate(position, &terrain_generator, &database),
+        };
+
+        // The chunk is still generated if its changes can't be read, so the players aren't left
+        // with a hole in the world. Changes made to it afterwards will overwrite the old ones.
+        let changed_blocks = match database.load_chunk_blocks(&position) {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                error!("Failed to load the chunk at {}: {}", position, err);
+                HashMap::new()
+            }
+        };
+        for (block_index, (block_id, block_state)) in changed_blocks.iter() {
+            chunk[*block_index] = *block_id;
+            chunk.set_block_state(*block_index, *block_state);
+            check_visible_faces = true;
+        }
+        chunk.changed_blocks = changed_blocks;
+
+        // Containers that have been saved replace the loot that was rolled for them.
+        match database.load_block_entities(&position) {
+            Ok(block_entities) => {
+                chunk.block_entities.retain(|(position, _)| {
+                    !block_entities
+                        .iter()
+                        .any(|(saved_position, _)| saved_position == position)
+                });
+                chunk.block_entities.extend(block_entities);
+            }
+            Err(err) => error!(
+                "Failed to load the block entities in the chunk at {}: {}",
+                position, err
+            ),
+        }
+
+        match database.load_structure_pieces(&position) {
+            Ok(pieces) => {
+                for piece in pieces.iter() {
+                    piece.apply(&mut chunk, position);
+                    check_visible_faces = true;
+                }
+            }
+            Err(err) => error!(
+                "Failed to load the structures in the chunk at {}: {}",
+                position, err
+            ),
+        }
+
+        if check_visible_faces {
+            chunk.check_visible_faces();
+        }
+
+        return (position, chunk);
+    }
+
+    /// Generate the chunk as it is before any of the saved changes to it are applied. The
+    /// structures that are planned in it are saved, so the chunks they cover can place them.
+    pub fn generate(
+        position: IVec3,
+        terrain_generator: &TerrainGenerator,
+        database: &Database,
+    ) -> Chunk {
         let mut chunk = Self {
-            changed_blocks,
+            changed_blocks: HashMap::new(),
             terrain_features: Vec::new(),
+            planned_structures: Vec::new(),
+            block_entities: Vec::new(),
             blocks: Vec::new(),
             block_state: HashMap::new(),
+            biomes: Vec::new(),
             visible_faces: HashSet::new(),
         };
 
         terrain_generator.generate_chunk(position, &mut chunk);
 
-        return (position, chunk);
+        // Structures are placed through the database, every chunk they cover applies its piece of
+        // them when it is loaded. A structure that was already planned the last time the chunk
+        // was generated is not saved again.
+        chunk
+            .planned_structures
+            .retain(|structure| match database.save_structure(structure) {
+                Ok(saved) => saved,
+                Err(err) => {
+                    error!(
+                        "Failed to save the structure '{}' at {}: {}",
+                        structure.name, structure.origin, err
+                    );
+                    false
+                }
+            });
+
+        return chunk;
     }
 
     pub fn make_uniform(&mut self, block_id: BlockId) {
@@ -169,6 +279,45 @@ impl Chunk {
 }
 
 // 'chunk[[x,y,z]]'
+/// A chunk as it was generated, before the saved changes to it are applied. Chunks that are
+/// generated ahead of time are saved like this, so they only have to be generated once.
+#[derive(Serialize, Deserialize)]
+pub struct GeneratedChunk {
+    pub blocks: Vec<BlockId>,
+    pub block_state: HashMap<usize, u16>,
+    pub biomes: Vec<u8>,
+    // The loot of the features has already been rolled into the block entities.
+    pub terrain_features: Vec<TerrainFeature>,
+    pub block_entities: Vec<(IVec3, ItemStorage)>,
+}
+
+impl From<Chunk> for GeneratedChunk {
+    fn from(chunk: Chunk) -> Self {
+        Self {
+            blocks: chunk.blocks,
+            block_state: chunk.block_state,
+            biomes: chunk.biomes,
+            terrain_features: chunk.terrain_features,
+            block_entities: chunk.block_entities,
+        }
+    }
+}
+
+impl From<GeneratedChunk> for Chunk {
+    fn from(generated: GeneratedChunk) -> Self {
+        Self {
+            changed_blocks: HashMap::new(),
+            terrain_features: generated.terrain_features,
+            planned_structures: Vec::new(),
+            block_entities: generated.block_entities,
+            blocks: generated.blocks,
+            block_state: generated.block_state,
+            biomes: generated.biomes,
+            visible_faces: HashSet::new(),
+        }
+    }
+}
+
 impl Index<[usize; 3]> for Chunk {
     type Output = BlockId;
 
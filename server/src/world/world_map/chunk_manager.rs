@@ -12,6 +12,7 @@ use crate::{
     database::Database,
     players::Player,
     settings::Settings,
+    stats::AverageTickTime,
     utils,
     world::{
         blocks::BlockState,
@@ -27,10 +28,12 @@ use crate::{
 pub struct ChunkManagerPlugin;
 impl Plugin for ChunkManagerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_event::<ChunkUnloadEvent>()
+        app.add_event::<ChunkLoadEvent>()
+            .add_event::<ChunkUnloadEvent>()
             .add_event::<SubscribeToChunk>()
             .insert_resource(WorldMap::default())
             .insert_resource(ChunkSubscriptions::default())
+            .add_systems(Startup, setup_render_distance_limit)
             // This is postupdate so that when a disconnect event is sent, the other systems can
             // assume that the connection is still registered as a subscriber.
             // TODO: This can be changed to run on Update when I sort out the spaghetti in
@@ -43,7 +46,9 @@ impl Plugin for ChunkManagerPlugin {
                     update_player_chunk_origin,
                     add_render_distance,
                     update_render_distance,
+                    adjust_render_distance_limit.run_if(resource_changed::<AverageTickTime>()),
                     subscribe_to_visible_chunks,
+                    handle_chunk_requests.before(handle_chunk_subscription_events),
                     handle_chunk_subscription_events.after(subscribe_to_visible_chunks),
                     unsubscribe_from_chunks,
                     handle_chunk_loading_tasks,
@@ -57,10 +62,38 @@ impl Plugin for ChunkManagerPlugin {
 #[derive(Component)]
 struct PlayerChunkOrigin(IVec3);
 
-// The max render distance is set by the server. The clients can then send a desired render
-// distance that is smaller if they wish, which is stored here.
+// How long a tick can take on average before the render distance is lowered, in milliseconds.
+// The server runs at ~60 ticks a second, so this leaves a little headroom.
+const OVERLOADED_TICK_TIME: f32 = 14.0;
+// Average tick time the server must be below before the render distance is raised again.
+const RELIEVED_TICK_TIME: f32 = 8.0;
+
+// The max render distance is set by the server, but it is lowered while the server can't keep up
+// with the amount of chunks it has to handle, and raised again when the load lets up.
+#[derive(Resource)]
+struct RenderDistanceLimit(u32);
+
+// The clients can send a desired render distance that is smaller than the server's limit if they
+// wish. The one used is the smallest of the two, and is sent back to the client whenever it
+// changes so it knows how far it can see.
 #[derive(Component)]
-struct PlayerRenderDistance(u32);
+struct PlayerRenderDistance {
+    requested: u32,
+    effective: u32,
+}
+
+impl PlayerRenderDistance {
+    // Returns true if the effective render distance changed.
+    fn update(&mut self, limit: u32) -> bool {
+        let effective = self.requested.min(limit);
+        if self.effective != effective {
+            self.effective = effective;
+            return true;
+        } else {
+            return false;
+        }
+    }
+}
 
 fn add_player_chunk_origin(
     mut commands: Commands,
@@ -87,26 +120,96 @@ fn update_player_chunk_origin(
     }
 }
 
+fn setup_render_distance_limit(mut commands: Commands, settings: Res<Settings>) {
+    commands.insert_resource(RenderDistanceLimit(settings.render_distance));
+}
+
 fn add_render_distance(
     mut commands: Commands,
     settings: Res<Settings>,
+    limit: Res<RenderDistanceLimit>,
     player_query: Query<Entity, Added<Player>>,
 ) {
     for entity in player_query.iter() {
-        commands
-            .entity(entity)
-            .insert(PlayerRenderDistance(settings.render_distance));
+        commands.entity(entity).insert(PlayerRenderDistance {
+            requested: settings.render_distance,
+            effective: limit.0,
+        });
     }
 }
 
 fn update_render_distance(
-    settings: Res<Settings>,
-    mut player_query: Query<&mut PlayerRenderDistance>,
+    net: Res<NetworkServer>,
+    limit: Res<RenderDistanceLimit>,
+    mut player_query: Query<(&mut PlayerRenderDistance, &mut PlayerChunkOrigin)>,
     mut render_distance_events: EventReader<NetworkData<messages::RenderDistance>>,
 ) {
     for event in render_distance_events.read() {
-        let mut render_distance = player_query.get_mut(event.source.entity()).unwrap();
-        render_distance.0 = event.render_distance.min(settings.render_distance);
+        let (mut render_distance, mut chunk_origin) =
+            player_query.get_mut(event.source.entity()).unwrap();
+        render_distance.requested = event.render_distance;
+        if render_distance.update(limit.0) {
+            // Triggers change detection so chunks are subscribed/unsubscribed to match.
+            chunk_origin.set_changed();
+        }
+
+        // Always answered, the client can't know what the limit is beforehand.
+        net.send_one(
+            event.source,
+            messages::EffectiveRenderDistance {
+                render_distance: render_distance.effective,
+            },
+        );
+    }
+}
+
+fn adjust_render_distance_limit(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    average_tick_time: Res<AverageTickTime>,
+    mut limit: ResMut<RenderDistanceLimit>,
+    mut player_query: Query<(
+        &ConnectionId,
+        &mut PlayerRenderDistance,
+        &mut PlayerChunkOrigin,
+    )>,
+) {
+    let new_limit = if average_tick_time.0 > OVERLOADED_TICK_TIME {
+        // min() so it won't be raised if the min is larger than the current limit.
+        limit
+            .0
+            .saturating_sub(1)
+            .max(settings.min_render_distance)
+            .min(limit.0)
+    } else if average_tick_time.0 < RELIEVED_TICK_TIME {
+        (limit.0 + 1).min(settings.render_distance)
+    } else {
+        limit.0
+    };
+
+    if new_limit == limit.0 {
+        return;
+    }
+
+    if new_limit < limit.0 {
+        warn!(
+            "The server is overloaded, lowering the render distance to {} chunks",
+            new_limit
+        );
+    }
+
+    limit.0 = new_limit;
+
+    for (connection_id, mut render_distance, mut chunk_origin) in player_query.iter_mut() {
+        if render_distance.update(limit.0) {
+            chunk_origin.set_changed();
+            net.send_one(
+                *connection_id,
+                messages::EffectiveRenderDistance {
+                    render_distance: render_distance.effective,
+                },
+            );
+        }
     }
 }
 
@@ -117,6 +220,10 @@ pub struct SubscribeToChunk {
     pub chunk_position: IVec3,
 }
 
+/// Sent when a chunk has finished loading and been added to the world map.
+#[derive(Event)]
+pub struct ChunkLoadEvent(pub IVec3);
+
 // Event sent when the server should unload a chunk and its associated entities.
 #[derive(Event)]
 pub struct ChunkUnloadEvent(pub IVec3);
@@ -209,6 +316,7 @@ fn handle_chunk_subscription_events(
                         position: event.chunk_position,
                         blocks: chunk.blocks.clone(),
                         block_state: chunk.block_state.clone(),
+                        biomes: chunk.biomes.clone(),
                     },
                 );
             }
@@ -222,7 +330,10 @@ fn handle_chunk_subscription_events(
                 terrain_generator.clone(),
                 database.clone(),
             ));
-            commands.spawn(ChunkLoadingTask(task));
+            commands.spawn(ChunkLoadingTask {
+                position: event.chunk_position,
+                task,
+            });
         };
     }
 }
@@ -244,7 +355,10 @@ fn unsubscribe_from_chunks(
             .unwrap();
         let removed = subscribed_chunks.extract_if(|chunk_position| {
             let distance = (*chunk_position - origin.0).abs() / CHUNK_SIZE as i32;
-            if distance.cmpgt(IVec3::splat(render_distance.0 as i32)).any() {
+            if distance
+                .cmpgt(IVec3::splat(render_distance.effective as i32))
+                .any()
+            {
                 return true;
             } else {
                 return false;
@@ -268,8 +382,68 @@ fn unsubscribe_from_chunks(
     }
 }
 
+// Clients request chunks they have been missing for a while. They are sent again if the client
+// is subscribed to them, or subscribed to them if it isn't.
+fn handle_chunk_requests(
+    net: Res<NetworkServer>,
+    world_map: Res<WorldMap>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    player_query: Query<(&PlayerChunkOrigin, &PlayerRenderDistance)>,
+    mut chunk_requests: EventReader<NetworkData<messages::ChunkRequest>>,
+    mut subscription_events: EventWriter<SubscribeToChunk>,
+) {
+    for request in chunk_requests.read() {
+        let Ok((origin, render_distance)) = player_query.get(request.source.entity()) else {
+            continue;
+        };
+
+        let Some(subscribed_chunks) = chunk_subscriptions
+            .subscriber_to_chunks
+            .get(&request.source)
+        else {
+            continue;
+        };
+
+        for chunk_position in request.positions.iter().copied() {
+            if utils::world_position_to_chunk_position(chunk_position) != chunk_position {
+                continue;
+            }
+
+            let distance = (chunk_position - origin.0).abs() / CHUNK_SIZE as i32;
+            if distance
+                .cmpgt(IVec3::splat(render_distance.effective as i32))
+                .any()
+            {
+                continue;
+            }
+
+            if !subscribed_chunks.contains(&chunk_position) {
+                subscription_events.send(SubscribeToChunk {
+                    connection_id: request.source,
+                    chunk_position,
+                });
+            } else if let Some(chunk) = world_map.get_chunk(&chunk_position) {
+                net.send_one(
+                    request.source,
+                    messages::Chunk {
+                        position: chunk_position,
+                        blocks: chunk.blocks.clone(),
+                        block_state: chunk.block_state.clone(),
+                        biomes: chunk.biomes.clone(),
+                    },
+                );
+            }
+            // Else it is still being generated, and will be sent when it's done.
+        }
+    }
+}
+
+/// A chunk that is being loaded or generated.
 #[derive(Component)]
-struct ChunkLoadingTask(Task<(IVec3, Chunk)>);
+pub struct ChunkLoadingTask {
+    pub position: IVec3,
+    task: Task<(IVec3, Chunk)>,
+}
 
 // TODO: This is too expensive to accommodate many players. I'm thinking chunks can be sorted into
 // columns. If it is a chunk that contains blocks, it would be considered a column base. All chunks
@@ -368,7 +542,7 @@ fn subscribe_to_visible_chunks(
                 let distance_to_adjacent = (adjacent_position - chunk_origin.0) / CHUNK_SIZE as i32;
                 if distance_to_adjacent
                     .abs()
-                    .cmpgt(IVec3::splat(render_distance.0 as i32))
+                    .cmpgt(IVec3::splat(render_distance.effective as i32))
                     .any()
                 {
                     continue;
@@ -400,9 +574,11 @@ fn handle_chunk_loading_tasks(
     chunk_subscriptions: Res<ChunkSubscriptions>,
     mut origin_query: Query<&mut PlayerChunkOrigin>,
     mut chunks: Query<(Entity, &mut ChunkLoadingTask)>,
+    mut load_chunk_events: EventWriter<ChunkLoadEvent>,
 ) {
     for (entity, mut task) in chunks.iter_mut() {
-        if let Some((chunk_position, mut chunk)) = future::block_on(future::poll_once(&mut task.0))
+        if let Some((chunk_position, mut chunk)) =
+            future::block_on(future::poll_once(&mut task.task))
         {
             // TODO: This seems to be a common operation? Maybe create some combination iterator
             // utilily to fight the drift. moore_neigbourhood(n) or something more friendly
@@ -448,6 +624,38 @@ fn handle_chunk_loading_tasks(
                 }
             }
 
+            // Structures that were planned with the chunk can reach further than its neighbours.
+            // The chunks they cover that are already loaded get their pieces here, the others
+            // load them from the database.
+            for structure in chunk.planned_structures.drain(..) {
+                for piece_position in structure.feature.blocks.keys() {
+                    if *piece_position == chunk_position {
+                        continue;
+                    }
+
+                    let Some(piece_chunk) = world_map.get_chunk_mut(piece_position) else {
+                        continue;
+                    };
+
+                    if let Some(changed) = structure
+                        .feature
+                        .apply_return_changed(piece_chunk, *piece_position)
+                    {
+                        if let Some(subscribers) =
+                            chunk_subscriptions.get_subscribers(piece_position)
+                        {
+                            net.send_many(
+                                subscribers,
+                                messages::BlockUpdates {
+                                    chunk_position: *piece_position,
+                                    blocks: changed,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+
             if let Some(subscribers) = chunk_subscriptions
                 .chunk_to_subscribers
                 .get(&chunk_position)
@@ -469,11 +677,13 @@ fn handle_chunk_loading_tasks(
                         position: chunk_position,
                         blocks: chunk.blocks.clone(),
                         block_state: chunk.block_state.clone(),
+                        biomes: chunk.biomes.clone(),
                     },
                 );
             }
 
             world_map.insert(chunk_position, chunk);
+            load_chunk_events.send(ChunkLoadEvent(chunk_position));
             commands.entity(entity).despawn();
         }
     }
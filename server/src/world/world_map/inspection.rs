@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use fmc_networking::{messages, NetworkData, NetworkServer};
+
+use crate::{
+    bevy_extensions::f64_transform::F64GlobalTransform,
+    players::Player,
+    settings::Settings,
+    utils,
+    world::{blocks::Blocks, models::Model},
+};
+
+use super::{
+    chunk_manager::{ChunkLoadingTask, ChunkSubscriptions},
+    persistence::BlockPersistence,
+    WorldMap,
+};
+
+// Commands for operators to look into how the chunks are doing when something seems off.
+//
+// '/worldstats' gives an overview of the loaded chunks, the chunks that are waiting to be
+// generated or saved, and how the models are spread across the chunks.
+//
+// '/chunkinfo <x> <z>' tells what state a single chunk is in. It is the chunk at the block
+// coordinates, at the height of the player.
+pub struct InspectionPlugin;
+impl Plugin for InspectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_inspection_commands);
+    }
+}
+
+fn format_position(position: IVec3) -> Vec<String> {
+    return vec![
+        position.x.to_string(),
+        position.y.to_string(),
+        position.z.to_string(),
+    ];
+}
+
+fn handle_inspection_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
+    chunk_subscriptions: Res<ChunkSubscriptions>,
+    persistence: Res<BlockPersistence>,
+    player_query: Query<(&Player, &F64GlobalTransform)>,
+    loading_query: Query<&ChunkLoadingTask>,
+    model_query: Query<&F64GlobalTransform, With<Model>>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        let command = words.next();
+        if command != Some("/worldstats") && command != Some("/chunkinfo") {
+            continue;
+        }
+
+        let Ok((player, transform)) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        let reply = |key: &str, args: Vec<String>| {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated(key, args),
+            );
+        };
+
+        if !settings.is_operator(&player.id) {
+            reply("inspection.not_operator", vec![]);
+            continue;
+        }
+
+        if command == Some("/worldstats") {
+            reply(
+                "worldstats.chunks",
+                vec![
+                    world_map.chunk_count().to_string(),
+                    loading_query.iter().len().to_string(),
+                ],
+            );
+            reply(
+                "worldstats.persistence",
+                vec![
+                    persistence.dirty_chunk_count().to_string(),
+                    persistence.queue_length().to_string(),
+                ],
+            );
+
+            let mut models_per_chunk: HashMap<IVec3, usize> = HashMap::new();
+            for transform in model_query.iter() {
+                let chunk_position = utils::world_position_to_chunk_position(
+                    transform.translation().floor().as_ivec3(),
+                );
+                *models_per_chunk.entry(chunk_position).or_default() += 1;
+            }
+
+            let model_count = models_per_chunk.values().sum::<usize>();
+            let average = model_count as f32 / world_map.chunk_count().max(1) as f32;
+            reply(
+                "worldstats.models",
+                vec![model_count.to_string(), format!("{:.2}", average)],
+            );
+
+            if let Some((chunk_position, count)) =
+                models_per_chunk.into_iter().max_by_key(|(_, count)| *count)
+            {
+                let mut args = format_position(chunk_position);
+                args.push(count.to_string());
+                reply("worldstats.busiest_chunk", args);
+            }
+
+            continue;
+        }
+
+        let (Some(Ok(x)), Some(Ok(z)), None) = (
+            words.next().map(str::parse::<i32>),
+            words.next().map(str::parse::<i32>),
+            words.next(),
+        ) else {
+            reply("chunkinfo.usage", vec![]);
+            continue;
+        };
+
+        let y = transform.translation().y.floor() as i32;
+        let chunk_position = utils::world_position_to_chunk_position(IVec3::new(x, y, z));
+
+        if let Some(chunk) = world_map.get_chunk(&chunk_position) {
+            reply("chunkinfo.loaded", format_position(chunk_position));
+            if chunk.is_uniform() {
+                let block_name = &Blocks::get().get_config(&chunk[0]).name;
+                reply("chunkinfo.uniform", vec![block_name.clone()]);
+            } else {
+                reply(
+                    "chunkinfo.regular",
+                    vec![chunk.changed_blocks.len().to_string()],
+                );
+            }
+        } else if loading_query
+            .iter()
+            .any(|loading| loading.position == chunk_position)
+        {
+            reply("chunkinfo.loading", format_position(chunk_position));
+        } else {
+            reply("chunkinfo.unloaded", format_position(chunk_position));
+        }
+
+        let subscribers: Vec<String> = chunk_subscriptions
+            .get_subscribers(&chunk_position)
+            .into_iter()
+            .flatten()
+            .map(|connection_id| {
+                // Connections that haven't finished joining yet don't have a player.
+                match player_query.get(connection_id.entity()) {
+                    Ok((player, _)) => player.username.clone(),
+                    Err(_) => connection_id.to_string(),
+                }
+            })
+            .collect();
+        reply(
+            "chunkinfo.subscribers",
+            vec![subscribers.len().to_string(), subscribers.join(", ")],
+        );
+
+        let pending_changes = persistence.pending_changes(&chunk_position);
+        match persistence.last_saved(&chunk_position) {
+            Some(last_saved) => reply(
+                "chunkinfo.last_saved",
+                vec![
+                    last_saved.elapsed().as_secs().to_string(),
+                    pending_changes.to_string(),
+                ],
+            ),
+            None => reply("chunkinfo.not_saved", vec![pending_changes.to_string()]),
+        }
+    }
+}
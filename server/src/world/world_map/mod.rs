@@ -1,17 +1,21 @@
 use std::{collections::HashMap, ops::Index, sync::Arc};
 
-use bevy::{app::AppExit, prelude::*, tasks::IoTaskPool};
+use bevy::prelude::*;
 
 use fmc_networking::{messages, BlockId, NetworkServer};
 
 pub mod chunk;
 pub mod chunk_manager;
+mod inspection;
+pub mod persistence;
+mod pregeneration;
+pub mod schematics;
 pub mod terrain_generation;
 mod world_map;
 
 pub use world_map::WorldMap;
 
-use crate::{database::Database, utils};
+use crate::utils;
 
 use self::chunk_manager::ChunkSubscriptions;
 
@@ -20,22 +24,21 @@ use super::blocks::{BlockFace, BlockState};
 pub struct WorldMapPlugin;
 impl Plugin for WorldMapPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(DatabaseSyncTimer(Timer::from_seconds(
-            5.0,
-            TimerMode::Repeating,
-        )))
-        .add_plugins(chunk_manager::ChunkManagerPlugin)
-        .add_plugins(terrain_generation::TerrainGenerationPlugin)
-        .add_event::<BlockUpdate>()
-        .add_event::<ChangedBlockEvent>()
-        .add_systems(
-            PostUpdate,
-            (
-                handle_block_updates.run_if(on_event::<BlockUpdate>()),
-                send_changed_block_event.after(handle_block_updates),
-                save_block_updates_to_database,
-            ),
-        );
+        app.add_plugins(chunk_manager::ChunkManagerPlugin)
+            .add_plugins(inspection::InspectionPlugin)
+            .add_plugins(persistence::PersistencePlugin)
+            .add_plugins(pregeneration::PregenerationPlugin)
+            .add_plugins(schematics::SchematicPlugin)
+            .add_plugins(terrain_generation::TerrainGenerationPlugin)
+            .add_event::<BlockUpdate>()
+            .add_event::<ChangedBlockEvent>()
+            .add_systems(
+                PostUpdate,
+                (
+                    handle_block_updates.run_if(on_event::<BlockUpdate>()),
+                    send_changed_block_event.after(handle_block_updates),
+                ),
+            );
     }
 }
 
@@ -166,78 +169,6 @@ fn handle_block_updates(
     }
 }
 
-#[derive(Resource, DerefMut, Deref)]
-struct DatabaseSyncTimer(Timer);
-
-async fn save_blocks(
-    database: Database,
-    block_updates: Vec<(IVec3, (BlockId, Option<BlockState>))>,
-) {
-    let mut conn = database.get_connection();
-    let transaction = conn.transaction().unwrap();
-    let mut statement = transaction
-        .prepare(
-            r#"
-        insert or replace into
-            blocks (x,y,z,block_id,block_state)
-        values
-            (?,?,?,?,?)
-        "#,
-        )
-        .unwrap();
-
-    for (position, (block_id, block_state)) in block_updates {
-        statement
-            .execute(rusqlite::params![
-                position.x,
-                position.y,
-                position.z,
-                block_id,
-                block_state.map(|state| state.0)
-            ])
-            .unwrap();
-    }
-    statement.finalize().unwrap();
-    transaction
-        .commit()
-        .expect("Failed to write blocks to database.");
-}
-
-fn save_block_updates_to_database(
-    database: Res<Database>,
-    time: Res<Time>,
-    mut block_events: EventReader<BlockUpdate>,
-    mut sync_timer: ResMut<DatabaseSyncTimer>,
-    exit_events: EventReader<AppExit>,
-    mut block_updates: Local<HashMap<IVec3, (BlockId, Option<BlockState>)>>,
-) {
-    for event in block_events.read() {
-        match event {
-            BlockUpdate::Change {
-                position,
-                block_id,
-                block_state,
-            } => {
-                block_updates.insert(*position, (*block_id, *block_state));
-            }
-        }
-    }
-
-    sync_timer.tick(time.delta());
-    if sync_timer.just_finished() {
-        let task_pool = IoTaskPool::get();
-        let block_updates = block_updates.drain().collect();
-        task_pool
-            .spawn(save_blocks(database.clone(), block_updates))
-            .detach();
-    }
-
-    if !exit_events.is_empty() {
-        let block_updates = block_updates.drain().collect();
-        futures_lite::future::block_on(save_blocks(database.clone(), block_updates));
-    }
-}
-
 fn send_changed_block_event(
     world_map: Res<WorldMap>,
     mut block_update_events: EventReader<BlockUpdate>,
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, TrySendError};
+use fmc_networking::BlockId;
+
+use crate::{
+    database::{Database, DatabaseError},
+    utils,
+    world::blocks::BlockState,
+};
+
+use super::BlockUpdate;
+
+// How many changed blocks can wait to be written before the game has to wait for the database.
+const QUEUE_CAPACITY: usize = 16384;
+// How long changes are collected before they are written in one transaction.
+const BATCH_INTERVAL: Duration = Duration::from_millis(500);
+// Large batches are split so a single transaction doesn't hold the database for too long.
+const MAX_BATCH_SIZE: usize = 4096;
+
+// Changed blocks are written to the database by a dedicated thread. The game sends the changes
+// through a bounded queue, and the thread collects them for a short while before writing them all
+// in one transaction. If the database can't keep up the queue fills and the game is slowed down
+// until there is space, instead of piling up changes in memory.
+//
+// When the server shuts down, the queue is closed and the thread writes what is left before
+// the server exits.
+pub struct PersistencePlugin;
+impl Plugin for PersistencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_worker).add_systems(
+            PostUpdate,
+            (
+                queue_block_updates,
+                flush_on_exit
+                    .after(queue_block_updates)
+                    .run_if(on_event::<AppExit>()),
+            ),
+        );
+    }
+}
+
+struct DirtyBlock {
+    position: IVec3,
+    block_id: BlockId,
+    block_state: Option<BlockState>,
+}
+
+// Which chunks have changes waiting to be written, and when the changes to each chunk were last
+// written. Shared with the thread.
+#[derive(Default)]
+struct ChunkSaves {
+    // How many of the queued changes are in each chunk.
+    dirty: HashMap<IVec3, usize>,
+    // Only chunks that have been written since the server started.
+    last_saved: HashMap<IVec3, Instant>,
+}
+
+/// Queue of block changes waiting to be written to the database.
+#[derive(Resource)]
+pub struct BlockPersistence {
+    // Both are taken when the server shuts down.
+    sender: Option<Sender<DirtyBlock>>,
+    worker: Option<JoinHandle<()>>,
+    // How many times the queue has been full.
+    stalls: AtomicU64,
+    chunk_saves: Arc<Mutex<ChunkSaves>>,
+}
+
+impl BlockPersistence {
+    /// Number of changes waiting to be written.
+    pub fn queue_length(&self) -> usize {
+        return self.sender.as_ref().map_or(0, |sender| sender.len());
+    }
+
+    /// How many times the queue has been full, forcing the game to wait for the database.
+    pub fn stalls(&self) -> u64 {
+        return self.stalls.load(Ordering::Relaxed);
+    }
+
+    /// Number of chunks that have changes waiting to be written.
+    pub fn dirty_chunk_count(&self) -> usize {
+        return self.chunk_saves.lock().unwrap().dirty.len();
+    }
+
+    /// Number of changes to the chunk that are waiting to be written.
+    pub fn pending_changes(&self, chunk_position: &IVec3) -> usize {
+        return self
+            .chunk_saves
+            .lock()
+            .unwrap()
+            .dirty
+            .get(chunk_position)
+            .copied()
+            .unwrap_or(0);
+    }
+
+    /// When changes to the chunk were last written, None if they haven't been since the server
+    /// started.
+    pub fn last_saved(&self, chunk_position: &IVec3) -> Option<Instant> {
+        return self
+            .chunk_saves
+            .lock()
+            .unwrap()
+            .last_saved
+            .get(chunk_position)
+            .copied();
+    }
+
+    fn send(&self, block: DirtyBlock) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        *self
+            .chunk_saves
+            .lock()
+            .unwrap()
+            .dirty
+            .entry(utils::world_position_to_chunk_position(block.position))
+            .or_default() += 1;
+
+        let block = match sender.try_send(block) {
+            Ok(()) => return,
+            Err(TrySendError::Full(block)) => block,
+            Err(TrySendError::Disconnected(_)) => {
+                error!("The block persistence thread has stopped, block changes are lost");
+                return;
+            }
+        };
+
+        self.stalls.fetch_add(1, Ordering::Relaxed);
+        if sender.send(block).is_err() {
+            error!("The block persistence thread has stopped, block changes are lost");
+        }
+    }
+}
+
+fn start_worker(mut commands: Commands, database: Res<Database>) {
+    let (sender, receiver) = crossbeam_channel::bounded(QUEUE_CAPACITY);
+
+    let chunk_saves = Arc::new(Mutex::new(ChunkSaves::default()));
+
+    let database = database.clone();
+    let worker_chunk_saves = chunk_saves.clone();
+    let worker = std::thread::Builder::new()
+        .name("block persistence".to_owned())
+        .spawn(move || run_worker(database, receiver, worker_chunk_saves))
+        .expect("Failed to start the block persistence thread");
+
+    commands.insert_resource(BlockPersistence {
+        sender: Some(sender),
+        worker: Some(worker),
+        stalls: AtomicU64::new(0),
+        chunk_saves,
+    });
+}
+
+fn run_worker(
+    database: Database,
+    receiver: Receiver<DirtyBlock>,
+    chunk_saves: Arc<Mutex<ChunkSaves>>,
+) {
+    let _waiting = Database::allow_waiting();
+
+    // Blocks that change several times during a batch are only written once.
+    let mut batch: HashMap<IVec3, (BlockId, Option<BlockState>)> = HashMap::new();
+    // How many changes were received for each chunk, including those that were overwritten.
+    let mut batch_chunks: HashMap<IVec3, usize> = HashMap::new();
+
+    // Waits for the first change of each batch, the loop ends when the queue is closed and empty.
+    while let Ok(block) = receiver.recv() {
+        add_to_batch(&mut batch, &mut batch_chunks, block);
+
+        let deadline = Instant::now() + BATCH_INTERVAL;
+        let mut closed = false;
+        while batch.len() < MAX_BATCH_SIZE {
+            match receiver.recv_deadline(deadline) {
+                Ok(block) => add_to_batch(&mut batch, &mut batch_chunks, block),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        let saved = match save_blocks(&database, &batch) {
+            Ok(()) => true,
+            Err(err) => {
+                error!(
+                    "Failed to write {} block changes to the database: {}",
+                    batch.len(),
+                    err
+                );
+                false
+            }
+        };
+        batch.clear();
+
+        // Failed changes are lost, so they are no longer waiting either.
+        let now = Instant::now();
+        let mut saves = chunk_saves.lock().unwrap();
+        for (chunk_position, count) in batch_chunks.drain() {
+            if let Some(dirty) = saves.dirty.get_mut(&chunk_position) {
+                *dirty = dirty.saturating_sub(count);
+                if *dirty == 0 {
+                    saves.dirty.remove(&chunk_position);
+                }
+            }
+            if saved {
+                saves.last_saved.insert(chunk_position, now);
+            }
+        }
+        drop(saves);
+
+        if closed {
+            break;
+        }
+    }
+}
+
+fn add_to_batch(
+    batch: &mut HashMap<IVec3, (BlockId, Option<BlockState>)>,
+    batch_chunks: &mut HashMap<IVec3, usize>,
+    block: DirtyBlock,
+) {
+    *batch_chunks
+        .entry(utils::world_position_to_chunk_position(block.position))
+        .or_default() += 1;
+    batch.insert(block.position, (block.block_id, block.block_state));
+}
+
+fn save_blocks(
+    database: &Database,
+    blocks: &HashMap<IVec3, (BlockId, Option<BlockState>)>,
+) -> Result<(), DatabaseError> {
+    return database.retry(|| {
+        let mut conn = database.get_connection()?;
+        let transaction = conn.transaction()?;
+        let mut statement = transaction.prepare(
+            r#"
+            insert or replace into
+                blocks (x,y,z,block_id,block_state)
+            values
+                (?,?,?,?,?)
+            "#,
+        )?;
+
+        for (position, (block_id, block_state)) in blocks.iter() {
+            statement.execute(rusqlite::params![
+                position.x,
+                position.y,
+                position.z,
+                block_id,
+                block_state.map(|state| state.0)
+            ])?;
+        }
+        statement.finalize()?;
+        transaction.commit()?;
+
+        return Ok(());
+    });
+}
+
+fn queue_block_updates(
+    persistence: Res<BlockPersistence>,
+    mut block_events: EventReader<BlockUpdate>,
+) {
+    for event in block_events.read() {
+        match event {
+            BlockUpdate::Change {
+                position,
+                block_id,
+                block_state,
+            } => persistence.send(DirtyBlock {
+                position: *position,
+                block_id: *block_id,
+                block_state: *block_state,
+            }),
+        }
+    }
+}
+
+fn flush_on_exit(mut persistence: ResMut<BlockPersistence>) {
+    // Closing the queue makes the thread write what is left and stop.
+    persistence.sender.take();
+
+    let Some(worker) = persistence.worker.take() else {
+        return;
+    };
+
+    info!("Writing the remaining block changes to the database...");
+    if worker.join().is_err() {
+        error!("The block persistence thread panicked, some block changes may be lost");
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::{
+    console::ConsoleCommand, constants::CHUNK_SIZE, database::Database, utils,
+    world::WorldProperties,
+};
+
+use super::{
+    chunk::{Chunk, GeneratedChunk},
+    terrain_generation::TerrainGenerator,
+};
+
+// The heights that are generated. Chunks above are only air and cheap to generate, and players
+// rarely dig further down.
+const MIN_HEIGHT: i32 = -64;
+const MAX_HEIGHT: i32 = 128;
+// Largest radius that can be generated, in chunks. It is already several million chunks.
+const MAX_RADIUS: u32 = 256;
+// How often the progress is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+// Generates the chunks around the spawn point ahead of time, so the first players to join don't
+// have to wait for them to be generated.
+//
+// 'pregen <radius>' in the console generates all the chunks within the radius, counted in chunks,
+// of the chunk the spawn point is in, closest first. They are saved to the database as they were
+// generated, and when they are loaded later only the changes made to them are applied. It keeps
+// every thread of the async compute pool busy until it is done, so it is best run before the
+// players join. Structures that are planned in the chunks are saved as usual, but chunks they
+// cover that are already loaded don't get their piece until they are loaded again.
+//
+// 'pregen stop' stops it. The chunks that are done are kept, and are skipped when it is run again.
+//
+// 'pregen clear' removes all the generated chunks. They are generated again on their own when
+// the settings, the terrain generation, or the names of the blocks, items or biomes change, but
+// not when what is in the configurations of them does, clear them after.
+pub struct PregenerationPlugin;
+impl Plugin for PregenerationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Pregeneration>().add_systems(
+            Update,
+            (handle_console_commands, pregenerate_chunks).chain(),
+        );
+    }
+}
+
+#[derive(Resource, Default)]
+struct Pregeneration(Option<Run>);
+
+struct Run {
+    // Chunks that have not been started yet, closest to the spawn point first.
+    queue: VecDeque<IVec3>,
+    // Each task returns if it had to generate the chunk, it is false if it was already done.
+    tasks: Vec<Task<bool>>,
+    total: usize,
+    generated: usize,
+    skipped: usize,
+    start: Instant,
+    last_progress: Instant,
+}
+
+impl Run {
+    fn new(center: IVec3, radius: u32) -> Self {
+        let radius = radius as i32;
+
+        let mut columns = Vec::new();
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                if x * x + z * z <= radius * radius {
+                    columns.push(IVec2::new(x, z));
+                }
+            }
+        }
+        columns.sort_by_key(|column| column.length_squared());
+
+        let center = utils::world_position_to_chunk_position(center);
+        let mut queue = VecDeque::new();
+        for column in columns {
+            for y in (MIN_HEIGHT..MAX_HEIGHT).step_by(CHUNK_SIZE) {
+                queue.push_back(IVec3::new(
+                    center.x + column.x * CHUNK_SIZE as i32,
+                    y,
+                    center.z + column.y * CHUNK_SIZE as i32,
+                ));
+            }
+        }
+
+        let now = Instant::now();
+        return Self {
+            total: queue.len(),
+            queue,
+            tasks: Vec::new(),
+            generated: 0,
+            skipped: 0,
+            start: now,
+            last_progress: now,
+        };
+    }
+
+    fn done(&self) -> usize {
+        return self.generated + self.skipped;
+    }
+}
+
+fn handle_console_commands(
+    database: Res<Database>,
+    world_properties: Res<WorldProperties>,
+    mut pregeneration: ResMut<Pregeneration>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    for command in console_commands.read() {
+        if command.name() != Some("pregen") {
+            continue;
+        }
+
+        match command.args() {
+            [arg] if arg == "stop" => match pregeneration.0.take() {
+                Some(run) => info!(
+                    "Stopped pregenerating chunks, {} of {} are done",
+                    run.done(),
+                    run.total
+                ),
+                None => info!("No chunks are being pregenerated"),
+            },
+            [arg] if arg == "clear" => {
+                if pregeneration.0.is_some() {
+                    info!("Chunks are being pregenerated, stop it first with 'pregen stop'");
+                    continue;
+                }
+
+                match database.clear_generated_chunks() {
+                    Ok(count) => info!("Removed {} pregenerated chunks", count),
+                    Err(err) => error!("Failed to remove the pregenerated chunks: {}", err),
+                }
+            }
+            [radius] => {
+                let radius = match radius.parse::<u32>() {
+                    Ok(radius) if radius <= MAX_RADIUS => radius,
+                    _ => {
+                        info!(
+                            "The radius must be a number of chunks between 0 and {}",
+                            MAX_RADIUS
+                        );
+                        continue;
+                    }
+                };
+
+                if pregeneration.0.is_some() {
+                    info!(
+                        "Chunks are already being pregenerated, stop it first with 'pregen stop'"
+                    );
+                    continue;
+                }
+
+                let run = Run::new(world_properties.spawn_point.center, radius);
+                info!(
+                    "Pregenerating {} chunks within {} chunks of the spawn point",
+                    run.total, radius
+                );
+                pregeneration.0 = Some(run);
+            }
+            _ => info!("Usage: pregen <radius>|stop|clear"),
+        }
+    }
+}
+
+fn pregenerate_chunks(
+    terrain_generator: Res<TerrainGenerator>,
+    database: Res<Database>,
+    mut pregeneration: ResMut<Pregeneration>,
+) {
+    let Some(run) = pregeneration.0.as_mut() else {
+        return;
+    };
+
+    run.tasks
+        .retain_mut(|task| match future::block_on(future::poll_once(task)) {
+            Some(true) => {
+                run.generated += 1;
+                false
+            }
+            Some(false) => {
+                run.skipped += 1;
+                false
+            }
+            None => true,
+        });
+
+    // Keep a few more tasks than there are threads, so none of them wait for the next tick.
+    let thread_pool = AsyncComputeTaskPool::get();
+    while run.tasks.len() < thread_pool.thread_num() * 2 {
+        let Some(position) = run.queue.pop_front() else {
+            break;
+        };
+        run.tasks.push(thread_pool.spawn(pregenerate(
+            position,
+            terrain_generator.clone(),
+            database.clone(),
+        )));
+    }
+
+    if run.tasks.is_empty() {
+        info!(
+            "Finished pregenerating chunks in {:.0?}, generated {} and skipped {} that were \
+            already done",
+            run.start.elapsed(),
+            run.generated,
+            run.skipped
+        );
+        pregeneration.0 = None;
+    } else if run.last_progress.elapsed() >= PROGRESS_INTERVAL {
+        run.last_progress = Instant::now();
+
+        let per_second = run.done() as f32 / run.start.elapsed().as_secs_f32();
+        let left = (run.total - run.done()) as f32 / per_second.max(1.0);
+        info!(
+            "Pregenerating chunks: {}/{} ({:.1}%), {:.0} chunks/s, about {:.0?} left",
+            run.done(),
+            run.total,
+            run.done() as f32 / run.total as f32 * 100.0,
+            per_second,
+            Duration::from_secs_f32(left)
+        );
+    }
+}
+
+// Returns false if the chunk had already been generated.
+async fn pregenerate(
+    position: IVec3,
+    terrain_generator: TerrainGenerator,
+    database: Database,
+) -> bool {
+    let _waiting = Database::allow_waiting();
+
+    let fingerprint = terrain_generator.fingerprint();
+
+    match database.has_generated_chunk(&position, fingerprint) {
+        Ok(true) => return false,
+        Ok(false) => (),
+        Err(err) => {
+            error!(
+                "Failed to check if the chunk at {} has been pregenerated: {}",
+                position, err
+            );
+            return false;
+        }
+    }
+
+    let chunk = Chunk::generate(position, &terrain_generator, &database);
+    if let Err(err) =
+        database.save_generated_chunk(&position, fingerprint, &GeneratedChunk::from(chunk))
+    {
+        error!(
+            "Failed to save the pregenerated chunk at {}: {}",
+            position, err
+        );
+    }
+
+    return true;
+}
@@ -0,0 +1,283 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use bevy::prelude::*;
+use fmc_networking::{messages, BlockId, NetworkData, NetworkServer};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    players::Player,
+    settings::Settings,
+    world::blocks::{BlockState, Blocks},
+};
+
+use super::{terrain_generation::TerrainFeature, BlockUpdate, WorldMap};
+
+// Where schematics are written to and read from.
+const SCHEMATIC_DIRECTORY: &str = "./schematics";
+// Largest number of blocks a schematic can hold, so a typo can't bring the server down.
+const MAX_VOLUME: i64 = 128 * 128 * 128;
+
+// Operators can copy a part of the world to a file and paste it somewhere else, or on another
+// server.
+//
+// '/schematic copy <name> <x1> <y1> <z1> <x2> <y2> <z2>' copies the blocks between the two
+// corners to 'schematics/<name>.json'.
+//
+// '/schematic paste <name> <x> <y> <z>' places the schematic with its lowest corner at the
+// position. It is turned into a terrain feature that replaces everything, and placed through
+// block updates, so the chunks are saved and the players are sent the changes as usual. All the
+// chunks it covers must be loaded.
+pub struct SchematicPlugin;
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_schematic_commands);
+    }
+}
+
+/// A cuboid of blocks. The blocks are stored by name, so schematics can be moved between servers.
+#[derive(Serialize, Deserialize)]
+pub struct Schematic {
+    /// Number of blocks along each axis.
+    size: [i32; 3],
+    /// Names of the blocks the schematic uses.
+    palette: Vec<String>,
+    /// Index into the palette for every block. Laid out in xzy order like the chunks, the block
+    /// at (x, y, z) is at `x * (size_z * size_y) + z * size_y + y`.
+    blocks: Vec<u16>,
+    /// Block states of the blocks that have them, by the index of the block.
+    block_states: HashMap<usize, u16>,
+}
+
+impl Schematic {
+    /// Copy the blocks between the two corners, both included.
+    pub fn copy(world_map: &WorldMap, corner: IVec3, other_corner: IVec3) -> Result<Self, String> {
+        let min = corner.min(other_corner);
+        let max = corner.max(other_corner);
+        let size = max - min + IVec3::ONE;
+
+        let volume = size.x as i64 * size.y as i64 * size.z as i64;
+        if volume > MAX_VOLUME {
+            return Err(format!(
+                "{} blocks is too large, it can be at most {}",
+                volume, MAX_VOLUME
+            ));
+        }
+
+        let block_configs = Blocks::get();
+        let mut palette = Vec::new();
+        let mut palette_indices: HashMap<BlockId, u16> = HashMap::new();
+        let mut blocks = Vec::with_capacity(volume as usize);
+        let mut block_states = HashMap::new();
+
+        for x in min.x..=max.x {
+            for z in min.z..=max.z {
+                for y in min.y..=max.y {
+                    let position = IVec3::new(x, y, z);
+                    let Some(block_id) = world_map.get_block(position) else {
+                        return Err(format!("The block at {} is not loaded", position));
+                    };
+
+                    let palette_index = *palette_indices.entry(block_id).or_insert_with(|| {
+                        palette.push(block_configs.get_config(&block_id).name.clone());
+                        palette.len() as u16 - 1
+                    });
+
+                    if let Some(block_state) = world_map.get_block_state(position) {
+                        block_states.insert(blocks.len(), block_state.as_u16());
+                    }
+
+                    blocks.push(palette_index);
+                }
+            }
+        }
+
+        return Ok(Self {
+            size: size.to_array(),
+            palette,
+            blocks,
+            block_states,
+        });
+    }
+
+    /// The schematic as a terrain feature with its lowest corner at the origin. It can replace
+    /// any block.
+    pub fn to_feature(&self, origin: IVec3) -> Result<TerrainFeature, String> {
+        let block_configs = Blocks::get();
+
+        let mut palette = Vec::with_capacity(self.palette.len());
+        for block_name in self.palette.iter() {
+            if !block_configs.contains_block(block_name) {
+                return Err(format!("There is no block named '{}'", block_name));
+            }
+            palette.push(block_configs.get_id(block_name));
+        }
+
+        let [width, height, depth] = self.size;
+        let volume = width as i64 * height as i64 * depth as i64;
+        if width <= 0 || height <= 0 || depth <= 0 || self.blocks.len() as i64 != volume {
+            return Err("The size does not match the number of blocks".to_owned());
+        } else if volume > MAX_VOLUME {
+            return Err(format!(
+                "{} blocks is too large, it can be at most {}",
+                volume, MAX_VOLUME
+            ));
+        }
+
+        let mut feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: block_configs.clone_ids().into_values().collect(),
+            loot: Vec::new(),
+        };
+
+        for (index, palette_index) in self.blocks.iter().enumerate() {
+            let Some(block_id) = palette.get(*palette_index as usize) else {
+                return Err(format!("{} is not in the palette", palette_index));
+            };
+
+            let offset = IVec3::new(
+                index as i32 / (depth * height),
+                index as i32 % height,
+                index as i32 / height % depth,
+            );
+            let block_state = self.block_states.get(&index).copied().map(BlockState);
+            feature.insert_block_with_state(origin + offset, *block_id, block_state);
+        }
+
+        return Ok(feature);
+    }
+
+    pub fn save(&self, name: &str) -> Result<PathBuf, String> {
+        let json = serde_json::to_string(self).map_err(|err| err.to_string())?;
+
+        std::fs::create_dir_all(SCHEMATIC_DIRECTORY).map_err(|err| err.to_string())?;
+        let path = schematic_path(name)?;
+        std::fs::write(&path, json).map_err(|err| err.to_string())?;
+
+        info!("Saved the schematic '{}' to '{}'", name, path.display());
+
+        return Ok(path);
+    }
+
+    pub fn load(name: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(schematic_path(name)?).map_err(|err| err.to_string())?;
+        return serde_json::from_str(&json).map_err(|err| err.to_string());
+    }
+}
+
+fn schematic_path(name: &str) -> Result<PathBuf, String> {
+    // The name is used as the filename, it can't be allowed to point outside the directory.
+    if std::path::Path::new(name).file_name() != Some(name.as_ref()) {
+        return Err(format!("'{}' can't be used as a filename", name));
+    }
+
+    return Ok(PathBuf::from(SCHEMATIC_DIRECTORY).join(name.to_owned() + ".json"));
+}
+
+/// Place the feature through block updates. Returns how many blocks were changed.
+fn paste(
+    feature: &TerrainFeature,
+    world_map: &WorldMap,
+    block_updates: &mut EventWriter<BlockUpdate>,
+) -> Result<usize, String> {
+    // Check first so it isn't left half placed.
+    for chunk_position in feature.blocks.keys() {
+        if !world_map.contains_chunk(chunk_position) {
+            return Err(format!("The chunk at {} is not loaded", chunk_position));
+        }
+    }
+
+    let mut count = 0;
+    for chunk_position in feature.blocks.keys() {
+        let chunk = world_map.get_chunk(chunk_position).unwrap();
+        let updates = feature.block_updates(chunk, *chunk_position);
+        count += updates.len();
+        block_updates.send_batch(updates);
+    }
+
+    return Ok(count);
+}
+
+fn parse_position<'a>(words: &mut impl Iterator<Item = &'a str>) -> Option<IVec3> {
+    let mut coordinates = [0; 3];
+    for coordinate in coordinates.iter_mut() {
+        *coordinate = words.next()?.parse::<i32>().ok()?;
+    }
+    return Some(IVec3::from_array(coordinates));
+}
+
+fn handle_schematic_commands(
+    net: Res<NetworkServer>,
+    settings: Res<Settings>,
+    world_map: Res<WorldMap>,
+    player_query: Query<&Player>,
+    mut block_updates: EventWriter<BlockUpdate>,
+    mut chat_messages: EventReader<NetworkData<messages::ChatMessageClient>>,
+) {
+    for chat_message in chat_messages.read() {
+        let mut words = chat_message.message.split_whitespace();
+        if words.next() != Some("/schematic") {
+            continue;
+        }
+
+        let Ok(player) = player_query.get(chat_message.source.entity()) else {
+            continue;
+        };
+
+        if !settings.is_operator(&player.id) {
+            net.send_one(
+                chat_message.source,
+                messages::ChatMessageServer::translated("schematic.not_operator", vec![]),
+            );
+            continue;
+        }
+
+        let command = words.next();
+        let name = words.next();
+        let reply = match (command, name) {
+            (Some("copy"), Some(name)) => {
+                match (
+                    parse_position(&mut words),
+                    parse_position(&mut words),
+                    words.next(),
+                ) {
+                    (Some(corner), Some(other_corner), None) => {
+                        match Schematic::copy(&world_map, corner, other_corner)
+                            .and_then(|schematic| schematic.save(name))
+                        {
+                            Ok(path) => messages::ChatMessageServer::translated(
+                                "schematic.copy.done",
+                                vec![name.to_owned(), path.display().to_string()],
+                            ),
+                            Err(err) => messages::ChatMessageServer::translated(
+                                "schematic.copy.failed",
+                                vec![err],
+                            ),
+                        }
+                    }
+                    _ => messages::ChatMessageServer::translated("schematic.usage", vec![]),
+                }
+            }
+            (Some("paste"), Some(name)) => match (parse_position(&mut words), words.next()) {
+                (Some(position), None) => {
+                    match Schematic::load(name)
+                        .and_then(|schematic| schematic.to_feature(position))
+                        .and_then(|feature| paste(&feature, &world_map, &mut block_updates))
+                    {
+                        Ok(count) => messages::ChatMessageServer::translated(
+                            "schematic.paste.done",
+                            vec![name.to_owned(), count.to_string()],
+                        ),
+                        Err(err) => messages::ChatMessageServer::translated(
+                            "schematic.paste.failed",
+                            vec![err],
+                        ),
+                    }
+                }
+                _ => messages::ChatMessageServer::translated("schematic.usage", vec![]),
+            },
+            _ => messages::ChatMessageServer::translated("schematic.usage", vec![]),
+        };
+
+        net.send_one(chat_message.source, reply);
+    }
+}
@@ -1,15 +1,31 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use bevy::math::{IVec3, Vec2};
 use fmc_networking::BlockId;
+use serde::Deserialize;
 
 use crate::world::{
     blocks::{Blocks, BLOCK_CONFIG_PATH},
     world_map::terrain_generation::blueprints::BLUEPRINT_PATH,
 };
 
-use super::blueprints::{load_blueprints, Blueprint};
+use super::{
+    blueprints::{load_blueprints, Blueprint, Features},
+    decorations::{Decoration, DecorationJson},
+    villages::{load_villages, Village, VILLAGE_PATH},
+};
+
+pub const BIOME_PATH: &str = "./resources/server/biomes/";
+// How the biomes look to the clients, each biome must have a file of the same name here.
+const CLIENT_BIOME_PATH: &str = "./resources/client/biomes/";
+
+// Close to the border between two biomes they are blended, so there's no seam where they meet.
+// Measured in distance between climates.
+const BLEND_DISTANCE: f32 = 0.05;
 
 pub struct Biome {
+    /// Number the clients know the biome by, its place among the biomes when sorted by name.
+    pub id: u8,
     pub top_layer_block: BlockId,
     pub mid_layer_block: BlockId,
     pub bottom_layer_block: BlockId,
@@ -17,9 +33,20 @@ pub struct Biome {
     pub sub_surface_liquid: BlockId,
     pub air: BlockId,
     pub sand: BlockId,
+    /// Lines the bottom of rivers.
+    pub riverbed_block: BlockId,
     pub blueprints: Vec<Blueprint>,
+    /// The villages that are built in the biome, None if it has none.
+    pub village: Option<Arc<Village>>,
+    /// Blocks scattered on the surface, tried in order.
+    pub decorations: Vec<Decoration>,
+    /// Multiplier for how tall the terrain can grow above its base height.
+    pub height_scale: f32,
+    /// Amount of blocks the base height of the terrain is moved up or down.
+    pub height_offset: f32,
 }
 
+#[derive(Deserialize)]
 struct BiomeJson {
     top_layer_block: String,
     mid_layer_block: String,
@@ -28,31 +55,48 @@ struct BiomeJson {
     sub_surface_liquid: String,
     air: String,
     sand: String,
+    riverbed_block: String,
     blueprints: Vec<String>,
+    // Name of the village config, the biome has no villages if left out.
+    #[serde(default)]
+    village: Option<String>,
+    #[serde(default)]
+    decorations: Vec<DecorationJson>,
+    // The climate the biome is placed in, between -1 and 1. Each block column gets the biome with
+    // the climate closest to its own.
+    temperature: f32,
+    humidity: f32,
+    #[serde(default = "default_height_scale")]
+    height_scale: f32,
+    #[serde(default)]
+    height_offset: f32,
+}
+
+fn default_height_scale() -> f32 {
+    1.0
+}
+
+/// The biome of a block column.
+pub struct ColumnBiome<'a> {
+    /// The biome the blocks of the column are taken from. Close to a border this is sometimes the
+    /// neighbouring biome, so the surface blocks of the two are mixed.
+    pub biome: &'a Biome,
+    /// Height scale blended with the neighbouring biome.
+    pub height_scale: f32,
+    /// Height offset blended with the neighbouring biome.
+    pub height_offset: f32,
 }
 
-// TODO: Create dynamically so it's easier to change. Should be able to add biomes between
-// intervals and error if they overlap.
 pub struct Biomes {
-    biomes: [Biome; 1],
+    // The biomes and the climate they are placed in, as (temperature, humidity).
+    biomes: Vec<(Vec2, Biome)>,
+    // Map from biome name to id, for all biomes, including those left out of the world.
+    ids: HashMap<String, u8>,
 }
 
 impl Biomes {
-    pub fn load() -> Self {
-        // TODO: Biomes should be loaded from file, and shouldn't look like this. No sand, air =
-        // filler. Not finished because I haven't decided on the biome model yet.
-        let biome_name = "base".to_owned();
-        let base_biome = BiomeJson {
-            top_layer_block: "grass".to_owned(),
-            mid_layer_block: "dirt".to_owned(),
-            bottom_layer_block: "stone".to_owned(),
-            surface_liquid: "surface_water".to_owned(),
-            sub_surface_liquid: "subsurface_water".to_owned(),
-            air: "air".to_owned(),
-            sand: "sand".to_owned(),
-            blueprints: vec!["distribute_trees".to_owned(), "coal_ore".to_owned()],
-        };
-
+    /// Load the biomes, if a name is given only that biome is kept and placed everywhere.
+    pub fn load(features: &Features, only: Option<&str>) -> Self {
         fn validate_block(biome_name: &str, block_name: &str) {
             let blocks = Blocks::get();
             if !blocks.contains_block(block_name) {
@@ -80,43 +124,236 @@ impl Biomes {
             }
         }
 
-        validate_block(&biome_name, &base_biome.top_layer_block);
-        validate_block(&biome_name, &base_biome.mid_layer_block);
-        validate_block(&biome_name, &base_biome.bottom_layer_block);
-        validate_block(&biome_name, &base_biome.surface_liquid);
-        validate_block(&biome_name, &base_biome.sub_surface_liquid);
-        validate_block(&biome_name, &base_biome.air);
-        validate_block(&biome_name, &base_biome.sand);
-
-        let blueprints = load_blueprints();
-        for blueprint_name in base_biome.blueprints.iter() {
-            validate_blueprint(&biome_name, blueprint_name, &blueprints);
+        let blueprints = load_blueprints(features);
+        let villages = load_villages(&blueprints);
+        let blocks = Blocks::get();
+
+        let directory = std::fs::read_dir(BIOME_PATH).expect(&format!(
+            "Could not read files from biomes directory, make sure it is present as '{}'",
+            BIOME_PATH
+        ));
+
+        let mut biomes = Vec::new();
+
+        for entry in directory {
+            let file_path = entry
+                .expect("Failed to read the filenames of the biomes")
+                .path();
+
+            let file = std::fs::File::open(&file_path).expect(&format!(
+                "Failed to open biome file at '{}'",
+                file_path.display()
+            ));
+            let biome_json: BiomeJson = serde_json::from_reader(file).expect(&format!(
+                "Failed to read biome at '{}'",
+                file_path.display()
+            ));
+            let biome_name = file_path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+
+            validate_block(&biome_name, &biome_json.top_layer_block);
+            validate_block(&biome_name, &biome_json.mid_layer_block);
+            validate_block(&biome_name, &biome_json.bottom_layer_block);
+            validate_block(&biome_name, &biome_json.surface_liquid);
+            validate_block(&biome_name, &biome_json.sub_surface_liquid);
+            validate_block(&biome_name, &biome_json.air);
+            validate_block(&biome_name, &biome_json.sand);
+            validate_block(&biome_name, &biome_json.riverbed_block);
+
+            for blueprint_name in biome_json.blueprints.iter() {
+                validate_blueprint(&biome_name, blueprint_name, &blueprints);
+            }
+
+            if let Some(village_name) = &biome_json.village {
+                if !villages.contains_key(village_name) {
+                    panic!(
+                        "Failed while validating the biomes. The biome '{}' has villages by the \
+                        name '{}', but no such village exists. This is most likely the result of \
+                        a missing file at '{}', make sure it is present.",
+                        biome_name, village_name, VILLAGE_PATH
+                    );
+                }
+            }
+
+            for decoration in biome_json.decorations.iter() {
+                validate_block(&biome_name, &decoration.block);
+                for block_name in decoration.placed_on.iter() {
+                    validate_block(&biome_name, block_name);
+                }
+            }
+
+            let climate = Vec2::new(biome_json.temperature, biome_json.humidity);
+            if climate.abs().max_element() > 1.0 {
+                panic!(
+                    "Failed while validating the biomes. The biome '{}' has a temperature or \
+                    humidity outside of -1..1, it would never be placed.",
+                    biome_name
+                );
+            }
+
+            let client_config_path = CLIENT_BIOME_PATH.to_owned() + &biome_name + ".json";
+            if !std::path::Path::new(&client_config_path).exists() {
+                panic!(
+                    "Failed while validating the biomes. The biome '{}' has no visual config for \
+                    the clients, make sure it is present at '{}'",
+                    biome_name, client_config_path
+                );
+            }
+
+            let biome = Biome {
+                // Assigned when all the biomes are loaded.
+                id: 0,
+                top_layer_block: blocks.get_id(&biome_json.top_layer_block),
+                mid_layer_block: blocks.get_id(&biome_json.mid_layer_block),
+                bottom_layer_block: blocks.get_id(&biome_json.bottom_layer_block),
+                surface_liquid: blocks.get_id(&biome_json.surface_liquid),
+                sub_surface_liquid: blocks.get_id(&biome_json.sub_surface_liquid),
+                air: blocks.get_id(&biome_json.air),
+                sand: blocks.get_id(&biome_json.sand),
+                riverbed_block: blocks.get_id(&biome_json.riverbed_block),
+                blueprints: biome_json
+                    .blueprints
+                    .iter()
+                    .map(|name| blueprints[name].clone())
+                    .collect(),
+                village: biome_json
+                    .village
+                    .as_ref()
+                    .map(|name| villages[name].clone()),
+                decorations: biome_json
+                    .decorations
+                    .iter()
+                    .map(|decoration| Decoration {
+                        block: blocks.get_id(&decoration.block),
+                        placed_on: decoration
+                            .placed_on
+                            .iter()
+                            .map(|name| blocks.get_id(name))
+                            .collect(),
+                        density: decoration.density,
+                    })
+                    .collect(),
+                height_scale: biome_json.height_scale,
+                height_offset: biome_json.height_offset,
+            };
+
+            biomes.push((biome_name, climate, biome));
+        }
+
+        if biomes.is_empty() {
+            panic!(
+                "Failed while loading the biomes. There has to be at least one biome at '{}'",
+                BIOME_PATH
+            );
         }
 
-        let blocks = Blocks::get();
+        if biomes.len() > u8::MAX as usize + 1 {
+            panic!(
+                "Failed while loading the biomes. There can be at most {} biomes",
+                u8::MAX as usize + 1
+            );
+        }
 
-        let base_biome = Biome {
-            top_layer_block: blocks.get_id(&base_biome.top_layer_block),
-            mid_layer_block: blocks.get_id(&base_biome.mid_layer_block),
-            bottom_layer_block: blocks.get_id(&base_biome.bottom_layer_block),
-            surface_liquid: blocks.get_id(&base_biome.surface_liquid),
-            sub_surface_liquid: blocks.get_id(&base_biome.sub_surface_liquid),
-            air: blocks.get_id(&base_biome.air),
-            sand: blocks.get_id(&base_biome.sand),
-            blueprints: base_biome
-                .blueprints
-                .iter()
-                .map(|name| blueprints[name].clone())
-                .collect(),
-        };
+        // Numbered by name. The clients are told which id belongs to which biome when they
+        // connect.
+        biomes.sort_by(|(first, _, _), (second, _, _)| first.cmp(second));
+        let mut ids = HashMap::with_capacity(biomes.len());
+        for (id, (name, _, biome)) in biomes.iter_mut().enumerate() {
+            biome.id = id as u8;
+            ids.insert(name.clone(), id as u8);
+        }
+
+        if let Some(only) = only {
+            biomes.retain(|(name, _, _)| name == only);
+            if biomes.is_empty() {
+                panic!(
+                    "Failed while loading the biomes. The world should only have the biome '{}', \
+                    but no biome by that name exists at '{}'",
+                    only, BIOME_PATH
+                );
+            }
+        }
+
+        for (i, (first_name, first_climate, _)) in biomes.iter().enumerate() {
+            for (second_name, second_climate, _) in biomes.iter().skip(i + 1) {
+                if first_climate == second_climate {
+                    panic!(
+                        "Failed while validating the biomes. The biomes '{}' and '{}' have the \
+                        same temperature and humidity, only one of them would be placed.",
+                        first_name, second_name
+                    );
+                }
+            }
+        }
 
         return Biomes {
-            biomes: [base_biome],
+            biomes: biomes
+                .into_iter()
+                .map(|(_, climate, biome)| (climate, biome))
+                .collect(),
+            ids,
         };
     }
 
-    // TODO: When implementing this, remember that the call sites also cheat.
-    pub fn get_biome(&self) -> &Biome {
-        return &self.biomes[0];
+    /// All the biomes that are placed in the world.
+    pub fn iter(&self) -> impl Iterator<Item = &Biome> {
+        return self.biomes.iter().map(|(_, biome)| biome);
     }
+
+    pub fn clone_ids(&self) -> HashMap<String, u8> {
+        return self.ids.clone();
+    }
+
+    /// The biome of the block column at the position, from the temperature and humidity there.
+    pub fn get_biome(&self, position: IVec3, temperature: f32, humidity: f32) -> ColumnBiome<'_> {
+        let climate = Vec2::new(temperature, humidity);
+
+        let mut closest = (f32::MAX, &self.biomes[0].1);
+        let mut second_closest = (f32::MAX, &self.biomes[0].1);
+        for (biome_climate, biome) in self.biomes.iter() {
+            let distance = climate.distance(*biome_climate);
+            if distance < closest.0 {
+                second_closest = closest;
+                closest = (distance, biome);
+            } else if distance < second_closest.0 {
+                second_closest = (distance, biome);
+            }
+        }
+
+        let (distance, biome) = closest;
+        let (neighbour_distance, neighbour) = second_closest;
+
+        // Halfway between the two at the border, all this biome at the blend distance.
+        let weight = if neighbour_distance - distance < BLEND_DISTANCE {
+            0.5 + 0.5 * (neighbour_distance - distance) / BLEND_DISTANCE
+        } else {
+            1.0
+        };
+
+        let lerp = |neighbour: f32, this: f32| neighbour + (this - neighbour) * weight;
+
+        return ColumnBiome {
+            biome: if column_noise(position) < weight {
+                biome
+            } else {
+                neighbour
+            },
+            height_scale: lerp(neighbour.height_scale, biome.height_scale),
+            height_offset: lerp(neighbour.height_offset, biome.height_offset),
+        };
+    }
+}
+
+// Noise between 0 and 1 that is the same each time for a block column. Used to scatter the blocks
+// of two biomes along their border.
+fn column_noise(position: IVec3) -> f32 {
+    let mut hash =
+        (position.x as u32).wrapping_mul(0x9E3779B1) ^ (position.z as u32).wrapping_mul(0x85EBCA77);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B3C6D);
+    hash ^= hash >> 12;
+    return (hash & 0xFFFF) as f32 / 0xFFFF as f32;
 }
@@ -2,7 +2,10 @@ use bevy::prelude::*;
 use rand::{distributions::Distribution, Rng};
 
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
     constants::CHUNK_SIZE,
@@ -15,10 +18,60 @@ use super::TerrainFeature;
 
 pub const BLUEPRINT_PATH: &str = "./resources/server/blueprints/";
 
+/// A terrain feature that is built by code, for shapes the blueprints can't describe. Features are
+/// registered by name with [RegisterFeature::register_feature], and placed by blueprints of the
+/// type "feature", e.g. {"type": "feature", "name": "boulder"}.
+pub trait Feature: Send + Sync + 'static {
+    /// Add the blocks of the feature at the position to 'feature'. The surface holds the first
+    /// block from the top that is not air for each block column of the chunk, see
+    /// [surface_position] for finding the one below the position.
+    fn build(
+        &self,
+        position: IVec3,
+        surface: &Vec<Option<(usize, BlockId)>>,
+        rng: &mut rand::rngs::StdRng,
+        feature: &mut TerrainFeature,
+    );
+}
+
+/// The features that can be placed by blueprints, by name.
+#[derive(Resource, Default)]
+pub struct Features(HashMap<String, Arc<dyn Feature>>);
+
+pub trait RegisterFeature {
+    /// Make a feature available to the blueprints. Must be done before startup, when the
+    /// blueprints are loaded.
+    fn register_feature(&mut self, name: &str, feature: impl Feature) -> &mut Self;
+}
+
+impl RegisterFeature for App {
+    fn register_feature(&mut self, name: &str, feature: impl Feature) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(Features::default)
+            .0
+            .insert(name.to_owned(), Arc::new(feature));
+        return self;
+    }
+}
+
+/// The surface block in the block column of the position, and its position. None if the column
+/// has no surface.
+pub fn surface_position(
+    position: IVec3,
+    surface: &Vec<Option<(usize, BlockId)>>,
+) -> Option<(IVec3, BlockId)> {
+    let (chunk_position, index) = utils::world_position_to_chunk_position_and_block_index(position);
+    let (surface_y, surface_block) = surface[index >> 4]?;
+
+    let mut position = position;
+    position.y = chunk_position.y + surface_y as i32;
+    return Some((position, surface_block));
+}
+
 // Blueprints contain instructions for placing terrain features.
 // Many features share the same layout, and even though blueprints are mainly meant to compose
 // features, some simple blueprints are made available to ease their creation. If you want to
-// create a new type of feature, it is meant to be programmed as a 'Generator'.
+// create a new type of feature, it is meant to be programmed as a 'Feature'.
 #[derive(Clone)]
 pub enum Blueprint {
     // A collection of blueprints that will be generated together.
@@ -37,8 +90,8 @@ pub enum Blueprint {
         // snap to the surface. [low_y, high_y]
         vertical_range: Option<[i32; 2]>,
     },
-    // A function that generates a feature
-    Generator(fn(position: IVec3, blocks: &mut TerrainFeature)),
+    // A feature that is built by code.
+    Feature(Arc<dyn Feature>),
     // TODO: There's room to introduce branches without cluttering the interface too much I think.
     // TODO: Some way to specify canopy style.
     //
@@ -71,23 +124,37 @@ pub enum Blueprint {
         /// Which blocks the ore can be placed into.
         can_replace: HashSet<BlockId>,
     },
+    // A fixed shape, like a building, laid out layer by layer from the bottom up. When it is
+    // distributed, the bottom layer replaces the surface block.
+    Layers {
+        /// Number of blocks along each axis
+        size: IVec3,
+        /// The blocks, relative to the lowest corner.
+        blocks: Vec<(IVec3, BlockId)>,
+        /// Which blocks the shape can replace.
+        can_replace: HashSet<BlockId>,
+    },
 }
 
 impl Blueprint {
     fn new(
         json_blueprint: &AmbiguousJsonBlueprint,
         named_blueprints: &HashMap<String, AmbiguousJsonBlueprint>,
+        features: &Features,
     ) -> Self {
         let blocks = Blocks::get();
         match json_blueprint {
-            AmbiguousJsonBlueprint::Named(name) => {
-                Blueprint::new(named_blueprints.get(name).unwrap(), named_blueprints)
-            }
+            AmbiguousJsonBlueprint::Named(name) => Blueprint::new(
+                named_blueprints.get(name).unwrap(),
+                named_blueprints,
+                features,
+            ),
             AmbiguousJsonBlueprint::Inline(json_blueprint) => match json_blueprint {
                 JsonBlueprint::Collection { blueprints, .. } => {
                     let mut collection = Vec::with_capacity(blueprints.len());
                     for sub_blueprint in blueprints {
-                        let sub_blueprint = Blueprint::new(sub_blueprint, named_blueprints);
+                        let sub_blueprint =
+                            Blueprint::new(sub_blueprint, named_blueprints, features);
                         collection.push(sub_blueprint);
                     }
                     Blueprint::Collection(collection)
@@ -97,7 +164,7 @@ impl Blueprint {
                     count,
                     vertical_range,
                 } => {
-                    let sub_blueprint = Blueprint::new(blueprint, named_blueprints);
+                    let sub_blueprint = Blueprint::new(blueprint, named_blueprints, features);
                     Blueprint::Distribution {
                         blueprint: Box::new(sub_blueprint),
                         count: *count,
@@ -143,10 +210,81 @@ impl Blueprint {
                         .map(|block_name| blocks.get_id(block_name))
                         .collect::<HashSet<BlockId>>(),
                 },
+                JsonBlueprint::Feature { name } => {
+                    Blueprint::Feature(features.0.get(name).unwrap().clone())
+                }
+                JsonBlueprint::Layers {
+                    palette,
+                    layers,
+                    can_replace,
+                } => {
+                    let mut size = IVec3::new(0, layers.len() as i32, 0);
+                    let mut layer_blocks = Vec::new();
+                    for (y, layer) in layers.iter().enumerate() {
+                        size.z = size.z.max(layer.len() as i32);
+                        for (z, row) in layer.iter().enumerate() {
+                            size.x = size.x.max(row.chars().count() as i32);
+                            for (x, symbol) in row.chars().enumerate() {
+                                // Spaces leave whatever is there.
+                                if symbol == ' ' {
+                                    continue;
+                                }
+                                layer_blocks.push((
+                                    IVec3::new(x as i32, y as i32, z as i32),
+                                    blocks.get_id(&palette[&symbol]),
+                                ));
+                            }
+                        }
+                    }
+
+                    Blueprint::Layers {
+                        size,
+                        blocks: layer_blocks,
+                        can_replace: can_replace
+                            .iter()
+                            .map(|block_name| blocks.get_id(block_name))
+                            .collect::<HashSet<BlockId>>(),
+                    }
+                }
             },
         }
     }
 
+    /// The size of a layers blueprint, None for the other types.
+    pub fn layers_size(&self) -> Option<IVec3> {
+        match self {
+            Blueprint::Layers { size, .. } => Some(*size),
+            _ => None,
+        }
+    }
+
+    /// Place a layers blueprint with its lowest corner at the origin, turned 'rotation' quarter
+    /// turns around the y axis. The first row of each layer is the front, with no rotation it
+    /// faces -z, and each turn moves it clockwise, to -x, +z and +x. The size along x and z is
+    /// swapped when the number of turns is odd. Does nothing for the other types.
+    pub fn place_layers(&self, origin: IVec3, rotation: u8, feature: &mut TerrainFeature) {
+        let Blueprint::Layers {
+            size,
+            blocks,
+            can_replace,
+        } = self
+        else {
+            return;
+        };
+
+        feature.can_replace.extend(can_replace);
+
+        for (offset, block_id) in blocks.iter() {
+            let rotated = match rotation % 4 {
+                0 => *offset,
+                1 => IVec3::new(offset.z, offset.y, size.x - 1 - offset.x),
+                2 => IVec3::new(size.x - 1 - offset.x, offset.y, size.z - 1 - offset.z),
+                _ => IVec3::new(size.z - 1 - offset.z, offset.y, offset.x),
+            };
+            feature.insert_block(origin + rotated, *block_id);
+        }
+    }
+
     // TODO: The surface parameter is too constricting, a blueprint might want to know all open
     // faces be it floor, roof or wall, below or above ground. Idk how to do it.
     pub fn construct(
@@ -158,6 +296,7 @@ impl Blueprint {
         let mut feature = TerrainFeature {
             blocks: HashMap::new(),
             can_replace: HashSet::new(),
+            loot: Vec::new(),
         };
         self._construct(chunk_position, surface, rng, &mut feature);
 
@@ -195,8 +334,8 @@ impl Blueprint {
                     blueprint._construct(position, surface, rng, feature);
                 }
             }
-            Blueprint::Generator(generator_function) => {
-                generator_function(origin, feature);
+            Blueprint::Feature(generator) => {
+                generator.build(origin, surface, rng, feature);
             }
             // TODO: Trunk width
             Blueprint::Tree {
@@ -211,21 +350,14 @@ impl Blueprint {
             } => {
                 // The distribution goes over a 3d space, so we convert it to 2d and set the y to
                 // whatever the surface height is at that position.
-                let (chunk_position, index) =
-                    utils::world_position_to_chunk_position_and_block_index(origin);
-                let index = index >> 4;
-                let (surface_y, surface_block) = match &surface[index] {
-                    Some(s) => s,
-                    None => return,
+                let Some((position, surface_block)) = surface_position(origin, surface) else {
+                    return;
                 };
 
-                if !soil_blocks.contains(surface_block) {
+                if !soil_blocks.contains(&surface_block) {
                     return;
                 }
 
-                let mut position = origin;
-                position.y = chunk_position.y + *surface_y as i32;
-
                 feature.can_replace.extend(can_replace);
 
                 let height = trunk_height + random_height.sample(rng);
@@ -302,6 +434,21 @@ impl Blueprint {
 
                 feature.can_replace.extend(can_replace);
             }
+            Blueprint::Layers { size, .. } => {
+                let Some((position, _)) = surface_position(origin, surface) else {
+                    return;
+                };
+
+                let rotation = rng.gen_range(0..4);
+                // Centered on the surface block.
+                let footprint = if rotation % 2 == 0 {
+                    *size
+                } else {
+                    IVec3::new(size.z, size.y, size.x)
+                };
+                let corner = position - IVec3::new(footprint.x / 2, 0, footprint.z / 2);
+                self.place_layers(corner, rotation, feature);
+            }
         }
     }
 }
@@ -358,9 +505,20 @@ enum JsonBlueprint {
         count: u32,
         can_replace: Vec<String>,
     },
+    Feature {
+        name: String,
+    },
+    Layers {
+        // The block each symbol of the layers stands for.
+        palette: HashMap<char, String>,
+        // The layers from the bottom up. Each layer is a list of rows along z, and each row is a
+        // string of symbols along x.
+        layers: Vec<Vec<String>>,
+        can_replace: Vec<String>,
+    },
 }
 
-pub fn load_blueprints() -> HashMap<String, Blueprint> {
+pub fn load_blueprints(features: &Features) -> HashMap<String, Blueprint> {
     let mut named_json_blueprints = HashMap::new();
 
     let directory = std::fs::read_dir(BLUEPRINT_PATH).expect(&format!(
@@ -418,6 +576,37 @@ pub fn load_blueprints() -> HashMap<String, Blueprint> {
         }
     }
 
+    fn validate_feature(blueprint_name: &str, feature_name: &str, features: &Features) {
+        if !features.0.contains_key(feature_name) {
+            panic!(
+                "Failed while validating the Feature Blueprints. The blueprint '{}' \
+                references a feature with the name '{}', but no feature by that name has been \
+                registered. Features are built into the server, or added by plugins.",
+                blueprint_name, feature_name
+            );
+        }
+    }
+
+    fn validate_layers(
+        blueprint_name: &str,
+        palette: &HashMap<char, String>,
+        layers: &Vec<Vec<String>>,
+    ) {
+        for block_name in palette.values() {
+            validate_block(blueprint_name, block_name);
+        }
+
+        for symbol in layers.iter().flatten().flat_map(|row| row.chars()) {
+            if symbol != ' ' && !palette.contains_key(&symbol) {
+                panic!(
+                    "Failed while validating the Feature Blueprints. The layers of the blueprint \
+                    '{}' use the symbol '{}', but it is not in the palette.",
+                    blueprint_name, symbol
+                );
+            }
+        }
+    }
+
     for (blueprint_name, json_blueprint) in named_json_blueprints.iter() {
         match json_blueprint {
             AmbiguousJsonBlueprint::Named(child_name) => {
@@ -426,16 +615,28 @@ pub fn load_blueprints() -> HashMap<String, Blueprint> {
             AmbiguousJsonBlueprint::Inline(json_blueprint) => match json_blueprint {
                 JsonBlueprint::Collection { blueprints } => {
                     for child_blueprint in blueprints {
-                        if let AmbiguousJsonBlueprint::Named(child_name) = child_blueprint {
-                            validate_blueprint(blueprint_name, child_name, &named_json_blueprints)
+                        match child_blueprint {
+                            AmbiguousJsonBlueprint::Named(child_name) => validate_blueprint(
+                                blueprint_name,
+                                child_name,
+                                &named_json_blueprints,
+                            ),
+                            AmbiguousJsonBlueprint::Inline(JsonBlueprint::Feature { name }) => {
+                                validate_feature(blueprint_name, name, features)
+                            }
+                            _ => (),
                         }
                     }
                 }
-                JsonBlueprint::Distribution { blueprint, .. } => {
-                    if let AmbiguousJsonBlueprint::Named(child_name) = blueprint.as_ref() {
+                JsonBlueprint::Distribution { blueprint, .. } => match blueprint.as_ref() {
+                    AmbiguousJsonBlueprint::Named(child_name) => {
                         validate_blueprint(blueprint_name, child_name, &named_json_blueprints)
                     }
-                }
+                    AmbiguousJsonBlueprint::Inline(JsonBlueprint::Feature { name }) => {
+                        validate_feature(blueprint_name, name, features)
+                    }
+                    _ => (),
+                },
                 JsonBlueprint::Tree {
                     trunk_block,
                     leaf_block,
@@ -462,6 +663,17 @@ pub fn load_blueprints() -> HashMap<String, Blueprint> {
                         validate_block(blueprint_name, block_name)
                     }
                 }
+                JsonBlueprint::Feature { name } => validate_feature(blueprint_name, name, features),
+                JsonBlueprint::Layers {
+                    palette,
+                    layers,
+                    can_replace,
+                } => {
+                    validate_layers(blueprint_name, palette, layers);
+                    for block_name in can_replace.iter() {
+                        validate_block(blueprint_name, block_name)
+                    }
+                }
             },
         }
     }
@@ -469,7 +681,7 @@ pub fn load_blueprints() -> HashMap<String, Blueprint> {
     let mut blueprints = HashMap::new();
 
     for (name, json_blueprint) in named_json_blueprints.iter() {
-        let blueprint = Blueprint::new(&json_blueprint, &named_json_blueprints);
+        let blueprint = Blueprint::new(&json_blueprint, &named_json_blueprints, features);
         blueprints.insert(name.to_owned(), blueprint);
     }
 
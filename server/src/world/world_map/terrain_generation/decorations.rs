@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+
+use bevy::math::IVec3;
+use fmc_networking::BlockId;
+use noise::Noise;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::constants::CHUNK_SIZE;
+
+use super::{biomes::ColumnBiome, chunk_seed, Chunk};
+
+// Decorations are single blocks like flowers and pebbles that are scattered on the surface. They
+// are much cheaper to place than blueprints, a single roll per block column, so they can cover
+// large areas. Noise decides where they grow dense and where they are sparse, so they form
+// patches instead of being spread evenly.
+
+// Each decoration samples the patch noise this far away from the previous one, so they don't all
+// grow in the same patches.
+const PATCH_OFFSET: i32 = 10_000;
+
+pub struct Decoration {
+    pub block: BlockId,
+    /// The surface blocks it can be placed on top of.
+    pub placed_on: HashSet<BlockId>,
+    /// Chance of it being placed on a block column at the center of its patches.
+    pub density: f32,
+}
+
+#[derive(Deserialize)]
+pub struct DecorationJson {
+    pub block: String,
+    pub placed_on: Vec<String>,
+    pub density: f32,
+}
+
+pub struct Decorations {
+    patches: Noise,
+}
+
+impl Decorations {
+    pub fn new(seed: i32) -> Self {
+        let freq = 1.0 / 48.0;
+        let patches = Noise::perlin(freq, seed + 10)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(2, 0.5, 2.0);
+
+        return Self { patches };
+    }
+
+    /// Scatter the decorations of each block column's biome on top of its surface block. The
+    /// surface is the topmost block of each column that has air above it, in xz order.
+    pub fn decorate(
+        &self,
+        seed: i32,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        surface: &[Option<(usize, BlockId)>],
+        biome_map: &[ColumnBiome],
+    ) {
+        let Some(decoration_count) = biome_map
+            .iter()
+            .map(|column_biome| column_biome.biome.decorations.len())
+            .max()
+            .filter(|count| *count > 0)
+        else {
+            return;
+        };
+
+        let patches: Vec<Vec<f32>> = (0..decoration_count)
+            .map(|index| {
+                let offset = IVec3::new(0, 0, index as i32 * PATCH_OFFSET);
+                self.patches
+                    .generate_3d_lattice(
+                        chunk_position * IVec3::new(1, 0, 1) + offset,
+                        1,
+                        CHUNK_SIZE,
+                        1,
+                        CHUNK_SIZE,
+                    )
+                    .0
+            })
+            .collect();
+
+        // Offset from the seed the blueprints use, so they don't roll the same numbers.
+        let mut rng = StdRng::seed_from_u64(chunk_seed(seed.wrapping_add(1), chunk_position));
+
+        for (column_index, surface_block) in surface.iter().enumerate() {
+            let Some((y, surface_block)) = *surface_block else {
+                continue;
+            };
+
+            let biome = biome_map[column_index].biome;
+            // The chunk is in xzy order, the block above is the next index.
+            let block_index = column_index * CHUNK_SIZE + y + 1;
+            if chunk[block_index] != biome.air {
+                continue;
+            }
+
+            for (decoration, patches) in biome.decorations.iter().zip(patches.iter()) {
+                if !decoration.placed_on.contains(&surface_block) {
+                    continue;
+                }
+
+                // From -1..1 to 0..1
+                let patch = (patches[column_index] * 0.5 + 0.5).clamp(0.0, 1.0);
+                if rng.gen::<f32>() < decoration.density * patch {
+                    chunk[block_index] = decoration.block;
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use fmc_networking::BlockId;
+use rand::{rngs::StdRng, Rng};
+
+use crate::{constants::CHUNK_SIZE, utils, world::blocks::Blocks};
+
+use super::{surface_position, Feature, TerrainFeature};
+
+// The features that are built into the server. They are registered in the
+// TerrainGenerationPlugin, and are available to blueprints by the name they are registered with.
+
+// Blueprints can place at most one feature of a kind in each chunk, and a boulder in every chunk
+// would be too many, so most of them are skipped.
+const BOULDER_CHANCE: f64 = 0.3;
+// Dungeons are meant to be a rare find.
+const DUNGEON_CHANCE: f64 = 0.02;
+// The loot table the dungeon chests are filled from.
+const DUNGEON_LOOT_TABLE: &str = "dungeon";
+
+/// A lump of stone half buried in the ground.
+pub struct Boulder;
+
+impl Feature for Boulder {
+    fn build(
+        &self,
+        position: IVec3,
+        surface: &Vec<Option<(usize, BlockId)>>,
+        rng: &mut StdRng,
+        feature: &mut TerrainFeature,
+    ) {
+        if !rng.gen_bool(BOULDER_CHANCE) {
+            return;
+        }
+
+        let blocks = Blocks::get();
+
+        let Some((position, surface_block)) = surface_position(position, surface) else {
+            return;
+        };
+
+        // Boulders would look out of place on sand.
+        if surface_block != blocks.get_id("grass") {
+            return;
+        }
+
+        let stone = blocks.get_id("stone");
+        feature.can_replace.extend(
+            ["air", "grass", "dirt"]
+                .iter()
+                .map(|block_name| blocks.get_id(block_name)),
+        );
+
+        let radius: f32 = rng.gen_range(1.0..2.5);
+        let extent = radius.ceil() as i32;
+        for x in -extent..=extent {
+            for y in -extent..=extent {
+                for z in -extent..=extent {
+                    // Flattened a little, and with a rough surface.
+                    let offset = Vec3::new(x as f32, y as f32 * 1.3, z as f32);
+                    if offset.length() + rng.gen_range(0.0..0.6) <= radius {
+                        feature.insert_block(position + IVec3::new(x, y, z), stone);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A small room buried underground, with a spawner in the middle and a chest or two along the
+/// walls.
+pub struct Dungeon;
+
+impl Feature for Dungeon {
+    fn build(
+        &self,
+        position: IVec3,
+        _surface: &Vec<Option<(usize, BlockId)>>,
+        rng: &mut StdRng,
+        feature: &mut TerrainFeature,
+    ) {
+        if !rng.gen_bool(DUNGEON_CHANCE) {
+            return;
+        }
+
+        let blocks = Blocks::get();
+        let air = blocks.get_id("air");
+        let wall = blocks.get_id("oak_planks");
+        let chest = blocks.get_id("chest");
+        let spawner = blocks.get_id("spawner");
+
+        // Water is left alone, the room would flood anyway.
+        feature.can_replace.extend(
+            ["air", "stone", "dirt", "sand", "coal_ore"]
+                .iter()
+                .map(|block_name| blocks.get_id(block_name)),
+        );
+
+        // Half of the interior's width, and its height.
+        let size = IVec3::new(rng.gen_range(2..=4), 3, rng.gen_range(2..=4));
+
+        // The loot is only rolled for the chunk that is being generated, so the room, walls
+        // included, is moved so that it fits inside the chunk of the position.
+        let chunk_position = utils::world_position_to_chunk_position(position);
+        let chunk_end = chunk_position + IVec3::splat(CHUNK_SIZE as i32 - 1);
+        let center = IVec3::new(
+            position
+                .x
+                .clamp(chunk_position.x + size.x + 1, chunk_end.x - size.x - 1),
+            position.y.clamp(chunk_position.y + 1, chunk_end.y - size.y),
+            position
+                .z
+                .clamp(chunk_position.z + size.z + 1, chunk_end.z - size.z - 1),
+        );
+
+        for x in -size.x - 1..=size.x + 1 {
+            for y in -1..=size.y {
+                for z in -size.z - 1..=size.z + 1 {
+                    let is_wall = x.abs() > size.x || z.abs() > size.z || y == -1 || y == size.y;
+                    let block_id = if is_wall { wall } else { air };
+                    feature.insert_block(center + IVec3::new(x, y, z), block_id);
+                }
+            }
+        }
+
+        feature.insert_block(center, spawner);
+
+        // The chests are put on the floor, up against the walls.
+        let mut chest_positions: Vec<IVec3> = Vec::with_capacity(2);
+        for _ in 0..rng.gen_range(1..=2) {
+            let offset = match rng.gen_range(0..4) {
+                0 => IVec3::new(-size.x, 0, rng.gen_range(-size.z..=size.z)),
+                1 => IVec3::new(size.x, 0, rng.gen_range(-size.z..=size.z)),
+                2 => IVec3::new(rng.gen_range(-size.x..=size.x), 0, -size.z),
+                _ => IVec3::new(rng.gen_range(-size.x..=size.x), 0, size.z),
+            };
+            let chest_position = center + offset;
+
+            if !chest_positions.contains(&chest_position) {
+                chest_positions.push(chest_position);
+                feature.insert_block(chest_position, chest);
+                feature
+                    .loot
+                    .push((chest_position, DUNGEON_LOOT_TABLE.to_owned()));
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use bevy::math::{IVec3, Vec2};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::constants::CHUNK_SIZE;
+
+use super::{biomes::Biome, chunk_seed, Chunk};
+
+// Lakes are bowls dug into the terrain above sea level and filled with liquid up to the height of
+// the terrain at their center. The world is divided into cells that can hold a single lake each,
+// and the lake is placed far enough from the edges of its cell that it never reaches into the
+// neighbouring cells. This way a chunk only has to look at the cell it is in.
+//
+// The terrain around the lake isn't guaranteed to be as high as the lake, so it is enclosed by a
+// rim of solid blocks that goes down past the bottom of the bowl. Where the terrain is lower the
+// rim raises it up to the water. Where the terrain is higher it is cut down into banks that slope
+// up from the shore. Without the rim the lake would drain out of the sides.
+
+// Width of the cells, in blocks. It is a multiple of the chunk size so chunks are never split
+// between cells.
+const CELL_SIZE: i32 = 256;
+// Chance of a cell having a lake.
+const LAKE_CHANCE: f32 = 0.4;
+const MIN_RADIUS: f32 = 8.0;
+const MAX_RADIUS: f32 = 24.0;
+const MIN_DEPTH: i32 = 2;
+const MAX_DEPTH: i32 = 6;
+// Width of the solid rim around the lake.
+const RIM_WIDTH: f32 = 3.0;
+// How far out from the water the shore is sand.
+const SHORE_WIDTH: f32 = 2.0;
+// How far out from the water the terrain is cut down into banks.
+const BANK_WIDTH: f32 = 12.0;
+// Height of the banks at their outer edge, the terrain above is cut off.
+const BANK_HEIGHT: f32 = 24.0;
+// How many blocks of sand line the bottom of the lake.
+const LAKEBED_THICKNESS: i32 = 2;
+
+/// A place where a lake can be, the height of the water depends on the terrain there.
+pub struct LakeSite {
+    /// The block column at the center of the lake.
+    pub center: IVec3,
+    radius: f32,
+    depth: i32,
+}
+
+impl LakeSite {
+    /// The lake site of the cell the chunk is in, None if the cell doesn't have a lake or the
+    /// lake doesn't reach into the chunk.
+    pub fn find(seed: i32, chunk_position: IVec3) -> Option<Self> {
+        let cell = IVec3::new(
+            chunk_position.x.div_euclid(CELL_SIZE),
+            0,
+            chunk_position.z.div_euclid(CELL_SIZE),
+        );
+
+        // Offset from the seed the chunks use, so the cells don't roll the same numbers as the
+        // chunk with the same position.
+        let mut rng = StdRng::seed_from_u64(chunk_seed(seed.wrapping_add(2), cell));
+        if rng.gen::<f32>() >= LAKE_CHANCE {
+            return None;
+        }
+
+        let radius = rng.gen_range(MIN_RADIUS..MAX_RADIUS);
+        let depth = rng.gen_range(MIN_DEPTH..=MAX_DEPTH);
+
+        let margin = (MAX_RADIUS + BANK_WIDTH).ceil() as i32;
+        let center = cell * CELL_SIZE
+            + IVec3::new(
+                rng.gen_range(margin..CELL_SIZE - margin),
+                0,
+                rng.gen_range(margin..CELL_SIZE - margin),
+            );
+
+        // Distance from the center to the closest block column of the chunk.
+        let closest = center.clamp(
+            chunk_position,
+            chunk_position + IVec3::splat(CHUNK_SIZE as i32 - 1),
+        );
+        let offset = closest - center;
+        if Vec2::new(offset.x as f32, offset.z as f32).length() > radius + BANK_WIDTH {
+            return None;
+        }
+
+        return Some(Self {
+            center,
+            radius,
+            depth,
+        });
+    }
+}
+
+pub struct Lake<'a> {
+    pub site: LakeSite,
+    /// Height of the water surface.
+    pub level: i32,
+    /// The biome at the center of the lake, all of the lake uses its blocks.
+    pub biome: &'a Biome,
+}
+
+impl Lake<'_> {
+    // Horizontal distance from the center of the lake to the block column.
+    fn distance(&self, column: IVec3) -> f32 {
+        return Vec2::new(
+            (column.x - self.site.center.x) as f32,
+            (column.z - self.site.center.z) as f32,
+        )
+        .length();
+    }
+
+    /// If the lake fills in blocks in the chunk, the water and the rim that holds it.
+    pub fn fills(&self, chunk_position: IVec3) -> bool {
+        let bottom = self.level - self.site.depth - LAKEBED_THICKNESS;
+        return chunk_position.y <= self.level && chunk_position.y + CHUNK_SIZE as i32 > bottom;
+    }
+
+    /// Which block columns of the chunk the lake and its rim cover, in xz order. Caves must not be
+    /// carved in them, or they would drain it.
+    pub fn lake_map(&self, chunk_position: IVec3) -> Vec<bool> {
+        return (0..CHUNK_SIZE.pow(2))
+            .map(|index| {
+                let column =
+                    chunk_position + IVec3::new((index >> 4) as i32, 0, (index & 0b1111) as i32);
+                self.distance(column) <= self.site.radius + RIM_WIDTH
+            })
+            .collect();
+    }
+
+    /// Dig out the lake and its banks, and fill it.
+    pub fn carve(&self, chunk_position: IVec3, chunk: &mut Chunk) {
+        let biome = self.biome;
+        let bottom = self.level - self.site.depth - LAKEBED_THICKNESS;
+
+        for column_index in 0..CHUNK_SIZE.pow(2) {
+            let column_position = chunk_position
+                + IVec3::new(
+                    (column_index >> 4) as i32,
+                    0,
+                    (column_index & 0b1111) as i32,
+                );
+            let distance = self.distance(column_position);
+            if distance > self.site.radius + BANK_WIDTH {
+                continue;
+            }
+
+            // The bowl is deepest at the center and reaches the surface at the radius. Outside of
+            // it the floor is at the water level, which makes the rim.
+            let floor = if distance < self.site.radius {
+                let edge = distance / self.site.radius;
+                self.level - (self.site.depth as f32 * (1.0 - edge * edge)).ceil() as i32
+            } else {
+                self.level
+            };
+            // The banks slope up from the water, everything above them is cleared.
+            let bank = ((distance - self.site.radius) / BANK_WIDTH).max(0.0);
+            let bank = self.level + (bank * bank * BANK_HEIGHT) as i32;
+            let rim = distance <= self.site.radius + RIM_WIDTH;
+            let shore = distance <= self.site.radius + SHORE_WIDTH;
+
+            let column = &mut chunk.blocks[column_index * CHUNK_SIZE..][..CHUNK_SIZE];
+            for (y, block) in column.iter_mut().enumerate() {
+                let height = chunk_position.y + y as i32;
+
+                if height > bank {
+                    *block = biome.air;
+                } else if height > self.level {
+                    // Cover the banks where they cut into the terrain.
+                    if height == bank && *block != biome.air {
+                        *block = biome.top_layer_block;
+                    }
+                } else if height > floor {
+                    *block = if height == self.level {
+                        biome.surface_liquid
+                    } else {
+                        biome.sub_surface_liquid
+                    };
+                } else if shore && height > floor - LAKEBED_THICKNESS {
+                    *block = biome.sand;
+                } else if rim
+                    && height > bottom
+                    && (*block == biome.air
+                        || *block == biome.surface_liquid
+                        || *block == biome.sub_surface_liquid)
+                {
+                    // Seal the lake where the terrain was lower.
+                    *block = if height == floor {
+                        biome.top_layer_block
+                    } else {
+                        biome.mid_layer_block
+                    };
+                }
+            }
+        }
+    }
+}
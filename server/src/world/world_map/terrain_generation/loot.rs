@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng};
+use serde::Deserialize;
+
+use crate::world::items::{Item, ItemId, ItemStack, ItemStorage, Items, ITEM_CONFIG_PATH};
+
+pub const LOOT_TABLE_PATH: &str = "./resources/server/loot_tables/";
+
+// Loot tables decide what is put in the containers that are placed during terrain generation.
+// Each roll picks one of the entries by weight, and puts a random amount of its item in the next
+// slot of the container.
+
+struct LootEntry {
+    item: ItemId,
+    max_stack_size: u32,
+    // Inclusive range of how many of the item there are.
+    count: [u32; 2],
+}
+
+pub struct LootTable {
+    // Inclusive range of how many item stacks the container gets.
+    rolls: [u32; 2],
+    weights: WeightedIndex<u32>,
+    entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    pub fn roll(&self, rng: &mut StdRng) -> ItemStorage {
+        let rolls = rng.gen_range(self.rolls[0]..=self.rolls[1]);
+
+        let mut storage = Vec::with_capacity(rolls as usize);
+        for _ in 0..rolls {
+            let entry = &self.entries[self.weights.sample(rng)];
+            let count = rng
+                .gen_range(entry.count[0]..=entry.count[1])
+                .min(entry.max_stack_size);
+            storage.push(ItemStack::new(
+                Item::new(entry.item),
+                count,
+                entry.max_stack_size,
+            ));
+        }
+
+        return ItemStorage(storage);
+    }
+}
+
+#[derive(Deserialize)]
+struct LootTableJson {
+    // Containers are only as large as they are, 'rolls' should not go above the number of slots
+    // in the containers the table is used for.
+    rolls: [u32; 2],
+    entries: Vec<LootEntryJson>,
+}
+
+#[derive(Deserialize)]
+struct LootEntryJson {
+    // Name of the item
+    item: String,
+    // Relative chance of the entry being picked
+    weight: u32,
+    count: [u32; 2],
+}
+
+pub struct LootTables(HashMap<String, LootTable>);
+
+impl LootTables {
+    pub fn load(items: &Items) -> Self {
+        let mut loot_tables = HashMap::new();
+
+        let directory = std::fs::read_dir(LOOT_TABLE_PATH).expect(&format!(
+            "Could not read files from loot table directory, make sure it is present as '{}'",
+            LOOT_TABLE_PATH
+        ));
+
+        for entry in directory {
+            let file_path = entry
+                .expect("Failed to read the filenames of the loot tables")
+                .path();
+
+            let file = std::fs::File::open(&file_path).expect(&format!(
+                "Failed to open loot table at '{}'",
+                file_path.display()
+            ));
+            let json: LootTableJson = serde_json::from_reader(file).expect(&format!(
+                "Failed to read loot table at '{}'",
+                file_path.display()
+            ));
+            let name = file_path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+
+            if json.rolls[0] > json.rolls[1] {
+                panic!(
+                    "Failed while validating the loot tables. The loot table '{}' rolls at least \
+                    {} times and at most {} times",
+                    name, json.rolls[0], json.rolls[1]
+                );
+            }
+
+            let mut entries = Vec::with_capacity(json.entries.len());
+            for entry in json.entries.iter() {
+                let Some(item_id) = items.get_id(&entry.item) else {
+                    panic!(
+                        "Failed while validating the loot tables. The loot table '{}' \
+                        references an item with the name '{}', but no item by that name exists. \
+                        Make sure an item by the same name is present at '{}'",
+                        name, entry.item, ITEM_CONFIG_PATH
+                    );
+                };
+
+                if entry.count[0] == 0 || entry.count[0] > entry.count[1] {
+                    panic!(
+                        "Failed while validating the loot tables. The item '{}' of the loot \
+                        table '{}' must have a count of at least 1, with the lowest first",
+                        entry.item, name
+                    );
+                }
+
+                entries.push(LootEntry {
+                    item: item_id,
+                    max_stack_size: items.get_config(&item_id).max_stack_size,
+                    count: entry.count,
+                });
+            }
+
+            let weights = match WeightedIndex::new(json.entries.iter().map(|entry| entry.weight)) {
+                Ok(w) => w,
+                Err(_) => panic!(
+                    "Failed while validating the loot tables. The loot table '{}' must have \
+                        at least one entry, and the weights must be above zero",
+                    name
+                ),
+            };
+
+            loot_tables.insert(
+                name,
+                LootTable {
+                    rolls: json.rolls,
+                    weights,
+                    entries,
+                },
+            );
+        }
+
+        return Self(loot_tables);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&LootTable> {
+        return self.0.get(name);
+    }
+}
@@ -5,18 +5,45 @@ use bevy::prelude::*;
 use fmc_networking::BlockId;
 use noise::Noise;
 use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use sha1::Digest;
 
-use crate::world::blocks::Blocks;
+use crate::settings::Generator;
+use crate::world::blocks::{Blocks, BLOCK_CONFIG_PATH};
+use crate::world::items::Items;
 use crate::{constants::CHUNK_SIZE, settings::Settings, utils, world::blocks::BlockState};
 
-use super::chunk::Chunk;
+use super::{chunk::Chunk, BlockUpdate};
 
 mod biomes;
 mod blueprints;
+mod decorations;
+mod features;
+mod lakes;
+mod loot;
+mod ores;
+mod ravines;
+mod rivers;
+mod structures;
+mod villages;
+mod worms;
+
+pub use blueprints::{surface_position, Feature, RegisterFeature};
+pub use structures::PlannedStructure;
 
 // The heighest point relative to the base height 3d noise can extend to create terrain.
 const MAX_HEIGHT: i32 = 120;
 
+// Caves and ravines only reach the surface where the base height of the terrain is at least this
+// far above sea level, so they stay away from the coasts.
+const COAST_MARGIN: f32 = 4.0;
+// How deep into the ground caves are pushed where they shouldn't reach the surface.
+const UNDERGROUND_CAVE_DEPTH: i32 = -32;
+
+// Part of the fingerprint of the generator. Changes to the generation that change how the
+// chunks turn out must bump it, so chunks that were generated ahead of time are generated again.
+const GENERATION_VERSION: u32 = 1;
+
 // TODO: Read this from biome
 // y_offset is the amount of blocks above the chunk that need to be generated to know how
 // deep we are, in order to know which blocks to use when at the surface.
@@ -26,19 +53,29 @@ pub struct TerrainGenerationPlugin;
 
 impl Plugin for TerrainGenerationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup);
+        app.init_resource::<blueprints::Features>()
+            .register_feature("boulder", features::Boulder)
+            .register_feature("dungeon", features::Dungeon)
+            .add_systems(Startup, setup);
     }
 }
 
-fn setup(mut commands: Commands, settings: Res<Settings>) {
-    commands.insert_resource(TerrainGenerator::new(settings.seed));
+fn setup(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    features: Res<blueprints::Features>,
+    items: Res<Items>,
+) {
+    commands.insert_resource(TerrainGenerator::new(&settings, &features, &items));
 }
 
 #[derive(Resource, Clone)]
 pub struct TerrainGenerator(Arc<TerrainGeneratorInner>);
 
 impl TerrainGenerator {
-    fn new(seed: i32) -> Self {
+    fn new(settings: &Settings, features: &blueprints::Features, items: &Items) -> Self {
+        let seed = settings.seed;
+
         //let freq = 1.0/200.0;
         //let terrain_low = Noise::simplex(0.0, seed).with_frequency(freq, 0.0, freq).fbm(4, 0.5, 2.0).mul_value(0.3);
         //let terrain_high = Noise::simplex(0.0, seed + 1).with_frequency(freq, 0.0, freq).fbm(4, 0.5, 2.0).max(terrain_low.clone());
@@ -100,6 +137,8 @@ impl TerrainGenerator {
             .range(0.1, -0.1, high, low)
             .mul_value(2.0);
 
+        // The snaking tunnels are dug by the worms in 'worms.rs', noise alone did not work out.
+        //
         // This is a failed attempt at making snaking tunnels. The idea is to generate 2d noise,
         // abs it, then use the values under some threshold as the direction of the tunnels. To
         // translate it into 3d, a 3d noise is generated through the same procedure, and overlayed
@@ -142,13 +181,53 @@ impl TerrainGenerator {
             Noise::constant(1.0),
         );
 
+        // Patches where the caves are allowed to break through the surface.
+        let freq = 1.0 / 256.0;
+        let cave_entrances = Noise::perlin(freq, seed + 13)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(2, 0.5, 2.0);
+
+        // The climate decides which biome is placed where.
+        let freq = 1.0 / 512.0;
+        let temperature = Noise::perlin(freq, seed + 7)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(4, 0.5, 2.0);
+        let humidity = Noise::perlin(freq, seed + 8)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(4, 0.5, 2.0);
+
+        let only_biome = match settings.generator {
+            Generator::SingleBiome => Some(settings.generator_biome.as_str()),
+            _ => None,
+        };
+
+        let flat_layers = match settings.generator {
+            Generator::Flat => Some(flat_layers(&settings.flat_layers)),
+            _ => None,
+        };
+
+        let biomes = biomes::Biomes::load(features, only_biome);
+        let fingerprint = fingerprint(settings, &biomes, items);
+        let worms = worms::Worms::new(seed, &biomes);
+
         Self(Arc::new(TerrainGeneratorInner {
-            biomes: biomes::Biomes::load(),
+            biomes,
+            ores: ores::Ores::load(),
+            loot_tables: loot::LootTables::load(items),
+            rivers: rivers::Rivers::new(seed),
+            ravines: ravines::Ravines::new(seed),
+            decorations: decorations::Decorations::new(seed),
+            worms,
+            temperature,
+            humidity,
             continents,
             terrain_height,
             terrain_shape,
             caves,
+            cave_entrances,
+            flat_layers,
             seed,
+            fingerprint,
         }))
     }
 
@@ -158,28 +237,132 @@ impl TerrainGenerator {
     // corresponded to 1 extra simd instruction, compared to the hundreds of instructions of the
     // noise it is applied to.
     pub fn generate_chunk(&self, chunk_position: IVec3, chunk: &mut Chunk) {
+        let _span = info_span!("generate_chunk").entered();
         self.0.generate_chunk(chunk_position, chunk);
     }
+
+    /// Identifies the settings and ids the chunks are generated with. Chunks that were saved as
+    /// they were generated can only be used while it stays the same.
+    pub fn fingerprint(&self) -> &[u8] {
+        return &self.0.fingerprint;
+    }
+
+    /// Map from biome name to the id the chunks are sent to the clients with.
+    pub fn biome_ids(&self) -> HashMap<String, u8> {
+        return self.0.biomes.clone_ids();
+    }
+
+    /// The names of the structures that can be located.
+    pub fn structure_names() -> impl Iterator<Item = &'static str> {
+        return structures::structure_names();
+    }
+
+    /// Find the closest place to the position the structure is generated at, at most
+    /// 'max_distance' regions away. The position is the surface block the structure is built from.
+    /// Flat worlds have no structures.
+    pub fn locate_structure(
+        &self,
+        name: &str,
+        position: IVec3,
+        max_distance: i32,
+    ) -> Option<IVec3> {
+        if self.0.flat_layers.is_some() {
+            return None;
+        }
+        let _span = info_span!("locate_structure").entered();
+        return structures::locate_structure(&self.0, name, position, max_distance);
+    }
 }
 
 struct TerrainGeneratorInner {
     biomes: biomes::Biomes,
+    ores: ores::Ores,
+    loot_tables: loot::LootTables,
+    rivers: rivers::Rivers,
+    ravines: ravines::Ravines,
+    decorations: decorations::Decorations,
+    worms: worms::Worms,
+    temperature: Noise,
+    humidity: Noise,
     continents: Noise,
     terrain_height: Noise,
     terrain_shape: Noise,
     caves: Noise,
+    cave_entrances: Noise,
+    // The block at each height of a flat world, starting at y = 0. Only set when the world is flat.
+    flat_layers: Option<Vec<BlockId>>,
     seed: i32,
+    fingerprint: Vec<u8>,
+}
+
+// Hash of what decides how the chunks turn out and which ids they are stored with. It doesn't
+// cover the contents of the block and biome configurations, only their names.
+fn fingerprint(settings: &Settings, biomes: &biomes::Biomes, items: &Items) -> Vec<u8> {
+    fn sorted_names<T: Ord>(ids: HashMap<String, T>) -> Vec<String> {
+        let mut ids: Vec<(String, T)> = ids.into_iter().collect();
+        ids.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+        return ids.into_iter().map(|(name, _)| name).collect();
+    }
+
+    let description = format!(
+        "{} {} {} {:?} {:?} {} {:?} {:?} {:?}",
+        env!("CARGO_PKG_VERSION"),
+        GENERATION_VERSION,
+        settings.seed,
+        settings.generator,
+        settings.flat_layers,
+        settings.generator_biome,
+        sorted_names(Blocks::get().clone_ids()),
+        sorted_names(items.clone_ids()),
+        sorted_names(biomes.clone_ids()),
+    );
+
+    return sha1::Sha1::digest(description).to_vec();
+}
+
+// Converts the layers of the flat generator to the block at each height.
+fn flat_layers(layers: &[(String, u32)]) -> Vec<BlockId> {
+    let blocks = Blocks::get();
+
+    let mut flat_layers = Vec::new();
+    for (block_name, thickness) in layers {
+        if !blocks.contains_block(block_name) {
+            panic!(
+                "Startup failed while setting up the flat world generator. One of its layers is \
+                made of the block '{}', but no block by that name exists. Make sure a block by \
+                the same name is present at '{}'",
+                block_name, BLOCK_CONFIG_PATH
+            );
+        }
+
+        let block_id = blocks.get_id(block_name);
+        flat_layers.extend(std::iter::repeat(block_id).take(*thickness as usize));
+    }
+
+    return flat_layers;
 }
 
 impl TerrainGeneratorInner {
     fn generate_chunk(&self, chunk_position: IVec3, chunk: &mut Chunk) {
         let air = Blocks::get().get_id("air");
-        if MAX_HEIGHT < chunk_position.y {
+        chunk.biomes = self
+            .biome_map(chunk_position)
+            .iter()
+            .map(|column_biome| column_biome.biome.id)
+            .collect();
+
+        if let Some(flat_layers) = &self.flat_layers {
+            self.generate_flat(flat_layers, chunk_position, chunk);
+        } else if MAX_HEIGHT < chunk_position.y {
             // Don't waste time generating if it is guaranteed to be air.
             chunk.make_uniform(air);
         } else {
             self.generate_terrain(chunk_position, chunk);
 
+            // Lakes can raise the terrain where it is lower than the water, so chunks that are
+            // only air still need to be generated when there is one.
+            let lake = self.lake(chunk_position);
+
             // TODO: Might make sense to test against water too.
             //
             // Test for air chunk uniformity early so we can break and elide the other generation
@@ -193,92 +376,188 @@ impl TerrainGeneratorInner {
                 }
             }
 
-            if uniform {
+            if uniform && !lake.as_ref().is_some_and(|lake| lake.fills(chunk_position)) {
                 chunk.make_uniform(air);
                 chunk.check_visible_faces();
                 return;
             }
 
-            self.carve_caves(chunk_position, chunk);
-            self.generate_features(chunk_position, chunk);
+            let river_map = self.rivers.river_map(chunk_position);
+            self.rivers.carve(
+                chunk_position,
+                chunk,
+                &river_map,
+                &self.biome_map(chunk_position),
+            );
+
+            let mut no_caves: Vec<bool> = river_map.iter().map(|&river| river > 0.0).collect();
+            if let Some(lake) = lake {
+                lake.carve(chunk_position, chunk);
+                for (no_cave, in_lake) in no_caves.iter_mut().zip(lake.lake_map(chunk_position)) {
+                    *no_cave |= in_lake;
+                }
+            }
+
+            // Away from the water caves and ravines are free to reach the surface.
+            let biome_map = self.biome_map(chunk_position);
+            let inland: Vec<bool> = self
+                .base_height_map(chunk_position, &biome_map)
+                .into_iter()
+                .zip(no_caves.iter())
+                .map(|(base_height, no_cave)| base_height > COAST_MARGIN && !no_cave)
+                .collect();
+
+            self.carve_caves(chunk_position, chunk, &no_caves, &inland);
+            self.worms.carve(chunk_position, chunk, &no_caves, &inland);
+            self.ravines.carve(
+                chunk_position,
+                chunk,
+                &self.ravines.ravine_map(chunk_position),
+                &biome_map,
+                &inland,
+            );
+            self.ores.generate(self.seed, chunk_position, chunk);
+            chunk.planned_structures = structures::plan_structures(self, chunk_position, chunk);
+
+            let surface = surface(chunk);
+            self.generate_features(chunk_position, chunk, &surface);
+            // Placed after the features so they don't take the spots of tree trunks and the like.
+            self.decorations.decorate(
+                self.seed,
+                chunk_position,
+                chunk,
+                &surface,
+                &self.biome_map(chunk_position),
+            );
         }
 
         chunk.check_visible_faces();
     }
 
-    fn generate_terrain(&self, chunk_position: IVec3, chunk: &mut Chunk) {
-        let (mut terrain_shape, _, _) = self.terrain_shape.generate_3d(
-            chunk_position.x as f32,
-            chunk_position.y as f32,
-            chunk_position.z as f32,
+    // Flat worlds are only the layers, there is nothing but air above and below them.
+    fn generate_flat(&self, flat_layers: &[BlockId], chunk_position: IVec3, chunk: &mut Chunk) {
+        let air = Blocks::get().get_id("air");
+
+        if chunk_position.y < 0 || chunk_position.y >= flat_layers.len() as i32 {
+            chunk.make_uniform(air);
+            return;
+        }
+
+        chunk.blocks = vec![air; CHUNK_SIZE.pow(3)];
+        for (i, block) in chunk.blocks.iter_mut().enumerate() {
+            // The chunk is in xzy order, the lowest bits of the index are the y coordinate.
+            let y = chunk_position.y as usize + (i & 0b1111);
+            if let Some(layer) = flat_layers.get(y) {
+                *block = *layer;
+            }
+        }
+    }
+
+    // The biome of each block column in the chunk, in xz order.
+    fn biome_map(&self, chunk_position: IVec3) -> Vec<biomes::ColumnBiome<'_>> {
+        let (temperature, _, _) = self.temperature.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
             CHUNK_SIZE,
-            CHUNK_SIZE + Y_OFFSET,
+            1,
             CHUNK_SIZE,
         );
+        let (humidity, _, _) = self.humidity.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
+            CHUNK_SIZE,
+            1,
+            CHUNK_SIZE,
+        );
+
+        return (0..CHUNK_SIZE.pow(2))
+            .map(|index| {
+                let position =
+                    chunk_position + IVec3::new((index >> 4) as i32, 0, (index & 0b1111) as i32);
+                self.biomes
+                    .get_biome(position, temperature[index], humidity[index])
+            })
+            .collect();
+    }
 
-        let (base_height, _, _) = self.continents.generate_3d(
-            chunk_position.x as f32,
-            0.0,
-            chunk_position.z as f32,
+    // The height the terrain is centered around in each block column of the chunk, in xz order.
+    fn base_height_map(
+        &self,
+        chunk_position: IVec3,
+        biome_map: &[biomes::ColumnBiome],
+    ) -> Vec<f32> {
+        let (continents, _, _) = self.continents.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
             CHUNK_SIZE,
             1,
             CHUNK_SIZE,
         );
 
-        let (terrain_height, _, _) = self.terrain_height.generate_3d(
-            chunk_position.x as f32,
-            0.0,
-            chunk_position.z as f32,
+        return continents
+            .into_iter()
+            .zip(biome_map)
+            .map(|(continent, column_biome)| {
+                continent * MAX_HEIGHT as f32 + column_biome.height_offset
+            })
+            .collect();
+    }
+
+    fn generate_terrain(&self, chunk_position: IVec3, chunk: &mut Chunk) {
+        let (mut terrain_shape, _, _) = self.terrain_shape.generate_3d_lattice(
+            chunk_position,
+            1,
+            CHUNK_SIZE,
+            CHUNK_SIZE + Y_OFFSET,
+            CHUNK_SIZE,
+        );
+
+        let (terrain_height, _, _) = self.terrain_height.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
             CHUNK_SIZE,
             1,
             CHUNK_SIZE,
         );
 
+        let biome_map = self.biome_map(chunk_position);
+        let base_height = self.base_height_map(chunk_position, &biome_map);
+
+        // The noise is in xzy order, so every column of the terrain shape is a contiguous slice.
+        const COLUMN_HEIGHT: usize = CHUNK_SIZE + Y_OFFSET;
+
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
                 let index = x << 4 | z;
-                let base_height = base_height[index] * MAX_HEIGHT as f32;
-                let terrain_height = terrain_height[index];
-                for y in 0..CHUNK_SIZE + Y_OFFSET {
-                    // Amount the density should be decreased by per block above the base height
-                    // for the maximum height to be MAX_HEIGHT.
-                    // MAX_HEIGHT * DECREMENT / terrain_height_max = 1
-                    const DECREMENT: f32 = 1.5 / MAX_HEIGHT as f32;
-                    let mut compression = ((chunk_position.y + y as i32) as f32 - base_height)
-                        * DECREMENT
-                        / terrain_height;
-                    if compression < 0.0 {
-                        // Below surface, extra compression
-                        compression *= 3.0;
-                    }
-                    let index = x * (CHUNK_SIZE * (CHUNK_SIZE + Y_OFFSET))
-                        + z * (CHUNK_SIZE + Y_OFFSET)
-                        + y;
-                    // Decrease density if above base height, increase if below
-                    terrain_shape[index] -= compression;
+                let column_biome = &biome_map[index];
+                let base_height = base_height[index];
+                let terrain_height = terrain_height[index] * column_biome.height_scale;
+                let column = &mut terrain_shape[index * COLUMN_HEIGHT..][..COLUMN_HEIGHT];
+                for (y, density) in column.iter_mut().enumerate() {
+                    *density = compress(
+                        *density,
+                        chunk_position.y + y as i32,
+                        base_height,
+                        terrain_height,
+                    );
                 }
             }
         }
 
         chunk.blocks = vec![0; CHUNK_SIZE.pow(3)];
 
-        let biome = self.biomes.get_biome();
-
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
                 let mut layer = 0;
 
-                let base_height = base_height[x << 4 | z] * MAX_HEIGHT as f32;
+                let index = x << 4 | z;
+                let column_biome = &biome_map[index];
+                let biome = column_biome.biome;
+                let base_height = base_height[index];
+                let column = &terrain_shape[index * COLUMN_HEIGHT..][..COLUMN_HEIGHT];
 
                 // Find how deep we are from above chunk.
-                for y in CHUNK_SIZE..CHUNK_SIZE + Y_OFFSET {
-                    // TODO: This needs to be converted to order xzy in simdnoise fork to make all
-                    // access contiguous.
-                    let block_index = x * (CHUNK_SIZE * (CHUNK_SIZE + Y_OFFSET))
-                        + z * (CHUNK_SIZE + Y_OFFSET)
-                        + y;
-                    let density = terrain_shape[block_index];
-
+                for (y, &density) in column.iter().enumerate().skip(CHUNK_SIZE) {
                     if density <= 0.0 {
                         if chunk_position.y + y as i32 <= 0 {
                             // For water
@@ -293,10 +572,7 @@ impl TerrainGeneratorInner {
                 for y in (0..CHUNK_SIZE).rev() {
                     let block_height = chunk_position.y + y as i32;
 
-                    let block_index = x * (CHUNK_SIZE * (CHUNK_SIZE + Y_OFFSET))
-                        + z * (CHUNK_SIZE + Y_OFFSET)
-                        + y;
-                    let density = terrain_shape[block_index];
+                    let density = column[y];
 
                     let block = if density <= 0.0 {
                         if block_height == 0 {
@@ -333,34 +609,115 @@ impl TerrainGeneratorInner {
         }
     }
 
-    fn carve_caves(&self, chunk_position: IVec3, chunk: &mut Chunk) {
+    // The height of the topmost block of the terrain in the block column at the position, and the
+    // biome of the column. None if the terrain is below sea level.
+    fn terrain_surface(&self, position: IVec3) -> Option<(i32, biomes::ColumnBiome<'_>)> {
+        let position = position * IVec3::new(1, 0, 1);
+        let sample = |noise: &Noise| noise.generate_3d_lattice(position, 1, 1, 1, 1).0[0];
+
+        let column_biome =
+            self.biomes
+                .get_biome(position, sample(&self.temperature), sample(&self.humidity));
+        let base_height = sample(&self.continents) * MAX_HEIGHT as f32 + column_biome.height_offset;
+        let terrain_height = sample(&self.terrain_height) * column_biome.height_scale;
+
+        // Chunks above MAX_HEIGHT are never generated, so the terrain can't be taller than that.
+        const COLUMN_HEIGHT: usize = MAX_HEIGHT as usize + CHUNK_SIZE;
+        let (column, _, _) =
+            self.terrain_shape
+                .generate_3d_lattice(position, 1, 1, COLUMN_HEIGHT, 1);
+
+        let height = column
+            .into_iter()
+            .enumerate()
+            .rev()
+            .find_map(|(y, density)| {
+                let height = y as i32;
+                (compress(density, height, base_height, terrain_height) > 0.0).then_some(height)
+            })?;
+
+        return Some((height, column_biome));
+    }
+
+    // The lake that reaches into the chunk, if there is one. Lakes are only placed above sea level
+    // and away from rivers, the rivers would drain them.
+    fn lake(&self, chunk_position: IVec3) -> Option<lakes::Lake<'_>> {
+        let site = lakes::LakeSite::find(self.seed, chunk_position)?;
+        let (level, column_biome) = self.terrain_surface(site.center)?;
+        if level < 1 || self.rivers.is_river(site.center) {
+            return None;
+        }
+
+        return Some(lakes::Lake {
+            site,
+            level,
+            biome: column_biome.biome,
+        });
+    }
+
+    // Caves are pushed underground by making them less dense the higher up they are. Caves and
+    // water do not cooperate well, if the caves reached the surface everywhere they would carve
+    // out the ground beneath the sea and rivers and leave the water floating. Inland, in patches
+    // decided by the cave entrance noise, they are let up so they open out to the surface.
+    fn carve_caves(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        no_caves: &[bool],
+        inland: &[bool],
+    ) {
+        // Where the cave entrance noise is above this, caves can reach the surface. It is fully
+        // open at 1.0.
+        const ENTRANCE_THRESHOLD: f32 = 0.3;
+
         let air = Blocks::get().get_id("air");
 
-        let biome = self.biomes.get_biome();
-        let (caves, _, _) = self.caves.generate_3d(
-            chunk_position.x as f32,
-            chunk_position.y as f32,
-            chunk_position.z as f32,
-            CHUNK_SIZE,
+        let biome_map = self.biome_map(chunk_position);
+        let (caves, _, _) =
+            self.caves
+                .generate_3d_lattice(chunk_position, 1, CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE);
+        let (entrances, _, _) = self.cave_entrances.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
             CHUNK_SIZE,
+            1,
             CHUNK_SIZE,
         );
+
+        // The height above which the caves start to fade out, for each block column.
+        let decay_points: Vec<i32> = entrances
+            .into_iter()
+            .zip(inland)
+            .map(|(entrance, &inland)| {
+                if !inland {
+                    return UNDERGROUND_CAVE_DEPTH;
+                }
+                let open =
+                    ((entrance - ENTRANCE_THRESHOLD) / (1.0 - ENTRANCE_THRESHOLD)).clamp(0.0, 1.0);
+                UNDERGROUND_CAVE_DEPTH
+                    + ((MAX_HEIGHT - UNDERGROUND_CAVE_DEPTH) as f32 * open) as i32
+            })
+            .collect();
+
         caves
             .into_iter()
             .zip(chunk.blocks.iter_mut())
             .enumerate()
             .for_each(|(i, (mut density, block))| {
-                // TODO: Caves and water do not cooperate well. You carve the surface without
-                // knowing there's water there and you get reverse moon pools underwater. Instead
-                // we just push the caves underground, causing there to be no cave entraces at the
-                // surface. There either needs to be a way to exclude caves from being generated
-                // beneath water, or some way to intelligently fill carved out space that touches
-                // water.
-                const DECAY_POINT: i32 = -32;
+                // The chunk is in xzy order, so the block column is the index without the y bits.
+                let column_index = i >> 4;
+
+                // Caves would drain the rivers and lakes like they would the sea, so none are
+                // carved below them.
+                if no_caves[column_index] {
+                    return;
+                }
+
                 let y = chunk_position.y + (i & 0b1111) as i32;
-                let density_offset = (y - DECAY_POINT).max(0) as f32 * 1.0 / 64.0;
+                let density_offset = (y - decay_points[column_index]).max(0) as f32 * 1.0 / 64.0;
                 density += density_offset;
 
+                let biome = biome_map[column_index].biome;
                 if (density / 2.0) < 0.001
                     && *block != biome.surface_liquid
                     && *block != biome.sub_surface_liquid
@@ -370,45 +727,21 @@ impl TerrainGeneratorInner {
             });
     }
 
-    fn generate_features(&self, chunk_position: IVec3, chunk: &mut Chunk) {
-        // TODO: It should be unique to each chunk but I don't know how.
-        let seed = self
-            .seed
-            .overflowing_add(chunk_position.x.pow(2))
-            .0
-            .overflowing_add(chunk_position.z)
-            .0;
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed as u64);
-
-        let air = Blocks::get().get_id("air");
-
-        // TODO: This should be done at terrain generation, but it clutters the code and it's in
-        // flux. Meanwhile, it is done here. An entire extra scan of the chunk, and it can't tell
-        // if it's the surface if it's the topmost block in a column.
-        //
-        // The surface contains the first block from the top that is not air for each block column
-        // of the chunk.
-        let mut surface = vec![None; CHUNK_SIZE.pow(2)];
-        for (column_index, block_column) in chunk.blocks.chunks(CHUNK_SIZE).enumerate() {
-            let mut air_encountered = false;
-            for (y_index, block_id) in block_column.into_iter().enumerate().rev() {
-                if air_encountered && *block_id != air {
-                    // The 2d surface stores the index in the 3d chunk and the block. The
-                    // bitshifting just converts it to a chunk index. See 'Chunk::Index' if
-                    // wondering what it means.
-                    surface[column_index] = Some((y_index, *block_id));
-                    break;
-                }
-                if *block_id == air {
-                    air_encountered = true;
-                }
-            }
-        }
+    fn generate_features(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        surface: &Vec<Option<(usize, BlockId)>>,
+    ) {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(chunk_seed(self.seed, chunk_position));
 
-        let biome = self.biomes.get_biome();
+        // Features can span several block columns, so the entire chunk uses the blueprints of the
+        // biome at its center.
+        let biome_map = self.biome_map(chunk_position);
+        let biome = biome_map[CHUNK_SIZE / 2 << 4 | CHUNK_SIZE / 2].biome;
 
         for blueprint in biome.blueprints.iter() {
-            let terrain_feature = blueprint.construct(chunk_position, &surface, &mut rng);
+            let mut terrain_feature = blueprint.construct(chunk_position, surface, &mut rng);
 
             if terrain_feature.blocks.is_empty() {
                 continue;
@@ -416,11 +749,100 @@ impl TerrainGeneratorInner {
 
             terrain_feature.apply(chunk, chunk_position);
 
+            // The loot is rolled every time the chunk is generated, but is the same every time. It
+            // only ends up in the container until the container has been saved.
+            for (position, loot_table) in terrain_feature.loot.drain(..) {
+                if utils::world_position_to_chunk_position(position) != chunk_position
+                    || chunk
+                        .block_entities
+                        .iter()
+                        .any(|(saved_position, _)| *saved_position == position)
+                {
+                    continue;
+                }
+
+                let Some(loot_table) = self.loot_tables.get(&loot_table) else {
+                    error!(
+                        "Tried to fill a container at {} with loot from the loot table '{}', but \
+                        there is no loot table by that name",
+                        position, loot_table
+                    );
+                    continue;
+                };
+
+                chunk
+                    .block_entities
+                    .push((position, loot_table.roll(&mut rng)));
+            }
+
             chunk.terrain_features.push(terrain_feature);
         }
     }
 }
 
+// Adjusts the density of the terrain shape noise by how far the block is above or below the base
+// height. Positive density is solid.
+fn compress(density: f32, height: i32, base_height: f32, terrain_height: f32) -> f32 {
+    // Amount the density should be decreased by per block above the base height for the maximum
+    // height to be MAX_HEIGHT.
+    // MAX_HEIGHT * DECREMENT / terrain_height_max = 1
+    const DECREMENT: f32 = 1.5 / MAX_HEIGHT as f32;
+    let mut compression = (height as f32 - base_height) * DECREMENT / terrain_height;
+    if compression < 0.0 {
+        // Below surface, extra compression
+        compression *= 3.0;
+    }
+    // Decrease density if above base height, increase if below
+    return density - compression;
+}
+
+// TODO: This should be done at terrain generation, but it clutters the code and it's in flux.
+// Meanwhile, it is done here. An entire extra scan of the chunk, and it can't tell if it's the
+// surface if it's the topmost block in a column.
+//
+// The surface contains the first block from the top that is not air for each block column of the
+// chunk, as the y index in the chunk and the block.
+fn surface(chunk: &Chunk) -> Vec<Option<(usize, BlockId)>> {
+    let air = Blocks::get().get_id("air");
+
+    let mut surface = vec![None; CHUNK_SIZE.pow(2)];
+    for (column_index, block_column) in chunk.blocks.chunks(CHUNK_SIZE).enumerate() {
+        let mut air_encountered = false;
+        for (y_index, block_id) in block_column.into_iter().enumerate().rev() {
+            if air_encountered && *block_id != air {
+                surface[column_index] = Some((y_index, *block_id));
+                break;
+            }
+            if *block_id == air {
+                air_encountered = true;
+            }
+        }
+    }
+
+    return surface;
+}
+
+/// A seed that is unique to the chunk. The same world seed and chunk position always give the same
+/// chunk seed, so anything random that is generated from it stays the same between runs.
+fn chunk_seed(seed: i32, chunk_position: IVec3) -> u64 {
+    // Each coordinate is mixed in separately, so chunks that share coordinates, or have them
+    // swapped, still get seeds that have nothing in common.
+    let mut hash = splitmix64(seed as u64);
+    hash = splitmix64(hash ^ chunk_position.x as u64);
+    hash = splitmix64(hash ^ chunk_position.y as u64);
+    hash = splitmix64(hash ^ chunk_position.z as u64);
+    return hash;
+}
+
+// https://prng.di.unimi.it/splitmix64.c
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    return z ^ (z >> 31);
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct TerrainFeature {
     /// The blocks the feature consists of segmented into the chunks they are a part of.
     pub blocks: HashMap<IVec3, Vec<(usize, BlockId, Option<u16>)>>,
@@ -440,10 +862,14 @@ pub struct TerrainFeature {
     // }
     // https://gist.github.com/daboross/976978d8200caf86e02acb6805961195 says really long at bottom
     pub can_replace: HashSet<BlockId>,
+    /// Containers the feature fills with loot, by block position and the name of the loot table.
+    /// Only the containers in the chunk the feature is built in are filled, features that place
+    /// them must keep them inside of it.
+    pub loot: Vec<(IVec3, String)>,
 }
 
 impl TerrainFeature {
-    fn insert_block(&mut self, position: IVec3, block_id: BlockId) {
+    pub fn insert_block(&mut self, position: IVec3, block_id: BlockId) {
         let (chunk_position, block_index) =
             utils::world_position_to_chunk_position_and_block_index(position);
         self.blocks
@@ -452,6 +878,24 @@ impl TerrainFeature {
             .push((block_index, block_id, None));
     }
 
+    pub fn insert_block_with_state(
+        &mut self,
+        position: IVec3,
+        block_id: BlockId,
+        block_state: Option<BlockState>,
+    ) {
+        let (chunk_position, block_index) =
+            utils::world_position_to_chunk_position_and_block_index(position);
+        self.blocks
+            .entry(chunk_position)
+            .or_insert(Vec::new())
+            .push((
+                block_index,
+                block_id,
+                block_state.map(|state| state.as_u16()),
+            ));
+    }
+
     // TODO: Is it possible to make it so that features can fail? There are many things that just
     // don't look very good when partially placed. Failure means it would have to revert to the
     // previous state, which is not an easy task. The features are applied to chunks as the chunks
@@ -500,4 +944,33 @@ impl TerrainFeature {
 
         return None;
     }
+
+    /// The block updates that place the feature in a chunk that is already loaded, so that it
+    /// goes through the same path as any other change to the world. Unlike when the feature is
+    /// applied as the chunk is generated, blocks that have been changed are replaced too. Blocks
+    /// that are already as the feature wants them are left out.
+    pub fn block_updates(&self, chunk: &Chunk, chunk_position: IVec3) -> Vec<BlockUpdate> {
+        let Some(feature_blocks) = self.blocks.get(&chunk_position) else {
+            return Vec::new();
+        };
+
+        let mut updates = Vec::with_capacity(feature_blocks.len());
+        for (block_index, block_id, block_state) in feature_blocks {
+            let block_state = block_state.map(BlockState);
+            if !self.can_replace.contains(&chunk[*block_index])
+                || (chunk[*block_index] == *block_id
+                    && chunk.get_block_state(block_index) == block_state)
+            {
+                continue;
+            }
+
+            updates.push(BlockUpdate::Change {
+                position: chunk_position + utils::block_index_to_position(*block_index),
+                block_id: *block_id,
+                block_state,
+            });
+        }
+
+        return updates;
+    }
 }
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use bevy::math::IVec3;
+use fmc_networking::BlockId;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    constants::CHUNK_SIZE,
+    utils,
+    world::blocks::{Blocks, BLOCK_CONFIG_PATH},
+};
+
+use super::Chunk;
+
+pub const ORE_PATH: &str = "./resources/server/ores/";
+
+const DIRECTIONS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+struct Ore {
+    /// The block the ore becomes for each block it can replace.
+    replacements: HashMap<BlockId, BlockId>,
+    /// Lowest height a vein can start at.
+    min_height: i32,
+    /// Highest height a vein can start at.
+    max_height: i32,
+    /// How many blocks long each vein is.
+    vein_size: u32,
+    /// How many veins are attempted in each chunk.
+    frequency: u32,
+}
+
+#[derive(Deserialize)]
+struct OreJson {
+    // The block that is placed.
+    block: String,
+    // Veins start between these heights, they can stray a few blocks outside of them.
+    min_height: i32,
+    max_height: i32,
+    vein_size: u32,
+    // Veins per chunk.
+    frequency: u32,
+    // Blocks the ore can be placed into.
+    can_replace: Vec<String>,
+    // Ores that look different depending on the stone they are in, e.g.
+    // {"granite": "granite_coal_ore"}. The stone is replaced by its variant instead of 'block',
+    // and the ore can be placed into it even if it isn't in 'can_replace'.
+    #[serde(default)]
+    variants: HashMap<String, String>,
+}
+
+pub struct Ores {
+    ores: Vec<Ore>,
+}
+
+impl Ores {
+    pub fn load() -> Self {
+        fn validate_block(ore_name: &str, block_name: &str) {
+            let blocks = Blocks::get();
+            if !blocks.contains_block(block_name) {
+                panic!(
+                    "Startup failed while validating the ores. The ore '{}' references a block \
+                    with the name '{}', but no block by that name exists. Make sure a block by \
+                    the same name is present at '{}'",
+                    ore_name, block_name, BLOCK_CONFIG_PATH
+                );
+            }
+        }
+
+        let blocks = Blocks::get();
+
+        let directory = std::fs::read_dir(ORE_PATH).expect(&format!(
+            "Could not read files from ores directory, make sure it is present as '{}'",
+            ORE_PATH
+        ));
+
+        // The veins of each ore are seeded by its position in the list, so it has to be the same
+        // every time.
+        let mut file_paths: Vec<_> = directory
+            .map(|entry| {
+                entry
+                    .expect("Failed to read the filenames of the ores")
+                    .path()
+            })
+            .collect();
+        file_paths.sort();
+
+        let mut ores = Vec::new();
+
+        for file_path in file_paths {
+            let file = std::fs::File::open(&file_path).expect(&format!(
+                "Failed to open ore file at '{}'",
+                file_path.display()
+            ));
+            let ore_json: OreJson = serde_json::from_reader(file)
+                .expect(&format!("Failed to read ore at '{}'", file_path.display()));
+            let ore_name = file_path
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+
+            validate_block(&ore_name, &ore_json.block);
+            for block_name in ore_json.can_replace.iter() {
+                validate_block(&ore_name, block_name);
+            }
+            for (block_name, variant) in ore_json.variants.iter() {
+                validate_block(&ore_name, block_name);
+                validate_block(&ore_name, variant);
+            }
+
+            // Veins are only looked for in the neighbouring chunks, see 'Ores::generate'.
+            if ore_json.vein_size > CHUNK_SIZE as u32 {
+                panic!(
+                    "Failed while validating the ores. The ore '{}' has a vein size larger than \
+                    {}, it would be cut off at the chunk borders.",
+                    ore_name, CHUNK_SIZE
+                );
+            }
+
+            let ore_block = blocks.get_id(&ore_json.block);
+            let mut replacements: HashMap<BlockId, BlockId> = ore_json
+                .can_replace
+                .iter()
+                .map(|block_name| (blocks.get_id(block_name), ore_block))
+                .collect();
+            for (block_name, variant) in ore_json.variants.iter() {
+                replacements.insert(blocks.get_id(block_name), blocks.get_id(variant));
+            }
+
+            ores.push(Ore {
+                replacements,
+                min_height: ore_json.min_height,
+                max_height: ore_json.max_height,
+                vein_size: ore_json.vein_size,
+                frequency: ore_json.frequency,
+            });
+        }
+
+        return Ores { ores };
+    }
+
+    /// Place the ore veins that pass through the chunk.
+    pub fn generate(&self, seed: i32, chunk_position: IVec3, chunk: &mut Chunk) {
+        // There's nothing for the ores to replace in a chunk of air.
+        if chunk.is_uniform() {
+            return;
+        }
+
+        for (ore_index, ore) in self.ores.iter().enumerate() {
+            // Veins cross into the neighbouring chunks. Instead of keeping them around until the
+            // neighbours are generated, each chunk generates the veins of its neighbours too, and
+            // keeps the blocks that fall inside of it. The veins are the same no matter which
+            // chunk generates them, so they line up at the borders.
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let vein_chunk_position =
+                            chunk_position + IVec3::new(x, y, z) * CHUNK_SIZE as i32;
+
+                        if vein_chunk_position.y > ore.max_height
+                            || vein_chunk_position.y + (CHUNK_SIZE as i32) <= ore.min_height
+                        {
+                            continue;
+                        }
+
+                        self.generate_veins(
+                            ore,
+                            vein_seed(seed, vein_chunk_position, ore_index),
+                            vein_chunk_position,
+                            chunk_position,
+                            chunk,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Generates the veins that start in one chunk, and places the blocks of them that are in
+    // another.
+    fn generate_veins(
+        &self,
+        ore: &Ore,
+        seed: u64,
+        vein_chunk_position: IVec3,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+    ) {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        for _ in 0..ore.frequency {
+            let mut position = vein_chunk_position
+                + IVec3::new(
+                    rng.gen_range(0..CHUNK_SIZE as i32),
+                    rng.gen_range(0..CHUNK_SIZE as i32),
+                    rng.gen_range(0..CHUNK_SIZE as i32),
+                );
+
+            if position.y < ore.min_height || position.y > ore.max_height {
+                continue;
+            }
+
+            for _ in 0..ore.vein_size {
+                position += DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())];
+
+                if utils::world_position_to_chunk_position(position) != chunk_position {
+                    continue;
+                }
+
+                let block_index = utils::world_position_to_block_index(position);
+                if let Some(ore_block) = ore.replacements.get(&chunk[block_index]) {
+                    chunk[block_index] = *ore_block;
+                }
+            }
+        }
+    }
+}
+
+fn vein_seed(seed: i32, chunk_position: IVec3, ore_index: usize) -> u64 {
+    // Large odd numbers so that neighbouring chunks get seeds that are far apart.
+    return (seed as u64)
+        ^ (chunk_position.x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (chunk_position.y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (chunk_position.z as u64).wrapping_mul(0x1656_67B1_9E37_79F9)
+        ^ (ore_index as u64).wrapping_mul(0x27D4_EB2F_1656_67C5);
+}
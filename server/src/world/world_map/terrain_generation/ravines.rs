@@ -0,0 +1,108 @@
+use bevy::math::IVec3;
+use noise::Noise;
+
+use crate::constants::CHUNK_SIZE;
+
+use super::{biomes::ColumnBiome, Chunk};
+
+// Ravines are cut along the lines where 2d noise crosses zero, the same way as the rivers, but
+// they are much narrower and cut straight down instead of sloping into a valley. A second noise
+// decides where there are ravines at all, so only a few of the lines become ravines, and they
+// grow shallower towards their ends.
+//
+// They never go below sea level and are kept away from the coasts, rivers and lakes, the water
+// would pour into them otherwise.
+
+// Where the absolute noise is less than this the terrain is part of the ravine.
+const RAVINE_WIDTH: f32 = 0.012;
+// Where the presence noise is above this there are ravines. Larger values make them rarer.
+const PRESENCE_THRESHOLD: f32 = 0.25;
+// The lowest the ravines are cut.
+const RAVINE_FLOOR: i32 = 2;
+// How much higher the floor is at the edge of the ravine than at its center. The steeper the
+// walls, the larger this is.
+const WALL_HEIGHT: f32 = 160.0;
+
+pub struct Ravines {
+    noise: Noise,
+    presence: Noise,
+}
+
+impl Ravines {
+    pub fn new(seed: i32) -> Self {
+        let freq = 1.0 / 384.0;
+        let noise = Noise::perlin(freq, seed + 11)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(3, 0.5, 2.0)
+            .abs();
+
+        let freq = 1.0 / 512.0;
+        let presence = Noise::perlin(freq, seed + 12)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(2, 0.5, 2.0);
+
+        return Self { noise, presence };
+    }
+
+    /// How close each block column of the chunk is to the center of a ravine, from 1.0 at the
+    /// center of its deepest parts to 0.0 outside of it. The columns are in xz order.
+    pub fn ravine_map(&self, chunk_position: IVec3) -> Vec<f32> {
+        let (noise, _, _) = self.noise.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
+            CHUNK_SIZE,
+            1,
+            CHUNK_SIZE,
+        );
+        let (presence, _, _) = self.presence.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
+            CHUNK_SIZE,
+            1,
+            CHUNK_SIZE,
+        );
+
+        return noise
+            .into_iter()
+            .zip(presence)
+            .map(|(value, presence)| {
+                let ravine = (1.0 - value / RAVINE_WIDTH).max(0.0);
+                let presence =
+                    ((presence - PRESENCE_THRESHOLD) / (1.0 - PRESENCE_THRESHOLD)).clamp(0.0, 1.0);
+                // Taper the ends off quickly, so the ravines don't fade away into shallow ditches.
+                ravine * (presence * 4.0).min(1.0)
+            })
+            .collect();
+    }
+
+    /// Cut out the ravines. Only the block columns marked as inland are cut, the rest are too close
+    /// to water.
+    pub fn carve(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        ravine_map: &[f32],
+        biome_map: &[ColumnBiome],
+        inland: &[bool],
+    ) {
+        for (column_index, &ravine) in ravine_map.iter().enumerate() {
+            if ravine == 0.0 || !inland[column_index] {
+                continue;
+            }
+
+            let biome = biome_map[column_index].biome;
+            let floor = RAVINE_FLOOR + ((1.0 - ravine) * WALL_HEIGHT) as i32;
+
+            let column = &mut chunk.blocks[column_index * CHUNK_SIZE..][..CHUNK_SIZE];
+            for (y, block) in column.iter_mut().enumerate() {
+                let height = chunk_position.y + y as i32;
+                if height > floor
+                    && *block != biome.surface_liquid
+                    && *block != biome.sub_surface_liquid
+                {
+                    *block = biome.air;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::math::IVec3;
+use noise::Noise;
+
+use crate::constants::CHUNK_SIZE;
+
+use super::{biomes::ColumnBiome, Chunk};
+
+// Rivers follow the lines where 2d noise crosses zero. The absolute value of the noise is
+// thresholded, so close to the lines it is a network of narrow channels that wind through the
+// terrain. The valleys and channels are carved down to sea level, which makes the rivers flow into
+// the sea wherever they reach it.
+
+// Where the absolute noise is less than this the terrain is part of the river valley.
+const VALLEY_WIDTH: f32 = 0.04;
+// Fraction of the valley closest to the center that is water.
+const CHANNEL_WIDTH: f32 = 0.35;
+// How deep the channel is at its center.
+const CHANNEL_DEPTH: f32 = 5.0;
+// Height of the valley walls at the outer edge of the valley, the terrain above is cut off.
+const BANK_HEIGHT: f32 = 40.0;
+// How many blocks of the riverbed block the channel is lined with.
+const RIVERBED_THICKNESS: i32 = 2;
+// The valley floor is sand up to this height above the water, the banks of the river.
+const SAND_BANK_HEIGHT: i32 = 2;
+
+pub struct Rivers {
+    noise: Noise,
+}
+
+impl Rivers {
+    pub fn new(seed: i32) -> Self {
+        let freq = 1.0 / 1024.0;
+        let noise = Noise::perlin(freq, seed + 9)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(4, 0.5, 2.0)
+            .abs();
+
+        return Self { noise };
+    }
+
+    /// How close each block column of the chunk is to the center of a river, from 1.0 at the
+    /// center to 0.0 outside of the river valley. The columns are in xz order.
+    pub fn river_map(&self, chunk_position: IVec3) -> Vec<f32> {
+        let (noise, _, _) = self.noise.generate_3d_lattice(
+            chunk_position * IVec3::new(1, 0, 1),
+            1,
+            CHUNK_SIZE,
+            1,
+            CHUNK_SIZE,
+        );
+
+        return noise
+            .into_iter()
+            .map(|value| (1.0 - value / VALLEY_WIDTH).max(0.0))
+            .collect();
+    }
+
+    /// If the block column at the position is part of a river valley.
+    pub fn is_river(&self, position: IVec3) -> bool {
+        let (noise, _, _) =
+            self.noise
+                .generate_3d_lattice(position * IVec3::new(1, 0, 1), 1, 1, 1, 1);
+        return noise[0] < VALLEY_WIDTH;
+    }
+
+    /// Carve the river valleys and fill the channels with water.
+    pub fn carve(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        river_map: &[f32],
+        biome_map: &[ColumnBiome],
+    ) {
+        for (column_index, &river) in river_map.iter().enumerate() {
+            if river == 0.0 {
+                continue;
+            }
+
+            let biome = biome_map[column_index].biome;
+
+            // The valley floor slopes from the top of the banks down to the water. In the channel
+            // it is the riverbed below the water.
+            let channel = 1.0 - CHANNEL_WIDTH;
+            let floor = if river < channel {
+                let slope = 1.0 - river / channel;
+                (slope * slope * BANK_HEIGHT) as i32
+            } else {
+                -((river - channel) / CHANNEL_WIDTH * CHANNEL_DEPTH).ceil() as i32 - 1
+            };
+
+            let column = &mut chunk.blocks[column_index * CHUNK_SIZE..][..CHUNK_SIZE];
+            for (y, block) in column.iter_mut().enumerate() {
+                let height = chunk_position.y + y as i32;
+
+                // The sea is already at sea level.
+                if *block == biome.surface_liquid || *block == biome.sub_surface_liquid {
+                    continue;
+                }
+
+                if height > floor {
+                    *block = if height > 0 {
+                        biome.air
+                    } else if height == 0 {
+                        biome.surface_liquid
+                    } else {
+                        biome.sub_surface_liquid
+                    };
+                } else if *block == biome.air {
+                    // The terrain is already below the valley floor.
+                    continue;
+                } else if floor < 1 && height > floor - RIVERBED_THICKNESS {
+                    *block = biome.riverbed_block;
+                } else if height == floor && floor <= SAND_BANK_HEIGHT {
+                    *block = biome.sand;
+                } else if height == floor {
+                    *block = biome.top_layer_block;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{constants::CHUNK_SIZE, world::blocks::Blocks};
+
+use super::{
+    villages::{build_village, fits_village},
+    Chunk, TerrainFeature, TerrainGeneratorInner,
+};
+
+// Structures are too large for the chunk they are planned in. They are planned when the chunk
+// that contains their origin is generated, and split into pieces by the chunks they cover. The
+// pieces are saved to the database, and each chunk applies its pieces when it is generated or
+// loaded, so it doesn't matter in which order the chunks are generated.
+
+// The world is divided into regions of this many chunks along x and z. Each region has at most one
+// structure, each type of structure is tried in turn until one of them is picked.
+const REGION_SIZE: i32 = 8;
+// How far the walls of the ruins reach into the ground, so they don't float above dips in the
+// terrain.
+const RUINS_FOUNDATION: i32 = 5;
+
+struct StructureType {
+    name: &'static str,
+    /// One in this many regions have the structure.
+    rarity: u32,
+    /// Builds the structure from the surface block at its origin. None if it can't be built
+    /// there.
+    build: fn(
+        generator: &TerrainGeneratorInner,
+        origin: IVec3,
+        rng: &mut StdRng,
+    ) -> Option<TerrainFeature>,
+    /// If the structure can be built at the surface block, decided from the terrain noise
+    /// without generating the chunk. Must agree with 'build'.
+    fits: fn(generator: &TerrainGeneratorInner, origin: IVec3) -> bool,
+}
+
+const STRUCTURES: [StructureType; 2] = [
+    StructureType {
+        name: "village",
+        rarity: 4,
+        build: build_village,
+        fits: fits_village,
+    },
+    StructureType {
+        name: "ruins",
+        rarity: 3,
+        build: build_ruins,
+        fits: fits_anywhere,
+    },
+];
+
+/// A structure that has been planned, but not yet placed in all the chunks it covers.
+pub struct PlannedStructure {
+    pub name: &'static str,
+    /// The surface block the structure was built from.
+    pub origin: IVec3,
+    pub feature: TerrainFeature,
+}
+
+/// The names of all the structures that can be generated.
+pub fn structure_names() -> impl Iterator<Item = &'static str> {
+    return STRUCTURES.iter().map(|structure_type| structure_type.name);
+}
+
+const REGION_WIDTH: i32 = REGION_SIZE * CHUNK_SIZE as i32;
+
+// Picks the structure of the region, and the block column its origin is in. All the chunks of the
+// region must agree on this, so it can only depend on the seed and the region. The rng is returned
+// to be used to build the structure.
+fn region_structure(seed: i32, region: IVec2) -> Option<(&'static StructureType, IVec2, StdRng)> {
+    for (index, structure_type) in STRUCTURES.iter().enumerate() {
+        let mut rng = StdRng::seed_from_u64(region_seed(seed, region, index));
+        if rng.gen_range(0..structure_type.rarity) != 0 {
+            continue;
+        }
+
+        let x = region.x * REGION_WIDTH + rng.gen_range(0..REGION_WIDTH);
+        let z = region.y * REGION_WIDTH + rng.gen_range(0..REGION_WIDTH);
+        return Some((structure_type, IVec2::new(x, z), rng));
+    }
+
+    return None;
+}
+
+/// Plan the structures that have their origin in the chunk.
+pub(super) fn plan_structures(
+    generator: &TerrainGeneratorInner,
+    chunk_position: IVec3,
+    chunk: &Chunk,
+) -> Vec<PlannedStructure> {
+    let mut structures = Vec::new();
+
+    // Uniform chunks have no surface to build on.
+    if chunk.is_uniform() {
+        return structures;
+    }
+
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+    let water = blocks.get_id("surface_water");
+
+    let region =
+        IVec2::new(chunk_position.x, chunk_position.z).div_euclid(IVec2::splat(REGION_WIDTH));
+    let Some((structure_type, column, mut rng)) = region_structure(generator.seed, region) else {
+        return structures;
+    };
+
+    let x = column.x - chunk_position.x;
+    let z = column.y - chunk_position.z;
+    if !(0..CHUNK_SIZE as i32).contains(&x) || !(0..CHUNK_SIZE as i32).contains(&z) {
+        return structures;
+    }
+
+    // The first block from the top of the column that is not air, the column must be open to
+    // the sky at the top of the chunk.
+    let column_index = (x as usize) << 4 | z as usize;
+    let column = &chunk.blocks[column_index * CHUNK_SIZE..(column_index + 1) * CHUNK_SIZE];
+    if column[CHUNK_SIZE - 1] != air {
+        return structures;
+    }
+    let Some(y) = (0..CHUNK_SIZE).rev().find(|y| column[*y] != air) else {
+        return structures;
+    };
+
+    // Structures at the bottom of the sea would never be found.
+    if column[y] == water {
+        return structures;
+    }
+
+    let origin = chunk_position + IVec3::new(x, y as i32, z);
+    if let Some(feature) = (structure_type.build)(generator, origin, &mut rng) {
+        structures.push(PlannedStructure {
+            name: structure_type.name,
+            origin,
+            feature,
+        });
+    }
+
+    return structures;
+}
+
+/// Find the closest place to the position where a structure by the name is generated, searching
+/// at most 'max_distance' regions away. This uses the same placement as when the chunks are
+/// generated, but it only looks at the terrain noise, so once the chunk is generated the
+/// structure may turn out to not be there after all, if the surface was carved away by a cave or
+/// a river.
+pub(super) fn locate_structure(
+    generator: &TerrainGeneratorInner,
+    name: &str,
+    position: IVec3,
+    max_distance: i32,
+) -> Option<IVec3> {
+    let start = IVec2::new(position.x, position.z).div_euclid(IVec2::splat(REGION_WIDTH));
+
+    let mut closest: Option<IVec3> = None;
+    // The regions are searched in rings around the start. A structure in the next ring can still
+    // be closer than one that was found, so the search goes one ring further before it stops.
+    let mut last_ring = max_distance;
+    let mut ring = 0;
+    while ring <= last_ring {
+        for x in -ring..=ring {
+            for z in -ring..=ring {
+                if x.abs() != ring && z.abs() != ring {
+                    continue;
+                }
+
+                let region = start + IVec2::new(x, z);
+                let Some((structure_type, column, _)) = region_structure(generator.seed, region)
+                else {
+                    continue;
+                };
+                if structure_type.name != name {
+                    continue;
+                }
+
+                let origin = IVec3::new(column.x, 0, column.y);
+                let Some((height, _)) = generator.terrain_surface(origin) else {
+                    continue;
+                };
+                let origin = IVec3::new(column.x, height, column.y);
+                // Structures are not planned in water, which is where the sea and the rivers
+                // are.
+                if height < 1
+                    || generator.rivers.is_river(origin)
+                    || !(structure_type.fits)(generator, origin)
+                {
+                    continue;
+                }
+
+                let is_closer = closest.map_or(true, |closest| {
+                    let position = position.as_dvec3();
+                    origin.as_dvec3().distance_squared(position)
+                        < closest.as_dvec3().distance_squared(position)
+                });
+                if is_closer {
+                    closest = Some(origin);
+                }
+            }
+        }
+
+        if closest.is_some() {
+            last_ring = last_ring.min(ring + 1);
+        }
+        ring += 1;
+    }
+
+    return closest;
+}
+
+fn region_seed(seed: i32, region: IVec2, structure_index: usize) -> u64 {
+    // Large odd numbers so that neighbouring regions get seeds that are far apart.
+    return (seed as u64)
+        ^ (region.x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (region.y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (structure_index as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+}
+
+fn fits_anywhere(_generator: &TerrainGeneratorInner, _origin: IVec3) -> bool {
+    return true;
+}
+
+// The remains of a square building split in two by a wall. The walls have crumbled to different
+// heights, and parts of the floor have rotted away.
+fn build_ruins(
+    _generator: &TerrainGeneratorInner,
+    origin: IVec3,
+    rng: &mut StdRng,
+) -> Option<TerrainFeature> {
+    let blocks = Blocks::get();
+    let wall = blocks.get_id("stone");
+    let floor = blocks.get_id("oak_planks");
+
+    let mut feature = TerrainFeature {
+        blocks: HashMap::new(),
+        can_replace: ["air", "grass", "dirt", "sand", "leaves"]
+            .iter()
+            .map(|name| blocks.get_id(name))
+            .collect::<HashSet<_>>(),
+        loot: Vec::new(),
+    };
+
+    let half_width: i32 = rng.gen_range(12..=20);
+    for x in -half_width..=half_width {
+        for z in -half_width..=half_width {
+            let outer_wall = x.abs() == half_width || z.abs() == half_width;
+            let inner_wall = x == 0;
+            // A passage through the middle of the building.
+            let doorway = z.abs() <= 1;
+
+            if (outer_wall || inner_wall) && !doorway {
+                let height = rng.gen_range(0..=4);
+                for y in -RUINS_FOUNDATION..=height {
+                    feature.insert_block(origin + IVec3::new(x, y, z), wall);
+                }
+            } else if rng.gen_bool(0.8) {
+                feature.insert_block(origin + IVec3::new(x, 0, z), floor);
+            }
+        }
+    }
+
+    return Some(feature);
+}
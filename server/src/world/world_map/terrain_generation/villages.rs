@@ -0,0 +1,405 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use bevy::prelude::*;
+use fmc_networking::BlockId;
+use rand::{
+    distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, seq::SliceRandom, Rng,
+};
+use serde::Deserialize;
+
+use crate::world::blocks::{Blocks, BLOCK_CONFIG_PATH};
+
+use super::{
+    blueprints::{Blueprint, BLUEPRINT_PATH},
+    TerrainFeature, TerrainGeneratorInner,
+};
+
+pub const VILLAGE_PATH: &str = "./resources/server/villages/";
+
+// Villages are laid out around a building at their center, with streets leading out from it in
+// some of the four directions. Plots are lined up along both sides of the streets, each with a
+// building from the village's set turned to face the street. Every building sits at the height of
+// the terrain at its plot, on a foundation that fills in the ground beneath it. Plots that are too
+// steep or in a river are left empty, and a street ends where the terrain gets too steep.
+//
+// The biomes name the village config they use, which decides which buildings the village is built
+// from. The buildings are blueprints of the "layers" type.
+
+// Blocks on each side of the center of the streets.
+const STREET_HALF_WIDTH: i32 = 1;
+// Blocks between the streets and the front of the buildings.
+const STREET_MARGIN: i32 = 1;
+// Blocks between neighbouring plots.
+const PLOT_SPACING: i32 = 2;
+// How much higher or lower than the center of the village a plot can be before it is left empty.
+const MAX_SLOPE: i32 = 6;
+// How far the foundations reach into the ground, so the buildings don't float above dips in the
+// terrain.
+const FOUNDATION_DEPTH: i32 = 4;
+
+pub struct Village {
+    center: Blueprint,
+    buildings: Vec<Blueprint>,
+    weights: WeightedIndex<u32>,
+    path_block: BlockId,
+    foundation_block: BlockId,
+    streets: [u32; 2],
+    street_length: [u32; 2],
+    can_replace: HashSet<BlockId>,
+}
+
+#[derive(Deserialize)]
+struct VillageJson {
+    // Blueprint of the building at the center of the village
+    center: String,
+    buildings: Vec<VillageBuildingJson>,
+    // Block the streets are made of
+    path_block: String,
+    // Block that fills in the ground beneath the buildings
+    foundation_block: String,
+    // Inclusive range of how many streets lead out from the center, at most 4.
+    streets: [u32; 2],
+    // Inclusive range of how long the streets are
+    street_length: [u32; 2],
+    // The blocks of the terrain that are cleared away for the village
+    can_replace: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct VillageBuildingJson {
+    blueprint: String,
+    // Relative chance of the building being picked for a plot
+    weight: u32,
+}
+
+pub fn load_villages(blueprints: &HashMap<String, Blueprint>) -> HashMap<String, Arc<Village>> {
+    fn validate_building(
+        village_name: &str,
+        blueprint_name: &str,
+        blueprints: &HashMap<String, Blueprint>,
+    ) {
+        match blueprints.get(blueprint_name) {
+            Some(blueprint) if blueprint.layers_size().is_some() => (),
+            Some(_) => panic!(
+                "Failed while validating the villages. The village '{}' uses the blueprint '{}' \
+                as a building, but only blueprints of the 'layers' type can be buildings.",
+                village_name, blueprint_name
+            ),
+            None => panic!(
+                "Failed while validating the villages. The village '{}' depends on a blueprint by \
+                the name '{}', but no such blueprint exists. This is most likely the result of a \
+                missing file at '{}', make sure it is present.",
+                village_name, blueprint_name, BLUEPRINT_PATH
+            ),
+        }
+    }
+
+    fn validate_block(village_name: &str, block_name: &str) {
+        if !Blocks::get().contains_block(block_name) {
+            panic!(
+                "Failed while validating the villages. The village '{}' references a block with \
+                the name '{}', but no block by that name exists. Make sure a block by the same \
+                name is present at '{}'",
+                village_name, block_name, BLOCK_CONFIG_PATH
+            );
+        }
+    }
+
+    let blocks = Blocks::get();
+    let mut villages = HashMap::new();
+
+    let directory = std::fs::read_dir(VILLAGE_PATH).expect(&format!(
+        "Could not read files from villages directory, make sure it is present as '{}'",
+        VILLAGE_PATH
+    ));
+
+    for entry in directory {
+        let file_path = entry
+            .expect("Failed to read the filenames of the villages")
+            .path();
+
+        let file = std::fs::File::open(&file_path).expect(&format!(
+            "Failed to open village file at '{}'",
+            file_path.display()
+        ));
+        let json: VillageJson = serde_json::from_reader(file).expect(&format!(
+            "Failed to read village at '{}'",
+            file_path.display()
+        ));
+        let name = file_path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+
+        validate_building(&name, &json.center, blueprints);
+        for building in json.buildings.iter() {
+            validate_building(&name, &building.blueprint, blueprints);
+        }
+
+        validate_block(&name, &json.path_block);
+        validate_block(&name, &json.foundation_block);
+        for block_name in json.can_replace.iter() {
+            validate_block(&name, block_name);
+        }
+
+        if json.streets[0] > json.streets[1]
+            || json.streets[1] > 4
+            || json.street_length[0] > json.street_length[1]
+        {
+            panic!(
+                "Failed while validating the villages. The village '{}' must have at most 4 \
+                streets, and the lowest number first for both the streets and their length.",
+                name
+            );
+        }
+
+        let weights = match WeightedIndex::new(json.buildings.iter().map(|b| b.weight)) {
+            Ok(w) => w,
+            Err(_) => panic!(
+                "Failed while validating the villages. The village '{}' must have at least one \
+                building, and the weights must be above zero.",
+                name
+            ),
+        };
+
+        villages.insert(
+            name,
+            Arc::new(Village {
+                center: blueprints[&json.center].clone(),
+                buildings: json
+                    .buildings
+                    .iter()
+                    .map(|building| blueprints[&building.blueprint].clone())
+                    .collect(),
+                weights,
+                path_block: blocks.get_id(&json.path_block),
+                foundation_block: blocks.get_id(&json.foundation_block),
+                streets: json.streets,
+                street_length: json.street_length,
+                can_replace: json
+                    .can_replace
+                    .iter()
+                    .map(|block_name| blocks.get_id(block_name))
+                    .collect(),
+            }),
+        );
+    }
+
+    return villages;
+}
+
+/// Build the village of the biome at the origin. None if the biome doesn't have villages.
+pub(super) fn build_village(
+    generator: &TerrainGeneratorInner,
+    origin: IVec3,
+    rng: &mut StdRng,
+) -> Option<TerrainFeature> {
+    let (_, column_biome) = generator.terrain_surface(origin)?;
+    let village = column_biome.biome.village.as_ref()?;
+    return Some(village.build(generator, origin, rng));
+}
+
+/// If the biome at the origin has villages.
+pub(super) fn fits_village(generator: &TerrainGeneratorInner, origin: IVec3) -> bool {
+    return generator
+        .terrain_surface(origin)
+        .is_some_and(|(_, column_biome)| column_biome.biome.village.is_some());
+}
+
+// An area of block columns, from the lowest to the highest corner.
+#[derive(Clone, Copy)]
+struct Area {
+    min: IVec2,
+    max: IVec2,
+}
+
+impl Area {
+    fn new(a: IVec3, b: IVec3) -> Self {
+        let a = IVec2::new(a.x, a.z);
+        let b = IVec2::new(b.x, b.z);
+        return Self {
+            min: a.min(b),
+            max: a.max(b),
+        };
+    }
+
+    // Areas that are closer than the spacing count as overlapping.
+    fn overlaps(&self, other: &Area, spacing: i32) -> bool {
+        return self.min.x - spacing <= other.max.x
+            && other.min.x <= self.max.x + spacing
+            && self.min.y - spacing <= other.max.y
+            && other.min.y <= self.max.y + spacing;
+    }
+}
+
+impl Village {
+    fn build(
+        &self,
+        generator: &TerrainGeneratorInner,
+        origin: IVec3,
+        rng: &mut StdRng,
+    ) -> TerrainFeature {
+        let air = Blocks::get().get_id("air");
+
+        let mut feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: self.can_replace.clone(),
+            loot: Vec::new(),
+        };
+
+        // The height of the terrain where a plot or a piece of street can go. The village is
+        // kept to the terrain around the height of its center, and out of the water.
+        let ground_height = |position: IVec3| -> Option<i32> {
+            let (height, _) = generator.terrain_surface(position)?;
+            if height < 1
+                || (height - origin.y).abs() > MAX_SLOPE
+                || generator.rivers.is_river(position)
+            {
+                return None;
+            }
+            return Some(height);
+        };
+
+        let mut occupied = Vec::new();
+
+        let center_size = self.center.layers_size().unwrap();
+        let center_corner = origin - IVec3::new(center_size.x / 2, 0, center_size.z / 2);
+        occupied.push(self.place_building(
+            &self.center,
+            center_corner,
+            rng.gen_range(0..4),
+            &mut feature,
+        ));
+
+        let mut directions = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+        directions.shuffle(rng);
+        let street_count = rng.gen_range(self.streets[0]..=self.streets[1]) as usize;
+
+        // The streets start at the edge of the center building, and run until their length is
+        // reached or the terrain gets too steep.
+        let street_start = center_size.x.max(center_size.z) / 2 + 1;
+        let mut streets = Vec::with_capacity(street_count);
+        for direction in directions.into_iter().take(street_count) {
+            let side = IVec3::new(direction.z, 0, direction.x);
+            let length = rng.gen_range(self.street_length[0]..=self.street_length[1]) as i32;
+
+            let mut street_end = street_start;
+            while street_end < street_start + length {
+                let mut position = origin + direction * street_end;
+                let Some(height) = ground_height(position) else {
+                    break;
+                };
+                position.y = height;
+
+                for offset in -STREET_HALF_WIDTH..=STREET_HALF_WIDTH {
+                    let position = position + side * offset;
+                    feature.insert_block(position, self.path_block);
+                    // Clear away the grass and whatever else is growing on it.
+                    feature.insert_block(position + IVec3::Y, air);
+                    feature.insert_block(position + IVec3::Y * 2, air);
+                }
+
+                street_end += 1;
+            }
+
+            if street_end == street_start {
+                continue;
+            }
+
+            occupied.push(Area::new(
+                origin + direction * street_start - side * STREET_HALF_WIDTH,
+                origin + direction * (street_end - 1) + side * STREET_HALF_WIDTH,
+            ));
+            streets.push((direction, side, street_end));
+        }
+
+        // The plots are filled after all the streets are laid, so no building ends up on top of
+        // a street.
+        let front_distance = STREET_HALF_WIDTH + STREET_MARGIN + 1;
+        for (direction, side, street_end) in streets {
+            for side in [side, -side] {
+                let mut along = street_start;
+                while along < street_end {
+                    let building = &self.buildings[self.weights.sample(rng)];
+                    let size = building.layers_size().unwrap();
+                    if along + size.x > street_end {
+                        break;
+                    }
+
+                    let near_corner = origin + direction * along + side * front_distance;
+                    let far_corner = near_corner + direction * (size.x - 1) + side * (size.z - 1);
+                    let area = Area::new(near_corner, far_corner);
+
+                    if occupied.iter().any(|other| area.overlaps(other, 1)) {
+                        along += 1;
+                        continue;
+                    }
+
+                    let center = (near_corner + far_corner) / 2;
+                    let Some(height) = ground_height(center) else {
+                        along += size.x + PLOT_SPACING;
+                        continue;
+                    };
+
+                    // The front of the building is its first row, it should face the street.
+                    let front = -side;
+                    let rotation = if front == IVec3::NEG_Z {
+                        0
+                    } else if front == IVec3::NEG_X {
+                        1
+                    } else if front == IVec3::Z {
+                        2
+                    } else {
+                        3
+                    };
+
+                    let corner = IVec3::new(area.min.x, height, area.min.y);
+                    occupied.push(self.place_building(building, corner, rotation, &mut feature));
+
+                    along += size.x + PLOT_SPACING;
+                }
+            }
+        }
+
+        return feature;
+    }
+
+    // Place a building with its lowest corner at the position, on a foundation and with the
+    // terrain inside it cleared. Returns the area it covers.
+    fn place_building(
+        &self,
+        building: &Blueprint,
+        corner: IVec3,
+        rotation: u8,
+        feature: &mut TerrainFeature,
+    ) -> Area {
+        let air = Blocks::get().get_id("air");
+
+        let size = building.layers_size().unwrap();
+        let footprint = if rotation % 2 == 0 {
+            size
+        } else {
+            IVec3::new(size.z, size.y, size.x)
+        };
+
+        for x in 0..footprint.x {
+            for z in 0..footprint.z {
+                let column = corner + IVec3::new(x, 0, z);
+                for y in -FOUNDATION_DEPTH..0 {
+                    feature.insert_block(column + IVec3::new(0, y, 0), self.foundation_block);
+                }
+                for y in 1..footprint.y {
+                    feature.insert_block(column + IVec3::new(0, y, 0), air);
+                }
+            }
+        }
+
+        building.place_layers(corner, rotation, feature);
+
+        return Area::new(corner, corner + footprint - IVec3::ONE);
+    }
+}
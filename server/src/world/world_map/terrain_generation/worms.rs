@@ -0,0 +1,273 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+    sync::{Arc, Mutex},
+};
+
+use bevy::math::{IVec2, IVec3, Vec3};
+use fmc_networking::BlockId;
+use noise::Noise;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{constants::CHUNK_SIZE, world::blocks::Blocks};
+
+use super::{biomes::Biomes, chunk_seed, Chunk, TerrainFeature, UNDERGROUND_CAVE_DEPTH};
+
+// Tunnels are dug by worms that crawl through the ground, instead of being cut out where noise
+// crosses a threshold. Each worm starts somewhere in its cell and takes a fixed number of steps,
+// carving out a sphere at each one. It turns towards where the steering noise grows, so worms
+// that pass near each other are pulled towards the same ridges of the noise and their tunnels
+// join. The steering noise is sampled at a position that is pushed around by a second noise, so
+// the turns don't follow the grid the noise is built on. The tunnels are kept mostly level, so
+// they can be walked.
+//
+// A worm reaches far outside its cell, so every chunk digs the worms of the cells around it and
+// carves the parts of them that pass through it. Everything about a worm is decided by the seed
+// and its cell, so the chunks agree on where the tunnels go no matter the order they are
+// generated in.
+
+// Width of the cells, in blocks.
+const CELL_SIZE: i32 = 64;
+// Most worms that can start in a cell, each cell has between none and this many.
+const MAX_WORMS: u32 = 2;
+// The heights the worms start between.
+const MIN_START_HEIGHT: f32 = -80.0;
+const MAX_START_HEIGHT: f32 = 32.0;
+const MIN_LENGTH: u32 = 48;
+const MAX_LENGTH: u32 = 96;
+// Distance the worm moves each step, it has to be less than the radius so the spheres overlap.
+const STEP_LENGTH: f32 = 1.5;
+const MIN_RADIUS: f32 = 1.6;
+const MAX_RADIUS: f32 = 3.2;
+// How much the steering noise widens and narrows the tunnel, relative to its radius.
+const RADIUS_VARIATION: f32 = 0.4;
+// Number of steps at each end over which the tunnel narrows, so it doesn't end in a flat wall.
+const TAPER_STEPS: f32 = 6.0;
+// How sharply the worms turn towards the steering noise.
+const TURN_RATE: f32 = 0.15;
+// How much of its vertical direction the worm keeps after each step.
+const LEVELING: f32 = 0.85;
+// How far the warp noise moves the position the steering noise is sampled at, in blocks.
+const WARP_DISTANCE: f32 = 24.0;
+const STEERING_FREQUENCY: f32 = 1.0 / 64.0;
+const WARP_FREQUENCY: f32 = 1.0 / 128.0;
+// Number of cells around the chunk's own that are searched for worms. A worm reaches at most
+// MAX_LENGTH * STEP_LENGTH blocks plus its radius away from where it starts, which must be less
+// than CELL_REACH * CELL_SIZE.
+const CELL_REACH: i32 = 3;
+// Dug worms are kept for the other chunks within their reach, this many cells at a time.
+const MAX_CACHED_CELLS: usize = 1024;
+
+struct Worm {
+    // Center and radius of each sphere the worm carves.
+    path: Vec<(Vec3, f32)>,
+    // Bounds of all the spheres.
+    min: Vec3,
+    max: Vec3,
+}
+
+pub struct Worms {
+    seed: i32,
+    steering: Noise,
+    warp: Noise,
+    // The worms can dig through anything but liquids, they would be left floating otherwise.
+    can_replace: HashSet<BlockId>,
+    cells: Mutex<HashMap<IVec2, Arc<Vec<Worm>>>>,
+}
+
+impl Worms {
+    pub fn new(seed: i32, biomes: &Biomes) -> Self {
+        let liquids: HashSet<BlockId> = biomes
+            .iter()
+            .flat_map(|biome| [biome.surface_liquid, biome.sub_surface_liquid])
+            .collect();
+        let can_replace = Blocks::get()
+            .clone_ids()
+            .into_values()
+            .filter(|block_id| !liquids.contains(block_id))
+            .collect();
+
+        return Self {
+            seed,
+            steering: Noise::perlin(STEERING_FREQUENCY, seed + 14),
+            warp: Noise::perlin(WARP_FREQUENCY, seed + 15),
+            can_replace,
+            cells: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Carve out the tunnels that pass through the chunk. Like the caves, they are kept away
+    /// from the columns that have water above them, and only reach up towards the surface in the
+    /// columns that are inland.
+    pub fn carve(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        no_caves: &[bool],
+        inland: &[bool],
+    ) {
+        let air = Blocks::get().get_id("air");
+
+        let chunk_min = chunk_position.as_vec3();
+        let chunk_max = chunk_min + Vec3::splat(CHUNK_SIZE as f32);
+        let chunk_cell =
+            IVec2::new(chunk_position.x, chunk_position.z).div_euclid(IVec2::splat(CELL_SIZE));
+
+        let mut carved = HashSet::new();
+        for cell_x in -CELL_REACH..=CELL_REACH {
+            for cell_z in -CELL_REACH..=CELL_REACH {
+                let worms = self.cell_worms(chunk_cell + IVec2::new(cell_x, cell_z));
+                for worm in worms.iter() {
+                    if worm.max.cmplt(chunk_min).any() || worm.min.cmpge(chunk_max).any() {
+                        continue;
+                    }
+
+                    for (center, radius) in worm.path.iter() {
+                        carve_sphere(
+                            chunk_position,
+                            *center,
+                            *radius,
+                            no_caves,
+                            inland,
+                            &mut carved,
+                        );
+                    }
+                }
+            }
+        }
+
+        if carved.is_empty() {
+            return;
+        }
+
+        let tunnels = TerrainFeature {
+            blocks: HashMap::from([(
+                chunk_position,
+                carved
+                    .into_iter()
+                    .map(|block_index| (block_index, air, None))
+                    .collect(),
+            )]),
+            can_replace: self.can_replace.clone(),
+            loot: Vec::new(),
+        };
+        tunnels.apply(chunk, chunk_position);
+    }
+
+    fn cell_worms(&self, cell: IVec2) -> Arc<Vec<Worm>> {
+        if let Some(worms) = self.cells.lock().unwrap().get(&cell) {
+            return worms.clone();
+        }
+
+        // Dug without holding the lock, so the other chunks being generated don't have to wait.
+        let worms = Arc::new(self.dig(cell));
+
+        let mut cells = self.cells.lock().unwrap();
+        if cells.len() >= MAX_CACHED_CELLS {
+            cells.clear();
+        }
+        cells.insert(cell, worms.clone());
+
+        return worms;
+    }
+
+    fn dig(&self, cell: IVec2) -> Vec<Worm> {
+        // Offset from the seed the chunks and lakes use, so the cells don't roll the same numbers.
+        let mut rng = StdRng::seed_from_u64(chunk_seed(
+            self.seed.wrapping_add(3),
+            IVec3::new(cell.x, 0, cell.y),
+        ));
+
+        let mut worms = Vec::new();
+        for _ in 0..rng.gen_range(0..=MAX_WORMS) {
+            let mut position = Vec3::new(
+                (cell.x * CELL_SIZE) as f32 + rng.gen_range(0.0..CELL_SIZE as f32),
+                rng.gen_range(MIN_START_HEIGHT..MAX_START_HEIGHT),
+                (cell.y * CELL_SIZE) as f32 + rng.gen_range(0.0..CELL_SIZE as f32),
+            );
+            let yaw: f32 = rng.gen_range(0.0..TAU);
+            let pitch: f32 = rng.gen_range(-0.3..0.3);
+            let mut direction = Vec3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            );
+            let length = rng.gen_range(MIN_LENGTH..=MAX_LENGTH);
+            let base_radius = rng.gen_range(MIN_RADIUS..MAX_RADIUS);
+
+            let mut worm = Worm {
+                path: Vec::with_capacity(length as usize),
+                min: Vec3::splat(f32::MAX),
+                max: Vec3::splat(f32::MIN),
+            };
+
+            for step in 0..length {
+                let (_, warp, _, _) = self
+                    .warp
+                    .generate_3d_with_derivatives(position.x, position.y, position.z, 1, 1, 1);
+                let warped = position + Vec3::from_array(warp[0]) / WARP_FREQUENCY * WARP_DISTANCE;
+
+                let (value, gradient, _, _) = self
+                    .steering
+                    .generate_3d_with_derivatives(warped.x, warped.y, warped.z, 1, 1, 1);
+                // Only the part of the gradient that points across the worm turns it.
+                let gradient = Vec3::from_array(gradient[0]) / STEERING_FREQUENCY;
+                let across = gradient - direction * gradient.dot(direction);
+
+                let mut new_direction = direction + across * TURN_RATE;
+                new_direction.y *= LEVELING;
+                direction = new_direction.try_normalize().unwrap_or(direction);
+
+                let from_end = step.min(length - 1 - step) as f32;
+                let taper = 0.4 + 0.6 * (from_end / TAPER_STEPS).min(1.0);
+                let radius = base_radius * (1.0 + value[0] * RADIUS_VARIATION) * taper;
+
+                worm.path.push((position, radius));
+                worm.min = worm.min.min(position - radius);
+                worm.max = worm.max.max(position + radius);
+
+                position += direction * STEP_LENGTH;
+            }
+
+            worms.push(worm);
+        }
+
+        return worms;
+    }
+}
+
+// Adds the blocks of the chunk that are inside the sphere to the carved blocks.
+fn carve_sphere(
+    chunk_position: IVec3,
+    center: Vec3,
+    radius: f32,
+    no_caves: &[bool],
+    inland: &[bool],
+    carved: &mut HashSet<usize>,
+) {
+    let min = (center - radius).floor().as_ivec3().max(chunk_position);
+    let max = (center + radius)
+        .ceil()
+        .as_ivec3()
+        .min(chunk_position + IVec3::splat(CHUNK_SIZE as i32 - 1));
+
+    for x in min.x..=max.x {
+        for z in min.z..=max.z {
+            let column_index = ((x - chunk_position.x) << 4 | (z - chunk_position.z)) as usize;
+            if no_caves[column_index] {
+                continue;
+            }
+
+            for y in min.y..=max.y {
+                if !inland[column_index] && y >= UNDERGROUND_CAVE_DEPTH {
+                    break;
+                }
+
+                let block_center = IVec3::new(x, y, z).as_vec3() + 0.5;
+                if block_center.distance_squared(center) <= radius * radius {
+                    carved.insert(column_index << 4 | (y - chunk_position.y) as usize);
+                }
+            }
+        }
+    }
+}
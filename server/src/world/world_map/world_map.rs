@@ -21,6 +21,10 @@ pub struct WorldMap {
 }
 
 impl WorldMap {
+    pub fn chunk_count(&self) -> usize {
+        return self.chunks.len();
+    }
+
     pub fn contains_chunk(&self, pos: &IVec3) -> bool {
         return self.chunks.contains_key(pos);
     }